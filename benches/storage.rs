@@ -0,0 +1,52 @@
+//! Storage benchmarks on a synthetic history
+//!
+//! Run with `cargo bench`; `omniscient bench` times the same operations
+//! against a database of any size.
+use criterion::{criterion_group, criterion_main, Criterion};
+use omniscient::models::SearchQuery;
+use omniscient::{bench, CaptureRequest, CommandCapture, Config, Storage};
+use tempfile::TempDir;
+
+/// Commands in the benchmark database
+const RECORDS: usize = 20_000;
+
+fn storage_benchmarks(c: &mut Criterion) {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("bench.db");
+    bench::generate(&Storage::new(&path).unwrap(), RECORDS, 42).unwrap();
+
+    let mut config = Config::default();
+    config.storage.path = path.to_string_lossy().to_string();
+
+    let capture = CommandCapture::new(config.clone()).unwrap();
+    let mut run = 0;
+    c.bench_function("capture", |b| {
+        b.iter(|| {
+            run += 1;
+            let request = CaptureRequest::new(&format!("cargo test bench_{}", run), 0, 1200);
+            capture.capture_request(request).unwrap()
+        })
+    });
+
+    let storage = Storage::open(&path, false).unwrap();
+    for (name, text, prefix) in [
+        ("search text", "kubectl logs", false),
+        ("search prefix", "git", true),
+    ] {
+        let query = SearchQuery {
+            text: Some(text.to_string()),
+            prefix,
+            limit: 20,
+            ..Default::default()
+        };
+        c.bench_function(name, |b| b.iter(|| storage.search(&query).unwrap()));
+    }
+
+    let mut group = c.benchmark_group("stats");
+    group.sample_size(10);
+    group.bench_function("stats", |b| b.iter(|| storage.get_stats().unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, storage_benchmarks);
+criterion_main!(benches);
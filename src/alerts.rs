@@ -0,0 +1,194 @@
+/// Alerts for dangerous commands
+///
+/// Captured commands are checked against the `[alerts]` watchlist. Matches
+/// are logged in the database for `omniscient alerts`, and with
+/// `alerts.warn` a warning is appended to a file next to the database
+/// (`<db>.alerts`) that the shell hook prints and removes before a later
+/// prompt. Capture runs in the background, so the warning shows up after the
+/// dangerous command has finished, usually one prompt later.
+use crate::config::{AlertRule, Config};
+use crate::error::{OmniscientError, Result};
+use crate::models::Alert;
+use regex::Regex;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// A compiled watchlist rule
+#[derive(Debug, Clone)]
+struct Rule {
+    name: String,
+    pattern: Regex,
+    dir_pattern: Option<Regex>,
+}
+
+/// Compiled `[alerts]` watchlist
+#[derive(Debug, Clone, Default)]
+pub struct Watchlist {
+    rules: Vec<Rule>,
+}
+
+impl Watchlist {
+    /// Compile a list of rules, rejecting invalid patterns
+    pub fn new(rules: &[AlertRule]) -> Result<Self> {
+        let compile = |name: &str, pattern: &str| {
+            Regex::new(pattern).map_err(|e| {
+                OmniscientError::config(format!(
+                    "Invalid pattern '{}' in alert rule '{}': {}",
+                    pattern, name, e
+                ))
+            })
+        };
+
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                Ok(Rule {
+                    name: rule.name.clone(),
+                    pattern: compile(&rule.name, &rule.pattern)?,
+                    dir_pattern: rule
+                        .dir_pattern
+                        .as_deref()
+                        .map(|dir| compile(&rule.name, dir))
+                        .transpose()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { rules })
+    }
+
+    /// The configured watchlist, or an empty one when alerts are disabled
+    pub fn from_config(config: &Config) -> Result<Self> {
+        if !config.alerts.enabled {
+            return Ok(Self::default());
+        }
+        Self::new(&config.alerts.watchlist)
+    }
+
+    /// Names of the rules a command run in `working_dir` matches
+    pub fn matches(&self, command: &str, working_dir: &str) -> Vec<&str> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.pattern.is_match(command))
+            .filter(|rule| {
+                rule.dir_pattern
+                    .as_ref()
+                    .is_none_or(|dir| dir.is_match(working_dir))
+            })
+            .map(|rule| rule.name.as_str())
+            .collect()
+    }
+}
+
+/// Warnings file belonging to the configured database (`<db>.alerts`)
+pub fn warnings_path(config: &Config) -> Result<PathBuf> {
+    let mut path = config.database_path()?.into_os_string();
+    path.push(".alerts");
+    Ok(PathBuf::from(path))
+}
+
+/// Append a warning for the shell hook to print
+pub fn queue_warning(path: &Path, alert: &Alert) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", format_warning(alert))?;
+    Ok(())
+}
+
+/// One-line warning about an alert
+pub fn format_warning(alert: &Alert) -> String {
+    format!(
+        "⚠ omniscient: '{}' matched the alert rule \"{}\" (see: omniscient alerts)",
+        alert.command.replace('\n', " ⏎ "),
+        alert.rule
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use tempfile::TempDir;
+
+    fn default_watchlist() -> Watchlist {
+        Watchlist::new(&crate::config::AlertsConfig::default().watchlist).unwrap()
+    }
+
+    #[test]
+    fn test_default_watchlist() {
+        let watchlist = default_watchlist();
+
+        for command in [
+            "rm -rf /",
+            "sudo rm -rf / --no-preserve-root",
+            "rm -fr ~",
+            "rm -r -f /*",
+            "kubectl --context prod delete namespace payments",
+            "kubectl delete ns staging",
+            "psql -c 'DROP TABLE users'",
+            "sudo dd if=image.iso of=/dev/sda",
+            "mkfs.ext4 /dev/sdb1",
+        ] {
+            assert_eq!(watchlist.matches(command, "/tmp").len(), 1, "{}", command);
+        }
+
+        for command in [
+            "rm -rf ./build",
+            "rm -rf /tmp/scratch",
+            "rm ~/notes.txt",
+            "kubectl get namespaces",
+            "terraform plan",
+            "dd if=/dev/zero of=disk.img",
+        ] {
+            assert!(watchlist.matches(command, "/tmp").is_empty(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_dir_pattern() {
+        let watchlist = default_watchlist();
+        assert!(watchlist
+            .matches("terraform destroy", "/infra/staging")
+            .is_empty());
+        assert_eq!(
+            watchlist.matches("terraform destroy -auto-approve", "/infra/prod/eu"),
+            vec!["terraform destroy in production"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_and_disabled() {
+        let rule = AlertRule {
+            name: "broken".to_string(),
+            pattern: "(".to_string(),
+            dir_pattern: None,
+        };
+        let err = Watchlist::new(&[rule]).unwrap_err();
+        assert!(err.to_string().contains("alert rule 'broken'"));
+
+        let mut config = Config::default();
+        config.alerts.enabled = false;
+        let watchlist = Watchlist::from_config(&config).unwrap();
+        assert!(watchlist.matches("rm -rf /", "/").is_empty());
+    }
+
+    #[test]
+    fn test_queue_warning() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("history.db.alerts");
+        let alert = Alert {
+            id: None,
+            command_id: 1,
+            rule: "database dropped".to_string(),
+            command: "dropdb prod".to_string(),
+            working_dir: "/tmp".to_string(),
+            timestamp: Utc::now(),
+        };
+
+        queue_warning(&path, &alert).unwrap();
+        queue_warning(&path, &alert).unwrap();
+        let warnings = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(warnings.lines().count(), 2);
+        assert!(warnings.starts_with("⚠ omniscient: 'dropdb prod' matched"));
+    }
+}
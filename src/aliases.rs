@@ -0,0 +1,181 @@
+/// Alias suggestions (`omniscient aliases suggest`)
+///
+/// Long commands you run often are turned into alias definitions for your
+/// shell rc file. Names are built from the first letter of each word
+/// (`docker compose up -d` becomes `dcud`), made unique with a number, and
+/// suggestions are ranked by the keystrokes they would have saved.
+use crate::parse::tokenize;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Shell syntax of the generated definitions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AliasSyntax {
+    Zsh,
+    Bash,
+    Fish,
+}
+
+impl AliasSyntax {
+    /// Look up a syntax by shell name ("zsh", "bash" or "fish")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "zsh" => Some(AliasSyntax::Zsh),
+            "bash" => Some(AliasSyntax::Bash),
+            "fish" => Some(AliasSyntax::Fish),
+            _ => None,
+        }
+    }
+
+    /// The syntax of the shell in `$SHELL`, zsh if it isn't known
+    pub fn detect() -> Self {
+        let shell = std::env::var("SHELL").unwrap_or_default();
+        let name = shell.rsplit('/').next().unwrap_or_default();
+        Self::from_name(name).unwrap_or(AliasSyntax::Zsh)
+    }
+
+    /// An alias definition in this syntax
+    pub fn define(&self, name: &str, command: &str) -> String {
+        match self {
+            AliasSyntax::Zsh | AliasSyntax::Bash => {
+                format!("alias {}='{}'", name, command.replace('\'', r"'\''"))
+            }
+            AliasSyntax::Fish => format!(
+                "alias {} '{}'",
+                name,
+                command.replace('\\', r"\\").replace('\'', r"\'")
+            ),
+        }
+    }
+}
+
+impl fmt::Display for AliasSyntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AliasSyntax::Zsh => write!(f, "zsh"),
+            AliasSyntax::Bash => write!(f, "bash"),
+            AliasSyntax::Fish => write!(f, "fish"),
+        }
+    }
+}
+
+/// A suggested alias
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AliasSuggestion {
+    /// Alias name
+    pub name: String,
+
+    /// The command it runs
+    pub command: String,
+
+    /// Number of times the command ran
+    pub uses: i64,
+
+    /// Characters the alias would have saved over all those runs
+    pub saved: i64,
+}
+
+/// Suggest aliases for `(command, uses)` candidates, best first
+///
+/// Multi-line commands are skipped. Names in `taken` (such as programs on
+/// the PATH) or given to a better suggestion get a number appended.
+pub fn suggest(
+    candidates: &[(String, i64)],
+    taken: &HashSet<String>,
+    limit: usize,
+) -> Vec<AliasSuggestion> {
+    let mut suggestions: Vec<AliasSuggestion> = candidates
+        .iter()
+        .filter(|(command, _)| !command.contains('\n'))
+        .filter_map(|(command, uses)| {
+            let name = initials(command)?;
+            let saved = (command.chars().count() - name.len()) as i64 * uses;
+            Some(AliasSuggestion {
+                name,
+                command: command.clone(),
+                uses: *uses,
+                saved,
+            })
+        })
+        .collect();
+
+    suggestions.sort_by(|a, b| {
+        b.saved
+            .cmp(&a.saved)
+            .then_with(|| a.command.cmp(&b.command))
+    });
+    suggestions.truncate(limit);
+
+    // Best suggestions get the plain names
+    let mut used = taken.clone();
+    for suggestion in &mut suggestions {
+        let mut name = suggestion.name.clone();
+        let mut n = 2;
+        while used.contains(&name) {
+            name = format!("{}{}", suggestion.name, n);
+            n += 1;
+        }
+        used.insert(name.clone());
+        suggestion.name = name;
+    }
+
+    suggestions
+}
+
+/// The lowercase first letter or digit of each word, if that gives at
+/// least two characters
+fn initials(command: &str) -> Option<String> {
+    let name: String = tokenize(command)
+        .iter()
+        .filter_map(|word| word.chars().find(char::is_ascii_alphanumeric))
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    (name.len() >= 2).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(list: &[(&str, i64)]) -> Vec<(String, i64)> {
+        list.iter()
+            .map(|(command, uses)| (command.to_string(), *uses))
+            .collect()
+    }
+
+    #[test]
+    fn test_suggest() {
+        let candidates = candidates(&[
+            ("docker compose up -d", 10),
+            ("git log --oneline --graph --all", 8),
+            ("git log --oneline --graph --author=me", 3),
+            ("echo 'multi\nline'", 50),
+        ]);
+        let taken: HashSet<String> = ["dcud".to_string()].into();
+
+        let suggestions = suggest(&candidates, &taken, 10);
+        assert_eq!(suggestions.len(), 3);
+        assert_eq!(suggestions[0].command, "git log --oneline --graph --all");
+        assert_eq!(suggestions[0].name, "gloga");
+        assert_eq!(suggestions[0].saved, (31 - 5) * 8);
+        assert_eq!(suggestions[1].name, "dcud2");
+        assert_eq!(suggestions[2].name, "gloga2");
+
+        assert_eq!(suggest(&candidates, &HashSet::new(), 1).len(), 1);
+    }
+
+    #[test]
+    fn test_define() {
+        let command = "grep -r 'TODO' src";
+        assert_eq!(
+            AliasSyntax::Bash.define("grts", command),
+            r#"alias grts='grep -r '\''TODO'\'' src'"#
+        );
+        assert_eq!(
+            AliasSyntax::Fish.define("grts", command),
+            r#"alias grts 'grep -r \'TODO\' src'"#
+        );
+        assert_eq!(AliasSyntax::from_name("fish"), Some(AliasSyntax::Fish));
+        assert_eq!(AliasSyntax::from_name("csh"), None);
+    }
+}
@@ -0,0 +1,527 @@
+/// HTTP/JSON API over the query layer (`omniscient serve --api`)
+///
+/// Read-only endpoints answer GET requests with JSON:
+///
+/// - `/search` - parameters mirror [`SearchQuery`]: `q`, `category`,
+///   `success`, `dir`, `recursive`, `branch`, `host`, `tmux_session`,
+///   `tmux_pane`, `env` (NAME=VALUE, repeatable), `prefix`, `ignore_case`, `exclude` and `exclude_category` (repeatable),
+///   `min_duration` and `max_duration` (e.g. 500ms, 2m), `exit_code`,
+///   `program`, `flag` (repeatable, e.g. `-n prod`), `limit`, `offset` and
+///   `order` (relevance, timestamp, last_used, usage)
+/// - `/recent`, `/top` - `dir`, `recursive` and `limit`
+/// - `/suggest` - commands starting with `prefix`, with `dir`, `recursive`
+///   and `limit`
+/// - `/stats`
+/// - `/categories`
+///
+/// `limit` is capped at [`MAX_LIMIT`] and `offset + limit` at
+/// [`MAX_WINDOW`], so one request can't make SQLite walk the whole history.
+///
+/// Errors are returned as `{"error": "..."}` with a 4xx/5xx status.
+/// Requests are handled one at a time on the calling thread.
+///
+/// Without a token (`api.token`) the API only listens on loopback addresses
+/// and only answers requests for a loopback host name, so a web page can't
+/// read the history through DNS rebinding. With a token it may listen on
+/// any address, and every request must carry `Authorization: Bearer <token>`.
+use crate::error::OmniscientError;
+use crate::models::{parse_duration, OrderBy, SearchQuery};
+use crate::storage::Storage;
+use serde_json::{json, Value};
+use std::net::ToSocketAddrs;
+
+/// Address the API listens on unless another one is given
+pub const DEFAULT_ADDR: &str = "127.0.0.1:7878";
+
+/// Most results one request returns
+pub const MAX_LIMIT: usize = 1_000;
+
+/// Furthest into the results a request reaches (`offset + limit`)
+pub const MAX_WINDOW: usize = 100_000;
+
+/// A request that could not be answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    /// HTTP status code
+    pub status: u16,
+
+    /// Message returned to the client
+    pub message: String,
+}
+
+impl ApiError {
+    fn bad_request<S: Into<String>>(message: S) -> Self {
+        Self {
+            status: 400,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<OmniscientError> for ApiError {
+    fn from(error: OmniscientError) -> Self {
+        Self {
+            status: 500,
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Answer a request for `path` with the given query parameters
+pub fn handle(
+    storage: &Storage,
+    path: &str,
+    params: &[(String, String)],
+) -> std::result::Result<Value, ApiError> {
+    let params = Params(params);
+
+    match path.trim_end_matches('/') {
+        "/search" => {
+            let limit = params.limit()?;
+            let query = SearchQuery {
+                text: params.get("q").map(str::to_string),
+                category: params.get("category").map(str::to_string),
+                success_only: params.bool("success")?,
+                working_dir: params.get("dir").map(str::to_string),
+                recursive: params.bool("recursive")?.unwrap_or(false),
+                env: params.env()?,
+                branch: params.get("branch").map(str::to_string),
+                host: params.get("host").map(str::to_string),
+                tmux_session: params.get("tmux_session").map(str::to_string),
+                tmux_pane: params.get("tmux_pane").map(str::to_string),
+                prefix: params.bool("prefix")?.unwrap_or(false),
+                ignore_case: params.bool("ignore_case")?.unwrap_or(false),
+                exclude: params.all("exclude"),
+                exclude_categories: params.all("exclude_category"),
+                min_duration_ms: params.duration("min_duration")?,
+                max_duration_ms: params.duration("max_duration")?,
+                exit_code: params.exit_code()?,
+                program: params.get("program").map(str::to_string),
+                flags: params.all("flag"),
+                limit,
+                offset: params.offset(limit)?,
+                order_by: params.order()?,
+            };
+            Ok(json!(storage.search(&query)?))
+        }
+        "/recent" => Ok(json!(storage.get_recent(
+            params.limit()?,
+            params.get("dir").map(str::to_string),
+            params.bool("recursive")?.unwrap_or(false),
+        )?)),
+        "/top" => Ok(json!(storage.get_top(
+            params.limit()?,
+            params.get("dir").map(str::to_string),
+            params.bool("recursive")?.unwrap_or(false),
+        )?)),
+        "/suggest" => {
+            let prefix = params
+                .get("prefix")
+                .ok_or_else(|| ApiError::bad_request("Missing parameter 'prefix'"))?;
+            Ok(json!(storage.suggest(
+                prefix,
+                params.limit()?,
+                params.get("dir").map(str::to_string),
+                params.bool("recursive")?.unwrap_or(false),
+            )?))
+        }
+        "/stats" => {
+            let stats = storage.get_stats_with_trends()?;
+            let success_rate = stats.success_rate();
+            let mut value = json!(stats);
+            value["success_rate"] = json!(success_rate);
+            Ok(value)
+        }
+        "/categories" => Ok(json!(storage.get_stats()?.by_category)),
+        _ => Err(ApiError {
+            status: 404,
+            message: format!("Unknown endpoint '{}'", path),
+        }),
+    }
+}
+
+/// Whether every address `addr` resolves to is a loopback address
+pub fn is_loopback(addr: &str) -> crate::error::Result<bool> {
+    let addrs: Vec<_> = addr
+        .to_socket_addrs()
+        .map_err(|e| OmniscientError::other(format!("Invalid address '{}': {}", addr, e)))?
+        .collect();
+    Ok(!addrs.is_empty() && addrs.iter().all(|addr| addr.ip().is_loopback()))
+}
+
+/// Whether a `Host` header names this machine's loopback interface
+/// (`localhost:7878`, `127.0.0.1`, `[::1]:7878`)
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(""),
+        None => host.split(':').next().unwrap_or(""),
+    };
+    name.eq_ignore_ascii_case("localhost")
+        || name
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Check a request's `Host` and `Authorization` headers
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+fn check_access(
+    host: Option<&str>,
+    authorization: Option<&str>,
+    token: Option<&str>,
+) -> std::result::Result<(), ApiError> {
+    match token {
+        Some(token) => {
+            let sent = authorization.and_then(|value| value.strip_prefix("Bearer "));
+            if sent.map(str::trim) != Some(token) {
+                return Err(ApiError {
+                    status: 401,
+                    message: "Missing or wrong token (Authorization: Bearer <token>)".to_string(),
+                });
+            }
+        }
+        None => {
+            if !host.is_some_and(is_loopback_host) {
+                return Err(ApiError {
+                    status: 403,
+                    message: "Only requests for localhost are answered".to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Refuse to listen on an address other than loopback without a token
+pub fn check_addr(addr: &str, token: Option<&str>) -> crate::error::Result<()> {
+    if token.is_none() && !is_loopback(addr)? {
+        return Err(OmniscientError::other(format!(
+            "Refusing to serve the API on {}: anyone on the network could read your history. \
+             Set a token first (omniscient config set api.token <token>)",
+            addr
+        )));
+    }
+    Ok(())
+}
+
+/// Serve the API on `addr` until the process is stopped
+///
+/// Refuses addresses other than loopback unless a `token` is given.
+#[cfg(feature = "cli")]
+pub fn serve(storage: &Storage, addr: &str, token: Option<&str>) -> crate::error::Result<()> {
+    check_addr(addr, token)?;
+
+    let server = tiny_http::Server::http(addr)
+        .map_err(|e| OmniscientError::other(format!("Cannot listen on {}: {}", addr, e)))?;
+
+    for request in server.incoming_requests() {
+        let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+        let params: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+        let header = |name: &'static str| {
+            request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv(name))
+                .map(|header| header.value.as_str())
+        };
+        let access = check_access(header("Host"), header("Authorization"), token);
+
+        let result = if let Err(error) = access {
+            Err(error)
+        } else if *request.method() == tiny_http::Method::Get {
+            handle(storage, path, &params)
+        } else {
+            Err(ApiError {
+                status: 405,
+                message: "Only GET requests are supported".to_string(),
+            })
+        };
+        let (status, body) = match result {
+            Ok(value) => (200, value),
+            Err(error) => (error.status, json!({ "error": error.message })),
+        };
+
+        let header = tiny_http::Header::from_bytes("Content-Type", "application/json")
+            .expect("static header is valid");
+        let response = tiny_http::Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(header);
+        // The client hanging up is not the server's problem
+        let _ = request.respond(response);
+    }
+
+    Ok(())
+}
+
+/// Query parameters of one request
+struct Params<'a>(&'a [(String, String)]);
+
+impl Params<'_> {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Values of a repeatable parameter
+    fn all(&self, name: &str) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    fn bool(&self, name: &str) -> std::result::Result<Option<bool>, ApiError> {
+        match self.get(name) {
+            None => Ok(None),
+            Some("" | "1" | "true" | "yes") => Ok(Some(true)),
+            Some("0" | "false" | "no") => Ok(Some(false)),
+            Some(value) => Err(ApiError::bad_request(format!(
+                "Invalid {} '{}'. Expected true or false",
+                name, value
+            ))),
+        }
+    }
+
+    /// A duration such as "500ms" or "2m", in milliseconds
+    fn duration(&self, name: &str) -> std::result::Result<Option<i64>, ApiError> {
+        self.get(name)
+            .map(|value| {
+                parse_duration(value).map_err(|e| {
+                    ApiError::bad_request(format!("Invalid {} '{}': {}", name, value, e))
+                })
+            })
+            .transpose()
+    }
+
+    fn exit_code(&self) -> std::result::Result<Option<i32>, ApiError> {
+        self.get("exit_code")
+            .map(|value| {
+                value.parse().map_err(|_| {
+                    ApiError::bad_request(format!(
+                        "Invalid exit_code '{}'. Expected a number",
+                        value
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    /// `limit`, at most [`MAX_LIMIT`]
+    fn limit(&self) -> std::result::Result<usize, ApiError> {
+        let limit = match self.get("limit") {
+            None => SearchQuery::default().limit,
+            Some(value) => value.parse().map_err(|_| {
+                ApiError::bad_request(format!("Invalid limit '{}'. Expected a number", value))
+            })?,
+        };
+        Ok(limit.min(MAX_LIMIT))
+    }
+
+    /// `offset`, so that `offset + limit` is at most [`MAX_WINDOW`]
+    fn offset(&self, limit: usize) -> std::result::Result<usize, ApiError> {
+        let offset = match self.get("offset") {
+            None => 0,
+            Some(value) => value.parse().map_err(|_| {
+                ApiError::bad_request(format!("Invalid offset '{}'. Expected a number", value))
+            })?,
+        };
+        Ok(offset.min(MAX_WINDOW - limit))
+    }
+
+    fn order(&self) -> std::result::Result<OrderBy, ApiError> {
+        match self.get("order") {
+            None | Some("relevance") => Ok(OrderBy::Relevance),
+            Some("timestamp") => Ok(OrderBy::Timestamp),
+            Some("last_used") => Ok(OrderBy::LastUsed),
+            Some("usage") => Ok(OrderBy::UsageCount),
+            Some(value) => Err(ApiError::bad_request(format!(
+                "Unsupported order '{}'. Supported: relevance, timestamp, last_used, usage",
+                value
+            ))),
+        }
+    }
+
+    fn env(&self) -> std::result::Result<Vec<(String, String)>, ApiError> {
+        self.0
+            .iter()
+            .filter(|(key, _)| key == "env")
+            .map(|(_, filter)| match filter.split_once('=') {
+                Some((name, value)) if !name.is_empty() => {
+                    Ok((name.to_string(), value.to_string()))
+                }
+                _ => Err(ApiError::bad_request(format!(
+                    "Invalid env filter '{}'. Expected NAME=VALUE",
+                    filter
+                ))),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CommandRecord;
+    use chrono::Utc;
+    use tempfile::NamedTempFile;
+
+    fn create_test_storage() -> (NamedTempFile, Storage) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let storage = Storage::new(temp_file.path()).unwrap();
+        for (command, category, exit_code) in [
+            ("git status", "git", 0),
+            ("git push", "git", 1),
+            ("docker ps", "docker", 0),
+        ] {
+            storage
+                .insert(&CommandRecord::new(
+                    command.to_string(),
+                    Utc::now(),
+                    exit_code,
+                    10,
+                    "/home/me/project".to_string(),
+                    category.to_string(),
+                ))
+                .unwrap();
+        }
+        (temp_file, storage)
+    }
+
+    #[test]
+    fn test_access_checks() {
+        assert!(is_loopback("127.0.0.1:7878").unwrap());
+        assert!(is_loopback("[::1]:7878").unwrap());
+        assert!(!is_loopback("0.0.0.0:7878").unwrap());
+        assert!(!is_loopback("192.168.1.5:7878").unwrap());
+
+        // Without a token only loopback host names are answered
+        assert!(check_access(Some("localhost:7878"), None, None).is_ok());
+        assert!(check_access(Some("127.0.0.1:7878"), None, None).is_ok());
+        assert!(check_access(Some("[::1]:7878"), None, None).is_ok());
+        assert_eq!(
+            check_access(Some("evil.example.com:7878"), None, None)
+                .unwrap_err()
+                .status,
+            403
+        );
+        assert!(check_access(None, None, None).is_err());
+
+        // With a token the host doesn't matter, the token does
+        let token = Some("s3cret");
+        assert!(check_access(Some("box:7878"), Some("Bearer s3cret"), token).is_ok());
+        assert_eq!(
+            check_access(Some("localhost"), Some("Bearer nope"), token)
+                .unwrap_err()
+                .status,
+            401
+        );
+        assert!(check_access(Some("localhost"), None, token).is_err());
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_search_params() {
+        let (_file, storage) = create_test_storage();
+
+        let results = handle(&storage, "/search", &params(&[("q", "git")])).unwrap();
+        assert_eq!(results.as_array().unwrap().len(), 2);
+
+        let results = handle(
+            &storage,
+            "/search",
+            &params(&[("q", "git"), ("success", "true"), ("limit", "5")]),
+        )
+        .unwrap();
+        let results = results.as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["command"], "git status");
+
+        let results = handle(&storage, "/search/", &params(&[("category", "docker")])).unwrap();
+        assert_eq!(results[0]["command"], "docker ps");
+
+        let results = handle(
+            &storage,
+            "/search",
+            &params(&[("exclude", "status"), ("exclude_category", "docker")]),
+        )
+        .unwrap();
+        assert!(results
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|r| r["command"] != "git status" && r["command"] != "docker ps"));
+    }
+
+    #[test]
+    fn test_limit_and_offset_are_clamped() {
+        let huge = usize::MAX.to_string();
+        let raw = params(&[("limit", &huge), ("offset", &huge)]);
+        assert_eq!(Params(&raw).limit().unwrap(), MAX_LIMIT);
+        assert_eq!(
+            Params(&raw).offset(MAX_LIMIT).unwrap(),
+            MAX_WINDOW - MAX_LIMIT
+        );
+
+        let raw = params(&[("limit", "5"), ("offset", "20")]);
+        assert_eq!(Params(&raw).limit().unwrap(), 5);
+        assert_eq!(Params(&raw).offset(5).unwrap(), 20);
+
+        // Huge values are answered instead of overflowing SQLite's integers
+        let (_file, storage) = create_test_storage();
+        let raw = params(&[("limit", &huge), ("offset", &huge)]);
+        let results = handle(&storage, "/search", &raw).unwrap();
+        assert!(results.as_array().unwrap().is_empty());
+        let results = handle(&storage, "/recent", &raw).unwrap();
+        assert_eq!(results.as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_stats_and_categories() {
+        let (_file, storage) = create_test_storage();
+
+        let stats = handle(&storage, "/stats", &[]).unwrap();
+        assert_eq!(stats["total_commands"], 3);
+        assert!(stats["success_rate"].as_f64().unwrap() > 66.0);
+
+        let categories = handle(&storage, "/categories", &[]).unwrap();
+        assert_eq!(categories[0]["category"], "git");
+        assert_eq!(categories[0]["count"], 2);
+
+        let recent = handle(&storage, "/recent", &params(&[("limit", "1")])).unwrap();
+        assert_eq!(recent.as_array().unwrap().len(), 1);
+
+        let suggestions = handle(&storage, "/suggest", &params(&[("prefix", "docker")])).unwrap();
+        assert_eq!(suggestions[0]["command"], "docker ps");
+    }
+
+    #[test]
+    fn test_errors() {
+        let (_file, storage) = create_test_storage();
+
+        assert_eq!(handle(&storage, "/nope", &[]).unwrap_err().status, 404);
+        assert_eq!(handle(&storage, "/suggest", &[]).unwrap_err().status, 400);
+        assert_eq!(
+            handle(&storage, "/search", &params(&[("limit", "lots")]))
+                .unwrap_err()
+                .status,
+            400
+        );
+        assert_eq!(
+            handle(&storage, "/search", &params(&[("env", "NOVALUE")]))
+                .unwrap_err()
+                .status,
+            400
+        );
+    }
+}
@@ -0,0 +1,197 @@
+/// Async wrappers for tokio (the `tokio` feature)
+///
+/// SQLite calls block, so calling [`Storage`] or [`CommandCapture`] from an
+/// async task stalls the executor thread. [`AsyncStorage`] and
+/// [`AsyncCapture`] run every call on tokio's blocking thread pool with
+/// `spawn_blocking` instead. Calls through one handle (and its clones) take
+/// turns on a single connection, as the synchronous types do.
+///
+/// Operations without a dedicated method go through
+/// [`AsyncStorage::call`], which runs any closure on the storage.
+use crate::capture::{CaptureOutcome, CaptureRequest, CommandCapture};
+use crate::config::Config;
+use crate::error::{OmniscientError, Result};
+use crate::models::{CommandRecord, SearchQuery, Stats};
+use crate::storage::Storage;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A [`Storage`] usable from async code
+#[derive(Clone)]
+pub struct AsyncStorage {
+    storage: Arc<Mutex<Storage>>,
+}
+
+impl AsyncStorage {
+    /// Wrap an open storage
+    pub fn new(storage: Storage) -> Self {
+        Self {
+            storage: Arc::new(Mutex::new(storage)),
+        }
+    }
+
+    /// Open the storage selected by `config` (see [`Storage::from_config`])
+    pub async fn from_config(config: Config) -> Result<Self> {
+        let storage = blocking(move || Storage::from_config(&config)).await?;
+        Ok(Self::new(storage))
+    }
+
+    /// Run `f` with the storage on the blocking thread pool
+    pub async fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Storage) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let storage = Arc::clone(&self.storage);
+        blocking(move || f(&lock(&storage))).await
+    }
+
+    /// See [`Storage::search`]
+    pub async fn search(&self, query: SearchQuery) -> Result<Vec<CommandRecord>> {
+        self.call(move |storage| storage.search(&query)).await
+    }
+
+    /// See [`Storage::get_recent`]
+    pub async fn get_recent(
+        &self,
+        limit: usize,
+        working_dir: Option<String>,
+        recursive: bool,
+    ) -> Result<Vec<CommandRecord>> {
+        self.call(move |storage| storage.get_recent(limit, working_dir, recursive))
+            .await
+    }
+
+    /// See [`Storage::get_top`]
+    pub async fn get_top(
+        &self,
+        limit: usize,
+        working_dir: Option<String>,
+        recursive: bool,
+    ) -> Result<Vec<CommandRecord>> {
+        self.call(move |storage| storage.get_top(limit, working_dir, recursive))
+            .await
+    }
+
+    /// See [`Storage::suggest`]
+    pub async fn suggest(
+        &self,
+        prefix: String,
+        limit: usize,
+        working_dir: Option<String>,
+        recursive: bool,
+    ) -> Result<Vec<CommandRecord>> {
+        self.call(move |storage| storage.suggest(&prefix, limit, working_dir, recursive))
+            .await
+    }
+
+    /// See [`Storage::get_by_id`]
+    pub async fn get_by_id(&self, id: i64) -> Result<Option<CommandRecord>> {
+        self.call(move |storage| storage.get_by_id(id)).await
+    }
+
+    /// See [`Storage::get_stats`]
+    pub async fn get_stats(&self) -> Result<Stats> {
+        self.call(|storage| storage.get_stats()).await
+    }
+
+    /// See [`Storage::insert`]
+    pub async fn insert(&self, record: CommandRecord) -> Result<i64> {
+        self.call(move |storage| storage.insert(&record)).await
+    }
+}
+
+/// A [`CommandCapture`] usable from async code
+#[derive(Clone)]
+pub struct AsyncCapture {
+    capture: Arc<Mutex<CommandCapture>>,
+}
+
+impl AsyncCapture {
+    /// Set up capture with `config` (see [`CommandCapture::new`])
+    pub async fn new(config: Config) -> Result<Self> {
+        let capture = blocking(move || CommandCapture::new(config)).await?;
+        Ok(Self {
+            capture: Arc::new(Mutex::new(capture)),
+        })
+    }
+
+    /// See [`CommandCapture::capture`]
+    pub async fn capture(
+        &self,
+        command: String,
+        exit_code: i32,
+        duration_ms: i64,
+    ) -> Result<CaptureOutcome> {
+        self.capture_request(CaptureRequest::new(&command, exit_code, duration_ms))
+            .await
+    }
+
+    /// See [`CommandCapture::capture_request`]
+    pub async fn capture_request(&self, request: CaptureRequest) -> Result<CaptureOutcome> {
+        let capture = Arc::clone(&self.capture);
+        blocking(move || lock(&capture).capture_request(request)).await
+    }
+}
+
+/// Run `f` on tokio's blocking thread pool
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| OmniscientError::other(format!("Storage task failed: {}", e)))?
+}
+
+/// Lock a shared handle; a panic in an earlier call leaves the connection
+/// usable, so a poisoned lock is taken over
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_async_storage() {
+        runtime().block_on(async {
+            let storage = AsyncStorage::new(Storage::in_memory().unwrap());
+            let record = CommandRecord::new(
+                "cargo build".to_string(),
+                chrono::Utc::now(),
+                0,
+                1200,
+                "/srv/app".to_string(),
+                "build".to_string(),
+            );
+            let id = storage.insert(record).await.unwrap();
+
+            // Clones share the connection, also when called concurrently
+            let other = storage.clone();
+            let search = tokio::spawn(async move {
+                other
+                    .search(SearchQuery {
+                        text: Some("cargo".to_string()),
+                        ..SearchQuery::default()
+                    })
+                    .await
+            });
+            let stats = storage.get_stats().await.unwrap();
+            assert_eq!(search.await.unwrap().unwrap()[0].id, Some(id));
+            assert_eq!(stats.total_commands, 1);
+
+            let count = storage.call(|storage| storage.count()).await.unwrap();
+            assert_eq!(count, 1);
+        });
+    }
+}
@@ -0,0 +1,341 @@
+/// Automatic database backups (`[backup]`, `omniscient backup`)
+///
+/// Backups are full copies of the database made with SQLite's online backup
+/// API, so unlike exports they keep everything: executions, captured output,
+/// bookmarks and alerts. When enabled, the capture hook writes one daily or
+/// weekly to the backup directory, each copy is checked before it counts,
+/// and only the newest `keep` are kept.
+use crate::config::Config;
+use crate::error::{OmniscientError, Result, ResultExt};
+use crate::models::CommandRecord;
+use crate::rotation::Rotation;
+use crate::storage::Storage;
+use chrono::NaiveDateTime;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// First bytes of every SQLite database file
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+
+/// File names of backups: `history-<stamp>.db`
+const BACKUP_PREFIX: &str = "history-";
+const BACKUP_SUFFIX: &str = ".db";
+const STAMP_FORMAT: &str = "%Y-%m-%d-%H%M%S";
+
+/// How often automatic backups are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupFrequency {
+    #[default]
+    Daily,
+    Weekly,
+}
+
+impl BackupFrequency {
+    /// Minimum number of days between backups
+    pub fn days(self) -> i64 {
+        match self {
+            BackupFrequency::Daily => 1,
+            BackupFrequency::Weekly => 7,
+        }
+    }
+}
+
+/// Rotated backups in a directory
+#[derive(Debug, Clone)]
+pub struct BackupSchedule {
+    rotation: Rotation,
+}
+
+impl BackupSchedule {
+    /// Create a schedule writing to `directory`
+    pub fn new<P: Into<PathBuf>>(directory: P, frequency: BackupFrequency, keep: usize) -> Self {
+        Self {
+            rotation: Rotation::new(directory, BACKUP_PREFIX, BACKUP_SUFFIX, STAMP_FORMAT)
+                .with_interval_days(frequency.days())
+                .with_keep(keep),
+        }
+    }
+
+    /// Create a schedule from the `[backup]` config section
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self::new(
+            config.backup_dir()?,
+            config.backup.frequency,
+            config.backup.keep,
+        ))
+    }
+
+    /// Directory the backups are written to
+    pub fn directory(&self) -> &Path {
+        self.rotation.directory()
+    }
+
+    /// Existing backups, oldest first
+    pub fn existing(&self) -> Result<Vec<(NaiveDateTime, PathBuf)>> {
+        self.rotation.existing()
+    }
+
+    /// Whether a backup should be written at `now` (local time)
+    pub fn is_due(&self, now: NaiveDateTime) -> Result<bool> {
+        self.rotation.is_due(now)
+    }
+
+    /// Write a backup now, check it, then delete backups beyond the
+    /// retention limit
+    ///
+    /// Waits for a backup another process is writing.
+    pub fn backup(&self, storage: &Storage, now: NaiveDateTime) -> Result<PathBuf> {
+        // Only a complete, readable copy replaces an older backup
+        self.rotation
+            .write(now, |partial| write_backup(storage, partial))
+    }
+
+    /// Write a backup if one is due
+    ///
+    /// Returns the path of the new backup, or `None` if none was due or
+    /// another process is already writing it.
+    pub fn run_if_due(&self, storage: &Storage, now: NaiveDateTime) -> Result<Option<PathBuf>> {
+        self.rotation
+            .write_if_due(now, |partial| write_backup(storage, partial))
+    }
+
+    /// Delete the oldest backups beyond the retention limit
+    ///
+    /// Returns the number of files deleted.
+    pub fn prune(&self) -> Result<usize> {
+        self.rotation.prune()
+    }
+}
+
+/// Copy the database to `path` and check the copy
+fn write_backup(storage: &Storage, path: &Path) -> Result<()> {
+    storage.backup_to(path)?;
+    verify(path)
+}
+
+/// Check that a backup is an intact omniscient database
+pub fn verify(path: &Path) -> Result<()> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("open backup {}", path.display()))?;
+
+    let status: String = conn
+        .query_row("PRAGMA quick_check", [], |row| row.get(0))
+        .with_context(|| format!("check backup {}", path.display()))?;
+    if status != "ok" {
+        return Err(OmniscientError::other(format!(
+            "Backup {} is damaged: {}",
+            path.display(),
+            status
+        )));
+    }
+
+    conn.query_row("SELECT COUNT(*) FROM commands", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .with_context(|| format!("{} is not an omniscient database", path.display()))?;
+
+    Ok(())
+}
+
+/// Whether a file is an SQLite database (a backup rather than an export)
+pub fn is_sqlite(path: &Path) -> Result<bool> {
+    let mut magic = [0u8; 16];
+    let mut file = fs::File::open(path)?;
+    Ok(file.read_exact(&mut magic).is_ok() && magic == SQLITE_MAGIC)
+}
+
+/// Read the command records of a backup
+///
+/// The backup is left untouched: a temporary copy is opened, so backups
+/// from older versions are upgraded on the fly.
+pub fn read_records(path: &Path) -> Result<Vec<CommandRecord>> {
+    verify(path)?;
+
+    let copy = std::env::temp_dir().join(format!("omniscient-restore-{}.db", std::process::id()));
+    fs::copy(path, &copy).with_context(|| format!("copy backup {}", path.display()))?;
+    let records = Storage::open(&copy, false).and_then(|storage| storage.get_all());
+
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = fs::remove_file(with_suffix(&copy, suffix));
+    }
+    records
+}
+
+/// Replace the database at `db_path` with a backup
+///
+/// The current database is kept as `<db>.before-restore-<timestamp>`;
+/// returns its path. Its write-ahead log is checkpointed first and, if that
+/// fails (a damaged database), moved along with it, so no recent commands
+/// are lost from the kept copy.
+pub fn replace_database(backup: &Path, db_path: &Path) -> Result<PathBuf> {
+    verify(backup)?;
+
+    let kept = with_suffix(
+        db_path,
+        &format!(
+            ".before-restore-{}",
+            chrono::Local::now().format("%Y%m%d%H%M%S")
+        ),
+    );
+
+    if db_path.exists() {
+        if let Ok(conn) = Connection::open(db_path) {
+            let _ = conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()));
+        }
+        fs::rename(db_path, &kept).with_context(|| format!("move {} aside", db_path.display()))?;
+    }
+    // The write-ahead log belongs to the old database
+    for suffix in ["-wal", "-shm"] {
+        let file = with_suffix(db_path, suffix);
+        if file.exists() {
+            fs::rename(&file, with_suffix(&kept, suffix))
+                .with_context(|| format!("move {} aside", file.display()))?;
+        }
+    }
+
+    // Only a complete copy takes the database's place
+    let partial = with_suffix(db_path, ".restore-partial");
+    let copied = fs::copy(backup, &partial).with_context(|| format!("copy {}", backup.display()));
+    if let Err(e) = copied.and_then(|_| Ok(fs::rename(&partial, db_path)?)) {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+
+    Ok(kept)
+}
+
+/// `path` with `suffix` appended to its file name (`history.db-wal`)
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file = path.as_os_str().to_owned();
+    file.push(suffix);
+    PathBuf::from(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CommandRecord;
+    use chrono::{NaiveDate, Utc};
+    use tempfile::{NamedTempFile, TempDir};
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 3, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn storage() -> (NamedTempFile, Storage) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let storage = Storage::new(temp_file.path()).unwrap();
+        storage
+            .insert(&CommandRecord::new(
+                "git status".to_string(),
+                Utc::now(),
+                0,
+                10,
+                "/tmp".to_string(),
+                "git".to_string(),
+            ))
+            .unwrap();
+        (temp_file, storage)
+    }
+
+    #[test]
+    fn test_backup_schedule() {
+        let dir = TempDir::new().unwrap();
+        let (_file, storage) = storage();
+        let schedule = BackupSchedule::new(dir.path().join("backups"), BackupFrequency::Daily, 2);
+
+        let first = schedule.run_if_due(&storage, at(1, 9)).unwrap().unwrap();
+        assert!(first.ends_with("history-2025-03-01-090000.db"));
+        verify(&first).unwrap();
+        let restored = Storage::new(&first).unwrap();
+        assert_eq!(restored.count().unwrap(), 1);
+
+        // Once per day
+        assert!(schedule.run_if_due(&storage, at(1, 23)).unwrap().is_none());
+        assert!(schedule.run_if_due(&storage, at(2, 8)).unwrap().is_some());
+
+        // Explicit backups are always written; the oldest is rotated out
+        schedule.backup(&storage, at(2, 9)).unwrap();
+        let existing = schedule.existing().unwrap();
+        assert_eq!(existing.len(), 2);
+        assert_eq!(existing[0].0, at(2, 8));
+        assert!(!first.exists());
+
+        let weekly = BackupSchedule::new(dir.path().join("backups"), BackupFrequency::Weekly, 2);
+        assert!(!weekly.is_due(at(8, 9)).unwrap());
+        assert!(weekly.is_due(at(9, 9)).unwrap());
+    }
+
+    #[test]
+    fn test_read_and_replace() {
+        let dir = TempDir::new().unwrap();
+        let (_file, storage) = storage();
+        let backup = dir.path().join("backup.db");
+        storage.backup_to(&backup).unwrap();
+        assert!(is_sqlite(&backup).unwrap());
+
+        let records = read_records(&backup).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].command, "git status");
+
+        let db = dir.path().join("history.db");
+        fs::write(&db, "damaged").unwrap();
+        let kept = replace_database(&backup, &db).unwrap();
+        assert_eq!(fs::read_to_string(kept).unwrap(), "damaged");
+        assert_eq!(Storage::new(&db).unwrap().count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_replace_keeps_write_ahead_log() {
+        let dir = TempDir::new().unwrap();
+        let (_file, storage) = storage();
+        let backup = dir.path().join("backup.db");
+        storage.backup_to(&backup).unwrap();
+
+        // Commands still in the WAL of a database that's open elsewhere
+        let db = dir.path().join("history.db");
+        let live = Storage::new(&db).unwrap();
+        for command in ["ls", "pwd"] {
+            live.insert(&CommandRecord::new(
+                command.to_string(),
+                Utc::now(),
+                0,
+                5,
+                "/tmp".to_string(),
+                "file".to_string(),
+            ))
+            .unwrap();
+        }
+
+        let kept = replace_database(&backup, &db).unwrap();
+        drop(live);
+        assert!(!with_suffix(&db, "-wal").exists());
+        assert!(!with_suffix(&db, ".restore-partial").exists());
+        assert_eq!(Storage::new(&db).unwrap().count().unwrap(), 1);
+        assert_eq!(Storage::open(&kept, false).unwrap().count().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_verify_rejects_other_files() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("history-2025-03-01-090000.db");
+        fs::write(&path, "not a database").unwrap();
+        assert!(!is_sqlite(&path).unwrap());
+        assert!(verify(&path).is_err());
+
+        let empty = dir.path().join("empty.db");
+        Connection::open(&empty)
+            .unwrap()
+            .execute_batch("CREATE TABLE other (id INTEGER)")
+            .unwrap();
+        let err = verify(&empty).unwrap_err();
+        assert!(err.to_string().contains("not an omniscient database"));
+    }
+}
@@ -0,0 +1,414 @@
+/// Benchmarks on a synthetic history (`omniscient bench`)
+///
+/// [`generate`] fills a database with a large, realistic-looking history
+/// (commands from a few dozen tools across projects, mostly successful,
+/// spread over a year), deterministically for a given seed. [`run`] then
+/// times captures, searches and statistics against it and compares them to
+/// the targets below, so regressions in the storage layer show up before
+/// users with big histories notice. `benches/storage.rs` runs the same
+/// operations under criterion.
+use crate::capture::{CaptureRequest, CommandCapture};
+use crate::category::Categorizer;
+use crate::config::{Config, STORAGE_SQLITE};
+use crate::error::{OmniscientError, Result};
+use crate::models::{CommandRecord, ExecutionMeta, SearchQuery};
+use crate::storage::Storage;
+use chrono::{Duration, Utc};
+use std::path::Path;
+use std::time::{Duration as Elapsed, Instant};
+
+/// Target for the 95th percentile capture latency (ms)
+pub const CAPTURE_TARGET_MS: f64 = 20.0;
+
+/// Target for the 95th percentile search latency (ms)
+pub const SEARCH_TARGET_MS: f64 = 100.0;
+
+/// Target for computing `omniscient stats` (ms)
+pub const STATS_TARGET_MS: f64 = 2000.0;
+
+/// Records inserted per transaction while generating
+const GENERATE_BATCH_SIZE: usize = 10_000;
+
+/// Command templates; `{}` placeholders are filled from [`WORDS`]
+const TEMPLATES: &[&str] = &[
+    "git status",
+    "git diff",
+    "git add -p",
+    "git commit -m \"fix {}\"",
+    "git checkout {}",
+    "git push origin {}",
+    "git pull --rebase",
+    "git log --oneline -n 20",
+    "cargo build --release",
+    "cargo test {}",
+    "cargo clippy --all-targets",
+    "npm install",
+    "npm run {}",
+    "docker compose up -d",
+    "docker run --rm -it {}",
+    "docker logs -f {}",
+    "kubectl get pods -n {}",
+    "kubectl logs {} -n prod -f",
+    "kubectl describe deployment {}",
+    "terraform plan -var-file={}.tfvars",
+    "ssh {}.example.com",
+    "curl -s https://api.example.com/{}",
+    "ls -la",
+    "cd {}",
+    "grep -rn \"{}\" src",
+    "vim src/{}.rs",
+    "make {}",
+    "python3 scripts/{}.py",
+    "psql -d {} -c \"select count(*) from users\"",
+    "rg {} --type rust",
+];
+
+/// Words substituted into the templates
+const WORDS: &[&str] = &[
+    "api",
+    "auth",
+    "billing",
+    "cache",
+    "cli",
+    "config",
+    "db",
+    "deploy",
+    "dev",
+    "docs",
+    "events",
+    "feature-login",
+    "gateway",
+    "infra",
+    "jobs",
+    "lint",
+    "main",
+    "metrics",
+    "parser",
+    "payments",
+    "prod",
+    "release",
+    "search",
+    "staging",
+    "storage",
+    "test",
+    "ui",
+    "users",
+    "web",
+    "worker",
+];
+
+/// Projects the synthetic commands run in
+const PROJECTS: &[&str] = &[
+    "api",
+    "billing",
+    "cli",
+    "dashboard",
+    "docs",
+    "dotfiles",
+    "infra",
+    "mobile",
+    "payments",
+    "platform",
+    "scripts",
+    "search",
+    "website",
+    "worker",
+];
+
+/// Searches timed by [`run`]: query text and whether it is a prefix search
+const SEARCHES: &[(&str, bool)] = &[
+    ("docker", false),
+    ("cargo test", false),
+    ("kubectl logs", false),
+    ("git", true),
+    ("payments", false),
+];
+
+/// Small deterministic pseudo-random generator (xorshift64*)
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A number below `n`
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// An element of `items`, earlier ones more likely
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        let a = self.below(items.len());
+        let b = self.below(items.len());
+        items[a.min(b)]
+    }
+}
+
+/// Insert `count` synthetic commands, each with one execution
+///
+/// The same seed always gives the same history. Returns the number of
+/// records inserted.
+pub fn generate(storage: &Storage, count: usize, seed: u64) -> Result<usize> {
+    let categorizer = Categorizer::new();
+    let mut rng = Rng::new(seed);
+    let now = Utc::now();
+
+    let mut inserted = 0;
+    while inserted < count {
+        let size = GENERATE_BATCH_SIZE.min(count - inserted);
+        let records: Vec<CommandRecord> = (0..size)
+            .map(|_| {
+                let command = rng.pick(TEMPLATES).replacen("{}", rng.pick(WORDS), 1);
+                let working_dir = format!("/home/dev/code/{}", rng.pick(PROJECTS));
+                let exit_code = match rng.below(20) {
+                    0 => 1,
+                    1 => 127,
+                    _ => 0,
+                };
+                // Mostly quick commands with a long tail of slow ones
+                let duration_ms = 10u64.pow(1 + rng.below(5) as u32) as i64 + rng.below(100) as i64;
+                let timestamp = now - Duration::seconds(rng.below(365 * 24 * 3600) as i64);
+                let category = categorizer.categorize(&command);
+
+                let mut record = CommandRecord::new(
+                    command,
+                    timestamp,
+                    exit_code,
+                    duration_ms,
+                    working_dir,
+                    category,
+                );
+                record.usage_count = 1 + rng.below(50) as i32;
+                record
+            })
+            .collect();
+
+        storage.with_tx(|| {
+            let ids = storage.insert_batch(&records)?;
+            for (id, record) in ids.iter().zip(&records) {
+                storage.log_execution(
+                    *id,
+                    record.timestamp,
+                    record.exit_code,
+                    record.duration_ms,
+                    &ExecutionMeta::default(),
+                )?;
+            }
+            Ok(())
+        })?;
+        inserted += size;
+    }
+
+    Ok(inserted)
+}
+
+/// Latencies of repeated runs of one operation
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timings {
+    /// Each run's duration in milliseconds, sorted
+    pub samples_ms: Vec<f64>,
+}
+
+impl Timings {
+    /// Time `runs` calls of `operation`
+    pub fn measure<F>(runs: usize, mut operation: F) -> Result<Self>
+    where
+        F: FnMut(usize) -> Result<()>,
+    {
+        let mut samples_ms = Vec::with_capacity(runs);
+        for run in 0..runs {
+            let start = Instant::now();
+            operation(run)?;
+            samples_ms.push(millis(start.elapsed()));
+        }
+        samples_ms.sort_by(f64::total_cmp);
+        Ok(Self { samples_ms })
+    }
+
+    /// The latency `p` percent of runs stayed under (nearest rank)
+    pub fn percentile(&self, p: f64) -> f64 {
+        if self.samples_ms.is_empty() {
+            return 0.0;
+        }
+        let rank = (p / 100.0 * self.samples_ms.len() as f64).ceil() as usize;
+        self.samples_ms[rank.clamp(1, self.samples_ms.len()) - 1]
+    }
+
+    /// Slowest run
+    pub fn max(&self) -> f64 {
+        self.samples_ms.last().copied().unwrap_or(0.0)
+    }
+}
+
+fn millis(elapsed: Elapsed) -> f64 {
+    elapsed.as_secs_f64() * 1000.0
+}
+
+/// Results of a benchmark run
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    /// Commands in the benchmarked database
+    pub records: usize,
+
+    /// Capturing a command, including opening the database
+    pub capture: Timings,
+
+    /// The searches in [`SEARCHES`]
+    pub search: Timings,
+
+    /// Computing the statistics of `omniscient stats`
+    pub stats: Timings,
+}
+
+impl BenchReport {
+    /// Whether every measurement met its target
+    pub fn meets_targets(&self) -> bool {
+        self.capture.percentile(95.0) <= CAPTURE_TARGET_MS
+            && self.search.percentile(95.0) <= SEARCH_TARGET_MS
+            && self.stats.max() <= STATS_TARGET_MS
+    }
+}
+
+/// Time captures, searches and statistics against the database at `path`
+///
+/// `runs` captures and searches are timed; statistics are computed three
+/// times. Captures are stored, so point this at a benchmark database rather
+/// than your real history.
+pub fn run(config: &Config, path: &Path, runs: usize) -> Result<BenchReport> {
+    if !path.exists() {
+        return Err(OmniscientError::other(format!(
+            "No benchmark database at {} (create one with --generate)",
+            path.display()
+        )));
+    }
+
+    let mut config = config.clone();
+    config.storage.storage_type = STORAGE_SQLITE.to_string();
+    config.storage.path = path.to_string_lossy().to_string();
+    config.auto_export.enabled = false;
+    config.backup.enabled = false;
+
+    // Every capture is a fresh process in practice, so it opens the database
+    let capture = Timings::measure(runs, |run| {
+        let capture = CommandCapture::new(config.clone())?;
+        let request = CaptureRequest::new(&format!("cargo test bench_{}", run), 0, 1200);
+        capture.capture_request(request)?;
+        Ok(())
+    })?;
+
+    let storage = Storage::open(path, false)?;
+    let search = Timings::measure(runs, |run| {
+        let (text, prefix) = SEARCHES[run % SEARCHES.len()];
+        let query = SearchQuery {
+            text: Some(text.to_string()),
+            prefix,
+            limit: 20,
+            ..Default::default()
+        };
+        storage.search(&query)?;
+        Ok(())
+    })?;
+
+    let stats = Timings::measure(3, |_| {
+        storage.get_stats()?;
+        Ok(())
+    })?;
+
+    Ok(BenchReport {
+        records: storage.count()?,
+        capture,
+        search,
+        stats,
+    })
+}
+
+/// Parse a record count such as `5000`, `50k` or `1M`
+pub fn parse_count(value: &str) -> Result<usize> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&value[..i], 1_000.0),
+        Some((i, 'm' | 'M')) => (&value[..i], 1_000_000.0),
+        _ => (value, 1.0),
+    };
+
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+        .map(|n| (n * multiplier).round() as usize)
+        .ok_or_else(|| {
+            OmniscientError::other(format!(
+                "Invalid count '{}' (expected e.g. 5000, 50k or 1M)",
+                value
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("5000").unwrap(), 5000);
+        assert_eq!(parse_count("50k").unwrap(), 50_000);
+        assert_eq!(parse_count("1M").unwrap(), 1_000_000);
+        assert_eq!(parse_count("2.5m").unwrap(), 2_500_000);
+        assert!(parse_count("lots").is_err());
+        assert!(parse_count("-1k").is_err());
+    }
+
+    #[test]
+    fn test_percentile() {
+        let timings = Timings {
+            samples_ms: (1..=100).map(f64::from).collect(),
+        };
+        assert_eq!(timings.percentile(50.0), 50.0);
+        assert_eq!(timings.percentile(95.0), 95.0);
+        assert_eq!(timings.max(), 100.0);
+        assert_eq!(Timings::default().percentile(95.0), 0.0);
+    }
+
+    #[test]
+    fn test_generate_and_run() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("bench.db");
+        let storage = Storage::new(&path).unwrap();
+        assert_eq!(generate(&storage, 250, 7).unwrap(), 250);
+        assert_eq!(storage.count().unwrap(), 250);
+        assert!(storage.get_stats().unwrap().by_category.len() > 3);
+
+        // The same seed gives the same history
+        let other = Storage::in_memory().unwrap();
+        generate(&other, 250, 7).unwrap();
+        let commands = |storage: &Storage| -> Vec<String> {
+            let mut all: Vec<String> = storage
+                .get_all()
+                .unwrap()
+                .into_iter()
+                .map(|r| r.command)
+                .collect();
+            all.sort();
+            all
+        };
+        assert_eq!(commands(&storage), commands(&other));
+        drop(storage);
+
+        let report = run(&Config::default(), &path, 5).unwrap();
+        assert_eq!(report.records, 255);
+        assert_eq!(report.capture.samples_ms.len(), 5);
+        assert_eq!(report.search.samples_ms.len(), 5);
+        assert_eq!(report.stats.samples_ms.len(), 3);
+
+        assert!(run(&Config::default(), &dir.path().join("missing.db"), 1).is_err());
+    }
+}
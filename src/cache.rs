@@ -0,0 +1,132 @@
+/// Small least-recently-used cache for query results
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+/// Fixed-capacity LRU cache
+pub struct QueryCache<K, V> {
+    capacity: usize,
+    entries: HashMap<K, V>,
+    /// Keys from least to most recently used
+    order: VecDeque<K>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> QueryCache<K, V> {
+    /// Create a cache holding at most `capacity` entries
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Look up a cached value, marking it as most recently used
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let value = self.entries.get(key)?.clone();
+        self.touch(key);
+        Some(value)
+    }
+
+    /// Store a value, evicting the least recently used entry if full
+    pub fn put(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.insert(key.clone(), value).is_some() {
+            self.touch(&key);
+            return;
+        }
+
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+
+    /// Remove all entries
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    /// Number of cached entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Move a key to the most recently used position
+    fn touch(&mut self, key: &K) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            if let Some(k) = self.order.remove(pos) {
+                self.order.push_back(k);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_and_put() {
+        let mut cache = QueryCache::new(2);
+        assert!(cache.is_empty());
+
+        cache.put("a", 1);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = QueryCache::new(2);
+        cache.put("a", 1);
+        cache.put("b", 2);
+
+        // Touch "a" so "b" becomes the eviction candidate
+        cache.get(&"a");
+        cache.put("c", 3);
+
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"c"), Some(3));
+    }
+
+    #[test]
+    fn test_overwrite_does_not_grow() {
+        let mut cache = QueryCache::new(2);
+        cache.put("a", 1);
+        cache.put("a", 2);
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&"a"), Some(2));
+    }
+
+    #[test]
+    fn test_zero_capacity_stores_nothing() {
+        let mut cache = QueryCache::new(0);
+        cache.put("a", 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut cache = QueryCache::new(4);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.clear();
+
+        assert!(cache.is_empty());
+        assert_eq!(cache.get(&"a"), None);
+    }
+}
@@ -1,94 +1,666 @@
 /// Command capture functionality - integrates redaction, categorization, and storage
+///
+/// # Capture protocol
+///
+/// Hooks for any shell report a finished command by running:
+///
+/// ```text
+/// omniscient capture --exit-code <code> --duration <ms> \
+///     [--expanded <cmd>] [--timestamp <time>] [--host <name>] \
+///     [--capture-id <uuid>] [--output-file <path>] <command>
+/// ```
+///
+/// and may additionally export these environment variables:
+///
+/// - `OMNISCIENT_SESSION` - identifier for the shell session (e.g. `$$` plus a start time)
+/// - `OMNISCIENT_CONTEXT` - free-form label for the current context (e.g. a project name)
+/// - `OMNISCIENT_DISABLE` - when set (and not `0`), nothing is captured
+///
+/// Any variables listed in `capture.env_vars` are read from the same
+/// environment and stored with the execution. When the working directory is
+/// inside a git repository, its branch and `origin` remote are recorded too,
+/// and inside tmux or GNU screen, the session, window and pane.
+///
+/// `--output-file` names a file holding what the command printed. Its last
+/// `capture.output_max_kb` KB are stored when `capture.output` is enabled, and
+/// the file is deleted either way.
+///
+/// `--timestamp` (also spelled `--started-at`) is when the command started,
+/// taken in the shell's preexec hook; without it the command is recorded at
+/// the time of capture, after it finished. It accepts Unix epoch seconds
+/// (fractional allowed, as in zsh's `$EPOCHREALTIME`) or an RFC 3339
+/// timestamp. `--capture-id` is a unique ID the hook chose for this capture
+/// (a UUID, say); a hook that retries passes the same ID again and the
+/// command is only stored once. Without one, `omniscient capture` picks its
+/// own before anything is written, which makes replaying the journal safe.
+/// All fields are validated by
+/// [`CaptureRequest::validate`] before anything is stored.
+use crate::alerts::{self, Watchlist};
 use crate::category::Categorizer;
 use crate::config::Config;
-use crate::error::Result;
-use crate::models::CommandRecord;
+use crate::error::{OmniscientError, Result};
+use crate::git::GitInfo;
+use crate::ignore::IgnoreRules;
+use crate::journal::{CaptureJournal, ReplayReport};
+use crate::models::{Alert, CommandRecord, ExecutionMeta, SkipReason};
+use crate::paths::PathRewriter;
+use crate::pause::Pause;
 use crate::redact::RedactionEngine;
 use crate::storage::Storage;
-use chrono::Utc;
+use crate::tmux::PaneInfo;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
 use std::env;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Environment variable holding the shell session identifier
+pub const SESSION_ENV: &str = "OMNISCIENT_SESSION";
+
+/// Environment variable holding a free-form context label
+pub const CONTEXT_ENV: &str = "OMNISCIENT_CONTEXT";
+
+/// Environment variable marking the detached process that stores a
+/// capture for `capture.async`
+pub const FOREGROUND_ENV: &str = "OMNISCIENT_CAPTURE_FOREGROUND";
+
+/// Maximum length of session, host, context, git and tmux values
+const MAX_META_LEN: usize = 256;
+
+/// Maximum length of a captured environment variable value
+const MAX_ENV_VALUE_LEN: usize = 1024;
+
+/// How far in the future a reported start time may be (to tolerate clock skew)
+const MAX_CLOCK_SKEW_SECS: i64 = 60;
+
+/// Last `max_bytes` of a file, starting at a line boundary, and whether
+/// anything before them was dropped
+fn read_tail(path: &Path, max_bytes: usize) -> std::io::Result<(Vec<u8>, bool)> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let truncated = len > max_bytes as u64;
+    if truncated {
+        file.seek(SeekFrom::Start(len - max_bytes as u64))?;
+    }
+
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    if truncated {
+        let start = bytes
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(0, |newline| newline + 1);
+        bytes.drain(..start);
+    }
+
+    Ok((bytes, truncated))
+}
+
+/// Everything a shell hook can report about a finished command
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureRequest {
+    /// The command as typed
+    pub command: String,
+
+    /// The command after alias expansion, if known
+    pub expanded: Option<String>,
+
+    /// Exit code of the command
+    pub exit_code: i32,
+
+    /// How long the command took (milliseconds)
+    pub duration_ms: i64,
+
+    /// When the command started (defaults to the capture time)
+    pub started_at: Option<DateTime<Utc>>,
+
+    /// Directory the command ran in (defaults to the current directory)
+    pub working_dir: Option<String>,
+
+    /// Session, host and context metadata
+    pub meta: ExecutionMeta,
+
+    /// What the command printed (only its tail if `output_truncated`)
+    pub output: Option<String>,
+
+    /// Whether the start of the output was dropped
+    pub output_truncated: bool,
+}
+
+impl CaptureRequest {
+    /// Create a request with only the required fields
+    pub fn new(command: &str, exit_code: i32, duration_ms: i64) -> Self {
+        Self {
+            command: command.to_string(),
+            exit_code,
+            duration_ms,
+            ..Default::default()
+        }
+    }
+
+    /// Fill session and context from `OMNISCIENT_SESSION` / `OMNISCIENT_CONTEXT`
+    /// when they were not set explicitly
+    pub fn with_env(mut self) -> Self {
+        if self.meta.session.is_none() {
+            self.meta.session = env::var(SESSION_ENV).ok();
+        }
+        if self.meta.context.is_none() {
+            self.meta.context = env::var(CONTEXT_ENV).ok();
+        }
+        self
+    }
+
+    /// Record the values of the named environment variables
+    ///
+    /// Unset and empty variables are left out, as are values too long to be
+    /// useful context (such as a full `PATH`).
+    pub fn with_env_vars(mut self, names: &[String]) -> Self {
+        for name in names {
+            if let Ok(value) = env::var(name) {
+                if !value.is_empty() && value.len() <= MAX_ENV_VALUE_LEN {
+                    self.meta.env.insert(name.clone(), value);
+                }
+            }
+        }
+        self
+    }
+
+    /// Fill branch and repository from the git repository containing `dir`
+    /// when they were not set explicitly
+    pub fn with_git(mut self, dir: &std::path::Path) -> Self {
+        if self.meta.branch.is_none() && self.meta.repo.is_none() {
+            if let Some(info) = GitInfo::detect(dir) {
+                self.meta.branch = info.branch;
+                self.meta.repo = info.repo;
+            }
+        }
+        self
+    }
+
+    /// Fill the tmux (or screen) session, window and pane of this process
+    /// when they were not set explicitly
+    pub fn with_tmux(mut self) -> Self {
+        let meta = &self.meta;
+        if meta.tmux_session.is_none() && meta.tmux_window.is_none() && meta.tmux_pane.is_none() {
+            if let Some(info) = PaneInfo::detect() {
+                self.meta.tmux_session = info.session;
+                self.meta.tmux_window = info.window;
+                self.meta.tmux_pane = info.pane;
+            }
+        }
+        self
+    }
+
+    /// Attach the last `max_bytes` of the output written to `path`
+    ///
+    /// A truncated tail starts at the first complete line; invalid UTF-8 is
+    /// replaced rather than rejected. An unreadable file leaves the output
+    /// empty, since the command itself is still worth recording.
+    pub fn with_output_file(mut self, path: &Path, max_bytes: usize) -> Self {
+        if let Ok((bytes, truncated)) = read_tail(path, max_bytes) {
+            let text = String::from_utf8_lossy(&bytes);
+            let text = text.trim_end();
+            if !text.is_empty() {
+                self.output = Some(text.to_string());
+                self.output_truncated = truncated;
+            }
+        }
+        self
+    }
+
+    /// Give the request a new random capture ID unless it has one, so storing
+    /// it again (such as from the journal) has no effect
+    pub fn with_capture_id(mut self) -> Self {
+        if self.meta.capture_id.is_none() {
+            self.meta.capture_id = Some(uuid::Uuid::new_v4().to_string());
+        }
+        self
+    }
+
+    /// Parse a `--timestamp` value (epoch seconds or RFC 3339)
+    pub fn parse_started_at(value: &str) -> Result<DateTime<Utc>> {
+        let value = value.trim();
+
+        if let Ok(secs) = value.parse::<f64>() {
+            let millis = (secs * 1000.0).round() as i64;
+            return Utc.timestamp_millis_opt(millis).single().ok_or_else(|| {
+                OmniscientError::capture(format!("Invalid --timestamp value '{}'", value))
+            });
+        }
+
+        DateTime::parse_from_rfc3339(value)
+            .map(|dt| dt.with_timezone(&Utc))
+            .map_err(|e| {
+                OmniscientError::capture(format!("Invalid --timestamp value '{}': {}", value, e))
+            })
+    }
+
+    /// Check that all fields are well-formed, normalizing empty metadata to `None`
+    pub fn validate(&mut self) -> Result<()> {
+        if self.duration_ms < 0 {
+            return Err(OmniscientError::capture(format!(
+                "Duration must not be negative (got {})",
+                self.duration_ms
+            )));
+        }
+
+        if let Some(started_at) = self.started_at {
+            if started_at > Utc::now() + Duration::seconds(MAX_CLOCK_SKEW_SECS) {
+                return Err(OmniscientError::capture(format!(
+                    "Start time {} is in the future",
+                    started_at.to_rfc3339()
+                )));
+            }
+        }
+
+        for (name, value) in [
+            ("session", &mut self.meta.session),
+            ("host", &mut self.meta.host),
+            ("context", &mut self.meta.context),
+            ("branch", &mut self.meta.branch),
+            ("repo", &mut self.meta.repo),
+            ("tmux session", &mut self.meta.tmux_session),
+            ("tmux window", &mut self.meta.tmux_window),
+            ("tmux pane", &mut self.meta.tmux_pane),
+            ("capture ID", &mut self.meta.capture_id),
+        ] {
+            if value.as_deref().map(str::trim) == Some("") {
+                *value = None;
+            }
+
+            if let Some(v) = value {
+                if v.len() > MAX_META_LEN {
+                    return Err(OmniscientError::capture(format!(
+                        "{} is longer than {} characters",
+                        name, MAX_META_LEN
+                    )));
+                }
+                if v.chars().any(char::is_control) {
+                    return Err(OmniscientError::capture(format!(
+                        "{} contains control characters",
+                        name
+                    )));
+                }
+            }
+        }
+
+        for (name, value) in &self.meta.env {
+            let valid_name = !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid_name {
+                return Err(OmniscientError::capture(format!(
+                    "'{}' is not a valid environment variable name",
+                    name
+                )));
+            }
+            if value.len() > MAX_ENV_VALUE_LEN || value.chars().any(char::is_control) {
+                return Err(OmniscientError::capture(format!(
+                    "Value of {} is too long or contains control characters",
+                    name
+                )));
+            }
+        }
+
+        if let Some(ref host) = self.meta.host {
+            if host.chars().any(char::is_whitespace) {
+                return Err(OmniscientError::capture(format!(
+                    "Host '{}' contains whitespace",
+                    host
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Run this `omniscient capture` invocation again in a detached background
+/// process (for `capture.async`), without waiting for it
+///
+/// The process gets its own process group, so closing the terminal or
+/// pressing Ctrl-C doesn't interrupt the write.
+pub fn spawn_detached() -> Result<()> {
+    let mut command = std::process::Command::new(env::current_exe()?);
+    command
+        .args(env::args_os().skip(1))
+        .env(FOREGROUND_ENV, "1")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    command.spawn()?;
+    Ok(())
+}
+
+/// Result of a capture attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureOutcome {
+    /// The command was stored (or merged into an existing record) with this ID
+    Stored(i64),
+
+    /// The command was not stored
+    Skipped(SkipReason),
+
+    /// A capture with the same capture ID was already stored, for the
+    /// command with this ID
+    Duplicate(i64),
+}
 
 /// Captures and stores a command execution
 pub struct CommandCapture {
     storage: Storage,
     redactor: RedactionEngine,
     categorizer: Categorizer,
+    paths: PathRewriter,
+    pause: Pause,
+    ignore: IgnoreRules,
+    watchlist: Watchlist,
     config: Config,
 }
 
 impl CommandCapture {
     /// Create a new command capture instance
     pub fn new(config: Config) -> Result<Self> {
-        let db_path = config.database_path()?;
-        let storage = Storage::new(db_path)?;
+        let storage = Storage::from_config(&config)?;
 
         let redactor = RedactionEngine::new(
             config.privacy.redact_patterns.clone(),
             config.privacy.enabled,
         )?;
 
-        let categorizer = Categorizer::new();
+        let categorizer = Categorizer::from_config(&config);
+
+        let paths = PathRewriter::from_config(&config)?;
+
+        let pause = Pause::from_config(&config)?;
+
+        let ignore = IgnoreRules::from_config(&config)?;
+
+        let watchlist = Watchlist::from_config(&config)?;
 
         Ok(Self {
             storage,
             redactor,
             categorizer,
+            paths,
+            pause,
+            ignore,
+            watchlist,
             config,
         })
     }
 
     /// Capture a command and store it
-    pub fn capture(&self, command: &str, exit_code: i32, duration_ms: i64) -> Result<()> {
+    pub fn capture(
+        &self,
+        command: &str,
+        exit_code: i32,
+        duration_ms: i64,
+    ) -> Result<CaptureOutcome> {
+        self.capture_expanded(command, None, exit_code, duration_ms)
+    }
+
+    /// Capture a command along with its alias-expanded form (if the shell provided one)
+    ///
+    /// The expanded command is stored and categorized; the typed text is kept
+    /// alongside it when the two differ.
+    pub fn capture_expanded(
+        &self,
+        command: &str,
+        expanded: Option<&str>,
+        exit_code: i32,
+        duration_ms: i64,
+    ) -> Result<CaptureOutcome> {
+        let mut request = CaptureRequest::new(command, exit_code, duration_ms);
+        request.expanded = expanded.map(str::to_string);
+        self.capture_request(request)
+    }
+
+    /// Validate and store a full capture request
+    ///
+    /// Skipped commands are counted per reason so users can find out why a
+    /// command is missing from their history.
+    pub fn capture_request(&self, mut request: CaptureRequest) -> Result<CaptureOutcome> {
+        // Paused capture never looks at the command
+        if Pause::disabled_by_env() || self.pause.state(Utc::now())?.is_paused() {
+            return self.skip(SkipReason::Paused);
+        }
+
+        // Neither is anything run under an opted-out directory
+        let current_dir = match request.working_dir {
+            Some(ref dir) => Some(PathBuf::from(dir)),
+            None => env::current_dir().ok(),
+        };
+        if current_dir
+            .as_deref()
+            .is_some_and(|dir| self.ignore.is_ignored(dir))
+        {
+            return self.skip(SkipReason::Ignored);
+        }
+
+        request.validate()?;
+
         // Skip if command is empty or whitespace only
-        let command = command.trim();
+        let (command, typed_command) =
+            Self::normalize(&request.command, request.expanded.as_deref());
         if command.is_empty() {
-            return Ok(());
+            return self.skip(SkipReason::Empty);
         }
 
         // Skip if duration is below minimum threshold
-        if duration_ms < self.config.capture.min_duration_ms {
-            return Ok(());
+        if request.duration_ms < self.config.capture.min_duration_ms {
+            return self.skip(SkipReason::TooFast);
         }
 
         // Check if command should be redacted
-        let processed_command = self.redactor.redact(command);
+        let processed_command = self.redactor.redact(&command);
 
         // If redacted, we don't want to store any information
         if processed_command == "[REDACTED]" {
-            return Ok(());
+            return self.skip(SkipReason::Redacted);
         }
 
-        // Get current working directory
-        let working_dir = env::current_dir()
-            .map(|p| p.to_string_lossy().to_string())
-            .unwrap_or_else(|_| "/unknown".to_string());
+        // The typed alias could contain sensitive text the expansion doesn't
+        if let Some(ref typed) = typed_command {
+            if self.redactor.should_redact(typed) {
+                return self.skip(SkipReason::Redacted);
+            }
+        }
+
+        // Never store secrets that happen to live in captured variables
+        for value in request.meta.env.values_mut() {
+            if self.redactor.should_redact(value) {
+                *value = "[REDACTED]".to_string();
+            }
+        }
+
+        // Output lines can leak secrets just like the command itself
+        let output = request.output.as_deref().map(|output| {
+            output
+                .lines()
+                .map(|line| {
+                    if self.redactor.should_redact(line) {
+                        "[REDACTED]"
+                    } else {
+                        line
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        });
+
+        // Get current working directory, collapsing configured aliases
+        let working_dir = current_dir
+            .map(|p| self.paths.canonicalize(&p.to_string_lossy()))
+            .unwrap_or_else(|| "/unknown".to_string());
 
         // Categorize the command
         let category = self.categorizer.categorize(&processed_command);
 
-        // Check if this command already exists
-        if let Some(existing) = self
-            .storage
-            .find_duplicate(&processed_command, &working_dir)?
-        {
-            // Update usage count
-            self.storage.increment_usage(existing.id.unwrap())?;
-        } else {
-            // Create new command record
-            let record = CommandRecord::new(
-                processed_command,
-                Utc::now(),
-                exit_code,
-                duration_ms,
-                working_dir,
-                category,
-            );
-
-            // Insert into storage
-            self.storage.insert(&record)?;
+        let executed_at = request.started_at.unwrap_or_else(Utc::now);
+
+        // Watched dangerous commands are logged (and warned about) once stored
+        let alerts: Vec<&str> = self.watchlist.matches(&processed_command, &working_dir);
+
+        // Other shells may be writing at the same time; the duplicate check
+        // and the writes happen in one transaction that is retried if locked
+        let stored = self.write(|| {
+            // A retried or replayed capture is stored once
+            if let Some(ref capture_id) = request.meta.capture_id {
+                if let Some(command_id) = self.storage.find_capture(capture_id)? {
+                    return Ok(Err(command_id));
+                }
+            }
+
+            // Check if this command already exists
+            let command_id = if let Some(existing) = self.storage.find_duplicate_by(
+                self.config.capture.duplicate_key,
+                &processed_command,
+                &working_dir,
+                request.exit_code,
+            )? {
+                // Update usage count
+                let id = existing.id.unwrap();
+                self.storage.increment_usage(id, executed_at)?;
+                id
+            } else {
+                // Create new command record
+                let mut record = CommandRecord::new(
+                    processed_command.clone(),
+                    executed_at,
+                    request.exit_code,
+                    request.duration_ms,
+                    working_dir.clone(),
+                    category.clone(),
+                );
+                record.typed_command = typed_command.clone();
+
+                // Insert into storage
+                self.storage.insert(&record)?
+            };
+
+            // Log this execution for sequence analysis and reports
+            let execution_id = self.storage.log_execution(
+                command_id,
+                executed_at,
+                request.exit_code,
+                request.duration_ms,
+                &request.meta,
+            )?;
+
+            if let Some(ref output) = output {
+                self.storage
+                    .save_output(execution_id, output, request.output_truncated)?;
+            }
+
+            let mut logged = Vec::new();
+            for rule in &alerts {
+                let mut alert = Alert {
+                    id: None,
+                    command_id,
+                    rule: rule.to_string(),
+                    command: processed_command.clone(),
+                    working_dir: working_dir.clone(),
+                    timestamp: executed_at,
+                };
+                alert.id = Some(self.storage.log_alert(&alert)?);
+                logged.push(alert);
+            }
+
+            Ok(Ok((command_id, logged)))
+        })?;
+        let (command_id, alerts) = match stored {
+            Ok(stored) => stored,
+            Err(command_id) => return Ok(CaptureOutcome::Duplicate(command_id)),
+        };
+
+        // The command is already stored, so a failed warning is not an error
+        if self.config.alerts.warn && !alerts.is_empty() {
+            let path = alerts::warnings_path(&self.config)?;
+            for alert in &alerts {
+                let _ = alerts::queue_warning(&path, alert);
+            }
         }
 
-        Ok(())
+        Ok(CaptureOutcome::Stored(command_id))
+    }
+
+    /// Store the captures the journal kept while the database was unavailable
+    ///
+    /// Everything is replayed in one transaction, so a failure leaves the
+    /// journal to be replayed again. Requests that are no longer valid are
+    /// dropped and counted.
+    pub fn replay_journal(&self, journal: &CaptureJournal) -> Result<ReplayReport> {
+        let report = self.write(|| {
+            let (requests, invalid) = journal.begin_replay()?;
+            self.store_all(requests, invalid)
+        })?;
+
+        journal.finish_replay()?;
+        Ok(report)
+    }
+
+    /// Store captures recorded elsewhere (such as on a remote host) in one
+    /// transaction, counting `invalid` lines that could not be read
+    pub fn replay(&self, requests: &[CaptureRequest], invalid: usize) -> Result<ReplayReport> {
+        self.write(|| self.store_all(requests.to_vec(), invalid))
+    }
+
+    /// Capture each request, counting what was stored, skipped and rejected
+    fn store_all(&self, requests: Vec<CaptureRequest>, invalid: usize) -> Result<ReplayReport> {
+        let mut report = ReplayReport {
+            invalid,
+            ..Default::default()
+        };
+        for request in requests {
+            match self.capture_request(request) {
+                Ok(CaptureOutcome::Stored(_)) => report.stored += 1,
+                Ok(CaptureOutcome::Skipped(_)) => report.skipped += 1,
+                Ok(CaptureOutcome::Duplicate(_)) => report.duplicates += 1,
+                Err(e) if e.is_storage() => return Err(e),
+                Err(_) => report.invalid += 1,
+            }
+        }
+        Ok(report)
+    }
+
+    /// Count a skipped command and report the reason
+    fn skip(&self, reason: SkipReason) -> Result<CaptureOutcome> {
+        self.write(|| self.storage.record_skip(reason))?;
+        Ok(CaptureOutcome::Skipped(reason))
+    }
+
+    /// Run a write, retrying while other processes hold the database lock
+    fn write<T, F>(&self, write: F) -> Result<T>
+    where
+        F: FnMut() -> Result<T>,
+    {
+        self.storage
+            .write_with_retry(self.config.storage.write_retries, write)
+    }
+
+    /// Normalize typed and expanded command text into (command, typed_command)
+    ///
+    /// The expansion becomes the stored command; the typed text is only kept
+    /// when it differs from the expansion.
+    fn normalize(typed: &str, expanded: Option<&str>) -> (String, Option<String>) {
+        let typed = typed.trim();
+
+        match expanded.map(str::trim) {
+            Some(expanded) if !expanded.is_empty() && expanded != typed => {
+                (expanded.to_string(), Some(typed.to_string()))
+            }
+            _ => (typed.to_string(), None),
+        }
     }
 
     /// Get statistics about captured commands
@@ -128,6 +700,42 @@ mod tests {
         assert_eq!(stats.total_commands, 1);
     }
 
+    #[test]
+    fn test_concurrent_captures() {
+        let mut config = create_test_config();
+        // No busy timeout, so lock contention goes through the retry path
+        config.storage.busy_timeout_ms = 0;
+        config.storage.write_retries = 100;
+        let capture = CommandCapture::new(config.clone()).unwrap();
+
+        let shells: Vec<_> = (0..4)
+            .map(|shell| {
+                let config = config.clone();
+                std::thread::spawn(move || {
+                    let capture = CommandCapture::new(config).unwrap();
+                    for i in 0..25 {
+                        capture.capture("git status", 0, 10).unwrap();
+                        capture
+                            .capture(&format!("echo {} {}", shell, i), 0, 10)
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for shell in shells {
+            shell.join().unwrap();
+        }
+
+        let commands = capture.storage.get_all().unwrap();
+        assert_eq!(commands.len(), 101);
+        let git = commands.iter().find(|c| c.command == "git status").unwrap();
+        assert_eq!(git.usage_count, 100);
+        assert_eq!(
+            capture.storage.get_executions(None, false).unwrap().len(),
+            200
+        );
+    }
+
     #[test]
     fn test_capture_duplicate_command() {
         let config = create_test_config();
@@ -228,6 +836,45 @@ mod tests {
         assert_eq!(commands[0].command, "slow command");
     }
 
+    #[test]
+    fn test_capture_reports_and_counts_skip_reasons() {
+        let mut config = create_test_config();
+        config.capture.min_duration_ms = 100;
+        let capture = CommandCapture::new(config).unwrap();
+
+        assert_eq!(
+            capture.capture("   ", 0, 500).unwrap(),
+            CaptureOutcome::Skipped(SkipReason::Empty)
+        );
+        assert_eq!(
+            capture.capture("ls", 0, 5).unwrap(),
+            CaptureOutcome::Skipped(SkipReason::TooFast)
+        );
+        assert_eq!(
+            capture.capture("ls -la", 0, 5).unwrap(),
+            CaptureOutcome::Skipped(SkipReason::TooFast)
+        );
+        assert_eq!(
+            capture.capture("export PASSWORD=x", 0, 500).unwrap(),
+            CaptureOutcome::Skipped(SkipReason::Redacted)
+        );
+        assert!(matches!(
+            capture.capture("cargo build", 0, 500).unwrap(),
+            CaptureOutcome::Stored(_)
+        ));
+
+        let counts = capture.storage.get_skip_counts().unwrap();
+        let summary: Vec<(SkipReason, u64)> = counts.iter().map(|c| (c.reason, c.count)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (SkipReason::TooFast, 2),
+                (SkipReason::Empty, 1),
+                (SkipReason::Redacted, 1)
+            ]
+        );
+    }
+
     #[test]
     fn test_capture_disabled_redaction() {
         let mut config = create_test_config();
@@ -261,4 +908,369 @@ mod tests {
         assert_eq!(commands.len(), 1);
         assert_eq!(commands[0].usage_count, 2);
     }
+
+    #[test]
+    fn test_capture_duplicate_key_with_exit_code() {
+        let mut config = create_test_config();
+        config.capture.duplicate_key = crate::models::DuplicateKey::CommandDirAndExitCode;
+        let capture = CommandCapture::new(config).unwrap();
+
+        capture.capture("terraform apply", 1, 100).unwrap();
+        capture.capture("terraform apply", 0, 100).unwrap();
+        capture.capture("terraform apply", 0, 100).unwrap();
+
+        let commands = capture.storage.get_all().unwrap();
+        assert_eq!(commands.len(), 2);
+        let succeeded = commands.iter().find(|c| c.exit_code == 0).unwrap();
+        assert_eq!(succeeded.usage_count, 2);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            CommandCapture::normalize("gst", Some("git status")),
+            ("git status".to_string(), Some("gst".to_string()))
+        );
+        assert_eq!(
+            CommandCapture::normalize(" ls ", Some("ls")),
+            ("ls".to_string(), None)
+        );
+        assert_eq!(
+            CommandCapture::normalize("ls", Some("  ")),
+            ("ls".to_string(), None)
+        );
+        assert_eq!(
+            CommandCapture::normalize("ls", None),
+            ("ls".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn test_capture_alias_expansion() {
+        let config = create_test_config();
+        let capture = CommandCapture::new(config).unwrap();
+
+        capture
+            .capture_expanded("gst", Some("git status"), 0, 10)
+            .unwrap();
+
+        let commands = capture.storage.get_recent(10, None, false).unwrap();
+        assert_eq!(commands[0].command, "git status");
+        assert_eq!(commands[0].typed_command.as_deref(), Some("gst"));
+        assert_eq!(commands[0].category, "git");
+    }
+
+    #[test]
+    fn test_replay_journal() {
+        let config = create_test_config();
+        let dir = tempfile::TempDir::new().unwrap();
+        let journal = CaptureJournal::new(
+            dir.path().join("history.db.journal"),
+            RedactionEngine::new(Vec::new(), false).unwrap(),
+        );
+
+        let mut request = CaptureRequest::new("cargo build", 0, 1200);
+        request.working_dir = Some("/work/api".to_string());
+        request.started_at = Some(Utc::now() - Duration::minutes(5));
+        journal.append(&request).unwrap();
+        journal
+            .append(&CaptureRequest::new("cargo test", 0, -1))
+            .unwrap();
+
+        let capture = CommandCapture::new(config).unwrap();
+        let report = capture.replay_journal(&journal).unwrap();
+        assert_eq!(report.stored, 1);
+        assert_eq!(report.invalid, 1);
+        assert_eq!(journal.pending().unwrap(), 0);
+
+        let commands = capture.storage.get_recent(10, None, false).unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].working_dir, "/work/api");
+        assert_eq!(commands[0].timestamp, request.started_at.unwrap());
+
+        // Nothing left to replay
+        let report = capture.replay_journal(&journal).unwrap();
+        assert_eq!(report, ReplayReport::default());
+    }
+
+    #[test]
+    fn test_replay_journal_keeps_run_times() {
+        let config = create_test_config();
+        let dir = tempfile::TempDir::new().unwrap();
+        let journal = CaptureJournal::new(
+            dir.path().join("history.db.journal"),
+            RedactionEngine::new(Vec::new(), false).unwrap(),
+        );
+        let run_at = |minutes_ago: i64| {
+            let mut request = CaptureRequest::new("cargo build", 0, 1200);
+            request.working_dir = Some("/work/api".to_string());
+            request.started_at = Some(Utc::now() - Duration::minutes(minutes_ago));
+            request
+        };
+
+        let capture = CommandCapture::new(config).unwrap();
+        capture.capture_request(run_at(30)).unwrap();
+
+        // Reruns are replayed later, the newest before an older one
+        let rerun = run_at(5);
+        journal.append(&rerun).unwrap();
+        journal.append(&run_at(20)).unwrap();
+
+        let report = capture.replay_journal(&journal).unwrap();
+        assert_eq!(report.stored, 2);
+
+        let commands = capture.storage.get_recent(10, None, false).unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].usage_count, 3);
+        assert_eq!(commands[0].last_used, rerun.started_at.unwrap());
+    }
+
+    #[test]
+    fn test_capture_id_is_idempotent() {
+        let config = create_test_config();
+        let capture = CommandCapture::new(config).unwrap();
+
+        let request = CaptureRequest::new("make deploy", 0, 900).with_capture_id();
+        let CaptureOutcome::Stored(id) = capture.capture_request(request.clone()).unwrap() else {
+            panic!("not stored");
+        };
+        assert_eq!(
+            capture.capture_request(request.clone()).unwrap(),
+            CaptureOutcome::Duplicate(id)
+        );
+
+        // Replaying it (say, from a journal that wasn't cleared) adds nothing
+        let fresh = CaptureRequest::new("make deploy", 0, 900).with_capture_id();
+        assert_ne!(fresh.meta.capture_id, request.meta.capture_id);
+        let report = capture.replay(&[request, fresh], 0).unwrap();
+        assert_eq!((report.stored, report.duplicates), (1, 1));
+
+        let commands = capture.storage.get_recent(10, None, false).unwrap();
+        assert_eq!(commands[0].usage_count, 2);
+        let executions = capture.storage.get_command_executions(id, 10).unwrap();
+        assert_eq!(executions.len(), 2);
+        assert!(executions[0].meta.capture_id.is_some());
+    }
+
+    #[test]
+    fn test_capture_redacts_typed_alias() {
+        let config = create_test_config();
+        let capture = CommandCapture::new(config).unwrap();
+
+        capture
+            .capture_expanded("show_password", Some("cat ~/.creds"), 0, 10)
+            .unwrap();
+
+        let stats = capture.stats().unwrap();
+        assert_eq!(stats.total_commands, 0);
+    }
+
+    #[test]
+    fn test_capture_logs_every_execution() {
+        let config = create_test_config();
+        let capture = CommandCapture::new(config).unwrap();
+
+        capture.capture("cargo build", 0, 10).unwrap();
+        capture.capture("cargo test", 0, 10).unwrap();
+        capture.capture("cargo build", 0, 10).unwrap();
+
+        let executions = capture.storage.get_executions(None, false).unwrap();
+        assert_eq!(executions.len(), 3);
+        assert_eq!(executions[2].command, "cargo build");
+    }
+
+    #[test]
+    fn test_parse_started_at() {
+        let epoch = CaptureRequest::parse_started_at("1700000000.25").unwrap();
+        assert_eq!(epoch.timestamp_millis(), 1_700_000_000_250);
+
+        let rfc = CaptureRequest::parse_started_at("2023-11-14T22:13:20Z").unwrap();
+        assert_eq!(rfc.timestamp(), 1_700_000_000);
+
+        assert!(CaptureRequest::parse_started_at("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_validate_request() {
+        let mut request = CaptureRequest::new("ls", 0, 10);
+        request.meta.session = Some("  ".to_string());
+        assert!(request.validate().is_ok());
+        assert!(request.meta.session.is_none());
+
+        let mut negative = CaptureRequest::new("ls", 0, -1);
+        assert!(negative.validate().is_err());
+
+        let mut future = CaptureRequest::new("ls", 0, 10);
+        future.started_at = Some(Utc::now() + Duration::hours(1));
+        assert!(future.validate().is_err());
+
+        let mut bad_host = CaptureRequest::new("ls", 0, 10);
+        bad_host.meta.host = Some("my host".to_string());
+        assert!(bad_host.validate().is_err());
+
+        let mut control = CaptureRequest::new("ls", 0, 10);
+        control.meta.context = Some("a\nb".to_string());
+        assert!(control.validate().is_err());
+    }
+
+    #[test]
+    fn test_capture_request_stores_metadata() {
+        let config = create_test_config();
+        let capture = CommandCapture::new(config).unwrap();
+
+        let started_at = Utc::now() - Duration::minutes(5);
+        let mut request = CaptureRequest::new("make test", 0, 1200);
+        request.started_at = Some(started_at);
+        request.meta = ExecutionMeta {
+            session: Some("fish-42".to_string()),
+            host: Some("buildbox".to_string()),
+            context: Some("release".to_string()),
+            ..Default::default()
+        };
+        capture.capture_request(request).unwrap();
+
+        let commands = capture.storage.get_all().unwrap();
+        assert_eq!(commands[0].timestamp, started_at);
+
+        let executions = capture.storage.get_executions(None, false).unwrap();
+        assert_eq!(executions[0].timestamp, started_at);
+        assert_eq!(executions[0].meta.session.as_deref(), Some("fish-42"));
+        assert_eq!(executions[0].meta.host.as_deref(), Some("buildbox"));
+        assert_eq!(executions[0].meta.context.as_deref(), Some("release"));
+    }
+
+    #[test]
+    fn test_capture_env_vars() {
+        let config = create_test_config();
+        let capture = CommandCapture::new(config).unwrap();
+
+        std::env::set_var("OMNISCIENT_TEST_PROFILE", "prod");
+        std::env::set_var("OMNISCIENT_TEST_EMPTY", "");
+        let names = [
+            "OMNISCIENT_TEST_PROFILE".to_string(),
+            "OMNISCIENT_TEST_EMPTY".to_string(),
+            "OMNISCIENT_TEST_UNSET".to_string(),
+        ];
+        let mut request = CaptureRequest::new("kubectl apply -f .", 0, 10).with_env_vars(&names);
+        request
+            .meta
+            .env
+            .insert("DB_URL".to_string(), "postgres://u:password@db".to_string());
+        capture.capture_request(request).unwrap();
+
+        let executions = capture.storage.get_executions(None, false).unwrap();
+        let env = &executions[0].meta.env;
+        assert_eq!(env.len(), 2);
+        assert_eq!(env["OMNISCIENT_TEST_PROFILE"], "prod");
+        assert_eq!(env["DB_URL"], "[REDACTED]");
+    }
+
+    #[test]
+    fn test_output_file_tail() {
+        let file = NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "first line\nsecond line\nthird\n").unwrap();
+
+        let request = CaptureRequest::new("ls", 0, 10).with_output_file(file.path(), 1024);
+        assert_eq!(
+            request.output.as_deref(),
+            Some("first line\nsecond line\nthird")
+        );
+        assert!(!request.output_truncated);
+
+        // The partial line at the cut is dropped
+        let request = CaptureRequest::new("ls", 0, 10).with_output_file(file.path(), 15);
+        assert_eq!(request.output.as_deref(), Some("third"));
+        assert!(request.output_truncated);
+
+        std::fs::write(file.path(), "\n").unwrap();
+        let request = CaptureRequest::new("ls", 0, 10).with_output_file(file.path(), 1024);
+        assert_eq!(request.output, None);
+
+        let request =
+            CaptureRequest::new("ls", 0, 10).with_output_file(Path::new("/nonexistent"), 1024);
+        assert_eq!(request.output, None);
+    }
+
+    #[test]
+    fn test_capture_output() {
+        let config = create_test_config();
+        let capture = CommandCapture::new(config).unwrap();
+
+        let mut request = CaptureRequest::new("./deploy.sh", 0, 10);
+        request.output = Some("connecting to postgres://u:password@db\ndone".to_string());
+        request.output_truncated = true;
+        let CaptureOutcome::Stored(id) = capture.capture_request(request).unwrap() else {
+            panic!("command was not stored");
+        };
+
+        let output = capture.storage.get_latest_output(id).unwrap().unwrap();
+        assert_eq!(output.text, "[REDACTED]\ndone");
+        assert!(output.truncated);
+    }
+
+    #[test]
+    fn test_validate_env_names() {
+        let mut request = CaptureRequest::new("ls", 0, 10);
+        request
+            .meta
+            .env
+            .insert("BAD NAME".to_string(), "x".to_string());
+        assert!(request.validate().is_err());
+    }
+
+    #[test]
+    fn test_capture_skipped_while_paused() {
+        let config = create_test_config();
+        let pause = Pause::from_config(&config).unwrap();
+        let capture = CommandCapture::new(config).unwrap();
+
+        pause.pause(Some(Utc::now() + Duration::hours(1))).unwrap();
+        assert_eq!(
+            capture.capture("cat client-secrets.txt", 0, 10).unwrap(),
+            CaptureOutcome::Skipped(SkipReason::Paused)
+        );
+
+        pause.resume().unwrap();
+        assert!(matches!(
+            capture.capture("ls", 0, 10).unwrap(),
+            CaptureOutcome::Stored(_)
+        ));
+        assert_eq!(capture.stats().unwrap().total_commands, 1);
+    }
+
+    #[test]
+    fn test_capture_skipped_in_ignored_dir() {
+        let mut config = create_test_config();
+        let cwd = env::current_dir().unwrap();
+        config.privacy.ignore_dirs = vec![cwd.to_string_lossy().to_string()];
+        let capture = CommandCapture::new(config).unwrap();
+
+        assert_eq!(
+            capture.capture("make deploy", 0, 10).unwrap(),
+            CaptureOutcome::Skipped(SkipReason::Ignored)
+        );
+        assert_eq!(capture.stats().unwrap().total_commands, 0);
+    }
+
+    #[test]
+    fn test_capture_logs_alerts() {
+        let config = create_test_config();
+        let warnings = alerts::warnings_path(&config).unwrap();
+        let capture = CommandCapture::new(config).unwrap();
+
+        capture.capture("ls", 0, 10).unwrap();
+        assert!(capture.storage.get_alerts(10).unwrap().is_empty());
+
+        capture
+            .capture("kubectl delete namespace payments", 0, 10)
+            .unwrap();
+        let alerts = capture.storage.get_alerts(10).unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "namespace deleted");
+        assert!(alerts[0].id.is_some());
+
+        let queued = std::fs::read_to_string(&warnings).unwrap();
+        std::fs::remove_file(&warnings).unwrap();
+        assert!(queued.contains("kubectl delete namespace payments"));
+    }
 }
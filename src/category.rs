@@ -1,9 +1,113 @@
 /// Categorization engine for automatically categorizing commands
-use std::collections::HashMap;
+///
+/// Commands are categorized by their first word, except for task runners
+/// and scripts: `npm run test`, `cargo build`, `make deploy` and
+/// `./deploy.sh` are categorized by the script or target they run, so
+/// builds, tests and deployments don't all end up as "package" or "other".
+/// Prefixes such as `sudo`, `env FOO=1` or `nohup` are skipped first.
+///
+/// Pipelines and command lists (`cd api && cargo test`, `cat log | grep
+/// error`) are categorized by their primary tool: the first part that isn't
+/// just setup like `cd` or `export` and that has a category.
+use crate::config::Config;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Commands that run the command following them, with their options that
+/// take a value (`sudo -u deploy systemctl restart nginx`)
+const PREFIX_WRAPPERS: &[(&str, &[&str])] = &[
+    (
+        "sudo",
+        &["-u", "-g", "-C", "-D", "-h", "-p", "-r", "-t", "-U"],
+    ),
+    ("doas", &["-u", "-C"]),
+    ("env", &["-u", "-C", "-S"]),
+    ("nohup", &[]),
+    ("time", &["-f", "-o"]),
+    ("nice", &["-n"]),
+    ("xargs", &["-a", "-d", "-E", "-I", "-L", "-n", "-P", "-s"]),
+];
+
+/// Wrappers that run the command as another user, usually root
+const PRIVILEGE_WRAPPERS: &[&str] = &["sudo", "doas"];
+
+/// Task runners whose first argument names a script or target, with the
+/// words that may come before it (`npm run build`)
+const WRAPPERS: &[(&str, &[&str])] = &[
+    ("npm", &["run", "run-script"]),
+    ("pnpm", &["run"]),
+    ("yarn", &["run"]),
+    ("bun", &["run"]),
+    ("cargo", &[]),
+    ("make", &[]),
+    ("just", &[]),
+];
+
+/// Words in script and target names -> category
+const TARGET_RULES: &[(&str, &[&str])] = &[
+    (
+        "test",
+        &[
+            "test", "tests", "spec", "e2e", "coverage", "bench", "nextest",
+        ],
+    ),
+    (
+        "build",
+        &["build", "compile", "bundle", "dist", "clean", "package"],
+    ),
+    (
+        "deploy",
+        &["deploy", "release", "publish", "ship", "rollout"],
+    ),
+    (
+        "lint",
+        &["lint", "fmt", "format", "clippy", "check", "typecheck"],
+    ),
+    ("dev", &["dev", "start", "serve", "watch"]),
+    (
+        "package",
+        &[
+            "install",
+            "add",
+            "remove",
+            "uninstall",
+            "update",
+            "upgrade",
+            "ci",
+        ],
+    ),
+];
+
+/// Commands that only prepare for the command after them in a list or
+/// pipeline (`cd api && make`, `echo $TOKEN | docker login`)
+const SETUP_COMMANDS: &[&str] = &[
+    "cd", "pushd", "popd", "export", "source", ".", "set", "unset", "echo", "printf", "true",
+    "false", "sleep", "clear",
+];
+
+/// Task runner options that take a value (`make -C src all`)
+const VALUE_OPTIONS: &[&str] = &[
+    "-C",
+    "-f",
+    "--file",
+    "--makefile",
+    "--directory",
+    "--justfile",
+    "--working-directory",
+    "--prefix",
+    "--cwd",
+    "--filter",
+];
+
+/// Extensions of scripts categorized by their file name
+const SCRIPT_EXTENSIONS: &[&str] = &["sh", "bash", "zsh", "py", "rb", "pl", "js", "ts"];
 
 /// Engine for categorizing commands based on the command name
 pub struct Categorizer {
     rules: HashMap<String, String>,
+
+    /// Command names with user-defined rules, which take precedence over
+    /// looking at scripts and targets
+    custom: HashSet<String>,
 }
 
 impl Categorizer {
@@ -93,22 +197,245 @@ impl Categorizer {
             rules.insert(cmd.to_string(), "vcs".to_string());
         }
 
-        Self { rules }
+        Self {
+            rules,
+            custom: HashSet::new(),
+        }
     }
 
-    /// Categorize a command based on its first word
-    pub fn categorize(&self, command: &str) -> String {
-        // Extract the first word (the actual command)
-        let first_word = command.split_whitespace().next().unwrap_or("");
+    /// Create a categorizer with the default rules plus the `[categories]`
+    /// rules from the config
+    pub fn from_config(config: &Config) -> Self {
+        Self::new().with_rules(&config.categories.rules)
+    }
 
-        // Remove any path prefix (e.g., /usr/bin/git -> git)
-        let cmd_name = first_word.rsplit('/').next().unwrap_or(first_word);
+    /// Add custom rules (command name -> category), overriding built-in ones
+    pub fn with_rules(mut self, rules: &BTreeMap<String, String>) -> Self {
+        for (cmd, category) in rules {
+            self.add_rule(cmd, category);
+        }
+        self
+    }
 
-        // Look up in rules, return "other" if not found
+    /// Map a command name to a category, replacing any existing rule
+    pub fn add_rule(&mut self, cmd_name: &str, category: &str) {
         self.rules
-            .get(cmd_name)
-            .cloned()
-            .unwrap_or_else(|| "other".to_string())
+            .insert(cmd_name.to_string(), category.to_string());
+        self.custom.insert(cmd_name.to_string());
+    }
+
+    /// Extract the name a command is categorized by: its first word after
+    /// any wrappers, without a path prefix (e.g. `/usr/bin/git status` ->
+    /// `git`, `sudo -u root systemctl start nginx` -> `systemctl`)
+    pub fn command_name(command: &str) -> &str {
+        let first_word = Self::unwrap(command)
+            .split_whitespace()
+            .next()
+            .unwrap_or("");
+        first_word.rsplit('/').next().unwrap_or(first_word)
+    }
+
+    /// Skip leading wrappers (`sudo`, `env`, `nohup`, `time`, `nice`,
+    /// `xargs`), their options and `VAR=value` assignments
+    ///
+    /// `env AWS_PROFILE=prod terraform apply` becomes `terraform apply`. A
+    /// wrapper without a command after it (`sudo -i`) is returned as is.
+    pub fn unwrap(command: &str) -> &str {
+        let mut rest = command.trim_start();
+        let mut value_options: Option<&[&str]> = None;
+
+        loop {
+            let (word, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if word.is_empty() {
+                return command.trim_start();
+            }
+            let tail = tail.trim_start();
+
+            if let Some(options) = value_options.filter(|_| word.starts_with('-')) {
+                rest = if options.contains(&word) {
+                    // Skip the option's value as well
+                    tail.split_once(char::is_whitespace)
+                        .map_or("", |(_, after)| after.trim_start())
+                } else {
+                    tail
+                };
+                continue;
+            }
+
+            if is_assignment(word) {
+                rest = tail;
+                continue;
+            }
+
+            let name = word.rsplit('/').next().unwrap_or(word);
+            match PREFIX_WRAPPERS.iter().find(|(wrapper, _)| *wrapper == name) {
+                Some((_, options)) => {
+                    value_options = Some(options);
+                    rest = tail;
+                }
+                None => return rest,
+            }
+        }
+    }
+
+    /// Whether any part of a command runs through `sudo` or `doas`
+    ///
+    /// Such commands are categorized by what they run (`sudo apt update` is
+    /// "package"), but run as another user, usually root.
+    pub fn runs_privileged(command: &str) -> bool {
+        Self::segments(command).into_iter().any(|segment| {
+            let segment = segment.trim_start();
+            let unwrapped = Self::unwrap(segment);
+            let wrappers = &segment[..segment.len() - unwrapped.len()];
+            wrappers
+                .split_whitespace()
+                .chain(unwrapped.split_whitespace().next())
+                .any(|word| {
+                    let name = word.rsplit('/').next().unwrap_or(word);
+                    PRIVILEGE_WRAPPERS.contains(&name)
+                })
+        })
+    }
+
+    /// Categorize a command based on its first word, or on the script or
+    /// target it runs; pipelines and lists by their primary tool
+    pub fn categorize(&self, command: &str) -> String {
+        let segments = Self::segments(command);
+        let is_setup = |segment: &&str| Self::is_setup(segment);
+
+        let category = segments
+            .iter()
+            .filter(|segment| !is_setup(segment))
+            .find_map(|segment| self.categorize_simple(segment))
+            .or_else(|| {
+                // Only setup, like a bare `cd`: categorize it as written
+                segments
+                    .iter()
+                    .all(is_setup)
+                    .then(|| self.categorize_simple(segments.first()?))
+                    .flatten()
+            });
+
+        // Return "other" if nothing matched
+        category.unwrap_or("other").to_string()
+    }
+
+    /// Whether a simple command only prepares for the next one (`cd`,
+    /// `export`, `echo`)
+    pub fn is_setup(command: &str) -> bool {
+        SETUP_COMMANDS.contains(&Self::command_name(command))
+    }
+
+    /// Categorize a single simple command, `None` if no rule matches
+    fn categorize_simple(&self, command: &str) -> Option<&str> {
+        let command = Self::unwrap(command);
+        let name = Self::command_name(command);
+
+        if self.custom.contains(name) {
+            return self.rule_for(name);
+        }
+        Self::target(command)
+            .and_then(target_category)
+            .or_else(|| self.rule_for(name))
+            .or_else(|| {
+                Self::is_script(command)
+                    .then(|| target_category(name))
+                    .flatten()
+            })
+    }
+
+    /// Split a command line into the simple commands of its pipelines and
+    /// lists (`|`, `||`, `&&`, `;`, `&` and line breaks)
+    ///
+    /// Quotes, escapes and `$(...)` substitutions are respected, and
+    /// redirections such as `2>&1` don't split. Parts are trimmed, with
+    /// grouping parentheses and braces removed; empty parts are dropped.
+    pub fn segments(command: &str) -> Vec<&str> {
+        let bytes = command.as_bytes();
+        let mut segments = Vec::new();
+        let mut start = 0;
+        let mut quote: Option<u8> = None;
+        let mut depth = 0;
+        let mut i = 0;
+
+        while i < bytes.len() {
+            let byte = bytes[i];
+            match (quote, byte) {
+                (_, b'\\') => i += 1,
+                (Some(q), _) if byte == q => quote = None,
+                (Some(b'\''), _) => {}
+                (_, b'$') if bytes.get(i + 1) == Some(&b'(') => {
+                    depth += 1;
+                    i += 1;
+                }
+                (Some(_), _) => {}
+                (None, b'\'' | b'"' | b'`') => quote = Some(byte),
+                (None, b')') if depth > 0 => depth -= 1,
+                (None, b'|' | b';' | b'\n' | b'&') if depth == 0 => {
+                    let redirect = byte == b'&'
+                        && (matches!(i.checked_sub(1).map(|j| bytes[j]), Some(b'>' | b'<'))
+                            || bytes.get(i + 1) == Some(&b'>'));
+                    if !redirect {
+                        segments.push(&command[start..i]);
+                        // `||`, `&&`, `|&` and `;;` are one separator
+                        if matches!(bytes.get(i + 1), Some(b'|' | b'&' | b';')) {
+                            i += 1;
+                        }
+                        start = i + 1;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        segments.push(&command[start.min(command.len())..]);
+
+        segments
+            .into_iter()
+            .map(|segment| {
+                segment
+                    .trim()
+                    .trim_start_matches(['(', '{', ' '])
+                    .trim_end_matches([')', '}', ' '])
+                    .trim()
+            })
+            .filter(|segment| !segment.is_empty())
+            .collect()
+    }
+
+    /// The script or target a task runner runs (`npm run test` -> `test`)
+    ///
+    /// Flags and `VAR=value` assignments are skipped; returns `None` for
+    /// other commands or when no target is given.
+    pub fn target(command: &str) -> Option<&str> {
+        let mut words = command.split_whitespace();
+        let name = words.next()?;
+        let name = name.rsplit('/').next().unwrap_or(name);
+        let (_, run_words) = WRAPPERS.iter().find(|(wrapper, _)| *wrapper == name)?;
+
+        while let Some(word) = words.next() {
+            if VALUE_OPTIONS.contains(&word) {
+                words.next();
+            } else if !word.starts_with('-') && !word.contains('=') && !run_words.contains(&word) {
+                return Some(word);
+            }
+        }
+        None
+    }
+
+    /// Whether a command runs a script by path (`./deploy.sh`,
+    /// `scripts/build.py`)
+    fn is_script(command: &str) -> bool {
+        let first_word = command.split_whitespace().next().unwrap_or("");
+        first_word.contains('/')
+            || first_word
+                .rsplit_once('.')
+                .is_some_and(|(_, ext)| SCRIPT_EXTENSIONS.contains(&ext))
+    }
+
+    /// Get the category a command name is mapped to, if any
+    pub fn rule_for(&self, cmd_name: &str) -> Option<&str> {
+        self.rules.get(cmd_name).map(String::as_str)
     }
 
     /// Get all available categories
@@ -125,6 +452,34 @@ impl Categorizer {
     }
 }
 
+/// Whether a word is a `NAME=value` environment assignment
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        name.chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Category of a script or target name from the words it is made of
+/// (`test:unit` -> test, `deploy-prod.sh` -> deploy)
+fn target_category(name: &str) -> Option<&'static str> {
+    let stem = name
+        .rsplit_once('.')
+        .filter(|(_, ext)| SCRIPT_EXTENSIONS.contains(ext))
+        .map_or(name, |(stem, _)| stem);
+    let words: Vec<String> = stem
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .map(str::to_lowercase)
+        .collect();
+
+    TARGET_RULES
+        .iter()
+        .find(|(_, keywords)| words.iter().any(|word| keywords.contains(&word.as_str())))
+        .map(|(category, _)| *category)
+}
+
 impl Default for Categorizer {
     fn default() -> Self {
         Self::new()
@@ -164,7 +519,7 @@ mod tests {
         let categorizer = Categorizer::new();
 
         assert_eq!(categorizer.categorize("npm install"), "package");
-        assert_eq!(categorizer.categorize("cargo build"), "package");
+        assert_eq!(categorizer.categorize("cargo add serde"), "package");
         assert_eq!(categorizer.categorize("pip install requests"), "package");
         assert_eq!(categorizer.categorize("brew install git"), "package");
     }
@@ -234,7 +589,7 @@ mod tests {
     fn test_system_categorization() {
         let categorizer = Categorizer::new();
 
-        assert_eq!(categorizer.categorize("sudo apt update"), "system");
+        assert_eq!(categorizer.categorize("sudo"), "system");
         assert_eq!(categorizer.categorize("systemctl status nginx"), "system");
         assert_eq!(categorizer.categorize("ps aux"), "system");
         assert_eq!(categorizer.categorize("top"), "system");
@@ -317,4 +672,175 @@ mod tests {
             "kubernetes"
         );
     }
+
+    #[test]
+    fn test_custom_rules_override_defaults() {
+        let rules: BTreeMap<String, String> = [
+            ("deploy.sh".to_string(), "deploy".to_string()),
+            ("make".to_string(), "ci".to_string()),
+        ]
+        .into();
+        let categorizer = Categorizer::new().with_rules(&rules);
+
+        assert_eq!(categorizer.categorize("./scripts/deploy.sh prod"), "deploy");
+        assert_eq!(categorizer.categorize("make test"), "ci");
+        assert_eq!(categorizer.categorize("git status"), "git");
+        assert!(categorizer.categories().contains(&"deploy".to_string()));
+    }
+
+    #[test]
+    fn test_wrapper_targets() {
+        let categorizer = Categorizer::new();
+
+        assert_eq!(categorizer.categorize("npm run test:unit"), "test");
+        assert_eq!(categorizer.categorize("npm test"), "test");
+        assert_eq!(categorizer.categorize("yarn build"), "build");
+        assert_eq!(categorizer.categorize("pnpm run dev"), "dev");
+        assert_eq!(categorizer.categorize("cargo build --release"), "build");
+        assert_eq!(
+            categorizer.categorize("cargo clippy -- -D warnings"),
+            "lint"
+        );
+        assert_eq!(
+            categorizer.categorize("make -j8 DEBUG=1 deploy-prod"),
+            "deploy"
+        );
+        assert_eq!(categorizer.categorize("just release"), "deploy");
+
+        // Unknown or missing targets fall back to the wrapper's category
+        assert_eq!(categorizer.categorize("npm run storybook"), "package");
+        assert_eq!(categorizer.categorize("make"), "build");
+        assert_eq!(categorizer.categorize("make all"), "build");
+    }
+
+    #[test]
+    fn test_scripts() {
+        let categorizer = Categorizer::new();
+
+        assert_eq!(categorizer.categorize("./deploy.sh prod"), "deploy");
+        assert_eq!(categorizer.categorize("scripts/run-tests"), "test");
+        assert_eq!(categorizer.categorize("lint.py --fix"), "lint");
+        assert_eq!(categorizer.categorize("./backup.sh"), "other");
+        // Only scripts: a plain command named like a target is left alone
+        assert_eq!(categorizer.categorize("deploy prod"), "other");
+        assert_eq!(categorizer.categorize("/usr/bin/git status"), "git");
+    }
+
+    #[test]
+    fn test_custom_rules_override_targets() {
+        let rules: BTreeMap<String, String> = [("npm".to_string(), "js".to_string())].into();
+        let categorizer = Categorizer::new().with_rules(&rules);
+
+        assert_eq!(categorizer.categorize("npm run test"), "js");
+        assert_eq!(categorizer.categorize("yarn test"), "test");
+    }
+
+    #[test]
+    fn test_wrappers_are_skipped() {
+        let categorizer = Categorizer::new();
+
+        assert_eq!(categorizer.categorize("sudo apt update"), "package");
+        assert_eq!(
+            categorizer.categorize("sudo -u www systemctl restart nginx"),
+            "system"
+        );
+        assert_eq!(
+            categorizer.categorize("env AWS_PROFILE=prod terraform apply"),
+            "cloud"
+        );
+        assert_eq!(categorizer.categorize("RUST_LOG=debug cargo test"), "test");
+        assert_eq!(
+            categorizer.categorize("nohup nice -n 10 ./deploy.sh &"),
+            "deploy"
+        );
+        assert_eq!(categorizer.categorize("time -p make"), "build");
+        assert_eq!(categorizer.categorize("xargs -I {} rm {}"), "file");
+        assert_eq!(categorizer.categorize("sudo -i"), "system");
+        assert_eq!(categorizer.categorize("doas -u www rm -rf /x"), "file");
+    }
+
+    #[test]
+    fn test_runs_privileged() {
+        assert!(Categorizer::runs_privileged("sudo apt update"));
+        assert!(Categorizer::runs_privileged("doas rm -rf /x"));
+        assert!(Categorizer::runs_privileged("/usr/bin/sudo -i"));
+        assert!(Categorizer::runs_privileged("env FOO=1 sudo -u www ls"));
+        assert!(Categorizer::runs_privileged("cd /tmp && sudo rm -rf x"));
+        assert!(!Categorizer::runs_privileged("apt update"));
+        assert!(!Categorizer::runs_privileged("grep sudo /var/log/auth.log"));
+    }
+
+    #[test]
+    fn test_unwrap() {
+        assert_eq!(
+            Categorizer::unwrap("  env -u HOME FOO=1 /usr/bin/git status"),
+            "/usr/bin/git status"
+        );
+        assert_eq!(
+            Categorizer::unwrap("git commit -m 'x=1'"),
+            "git commit -m 'x=1'"
+        );
+        assert_eq!(Categorizer::unwrap("sudo -u root"), "sudo -u root");
+        assert_eq!(Categorizer::unwrap("FOO=1"), "FOO=1");
+        assert_eq!(Categorizer::command_name("sudo -E docker ps"), "docker");
+    }
+
+    #[test]
+    fn test_segments() {
+        assert_eq!(
+            Categorizer::segments("cat foo | grep bar | wc -l"),
+            vec!["cat foo", "grep bar", "wc -l"]
+        );
+        assert_eq!(
+            Categorizer::segments("cd x && cargo test || echo 'a | b; c'"),
+            vec!["cd x", "cargo test", "echo 'a | b; c'"]
+        );
+        assert_eq!(
+            Categorizer::segments("make 2>&1 | tee log &"),
+            vec!["make 2>&1", "tee log"]
+        );
+        assert_eq!(
+            Categorizer::segments("(cd api; echo \"$(date | cut -c1-4)\") |& less"),
+            vec!["cd api", "echo \"$(date | cut -c1-4)\"", "less"]
+        );
+        assert_eq!(Categorizer::segments("echo a\\;b"), vec!["echo a\\;b"]);
+        assert!(Categorizer::segments("  ").is_empty());
+    }
+
+    #[test]
+    fn test_compound_commands() {
+        let categorizer = Categorizer::new();
+
+        assert_eq!(categorizer.categorize("cd api && cargo test"), "test");
+        assert_eq!(categorizer.categorize("cat foo | grep bar | wc -l"), "file");
+        assert_eq!(
+            categorizer.categorize("echo $TOKEN | docker login --password-stdin"),
+            "docker"
+        );
+        assert_eq!(
+            categorizer.categorize("export KUBECONFIG=~/k; kubectl get pods | grep api"),
+            "kubernetes"
+        );
+        assert_eq!(
+            categorizer.categorize("mytool render | kubectl apply -f -"),
+            "kubernetes"
+        );
+        assert_eq!(categorizer.categorize("cd ~/src"), "file");
+        assert_eq!(categorizer.categorize("cd ~/src && mytool"), "other");
+    }
+
+    #[test]
+    fn test_target() {
+        assert_eq!(Categorizer::target("npm run build"), Some("build"));
+        assert_eq!(Categorizer::target("make -C src V=1 all"), Some("all"));
+        assert_eq!(Categorizer::target("git status"), None);
+        assert_eq!(Categorizer::target("just"), None);
+    }
+
+    #[test]
+    fn test_command_name() {
+        assert_eq!(Categorizer::command_name("/usr/bin/git status"), "git");
+        assert_eq!(Categorizer::command_name("  ./run.sh -v"), "run.sh");
+        assert_eq!(Categorizer::command_name(""), "");
+    }
 }
@@ -0,0 +1,15 @@
+/// System clipboard access for the `--copy` flags
+///
+/// On X11/Wayland the copied text is served by this process, so it only
+/// outlives the command when a clipboard manager takes it over on exit.
+use crate::error::{OmniscientError, Result};
+
+/// Copy `text` to the system clipboard
+pub fn copy(text: &str) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new().map_err(clipboard_error)?;
+    clipboard.set_text(text).map_err(clipboard_error)
+}
+
+fn clipboard_error(error: arboard::Error) -> OmniscientError {
+    OmniscientError::other(format!("Clipboard unavailable: {}", error))
+}
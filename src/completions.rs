@@ -0,0 +1,131 @@
+/// Shell completion scripts for the omniscient CLI
+///
+/// clap_complete generates completions from the static CLI definition. For
+/// bash, zsh and fish a small wrapper is appended that completes
+/// `omniscient category <TAB>` with the category names in the history
+/// database, fetched through `omniscient completions --list-categories`.
+use crate::error::Result;
+use clap_complete::Shell;
+use std::io::Write;
+
+/// Hidden flag that prints category names, one per line
+pub const LIST_CATEGORIES_FLAG: &str = "--list-categories";
+
+/// Look up a shell by name ("bash", "zsh", "fish", "powershell", "elvish")
+pub fn shell_from_name(name: &str) -> Option<Shell> {
+    match name {
+        "bash" => Some(Shell::Bash),
+        "zsh" => Some(Shell::Zsh),
+        "fish" => Some(Shell::Fish),
+        "powershell" | "pwsh" => Some(Shell::PowerShell),
+        "elvish" => Some(Shell::Elvish),
+        _ => None,
+    }
+}
+
+/// Write the completion script for `shell` to `out`
+pub fn write_completions<W: Write>(
+    shell: Shell,
+    command: &mut clap::Command,
+    out: &mut W,
+) -> Result<()> {
+    let name = command.get_name().to_string();
+
+    // Generate into memory: clap_complete panics if the writer fails
+    let mut script = Vec::new();
+    clap_complete::generate(shell, command, &name, &mut script);
+
+    if let Some(snippet) = category_completion(shell) {
+        writeln!(script)?;
+        write!(script, "{}", snippet.replace("{bin}", &name))?;
+    }
+
+    out.write_all(&script)?;
+    Ok(())
+}
+
+/// Dynamic category completion for shells whose scripts can be extended
+fn category_completion(shell: Shell) -> Option<&'static str> {
+    match shell {
+        Shell::Bash => Some(
+            r#"# Complete category names from the history database
+_{bin}_dynamic() {
+    if [[ ${COMP_CWORD} -eq 2 && "${COMP_WORDS[1]}" == "category" ]]; then
+        local categories
+        categories="$({bin} completions --list-categories 2>/dev/null)"
+        COMPREPLY=( $(compgen -W "${categories}" -- "${COMP_WORDS[COMP_CWORD]}") )
+        return 0
+    fi
+    _{bin} "$@"
+}
+complete -F _{bin}_dynamic -o bashdefault -o default {bin}
+"#,
+        ),
+        Shell::Zsh => Some(
+            r#"# Complete category names from the history database
+_{bin}_dynamic() {
+    if (( CURRENT == 3 )) && [[ "${words[2]}" == "category" ]]; then
+        local -a categories
+        categories=(${(f)"$({bin} completions --list-categories 2>/dev/null)"})
+        _describe -t categories 'category' categories
+        return
+    fi
+    _{bin} "$@"
+}
+compdef _{bin}_dynamic {bin}
+"#,
+        ),
+        Shell::Fish => Some(
+            r#"# Complete category names from the history database
+complete -c {bin} -n "__fish_seen_subcommand_from category" -f -a "({bin} completions --list-categories 2>/dev/null)"
+"#,
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cli() -> clap::Command {
+        clap::Command::new("omniscient")
+            .subcommand(clap::Command::new("category").arg(clap::Arg::new("name")))
+            .subcommand(clap::Command::new("completions").arg(clap::Arg::new("shell")))
+    }
+
+    fn generate(shell: Shell) -> String {
+        let mut out = Vec::new();
+        write_completions(shell, &mut cli(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_dynamic_categories_appended() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = generate(shell);
+            assert!(script.contains("omniscient completions --list-categories"));
+            assert!(!script.contains("{bin}"), "{} script", shell);
+        }
+
+        let bash = generate(Shell::Bash);
+        assert!(bash.contains("_omniscient()"));
+        assert!(bash
+            .trim_end()
+            .ends_with("complete -F _omniscient_dynamic -o bashdefault -o default omniscient"));
+    }
+
+    #[test]
+    fn test_static_only_shells() {
+        let script = generate(Shell::PowerShell);
+        assert!(script.contains("Register-ArgumentCompleter"));
+        assert!(!script.contains(LIST_CATEGORIES_FLAG));
+    }
+
+    #[test]
+    fn test_shell_from_name() {
+        assert_eq!(shell_from_name("zsh"), Some(Shell::Zsh));
+        assert_eq!(shell_from_name("pwsh"), Some(Shell::PowerShell));
+        assert_eq!(shell_from_name("tcsh"), None);
+    }
+}
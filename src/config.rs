@@ -1,8 +1,56 @@
+use crate::backup::BackupFrequency;
 /// Configuration management for Omniscient
-use crate::error::{OmniscientError, Result};
-use serde::{Deserialize, Serialize};
+use crate::error::{OmniscientError, Result, ResultExt};
+use crate::format::{ColorMode, DisplayWidth, ListingFormat};
+use crate::lock::FileLock;
+use crate::models::{parse_duration, DuplicateKey};
+use crate::paths::normalize_separators;
+use crate::timezone::DisplayZone;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Environment variable naming an alternative config file
+pub const CONFIG_ENV: &str = "OMNISCIENT_CONFIG";
+
+/// Environment variable naming an alternative database (overrides `storage.path`)
+pub const DB_ENV: &str = "OMNISCIENT_DB";
+
+/// Environment variable naming one directory for both the config file and
+/// the data, instead of the platform's default locations
+pub const HOME_ENV: &str = "OMNISCIENT_HOME";
+
+/// Whether config and data follow the XDG base directory layout (Linux and
+/// the BSDs)
+const USES_XDG: bool = cfg!(not(any(windows, target_os = "macos")));
+
+/// File in the XDG data directory naming the ~/.omniscient it was moved from
+const MIGRATED_MARKER: &str = "migrated-from";
+
+/// `storage.type` for an SQLite database file at `storage.path`
+pub const STORAGE_SQLITE: &str = "sqlite";
+
+/// `storage.type` for a database that only lives as long as the process
+pub const STORAGE_MEMORY: &str = "memory";
+
+/// Read a millisecond count written either as an integer or a duration string
+fn deserialize_duration_ms<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<i64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawDuration {
+        Millis(i64),
+        Text(String),
+    }
+
+    match RawDuration::deserialize(deserializer)? {
+        RawDuration::Millis(ms) => Ok(ms),
+        RawDuration::Text(text) => parse_duration(&text).map_err(serde::de::Error::custom),
+    }
+}
 
 /// Main configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,17 +58,79 @@ pub struct Config {
     pub storage: StorageConfig,
     pub privacy: PrivacyConfig,
     pub capture: CaptureConfig,
+
+    #[serde(default)]
+    pub auto_export: AutoExportConfig,
+
+    #[serde(default)]
+    pub backup: BackupConfig,
+
+    #[serde(default)]
+    pub paths: PathsConfig,
+
+    #[serde(default)]
+    pub categories: CategoriesConfig,
+
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+
+    #[serde(default)]
+    pub output: OutputConfig,
+
+    #[serde(default)]
+    pub display: DisplayConfig,
+
+    #[serde(default)]
+    pub aliases: AliasesConfig,
+
+    #[serde(default)]
+    pub suggestions: SuggestionsConfig,
+
+    #[serde(default)]
+    pub remote: RemoteConfig,
+
+    #[serde(default)]
+    pub api: ApiConfig,
 }
 
 /// Storage configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StorageConfig {
-    /// Storage type (currently only "sqlite" is supported)
+    /// Storage type: "sqlite", or "memory" for a throwaway in-memory database
     #[serde(rename = "type")]
     pub storage_type: String,
 
     /// Path to the database file
     pub path: String,
+
+    /// Number of query results to cache in long-running processes (0 disables)
+    #[serde(default)]
+    pub query_cache_size: usize,
+
+    /// Copy the database aside before applying schema migrations
+    #[serde(default)]
+    pub backup_before_migrate: bool,
+
+    /// How long to wait for another process's write lock (ms, or a duration
+    /// string like "5s")
+    #[serde(
+        default = "default_busy_timeout_ms",
+        deserialize_with = "deserialize_duration_ms"
+    )]
+    pub busy_timeout_ms: i64,
+
+    /// How many times a capture is retried (with backoff) while the
+    /// database stays locked
+    #[serde(default = "default_write_retries")]
+    pub write_retries: u32,
+}
+
+fn default_busy_timeout_ms() -> i64 {
+    crate::storage::DEFAULT_BUSY_TIMEOUT_MS as i64
+}
+
+fn default_write_retries() -> u32 {
+    5
 }
 
 /// Privacy and redaction configuration
@@ -31,24 +141,465 @@ pub struct PrivacyConfig {
 
     /// Whether redaction is enabled
     pub enabled: bool,
+
+    /// Directories whose commands are never recorded (`~` is expanded);
+    /// a `.omniscientignore` file has the same effect
+    #[serde(default)]
+    pub ignore_dirs: Vec<String>,
 }
 
 /// Capture behavior configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CaptureConfig {
-    /// Minimum command duration to capture (ms)
+    /// Minimum command duration to capture (ms, or a duration string like "2s")
+    #[serde(deserialize_with = "deserialize_duration_ms")]
     pub min_duration_ms: i64,
 
     /// Maximum number of commands to keep in history
     pub max_history_size: usize,
+
+    /// How repeated commands are matched to existing records
+    /// ("command_and_dir", "command_dir_and_exit_code" or "command")
+    #[serde(default)]
+    pub duplicate_key: DuplicateKey,
+
+    /// Environment variables recorded with each execution (e.g. "AWS_PROFILE")
+    #[serde(default)]
+    pub env_vars: Vec<String>,
+
+    /// Record the tail of each command's stdout/stderr (takes effect after
+    /// re-running `omniscient init`)
+    #[serde(default)]
+    pub output: bool,
+
+    /// How much of each command's output is kept (KB, from the end)
+    #[serde(default = "default_output_max_kb")]
+    pub output_max_kb: usize,
+
+    /// Return from `omniscient capture` right away and store the command
+    /// from a detached background process, so the prompt never waits on
+    /// the database (even with hooks that don't background the capture)
+    #[serde(default, rename = "async")]
+    pub async_write: bool,
+}
+
+fn default_output_max_kb() -> usize {
+    16
+}
+
+/// Working directory normalization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathsConfig {
+    /// Resolve symlinks in working directories at capture and query time,
+    /// so a directory reached through a symlinked path keeps one history
+    pub resolve_symlinks: bool,
+
+    /// Directory prefixes to rewrite at capture and query time (from -> to);
+    /// `~` is expanded on both sides
+    pub rewrites: BTreeMap<String, String>,
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        Self {
+            resolve_symlinks: true,
+            rewrites: BTreeMap::new(),
+        }
+    }
+}
+
+/// Custom categorization rules
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CategoriesConfig {
+    /// Command name -> category, overriding the built-in rules
+    /// (written by `omniscient train`)
+    pub rules: BTreeMap<String, String>,
+
+    /// Categories that `omniscient run` only prints unless `--force` is given
+    pub destructive: Vec<String>,
+}
+
+impl Default for CategoriesConfig {
+    fn default() -> Self {
+        Self {
+            rules: BTreeMap::new(),
+            destructive: ["system", "database", "kubernetes", "cloud"]
+                .iter()
+                .map(|c| c.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl CategoriesConfig {
+    /// Whether re-running `command`, categorized as `category`, needs
+    /// `--force`: commands run through `sudo` or `doas` always do
+    pub fn is_destructive(&self, command: &str, category: &str) -> bool {
+        self.destructive.iter().any(|c| c == category)
+            || crate::category::Categorizer::runs_privileged(command)
+    }
+}
+
+/// Watchlist of dangerous commands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertsConfig {
+    /// Whether captured commands are checked against the watchlist
+    pub enabled: bool,
+
+    /// Print a warning in the shell after a watched command ran (takes
+    /// effect after re-running `omniscient init`)
+    pub warn: bool,
+
+    /// Patterns that raise an alert
+    pub watchlist: Vec<AlertRule>,
+}
+
+/// One watched pattern
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    /// Short description shown with the alert
+    pub name: String,
+
+    /// Regex matched against the command
+    pub pattern: String,
+
+    /// Regex the working directory must match (e.g. "prod"); any directory
+    /// when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_pattern: Option<String>,
+}
+
+impl AlertRule {
+    fn new(name: &str, pattern: &str, dir_pattern: Option<&str>) -> Self {
+        Self {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            dir_pattern: dir_pattern.map(str::to_string),
+        }
+    }
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            warn: true,
+            watchlist: vec![
+                AlertRule::new(
+                    "recursive delete of / or ~",
+                    r"\brm\s+(-\S+\s+)*-[a-zA-Z]*[rR][a-zA-Z]*\s+(-\S+\s+)*(/|/\*|~|~/|\$HOME)(\s|$)",
+                    None,
+                ),
+                AlertRule::new(
+                    "namespace deleted",
+                    r"\bkubectl\b.*\bdelete\s+(namespace|ns)\b",
+                    None,
+                ),
+                AlertRule::new(
+                    "terraform destroy in production",
+                    r"\bterraform\s+destroy\b",
+                    Some("prod"),
+                ),
+                AlertRule::new(
+                    "database dropped",
+                    r"(?i)\bdrop\s+(database|schema|table)\b",
+                    None,
+                ),
+                AlertRule::new(
+                    "disk overwritten",
+                    r"\b(mkfs(\.\w+)?|dd\b.*\bof=/dev/)",
+                    None,
+                ),
+            ],
+        }
+    }
+}
+
+/// Automatic, rotated copies of the database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Whether the capture hook writes backups when one is due
+    pub enabled: bool,
+
+    /// How often: "daily" or "weekly"
+    pub frequency: BackupFrequency,
+
+    /// Directory the backups are written to
+    pub directory: String,
+
+    /// Number of backups to keep (older ones are deleted)
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            frequency: BackupFrequency::Daily,
+            directory: default_data_path("backups"),
+            keep: 7,
+        }
+    }
+}
+
+/// Terminal output
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    /// When to color output: "auto" (terminals only, unless NO_COLOR is
+    /// set), "always" or "never"
+    pub color: ColorMode,
+
+    /// How search, recent, here and top print commands: "verbose" or
+    /// "compact" (one line per command)
+    pub format: ListingFormat,
+
+    /// Styles of the colored output
+    pub theme: ThemeConfig,
+}
+
+/// Output styles, each a color and modifiers such as "bright blue bold",
+/// "#5f87af" or "dimmed"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// Successful commands and completed actions
+    pub success: String,
+
+    /// Failed commands and errors
+    pub failure: String,
+
+    /// Warnings
+    pub warning: String,
+
+    /// Timestamps, directories and other secondary details
+    pub metadata: String,
+
+    /// Search matches
+    pub highlight: String,
+
+    /// Charts and branch names
+    pub accent: String,
+
+    /// Category name -> style, overriding the built-in category colors
+    pub categories: BTreeMap<String, String>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            success: "green".to_string(),
+            failure: "red".to_string(),
+            warning: "yellow".to_string(),
+            metadata: "dimmed".to_string(),
+            highlight: "bold underline".to_string(),
+            accent: "cyan".to_string(),
+            categories: BTreeMap::new(),
+        }
+    }
+}
+
+/// Thresholds of `omniscient aliases suggest`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AliasesConfig {
+    /// Shortest command (in characters) worth an alias
+    pub min_length: usize,
+
+    /// Fewest runs of a command worth an alias
+    pub min_uses: i64,
+}
+
+impl Default for AliasesConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 20,
+            min_uses: 5,
+        }
+    }
+}
+
+/// How listings lay out commands
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Width long commands are truncated to in listings: "auto" (the
+    /// terminal's width), "full" (never truncate) or a number of columns;
+    /// `--full` disables truncation for one listing
+    pub width: DisplayWidth,
+
+    /// Time zone timestamps are shown in: "local" (the system's), "UTC" or
+    /// an IANA name such as "Europe/Berlin"
+    pub timezone: DisplayZone,
+}
+
+/// Suggestions printed by the shell hook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SuggestionsConfig {
+    /// Show the most used commands of a directory when you `cd` into it
+    /// (takes effect after re-running `omniscient init`)
+    pub on_cd: bool,
+
+    /// Number of commands to show
+    pub count: usize,
+}
+
+impl Default for SuggestionsConfig {
+    fn default() -> Self {
+        Self {
+            on_cd: false,
+            count: 3,
+        }
+    }
+}
+
+/// Hosts captured over SSH (see `omniscient remote-install`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// Host name -> SSH destination, synced by `omniscient remote-sync`
+    pub hosts: BTreeMap<String, String>,
+}
+
+/// HTTP API (`omniscient serve --api`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Token clients must send as `Authorization: Bearer <token>`; needed to
+    /// listen on an address other than loopback (empty: no token)
+    pub token: String,
+}
+
+/// Automatic, rotated exports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoExportConfig {
+    /// Whether automatic exports are enabled
+    pub enabled: bool,
+
+    /// Directory the exports are written to
+    pub directory: String,
+
+    /// Minimum number of days between exports
+    pub interval_days: u32,
+
+    /// Number of exports to keep (older ones are deleted)
+    pub keep: usize,
+}
+
+impl Default for AutoExportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: default_data_path("exports"),
+            interval_days: 1,
+            keep: 7,
+        }
+    }
+}
+
+/// Default path of `name` in the data directory, as written in the config
+fn default_data_path(name: &str) -> String {
+    match (Config::omniscient_dir(), Config::home_dir()) {
+        (Ok(dir), Ok(home)) => normalize_separators(&tilde_path(&dir.join(name), &home)),
+        _ => format!("~/.omniscient/{}", name),
+    }
+}
+
+/// `path` with a leading `home` written as `~`
+fn tilde_path(path: &Path, home: &Path) -> String {
+    match path.strip_prefix(home) {
+        Ok(rest) => Path::new("~").join(rest).to_string_lossy().into_owned(),
+        Err(_) => path.to_string_lossy().into_owned(),
+    }
+}
+
+/// `<base>/omniscient` for a platform base directory
+fn platform_dir(base: Option<PathBuf>) -> Result<PathBuf> {
+    base.map(|dir| dir.join("omniscient"))
+        .ok_or(OmniscientError::NoHomeDir)
+}
+
+/// Copy the config file of `legacy` to `config_dir` with the paths that
+/// pointed into `legacy` updated, then move `legacy` to `data_dir`
+///
+/// The config is written first: until the data directory moves, every
+/// process keeps reading the old one, and once it has moved the new config
+/// is in place. Nothing moves if `data_dir` or the new config file already
+/// exist. Returns whether `legacy` was moved.
+fn migrate_dir(legacy: &Path, data_dir: &Path, config_dir: &Path, home: &Path) -> Result<bool> {
+    let config_path = config_dir.join("config.toml");
+    if !legacy.is_dir() || data_dir.exists() || config_path.exists() {
+        return Ok(false);
+    }
+
+    if let Some(parent) = data_dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let old_config = legacy.join("config.toml");
+    let copied_config = old_config.exists();
+    if copied_config {
+        let mut contents = fs::read_to_string(&old_config)
+            .with_context(|| format!("read config {}", old_config.display()))?;
+        let new_dir = tilde_path(data_dir, home);
+        for old_dir in [
+            tilde_path(legacy, home),
+            legacy.to_string_lossy().into_owned(),
+        ] {
+            for quote in ['"', '\''] {
+                for end in ['/', quote] {
+                    contents = contents.replace(
+                        &format!("{}{}{}", quote, old_dir, end),
+                        &format!("{}{}{}", quote, new_dir, end),
+                    );
+                }
+            }
+        }
+
+        fs::create_dir_all(config_dir)?;
+        let partial = config_dir.join(".config.toml.partial");
+        fs::write(&partial, contents)
+            .with_context(|| format!("write config {}", partial.display()))?;
+        fs::rename(&partial, &config_path)
+            .with_context(|| format!("write config {}", config_path.display()))?;
+    }
+
+    if let Err(e) = fs::rename(legacy, data_dir) {
+        // Keep using ~/.omniscient with its own config
+        if copied_config {
+            let _ = fs::remove_file(&config_path);
+        }
+        return Err(e)
+            .with_context(|| format!("move {} to {}", legacy.display(), data_dir.display()));
+    }
+
+    if copied_config {
+        fs::remove_file(data_dir.join("config.toml"))?;
+    }
+    fs::write(
+        data_dir.join(MIGRATED_MARKER),
+        format!("{}\n", legacy.display()),
+    )?;
+
+    Ok(true)
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             storage: StorageConfig {
-                storage_type: "sqlite".to_string(),
-                path: "~/.omniscient/history.db".to_string(),
+                storage_type: STORAGE_SQLITE.to_string(),
+                path: default_data_path("history.db"),
+                query_cache_size: 0,
+                backup_before_migrate: false,
+                busy_timeout_ms: default_busy_timeout_ms(),
+                write_retries: default_write_retries(),
             },
             privacy: PrivacyConfig {
                 redact_patterns: vec![
@@ -59,11 +610,28 @@ impl Default for Config {
                     "apikey".to_string(),
                 ],
                 enabled: true,
+                ignore_dirs: Vec::new(),
             },
             capture: CaptureConfig {
                 min_duration_ms: 0,
                 max_history_size: 100_000,
+                duplicate_key: DuplicateKey::CommandAndDir,
+                env_vars: Vec::new(),
+                output: false,
+                output_max_kb: default_output_max_kb(),
+                async_write: false,
             },
+            auto_export: AutoExportConfig::default(),
+            backup: BackupConfig::default(),
+            paths: PathsConfig::default(),
+            categories: CategoriesConfig::default(),
+            alerts: AlertsConfig::default(),
+            output: OutputConfig::default(),
+            display: DisplayConfig::default(),
+            aliases: AliasesConfig::default(),
+            suggestions: SuggestionsConfig::default(),
+            remote: RemoteConfig::default(),
+            api: ApiConfig::default(),
         }
     }
 }
@@ -71,24 +639,33 @@ impl Default for Config {
 impl Config {
     /// Load configuration from file, or create default if it doesn't exist
     pub fn load() -> Result<Self> {
-        let config_path = Self::config_path()?;
+        Self::load_from(&Self::config_path()?)
+    }
 
+    /// Load configuration from `config_path`, creating a default file there
+    /// if it doesn't exist
+    pub fn load_from(config_path: &Path) -> Result<Self> {
         if !config_path.exists() {
             let config = Self::default();
-            config.save()?;
+            config.save_to(config_path)?;
             return Ok(config);
         }
 
-        let contents = fs::read_to_string(&config_path)?;
-        let config: Config = toml::from_str(&contents)?;
+        let contents = fs::read_to_string(config_path)
+            .with_context(|| format!("read config {}", config_path.display()))?;
+        let config: Config = toml::from_str(&contents)
+            .with_context(|| format!("parse config {}", config_path.display()))?;
 
         Ok(config)
     }
 
     /// Save configuration to file
     pub fn save(&self) -> Result<()> {
-        let config_path = Self::config_path()?;
+        self.save_to(&Self::config_path()?)
+    }
 
+    /// Save configuration to `config_path`
+    pub fn save_to(&self, config_path: &Path) -> Result<()> {
         // Create parent directory if it doesn't exist
         if let Some(parent) = config_path.parent() {
             fs::create_dir_all(parent)?;
@@ -97,21 +674,124 @@ impl Config {
         let toml_string = toml::to_string_pretty(self)
             .map_err(|e| OmniscientError::config(format!("Failed to serialize config: {}", e)))?;
 
-        fs::write(&config_path, toml_string)?;
+        fs::write(config_path, toml_string)
+            .with_context(|| format!("write config {}", config_path.display()))?;
 
         Ok(())
     }
 
-    /// Get the path to the configuration file
+    /// Get the path to the configuration file (`$OMNISCIENT_CONFIG` or
+    /// `config.toml` in [`Config::config_dir`])
     pub fn config_path() -> Result<PathBuf> {
-        let omniscient_dir = Self::omniscient_dir()?;
-        Ok(omniscient_dir.join("config.toml"))
+        if let Some(path) = env::var_os(CONFIG_ENV).filter(|p| !p.is_empty()) {
+            return Ok(PathBuf::from(path));
+        }
+
+        Ok(Self::config_dir()?.join("config.toml"))
     }
 
-    /// Get the Omniscient data directory (~/.omniscient)
+    /// Get the Omniscient data directory (database, exports, backups)
+    ///
+    /// `$OMNISCIENT_HOME` if set, otherwise `$XDG_DATA_HOME/omniscient`
+    /// (~/.local/share/omniscient) on Linux, %APPDATA%\omniscient on Windows
+    /// and ~/.omniscient on macOS. A ~/.omniscient directory that hasn't been
+    /// migrated yet (see [`Config::migrate_legacy_dir`]) is still used, but
+    /// only until the XDG data directory exists.
     pub fn omniscient_dir() -> Result<PathBuf> {
-        let home = Self::home_dir()?;
-        Ok(home.join(".omniscient"))
+        if let Some(dir) = Self::home_override() {
+            return Ok(dir);
+        }
+        if cfg!(windows) {
+            return platform_dir(dirs::data_dir());
+        }
+
+        if Self::uses_legacy_dir()? {
+            return Self::legacy_dir();
+        }
+        platform_dir(dirs::data_dir())
+    }
+
+    /// Get the directory of the config file
+    ///
+    /// `$XDG_CONFIG_HOME/omniscient` (~/.config/omniscient) on Linux, the
+    /// data directory everywhere else and with `$OMNISCIENT_HOME` or an
+    /// unmigrated ~/.omniscient.
+    pub fn config_dir() -> Result<PathBuf> {
+        if USES_XDG && Self::home_override().is_none() && !Self::uses_legacy_dir()? {
+            return platform_dir(dirs::config_dir());
+        }
+        Self::omniscient_dir()
+    }
+
+    /// Whether ~/.omniscient holds the data: always on macOS, and on XDG
+    /// platforms while it exists and the XDG data directory doesn't
+    fn uses_legacy_dir() -> Result<bool> {
+        if !USES_XDG {
+            return Ok(true);
+        }
+        Ok(!platform_dir(dirs::data_dir())?.exists() && Self::legacy_dir()?.is_dir())
+    }
+
+    /// The ~/.omniscient the XDG data directory was moved from, if it was
+    pub fn migrated_from() -> Option<PathBuf> {
+        let marker = platform_dir(dirs::data_dir()).ok()?.join(MIGRATED_MARKER);
+        let legacy = fs::read_to_string(marker).ok()?;
+        Some(PathBuf::from(legacy.trim_end()))
+    }
+
+    /// Refuse a missing path inside a ~/.omniscient that was moved to the XDG
+    /// directories, such as `--config ~/.omniscient/config.toml` pinned by an
+    /// old service file, instead of starting over with an empty file there
+    pub fn check_not_moved(path: &Path) -> Result<()> {
+        match Self::migrated_from() {
+            Some(legacy) if path.starts_with(&legacy) && !path.exists() => {
+                Err(OmniscientError::config(format!(
+                    "{} no longer exists: {} was moved to {} (config: {}). \
+                     Use the new path, or run 'omniscient daemon install' again for services.",
+                    path.display(),
+                    legacy.display(),
+                    platform_dir(dirs::data_dir())?.display(),
+                    Self::config_dir()?.join("config.toml").display()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The directory used before the XDG layout (~/.omniscient)
+    pub fn legacy_dir() -> Result<PathBuf> {
+        Ok(Self::home_dir()?.join(".omniscient"))
+    }
+
+    /// Move ~/.omniscient to the XDG directories: the config file to
+    /// [`Config::config_dir`] and everything else to
+    /// [`Config::omniscient_dir`]
+    ///
+    /// Only on XDG platforms without `$OMNISCIENT_HOME`, and only if the new
+    /// directories aren't in use yet. Paths in the config that pointed into
+    /// ~/.omniscient are updated. Processes migrating at the same time take
+    /// turns on a lock file. Returns the new data directory if the old one
+    /// was moved.
+    pub fn migrate_legacy_dir() -> Result<Option<PathBuf>> {
+        if !USES_XDG || Self::home_override().is_some() || !Self::uses_legacy_dir()? {
+            return Ok(None);
+        }
+
+        let data_dir = platform_dir(dirs::data_dir())?;
+        let config_dir = platform_dir(dirs::config_dir())?;
+        let lock_path = data_dir.with_file_name(".omniscient-migrate.lock");
+        let _lock = FileLock::acquire(&lock_path)?;
+
+        let legacy = Self::legacy_dir()?;
+        let moved = migrate_dir(&legacy, &data_dir, &config_dir, &Self::home_dir()?)?;
+        Ok(moved.then_some(data_dir))
+    }
+
+    /// The directory from `$OMNISCIENT_HOME`
+    fn home_override() -> Option<PathBuf> {
+        env::var_os(HOME_ENV)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
     }
 
     /// Get the user's home directory
@@ -119,9 +799,20 @@ impl Config {
         dirs::home_dir().ok_or(OmniscientError::NoHomeDir)
     }
 
+    /// The rest of a path starting with `~/` (or `~\` on Windows)
+    pub fn strip_tilde(path: &str) -> Option<&str> {
+        path.strip_prefix("~/").or_else(|| {
+            if cfg!(windows) {
+                path.strip_prefix("~\\")
+            } else {
+                None
+            }
+        })
+    }
+
     /// Expand tilde (~) in paths to home directory
     pub fn expand_path(&self, path: &str) -> Result<PathBuf> {
-        if let Some(stripped) = path.strip_prefix("~/") {
+        if let Some(stripped) = Self::strip_tilde(path) {
             let home = Self::home_dir()?;
             Ok(home.join(stripped))
         } else if path == "~" {
@@ -131,11 +822,243 @@ impl Config {
         }
     }
 
+    /// Read a setting by dotted key (e.g. "privacy.enabled")
+    ///
+    /// Segments containing dots can be quoted:
+    /// `categories.rules."deploy.sh"`.
+    pub fn get_value(&self, key: &str) -> Result<toml::Value> {
+        self.get_path(&Self::split_key(key)?)
+            .map_err(|_| OmniscientError::config(format!("Unknown config key '{}'", key)))
+    }
+
+    fn get_path(&self, segments: &[String]) -> Result<toml::Value> {
+        let root = toml::Value::try_from(self)
+            .map_err(|e| OmniscientError::config(format!("Failed to serialize config: {}", e)))?;
+
+        let mut value = &root;
+        for segment in segments {
+            value = value.get(segment.as_str()).ok_or_else(|| {
+                OmniscientError::config(format!("Unknown config key '{}'", segments.join(".")))
+            })?;
+        }
+
+        Ok(value.clone())
+    }
+
+    /// Write the setting at dotted `key` to `config_path`, leaving the rest
+    /// of the file as written
+    pub fn save_key(&self, config_path: &Path, key: &str) -> Result<()> {
+        self.save_paths(config_path, &[Self::split_key(key)?])
+    }
+
+    /// Write the settings at `paths` (key segments, such as `["categories",
+    /// "rules", "deploy.sh"]`) to `config_path`
+    ///
+    /// Only those keys are replaced or added; comments, ordering and
+    /// formatting elsewhere in the file are kept. Without a file, the whole
+    /// configuration is saved.
+    pub fn save_paths(&self, config_path: &Path, paths: &[Vec<String>]) -> Result<()> {
+        if !config_path.exists() {
+            return self.save_to(config_path);
+        }
+
+        let contents = fs::read_to_string(config_path)
+            .with_context(|| format!("read config {}", config_path.display()))?;
+        let mut document: toml_edit::DocumentMut = contents.parse().map_err(|e| {
+            OmniscientError::config(format!(
+                "Failed to parse config {}: {}",
+                config_path.display(),
+                e
+            ))
+        })?;
+
+        for segments in paths {
+            let key = segments.join(".");
+            let not_a_table =
+                || OmniscientError::config(format!("Cannot set '{}': parent is not a table", key));
+
+            let value: toml_edit::Value =
+                self.get_path(segments)?.to_string().parse().map_err(|e| {
+                    OmniscientError::config(format!("Failed to serialize '{}': {}", key, e))
+                })?;
+
+            let (last, parents) = segments
+                .split_last()
+                .ok_or_else(|| OmniscientError::config("Empty config key"))?;
+            let mut table = document.as_table_mut() as &mut dyn toml_edit::TableLike;
+            for segment in parents {
+                table = table
+                    .entry(segment)
+                    .or_insert_with(|| {
+                        let mut new = toml_edit::Table::new();
+                        new.set_implicit(true);
+                        toml_edit::Item::Table(new)
+                    })
+                    .as_table_like_mut()
+                    .ok_or_else(not_a_table)?;
+            }
+
+            // Keep the comment after the old value
+            let mut value = value;
+            if let Some(old) = table.get(last).and_then(toml_edit::Item::as_value) {
+                *value.decor_mut() = old.decor().clone();
+            }
+            table.insert(last, toml_edit::Item::Value(value));
+        }
+
+        fs::write(config_path, document.to_string())
+            .with_context(|| format!("write config {}", config_path.display()))?;
+
+        Ok(())
+    }
+
+    /// Change a setting by dotted key, validating the result
+    ///
+    /// `raw` is parsed as a TOML value (`true`, `100`, `["a", "b"]`) and
+    /// falls back to a plain string, so `capture.min_duration_ms 2s` works.
+    /// Lists also accept comma-separated items. Nothing is changed if the
+    /// key is unknown or the new value is invalid.
+    pub fn set_value(&mut self, key: &str, raw: &str) -> Result<()> {
+        let segments = Self::split_key(key)?;
+        let unknown = || OmniscientError::config(format!("Unknown config key '{}'", key));
+
+        let mut root = toml::Value::try_from(&*self)
+            .map_err(|e| OmniscientError::config(format!("Failed to serialize config: {}", e)))?;
+
+        // Walk to the parent table; only the last key may be new (map entries)
+        let (last, parents) = segments.split_last().ok_or_else(unknown)?;
+        let mut table = root.as_table_mut().ok_or_else(unknown)?;
+        for segment in parents {
+            table = table
+                .get_mut(segment.as_str())
+                .and_then(toml::Value::as_table_mut)
+                .ok_or_else(unknown)?;
+        }
+
+        let value = Self::parse_raw_value(raw, table.get(last.as_str()));
+        if value.is_table() {
+            return Err(OmniscientError::config(format!(
+                "'{}' is a section; set one of its keys instead",
+                key
+            )));
+        }
+        table.insert(last.clone(), value);
+
+        let updated: Config = root.try_into().map_err(|e: toml::de::Error| {
+            OmniscientError::config(format!("Invalid value for '{}': {}", key, e.message()))
+        })?;
+        updated.validate()?;
+
+        // Keys serde doesn't know are dropped on the way back in
+        updated.get_value(key).map_err(|_| unknown())?;
+
+        *self = updated;
+        Ok(())
+    }
+
+    /// Check settings that deserialize fine but cannot work
+    pub fn validate(&self) -> Result<()> {
+        if ![STORAGE_SQLITE, STORAGE_MEMORY].contains(&self.storage.storage_type.as_str()) {
+            return Err(OmniscientError::config(format!(
+                "Unsupported storage type '{}' (supported: \"{}\", \"{}\")",
+                self.storage.storage_type, STORAGE_SQLITE, STORAGE_MEMORY
+            )));
+        }
+
+        if self.capture.max_history_size == 0 {
+            return Err(OmniscientError::config(
+                "capture.max_history_size must be greater than 0",
+            ));
+        }
+
+        if self.capture.output_max_kb == 0 {
+            return Err(OmniscientError::config(
+                "capture.output_max_kb must be greater than 0",
+            ));
+        }
+
+        crate::alerts::Watchlist::new(&self.alerts.watchlist)?;
+
+        #[cfg(feature = "cli")]
+        crate::theme::Theme::from_config(self)?;
+
+        crate::redact::RedactionEngine::new(
+            self.privacy.redact_patterns.clone(),
+            self.privacy.enabled,
+        )?;
+
+        Ok(())
+    }
+
+    /// Split a dotted key into segments, honoring double-quoted segments
+    fn split_key(key: &str) -> Result<Vec<String>> {
+        let mut segments = vec![String::new()];
+        let mut quoted = false;
+
+        for c in key.chars() {
+            match c {
+                '"' => quoted = !quoted,
+                '.' if !quoted => segments.push(String::new()),
+                c => segments.last_mut().unwrap().push(c),
+            }
+        }
+
+        if quoted || segments.iter().any(|s| s.is_empty()) {
+            return Err(OmniscientError::config(format!(
+                "Invalid config key '{}'",
+                key
+            )));
+        }
+
+        Ok(segments)
+    }
+
+    /// Interpret a command-line value, guided by the current value's type
+    fn parse_raw_value(raw: &str, current: Option<&toml::Value>) -> toml::Value {
+        let parsed = toml::from_str::<toml::Table>(&format!("value = {}", raw))
+            .ok()
+            .and_then(|mut t| t.remove("value"));
+
+        match (parsed, current) {
+            // Lists may be given as "a, b, c"
+            (parsed, Some(toml::Value::Array(_)))
+                if !matches!(parsed, Some(toml::Value::Array(_))) =>
+            {
+                toml::Value::Array(
+                    raw.split(',')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .map(|item| toml::Value::String(item.to_string()))
+                        .collect(),
+                )
+            }
+            // Keep strings as typed, even if they look like numbers
+            (_, Some(toml::Value::String(_))) => toml::Value::String(raw.to_string()),
+            (Some(value), _) => value,
+            (None, _) => toml::Value::String(raw.to_string()),
+        }
+    }
+
+    /// The database path set by `$OMNISCIENT_DB`, if any
+    pub fn database_from_env() -> Option<String> {
+        env::var(DB_ENV).ok().filter(|p| !p.is_empty())
+    }
+
     /// Get the expanded database path
     pub fn database_path(&self) -> Result<PathBuf> {
         self.expand_path(&self.storage.path)
     }
 
+    /// Get the expanded automatic export directory
+    pub fn auto_export_dir(&self) -> Result<PathBuf> {
+        self.expand_path(&self.auto_export.directory)
+    }
+
+    /// Get the expanded backup directory
+    pub fn backup_dir(&self) -> Result<PathBuf> {
+        self.expand_path(&self.backup.directory)
+    }
+
     /// Ensure all required directories exist
     pub fn ensure_directories(&self) -> Result<()> {
         let omniscient_dir = Self::omniscient_dir()?;
@@ -159,11 +1082,166 @@ mod tests {
         let config = Config::default();
 
         assert_eq!(config.storage.storage_type, "sqlite");
-        assert_eq!(config.storage.path, "~/.omniscient/history.db");
+        assert_eq!(config.storage.path, default_data_path("history.db"));
         assert!(config.privacy.enabled);
         assert!(!config.privacy.redact_patterns.is_empty());
         assert_eq!(config.capture.min_duration_ms, 0);
         assert_eq!(config.capture.max_history_size, 100_000);
+        assert!(config.categories.is_destructive("psql", "database"));
+        assert!(!config.categories.is_destructive("git pull", "git"));
+        assert!(config
+            .categories
+            .is_destructive("sudo apt update", "package"));
+        assert!(config.categories.is_destructive("doas rm -rf /x", "file"));
+    }
+
+    #[test]
+    fn test_load_from_and_save_to() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("work/config.toml");
+
+        // A missing file is created with defaults
+        let mut config = Config::load_from(&path).unwrap();
+        assert!(path.exists());
+        assert_eq!(config.storage.path, default_data_path("history.db"));
+
+        config.storage.path = "~/.omniscient/work.db".to_string();
+        config.save_to(&path).unwrap();
+        assert_eq!(
+            Config::load_from(&path).unwrap().storage.path,
+            "~/.omniscient/work.db"
+        );
+    }
+
+    #[test]
+    fn test_get_value() {
+        let config = Config::default();
+
+        assert_eq!(
+            config.get_value("privacy.enabled").unwrap(),
+            toml::Value::Boolean(true)
+        );
+        assert_eq!(
+            config.get_value("storage.type").unwrap().as_str(),
+            Some("sqlite")
+        );
+        assert!(config.get_value("capture").unwrap().is_table());
+        assert!(config.get_value("capture.nope").is_err());
+        assert!(config.get_value("capture..min_duration_ms").is_err());
+    }
+
+    #[test]
+    fn test_set_value() {
+        let mut config = Config::default();
+
+        config.set_value("privacy.enabled", "false").unwrap();
+        assert!(!config.privacy.enabled);
+
+        config.set_value("capture.min_duration_ms", "2s").unwrap();
+        assert_eq!(config.capture.min_duration_ms, 2_000);
+
+        config
+            .set_value("capture.env_vars", "AWS_PROFILE, KUBECONFIG")
+            .unwrap();
+        assert_eq!(config.capture.env_vars, vec!["AWS_PROFILE", "KUBECONFIG"]);
+
+        config
+            .set_value("privacy.redact_patterns", r#"["password", 'pat_\w+']"#)
+            .unwrap();
+        assert_eq!(config.privacy.redact_patterns.len(), 2);
+
+        config
+            .set_value("capture.duplicate_key", "command")
+            .unwrap();
+        assert_eq!(config.capture.duplicate_key, DuplicateKey::Command);
+
+        config.set_value("capture.async", "true").unwrap();
+        assert!(config.capture.async_write);
+
+        config.set_value("suggestions.on_cd", "true").unwrap();
+        assert!(config.suggestions.on_cd);
+
+        config.set_value("display.width", "100").unwrap();
+        assert_eq!(config.display.width, DisplayWidth::Columns(100));
+        config.set_value("display.width", "full").unwrap();
+        assert_eq!(config.display.width, DisplayWidth::Full);
+
+        config.set_value("display.timezone", "Asia/Tokyo").unwrap();
+        assert_eq!(config.display.timezone.to_string(), "Asia/Tokyo");
+        assert!(config.set_value("display.timezone", "Nowhere").is_err());
+
+        // Map sections accept new keys, quoted when they contain dots
+        config
+            .set_value(r#"categories.rules."deploy.sh""#, "deploy")
+            .unwrap();
+        assert_eq!(config.categories.rules["deploy.sh"], "deploy");
+    }
+
+    #[test]
+    fn test_set_value_rejects_invalid() {
+        let mut config = Config::default();
+
+        assert!(config.set_value("privacy.enabled", "maybe").is_err());
+        assert!(config.set_value("capture.min_duration_ms", "soon").is_err());
+        assert!(config.set_value("capture.duplicate_key", "nope").is_err());
+        assert!(config.set_value("capture.max_history_size", "0").is_err());
+        assert!(config
+            .set_value("privacy.redact_patterns", "(unclosed")
+            .is_err());
+        assert!(config.set_value("privacy.typo", "true").is_err());
+        assert!(config.set_value("nosection.key", "1").is_err());
+        assert!(config.set_value("capture", "1").is_err());
+
+        // Nothing was changed
+        assert!(config.privacy.enabled);
+        assert_eq!(config.capture.max_history_size, 100_000);
+    }
+
+    #[test]
+    fn test_save_key_keeps_file_as_written() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = format!(
+            "# My settings\n\n{}",
+            toml::to_string_pretty(&Config::default())
+                .unwrap()
+                .replacen("[storage]\n", "[storage]\n# Off on this laptop\n", 1)
+                .replacen(
+                    "max_history_size = 100000",
+                    "max_history_size = 100_000 # for now",
+                    1
+                )
+        );
+        fs::write(&path, &original).unwrap();
+
+        let mut config = Config::load_from(&path).unwrap();
+        config.set_value("capture.max_history_size", "500").unwrap();
+        config.save_key(&path, "capture.max_history_size").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            original.replace("100_000 # for now", "500 # for now")
+        );
+
+        config
+            .categories
+            .rules
+            .insert("deploy.sh".into(), "deploy".into());
+        config
+            .save_paths(
+                &path,
+                &[vec![
+                    "categories".into(),
+                    "rules".into(),
+                    "deploy.sh".into(),
+                ]],
+            )
+            .unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with("# My settings\n"));
+        assert!(saved.contains("# Off on this laptop\n"));
+        let reloaded = Config::load_from(&path).unwrap();
+        assert_eq!(reloaded.categories.rules["deploy.sh"], "deploy");
+        assert_eq!(reloaded.capture.max_history_size, 500);
     }
 
     #[test]
@@ -199,6 +1277,108 @@ mod tests {
         assert_eq!(config.privacy.redact_patterns.len(), 2);
         assert_eq!(config.capture.min_duration_ms, 100);
         assert_eq!(config.capture.max_history_size, 50_000);
+        assert_eq!(config.storage.query_cache_size, 0);
+        assert!(config.capture.env_vars.is_empty());
+        assert!(!config.auto_export.enabled);
+    }
+
+    #[test]
+    fn test_partial_auto_export_section() {
+        let toml_string = r#"
+            [storage]
+            type = "sqlite"
+            path = "~/.omniscient/history.db"
+
+            [privacy]
+            redact_patterns = []
+            enabled = true
+
+            [capture]
+            min_duration_ms = 0
+            max_history_size = 1000
+
+            [auto_export]
+            enabled = true
+            keep = 30
+        "#;
+
+        let config: Config = toml::from_str(toml_string).unwrap();
+        assert!(config.auto_export.enabled);
+        assert_eq!(config.auto_export.keep, 30);
+        assert_eq!(config.auto_export.interval_days, 1);
+        assert_eq!(config.auto_export.directory, default_data_path("exports"));
+    }
+
+    #[test]
+    fn test_path_rewrites_deserialization() {
+        let toml_string = r#"
+            [storage]
+            type = "sqlite"
+            path = "~/.omniscient/history.db"
+
+            [privacy]
+            redact_patterns = []
+            enabled = true
+
+            [capture]
+            min_duration_ms = 0
+            max_history_size = 1000
+
+            [paths.rewrites]
+            "/mnt/c/Users/me" = "~"
+            "/private/var" = "/var"
+        "#;
+
+        let config: Config = toml::from_str(toml_string).unwrap();
+        assert_eq!(config.paths.rewrites.len(), 2);
+        assert_eq!(config.paths.rewrites["/mnt/c/Users/me"], "~");
+    }
+
+    #[test]
+    fn test_duplicate_key_deserialization() {
+        let toml_string = r#"
+            [storage]
+            type = "sqlite"
+            path = "~/.omniscient/history.db"
+
+            [privacy]
+            redact_patterns = []
+            enabled = true
+
+            [capture]
+            min_duration_ms = 0
+            max_history_size = 1000
+            duplicate_key = "command_dir_and_exit_code"
+        "#;
+
+        let config: Config = toml::from_str(toml_string).unwrap();
+        assert_eq!(
+            config.capture.duplicate_key,
+            DuplicateKey::CommandDirAndExitCode
+        );
+    }
+
+    #[test]
+    fn test_min_duration_accepts_duration_string() {
+        let toml_string = r#"
+            [storage]
+            type = "sqlite"
+            path = "~/.omniscient/history.db"
+
+            [privacy]
+            redact_patterns = []
+            enabled = true
+
+            [capture]
+            min_duration_ms = "1.5s"
+            max_history_size = 1000
+        "#;
+
+        let config: Config = toml::from_str(toml_string).unwrap();
+        assert_eq!(config.capture.min_duration_ms, 1_500);
+
+        let invalid = toml_string.replace("1.5s", "soon");
+        assert!(toml::from_str::<Config>(&invalid).is_err());
     }
 
     #[test]
@@ -224,8 +1404,63 @@ mod tests {
         let db_path = config.database_path().unwrap();
 
         assert!(!db_path.to_string_lossy().contains('~'));
-        assert!(db_path
-            .to_string_lossy()
-            .ends_with(".omniscient/history.db"));
+        assert!(db_path.to_string_lossy().ends_with("omniscient/history.db"));
+    }
+
+    #[test]
+    fn test_migrate_legacy_dir() {
+        let home = tempfile::TempDir::new().unwrap();
+        let home = home.path();
+        let legacy = home.join(".omniscient");
+        let data_dir = home.join(".local/share/omniscient");
+        let config_dir = home.join(".config/omniscient");
+
+        fs::create_dir_all(legacy.join("exports")).unwrap();
+        fs::write(legacy.join("history.db"), "db").unwrap();
+        let mut config = Config::default();
+        config.storage.path = "~/.omniscient/history.db".to_string();
+        config.backup.directory = format!("{}/backups", legacy.display());
+        config.auto_export.directory = "/srv/.omniscient/exports".to_string();
+        config.save_to(&legacy.join("config.toml")).unwrap();
+
+        assert!(migrate_dir(&legacy, &data_dir, &config_dir, home).unwrap());
+        assert!(!legacy.exists());
+        assert_eq!(
+            fs::read_to_string(data_dir.join("history.db")).unwrap(),
+            "db"
+        );
+        assert!(data_dir.join("exports").is_dir());
+        assert!(!data_dir.join("config.toml").exists());
+        assert_eq!(
+            fs::read_to_string(data_dir.join(MIGRATED_MARKER)).unwrap(),
+            format!("{}\n", legacy.display())
+        );
+
+        let config = Config::load_from(&config_dir.join("config.toml")).unwrap();
+        assert_eq!(config.storage.path, "~/.local/share/omniscient/history.db");
+        assert_eq!(config.backup.directory, "~/.local/share/omniscient/backups");
+        assert_eq!(config.auto_export.directory, "/srv/.omniscient/exports");
+
+        // Once migrated, a new ~/.omniscient stays where it is
+        fs::create_dir(&legacy).unwrap();
+        assert!(!migrate_dir(&legacy, &data_dir, &config_dir, home).unwrap());
+        assert!(legacy.exists());
+    }
+
+    #[test]
+    fn test_failed_migration_keeps_legacy_config() {
+        let home = tempfile::TempDir::new().unwrap();
+        let home = home.path();
+        let legacy = home.join(".omniscient");
+        let config_dir = home.join(".config/omniscient");
+        Config::default()
+            .save_to(&legacy.join("config.toml"))
+            .unwrap();
+
+        // A directory can't be moved into itself
+        let data_dir = legacy.join("nested/omniscient");
+        assert!(migrate_dir(&legacy, &data_dir, &config_dir, home).is_err());
+        assert!(legacy.join("config.toml").exists());
+        assert!(!config_dir.join("config.toml").exists());
     }
 }
@@ -0,0 +1,319 @@
+/// History deduplication (`omniscient dedupe`)
+///
+/// Near-duplicates pile up over time: the same command with different
+/// spacing, or the same directory reached through a symlink. Deduplication
+/// normalizes command text and working directories, groups records that
+/// become identical under the configured duplicate key and merges each group
+/// into its oldest record.
+use crate::config::Config;
+use crate::error::Result;
+use crate::models::{CommandRecord, DuplicateKey};
+use crate::paths::PathRewriter;
+use crate::storage::Storage;
+use std::collections::HashMap;
+
+/// Records that normalize to the same command
+#[derive(Debug, Clone)]
+pub struct MergeGroup {
+    /// Normalized command text
+    pub command: String,
+
+    /// Normalized working directory
+    pub working_dir: String,
+
+    /// The oldest record, which the others are merged into
+    pub target: CommandRecord,
+
+    /// Records merged into the target and deleted
+    pub duplicates: Vec<CommandRecord>,
+}
+
+impl MergeGroup {
+    /// Whether the target itself changes (its command or directory was not normalized)
+    pub fn is_renamed(&self) -> bool {
+        self.target.command != self.command || self.target.working_dir != self.working_dir
+    }
+}
+
+/// Finds and merges near-duplicate records
+#[derive(Debug, Clone, Default)]
+pub struct Deduper {
+    key: DuplicateKey,
+    rewriter: PathRewriter,
+}
+
+impl Deduper {
+    /// Create a deduper grouping records by `key`, with directories
+    /// canonicalized by `rewriter`
+    pub fn new(key: DuplicateKey, rewriter: PathRewriter) -> Self {
+        Self { key, rewriter }
+    }
+
+    /// Create a deduper from the capture duplicate key and `[paths]`, so
+    /// directories are canonicalized the way capture does it
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self::new(
+            config.capture.duplicate_key,
+            PathRewriter::from_config(config)?,
+        ))
+    }
+
+    /// Group records that are duplicates once normalized
+    ///
+    /// Only groups with something to do are returned: duplicates to merge or
+    /// a target whose command or directory changes.
+    pub fn plan(&self, records: Vec<CommandRecord>) -> Vec<MergeGroup> {
+        let mut dirs: HashMap<String, String> = HashMap::new();
+        let mut groups: HashMap<(String, String, Option<i32>), MergeGroup> = HashMap::new();
+        let mut order = Vec::new();
+
+        for record in records {
+            let command = normalize_command(&record.command);
+            let working_dir = dirs
+                .entry(record.working_dir.clone())
+                .or_insert_with(|| self.rewriter.canonicalize(&record.working_dir))
+                .clone();
+            let key = match self.key {
+                DuplicateKey::CommandAndDir => (command.clone(), working_dir.clone(), None),
+                DuplicateKey::CommandDirAndExitCode => {
+                    (command.clone(), working_dir.clone(), Some(record.exit_code))
+                }
+                DuplicateKey::Command => (command.clone(), String::new(), None),
+            };
+
+            match groups.get_mut(&key) {
+                Some(group) => {
+                    // The oldest record survives
+                    if (record.timestamp, record.id) < (group.target.timestamp, group.target.id) {
+                        group.working_dir = working_dir;
+                        let target = std::mem::replace(&mut group.target, record);
+                        group.duplicates.push(target);
+                    } else {
+                        group.duplicates.push(record);
+                    }
+                }
+                None => {
+                    order.push(key.clone());
+                    groups.insert(
+                        key,
+                        MergeGroup {
+                            command,
+                            working_dir,
+                            target: record,
+                            duplicates: Vec::new(),
+                        },
+                    );
+                }
+            }
+        }
+
+        order
+            .into_iter()
+            .filter_map(|key| groups.remove(&key))
+            .filter(|group| !group.duplicates.is_empty() || group.is_renamed())
+            .collect()
+    }
+
+    /// Merge every group, returning the number of records removed
+    pub fn apply(&self, storage: &Storage, groups: &[MergeGroup]) -> Result<usize> {
+        let mut removed = 0;
+
+        for group in groups {
+            let sources: Vec<i64> = group.duplicates.iter().filter_map(|cmd| cmd.id).collect();
+            storage.merge_commands(
+                group.target.id.unwrap_or_default(),
+                &sources,
+                &group.command,
+                &group.working_dir,
+            )?;
+            removed += sources.len();
+        }
+
+        Ok(removed)
+    }
+
+    /// Find and merge duplicates in the whole history
+    pub fn run(&self, storage: &Storage) -> Result<usize> {
+        let groups = self.plan(storage.get_all()?);
+        self.apply(storage, &groups)
+    }
+}
+
+/// Normalize whitespace in a command
+///
+/// On the first line, runs of spaces and tabs outside quotes collapse to a
+/// single space and leading and trailing whitespace is removed. Quoted text
+/// and everything after the first line break (loop bodies, heredocs, where
+/// indentation can be data) are kept as they are, so the command still
+/// means the same thing.
+pub fn normalize_command(command: &str) -> String {
+    let mut out = String::with_capacity(command.len());
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut pending_space = false;
+
+    for (index, c) in command.char_indices() {
+        if quote.is_none() && !escaped {
+            match c {
+                ' ' | '\t' => {
+                    pending_space = true;
+                    continue;
+                }
+                '\n' => {
+                    // Trailing whitespace of the first line is dropped
+                    out.push_str(&command[index..]);
+                    return out;
+                }
+                _ => {}
+            }
+        }
+
+        if pending_space && !out.is_empty() && !out.ends_with('\n') {
+            out.push(' ');
+        }
+        pending_space = false;
+        out.push(c);
+
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && quote != Some('\'') {
+            escaped = true;
+        } else if quote == Some(c) {
+            quote = None;
+        } else if quote.is_none() && (c == '\'' || c == '"') {
+            quote = Some(c);
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use tempfile::{NamedTempFile, TempDir};
+
+    fn record(command: &str, dir: &str, age_days: i64, usage: i32) -> CommandRecord {
+        let mut cmd = CommandRecord::new(
+            command.to_string(),
+            Utc::now() - Duration::days(age_days),
+            0,
+            10,
+            dir.to_string(),
+            "other".to_string(),
+        );
+        cmd.usage_count = usage;
+        cmd
+    }
+
+    #[test]
+    fn test_normalize_command() {
+        assert_eq!(normalize_command("  git   status  "), "git status");
+        assert_eq!(normalize_command("ls\t-la"), "ls -la");
+        assert_eq!(
+            normalize_command("echo 'a   b'  \"c  d\""),
+            "echo 'a   b' \"c  d\""
+        );
+        assert_eq!(normalize_command(r"echo a\  b"), r"echo a\  b");
+        assert_eq!(
+            normalize_command("for  f in *; do  \n    echo $f\ndone "),
+            "for f in *; do\n    echo $f\ndone "
+        );
+        // Line breaks inside quotes are part of the argument
+        assert_eq!(normalize_command("echo 'a  \n  b'"), "echo 'a  \n  b'");
+    }
+
+    #[test]
+    fn test_normalize_command_keeps_heredoc_body() {
+        let heredoc = "cat <<EOF > app.yml\n  key:   v\n\tnested: w \nEOF";
+        assert_eq!(normalize_command(heredoc), heredoc);
+        assert_eq!(
+            normalize_command("cat  <<EOF  \n  key: v\nEOF"),
+            "cat <<EOF\n  key: v\nEOF"
+        );
+    }
+
+    #[test]
+    fn test_plan_groups_near_duplicates() {
+        let mut first = record("git status ", "/srv/api", 1, 2);
+        first.id = Some(2);
+        let mut oldest = record("git  status", "/srv/api/", 5, 3);
+        oldest.id = Some(7);
+        let mut other_dir = record("git status", "/srv/web", 1, 1);
+        other_dir.id = Some(9);
+        let mut clean = record("ls", "/srv/api", 1, 1);
+        clean.id = Some(10);
+
+        let groups = Deduper::default().plan(vec![first, oldest, other_dir, clean]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].command, "git status");
+        assert_eq!(groups[0].working_dir, "/srv/api");
+        assert_eq!(groups[0].target.id, Some(7));
+        assert_eq!(groups[0].duplicates.len(), 1);
+        assert_eq!(groups[0].duplicates[0].id, Some(2));
+
+        let groups = Deduper::new(DuplicateKey::Command, PathRewriter::default())
+            .plan(vec![record("make", "/a", 1, 1), record("make", "/b", 2, 1)]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].working_dir, "/b");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_plan_merges_symlinked_dirs() {
+        let dir = TempDir::new().unwrap();
+        let real = dir.path().join("real");
+        let link = dir.path().join("link");
+        std::fs::create_dir(&real).unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        let deduper = Deduper::new(
+            DuplicateKey::default(),
+            PathRewriter::default().with_symlink_resolution(),
+        );
+        let groups = deduper.plan(vec![
+            record("make", &real.to_string_lossy(), 1, 1),
+            record("make", &link.to_string_lossy(), 2, 1),
+        ]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            groups[0].working_dir,
+            std::fs::canonicalize(&real).unwrap().to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn test_run_merges_records() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let storage = Storage::new(temp_file.path()).unwrap();
+
+        let oldest = storage
+            .insert(&record("cargo  test", "/srv/api", 10, 2))
+            .unwrap();
+        let newer = storage
+            .insert(&record("cargo test ", "/srv/api", 1, 3))
+            .unwrap();
+        storage.set_note(newer, Some("runs the suite")).unwrap();
+        storage.add_bookmark(newer).unwrap();
+        storage.insert(&record("ls", "/srv/api", 1, 1)).unwrap();
+
+        assert_eq!(Deduper::default().run(&storage).unwrap(), 1);
+
+        let records = storage.get_all().unwrap();
+        assert_eq!(records.len(), 2);
+        let merged = storage.get_by_id(oldest).unwrap().unwrap();
+        assert_eq!(merged.command, "cargo test");
+        assert_eq!(merged.usage_count, 5);
+        assert!(merged.timestamp < Utc::now() - Duration::days(9));
+        assert_eq!(merged.note.as_deref(), Some("runs the suite"));
+        assert!(storage.is_bookmarked(oldest).unwrap());
+        assert!(storage.get_by_id(newer).unwrap().is_none());
+
+        // Nothing left to do
+        assert_eq!(Deduper::default().run(&storage).unwrap(), 0);
+        assert!(Deduper::default()
+            .plan(storage.get_all().unwrap())
+            .is_empty());
+    }
+}
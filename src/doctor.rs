@@ -0,0 +1,488 @@
+/// Installation health checks (`omniscient doctor`)
+///
+/// Walks through everything capture depends on - the config file, the hook
+/// in the shell's rc files and in the running shell, the database, its WAL,
+/// integrity and search index, whether commands are actually arriving and
+/// which were skipped - and says how to fix whatever is wrong.
+use crate::config::Config;
+use crate::format::format_age;
+use crate::journal::CaptureJournal;
+use crate::lint;
+use crate::models::SkipCount;
+use crate::pause::Pause;
+use crate::shell::ShellType;
+use crate::storage::{HealthReport, Storage};
+use chrono::{DateTime, Duration, Utc};
+use std::fs;
+use std::path::PathBuf;
+
+/// Environment variable the hook exports in every shell it runs in
+pub const SESSION_ENV: &str = "OMNISCIENT_SESSION";
+
+/// WAL files larger than this mean checkpoints are not keeping up (64 MiB)
+const WAL_WARN_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Hours without a capture before it is worth a look
+const QUIET_HOURS: i64 = 24;
+
+/// Outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// One diagnosed aspect of the installation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// What was checked
+    pub name: &'static str,
+
+    /// Outcome
+    pub status: CheckStatus,
+
+    /// What was found
+    pub detail: String,
+
+    /// How to fix it, for warnings and errors
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn warning(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Warning,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn error(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Error,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// What to diagnose
+pub struct Doctor {
+    /// Config file in use
+    pub config_path: PathBuf,
+
+    /// Database path overriding the config (`--db` or `$OMNISCIENT_DB`)
+    pub database: Option<String>,
+
+    /// Shell whose rc files hold the hook
+    pub shell: ShellType,
+
+    /// Home directory holding the rc files
+    pub home: PathBuf,
+
+    /// Session of the hook in the shell running the doctor, if loaded
+    pub session: Option<String>,
+}
+
+impl Doctor {
+    /// Run every check, in order
+    ///
+    /// A config that fails to load is reported and the defaults are used
+    /// for the remaining checks.
+    pub fn run(&self) -> Vec<Check> {
+        let mut checks = Vec::new();
+
+        let mut config = match Config::load_from(&self.config_path).and_then(|config| {
+            config.validate()?;
+            Ok(config)
+        }) {
+            Ok(config) => {
+                checks.push(Check::ok(
+                    "config",
+                    format!("{} is valid", self.config_path.display()),
+                ));
+                config
+            }
+            Err(e) => {
+                checks.push(Check::error(
+                    "config",
+                    e.to_string(),
+                    format!(
+                        "Fix {} (or move it aside to start from the defaults)",
+                        self.config_path.display()
+                    ),
+                ));
+                Config::default()
+            }
+        };
+        if let Some(database) = &self.database {
+            config.storage.path = database.clone();
+        }
+
+        checks.extend(self.hook_checks());
+        checks.extend(database_checks(&config, Utc::now()));
+        checks
+    }
+
+    /// The hook in the rc files, its dependencies and conflicts, and in the
+    /// running shell
+    fn hook_checks(&self) -> Vec<Check> {
+        let mut checks = Vec::new();
+        let rc = match self.shell {
+            ShellType::Zsh => "~/.zshrc",
+            ShellType::Bash => "~/.bashrc",
+        };
+
+        match lint::find_hook(self.shell, &self.home) {
+            Ok(Some((path, line))) => checks.push(Check::ok(
+                "shell hook",
+                format!("Set up in {}:{}", path.display(), line),
+            )),
+            Ok(None) => checks.push(Check::error(
+                "shell hook",
+                format!("Not found in the {} rc files", self.shell),
+                format!("Run: omniscient init --shell {} --write", self.shell),
+            )),
+            Err(e) => checks.push(Check::error(
+                "shell hook",
+                format!("Could not read the rc files: {}", e),
+                "Check the permissions of your shell rc files",
+            )),
+        }
+
+        if self.shell == ShellType::Bash && matches!(lint::find_bash_preexec(&self.home), Ok(None))
+        {
+            checks.push(Check::warning(
+                "bash-preexec",
+                "Not loaded from the bash rc files (the bash hook needs it)",
+                "See 'omniscient init --shell bash' for installation steps",
+            ));
+        }
+
+        if let Ok(conflicts) = lint::scan(self.shell, &self.home) {
+            for conflict in conflicts {
+                checks.push(Check::warning(
+                    "hook conflict",
+                    conflict.to_string(),
+                    format!(
+                        "Remove the line, or use a capture-only hook: omniscient init --shell {} --compat",
+                        self.shell
+                    ),
+                ));
+            }
+        }
+
+        checks.push(match &self.session {
+            _ if Pause::disabled_by_env() => Check::warning(
+                "current shell",
+                "OMNISCIENT_DISABLE is set, so this shell is not captured",
+                "Run: unset OMNISCIENT_DISABLE",
+            ),
+            Some(session) => Check::ok("current shell", format!("Hook active ({})", session)),
+            None => Check::warning(
+                "current shell",
+                "Hook not active in this shell",
+                format!("Open a new shell or run: source {}", rc),
+            ),
+        });
+
+        checks
+    }
+}
+
+/// The database, its WAL, integrity, search index and capture activity
+fn database_checks(config: &Config, now: DateTime<Utc>) -> Vec<Check> {
+    let fix_access = "Check the permissions of the file and its directory, or restore a backup (omniscient backup list)";
+    let path = match config.database_path() {
+        Ok(path) => path,
+        Err(e) => return vec![Check::error("database", e.to_string(), fix_access)],
+    };
+    let storage = match Storage::open(&path, false) {
+        Ok(storage) => storage,
+        Err(e) => {
+            return vec![Check::error(
+                "database",
+                format!("Cannot open {}: {}", path.display(), e),
+                fix_access,
+            )]
+        }
+    };
+    let report = match storage.check_health() {
+        Ok(report) => report,
+        Err(e) => {
+            return vec![Check::error(
+                "database",
+                format!("Cannot read {}: {}", path.display(), e),
+                fix_access,
+            )]
+        }
+    };
+
+    let mut checks = vec![Check::ok(
+        "database",
+        format!(
+            "{} ({} commands, schema v{})",
+            path.display(),
+            storage.count().unwrap_or(0),
+            storage.schema_version().unwrap_or(0)
+        ),
+    )];
+
+    let mut wal = path.clone().into_os_string();
+    wal.push("-wal");
+    let wal_bytes = fs::metadata(PathBuf::from(wal)).map_or(0, |m| m.len());
+    checks.push(wal_check(&report.journal_mode, wal_bytes));
+
+    checks.push(if report.integrity_errors.is_empty() {
+        Check::ok("integrity", "No corruption found")
+    } else {
+        Check::error(
+            "integrity",
+            report.integrity_errors.join("; "),
+            "Restore the latest backup (omniscient backup list), or export what is readable (omniscient export)",
+        )
+    });
+
+    checks.push(match &report.search_index_error {
+        None => Check::ok("search index", "In sync with the history"),
+        Some(error) => Check::error(
+            "search index",
+            format!("Out of sync with the history: {}", error),
+            "Run: omniscient db maintain",
+        ),
+    });
+
+    let paused = Pause::from_config(config)
+        .and_then(|pause| pause.state(now))
+        .is_ok_and(|state| state.is_paused());
+    let pending = CaptureJournal::from_config(config)
+        .and_then(|journal| journal.pending())
+        .unwrap_or(0);
+    checks.extend(activity_checks(&report, paused, pending, now));
+    checks.extend(skip_checks(
+        &storage.get_skip_counts().unwrap_or_default(),
+        now,
+    ));
+
+    checks
+}
+
+/// The journal mode and how much the WAL has grown
+fn wal_check(journal_mode: &str, wal_bytes: u64) -> Check {
+    if !journal_mode.eq_ignore_ascii_case("wal") {
+        return Check::warning(
+            "WAL",
+            format!(
+                "Journal mode is '{}', so concurrent shells block each other",
+                journal_mode
+            ),
+            "Close other omniscient processes; the next command switches back to WAL",
+        );
+    }
+    if wal_bytes > WAL_WARN_BYTES {
+        return Check::warning(
+            "WAL",
+            format!(
+                "Write-ahead log is {} MiB, checkpoints are not keeping up",
+                wal_bytes / (1024 * 1024)
+            ),
+            "Run: omniscient db maintain",
+        );
+    }
+    Check::ok("WAL", format!("Enabled ({} KiB pending)", wal_bytes / 1024))
+}
+
+/// Whether captures are arriving, and anything holding them back
+fn activity_checks(
+    report: &HealthReport,
+    paused: bool,
+    pending: usize,
+    now: DateTime<Utc>,
+) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    if paused {
+        checks.push(Check::warning(
+            "capture",
+            "Capture is paused",
+            "Run: omniscient resume",
+        ));
+    }
+    if pending > 0 {
+        checks.push(Check::warning(
+            "capture",
+            format!("{} captures are waiting in the journal", pending),
+            "They are stored by the next capture; if they stay, check the database checks above",
+        ));
+    }
+
+    checks.push(match report.last_capture {
+        None => Check::warning(
+            "recent activity",
+            "Nothing captured yet",
+            "Run a command in a shell with the hook loaded, then check again",
+        ),
+        Some(last) if now - last > Duration::hours(QUIET_HOURS) => Check::warning(
+            "recent activity",
+            format!("Last capture {} ago", format_age(last, now)),
+            "If you have used the shell since, check the shell hook checks above",
+        ),
+        Some(last) => Check::ok(
+            "recent activity",
+            format!("Last capture {} ago", format_age(last, now)),
+        ),
+    });
+
+    checks
+}
+
+/// How many commands were not stored, per reason
+fn skip_checks(skips: &[SkipCount], now: DateTime<Utc>) -> Vec<Check> {
+    if skips.is_empty() {
+        return vec![Check::ok("skipped", "No commands skipped")];
+    }
+
+    skips
+        .iter()
+        .map(|skip| {
+            Check::ok(
+                "skipped",
+                format!(
+                    "{} {} ({}), last {} ago",
+                    skip.count,
+                    skip.reason,
+                    skip.reason.description(),
+                    format_age(skip.last_skipped, now)
+                ),
+            )
+        })
+        .collect()
+}
+
+/// The most severe status among `checks`
+pub fn worst(checks: &[Check]) -> CheckStatus {
+    checks
+        .iter()
+        .map(|check| check.status)
+        .max()
+        .unwrap_or(CheckStatus::Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::SkipReason;
+    use tempfile::TempDir;
+
+    fn report(last_capture: Option<DateTime<Utc>>) -> HealthReport {
+        HealthReport {
+            journal_mode: "wal".to_string(),
+            integrity_errors: Vec::new(),
+            search_index_error: None,
+            last_capture,
+        }
+    }
+
+    #[test]
+    fn test_wal_check() {
+        assert_eq!(wal_check("wal", 4096).status, CheckStatus::Ok);
+        assert_eq!(wal_check("delete", 0).status, CheckStatus::Warning);
+
+        let check = wal_check("wal", WAL_WARN_BYTES + 1);
+        assert_eq!(check.status, CheckStatus::Warning);
+        assert_eq!(check.fix.as_deref(), Some("Run: omniscient db maintain"));
+    }
+
+    #[test]
+    fn test_skip_checks() {
+        let now = Utc::now();
+        let checks = skip_checks(&[], now);
+        assert_eq!(checks[0].detail, "No commands skipped");
+
+        let skips = [
+            SkipCount {
+                reason: SkipReason::TooFast,
+                count: 12,
+                last_skipped: now - Duration::minutes(5),
+            },
+            SkipCount {
+                reason: SkipReason::Redacted,
+                count: 1,
+                last_skipped: now - Duration::days(2),
+            },
+        ];
+        let checks = skip_checks(&skips, now);
+        assert_eq!(worst(&checks), CheckStatus::Ok);
+        assert_eq!(
+            checks[0].detail,
+            "12 too_fast (faster than capture.min_duration_ms), last 5m ago"
+        );
+        assert!(checks[1].detail.starts_with("1 redacted"));
+    }
+
+    #[test]
+    fn test_activity_checks() {
+        let now = Utc::now();
+
+        let checks = activity_checks(&report(Some(now - Duration::minutes(5))), false, 0, now);
+        assert_eq!(worst(&checks), CheckStatus::Ok);
+        assert_eq!(checks[0].detail, "Last capture 5m ago");
+
+        let checks = activity_checks(&report(Some(now - Duration::days(3))), false, 0, now);
+        assert_eq!(worst(&checks), CheckStatus::Warning);
+
+        let checks = activity_checks(&report(None), true, 2, now);
+        let details: Vec<&str> = checks.iter().map(|c| c.detail.as_str()).collect();
+        assert_eq!(
+            details,
+            vec![
+                "Capture is paused",
+                "2 captures are waiting in the journal",
+                "Nothing captured yet"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_doctor_run() {
+        let home = TempDir::new().unwrap();
+        let config_path = home.path().join("config.toml");
+        fs::write(&config_path, "[capture]\nmax_history_size = 0\n").unwrap();
+        fs::write(home.path().join(".zshrc"), "eval \"$(omniscient init)\"\n").unwrap();
+
+        let doctor = Doctor {
+            config_path,
+            database: Some(
+                home.path()
+                    .join("history.db")
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            shell: ShellType::Zsh,
+            home: home.path().to_path_buf(),
+            session: Some("zsh-1-2".to_string()),
+        };
+        let checks = doctor.run();
+        let status = |name: &str| checks.iter().find(|c| c.name == name).unwrap().status;
+
+        assert_eq!(status("config"), CheckStatus::Error);
+        assert_eq!(status("shell hook"), CheckStatus::Ok);
+        assert_eq!(status("database"), CheckStatus::Ok);
+        assert_eq!(status("WAL"), CheckStatus::Ok);
+        assert_eq!(status("search index"), CheckStatus::Ok);
+        assert_eq!(status("recent activity"), CheckStatus::Warning);
+        assert_eq!(worst(&checks), CheckStatus::Error);
+    }
+}
@@ -1,3 +1,4 @@
+use std::panic::Location;
 use std::path::PathBuf;
 use thiserror::Error;
 
@@ -58,6 +59,18 @@ pub enum OmniscientError {
     /// Generic error for edge cases
     #[error("{0}")]
     Other(String),
+
+    /// An error annotated with the operation that failed
+    ///
+    /// Created with [`ResultExt::context`]; `location` is where the context
+    /// was attached.
+    #[error("{context}: {source}")]
+    Context {
+        context: String,
+        location: &'static Location<'static>,
+        #[source]
+        source: Box<OmniscientError>,
+    },
 }
 
 impl OmniscientError {
@@ -85,6 +98,83 @@ impl OmniscientError {
     pub fn other<S: Into<String>>(msg: S) -> Self {
         Self::Other(msg.into())
     }
+
+    /// Whether the database failed (locked, unreadable or corrupt), rather
+    /// than the request made of it
+    pub fn is_storage(&self) -> bool {
+        match self {
+            Self::Storage(_) | Self::DatabaseInit(_) => true,
+            Self::Context { source, .. } => source.is_storage(),
+            _ => false,
+        }
+    }
+
+    /// Whether the database was locked by another connection
+    /// (`SQLITE_BUSY` / `SQLITE_LOCKED`), so retrying may succeed
+    pub fn is_busy(&self) -> bool {
+        match self {
+            Self::Storage(rusqlite::Error::SqliteFailure(error, _)) => matches!(
+                error.code,
+                rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+            ),
+            Self::Context { source, .. } => source.is_busy(),
+            _ => false,
+        }
+    }
+
+    /// Wrap this error with a description of the operation that failed
+    #[track_caller]
+    pub fn context<S: Into<String>>(self, context: S) -> Self {
+        Self::Context {
+            context: context.into(),
+            location: Location::caller(),
+            source: Box::new(self),
+        }
+    }
+
+    /// The underlying error, with all context removed
+    pub fn root_cause(&self) -> &OmniscientError {
+        match self {
+            Self::Context { source, .. } => source.root_cause(),
+            other => other,
+        }
+    }
+
+    /// Where the outermost context was attached, if any
+    pub fn location(&self) -> Option<&'static Location<'static>> {
+        match self {
+            Self::Context { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+}
+
+/// Attach operation context to errors, e.g.
+/// `storage.get_by_id(id).context(format!("load command {}", id))?`
+pub trait ResultExt<T> {
+    /// Wrap any error with a fixed description of the operation
+    fn context<S: Into<String>>(self, context: S) -> Result<T>;
+
+    /// Wrap any error with a description built only when an error occurs
+    fn with_context<S: Into<String>, F: FnOnce() -> S>(self, context: F) -> Result<T>;
+}
+
+impl<T, E: Into<OmniscientError>> ResultExt<T> for std::result::Result<T, E> {
+    #[track_caller]
+    fn context<S: Into<String>>(self, context: S) -> Result<T> {
+        match self {
+            Ok(value) => Ok(value),
+            Err(e) => Err(e.into().context(context)),
+        }
+    }
+
+    #[track_caller]
+    fn with_context<S: Into<String>, F: FnOnce() -> S>(self, context: F) -> Result<T> {
+        match self {
+            Ok(value) => Ok(value),
+            Err(e) => Err(e.into().context(context())),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -118,4 +208,34 @@ mod tests {
 
         assert_eq!(returns_result().unwrap(), 42);
     }
+
+    #[test]
+    fn test_context_chain() {
+        fn load() -> Result<()> {
+            Err(OmniscientError::other("disk on fire"))
+        }
+
+        let err = load()
+            .context("load command 42")
+            .with_context(|| "show history".to_string())
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "show history: load command 42: disk on fire"
+        );
+        assert!(matches!(err.root_cause(), OmniscientError::Other(_)));
+        assert_eq!(err.location().unwrap().file(), file!());
+    }
+
+    #[test]
+    fn test_context_wraps_foreign_errors() {
+        let io: std::result::Result<(), std::io::Error> =
+            Err(std::io::Error::new(std::io::ErrorKind::NotFound, "missing"));
+        let err = io.context("read export.json").unwrap_err();
+
+        assert_eq!(err.to_string(), "read export.json: IO error: missing");
+        assert!(matches!(err.root_cause(), OmniscientError::Io(_)));
+        assert!(OmniscientError::other("plain").location().is_none());
+    }
 }
@@ -1,14 +1,66 @@
 /// Export and import functionality for command history
-use crate::error::Result;
-use crate::models::CommandRecord;
+use crate::config::Config;
+use crate::error::{OmniscientError, Result, ResultExt};
+use crate::models::{CommandRecord, DuplicateKey};
+use crate::redact::RedactionEngine;
+use crate::rotation::Rotation;
+use crate::storage::RecordChange;
 use crate::Storage;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::Path;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 
 /// Export format version for compatibility checking
 const EXPORT_VERSION: &str = "1.0";
 
+/// Compression level for zstd exports
+const ZSTD_LEVEL: i32 = 19;
+
+/// Compression level for scheduled exports, which run in the background of
+/// a capture: much faster for a slightly larger file
+const SCHEDULED_ZSTD_LEVEL: i32 = 3;
+
+/// Magic number at the start of every zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// File name prefix and suffix of scheduled exports
+const SCHEDULED_PREFIX: &str = "history-";
+const SCHEDULED_SUFFIX: &str = ".json.zst";
+
+/// Command text, directory and exit code as relevant to the duplicate key
+type PendingKey = (String, Option<String>, Option<i32>);
+
+/// Records merged in memory before being written in one transaction
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+/// Records masked per transaction in redacted SQLite exports
+const REDACT_BATCH_SIZE: usize = 1000;
+
+/// Value of the `format` field in NDJSON headers
+const NDJSON_FORMAT: &str = "ndjson";
+
+/// First line of an NDJSON export; every following line is one record
+#[derive(Debug, Serialize, Deserialize)]
+struct NdjsonHeader {
+    format: String,
+    version: String,
+    exported_at: String,
+}
+
+/// Open an export for reading, transparently decompressing zstd
+fn open_export(path: &Path) -> Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    if reader.fill_buf()?.starts_with(&ZSTD_MAGIC) {
+        let decoder = zstd::stream::read::Decoder::with_buffer(reader)?;
+        return Ok(Box::new(BufReader::new(decoder)));
+    }
+
+    Ok(Box::new(reader))
+}
+
 /// Export file structure
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExportData {
@@ -25,40 +77,450 @@ pub struct ExportData {
     pub commands: Vec<CommandRecord>,
 }
 
-/// Export command history to JSON file
+/// Output format for exports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    /// Full-fidelity JSON that can be imported again
+    #[default]
+    Json,
+
+    /// Human-readable Markdown grouped by category (export only)
+    Markdown,
+
+    /// JSON compressed with zstd (importable like plain JSON)
+    CompressedJson,
+
+    /// Standalone SQLite database with redacted records and capture
+    /// bookkeeping removed
+    Sqlite,
+
+    /// Newline-delimited JSON, written and read one record at a time
+    Ndjson,
+}
+
+/// Which commands an export includes (everything by default)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportFilter {
+    /// Only these categories (any category when empty)
+    pub categories: Vec<String>,
+
+    /// Only commands run in this directory
+    pub working_dir: Option<String>,
+
+    /// Include subdirectories of `working_dir`
+    pub recursive: bool,
+
+    /// Only commands used at or after this time
+    pub since: Option<DateTime<Utc>>,
+
+    /// Leave out commands whose last run failed
+    pub exclude_failed: bool,
+}
+
+impl ExportFilter {
+    /// Whether a record belongs in the export
+    pub fn matches(&self, record: &CommandRecord) -> bool {
+        if !self.categories.is_empty() && !self.categories.contains(&record.category) {
+            return false;
+        }
+
+        if let Some(ref dir) = self.working_dir {
+            let dir = dir.trim_end_matches('/');
+            let inside = record
+                .working_dir
+                .strip_prefix(dir)
+                .is_some_and(|rest| rest.is_empty() || (self.recursive && rest.starts_with('/')));
+            if !inside {
+                return false;
+            }
+        }
+
+        if self.since.is_some_and(|since| record.last_used < since) {
+            return false;
+        }
+
+        !(self.exclude_failed && record.exit_code != 0)
+    }
+}
+
+/// Export command history to a file
 pub struct Exporter {
     storage: Storage,
+    format: ExportFormat,
+    redactor: Option<RedactionEngine>,
+    filter: ExportFilter,
+    mask: bool,
+    strip_locations: bool,
+    compression_level: i32,
 }
 
 impl Exporter {
     /// Create a new exporter with the given storage
     pub fn new(storage: Storage) -> Self {
-        Self { storage }
+        Self {
+            storage,
+            format: ExportFormat::default(),
+            redactor: None,
+            filter: ExportFilter::default(),
+            mask: false,
+            strip_locations: false,
+            compression_level: ZSTD_LEVEL,
+        }
+    }
+
+    /// Write the export in the given format
+    pub fn with_format(mut self, format: ExportFormat) -> Self {
+        self.format = format;
+        self
     }
 
-    /// Export all commands to a JSON file
+    /// Compress zstd exports at this level instead of the default 19
+    pub fn with_compression_level(mut self, level: i32) -> Self {
+        self.compression_level = level;
+        self
+    }
+
+    /// Leave out records matching these redaction patterns (SQLite exports)
+    pub fn with_redactor(mut self, redactor: RedactionEngine) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Mask sensitive text in every exported record with the redactor
+    /// (`export --redact`), and with `strip_locations` also leave out
+    /// working directories and hostnames
+    pub fn with_masking(mut self, strip_locations: bool) -> Self {
+        self.mask = true;
+        self.strip_locations = strip_locations;
+        self
+    }
+
+    /// Only export commands matching a filter
+    pub fn with_filter(mut self, filter: ExportFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Export all commands to a file
+    ///
+    /// Refuses to write over the database being exported.
     pub fn export<P: AsRef<Path>>(&self, output_path: P) -> Result<ExportStats> {
-        let commands = self.storage.get_all()?;
+        let path = output_path.as_ref();
+        if self.is_database(path) {
+            return Err(OmniscientError::other(format!(
+                "Not exporting to {}: it is the database being exported",
+                path.display()
+            )));
+        }
+        self.export_file(path)
+            .with_context(|| format!("export to {}", path.display()))
+    }
+
+    /// Write the export in the configured format
+    fn export_file(&self, output_path: &Path) -> Result<ExportStats> {
+        match self.format {
+            ExportFormat::Sqlite => return self.export_sqlite(output_path),
+            ExportFormat::Ndjson => return self.export_ndjson(output_path),
+            _ => {}
+        }
+
+        let mut commands = self.storage.get_all()?;
+        commands.retain(|record| self.filter.matches(record));
+        commands.iter_mut().for_each(|record| self.sanitize(record));
         let command_count = commands.len();
 
-        let export_data = ExportData {
+        let contents = match self.format {
+            ExportFormat::Json | ExportFormat::CompressedJson => {
+                let export_data = ExportData {
+                    version: EXPORT_VERSION.to_string(),
+                    exported_at: chrono::Utc::now().to_rfc3339(),
+                    command_count,
+                    commands,
+                };
+
+                // Serialize to pretty JSON
+                let json = serde_json::to_string_pretty(&export_data)?;
+                if self.format == ExportFormat::CompressedJson {
+                    zstd::encode_all(json.as_bytes(), self.compression_level)?
+                } else {
+                    json.into_bytes()
+                }
+            }
+            ExportFormat::Markdown => render_markdown(&commands).into_bytes(),
+            ExportFormat::Sqlite | ExportFormat::Ndjson => {
+                unreachable!("streamed formats are written by their own methods")
+            }
+        };
+
+        // Write to file
+        fs::write(output_path, contents)?;
+
+        Ok(ExportStats {
+            commands_exported: command_count,
+            file_path: output_path.display().to_string(),
+        })
+    }
+
+    /// Stream records to a newline-delimited JSON file with constant memory
+    fn export_ndjson(&self, output_path: &Path) -> Result<ExportStats> {
+        let mut writer = BufWriter::new(fs::File::create(output_path)?);
+
+        let header = NdjsonHeader {
+            format: NDJSON_FORMAT.to_string(),
             version: EXPORT_VERSION.to_string(),
             exported_at: chrono::Utc::now().to_rfc3339(),
-            command_count,
-            commands,
         };
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+
+        let mut commands_exported = 0;
+        self.storage.for_each_record(|mut record| {
+            if self.filter.matches(&record) {
+                self.sanitize(&mut record);
+                serde_json::to_writer(&mut writer, &record)?;
+                writer.write_all(b"\n")?;
+                commands_exported += 1;
+            }
+            Ok(())
+        })?;
+        writer.flush()?;
 
-        // Serialize to pretty JSON
-        let json = serde_json::to_string_pretty(&export_data)?;
+        Ok(ExportStats {
+            commands_exported,
+            file_path: output_path.display().to_string(),
+        })
+    }
 
-        // Write to file
-        fs::write(output_path.as_ref(), json)?;
+    /// Copy the database with the backup API, then prune the copy
+    ///
+    /// The copy is built in a temporary file next to `output_path` and only
+    /// then moved over it.
+    fn export_sqlite(&self, output_path: &Path) -> Result<ExportStats> {
+        let mut partial = output_path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+
+        let written = self.write_sqlite(&partial);
+        // The temporary file's journal never outlives it
+        for suffix in ["-wal", "-shm"] {
+            let _ = fs::remove_file(with_suffix(&partial, suffix));
+        }
+        let commands_exported = match written {
+            Ok(count) => count,
+            Err(e) => {
+                let _ = fs::remove_file(&partial);
+                return Err(e);
+            }
+        };
+
+        // A journal left next to the target would be applied to the new copy
+        for suffix in ["-wal", "-shm"] {
+            let stale = with_suffix(output_path, suffix);
+            if stale.exists() {
+                fs::remove_file(&stale)?;
+            }
+        }
+        fs::rename(&partial, output_path)?;
 
         Ok(ExportStats {
-            commands_exported: command_count,
-            file_path: output_path.as_ref().display().to_string(),
+            commands_exported,
+            file_path: output_path.display().to_string(),
         })
     }
+
+    /// Write the pruned copy to `path`, returning the number of commands
+    fn write_sqlite(&self, path: &Path) -> Result<usize> {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        self.storage.backup_to(path)?;
+
+        let copy = Storage::new(path)?;
+        if self.mask {
+            // Masked before pruning, so the vacuum also clears the originals
+            copy.rewrite_records(REDACT_BATCH_SIZE, |record| {
+                let mut sanitized = record.clone();
+                self.sanitize(&mut sanitized);
+                (sanitized != *record).then_some(RecordChange::Update(sanitized))
+            })?;
+            copy.scrub_for_sharing(self.strip_locations)?;
+        }
+        copy.prune_for_archive(|record| {
+            let redacted = match self.redactor {
+                Some(ref redactor) => {
+                    redactor.should_redact(&record.command)
+                        || record
+                            .typed_command
+                            .as_deref()
+                            .is_some_and(|typed| redactor.should_redact(typed))
+                }
+                None => false,
+            };
+            redacted || !self.filter.matches(record)
+        })?;
+
+        copy.count()
+    }
+
+    /// Whether `path` is the file of the database being exported
+    fn is_database(&self, path: &Path) -> bool {
+        let Some(database) = self.storage.database_path() else {
+            return false;
+        };
+        match (fs::canonicalize(database), fs::canonicalize(path)) {
+            (Ok(database), Ok(path)) => database == path,
+            _ => false,
+        }
+    }
+}
+
+/// `path` with `suffix` appended to its file name (`archive.db-wal`)
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file = path.as_os_str().to_owned();
+    file.push(suffix);
+    PathBuf::from(file)
+}
+
+impl Exporter {
+    /// Apply `export --redact` to one record
+    fn sanitize(&self, record: &mut CommandRecord) {
+        if !self.mask {
+            return;
+        }
+        if let Some(ref redactor) = self.redactor {
+            redactor.mask_record(record);
+        }
+        if self.strip_locations {
+            record.working_dir = String::new();
+        }
+    }
+}
+
+/// Render commands as a Markdown document grouped by category
+///
+/// Categories with the most commands come first; within a category commands
+/// are ordered by usage count.
+pub fn render_markdown(commands: &[CommandRecord]) -> String {
+    let mut by_category: BTreeMap<&str, Vec<&CommandRecord>> = BTreeMap::new();
+    for cmd in commands {
+        by_category
+            .entry(cmd.category.as_str())
+            .or_default()
+            .push(cmd);
+    }
+
+    let mut groups: Vec<(&str, Vec<&CommandRecord>)> = by_category.into_iter().collect();
+    groups.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then(a.0.cmp(b.0)));
+
+    let mut out = String::from("# Command History\n\n");
+    out.push_str(&format!(
+        "_Exported {} · {} commands_\n",
+        chrono::Utc::now().format("%Y-%m-%d"),
+        commands.len()
+    ));
+
+    for (category, mut cmds) in groups {
+        cmds.sort_by(|a, b| {
+            b.usage_count
+                .cmp(&a.usage_count)
+                .then(a.command.cmp(&b.command))
+        });
+
+        out.push_str(&format!("\n## {} ({})\n", category, cmds.len()));
+
+        for cmd in cmds {
+            // Use a fence longer than any backtick run inside the command
+            let fence = "`".repeat(longest_backtick_run(&cmd.command).max(2) + 1);
+            out.push_str(&format!("\n{}sh\n{}\n{}\n", fence, cmd.command, fence));
+            out.push_str(&format!(
+                "Used {} {} · last used {} · `{}`\n",
+                cmd.usage_count,
+                if cmd.usage_count == 1 {
+                    "time"
+                } else {
+                    "times"
+                },
+                cmd.last_used.format("%Y-%m-%d"),
+                cmd.working_dir
+            ));
+            if let Some(ref note) = cmd.note {
+                out.push_str(&format!("\n> {}\n", note));
+            }
+        }
+    }
+
+    out
+}
+
+/// Length of the longest run of consecutive backticks in a string
+pub(crate) fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+/// Periodic compressed exports to dated files, keeping only the newest few
+///
+/// Exports are named `history-YYYY-MM-DD.json.zst`. A new one is written once
+/// the newest existing export is at least `interval_days` old.
+pub struct ExportSchedule {
+    rotation: Rotation,
+}
+
+impl ExportSchedule {
+    /// Create a schedule writing to `directory`
+    pub fn new<P: Into<PathBuf>>(directory: P, interval_days: u32, keep: usize) -> Self {
+        Self {
+            rotation: Rotation::new(directory, SCHEDULED_PREFIX, SCHEDULED_SUFFIX, "%Y-%m-%d")
+                .with_interval_days(i64::from(interval_days))
+                .with_keep(keep),
+        }
+    }
+
+    /// Create a schedule from the `[auto_export]` config section
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self::new(
+            config.auto_export_dir()?,
+            config.auto_export.interval_days,
+            config.auto_export.keep,
+        ))
+    }
+
+    /// Existing scheduled exports, oldest first
+    pub fn existing(&self) -> Result<Vec<(NaiveDate, PathBuf)>> {
+        Ok(self
+            .rotation
+            .existing()?
+            .into_iter()
+            .map(|(stamp, path)| (stamp.date(), path))
+            .collect())
+    }
+
+    /// Whether an export should be written on the given day
+    pub fn is_due(&self, today: NaiveDate) -> Result<bool> {
+        self.rotation.is_due(today.and_time(NaiveTime::MIN))
+    }
+
+    /// Write today's export if one is due, then delete exports beyond the
+    /// retention limit
+    ///
+    /// Returns the path of the new export, or `None` if none was due or
+    /// another process is already writing it.
+    pub fn run_if_due(&self, storage: Storage, today: NaiveDate) -> Result<Option<PathBuf>> {
+        self.rotation
+            .write_if_due(today.and_time(NaiveTime::MIN), |partial| {
+                Exporter::new(storage)
+                    .with_format(ExportFormat::CompressedJson)
+                    .with_compression_level(SCHEDULED_ZSTD_LEVEL)
+                    .export(partial)
+                    .map(|_| ())
+            })
+    }
+
+    /// Delete the oldest exports beyond the retention limit
+    ///
+    /// Returns the number of files deleted.
+    pub fn prune(&self) -> Result<usize> {
+        self.rotation.prune()
+    }
 }
 
 /// Statistics from an export operation
@@ -80,24 +542,97 @@ pub enum ImportStrategy {
     /// Preserve the higher usage count
     #[default]
     PreserveHigher,
+
+    /// Replace existing commands with the imported ones
+    Overwrite,
+}
+
+impl ImportStrategy {
+    /// Look up a strategy by name ("skip", "merge", "preserve-higher" or
+    /// "overwrite")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "skip" => Some(ImportStrategy::Skip),
+            "merge" | "update-usage" => Some(ImportStrategy::UpdateUsage),
+            "preserve-higher" => Some(ImportStrategy::PreserveHigher),
+            "overwrite" => Some(ImportStrategy::Overwrite),
+            _ => None,
+        }
+    }
 }
 
 /// Import command history from JSON file
 pub struct Importer {
     storage: Storage,
     strategy: ImportStrategy,
+    duplicate_key: DuplicateKey,
 }
 
 impl Importer {
     /// Create a new importer with the given storage and strategy
     pub fn new(storage: Storage, strategy: ImportStrategy) -> Self {
-        Self { storage, strategy }
+        Self {
+            storage,
+            strategy,
+            duplicate_key: DuplicateKey::default(),
+        }
     }
 
-    /// Import commands from a JSON file
+    /// Match duplicates using the given key (should agree with capture settings)
+    pub fn with_duplicate_key(mut self, key: DuplicateKey) -> Self {
+        self.duplicate_key = key;
+        self
+    }
+
+    /// Import commands from a JSON or NDJSON export (optionally
+    /// zstd-compressed) or an SQLite backup
     pub fn import<P: AsRef<Path>>(&self, input_path: P) -> Result<ImportStats> {
-        // Read and parse the JSON file
-        let json = fs::read_to_string(input_path.as_ref())?;
+        let path = input_path.as_ref();
+        self.import_file(path)
+            .with_context(|| format!("import {}", path.display()))
+    }
+
+    /// Import a file in a single transaction, so a file that fails halfway
+    /// leaves the database unchanged
+    pub fn import_atomic<P: AsRef<Path>>(&self, input_path: P) -> Result<ImportStats> {
+        self.storage
+            .write_with_retry(0, || self.import(input_path.as_ref()))
+    }
+
+    /// What importing a file would do, without changing the database
+    pub fn preview<P: AsRef<Path>>(&self, input_path: P) -> Result<ImportStats> {
+        self.storage.dry_run(|| self.import(input_path.as_ref()))
+    }
+
+    /// Detect the export format of a file and import it
+    fn import_file(&self, input_path: &Path) -> Result<ImportStats> {
+        if crate::backup::is_sqlite(input_path)? {
+            let records = crate::backup::read_records(input_path)?;
+            let mut stats = ImportStats {
+                total_commands: records.len(),
+                imported: 0,
+                skipped: 0,
+                updated: 0,
+            };
+            self.import_records(records.into_iter().map(Ok), &mut stats)?;
+            return Ok(stats);
+        }
+
+        let mut reader = open_export(input_path)?;
+
+        // NDJSON exports start with a one-line header; anything else is a
+        // regular JSON document
+        let mut first_line = String::new();
+        reader.read_line(&mut first_line)?;
+
+        if let Ok(header) = serde_json::from_str::<NdjsonHeader>(first_line.trim()) {
+            if header.format == NDJSON_FORMAT {
+                return self.import_ndjson(reader);
+            }
+        }
+
+        let mut json = first_line;
+        reader.read_to_string(&mut json)?;
         let export_data: ExportData = serde_json::from_str(&json)?;
 
         // Validate version (for now, just check it exists)
@@ -114,56 +649,163 @@ impl Importer {
             updated: 0,
         };
 
-        // Import each command
-        for cmd in export_data.commands {
+        self.import_records(export_data.commands.into_iter().map(Ok), &mut stats)?;
+
+        Ok(stats)
+    }
+
+    /// Import the records following an NDJSON header, one per line
+    fn import_ndjson(&self, reader: Box<dyn BufRead>) -> Result<ImportStats> {
+        let mut stats = ImportStats {
+            total_commands: 0,
+            imported: 0,
+            skipped: 0,
+            updated: 0,
+        };
+
+        // Line numbers count the header as line 1
+        let records = reader
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, Ok(l) if l.trim().is_empty()))
+            .map(|(index, line)| {
+                serde_json::from_str::<CommandRecord>(&line?)
+                    .with_context(|| format!("line {}", index + 2))
+            });
+
+        self.import_records(records, &mut stats)?;
+        stats.total_commands = stats.imported + stats.updated + stats.skipped;
+
+        Ok(stats)
+    }
+
+    /// Merge or insert records, writing new ones in batches
+    ///
+    /// Everything runs in one transaction, so a bad record halfway through
+    /// undoes the merges into existing records as well as the inserts.
+    fn import_records<I>(&self, records: I, stats: &mut ImportStats) -> Result<()>
+    where
+        I: Iterator<Item = Result<CommandRecord>>,
+    {
+        self.storage
+            .with_tx(|| self.import_records_in_tx(records, stats))
+    }
+
+    fn import_records_in_tx<I>(&self, records: I, stats: &mut ImportStats) -> Result<()>
+    where
+        I: Iterator<Item = Result<CommandRecord>>,
+    {
+        // New records are collected and inserted in batches; the map tracks
+        // unflushed ones by duplicate key so repeats within the file still merge
+        let mut new_records: Vec<CommandRecord> = Vec::new();
+        let mut pending: HashMap<PendingKey, usize> = HashMap::new();
+
+        for cmd in records {
+            let cmd = cmd?;
+            let key = self.pending_key(&cmd);
+            if let Some(&index) = pending.get(&key) {
+                self.merge(&mut new_records[index], &cmd, stats);
+                continue;
+            }
+
             // Check for duplicates
-            let duplicate = self
-                .storage
-                .find_duplicate(&cmd.command, &cmd.working_dir)?;
+            let duplicate = self.storage.find_duplicate_by(
+                self.duplicate_key,
+                &cmd.command,
+                &cmd.working_dir,
+                cmd.exit_code,
+            )?;
 
             match duplicate {
                 Some(existing) => {
-                    // Handle duplicate based on strategy
-                    match self.strategy {
-                        ImportStrategy::Skip => {
-                            stats.skipped += 1;
-                        }
-                        ImportStrategy::UpdateUsage => {
-                            // Update the existing command with combined usage count
-                            let new_count = existing.usage_count + cmd.usage_count;
-                            self.update_usage_count(existing.id.unwrap(), new_count)?;
-                            stats.updated += 1;
-                        }
-                        ImportStrategy::PreserveHigher => {
-                            // Keep the higher usage count
-                            if cmd.usage_count > existing.usage_count {
-                                self.update_usage_count(existing.id.unwrap(), cmd.usage_count)?;
-                                stats.updated += 1;
-                            } else {
-                                stats.skipped += 1;
-                            }
-                        }
+                    let mut merged = existing.clone();
+                    self.merge(&mut merged, &cmd, stats);
+
+                    if merged != existing {
+                        self.storage.update_record(&merged)?;
                     }
                 }
                 None => {
-                    // No duplicate, insert as new command
-                    self.storage.insert(&cmd)?;
+                    // No duplicate, queue as new command
+                    pending.insert(key, new_records.len());
+                    new_records.push(cmd);
                     stats.imported += 1;
+
+                    if new_records.len() >= IMPORT_BATCH_SIZE {
+                        // Flushed records are found by find_duplicate_by from now on
+                        self.storage.insert_batch(&new_records)?;
+                        new_records.clear();
+                        pending.clear();
+                    }
                 }
             }
         }
 
-        Ok(stats)
-    }
+        self.storage.insert_batch(&new_records)?;
 
-    /// Update usage count for an existing command
-    fn update_usage_count(&self, id: i64, _new_count: i32) -> Result<()> {
-        // For now, we'll just increment once to update last_used timestamp
-        // The usage count merging is a best-effort approach
-        // TODO: Add a set_usage_count method to Storage for more accurate updates
-        self.storage.increment_usage(id)?;
         Ok(())
     }
+
+    /// Merge an imported duplicate into an existing record according to the strategy
+    fn merge(
+        &self,
+        existing: &mut CommandRecord,
+        incoming: &CommandRecord,
+        stats: &mut ImportStats,
+    ) {
+        // Carry over a note the local record doesn't have yet
+        if existing.note.is_none() {
+            existing.note = incoming.note.clone();
+        }
+
+        match self.strategy {
+            ImportStrategy::Skip => {
+                stats.skipped += 1;
+            }
+            ImportStrategy::UpdateUsage => {
+                // Combine usage counts and keep the latest use
+                existing.usage_count += incoming.usage_count;
+                existing.last_used = existing.last_used.max(incoming.last_used);
+                stats.updated += 1;
+            }
+            ImportStrategy::PreserveHigher => {
+                // Keep the higher usage count
+                if incoming.usage_count > existing.usage_count {
+                    existing.usage_count = incoming.usage_count;
+                    existing.last_used = existing.last_used.max(incoming.last_used);
+                    stats.updated += 1;
+                } else {
+                    stats.skipped += 1;
+                }
+            }
+            ImportStrategy::Overwrite => {
+                // The imported record wins; only the local ID (and a note
+                // the import lacks) survive
+                let note = existing.note.take();
+                *existing = CommandRecord {
+                    id: existing.id,
+                    note: incoming.note.clone().or(note),
+                    ..incoming.clone()
+                };
+                stats.updated += 1;
+            }
+        }
+    }
+
+    /// Key identifying records that count as duplicates of each other
+    fn pending_key(&self, cmd: &CommandRecord) -> PendingKey {
+        match self.duplicate_key {
+            DuplicateKey::CommandAndDir => {
+                (cmd.command.clone(), Some(cmd.working_dir.clone()), None)
+            }
+            DuplicateKey::CommandDirAndExitCode => (
+                cmd.command.clone(),
+                Some(cmd.working_dir.clone()),
+                Some(cmd.exit_code),
+            ),
+            DuplicateKey::Command => (cmd.command.clone(), None, None),
+        }
+    }
 }
 
 /// Statistics from an import operation
@@ -188,6 +830,7 @@ impl ImportStats {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::ExecutionMeta;
     use chrono::Utc;
     use tempfile::NamedTempFile;
 
@@ -246,6 +889,392 @@ mod tests {
         assert_eq!(export_data.commands.len(), 2);
     }
 
+    #[test]
+    fn test_export_filter() {
+        let storage = create_test_storage();
+        let mut deploy = create_test_command("kubectl apply -f api.yaml", "kubernetes", 4);
+        deploy.working_dir = "/home/me/work/api/deploy".to_string();
+        storage.insert(&deploy).unwrap();
+        let mut failed = create_test_command("kubectl rollout undo", "kubernetes", 1);
+        failed.working_dir = "/home/me/work/api".to_string();
+        failed.exit_code = 1;
+        storage.insert(&failed).unwrap();
+        let mut old = create_test_command("git status", "git", 7);
+        old.working_dir = "/home/me/work/api".to_string();
+        old.last_used = Utc::now() - chrono::Duration::days(400);
+        storage.insert(&old).unwrap();
+        let mut elsewhere = create_test_command("kubectl get pods", "kubernetes", 2);
+        elsewhere.working_dir = "/home/me/work/apiary".to_string();
+        storage.insert(&elsewhere).unwrap();
+
+        let filter = ExportFilter {
+            categories: vec!["kubernetes".to_string()],
+            working_dir: Some("/home/me/work/api/".to_string()),
+            recursive: true,
+            exclude_failed: true,
+            ..Default::default()
+        };
+        let exporter = Exporter::new(storage).with_filter(filter);
+        let temp_file = NamedTempFile::new().unwrap();
+        let stats = exporter.export(temp_file.path()).unwrap();
+        assert_eq!(stats.commands_exported, 1);
+        let data: ExportData =
+            serde_json::from_str(&fs::read_to_string(temp_file.path()).unwrap()).unwrap();
+        assert_eq!(data.commands[0].command, "kubectl apply -f api.yaml");
+
+        // Without recursion only the directory itself; since skips old commands
+        let filter = ExportFilter {
+            working_dir: Some("/home/me/work/api".to_string()),
+            since: Some(Utc::now() - chrono::Duration::days(30)),
+            ..Default::default()
+        };
+        let exporter = exporter
+            .with_filter(filter)
+            .with_format(ExportFormat::Ndjson);
+        let stats = exporter.export(temp_file.path()).unwrap();
+        assert_eq!(stats.commands_exported, 1);
+        let contents = fs::read_to_string(temp_file.path()).unwrap();
+        assert!(contents.contains("kubectl rollout undo"));
+        assert!(!contents.contains("git status"));
+    }
+
+    #[test]
+    fn test_export_markdown() {
+        let storage = create_test_storage();
+        storage
+            .insert(&create_test_command("git status", "git", 5))
+            .unwrap();
+        storage
+            .insert(&create_test_command("git log", "git", 9))
+            .unwrap();
+        let mut noted = create_test_command("docker ps", "docker", 1);
+        noted.note = Some("list running containers".to_string());
+        storage.insert(&noted).unwrap();
+
+        let exporter = Exporter::new(storage).with_format(ExportFormat::Markdown);
+        let temp_file = NamedTempFile::new().unwrap();
+        let stats = exporter.export(temp_file.path()).unwrap();
+        assert_eq!(stats.commands_exported, 3);
+
+        let markdown = fs::read_to_string(temp_file.path()).unwrap();
+        assert!(markdown.starts_with("# Command History"));
+        assert!(markdown.contains("## git (2)"));
+        assert!(markdown.contains("```sh\ngit log\n```"));
+        assert!(markdown.contains("Used 1 time · last used"));
+        assert!(markdown.contains("> list running containers"));
+
+        // Larger categories first, most used commands first within a category
+        let git = markdown.find("## git").unwrap();
+        let docker = markdown.find("## docker").unwrap();
+        assert!(git < docker);
+        assert!(markdown.find("git log").unwrap() < markdown.find("git status").unwrap());
+    }
+
+    #[test]
+    fn test_markdown_fence_longer_than_backticks_in_command() {
+        let cmd = create_test_command("echo ```", "other", 1);
+        let markdown = render_markdown(&[cmd]);
+        assert!(markdown.contains("````sh\necho ```\n````"));
+    }
+
+    #[test]
+    fn test_compressed_export_roundtrip() {
+        let source_storage = create_test_storage();
+        source_storage
+            .insert(&create_test_command("git status", "git", 5))
+            .unwrap();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        Exporter::new(source_storage)
+            .with_format(ExportFormat::CompressedJson)
+            .export(temp_file.path())
+            .unwrap();
+
+        let bytes = fs::read(temp_file.path()).unwrap();
+        assert!(bytes.starts_with(&ZSTD_MAGIC));
+
+        let importer = Importer::new(create_test_storage(), ImportStrategy::Skip);
+        let stats = importer.import(temp_file.path()).unwrap();
+        assert_eq!(stats.imported, 1);
+    }
+
+    #[test]
+    fn test_export_sqlite_drops_redacted_records() {
+        let storage = create_test_storage();
+        storage
+            .insert(&create_test_command("git status", "git", 5))
+            .unwrap();
+        let mut aliased = create_test_command("cat ~/.creds", "file", 1);
+        aliased.typed_command = Some("show_password".to_string());
+        storage.insert(&aliased).unwrap();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("archive.db");
+        let redactor = RedactionEngine::new(vec!["password".to_string()], true).unwrap();
+        let stats = Exporter::new(storage)
+            .with_format(ExportFormat::Sqlite)
+            .with_redactor(redactor)
+            .export(&path)
+            .unwrap();
+        assert_eq!(stats.commands_exported, 1);
+
+        // The copy is a plain single-file database
+        assert!(!dir.path().join("archive.db-wal").exists());
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        let commands: Vec<String> = conn
+            .prepare("SELECT command FROM commands")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        assert_eq!(commands, vec!["git status"]);
+    }
+
+    #[test]
+    fn test_export_sqlite_replaces_target_safely() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = dir.path().join("history.db");
+        let storage = Storage::new(&db).unwrap();
+        storage
+            .insert(&create_test_command("git status", "git", 5))
+            .unwrap();
+        let exporter = Exporter::new(storage).with_format(ExportFormat::Sqlite);
+
+        // Never over the database itself, however it is spelled
+        let same = dir.path().join(".").join("history.db");
+        assert!(exporter.export(&same).is_err());
+        assert!(exporter.export(&db).is_err());
+        assert_eq!(Storage::new(&db).unwrap().count().unwrap(), 1);
+
+        // A stale journal next to the target is cleared, not applied
+        let path = dir.path().join("archive.db");
+        fs::write(&path, "old archive").unwrap();
+        fs::write(dir.path().join("archive.db-wal"), "stale").unwrap();
+        exporter.export(&path).unwrap();
+        assert!(!dir.path().join("archive.db-wal").exists());
+        assert!(!dir.path().join("archive.db.partial").exists());
+        assert_eq!(Storage::new(&path).unwrap().count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_export_with_masking() {
+        let storage = create_test_storage();
+        let id = storage
+            .insert(&create_test_command("export API_TOKEN=abc123", "system", 1))
+            .unwrap();
+        let meta = ExecutionMeta {
+            host: Some("laptop".to_string()),
+            ..Default::default()
+        };
+        let execution = storage.log_execution(id, Utc::now(), 0, 1, &meta).unwrap();
+        storage
+            .save_output(execution, "token accepted", false)
+            .unwrap();
+        storage
+            .insert(&create_test_command("git status", "git", 5))
+            .unwrap();
+        let redactor = RedactionEngine::new(vec!["token".to_string()], true).unwrap();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let exporter = Exporter::new(storage)
+            .with_redactor(redactor)
+            .with_masking(true);
+        assert_eq!(
+            exporter.export(temp_file.path()).unwrap().commands_exported,
+            2
+        );
+        let contents = fs::read_to_string(temp_file.path()).unwrap();
+        assert!(!contents.contains("abc123"));
+        assert!(!contents.contains("/tmp"));
+        let data: ExportData = serde_json::from_str(&contents).unwrap();
+        let commands: Vec<&str> = data.commands.iter().map(|c| c.command.as_str()).collect();
+        assert!(commands.contains(&"export [REDACTED]"));
+        assert!(commands.contains(&"git status"));
+
+        // SQLite exports keep masked records and lose hosts and output
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("archive.db");
+        let stats = exporter
+            .with_format(ExportFormat::Sqlite)
+            .export(&path)
+            .unwrap();
+        assert_eq!(stats.commands_exported, 2);
+        let raw = fs::read(&path).unwrap();
+        let raw = String::from_utf8_lossy(&raw);
+        assert!(!raw.contains("abc123"));
+        assert!(!raw.contains("laptop"));
+        assert!(!raw.contains("token accepted"));
+        assert!(raw.contains("export [REDACTED]"));
+    }
+
+    #[test]
+    fn test_ndjson_roundtrip() {
+        let source_storage = create_test_storage();
+        source_storage
+            .insert(&create_test_command("git status", "git", 5))
+            .unwrap();
+        source_storage
+            .insert(&create_test_command("docker ps", "docker", 3))
+            .unwrap();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let stats = Exporter::new(source_storage)
+            .with_format(ExportFormat::Ndjson)
+            .export(temp_file.path())
+            .unwrap();
+        assert_eq!(stats.commands_exported, 2);
+
+        let contents = fs::read_to_string(temp_file.path()).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("\"format\":\"ndjson\""));
+
+        let target = create_test_storage();
+        target
+            .insert(&create_test_command("git status", "git", 1))
+            .unwrap();
+        let importer = Importer::new(target, ImportStrategy::PreserveHigher);
+        let stats = importer.import(temp_file.path()).unwrap();
+
+        assert_eq!(stats.total_commands, 2);
+        assert_eq!(stats.imported, 1);
+        assert_eq!(stats.updated, 1);
+        assert_eq!(importer.storage.count().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_ndjson_roundtrip_multiline_command() {
+        let command = "cat <<EOF > motd\nwelcome\nEOF";
+        let source_storage = create_test_storage();
+        source_storage
+            .insert(&create_test_command(command, "other", 0))
+            .unwrap();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        Exporter::new(source_storage)
+            .with_format(ExportFormat::Ndjson)
+            .export(temp_file.path())
+            .unwrap();
+
+        // Line breaks in commands are escaped, so each record stays on one line
+        let contents = fs::read_to_string(temp_file.path()).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        assert!(contents.contains(r#""cat <<EOF > motd\nwelcome\nEOF""#));
+
+        let importer = Importer::new(create_test_storage(), ImportStrategy::Skip);
+        let stats = importer.import(temp_file.path()).unwrap();
+        assert_eq!(stats.imported, 1);
+        assert_eq!(importer.storage.get_all().unwrap()[0].command, command);
+    }
+
+    #[test]
+    fn test_ndjson_import_rejects_malformed_line() {
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(
+            temp_file.path(),
+            "{\"format\":\"ndjson\",\"version\":\"1.0\",\"exported_at\":\"now\"}\nnot json\n",
+        )
+        .unwrap();
+
+        let importer = Importer::new(create_test_storage(), ImportStrategy::Skip);
+        assert!(importer.import(temp_file.path()).is_err());
+    }
+
+    #[test]
+    fn test_failed_import_leaves_existing_records_unchanged() {
+        let storage = create_test_storage();
+        storage
+            .insert(&create_test_command("git status", "git", 5))
+            .unwrap();
+
+        let merged = serde_json::to_string(&create_test_command("git status", "git", 3)).unwrap();
+        let new = serde_json::to_string(&create_test_command("ls -la", "file", 1)).unwrap();
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(
+            temp_file.path(),
+            format!(
+                "{{\"format\":\"{}\",\"version\":\"1.0\",\"exported_at\":\"now\"}}\n{}\n{}\nnot json\n",
+                NDJSON_FORMAT, merged, new
+            ),
+        )
+        .unwrap();
+
+        let importer = Importer::new(storage, ImportStrategy::UpdateUsage);
+        assert!(importer.import(temp_file.path()).is_err());
+
+        let records = importer.storage.get_all().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].usage_count, 5);
+    }
+
+    #[test]
+    fn test_export_schedule_interval() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let schedule = ExportSchedule::new(dir.path().join("exports"), 2, 7);
+        let day = |d: u32| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+
+        let first = schedule.run_if_due(create_test_storage(), day(1)).unwrap();
+        assert_eq!(
+            first.unwrap().file_name().unwrap(),
+            "history-2024-03-01.json.zst"
+        );
+
+        // Not due again until the interval has passed
+        assert!(schedule
+            .run_if_due(create_test_storage(), day(2))
+            .unwrap()
+            .is_none());
+        assert!(schedule
+            .run_if_due(create_test_storage(), day(3))
+            .unwrap()
+            .is_some());
+
+        assert_eq!(schedule.existing().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_export_schedule_skips_while_locked() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let schedule = ExportSchedule::new(dir.path(), 1, 2);
+        let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+
+        let lock =
+            crate::lock::FileLock::try_acquire(&dir.path().join(".history.json.zst.lock")).unwrap();
+        assert!(schedule
+            .run_if_due(create_test_storage(), today)
+            .unwrap()
+            .is_none());
+        assert!(schedule.existing().unwrap().is_empty());
+
+        drop(lock);
+        assert!(schedule
+            .run_if_due(create_test_storage(), today)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_export_schedule_rotation() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let schedule = ExportSchedule::new(dir.path(), 1, 2);
+        fs::write(dir.path().join("unrelated.txt"), "keep me").unwrap();
+
+        for d in 1..=4 {
+            let today = NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+            schedule.run_if_due(create_test_storage(), today).unwrap();
+        }
+
+        let dates: Vec<String> = schedule
+            .existing()
+            .unwrap()
+            .iter()
+            .map(|(date, _)| date.to_string())
+            .collect();
+        assert_eq!(dates, vec!["2024-03-03", "2024-03-04"]);
+        assert!(dir.path().join("unrelated.txt").exists());
+    }
+
     #[test]
     fn test_import_new_commands() {
         let storage = create_test_storage();
@@ -330,6 +1359,64 @@ mod tests {
         assert_eq!(stats.imported, 0);
         assert_eq!(stats.skipped, 0);
         assert_eq!(stats.updated, 1); // Higher count preserved
+
+        let records = importer.storage.get_all().unwrap();
+        assert_eq!(records[0].usage_count, 10);
+    }
+
+    #[test]
+    fn test_import_with_duplicates_update_usage() {
+        let storage = create_test_storage();
+        storage
+            .insert(&create_test_command("git status", "git", 5))
+            .unwrap();
+
+        let source_storage = create_test_storage();
+        source_storage
+            .insert(&create_test_command("git status", "git", 3))
+            .unwrap();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        Exporter::new(source_storage)
+            .export(temp_file.path())
+            .unwrap();
+
+        let importer = Importer::new(storage, ImportStrategy::UpdateUsage);
+        let stats = importer.import(temp_file.path()).unwrap();
+        assert_eq!(stats.updated, 1);
+
+        let records = importer.storage.get_all().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].usage_count, 8);
+    }
+
+    #[test]
+    fn test_import_with_duplicates_overwrite() {
+        let storage = create_test_storage();
+        let mut local = create_test_command("git status", "git", 50);
+        local.note = Some("local note".to_string());
+        storage.insert(&local).unwrap();
+
+        let source_storage = create_test_storage();
+        let mut incoming = create_test_command("git status", "vcs", 3);
+        incoming.exit_code = 1;
+        source_storage.insert(&incoming).unwrap();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        Exporter::new(source_storage)
+            .export(temp_file.path())
+            .unwrap();
+
+        let importer = Importer::new(storage, ImportStrategy::Overwrite);
+        let stats = importer.import(temp_file.path()).unwrap();
+        assert_eq!(stats.updated, 1);
+
+        let records = importer.storage.get_all().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].usage_count, 3);
+        assert_eq!(records[0].category, "vcs");
+        assert_eq!(records[0].exit_code, 1);
+        assert_eq!(records[0].note.as_deref(), Some("local note"));
     }
 
     #[test]
@@ -361,4 +1448,180 @@ mod tests {
         assert_eq!(import_stats.imported, 3);
         assert_eq!(import_stats.skipped, 0);
     }
+
+    #[test]
+    fn test_import_respects_duplicate_key() {
+        let source_storage = create_test_storage();
+        let mut failed = create_test_command("terraform apply", "cloud", 1);
+        failed.exit_code = 1;
+        source_storage.insert(&failed).unwrap();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        Exporter::new(source_storage)
+            .export(temp_file.path())
+            .unwrap();
+
+        let target_storage = create_test_storage();
+        target_storage
+            .insert(&create_test_command("terraform apply", "cloud", 1))
+            .unwrap();
+
+        let importer = Importer::new(target_storage, ImportStrategy::Skip)
+            .with_duplicate_key(DuplicateKey::CommandDirAndExitCode);
+        let stats = importer.import(temp_file.path()).unwrap();
+
+        assert_eq!(stats.imported, 1);
+        assert_eq!(importer.storage.count().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_import_merges_duplicates_within_file() {
+        let source_storage = create_test_storage();
+        source_storage
+            .insert(&create_test_command("git status", "git", 2))
+            .unwrap();
+        source_storage
+            .insert(&create_test_command("git status", "git", 7))
+            .unwrap();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        Exporter::new(source_storage)
+            .export(temp_file.path())
+            .unwrap();
+
+        let importer = Importer::new(create_test_storage(), ImportStrategy::PreserveHigher);
+        let stats = importer.import(temp_file.path()).unwrap();
+
+        assert_eq!(stats.imported, 1);
+        assert_eq!(stats.updated, 1);
+
+        let records = importer.storage.get_all().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].usage_count, 7);
+    }
+
+    #[test]
+    fn test_notes_survive_export_import() {
+        let source_storage = create_test_storage();
+        let mut noted = create_test_command("terraform apply", "cloud", 1);
+        noted.note = Some("run plan first".to_string());
+        source_storage.insert(&noted).unwrap();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        Exporter::new(source_storage)
+            .export(temp_file.path())
+            .unwrap();
+
+        let json = fs::read_to_string(temp_file.path()).unwrap();
+        assert!(json.contains("run plan first"));
+
+        // Existing record without a note picks up the imported one
+        let target_storage = create_test_storage();
+        target_storage
+            .insert(&create_test_command("terraform apply", "cloud", 1))
+            .unwrap();
+        let importer = Importer::new(target_storage, ImportStrategy::Skip);
+        importer.import(temp_file.path()).unwrap();
+
+        let records = importer.storage.get_all().unwrap();
+        assert_eq!(records[0].note.as_deref(), Some("run plan first"));
+    }
+
+    #[test]
+    fn test_import_error_names_file_and_line() {
+        let importer = Importer::new(create_test_storage(), ImportStrategy::Skip);
+
+        let input = NamedTempFile::new().unwrap();
+        fs::write(
+            input.path(),
+            format!(
+                "{{\"format\":\"{}\",\"version\":\"1\",\"exported_at\":\"x\"}}\n{{\"broken\"\n",
+                NDJSON_FORMAT
+            ),
+        )
+        .unwrap();
+
+        let err = importer.import(input.path()).unwrap_err();
+        let message = err.to_string();
+        assert!(message.starts_with(&format!("import {}: line 2:", input.path().display())));
+        assert!(matches!(
+            err.root_cause(),
+            crate::error::OmniscientError::Serialization(_)
+        ));
+    }
+
+    #[test]
+    fn test_preview_and_atomic_import() {
+        let storage = create_test_storage();
+        storage
+            .insert(&create_test_command("git status", "git", 5))
+            .unwrap();
+
+        let source = create_test_storage();
+        source
+            .insert(&create_test_command("git status", "git", 9))
+            .unwrap();
+        source
+            .insert(&create_test_command("docker ps", "docker", 1))
+            .unwrap();
+        let backup = NamedTempFile::new().unwrap();
+        source.backup_to(backup.path()).unwrap();
+
+        let importer = Importer::new(storage, ImportStrategy::PreserveHigher);
+        let preview = importer.preview(backup.path()).unwrap();
+        assert_eq!((preview.imported, preview.updated), (1, 1));
+        assert_eq!(importer.storage.count().unwrap(), 1);
+
+        let stats = importer.import_atomic(backup.path()).unwrap();
+        assert_eq!((stats.imported, stats.updated), (1, 1));
+        let records = importer.storage.get_all().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records
+            .iter()
+            .any(|r| r.command == "git status" && r.usage_count == 9));
+    }
+
+    #[test]
+    fn test_atomic_import_rolls_back() {
+        let storage = create_test_storage();
+        storage
+            .insert(&create_test_command("git status", "git", 5))
+            .unwrap();
+        let importer = Importer::new(storage, ImportStrategy::PreserveHigher);
+
+        let mut higher = create_test_command("git status", "git", 9);
+        higher.id = None;
+        let input = NamedTempFile::new().unwrap();
+        fs::write(
+            input.path(),
+            format!(
+                "{{\"format\":\"{}\",\"version\":\"1\",\"exported_at\":\"x\"}}\n{}\n{{\"broken\"\n",
+                NDJSON_FORMAT,
+                serde_json::to_string(&higher).unwrap()
+            ),
+        )
+        .unwrap();
+
+        assert!(importer.import_atomic(input.path()).is_err());
+        let records = importer.storage.get_all().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].usage_count, 5);
+    }
+
+    #[test]
+    fn test_strategy_from_name() {
+        assert_eq!(
+            ImportStrategy::from_name("merge"),
+            Some(ImportStrategy::UpdateUsage)
+        );
+        assert_eq!(
+            ImportStrategy::from_name("preserve-higher"),
+            Some(ImportStrategy::PreserveHigher)
+        );
+        assert_eq!(
+            ImportStrategy::from_name("overwrite"),
+            Some(ImportStrategy::Overwrite)
+        );
+        assert_eq!(ImportStrategy::from_name("newest"), None);
+    }
 }
@@ -0,0 +1,157 @@
+/// Flag usage of a program (`omniscient flags`)
+///
+/// Counts which flags, flag combinations and subcommands you use with one
+/// tool, weighted by how often each command ran, so the habits worth an
+/// alias stand out. Flags are counted by name: `--output=json` counts as
+/// `--output`, and bundles such as `-avz` are kept as written.
+use crate::models::CommandRecord;
+use crate::parse::ParsedCommand;
+use std::collections::HashMap;
+
+/// Flag usage of a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlagReport {
+    /// Number of distinct commands running the program
+    pub commands: usize,
+
+    /// Total runs of those commands
+    pub runs: i64,
+
+    /// Runs per flag, most used first
+    pub flags: Vec<(String, i64)>,
+
+    /// Runs per set of flags used together (two or more), most used first
+    pub combinations: Vec<(String, i64)>,
+
+    /// Runs per subcommand, most used first
+    pub subcommands: Vec<(String, i64)>,
+}
+
+impl FlagReport {
+    /// Count the flags of `records`, keeping the top `limit` of each list
+    ///
+    /// With `subcommand`, only commands running that subcommand count.
+    pub fn new(records: &[CommandRecord], subcommand: Option<&str>, limit: usize) -> Self {
+        let mut flags: HashMap<String, i64> = HashMap::new();
+        let mut combinations: HashMap<String, i64> = HashMap::new();
+        let mut subcommands: HashMap<String, i64> = HashMap::new();
+        let mut commands = 0;
+        let mut runs = 0;
+
+        for record in records {
+            let parsed = ParsedCommand::parse(&record.command);
+            if subcommand.is_some() && parsed.subcommand.as_deref() != subcommand {
+                continue;
+            }
+            let uses = i64::from(record.usage_count.max(1));
+            commands += 1;
+            runs += uses;
+
+            if let Some(name) = parsed.subcommand {
+                *subcommands.entry(name).or_default() += uses;
+            }
+
+            let mut names: Vec<&str> = parsed.flags.iter().map(|flag| flag_name(flag)).collect();
+            names.sort_unstable();
+            names.dedup();
+            for name in &names {
+                *flags.entry(name.to_string()).or_default() += uses;
+            }
+            if names.len() > 1 {
+                *combinations.entry(names.join(" ")).or_default() += uses;
+            }
+        }
+
+        Self {
+            commands,
+            runs,
+            flags: top(flags, limit),
+            combinations: top(combinations, limit),
+            subcommands: top(subcommands, limit),
+        }
+    }
+}
+
+/// A flag without its `=value`
+fn flag_name(flag: &str) -> &str {
+    flag.split_once('=').map_or(flag, |(name, _)| name)
+}
+
+/// The `limit` largest counts, ties in name order
+fn top(counts: HashMap<String, i64>, limit: usize) -> Vec<(String, i64)> {
+    let mut counts: Vec<(String, i64)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(limit);
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn record(command: &str, usage_count: i32) -> CommandRecord {
+        let mut record = CommandRecord::new(
+            command.to_string(),
+            Utc::now(),
+            0,
+            10,
+            "/tmp".to_string(),
+            "network".to_string(),
+        );
+        record.usage_count = usage_count;
+        record
+    }
+
+    #[test]
+    fn test_flag_report() {
+        let records = vec![
+            record("rsync -avz --delete src/ host:dst/", 10),
+            record("rsync --delete -avz a/ b/", 5),
+            record("rsync -avz --progress x y", 2),
+            record("rsync --exclude=.git --exclude=target . host:", 1),
+        ];
+        let report = FlagReport::new(&records, None, 10);
+
+        assert_eq!(report.commands, 4);
+        assert_eq!(report.runs, 18);
+        assert_eq!(
+            report.flags,
+            vec![
+                ("-avz".to_string(), 17),
+                ("--delete".to_string(), 15),
+                ("--progress".to_string(), 2),
+                ("--exclude".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            report.combinations,
+            vec![
+                ("--delete -avz".to_string(), 15),
+                ("--progress -avz".to_string(), 2),
+            ]
+        );
+        assert!(report.subcommands.is_empty());
+        assert_eq!(FlagReport::new(&records, None, 1).flags.len(), 1);
+    }
+
+    #[test]
+    fn test_subcommands() {
+        let records = vec![
+            record("kubectl get pods -n prod -o wide", 3),
+            record("kubectl logs api -n prod -f", 2),
+            record("kubectl get svc -A", 1),
+        ];
+
+        let report = FlagReport::new(&records, None, 10);
+        assert_eq!(
+            report.subcommands,
+            vec![("get".to_string(), 4), ("logs".to_string(), 2)]
+        );
+
+        let report = FlagReport::new(&records, Some("get"), 10);
+        assert_eq!(report.commands, 2);
+        assert_eq!(report.flags[0], ("-n".to_string(), 3));
+        assert_eq!(report.combinations, vec![("-n -o".to_string(), 3)]);
+    }
+}
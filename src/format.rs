@@ -0,0 +1,559 @@
+/// Output formats of command listings (search, recent, here, top)
+///
+/// The verbose format spreads each command over several lines with its
+/// metadata. The compact format prints one command per line (timestamp,
+/// status, command) so listings can be piped into grep, awk or cut. For
+/// anything else a [`Template`] such as `"{timestamp} {dir} {command}"`
+/// defines the line printed for each command.
+use crate::error::{OmniscientError, Result};
+use crate::models::CommandRecord;
+use crate::timezone::DisplayZone;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::{self, IsTerminal};
+use std::process::{Command, Stdio};
+
+/// Timestamp format used when a template doesn't give one
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Field names accepted in templates
+pub const TEMPLATE_FIELDS: &[&str] = &[
+    "id",
+    "command",
+    "typed",
+    "timestamp",
+    "last_used",
+    "exit_code",
+    "status",
+    "duration",
+    "duration_ms",
+    "dir",
+    "category",
+    "usage",
+    "note",
+];
+
+/// When to color output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMode {
+    /// Only when writing to a terminal, unless NO_COLOR is set
+    #[default]
+    Auto,
+
+    /// Always, even when piped
+    Always,
+
+    /// Never
+    Never,
+}
+
+/// How listings print commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingFormat {
+    /// Command on one line, metadata on the following lines
+    #[default]
+    Verbose,
+
+    /// One line per command
+    Compact,
+}
+
+impl ListingFormat {
+    /// Look up a format by name ("verbose" or "compact")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "verbose" | "full" => Some(ListingFormat::Verbose),
+            "compact" | "oneline" => Some(ListingFormat::Compact),
+            _ => None,
+        }
+    }
+}
+
+/// How wide listings may get before long commands are truncated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(try_from = "RawWidth", into = "RawWidth")]
+pub enum DisplayWidth {
+    /// The terminal's width; nothing is truncated when output is piped
+    #[default]
+    Auto,
+
+    /// Never truncate
+    Full,
+
+    /// A fixed number of columns
+    Columns(usize),
+}
+
+/// `DisplayWidth` as written in the config: "auto", "full" or a number
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawWidth {
+    Name(String),
+    Columns(usize),
+}
+
+impl TryFrom<RawWidth> for DisplayWidth {
+    type Error = String;
+
+    fn try_from(raw: RawWidth) -> std::result::Result<Self, Self::Error> {
+        match raw {
+            RawWidth::Name(name) => match name.as_str() {
+                "auto" => Ok(DisplayWidth::Auto),
+                "full" => Ok(DisplayWidth::Full),
+                // `omniscient config set display.width 100` keeps the type of "auto"
+                _ => name.parse().map(DisplayWidth::Columns).map_err(|_| {
+                    format!(
+                        "invalid width '{}' (expected \"auto\", \"full\" or a number of columns)",
+                        name
+                    )
+                }),
+            },
+            RawWidth::Columns(columns) => Ok(DisplayWidth::Columns(columns)),
+        }
+    }
+}
+
+impl From<DisplayWidth> for RawWidth {
+    fn from(width: DisplayWidth) -> Self {
+        match width {
+            DisplayWidth::Auto => RawWidth::Name("auto".to_string()),
+            DisplayWidth::Full => RawWidth::Name("full".to_string()),
+            DisplayWidth::Columns(columns) => RawWidth::Columns(columns),
+        }
+    }
+}
+
+impl DisplayWidth {
+    /// Number of columns listings may use, or `None` for no limit
+    ///
+    /// Asks the terminal for its width in auto mode, so call it only when
+    /// printing a listing.
+    pub fn columns(&self) -> Option<usize> {
+        match self {
+            DisplayWidth::Auto if io::stdout().is_terminal() => terminal_width(),
+            DisplayWidth::Auto | DisplayWidth::Full => None,
+            DisplayWidth::Columns(columns) => Some(*columns),
+        }
+    }
+}
+
+/// Commands are never cut shorter than this, however narrow the terminal
+const MIN_COMMAND_WIDTH: usize = 20;
+
+/// Number of columns of the terminal, from `$COLUMNS` or `stty size`
+pub fn terminal_width() -> Option<usize> {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .or_else(|| terminal_size().map(|(_, columns)| columns))
+        .filter(|&columns| columns > 0)
+}
+
+/// Rows and columns of the controlling terminal, as `stty size` reports them
+#[cfg(unix)]
+pub(crate) fn terminal_size() -> Option<(usize, usize)> {
+    let tty = std::fs::File::open("/dev/tty").ok()?;
+    let output = Command::new("stty")
+        .arg("size")
+        .stdin(tty)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut fields = text.split_whitespace().map(|field| field.parse().ok());
+    Some((fields.next()??, fields.next()??))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn terminal_size() -> Option<(usize, usize)> {
+    None
+}
+
+/// A command on a single line: multi-line commands (heredocs, loops) show
+/// their first line and how many lines they have, as in `cat <<EOF ↵ (3 lines)`
+///
+/// `omniscient show` prints the whole command.
+pub fn one_line(command: &str) -> String {
+    fit(command, None)
+}
+
+/// A command on a single line (see [`one_line`]) of at most `width`
+/// characters, cut with "…" where it doesn't fit
+///
+/// The line count of a multi-line command is kept; the first line is cut
+/// instead.
+pub fn fit(command: &str, width: Option<usize>) -> String {
+    let command = command.trim_end();
+    let mut lines = command.lines();
+    let first = lines.next().unwrap_or_default().trim_end();
+    let suffix = match lines.count() {
+        0 => String::new(),
+        more => format!(" ↵ ({} lines)", more + 1),
+    };
+
+    let Some(width) = width else {
+        return format!("{}{}", first, suffix);
+    };
+    let room = width
+        .saturating_sub(suffix.chars().count())
+        .max(MIN_COMMAND_WIDTH);
+    if first.chars().count() <= room {
+        return format!("{}{}", first, suffix);
+    }
+
+    let kept: String = first.chars().take(room - 1).collect();
+    format!("{}…{}", kept.trim_end(), suffix)
+}
+
+/// Compact age of a timestamp ("45s", "12m", "3h", "5d", "8w")
+pub fn format_age(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let seconds = (now - then).num_seconds().max(0);
+    match seconds {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86_400 => format!("{}h", s / 3600),
+        s if s < 7 * 86_400 => format!("{}d", s / 86_400),
+        s => format!("{}w", s / (7 * 86_400)),
+    }
+}
+
+/// A record field referenced by a template
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    Id,
+    Command,
+    Typed,
+    Timestamp(String),
+    LastUsed(String),
+    ExitCode,
+    Status,
+    Duration,
+    DurationMs,
+    Dir,
+    Category,
+    Usage,
+    Note,
+}
+
+impl Field {
+    /// Parse `name` or `name:format`; only timestamps take a format
+    /// (strftime, e.g. `{timestamp:%H:%M}`)
+    fn parse(spec: &str) -> Result<Self> {
+        let (name, format) = match spec.split_once(':') {
+            Some((name, format)) => (name.trim(), Some(format)),
+            None => (spec.trim(), None),
+        };
+
+        let field = match name {
+            "timestamp" | "time" => {
+                return Ok(Field::Timestamp(time_format(spec, format)?));
+            }
+            "last_used" => return Ok(Field::LastUsed(time_format(spec, format)?)),
+            "id" => Field::Id,
+            "command" | "cmd" => Field::Command,
+            "typed" | "typed_command" => Field::Typed,
+            "exit_code" | "exit" => Field::ExitCode,
+            "status" => Field::Status,
+            "duration" => Field::Duration,
+            "duration_ms" => Field::DurationMs,
+            "dir" | "working_dir" => Field::Dir,
+            "category" => Field::Category,
+            "usage" | "usage_count" => Field::Usage,
+            "note" => Field::Note,
+            _ => {
+                return Err(OmniscientError::other(format!(
+                    "Unknown field '{{{}}}' (fields: {})",
+                    name,
+                    TEMPLATE_FIELDS.join(", ")
+                )))
+            }
+        };
+
+        if format.is_some() {
+            return Err(OmniscientError::other(format!(
+                "Field '{{{}}}' doesn't take a format",
+                spec
+            )));
+        }
+        Ok(field)
+    }
+}
+
+/// Check a strftime format, defaulting to `DEFAULT_TIME_FORMAT`
+fn time_format(spec: &str, format: Option<&str>) -> Result<String> {
+    let format = format.unwrap_or(DEFAULT_TIME_FORMAT);
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(OmniscientError::other(format!(
+            "Invalid time format in '{{{}}}'",
+            spec
+        )));
+    }
+    Ok(format.to_string())
+}
+
+/// A piece of a template
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+/// User-defined line format over [`CommandRecord`] fields
+///
+/// Fields are written in braces (`{command}`, `{timestamp:%H:%M}`), `{{`
+/// and `}}` stand for literal braces and `\t` and `\n` for a tab and a
+/// line break.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+    zone: DisplayZone,
+}
+
+impl Template {
+    /// Parse a template, rejecting unknown fields and unbalanced braces
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '\\' => match chars.peek() {
+                    Some('t') => {
+                        chars.next();
+                        literal.push('\t');
+                    }
+                    Some('n') => {
+                        chars.next();
+                        literal.push('\n');
+                    }
+                    Some('\\') => {
+                        chars.next();
+                        literal.push('\\');
+                    }
+                    _ => literal.push('\\'),
+                },
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => {
+                                return Err(OmniscientError::other(format!(
+                                    "Unclosed '{{' in template '{}'",
+                                    template
+                                )))
+                            }
+                        }
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(Field::parse(&spec)?));
+                }
+                '}' => {
+                    return Err(OmniscientError::other(format!(
+                        "Unmatched '}}' in template '{}' (write '}}}}' for a literal brace)",
+                        template
+                    )))
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self {
+            parts,
+            zone: DisplayZone::default(),
+        })
+    }
+
+    /// Render timestamps in `zone` (local time by default)
+    pub fn in_zone(mut self, zone: DisplayZone) -> Self {
+        self.zone = zone;
+        self
+    }
+
+    /// Fill in the template for one record
+    pub fn render(&self, cmd: &CommandRecord) -> String {
+        let time = |t: &DateTime<Utc>, format: &str| self.zone.format(t, format);
+        let mut out = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Field(field) => out.push_str(&match field {
+                    Field::Id => cmd.id.unwrap_or_default().to_string(),
+                    Field::Command => cmd.command.clone(),
+                    Field::Typed => cmd
+                        .typed_command
+                        .clone()
+                        .unwrap_or_else(|| cmd.command.clone()),
+                    Field::Timestamp(format) => time(&cmd.timestamp, format),
+                    Field::LastUsed(format) => time(&cmd.last_used, format),
+                    Field::ExitCode => cmd.exit_code.to_string(),
+                    Field::Status => cmd.status_symbol().to_string(),
+                    Field::Duration => cmd.duration_display(),
+                    Field::DurationMs => cmd.duration_ms.to_string(),
+                    Field::Dir => cmd.working_dir.clone(),
+                    Field::Category => cmd.category.clone(),
+                    Field::Usage => cmd.usage_count.to_string(),
+                    Field::Note => cmd.note.clone().unwrap_or_default(),
+                }),
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            ListingFormat::from_name("compact"),
+            Some(ListingFormat::Compact)
+        );
+        assert_eq!(
+            ListingFormat::from_name("oneline"),
+            Some(ListingFormat::Compact)
+        );
+        assert_eq!(
+            ListingFormat::from_name("verbose"),
+            Some(ListingFormat::Verbose)
+        );
+        assert_eq!(ListingFormat::from_name("json"), None);
+    }
+
+    fn record() -> CommandRecord {
+        let timestamp = DateTime::parse_from_rfc3339("2025-03-04T05:06:07Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut cmd = CommandRecord::new(
+            "git status".to_string(),
+            timestamp,
+            1,
+            1500,
+            "/srv/api".to_string(),
+            "git".to_string(),
+        );
+        cmd.id = Some(42);
+        cmd.usage_count = 3;
+        cmd
+    }
+
+    #[test]
+    fn test_render_template() {
+        let cmd = record();
+        let render = |template: &str| {
+            Template::parse(template)
+                .unwrap()
+                .in_zone(DisplayZone::from_name("UTC").unwrap())
+                .render(&cmd)
+        };
+
+        assert_eq!(
+            render("{timestamp} {dir} {command}"),
+            "2025-03-04 05:06:07 /srv/api git status"
+        );
+        assert_eq!(
+            render("{id}\\t{timestamp:%H:%M}\\t{exit_code} {status}"),
+            "42\t05:06\t1 ✗"
+        );
+        assert_eq!(
+            render("{{{category}}} {usage}x {duration} ({duration_ms}ms){note}"),
+            "{git} 3x 1.5s (1500ms)"
+        );
+        assert_eq!(render("plain"), "plain");
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let err = Template::parse("{command} {host}").unwrap_err();
+        assert!(err.to_string().contains("Unknown field '{host}'"));
+        assert!(Template::parse("{command").is_err());
+        assert!(Template::parse("command}").is_err());
+        assert!(Template::parse("{dir:%Y}").is_err());
+        assert!(Template::parse("{timestamp:%Q}").is_err());
+    }
+
+    #[test]
+    fn test_fit() {
+        let command = "docker run --rm -it -v $PWD:/work -w /work rust:1.80 cargo build";
+        assert_eq!(fit(command, None), command);
+        assert_eq!(fit(command, Some(80)), command);
+        assert_eq!(fit(command, Some(30)), "docker run --rm -it -v $PWD:/…");
+        assert_eq!(fit(command, Some(30)).chars().count(), 30);
+
+        // Never shorter than the minimum, and the line count survives
+        assert_eq!(fit(command, Some(5)).chars().count(), MIN_COMMAND_WIDTH);
+        assert_eq!(
+            fit("for f in /var/log/*.log; do\n  gzip \"$f\"\ndone", Some(32)),
+            "for f in /var/log/*… ↵ (3 lines)"
+        );
+    }
+
+    #[test]
+    fn test_display_width_config() {
+        #[derive(Deserialize, Serialize)]
+        struct Display {
+            width: DisplayWidth,
+        }
+        let parse = |text: &str| toml::from_str::<Display>(text).map(|d| d.width);
+
+        assert_eq!(parse("width = \"auto\"").unwrap(), DisplayWidth::Auto);
+        assert_eq!(parse("width = \"full\"").unwrap(), DisplayWidth::Full);
+        assert_eq!(parse("width = 100").unwrap(), DisplayWidth::Columns(100));
+        assert!(parse("width = \"wide\"").is_err());
+
+        let written = toml::to_string(&Display {
+            width: DisplayWidth::Columns(120),
+        })
+        .unwrap();
+        assert_eq!(written.trim(), "width = 120");
+        assert_eq!(DisplayWidth::Full.columns(), None);
+        assert_eq!(DisplayWidth::Columns(90).columns(), Some(90));
+    }
+
+    #[test]
+    fn test_one_line() {
+        assert_eq!(one_line("git status"), "git status");
+        assert_eq!(
+            one_line("for f in *; do\n  echo $f\ndone\n"),
+            "for f in *; do ↵ (3 lines)"
+        );
+        assert_eq!(
+            one_line("cat <<'EOF' > notes.txt\r\nhello\r\nEOF"),
+            "cat <<'EOF' > notes.txt ↵ (3 lines)"
+        );
+    }
+
+    #[test]
+    fn test_format_age() {
+        let now = Utc::now();
+        assert_eq!(format_age(now - Duration::seconds(45), now), "45s");
+        assert_eq!(format_age(now - Duration::minutes(12), now), "12m");
+        assert_eq!(format_age(now - Duration::days(5), now), "5d");
+        assert_eq!(format_age(now - Duration::days(60), now), "8w");
+        assert_eq!(format_age(now + Duration::minutes(1), now), "0s");
+    }
+}
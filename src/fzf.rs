@@ -0,0 +1,95 @@
+/// fzf integration - history as tab-separated lines and back
+///
+/// `omniscient fzf` writes one line per command: the command, its working
+/// directory, its age and its ID, separated by tabs. The ID is only there
+/// so `omniscient fzf --select` can print the exact stored command (including
+/// any tabs or newlines flattened for display); fzf hides it with
+/// `--with-nth=1..3`.
+use crate::format::format_age;
+use crate::models::CommandRecord;
+use chrono::{DateTime, Utc};
+
+/// Format a record as a line for fzf
+pub fn format_line(record: &CommandRecord, now: DateTime<Utc>) -> String {
+    format!(
+        "{}\t{}\t{}\t{}",
+        flatten(&record.command),
+        flatten(&record.working_dir),
+        format_age(record.last_used, now),
+        record.id.unwrap_or_default()
+    )
+}
+
+/// A line picked in fzf
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// Command text as displayed
+    pub command: String,
+
+    /// Stored command ID, if the line still has one
+    pub id: Option<i64>,
+}
+
+/// Parse a line produced by [`format_line`], returning `None` for blank input
+pub fn parse_selection(line: &str) -> Option<Selection> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.trim().is_empty() {
+        return None;
+    }
+
+    let fields: Vec<&str> = line.split('\t').collect();
+    let id = match fields.as_slice() {
+        [_, _, _, id, ..] => id.trim().parse().ok(),
+        _ => None,
+    };
+
+    Some(Selection {
+        command: fields[0].to_string(),
+        id,
+    })
+}
+
+/// Keep a field on one line and free of the separator
+fn flatten(text: &str) -> String {
+    text.replace(['\t', '\r'], " ").replace('\n', " ⏎ ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_format_line_roundtrip() {
+        let now = Utc::now();
+        let mut record = CommandRecord::new(
+            "printf 'a\tb'\necho done".to_string(),
+            now,
+            0,
+            10,
+            "/home/me/project".to_string(),
+            "other".to_string(),
+        );
+        record.id = Some(42);
+        record.last_used = now - Duration::hours(3);
+
+        let line = format_line(&record, now);
+        assert_eq!(line, "printf 'a b' ⏎ echo done\t/home/me/project\t3h\t42");
+
+        let selection = parse_selection(&format!("{}\n", line)).unwrap();
+        assert_eq!(selection.id, Some(42));
+        assert_eq!(selection.command, "printf 'a b' ⏎ echo done");
+    }
+
+    #[test]
+    fn test_parse_selection_without_id() {
+        assert_eq!(
+            parse_selection("git status"),
+            Some(Selection {
+                command: "git status".to_string(),
+                id: None
+            })
+        );
+        assert_eq!(parse_selection("  \n"), None);
+    }
+}
@@ -0,0 +1,108 @@
+/// Execution history of a command (`omniscient history`)
+///
+/// Lists every recorded run of one command, or of all commands matching a
+/// glob pattern, with a summary of how often it failed and how its duration
+/// developed over time.
+use crate::models::Execution;
+use chrono::{DateTime, Utc};
+
+/// Blocks of a sparkline, lowest first
+const SPARK_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Aggregate of a list of executions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistorySummary {
+    /// Number of executions
+    pub runs: usize,
+
+    /// Executions with a non-zero exit code
+    pub failures: usize,
+
+    /// First and last execution
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+
+    /// Shortest, average and longest duration in milliseconds
+    pub min_ms: i64,
+    pub avg_ms: i64,
+    pub max_ms: i64,
+}
+
+impl HistorySummary {
+    /// Summarize executions in chronological order; `None` when empty
+    pub fn new(executions: &[Execution]) -> Option<Self> {
+        let first = executions.first()?;
+        let last = executions.last()?;
+        let durations = executions.iter().map(|e| e.duration_ms);
+
+        Some(Self {
+            runs: executions.len(),
+            failures: executions.iter().filter(|e| e.exit_code != 0).count(),
+            first: first.timestamp,
+            last: last.timestamp,
+            min_ms: durations.clone().min().unwrap_or_default(),
+            avg_ms: durations.clone().sum::<i64>() / executions.len() as i64,
+            max_ms: durations.max().unwrap_or_default(),
+        })
+    }
+}
+
+/// One block per value, scaled between the smallest and the largest value
+pub fn sparkline(values: &[i64]) -> String {
+    let (Some(&min), Some(&max)) = (values.iter().min(), values.iter().max()) else {
+        return String::new();
+    };
+    let range = (max - min).max(1) as f64;
+    let top = (SPARK_BLOCKS.len() - 1) as f64;
+
+    values
+        .iter()
+        .map(|&value| SPARK_BLOCKS[((value - min) as f64 / range * top).round() as usize])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ExecutionMeta;
+    use chrono::Duration;
+
+    fn execution(age_days: i64, exit_code: i32, duration_ms: i64) -> Execution {
+        Execution {
+            command_id: 1,
+            command: "cargo build".to_string(),
+            working_dir: "/srv/api".to_string(),
+            timestamp: Utc::now() - Duration::days(age_days),
+            exit_code,
+            duration_ms,
+            meta: ExecutionMeta::default(),
+        }
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(sparkline(&[5, 5, 5]), "▁▁▁");
+        assert_eq!(sparkline(&[0, 70, 35, 10]), "▁█▅▂");
+    }
+
+    #[test]
+    fn test_summary() {
+        assert!(HistorySummary::new(&[]).is_none());
+
+        let executions = vec![
+            execution(3, 0, 1_000),
+            execution(2, 101, 4_000),
+            execution(1, 0, 1_000),
+        ];
+        let summary = HistorySummary::new(&executions).unwrap();
+        assert_eq!(summary.runs, 3);
+        assert_eq!(summary.failures, 1);
+        assert_eq!(summary.first, executions[0].timestamp);
+        assert_eq!(summary.last, executions[2].timestamp);
+        assert_eq!(
+            (summary.min_ms, summary.avg_ms, summary.max_ms),
+            (1_000, 2_000, 4_000)
+        );
+    }
+}
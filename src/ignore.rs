@@ -0,0 +1,96 @@
+/// Directory opt-out - commands run under ignored directories are never recorded
+///
+/// A directory is ignored when it (or any parent) contains a
+/// `.omniscientignore` marker file, or when it lies under one of the
+/// `privacy.ignore_dirs` entries in the config.
+use crate::config::Config;
+use crate::error::Result;
+use std::path::{Path, PathBuf};
+
+/// Marker file that opts a directory tree out of capture
+pub const IGNORE_FILE: &str = ".omniscientignore";
+
+/// Decides whether a working directory is excluded from capture
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    /// Blocklisted directories with `~` expanded
+    dirs: Vec<PathBuf>,
+}
+
+impl IgnoreRules {
+    /// Create rules from a list of blocklisted directories
+    ///
+    /// A leading `~` is expanded to `home`.
+    pub fn new(dirs: &[String], home: &Path) -> Self {
+        let dirs = dirs
+            .iter()
+            .map(|dir| {
+                if dir == "~" {
+                    home.to_path_buf()
+                } else if let Some(rest) = dir.strip_prefix("~/") {
+                    home.join(rest)
+                } else {
+                    PathBuf::from(dir)
+                }
+            })
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect();
+
+        Self { dirs }
+    }
+
+    /// Create rules from the `privacy.ignore_dirs` config entry
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let home = Config::home_dir()?;
+        Ok(Self::new(&config.privacy.ignore_dirs, &home))
+    }
+
+    /// Whether commands run in `dir` should not be recorded
+    ///
+    /// Blocklisted directories match whole path components, so ignoring
+    /// `~/clients/acme` does not ignore `~/clients/acme-public`.
+    pub fn is_ignored(&self, dir: &Path) -> bool {
+        if self.dirs.iter().any(|ignored| dir.starts_with(ignored)) {
+            return true;
+        }
+
+        dir.ancestors()
+            .any(|ancestor| ancestor.join(IGNORE_FILE).exists())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_marker_file_ignores_subtree() {
+        let root = TempDir::new().unwrap();
+        let secret = root.path().join("clients/secret-project");
+        let nested = secret.join("src/deep");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(secret.join(IGNORE_FILE), "").unwrap();
+
+        let rules = IgnoreRules::default();
+        assert!(rules.is_ignored(&secret));
+        assert!(rules.is_ignored(&nested));
+        assert!(!rules.is_ignored(&root.path().join("clients")));
+    }
+
+    #[test]
+    fn test_config_blocklist() {
+        let home = Path::new("/home/me");
+        let rules = IgnoreRules::new(
+            &["~/clients/acme".to_string(), "/srv/private".to_string()],
+            home,
+        );
+
+        assert!(rules.is_ignored(Path::new("/home/me/clients/acme")));
+        assert!(rules.is_ignored(Path::new("/home/me/clients/acme/api")));
+        assert!(rules.is_ignored(Path::new("/srv/private/db")));
+        assert!(!rules.is_ignored(Path::new("/home/me/clients/acme-public")));
+        assert!(!rules.is_ignored(Path::new("/home/me")));
+    }
+}
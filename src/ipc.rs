@@ -0,0 +1,351 @@
+/// Unix socket JSON-RPC interface for editor plugins
+///
+/// `omniscient serve --socket` listens on a Unix socket and answers
+/// newline-delimited JSON-RPC 2.0 requests. The methods are the HTTP API
+/// endpoints (`search`, `recent`, `top`, `suggest`, `stats`, `categories`)
+/// and take the same parameters as a JSON object:
+///
+/// ```text
+/// → {"jsonrpc":"2.0","id":1,"method":"suggest","params":{"prefix":"git ch","limit":5}}
+/// ← {"jsonrpc":"2.0","id":1,"result":[{"command":"git checkout main", ...}]}
+/// ```
+///
+/// Each connection is served on its own thread with its own database
+/// connection, so a plugin can keep one connection open per editor session
+/// instead of spawning the CLI per keystroke. [`IpcClient`] is a minimal
+/// client for Rust callers.
+use crate::api;
+use crate::config::Config;
+use crate::error::{OmniscientError, Result, ResultExt};
+use crate::models::CommandRecord;
+use crate::storage::Storage;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Socket belonging to the configured database (`<db>.sock`)
+pub fn default_socket_path(config: &Config) -> Result<PathBuf> {
+    let mut path = config.database_path()?.into_os_string();
+    path.push(".sock");
+    Ok(PathBuf::from(path))
+}
+
+/// Listening socket that answers queries against one database
+pub struct IpcServer {
+    listener: UnixListener,
+    db_path: PathBuf,
+}
+
+impl IpcServer {
+    /// Listen on `socket_path`, replacing a stale socket left by a server
+    /// that is no longer running
+    ///
+    /// Anything at `socket_path` other than a socket (a file, a symlink) is
+    /// left alone and is an error. The socket is only accessible to its
+    /// owner (mode 0600), since it answers queries over the whole history.
+    pub fn bind(socket_path: &Path, db_path: &Path) -> Result<Self> {
+        if let Ok(metadata) = fs::symlink_metadata(socket_path) {
+            if !metadata.file_type().is_socket() {
+                return Err(OmniscientError::other(format!(
+                    "{} exists and is not a socket",
+                    socket_path.display()
+                )));
+            }
+            if UnixStream::connect(socket_path).is_ok() {
+                return Err(OmniscientError::other(format!(
+                    "A server is already listening on {}",
+                    socket_path.display()
+                )));
+            }
+            fs::remove_file(socket_path)
+                .with_context(|| format!("remove stale socket {}", socket_path.display()))?;
+        }
+
+        let listener = UnixListener::bind(socket_path)
+            .with_context(|| format!("listen on {}", socket_path.display()))?;
+        fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("restrict access to {}", socket_path.display()))?;
+
+        Ok(Self {
+            listener,
+            db_path: db_path.to_path_buf(),
+        })
+    }
+
+    /// Accept connections until the process is stopped
+    ///
+    /// A failed accept (e.g. too many open files) is logged and retried
+    /// after a short pause.
+    pub fn run(self) -> Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("omniscient: accept on socket failed: {}", e);
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+            };
+            let db_path = self.db_path.clone();
+            thread::spawn(move || {
+                // A failing connection only affects its own client
+                let _ = serve_connection(stream, &db_path);
+            });
+        }
+
+        Ok(())
+    }
+}
+
+fn serve_connection(stream: UnixStream, db_path: &Path) -> Result<()> {
+    let storage = Storage::new(db_path)?;
+    let reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(writer, "{}", respond(&storage, &line))?;
+    }
+
+    Ok(())
+}
+
+/// Answer one JSON-RPC request line
+pub fn respond(storage: &Storage, line: &str) -> Value {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return error_response(Value::Null, PARSE_ERROR, e.to_string()),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return error_response(id, INVALID_REQUEST, "Missing method".to_string());
+    };
+    let params = match request.get("params") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Object(object)) => object
+            .iter()
+            .flat_map(|(key, value)| {
+                let values = match value {
+                    Value::Array(items) => items.clone(),
+                    value => vec![value.clone()],
+                };
+                values.into_iter().filter_map(move |value| match value {
+                    Value::Null => None,
+                    Value::String(text) => Some((key.clone(), text)),
+                    value => Some((key.clone(), value.to_string())),
+                })
+            })
+            .collect(),
+        Some(_) => {
+            return error_response(id, INVALID_PARAMS, "Params must be an object".to_string())
+        }
+    };
+
+    match api::handle(storage, &format!("/{}", method), &params) {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => {
+            let code = match error.status {
+                404 => METHOD_NOT_FOUND,
+                400 => INVALID_PARAMS,
+                _ => INTERNAL_ERROR,
+            };
+            let message = if code == METHOD_NOT_FOUND {
+                format!("Unknown method '{}'", method)
+            } else {
+                error.message
+            };
+            error_response(id, code, message)
+        }
+    }
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// Client for a running `omniscient serve --socket`
+pub struct IpcClient {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+}
+
+impl IpcClient {
+    /// Connect to the server listening on `socket_path`
+    pub fn connect<P: AsRef<Path>>(socket_path: P) -> Result<Self> {
+        let socket_path = socket_path.as_ref();
+        let writer = UnixStream::connect(socket_path)
+            .with_context(|| format!("connect to {}", socket_path.display()))?;
+        let reader = BufReader::new(writer.try_clone()?);
+
+        Ok(Self {
+            reader,
+            writer,
+            next_id: 1,
+        })
+    }
+
+    /// Call a method and return its result
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        writeln!(self.writer, "{}", request)?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(OmniscientError::other("IPC server closed the connection"));
+        }
+        let mut response: Value = serde_json::from_str(&line)?;
+
+        if let Some(error) = response.get("error") {
+            return Err(OmniscientError::other(format!(
+                "IPC error {}: {}",
+                error["code"],
+                error["message"].as_str().unwrap_or("unknown error")
+            )));
+        }
+        Ok(response["result"].take())
+    }
+
+    /// Search history (see [`api`] for the supported filters)
+    pub fn search(&mut self, text: &str, limit: usize) -> Result<Vec<CommandRecord>> {
+        let result = self.call("search", json!({ "q": text, "limit": limit }))?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    /// Commands starting with `prefix`, most relevant first
+    pub fn suggest(&mut self, prefix: &str, limit: usize) -> Result<Vec<CommandRecord>> {
+        let result = self.call("suggest", json!({ "prefix": prefix, "limit": limit }))?;
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_test_db(dir: &TempDir) -> PathBuf {
+        let db_path = dir.path().join("history.db");
+        let storage = Storage::new(&db_path).unwrap();
+        for command in ["git checkout main", "git status", "ls -la"] {
+            storage
+                .insert(&CommandRecord::new(
+                    command.to_string(),
+                    chrono::Utc::now(),
+                    0,
+                    10,
+                    "/tmp".to_string(),
+                    "other".to_string(),
+                ))
+                .unwrap();
+        }
+        db_path
+    }
+
+    #[test]
+    fn test_respond() {
+        let dir = TempDir::new().unwrap();
+        let storage = Storage::new(create_test_db(&dir)).unwrap();
+
+        let response = respond(
+            &storage,
+            r#"{"jsonrpc":"2.0","id":7,"method":"search","params":{"q":"git","limit":1}}"#,
+        );
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["result"].as_array().unwrap().len(), 1);
+
+        let response = respond(&storage, r#"{"jsonrpc":"2.0","id":8,"method":"nope"}"#);
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+        let response = respond(
+            &storage,
+            r#"{"jsonrpc":"2.0","id":9,"method":"search","params":{"limit":"x"}}"#,
+        );
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+        let response = respond(&storage, "{not json");
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+        assert!(response["id"].is_null());
+    }
+
+    #[test]
+    fn test_client_server() {
+        let dir = TempDir::new().unwrap();
+        let db_path = create_test_db(&dir);
+        let socket_path = dir.path().join("history.db.sock");
+
+        let server = IpcServer::bind(&socket_path, &db_path).unwrap();
+        thread::spawn(move || server.run());
+
+        let mut client = IpcClient::connect(&socket_path).unwrap();
+        let suggestions = client.suggest("git ch", 5).unwrap();
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].command, "git checkout main");
+
+        // The connection stays usable for further requests
+        assert_eq!(client.search("git", 10).unwrap().len(), 2);
+        assert!(client.call("nope", Value::Null).is_err());
+        assert_eq!(
+            client.call("stats", Value::Null).unwrap()["total_commands"],
+            3
+        );
+
+        // A second server can't take over a live socket
+        assert!(IpcServer::bind(&socket_path, &db_path).is_err());
+    }
+
+    #[test]
+    fn test_socket_is_private() {
+        let dir = TempDir::new().unwrap();
+        let db_path = create_test_db(&dir);
+        let socket_path = dir.path().join("history.db.sock");
+
+        let _server = IpcServer::bind(&socket_path, &db_path).unwrap();
+        let mode = fs::metadata(&socket_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_bind_leaves_other_files_alone() {
+        let dir = TempDir::new().unwrap();
+        let db_path = create_test_db(&dir);
+
+        let file = dir.path().join("notes.txt");
+        fs::write(&file, "keep me").unwrap();
+        assert!(IpcServer::bind(&file, &db_path).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "keep me");
+
+        let link = dir.path().join("link.sock");
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+        assert!(IpcServer::bind(&link, &db_path).is_err());
+        assert!(fs::symlink_metadata(&link).is_ok());
+
+        // A socket nobody listens on anymore is replaced
+        let stale = dir.path().join("stale.sock");
+        drop(UnixListener::bind(&stale).unwrap());
+        assert!(IpcServer::bind(&stale, &db_path).is_ok());
+    }
+}
@@ -0,0 +1,243 @@
+/// Capture journal - keeps captures the database could not take
+///
+/// When the database is locked for too long, unreadable or corrupt, the
+/// capture hook appends the request to an append-only NDJSON file next to
+/// it (`<db>.journal`) instead of losing the command. The next capture that
+/// reaches the database replays the journal into it
+/// ([`CommandCapture::replay_journal`](crate::CommandCapture::replay_journal)).
+///
+/// Replaying first renames the journal to `<db>.journal.replaying`, so
+/// captures spilled meanwhile start a new journal, and only deletes that
+/// file once everything in it is committed. A replay interrupted by a crash
+/// is picked up again next time.
+use crate::capture::CaptureRequest;
+use crate::config::Config;
+use crate::error::{Result, ResultExt};
+use crate::redact::RedactionEngine;
+use chrono::Utc;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Outcome of replaying the journal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// Captures stored in the database
+    pub stored: usize,
+
+    /// Captures skipped, e.g. because capture was paused
+    pub skipped: usize,
+
+    /// Captures stored before (same capture ID), e.g. by an earlier replay
+    pub duplicates: usize,
+
+    /// Lines that could not be read or were rejected as invalid
+    pub invalid: usize,
+}
+
+/// Append-only file of captures waiting for the database
+pub struct CaptureJournal {
+    path: PathBuf,
+    redactor: RedactionEngine,
+}
+
+impl CaptureJournal {
+    /// Create a journal at `path`, never writing commands `redactor` flags
+    pub fn new(path: PathBuf, redactor: RedactionEngine) -> Self {
+        Self { path, redactor }
+    }
+
+    /// The journal belonging to the configured database (`<db>.journal`)
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut path = config.database_path()?.into_os_string();
+        path.push(".journal");
+        let redactor = RedactionEngine::new(
+            config.privacy.redact_patterns.clone(),
+            config.privacy.enabled,
+        )?;
+        Ok(Self::new(PathBuf::from(path), redactor))
+    }
+
+    /// Path of the journal file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path the journal is moved to while it is replayed
+    fn replaying_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".replaying");
+        PathBuf::from(path)
+    }
+
+    /// Append a capture, returning false if it was left out for privacy
+    ///
+    /// The start time and working directory are filled in now, since the
+    /// replay happens later and elsewhere. Commands the redaction patterns
+    /// match are never written to disk, and output is dropped.
+    pub fn append(&self, request: &CaptureRequest) -> Result<bool> {
+        let sensitive = [Some(&request.command), request.expanded.as_ref()]
+            .into_iter()
+            .flatten()
+            .any(|command| self.redactor.should_redact(command));
+        if sensitive {
+            return Ok(false);
+        }
+
+        let mut request = request.clone();
+        request.started_at = request.started_at.or_else(|| Some(Utc::now()));
+        if request.working_dir.is_none() {
+            request.working_dir = std::env::current_dir()
+                .ok()
+                .map(|dir| dir.to_string_lossy().to_string());
+        }
+        request.output = None;
+        request.output_truncated = false;
+        for value in request.meta.env.values_mut() {
+            if self.redactor.should_redact(value) {
+                *value = "[REDACTED]".to_string();
+            }
+        }
+
+        // One write per line, synced, so a crash loses at most this line
+        let mut line = serde_json::to_string(&request)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("open capture journal {}", self.path.display()))?;
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+
+        Ok(true)
+    }
+
+    /// Number of captures waiting to be replayed
+    pub fn pending(&self) -> Result<usize> {
+        let mut count = 0;
+        for path in [self.replaying_path(), self.path.clone()] {
+            match fs::File::open(&path) {
+                Ok(file) => count += BufReader::new(file).lines().count(),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(count)
+    }
+
+    /// Move the journal aside and read it, returning the captures and the
+    /// number of lines that could not be parsed
+    ///
+    /// A journal left from an interrupted replay is read instead; the
+    /// current one waits for the next replay. Call [`Self::finish_replay`]
+    /// once the captures are stored.
+    pub fn begin_replay(&self) -> Result<(Vec<CaptureRequest>, usize)> {
+        let replaying = self.replaying_path();
+        if !replaying.exists() {
+            match fs::rename(&self.path, &replaying) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let file = fs::File::open(&replaying)?;
+        read_requests(BufReader::new(file))
+    }
+
+    /// Delete the replayed journal
+    pub fn finish_replay(&self) -> Result<()> {
+        match fs::remove_file(self.replaying_path()) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Read journal lines, returning the captures and the number of lines that
+/// could not be parsed
+pub fn read_requests<R: BufRead>(reader: R) -> Result<(Vec<CaptureRequest>, usize)> {
+    let mut requests = Vec::new();
+    let mut invalid = 0;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(request) => requests.push(request),
+            Err(_) => invalid += 1,
+        }
+    }
+
+    Ok((requests, invalid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn journal(dir: &TempDir) -> CaptureJournal {
+        let redactor = RedactionEngine::new(vec!["password".to_string()], true).unwrap();
+        CaptureJournal::new(dir.path().join("history.db.journal"), redactor)
+    }
+
+    #[test]
+    fn test_append_and_replay() {
+        let dir = TempDir::new().unwrap();
+        let journal = journal(&dir);
+        assert_eq!(journal.pending().unwrap(), 0);
+        assert_eq!(journal.begin_replay().unwrap(), (Vec::new(), 0));
+
+        let mut request = CaptureRequest::new("cargo build", 0, 1200);
+        request.output = Some("Compiling".to_string());
+        assert!(journal.append(&request).unwrap());
+        assert!(!journal
+            .append(&CaptureRequest::new("mysql --password=hunter2", 0, 5))
+            .unwrap());
+        assert_eq!(journal.pending().unwrap(), 1);
+
+        let (requests, invalid) = journal.begin_replay().unwrap();
+        assert_eq!(invalid, 0);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].command, "cargo build");
+        assert!(requests[0].started_at.is_some());
+        assert!(requests[0].working_dir.is_some());
+        assert_eq!(requests[0].output, None);
+
+        // Captures spilled during the replay wait for the next one
+        journal
+            .append(&CaptureRequest::new("cargo test", 1, 3000))
+            .unwrap();
+        assert_eq!(journal.pending().unwrap(), 2);
+        journal.finish_replay().unwrap();
+        assert_eq!(journal.pending().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_interrupted_replay_is_resumed() {
+        let dir = TempDir::new().unwrap();
+        let journal = journal(&dir);
+        journal.append(&CaptureRequest::new("ls", 0, 1)).unwrap();
+        journal.begin_replay().unwrap();
+
+        // A crash before finish_replay leaves the file for the next replay
+        journal.append(&CaptureRequest::new("pwd", 0, 1)).unwrap();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(journal.replaying_path())
+            .unwrap();
+        file.write_all(b"{\"command\": \"trunc").unwrap();
+
+        let (requests, invalid) = journal.begin_replay().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].command, "ls");
+        assert_eq!(invalid, 1);
+        journal.finish_replay().unwrap();
+
+        let (requests, _) = journal.begin_replay().unwrap();
+        assert_eq!(requests[0].command, "pwd");
+    }
+}
@@ -1,24 +1,151 @@
-/// Omniscient - CLI command history tracker
-///
-/// This library provides the core functionality for tracking, storing,
-/// and searching command-line history across sessions.
+//! Omniscient - CLI command history tracker
+//!
+//! This library provides the core functionality for tracking, storing,
+//! and searching command-line history across sessions.
+//!
+//! # Embedding
+//!
+//! Prompt frameworks, TUIs and other tools can capture and search history
+//! through the library without the command line interface. Turn off the
+//! default `cli` feature to leave out clap, shell completions, the clipboard,
+//! the HTTP server and the terminal-only modules (colors and themes, the
+//! pager, fzf output, `train` and `bench`):
+//!
+//! ```toml
+//! [dependencies]
+//! omniscient = { version = "1.2", default-features = false }
+//! ```
+//!
+//! The types re-exported at the crate root are the stable API and follow
+//! semantic versioning: [`Config`], [`Storage`], [`CommandCapture`] and
+//! [`CaptureRequest`] for capturing, [`SearchQuery`] and [`CommandRecord`]
+//! for searching, and the statistics types. Modules that only serve the
+//! binary may change between minor versions.
+//!
+//! ```no_run
+//! use omniscient::{CommandCapture, Config, SearchQuery, Storage};
+//!
+//! # fn main() -> omniscient::Result<()> {
+//! let config = Config::load()?;
+//!
+//! // Store a command the way the shell hook does
+//! let capture = CommandCapture::new(config.clone())?;
+//! capture.capture("cargo build --release", 0, 5_230)?;
+//!
+//! // Search it
+//! let storage = Storage::from_config(&config)?;
+//! let query = SearchQuery {
+//!     text: Some("cargo".to_string()),
+//!     limit: 5,
+//!     ..SearchQuery::default()
+//! };
+//! for record in storage.search(&query)? {
+//!     println!("{} ({} uses)", record.command, record.usage_count);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+pub mod alerts;
+pub mod aliases;
+pub mod api;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+pub mod backup;
+#[cfg(feature = "cli")]
+pub mod bench;
+pub mod cache;
 pub mod capture;
 pub mod category;
+#[cfg(feature = "cli")]
+pub mod clipboard;
+#[cfg(feature = "cli")]
+pub mod completions;
 pub mod config;
+pub mod dedupe;
+pub mod doctor;
 pub mod error;
 pub mod export;
+pub mod flags;
+pub mod format;
+#[cfg(feature = "cli")]
+pub mod fzf;
+pub mod git;
+pub mod history;
+pub mod ignore;
+#[cfg(unix)]
+pub mod ipc;
+pub mod journal;
+pub mod lint;
+pub mod lock;
+pub mod migrations;
 pub mod models;
+#[cfg(feature = "cli")]
+pub mod pager;
+pub mod parse;
+pub mod paths;
+pub mod pause;
+pub mod query;
 pub mod redact;
+pub mod remote;
+pub mod report;
+pub mod rerun;
+pub mod rotation;
+pub mod service;
 pub mod shell;
+pub mod similar;
 pub mod storage;
+#[cfg(feature = "cli")]
+pub mod theme;
+pub mod timezone;
+pub mod tmux;
+#[cfg(feature = "cli")]
+pub mod train;
+pub mod typos;
+pub mod workflow;
+pub mod wrapped;
 
 // Re-export commonly used types
-pub use capture::CommandCapture;
+pub use alerts::Watchlist;
+pub use aliases::{AliasSuggestion, AliasSyntax};
+#[cfg(feature = "tokio")]
+pub use asynchronous::{AsyncCapture, AsyncStorage};
+pub use backup::{BackupFrequency, BackupSchedule};
+pub use capture::{CaptureOutcome, CaptureRequest, CommandCapture};
 pub use category::Categorizer;
 pub use config::Config;
-pub use error::{OmniscientError, Result};
-pub use export::{Exporter, ImportStrategy, Importer};
-pub use models::{CommandRecord, OrderBy, SearchQuery, Stats};
-pub use redact::RedactionEngine;
+pub use dedupe::{Deduper, MergeGroup};
+pub use error::{OmniscientError, Result, ResultExt};
+pub use export::{ExportFilter, ExportFormat, ExportSchedule, Exporter, ImportStrategy, Importer};
+pub use format::{ColorMode, DisplayWidth, ListingFormat};
+pub use git::GitInfo;
+pub use ignore::IgnoreRules;
+pub use journal::{CaptureJournal, ReplayReport};
+pub use lint::{ConflictingTool, HookConflict};
+pub use models::{
+    exit_code_meaning, humanize_duration, longest_streak, parse_duration, parse_time,
+    parse_time_end, ActivityBucket, ActivityPeriod, ActivityReport, Alert, BranchStats,
+    CategoryBreakdown, CommandCount, CommandDetail, CommandOutput, CommandRecord, DirStats,
+    DuplicateKey, DurationStats, Execution, ExecutionMeta, ExitCodeCount, FailedRun, FailureStats,
+    OrderBy, SearchQuery, SkipCount, SkipReason, SlowestBy, Stats, Summary, ToolUsage,
+    VocabularyBucket, Wrapped,
+};
+#[cfg(feature = "cli")]
+pub use pager::Pager;
+pub use parse::ParsedCommand;
+pub use paths::PathRewriter;
+pub use pause::{Pause, PauseState};
+pub use query::QueryResult;
+pub use redact::{AuditFinding, RedactMode, RedactionEngine, RedactionMatch, RedactionReport};
+pub use remote::RemoteHost;
+pub use report::{ReportFormat, ReportPeriod};
+pub use rerun::{compare_context, ContextDifference};
 pub use shell::{ShellHook, ShellType};
-pub use storage::Storage;
+pub use similar::SimilarCommand;
+pub use storage::{HealthReport, MaintenanceReport, RecordChange, Storage};
+#[cfg(feature = "cli")]
+pub use theme::Theme;
+pub use timezone::DisplayZone;
+#[cfg(feature = "cli")]
+pub use train::{Trainer, TrainingCandidate, TrainingSummary};
+pub use typos::{Typo, TypoDetector};
+pub use workflow::{Workflow, WorkflowDetector};
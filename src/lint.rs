@@ -0,0 +1,336 @@
+/// Shell rc file linting - detects history tools whose hooks conflict with Omniscient
+use crate::error::Result;
+use crate::shell::{ShellType, BLOCK_END, BLOCK_START};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Function name registered by every hook `omniscient init` emits
+const OMNISCIENT_HOOK_MARKER: &str = "_omniscient_precmd";
+
+/// Another tool (or a duplicate Omniscient hook) that conflicts with ours
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictingTool {
+    Atuin,
+    Mcfly,
+    ZshHistdb,
+    /// The Omniscient hook is installed more than once
+    DuplicateOmniscient,
+}
+
+impl ConflictingTool {
+    /// Explain how the tool interferes with Omniscient
+    pub fn description(&self) -> &'static str {
+        match self {
+            ConflictingTool::Atuin => {
+                "atuin binds Ctrl-R/Up and registers its own precmd hook, which can clobber the exit status we record"
+            }
+            ConflictingTool::Mcfly => {
+                "mcfly binds Ctrl-R and rewrites PROMPT_COMMAND, which can reorder or skip our precmd hook"
+            }
+            ConflictingTool::ZshHistdb => {
+                "zsh-histdb adds its own zshaddhistory/precmd hooks, which can clobber the exit status we record"
+            }
+            ConflictingTool::DuplicateOmniscient => {
+                "the Omniscient hook is registered more than once, so every command is captured twice"
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConflictingTool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConflictingTool::Atuin => write!(f, "atuin"),
+            ConflictingTool::Mcfly => write!(f, "mcfly"),
+            ConflictingTool::ZshHistdb => write!(f, "zsh-histdb"),
+            ConflictingTool::DuplicateOmniscient => write!(f, "omniscient (duplicate)"),
+        }
+    }
+}
+
+/// A conflict found in an rc file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookConflict {
+    /// The conflicting tool
+    pub tool: ConflictingTool,
+
+    /// The rc file containing the conflicting line
+    pub file: PathBuf,
+
+    /// 1-based line number
+    pub line: usize,
+
+    /// The offending line, trimmed
+    pub text: String,
+}
+
+impl fmt::Display for HookConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} - {}",
+            self.file.display(),
+            self.line,
+            self.tool,
+            self.tool.description()
+        )
+    }
+}
+
+/// Get the rc files a shell reads at startup, relative to the home directory
+pub fn rc_files(shell: ShellType, home: &Path) -> Vec<PathBuf> {
+    let names: &[&str] = match shell {
+        ShellType::Zsh => &[".zshenv", ".zprofile", ".zshrc"],
+        ShellType::Bash => &[".bash_profile", ".bashrc", ".profile"],
+    };
+
+    names.iter().map(|name| home.join(name)).collect()
+}
+
+/// Scan the shell's rc files for conflicting history tools
+pub fn scan(shell: ShellType, home: &Path) -> Result<Vec<HookConflict>> {
+    let mut conflicts = Vec::new();
+    let mut omniscient_hooks = Vec::new();
+
+    for path in rc_files(shell, home) {
+        if !path.exists() {
+            continue;
+        }
+
+        let contents = fs::read_to_string(&path)?;
+        for conflict in lint_contents(&path, &contents) {
+            if conflict.tool == ConflictingTool::DuplicateOmniscient {
+                omniscient_hooks.push(conflict);
+            } else {
+                conflicts.push(conflict);
+            }
+        }
+    }
+
+    // The first hook is the real one; any further registrations are duplicates
+    conflicts.extend(omniscient_hooks.into_iter().skip(1));
+
+    Ok(conflicts)
+}
+
+/// Lint the contents of a single rc file
+///
+/// Every Omniscient hook registration is reported as `DuplicateOmniscient`;
+/// callers decide how many registrations are expected.
+pub fn lint_contents(path: &Path, contents: &str) -> Vec<HookConflict> {
+    let mut conflicts = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if let Some(tool) = detect_tool(trimmed) {
+            conflicts.push(HookConflict {
+                tool,
+                file: path.to_path_buf(),
+                line: index + 1,
+                text: trimmed.to_string(),
+            });
+        }
+    }
+
+    conflicts
+}
+
+/// Find where the shell's rc files set up the Omniscient hook, either pasted
+/// in or loaded with `eval "$(omniscient init)"`
+pub fn find_hook(shell: ShellType, home: &Path) -> Result<Option<(PathBuf, usize)>> {
+    find_line(shell, home, is_hook_line)
+}
+
+/// Find a hook set up outside the block `omniscient init --write` manages,
+/// which would capture every command twice if the block were added
+pub fn find_unmanaged_hook(shell: ShellType, home: &Path) -> Result<Option<(PathBuf, usize)>> {
+    for path in rc_files(shell, home) {
+        if !path.exists() {
+            continue;
+        }
+
+        let contents = fs::read_to_string(&path)?;
+        let mut in_block = false;
+        for (index, line) in contents.lines().enumerate() {
+            let trimmed = line.trim();
+            if in_block {
+                in_block = trimmed != BLOCK_END;
+            } else if trimmed.starts_with(BLOCK_START) {
+                in_block = true;
+            } else if !trimmed.starts_with('#') && is_hook_line(trimmed) {
+                return Ok(Some((path, index + 1)));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Whether an rc line loads or registers the Omniscient hook
+fn is_hook_line(line: &str) -> bool {
+    line.contains("omniscient init")
+        || (line.starts_with("precmd_functions") && line.contains(OMNISCIENT_HOOK_MARKER))
+}
+
+/// Find where bash's rc files load bash-preexec, which the bash hook needs
+pub fn find_bash_preexec(home: &Path) -> Result<Option<(PathBuf, usize)>> {
+    find_line(ShellType::Bash, home, |line| line.contains("bash-preexec"))
+}
+
+/// The first uncommented rc line matching `matches`, with its 1-based number
+fn find_line(
+    shell: ShellType,
+    home: &Path,
+    matches: impl Fn(&str) -> bool,
+) -> Result<Option<(PathBuf, usize)>> {
+    for path in rc_files(shell, home) {
+        if !path.exists() {
+            continue;
+        }
+
+        let contents = fs::read_to_string(&path)?;
+        for (index, line) in contents.lines().enumerate() {
+            let trimmed = line.trim();
+            if !trimmed.starts_with('#') && matches(trimmed) {
+                return Ok(Some((path, index + 1)));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Identify which tool (if any) a single rc line sets up
+fn detect_tool(line: &str) -> Option<ConflictingTool> {
+    if line.contains("atuin init") {
+        Some(ConflictingTool::Atuin)
+    } else if line.contains("mcfly init") {
+        Some(ConflictingTool::Mcfly)
+    } else if line.contains("sqlite-history.zsh") || line.contains("zsh-histdb") {
+        Some(ConflictingTool::ZshHistdb)
+    } else if line.starts_with("precmd_functions") && line.contains(OMNISCIENT_HOOK_MARKER) {
+        Some(ConflictingTool::DuplicateOmniscient)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_detects_history_tools() {
+        let contents = r#"
+# eval "$(atuin init zsh)"   <- commented out, ignored
+eval "$(atuin init zsh)"
+eval "$(mcfly init zsh)"
+source ~/.oh-my-zsh/custom/plugins/zsh-histdb/sqlite-history.zsh
+alias gst='git status'
+"#;
+        let conflicts = lint_contents(Path::new(".zshrc"), contents);
+        let tools: Vec<ConflictingTool> = conflicts.iter().map(|c| c.tool).collect();
+
+        assert_eq!(
+            tools,
+            vec![
+                ConflictingTool::Atuin,
+                ConflictingTool::Mcfly,
+                ConflictingTool::ZshHistdb
+            ]
+        );
+        assert_eq!(conflicts[0].line, 3);
+    }
+
+    #[test]
+    fn test_scan_reports_duplicate_hooks_only() {
+        let home = TempDir::new().unwrap();
+        let hook = crate::ShellHook::new(ShellType::Zsh).generate();
+        fs::write(home.path().join(".zshrc"), &hook).unwrap();
+
+        assert!(scan(ShellType::Zsh, home.path()).unwrap().is_empty());
+
+        fs::write(home.path().join(".zshrc"), format!("{}\n{}", hook, hook)).unwrap();
+        let conflicts = scan(ShellType::Zsh, home.path()).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].tool, ConflictingTool::DuplicateOmniscient);
+    }
+
+    #[test]
+    fn test_scan_compat_hook_is_recognized() {
+        let home = TempDir::new().unwrap();
+        let hook = crate::ShellHook::new(ShellType::Bash)
+            .with_compat_mode()
+            .generate();
+        fs::write(home.path().join(".bashrc"), format!("{}\n{}", hook, hook)).unwrap();
+
+        let conflicts = scan(ShellType::Bash, home.path()).unwrap();
+        assert_eq!(conflicts.len(), 1);
+    }
+
+    #[test]
+    fn test_find_hook() {
+        let home = TempDir::new().unwrap();
+        assert_eq!(find_hook(ShellType::Zsh, home.path()).unwrap(), None);
+
+        fs::write(
+            home.path().join(".zshrc"),
+            "# eval \"$(omniscient init)\"\nexport EDITOR=vim\neval \"$(omniscient init)\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            find_hook(ShellType::Zsh, home.path()).unwrap(),
+            Some((home.path().join(".zshrc"), 3))
+        );
+
+        let hook = crate::ShellHook::new(ShellType::Bash).generate();
+        fs::write(home.path().join(".bashrc"), &hook).unwrap();
+        assert!(find_hook(ShellType::Bash, home.path()).unwrap().is_some());
+        assert_eq!(find_bash_preexec(home.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_find_unmanaged_hook() {
+        let home = TempDir::new().unwrap();
+        let rc = crate::shell::rc_file(ShellType::Zsh, home.path());
+        crate::ShellHook::new(ShellType::Zsh).write_to(&rc).unwrap();
+        assert!(find_hook(ShellType::Zsh, home.path()).unwrap().is_some());
+        assert_eq!(
+            find_unmanaged_hook(ShellType::Zsh, home.path()).unwrap(),
+            None
+        );
+
+        let mut contents = fs::read_to_string(&rc).unwrap();
+        contents.push_str("eval \"$(omniscient init zsh)\"\n");
+        fs::write(&rc, &contents).unwrap();
+        assert_eq!(
+            find_unmanaged_hook(ShellType::Zsh, home.path()).unwrap(),
+            Some((rc, contents.lines().count()))
+        );
+    }
+
+    #[test]
+    fn test_scan_missing_files() {
+        let home = TempDir::new().unwrap();
+        assert!(scan(ShellType::Bash, home.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_conflict_display() {
+        let conflict = HookConflict {
+            tool: ConflictingTool::Mcfly,
+            file: PathBuf::from("/home/me/.bashrc"),
+            line: 12,
+            text: "eval \"$(mcfly init bash)\"".to_string(),
+        };
+
+        let display = conflict.to_string();
+        assert!(display.starts_with("/home/me/.bashrc:12: mcfly"));
+    }
+}
@@ -0,0 +1,68 @@
+/// Advisory lock files
+///
+/// Some work must only run in one process at a time, such as moving the data
+/// directory or a scheduled export that any capture may start. A
+/// [`FileLock`] holds an exclusive lock on a file until it is dropped; the
+/// file itself stays in place for the next process.
+use crate::error::{Result, ResultExt};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::Path;
+
+/// An exclusive lock, released when dropped
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Wait until no other process holds the lock at `path`, then take it
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let file = open(path)?;
+        file.lock()
+            .with_context(|| format!("lock {}", path.display()))?;
+        Ok(Self { _file: file })
+    }
+
+    /// Take the lock at `path` unless another process holds it
+    pub fn try_acquire(path: &Path) -> Result<Option<Self>> {
+        let file = open(path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self { _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => {
+                Err(e).with_context(|| format!("lock {}", path.display()))
+            }
+        }
+    }
+}
+
+fn open(path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("open lock file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("export.lock");
+
+        let lock = FileLock::try_acquire(&path).unwrap();
+        assert!(lock.is_some());
+        assert!(FileLock::try_acquire(&path).unwrap().is_none());
+
+        drop(lock);
+        assert!(FileLock::try_acquire(&path).unwrap().is_some());
+        assert!(FileLock::acquire(&path).is_ok());
+    }
+}
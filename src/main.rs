@@ -1,18 +1,127 @@
 /// Main CLI entry point for Omniscient
-use clap::{Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use colored::Colorize;
-use omniscient::{Config, Result};
+use omniscient::format::{one_line, DisplayWidth, Template};
+use omniscient::shell::RcChange;
+use omniscient::timezone::DisplayZone;
+use omniscient::{ColorMode, Config, ListingFormat, Result, Theme};
 use std::env;
+use std::io::Write;
+use std::sync::OnceLock;
 
 #[derive(Parser)]
 #[command(name = "omniscient")]
 #[command(about = "CLI command history tracker - never forget a command again", long_about = None)]
 #[command(version)]
 struct Cli {
+    /// Copy the database aside before applying schema upgrades
+    #[arg(long, global = true)]
+    backup_before_migrate: bool,
+
+    /// Use this database instead of the configured one (or set OMNISCIENT_DB)
+    #[arg(long, global = true, value_name = "PATH")]
+    db: Option<String>,
+
+    /// Use this config file instead of the default one (or set OMNISCIENT_CONFIG)
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<std::path::PathBuf>,
+
+    /// Print long listings directly instead of through $PAGER
+    #[arg(long, global = true)]
+    no_pager: bool,
+
+    /// Don't color output (same as NO_COLOR=1 or output.color = "never")
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Print long commands in full instead of truncating them to the
+    /// terminal width (or display.width in config)
+    #[arg(long, global = true)]
+    full: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+/// Paging options shared by listing commands
+#[derive(Args)]
+struct Paging {
+    /// Skip this many results
+    #[arg(long, default_value = "0", conflicts_with = "page")]
+    offset: usize,
+
+    /// Show this page of results (pages are --limit results long, starting at 1)
+    #[arg(long)]
+    page: Option<usize>,
+}
+
+impl Paging {
+    /// Number of results to skip for pages of `limit` results
+    fn offset(&self, limit: usize) -> usize {
+        match self.page {
+            Some(page) => page.saturating_sub(1) * limit,
+            None => self.offset,
+        }
+    }
+}
+
+/// Output format options shared by listing commands
+#[derive(Args)]
+struct Formatting {
+    /// Output format: verbose, or compact for one line per command (default:
+    /// output.format in config)
+    #[arg(long, value_name = "FORMAT")]
+    format: Option<String>,
+
+    /// Print each command with a template such as "{timestamp} {dir}
+    /// {command}" (fields: id, command, typed, timestamp, last_used,
+    /// exit_code, status, duration, duration_ms, dir, category, usage, note)
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "format")]
+    format_string: Option<String>,
+}
+
+/// How a listing prints each command
+enum Layout {
+    /// Command line followed by metadata lines, under a header
+    Verbose,
+
+    /// One line per command, no header
+    Compact,
+
+    /// One template line per command, no header
+    Template(Template),
+}
+
+impl Formatting {
+    /// The requested layout, falling back to the configured format
+    fn layout(&self, config: &Config) -> Layout {
+        if let Some(ref template) = self.format_string {
+            return match Template::parse(template) {
+                Ok(template) => Layout::Template(template.in_zone(config.display.timezone)),
+                Err(e) => {
+                    eprintln!("Error: Invalid --format-string: {}", e);
+                    std::process::exit(1);
+                }
+            };
+        }
+
+        let format = match self.format {
+            Some(ref name) => ListingFormat::from_name(name).unwrap_or_else(|| {
+                eprintln!(
+                    "Error: Unsupported format '{}'. Supported formats: verbose, compact",
+                    name
+                );
+                std::process::exit(1);
+            }),
+            None => config.output.format,
+        };
+        match format {
+            ListingFormat::Verbose => Layout::Verbose,
+            ListingFormat::Compact => Layout::Compact,
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize shell integration (generates hook code)
@@ -20,6 +129,27 @@ enum Commands {
         /// Specify shell type (zsh, bash). Auto-detected if not provided.
         #[arg(long)]
         shell: Option<String>,
+
+        /// Capture-only hook that coexists with atuin, mcfly, zsh-histdb, etc.
+        #[arg(long)]
+        compat: bool,
+
+        /// Add the hook to ~/.zshrc or ~/.bashrc (or update it there) instead
+        /// of printing it; re-run after upgrading or changing hook settings
+        #[arg(long)]
+        write: bool,
+
+        /// Remove the hook added with --write
+        #[arg(long, conflicts_with_all = ["write", "compat"])]
+        uninstall: bool,
+    },
+
+    /// Check the installation (hook, config, database, capture activity) and
+    /// suggest fixes
+    Doctor {
+        /// Shell whose rc files to check (zsh, bash). Auto-detected if not provided.
+        #[arg(long)]
+        shell: Option<String>,
     },
 
     /// Capture a command (internal use by shell hook)
@@ -32,26 +162,130 @@ enum Commands {
         #[arg(long)]
         duration: i64,
 
+        /// The command after alias expansion, if the shell provides it
+        #[arg(long)]
+        expanded: Option<String>,
+
+        /// When the command started (Unix epoch seconds or RFC 3339); defaults to now
+        #[arg(long, visible_alias = "timestamp")]
+        started_at: Option<String>,
+
+        /// Host the command ran on
+        #[arg(long)]
+        host: Option<String>,
+
+        /// File holding the command's output (read if capture.output is on, then deleted)
+        #[arg(long)]
+        output_file: Option<String>,
+
+        /// Unique ID of this capture (e.g. a UUID); retries with the same ID are stored once
+        #[arg(long)]
+        capture_id: Option<String>,
+
         /// The command to capture
         command: String,
     },
 
     /// Search command history
     Search {
-        /// Search query
-        query: String,
+        /// Search query (optional with --program or --flag)
+        #[arg(required_unless_present_any = ["program", "flag"])]
+        query: Option<String>,
 
         /// Maximum number of results
         #[arg(short, long, default_value = "20")]
         limit: usize,
 
-        /// Filter by directory
+        /// Filter by directory (supports globs such as '~/work/*/api')
         #[arg(short, long)]
         dir: Option<String>,
 
         /// Include subdirectories
         #[arg(short, long)]
         recursive: bool,
+
+        /// Only commands run with an environment variable set (NAME=VALUE, repeatable)
+        #[arg(long = "env", value_name = "NAME=VALUE")]
+        env_filters: Vec<String>,
+
+        /// Only commands run on this git branch
+        #[arg(long)]
+        branch: Option<String>,
+
+        /// Only commands run on this host (e.g. one set up with remote-install)
+        #[arg(long)]
+        host: Option<String>,
+
+        /// Only commands run in this tmux (or screen) session
+        #[arg(long, value_name = "SESSION")]
+        tmux_session: Option<String>,
+
+        /// Only commands run in this tmux pane (e.g. %3, from $TMUX_PANE)
+        #[arg(long, value_name = "PANE")]
+        tmux_pane: Option<String>,
+
+        /// Match each word as a prefix ("git chec" finds "git checkout")
+        #[arg(short, long)]
+        prefix: bool,
+
+        /// Match anywhere in the command ignoring case, not just whole words
+        #[arg(short, long)]
+        ignore_case: bool,
+
+        /// Leave out commands containing this term (repeatable)
+        #[arg(long, value_name = "TERM")]
+        exclude: Vec<String>,
+
+        /// Leave out commands in this category (repeatable)
+        #[arg(long, value_name = "CATEGORY")]
+        exclude_category: Vec<String>,
+
+        /// Only commands that took at least this long (e.g. 500ms, 2s, 5m)
+        #[arg(long, value_name = "DURATION")]
+        min_duration: Option<String>,
+
+        /// Only commands that took at most this long (e.g. 500ms, 2s, 5m)
+        #[arg(long, value_name = "DURATION")]
+        max_duration: Option<String>,
+
+        /// Only commands that exited with this code (e.g. 137 for OOM kills,
+        /// 130 for Ctrl-C)
+        #[arg(long, value_name = "CODE", allow_negative_numbers = true)]
+        exit_code: Option<i32>,
+
+        /// Only commands running this program, even behind sudo or after
+        /// `cd x &&` (e.g. kubectl)
+        #[arg(long)]
+        program: Option<String>,
+
+        /// Only commands given this flag, optionally with a value (e.g. -n,
+        /// "-n prod", --output=json; repeatable)
+        #[arg(long, value_name = "FLAG", allow_hyphen_values = true)]
+        flag: Vec<String>,
+
+        /// Run the Nth result (default: the first) instead of listing results
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+        exec: Option<usize>,
+
+        /// Copy the Nth result (default: the first) to the clipboard
+        #[arg(
+            long,
+            value_name = "N",
+            num_args = 0..=1,
+            default_missing_value = "1",
+            conflicts_with = "exec"
+        )]
+        copy: Option<usize>,
+
+        /// With --exec, run without asking
+        #[arg(long, requires = "exec")]
+        yes: bool,
+
+        #[command(flatten)]
+        paging: Paging,
+
+        #[command(flatten)]
+        formatting: Formatting,
     },
 
     /// Show commands executed in current directory
@@ -64,9 +298,31 @@ enum Commands {
         #[arg(short, long)]
         dir: Option<String>,
 
+        /// Show the directory's commands on this host instead (one synced
+        /// with remote-sync)
+        #[arg(long)]
+        host: Option<String>,
+
         /// Maximum number of results
         #[arg(short, long, default_value = "20")]
         limit: usize,
+
+        #[command(flatten)]
+        paging: Paging,
+
+        #[command(flatten)]
+        formatting: Formatting,
+    },
+
+    /// Print the most used commands of a directory (used by the shell hook
+    /// on cd when suggestions.on_cd is enabled)
+    OnCd {
+        /// Directory to query (default: current directory)
+        dir: Option<String>,
+
+        /// Number of commands (default: suggestions.count in config)
+        #[arg(short = 'n', long)]
+        count: Option<usize>,
     },
 
     /// Show recent commands
@@ -75,13 +331,43 @@ enum Commands {
         #[arg(default_value = "20")]
         n: usize,
 
-        /// Filter by directory
+        /// Filter by directory (supports globs such as '~/work/*/api')
         #[arg(short, long)]
         dir: Option<String>,
 
         /// Include subdirectories
         #[arg(short, long)]
         recursive: bool,
+
+        /// Only commands run on this host (e.g. one synced with remote-sync)
+        #[arg(long)]
+        host: Option<String>,
+
+        /// Only commands run in this tmux (or screen) session
+        #[arg(long, value_name = "SESSION")]
+        tmux_session: Option<String>,
+
+        /// Only commands run in this tmux pane (e.g. %3, from $TMUX_PANE)
+        #[arg(long, value_name = "PANE")]
+        tmux_pane: Option<String>,
+
+        /// Only commands that took at least this long (e.g. 500ms, 2s, 5m)
+        #[arg(long, value_name = "DURATION")]
+        min_duration: Option<String>,
+
+        /// Only commands that took at most this long (e.g. 500ms, 2s, 5m)
+        #[arg(long, value_name = "DURATION")]
+        max_duration: Option<String>,
+
+        /// Copy the Nth result (default: the first) to the clipboard
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+        copy: Option<usize>,
+
+        #[command(flatten)]
+        paging: Paging,
+
+        #[command(flatten)]
+        formatting: Formatting,
     },
 
     /// Show most frequently used commands
@@ -90,13 +376,84 @@ enum Commands {
         #[arg(default_value = "10")]
         n: usize,
 
-        /// Filter by directory
+        /// Filter by directory (supports globs such as '~/work/*/api')
+        #[arg(short, long)]
+        dir: Option<String>,
+
+        /// Include subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Copy the Nth result (default: the first) to the clipboard
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+        copy: Option<usize>,
+
+        #[command(flatten)]
+        paging: Paging,
+
+        #[command(flatten)]
+        formatting: Formatting,
+    },
+
+    /// Show the commands that take longest to run
+    Slow {
+        /// Number of commands to show
+        #[arg(default_value = "10")]
+        n: usize,
+
+        /// Rank by average or maximum duration (avg, max)
+        #[arg(long, default_value = "avg")]
+        by: String,
+
+        /// Only include commands run at least this many times
+        #[arg(long, default_value = "1")]
+        min_runs: usize,
+
+        /// Filter by directory (supports globs such as '~/work/*/api')
+        #[arg(short, long)]
+        dir: Option<String>,
+
+        /// Include subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+    },
+
+    /// Show the commands that fail most often and how they fail
+    Failures {
+        /// Number of commands (and recent failures) to show
+        #[arg(default_value = "10")]
+        n: usize,
+
+        /// Only rank commands run at least this many times
+        #[arg(long, default_value = "2")]
+        min_runs: usize,
+
+        /// Filter by directory (supports globs such as '~/work/*/api')
+        #[arg(short, long)]
+        dir: Option<String>,
+
+        /// Include subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+    },
+
+    /// Directories with the most activity, their success rates and top categories
+    Dirs {
+        /// Number of directories to show
+        #[arg(default_value = "10")]
+        n: usize,
+
+        /// Only directories matching this one (supports globs such as '~/work/*')
         #[arg(short, long)]
         dir: Option<String>,
 
         /// Include subdirectories
         #[arg(short, long)]
         recursive: bool,
+
+        /// Only count activity since a date, timestamp or duration ago (e.g. 2025-11-01, 7d)
+        #[arg(long)]
+        since: Option<String>,
     },
 
     /// Filter commands by category
@@ -108,367 +465,3389 @@ enum Commands {
         #[arg(short, long, default_value = "20")]
         limit: usize,
 
-        /// Filter by directory
+        /// Filter by directory (supports globs such as '~/work/*/api')
         #[arg(short, long)]
         dir: Option<String>,
 
         /// Include subdirectories
         #[arg(short, long)]
         recursive: bool,
+
+        /// Show where the category is used most instead of its commands (dir)
+        #[arg(long, value_name = "FIELD")]
+        group_by: Option<String>,
+
+        /// Only count runs since a date, timestamp or duration ago (with --group-by)
+        #[arg(long, requires = "group_by")]
+        since: Option<String>,
     },
 
-    /// Show usage statistics
-    Stats,
+    /// Print history as tab-separated lines for piping into fzf
+    Fzf {
+        /// Maximum number of commands to print
+        #[arg(short = 'n', long, default_value = "10000")]
+        limit: usize,
 
-    /// Export command history to JSON
-    Export {
-        /// Output file path
-        #[arg(default_value = "history.json")]
-        file: String,
+        /// Filter by directory (supports globs such as '~/work/*/api')
+        #[arg(short, long)]
+        dir: Option<String>,
+
+        /// Include subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Read fzf's selection from stdin and print just the command
+        #[arg(long)]
+        select: bool,
     },
 
-    /// Import command history from JSON
-    Import {
-        /// Input file path
-        file: String,
+    /// Suggest aliases for long commands you run often
+    Aliases {
+        #[command(subcommand)]
+        action: AliasesAction,
     },
 
-    /// Show configuration
-    Config,
-}
+    /// Show failed commands you corrected right away, with alias suggestions
+    Typos {
+        /// Number of typos to show
+        #[arg(default_value = "10")]
+        n: usize,
 
-/// Return a colored status symbol for a command record
-fn colorize_status(cmd: &omniscient::CommandRecord) -> colored::ColoredString {
-    if cmd.is_success() {
-        "✓".green()
-    } else {
-        "✗".red()
-    }
-}
+        /// Seconds after a failure within which a retry counts as a correction
+        #[arg(long, default_value = "30")]
+        window: i64,
 
-/// Return a colored string for a category name
-fn colorize_category(category: &str) -> colored::ColoredString {
-    match category {
-        "git" => category.cyan(),
-        "docker" => category.blue(),
-        "network" => category.magenta(),
-        "file" => category.yellow(),
-        "package" => category.bright_green(),
-        "database" => category.bright_magenta(),
-        "kubernetes" => category.bright_blue(),
-        "cloud" => category.bright_cyan(),
-        "system" => category.bright_yellow(),
-        "editor" => category.white(),
-        "build" => category.bright_red(),
-        "vcs" => category.bright_white(),
-        _ => category.normal(),
-    }
-}
-
-/// Highlight the first occurrence of `query` in `text` using bold + underline
-fn highlight_match(text: &str, query: &str) -> String {
-    let lower_text = text.to_lowercase();
-    let lower_query = query.to_lowercase();
-    match lower_text.find(&lower_query) {
-        Some(start) => {
-            let end = start + query.len();
-            let prefix = &text[..start];
-            let matched = &text[start..end];
-            let suffix = &text[end..];
-            format!("{}{}{}", prefix, matched.bold().underline(), suffix)
-        }
-        None => text.to_string(),
-    }
-}
+        /// Maximum number of edits between the typo and the correction
+        #[arg(long, default_value = "2")]
+        max_distance: usize,
+    },
 
-/// Resolve the directory to query (from --dir flag or current directory)
-fn resolve_directory(dir_arg: Option<String>) -> Result<String> {
-    match dir_arg {
-        Some(path) => Ok(path),
-        None => env::current_dir()
-            .map(|p| p.to_string_lossy().to_string())
-            .map_err(omniscient::OmniscientError::Io),
-    }
-}
+    /// Show frequently repeated command sequences per project
+    Workflows {
+        /// Number of workflows to show per directory
+        #[arg(default_value = "5")]
+        n: usize,
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+        /// Filter by directory (supports globs such as '~/work/*/api')
+        #[arg(short, long)]
+        dir: Option<String>,
 
-    // Load configuration
-    let config = Config::load()?;
-    config.ensure_directories()?;
+        /// Include subdirectories
+        #[arg(short, long)]
+        recursive: bool,
 
-    match cli.command {
-        Commands::Init { shell } => {
-            use omniscient::ShellType;
+        /// Minimum number of times a sequence must repeat
+        #[arg(long, default_value = "2")]
+        min_count: usize,
+    },
 
-            // Determine shell type (manual or auto-detect)
-            let shell_type = if let Some(shell_name) = shell {
-                match shell_name.as_str() {
-                    "zsh" => ShellType::Zsh,
-                    "bash" => ShellType::Bash,
-                    _ => {
-                        eprintln!(
-                            "Error: Unsupported shell '{}'. Supported shells: zsh, bash",
-                            shell_name
-                        );
-                        eprintln!("Tip: Omit --shell flag to auto-detect your shell.");
-                        std::process::exit(1);
-                    }
-                }
-            } else {
-                omniscient::ShellHook::detect_shell()?
-            };
+    /// Manage bookmarked commands (pinned to the top of search results)
+    Bookmark {
+        #[command(subcommand)]
+        action: BookmarkAction,
+    },
 
-            let hook = omniscient::ShellHook::new(shell_type);
-            println!("{}", hook.generate());
-            eprintln!("{}", hook.installation_instructions());
-            Ok(())
-        }
-        Commands::Capture {
-            exit_code,
-            duration,
-            command,
-        } => {
-            // Create capture instance
-            let capture = omniscient::CommandCapture::new(config)?;
+    /// Attach a note to a command (shown in search output and included in exports)
+    Note {
+        /// Command ID (shown in search/recent output)
+        id: i64,
 
-            // Capture the command (errors are silently ignored to not break shell)
-            if let Err(e) = capture.capture(&command, exit_code, duration) {
-                // Log error but don't fail (shell must continue working)
-                eprintln!("omniscient: capture error: {}", e);
-            }
+        /// Note text (omit to show the current note)
+        text: Option<String>,
 
-            Ok(())
-        }
-        Commands::Search {
-            query,
-            limit,
-            dir,
-            recursive,
-        } => {
-            let storage = omniscient::Storage::new(&config.database_path()?)?;
+        /// Remove the note
+        #[arg(long, conflicts_with = "text")]
+        clear: bool,
+    },
 
-            let working_dir = if dir.is_some() {
-                Some(resolve_directory(dir)?)
-            } else {
-                None
-            };
+    /// Show everything stored about a command: metadata, executions and output
+    Show {
+        /// Command ID (shown in search/recent output)
+        id: i64,
 
-            let search_query = omniscient::SearchQuery {
-                text: Some(query.clone()),
-                category: None,
-                success_only: None,
-                working_dir,
-                recursive,
-                limit,
-                order_by: omniscient::OrderBy::Relevance,
-            };
+        /// Maximum number of executions to list
+        #[arg(short = 'n', long, default_value = "20")]
+        limit: usize,
 
-            let results = storage.search(&search_query)?;
+        /// Print the record as JSON
+        #[arg(long)]
+        json: bool,
+    },
 
-            if results.is_empty() {
-                println!("No commands found matching '{}'", query);
-                return Ok(());
-            }
+    /// Every recorded run of a command: when, where, exit codes and a
+    /// duration trend
+    History {
+        /// The command, or a glob pattern such as "cargo build*"
+        command: String,
 
-            println!("\nFound {} matching command(s):\n", results.len());
-            for cmd in results {
-                println!(
-                    "[{}] {} {}",
-                    cmd.timestamp
-                        .format("%Y-%m-%d %H:%M:%S")
-                        .to_string()
-                        .dimmed(),
-                    colorize_status(&cmd),
-                    highlight_match(&cmd.command, &query)
-                );
-                println!(
-                    "  Category: {} | Duration: {} | Usage: {} times | Dir: {}",
-                    colorize_category(&cmd.category),
+        /// Maximum number of runs to list (the most recent ones)
+        #[arg(short = 'n', long, default_value = "50")]
+        limit: usize,
+    },
+
+    /// The flags, flag combinations and subcommands you use most with a
+    /// program (e.g. to find rsync options worth an alias)
+    Flags {
+        /// Program name, e.g. "rsync" or "kubectl"
+        program: String,
+
+        /// Only count commands running this subcommand (e.g. "get")
+        subcommand: Option<String>,
+
+        /// Maximum number of entries per list
+        #[arg(short = 'n', long, default_value = "10")]
+        limit: usize,
+    },
+
+    /// Find stored commands similar to one you half remember (same words in
+    /// any order, or small edits)
+    Similar {
+        /// The command as well as you remember it
+        command: String,
+
+        /// Maximum number of results
+        #[arg(short = 'n', long, default_value = "10")]
+        limit: usize,
+
+        /// Minimum similarity from 0 to 1
+        #[arg(long, default_value = "0.3")]
+        min_score: f64,
+    },
+
+    /// Copy a command into another directory's scope (e.g. after copying a project)
+    Promote {
+        /// Command ID (shown in search/recent output)
+        id: i64,
+
+        /// Directory to promote the command to
+        #[arg(long)]
+        to_dir: String,
+
+        /// Move the command instead of copying it
+        #[arg(long = "move")]
+        move_record: bool,
+    },
+
+    /// Run a stored command again, warning if the context has changed
+    Run {
+        /// Command ID (shown in search/recent output)
+        id: i64,
+
+        /// Run without asking, even if the context differs
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Only print the command
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Run commands from destructive categories or with sudo/doas (only
+        /// printed by default)
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Stop capturing commands (for demos, screen shares, sensitive work)
+    Pause {
+        /// Resume automatically after this long (e.g. 30m, 1h)
+        #[arg(long = "for", value_name = "DURATION")]
+        duration: Option<String>,
+    },
+
+    /// Resume capturing commands after `omniscient pause`
+    Resume,
+
+    /// Teach the categorizer about commands it files under "other"
+    Train {
+        /// Number of command names to go through
+        #[arg(short, long, default_value = "20")]
+        limit: usize,
+    },
+
+    /// Database maintenance
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+
+    /// Run a read-only SQL query against the history (SELECT only), e.g.
+    /// "SELECT working_dir, COUNT(*) FROM commands GROUP BY 1"
+    Query {
+        /// SELECT statement (tables: commands, executions, outputs, alerts, ...)
+        sql: String,
+
+        /// Print the rows as a JSON array of objects
+        #[arg(long)]
+        json: bool,
+
+        /// Maximum number of rows
+        #[arg(short = 'n', long, default_value = "1000")]
+        limit: usize,
+    },
+
+    /// Time captures, searches and stats on a synthetic history and compare
+    /// them to performance targets (exits with 1 if one is missed)
+    Bench {
+        /// Create the benchmark database with this many commands first
+        /// (e.g. 50k or 1M), replacing an existing one
+        #[arg(long, value_name = "COUNT")]
+        generate: Option<String>,
+
+        /// Benchmark database (default: bench.db next to the configured database)
+        #[arg(long, value_name = "PATH")]
+        path: Option<std::path::PathBuf>,
+
+        /// Number of captures and searches to time
+        #[arg(long, default_value = "100")]
+        runs: usize,
+    },
+
+    /// Merge near-duplicate commands (extra whitespace, symlinked directories)
+    Dedupe {
+        /// Only show what would be merged
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Show usage statistics
+    Stats {
+        /// Show how many commands capture skipped, and why
+        #[arg(long)]
+        capture_health: bool,
+
+        /// Show activity per git branch
+        #[arg(long, conflicts_with = "capture_health")]
+        by_branch: bool,
+
+        /// Show commands per day, week or month and the busiest hours
+        #[arg(long, conflicts_with_all = ["capture_health", "by_branch"])]
+        activity: bool,
+
+        /// Show how many commands you ran for the first time per week or
+        /// month, and how your command vocabulary grew
+        #[arg(long, conflicts_with_all = ["capture_health", "by_branch", "activity"])]
+        vocabulary: bool,
+
+        /// Print the statistics as JSON (with per-category success rates,
+        /// durations and last use, daily counts for the last 30 days and new commands per
+        /// week for the last 12 weeks)
+        #[arg(long, conflicts_with_all = ["capture_health", "by_branch", "activity", "vocabulary", "since", "until"])]
+        json: bool,
+
+        /// Bucket size (day, week, month); defaults to day for --activity and
+        /// week for --vocabulary
+        #[arg(long)]
+        period: Option<String>,
+
+        /// Only count activity since a date, timestamp or duration ago (e.g. 2025-11-01, 7d)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only count activity before a date, timestamp or duration ago
+        #[arg(long)]
+        until: Option<String>,
+    },
+
+    /// Summarize the last week or month: totals, new commands, top tools, busiest projects
+    Report {
+        /// Period to summarize (week, month)
+        #[arg(long, default_value = "week")]
+        period: String,
+
+        /// Output format (text, markdown, html)
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+
+    /// Your year in the shell: top commands, categories, streaks and more
+    Wrapped {
+        /// Year to review (default: the current year)
+        year: Option<i32>,
+    },
+
+    /// Review dangerous commands that matched the alerts watchlist
+    Alerts {
+        /// Number of alerts to show
+        #[arg(short = 'n', long, default_value = "20")]
+        limit: usize,
+
+        /// Delete all logged alerts
+        #[arg(long)]
+        clear: bool,
+    },
+
+    /// Copy the database to the backup directory ([backup] in config)
+    Backup {
+        #[command(subcommand)]
+        action: BackupAction,
+    },
+
+    /// Export command history to JSON or Markdown
+    Export {
+        /// Output file path
+        #[arg(default_value = "history.json")]
+        file: String,
+
+        /// Output format (json, ndjson, markdown, sqlite)
+        #[arg(long, default_value = "json")]
+        format: String,
+
+        /// Only export commands in this category (repeatable)
+        #[arg(long)]
+        category: Vec<String>,
+
+        /// Only export commands run in this directory
+        #[arg(long)]
+        dir: Option<String>,
+
+        /// With --dir, include subdirectories
+        #[arg(short, long, requires = "dir")]
+        recursive: bool,
+
+        /// Only export commands used since a date, timestamp or duration ago (e.g. 2025-01-01, 30d)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Leave out commands whose last run failed
+        #[arg(long)]
+        exclude_failed: bool,
+
+        /// Mask secrets matching the redaction patterns, even if privacy is
+        /// disabled or the database predates the patterns
+        #[arg(long)]
+        redact: bool,
+
+        /// With --redact, also remove working directories and hostnames
+        #[arg(long, requires = "redact")]
+        strip_locations: bool,
+    },
+
+    /// Import command history from JSON
+    Import {
+        /// Input file path
+        file: String,
+
+        /// How to handle commands that already exist: skip, merge (add up
+        /// usage counts), preserve-higher (keep the higher usage count) or
+        /// overwrite (replace them with the imported ones)
+        #[arg(long, default_value = "preserve-higher")]
+        strategy: String,
+    },
+
+    /// Restore history from an export or a backup, showing what will change first
+    Restore {
+        /// Export (JSON, NDJSON, compressed) or SQLite backup to restore from
+        file: String,
+
+        /// How to handle commands that already exist: skip, merge (add up
+        /// usage counts), preserve-higher (keep the higher usage count) or
+        /// overwrite (replace them with the restored ones)
+        #[arg(long, default_value = "preserve-higher")]
+        strategy: String,
+
+        /// Only show what would change
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Restore without asking
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Replace the whole database with an SQLite backup instead of
+        /// merging records (the current database is kept next to it)
+        #[arg(long, conflicts_with = "strategy")]
+        replace: bool,
+    },
+
+    /// Capture commands run on a remote host over SSH: installs a small
+    /// shell hook there that keeps them until `omniscient remote-sync`
+    RemoteInstall {
+        /// SSH destination, e.g. deploy@build.example.com or a ~/.ssh/config alias
+        target: String,
+
+        /// Name to tag the host's commands with (default: the host part of
+        /// the destination)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Remote shell (zsh, bash). Detected from the remote $SHELL if not provided.
+        #[arg(long)]
+        shell: Option<String>,
+    },
+
+    /// Fetch and store the commands captured on remote hosts
+    RemoteSync {
+        /// Host name or SSH destination (default: every host in [remote.hosts])
+        host: Option<String>,
+    },
+
+    /// Show whether (and how) a command would be redacted
+    RedactTest {
+        /// Command text to check against the current patterns
+        command: String,
+    },
+
+    /// Find stored commands that match the current redaction patterns
+    RedactAudit {
+        /// Delete the matching records
+        #[arg(long, conflicts_with = "mask")]
+        purge: bool,
+
+        /// Mask the sensitive part of the matching records
+        #[arg(long)]
+        mask: bool,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Apply the current redaction patterns to stored history
+    Redact {
+        #[command(subcommand)]
+        action: RedactAction,
+    },
+
+    /// Show configuration, or get/set individual settings
+    Config {
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
+    },
+
+    /// Serve history queries to other tools
+    #[command(group(clap::ArgGroup::new("mode").required(true).args(["api", "socket"])))]
+    Serve {
+        /// HTTP/JSON API (GET /search, /recent, /top, /suggest, /stats, /categories)
+        #[arg(long)]
+        api: bool,
+
+        /// JSON-RPC over a Unix socket for editor plugins (default: <database>.sock)
+        #[arg(long, value_name = "PATH", num_args = 0..=1)]
+        socket: Option<Option<std::path::PathBuf>>,
+
+        /// Address to listen on
+        #[arg(long, default_value = omniscient::api::DEFAULT_ADDR)]
+        addr: String,
+    },
+
+    /// Run the query daemon and daily maintenance as a systemd user unit
+    /// (Linux) or launchd agent (macOS)
+    Daemon {
+        #[command(subcommand)]
+        action: DaemonAction,
+    },
+
+    /// Print a shell completion script (bash, zsh, fish, powershell, elvish)
+    Completions {
+        /// Shell to generate completions for
+        #[arg(required_unless_present = "list_categories")]
+        shell: Option<String>,
+
+        /// Print known category names (used by the completion scripts)
+        #[arg(long, hide = true)]
+        list_categories: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AliasesAction {
+    /// Print alias definitions to paste into your shell rc file
+    Suggest {
+        /// Shell syntax: zsh, bash or fish (default: detected from $SHELL)
+        #[arg(long)]
+        shell: Option<String>,
+
+        /// Shortest command worth an alias, in characters (default:
+        /// aliases.min_length)
+        #[arg(long)]
+        min_length: Option<usize>,
+
+        /// Fewest runs of a command worth an alias (default: aliases.min_uses)
+        #[arg(long)]
+        min_uses: Option<i64>,
+
+        /// Maximum number of suggestions
+        #[arg(short = 'n', long, default_value = "10")]
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum BookmarkAction {
+    /// Bookmark a command by ID
+    Add {
+        /// Command ID (shown in search/recent output)
+        id: i64,
+    },
+
+    /// Remove a bookmark
+    Remove {
+        /// Command ID
+        id: i64,
+    },
+
+    /// List bookmarked commands
+    List {
+        /// Maximum number of results
+        #[arg(short, long, default_value = "20")]
+        limit: usize,
+
+        #[command(flatten)]
+        paging: Paging,
+    },
+}
+
+#[derive(Subcommand)]
+enum RedactAction {
+    /// Mask (or delete) every stored command that matches a redaction pattern
+    Apply {
+        /// Delete matching records instead of masking them
+        #[arg(long)]
+        delete: bool,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print a setting (e.g. privacy.enabled, capture.min_duration_ms)
+    Get {
+        /// Dotted key; quote segments that contain dots (categories.rules."deploy.sh")
+        key: String,
+    },
+
+    /// Change a setting and save the config file
+    Set {
+        /// Dotted key (e.g. capture.min_duration_ms)
+        key: String,
+
+        /// New value (TOML syntax; lists may be comma-separated)
+        value: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum BackupAction {
+    /// Write a backup now, whether or not one is due
+    Now,
+
+    /// List existing backups, newest first
+    List,
+}
+
+#[derive(Subcommand)]
+enum DaemonAction {
+    /// Write the service files and start them
+    Install {
+        /// Print the service files instead of installing them
+        #[arg(long)]
+        print: bool,
+
+        /// Write the service files without starting them
+        #[arg(long)]
+        no_start: bool,
+    },
+
+    /// Stop the services and remove their files
+    Uninstall,
+}
+
+#[derive(Subcommand)]
+enum DbAction {
+    /// Check integrity, rebuild the search index, and reclaim unused space
+    Maintain,
+
+    /// Canonicalize the directories of existing records (resolve symlinks,
+    /// apply [paths.rewrites]), merging split histories
+    #[command(alias = "canonicalize")]
+    UnifyPaths,
+}
+
+/// Output theme, set from the config at startup
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
+
+/// Width listings are truncated to, set from the config (or `--full`) at startup
+static DISPLAY_WIDTH: OnceLock<DisplayWidth> = OnceLock::new();
+
+/// Time zone timestamps are shown in, set from the config at startup
+static DISPLAY_ZONE: OnceLock<DisplayZone> = OnceLock::new();
+
+/// A stored (UTC) timestamp in the display time zone
+fn display_time(time: &chrono::DateTime<chrono::Utc>, format: &str) -> String {
+    DISPLAY_ZONE
+        .get()
+        .copied()
+        .unwrap_or_default()
+        .format(time, format)
+}
+
+/// Columns taken by the timestamp and status before a command in compact
+/// listings ("2025-01-01 12:00:00 ✓ ")
+const COMPACT_PREFIX: usize = 22;
+
+/// Columns taken by the bracketed timestamp and status before a command in
+/// verbose listings ("[2025-01-01 12:00:00] ✓ ")
+const VERBOSE_PREFIX: usize = 24;
+
+/// A command on one line, truncated to fit the display width on a listing
+/// line that already has `used` columns of other text
+fn fit(command: &str, used: usize) -> String {
+    // Only listings ask the terminal for its width
+    static COLUMNS: OnceLock<Option<usize>> = OnceLock::new();
+    let columns =
+        COLUMNS.get_or_init(|| DISPLAY_WIDTH.get().copied().unwrap_or_default().columns());
+    omniscient::format::fit(command, columns.map(|columns| columns.saturating_sub(used)))
+}
+
+/// Number of columns `text` takes, leaving out color codes
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            'm' if in_escape => in_escape = false,
+            _ if in_escape => {}
+            _ => width += 1,
+        }
+    }
+    width
+}
+
+/// Styles of the output theme, by role
+trait Themed {
+    fn success(&self) -> colored::ColoredString;
+    fn failure(&self) -> colored::ColoredString;
+    fn warning(&self) -> colored::ColoredString;
+    fn metadata(&self) -> colored::ColoredString;
+    fn accent(&self) -> colored::ColoredString;
+}
+
+impl Themed for str {
+    fn success(&self) -> colored::ColoredString {
+        theme().success.paint(self)
+    }
+
+    fn failure(&self) -> colored::ColoredString {
+        theme().failure.paint(self)
+    }
+
+    fn warning(&self) -> colored::ColoredString {
+        theme().warning.paint(self)
+    }
+
+    fn metadata(&self) -> colored::ColoredString {
+        theme().metadata.paint(self)
+    }
+
+    fn accent(&self) -> colored::ColoredString {
+        theme().accent.paint(self)
+    }
+}
+
+/// Return a colored status symbol for a command record
+fn colorize_status(cmd: &omniscient::CommandRecord) -> colored::ColoredString {
+    if cmd.is_success() {
+        "✓".success()
+    } else {
+        "✗".failure()
+    }
+}
+
+/// Print a command with its metadata, executions and captured output
+fn print_detail(detail: &omniscient::CommandDetail, output_enabled: bool) {
+    let record = &detail.record;
+    let time = |t: &chrono::DateTime<chrono::Utc>| display_time(t, "%Y-%m-%d %H:%M:%S");
+
+    println!("{}\n", record.command.bold());
+    println!("  ID:          {}", record.id.unwrap_or_default());
+    if let Some(ref typed) = record.typed_command {
+        println!("  Typed as:    {}", typed);
+    }
+    println!("  Category:    {}", colorize_category(&record.category));
+    println!("  Directory:   {}", record.working_dir);
+    let meaning = omniscient::exit_code_meaning(record.exit_code)
+        .map(|m| format!(" ({})", m))
+        .unwrap_or_default();
+    println!(
+        "  Status:      {} exit {}{}",
+        colorize_status(record),
+        record.exit_code,
+        meaning
+    );
+    println!(
+        "  Duration:    {}",
+        omniscient::humanize_duration(record.duration_ms)
+    );
+    println!("  Usage:       {} times", record.usage_count);
+    println!("  First run:   {}", time(&record.timestamp));
+    println!("  Last run:    {}", time(&record.last_used));
+    if detail.bookmarked {
+        println!("  Bookmarked:  {}", "★".warning());
+    }
+    if let Some(ref note) = record.note {
+        println!("  Note:        {}", note);
+    }
+
+    if !detail.executions.is_empty() {
+        println!(
+            "\n{}",
+            format!(
+                "Executions (latest {} of {}):",
+                detail.executions.len(),
+                detail.execution_count
+            )
+            .bold()
+        );
+        for execution in &detail.executions {
+            let status = if execution.exit_code == 0 {
+                "✓".success()
+            } else {
+                "✗".failure()
+            };
+            let mut line = format!(
+                "  [{}] {} {:>6}",
+                time(&execution.timestamp).metadata(),
+                status,
+                omniscient::humanize_duration(execution.duration_ms)
+            );
+            if execution.exit_code != 0 {
+                line.push_str(&format!("  exit {}", execution.exit_code));
+            }
+            let meta = &execution.meta;
+            let tmux = meta.tmux_location();
+            for (label, value) in [
+                ("branch", &meta.branch),
+                ("host", &meta.host),
+                ("context", &meta.context),
+                ("session", &meta.session),
+                ("tmux", &tmux),
+            ] {
+                if let Some(value) = value {
+                    line.push_str(&format!("  {}: {}", label, value));
+                }
+            }
+            println!("{}", line);
+            if !meta.env.is_empty() {
+                let env: Vec<String> = meta
+                    .env
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect();
+                println!("      env: {}", env.join(" ").metadata());
+            }
+        }
+    }
+
+    match detail.output {
+        Some(ref output) => {
+            println!(
+                "\n{}",
+                format!("Output of the run at {}:", time(&output.timestamp)).bold()
+            );
+            if output.truncated {
+                println!("{}", "[... earlier output truncated]".metadata());
+            }
+            println!("{}", output.text);
+        }
+        None if output_enabled => println!("\nNo output captured"),
+        None => println!(
+            "\nNo output captured (enable it with: omniscient config set capture.output true)"
+        ),
+    }
+}
+
+/// Return a colored string for a category name
+fn colorize_category(category: &str) -> colored::ColoredString {
+    theme().category(category)
+}
+
+/// Highlight the first occurrence of `query` in `text` in the highlight style
+fn highlight_match(text: &str, query: &str) -> String {
+    let lower_text = text.to_lowercase();
+    let lower_query = query.to_lowercase();
+    match lower_text.find(&lower_query) {
+        Some(start) => {
+            let end = start + query.len();
+            let prefix = &text[..start];
+            let matched = &text[start..end];
+            let suffix = &text[end..];
+            format!("{}{}{}", prefix, theme().highlight.paint(matched), suffix)
+        }
+        None => text.to_string(),
+    }
+}
+
+/// One line of compact listing output: timestamp, status and command
+fn compact_line(cmd: &omniscient::CommandRecord, command: &str) -> String {
+    format!(
+        "{} {} {}",
+        display_time(&cmd.timestamp, "%Y-%m-%d %H:%M:%S").metadata(),
+        colorize_status(cmd),
+        command
+    )
+}
+
+/// Format a command for display, noting the alias the user typed if any,
+/// on a listing line that already has `used` columns of other text
+fn display_command(cmd: &omniscient::CommandRecord, used: usize) -> String {
+    match cmd.typed_command {
+        Some(ref typed) => {
+            let typed = format!("({})", one_line(typed));
+            let used = used + typed.chars().count() + 1;
+            format!("{} {}", fit(&cmd.command, used), typed.metadata())
+        }
+        None => fit(&cmd.command, used),
+    }
+}
+
+/// Write an automatic export if enabled and due, returning its path
+fn run_scheduled_export(config: &Config) -> Result<Option<std::path::PathBuf>> {
+    if !config.auto_export.enabled {
+        return Ok(None);
+    }
+
+    let schedule = omniscient::ExportSchedule::from_config(config)?;
+    let today = chrono::Local::now().date_naive();
+    if !schedule.is_due(today)? {
+        return Ok(None);
+    }
+
+    schedule.run_if_due(omniscient::Storage::from_config(config)?, today)
+}
+
+/// Write an automatic backup if enabled and due, returning its path
+fn run_scheduled_backup(config: &Config) -> Result<Option<std::path::PathBuf>> {
+    if !config.backup.enabled {
+        return Ok(None);
+    }
+
+    let schedule = omniscient::BackupSchedule::from_config(config)?;
+    let now = chrono::Local::now().naive_local();
+    if !schedule.is_due(now)? {
+        return Ok(None);
+    }
+
+    schedule.run_if_due(&omniscient::Storage::from_config(config)?, now)
+}
+
+/// Print an activity report: one bar per bucket and an hour-of-day heatmap
+fn print_activity(report: &omniscient::ActivityReport) {
+    let title = match report.period {
+        omniscient::ActivityPeriod::Day => "Daily",
+        omniscient::ActivityPeriod::Week => "Weekly",
+        omniscient::ActivityPeriod::Month => "Monthly",
+    };
+    println!("\n=== {} Activity ===\n", title);
+
+    if report.buckets.is_empty() {
+        println!("No commands in this time range.\n");
+        return;
+    }
+
+    let max = report
+        .buckets
+        .iter()
+        .map(|b| b.executions)
+        .max()
+        .unwrap_or(1);
+    for bucket in &report.buckets {
+        let width = (bucket.executions * 40).div_ceil(max);
+        let rate = format!("{:5.1}%", bucket.success_rate());
+        let rate = if bucket.successful == bucket.executions {
+            rate.success()
+        } else if bucket.success_rate() >= 80.0 {
+            rate.warning()
+        } else {
+            rate.failure()
+        };
+        println!(
+            "  {}  {:40} {:6}  {} ok",
+            bucket.start.format("%Y-%m-%d"),
+            "█".repeat(width).accent(),
+            bucket.executions,
+            rate
+        );
+    }
+    println!("\n  Total: {} commands", report.total());
+
+    // Shade each cell relative to the busiest one
+    const SHADES: &[char] = &[' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+    let busiest = report.heatmap.iter().flatten().copied().max().unwrap_or(0);
+
+    let zone = DISPLAY_ZONE.get().copied().unwrap_or_default();
+    println!("\nBusiest Hours ({} time):\n", zone);
+    println!("       0     3     6     9     12    15    18    21");
+    for (day, hours) in ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]
+        .iter()
+        .zip(&report.heatmap)
+    {
+        let row: String = hours
+            .iter()
+            .flat_map(|&count| {
+                let shade = SHADES[(count * (SHADES.len() - 1)).div_ceil(busiest.max(1))];
+                [shade, shade]
+            })
+            .collect();
+        println!("  {}  {}", day, row);
+    }
+
+    if let Some(hour) = report.busiest_hour() {
+        println!("\n  Busiest hour: {:02}:00-{:02}:00", hour, (hour + 1) % 24);
+    }
+    println!();
+}
+
+/// Print new commands per bucket as a bar chart with the vocabulary's size
+fn print_vocabulary(buckets: &[omniscient::VocabularyBucket], period: omniscient::ActivityPeriod) {
+    let unit = match period {
+        omniscient::ActivityPeriod::Day => "day",
+        omniscient::ActivityPeriod::Week => "week",
+        omniscient::ActivityPeriod::Month => "month",
+    };
+    println!("\n=== Vocabulary Growth (new commands per {}) ===\n", unit);
+
+    let learned: usize = buckets.iter().map(|b| b.new_commands).sum();
+    if learned == 0 {
+        println!("No new commands in this time range.\n");
+        return;
+    }
+
+    let max = buckets.iter().map(|b| b.new_commands).max().unwrap_or(1);
+    for bucket in buckets {
+        let width = (bucket.new_commands * 40).div_ceil(max);
+        println!(
+            "  {}  {:40} {:5}  {}",
+            bucket.start.format("%Y-%m-%d"),
+            "█".repeat(width).accent(),
+            bucket.new_commands,
+            format!("{} known", bucket.total).metadata()
+        );
+    }
+
+    println!(
+        "\n  {} new commands, {:.1} per {}, {}",
+        learned,
+        learned as f64 / buckets.len() as f64,
+        unit,
+        vocabulary_trend(buckets)
+    );
+    println!();
+}
+
+/// Whether more or fewer new commands were run per bucket in the second
+/// half of `buckets` than in the first
+fn vocabulary_trend(buckets: &[omniscient::VocabularyBucket]) -> &'static str {
+    let average = |buckets: &[omniscient::VocabularyBucket]| {
+        buckets.iter().map(|b| b.new_commands).sum::<usize>() as f64 / buckets.len().max(1) as f64
+    };
+    let (earlier, later) = buckets.split_at(buckets.len() / 2);
+    let (earlier, later) = (average(earlier), average(later));
+    if later == 0.0 {
+        "not growing lately"
+    } else if later > earlier * 1.25 {
+        "growing faster"
+    } else if later < earlier * 0.8 {
+        "growing slower"
+    } else {
+        "growing steadily"
+    }
+}
+
+/// Print a stored command and run it again in the current shell
+///
+/// Commands from destructive categories, or run through `sudo`/`doas`, are
+/// only printed unless `force` is set. Running always asks first unless `yes` is set, and commands with a
+/// redacted secret are never run. Exits with the command's status.
+fn rerun(
+    config: &Config,
+    storage: &omniscient::Storage,
+    cmd: &omniscient::CommandRecord,
+    yes: bool,
+    dry_run: bool,
+    force: bool,
+) -> Result<()> {
+    let id = cmd.id.unwrap_or_default();
+
+    // Gather the current context the same way capture does
+    let current_dir = resolve_directory(None, config)?;
+    let mut current = omniscient::CaptureRequest::new(&cmd.command, 0, 0)
+        .with_env_vars(&config.capture.env_vars)
+        .with_git(std::path::Path::new(&current_dir))
+        .meta;
+    let redactor = omniscient::RedactionEngine::new(
+        config.privacy.redact_patterns.clone(),
+        config.privacy.enabled,
+    )?;
+    for value in current.env.values_mut() {
+        if redactor.should_redact(value) {
+            *value = omniscient::redact::REDACTED.to_string();
+        }
+    }
+
+    let recorded = storage.get_latest_execution(id)?.map(|e| e.meta);
+    let differences = omniscient::compare_context(cmd, recorded.as_ref(), &current_dir, &current);
+
+    println!("{} {}", "$".metadata(), cmd.command.bold());
+    if dry_run {
+        return Ok(());
+    }
+
+    let destructive = config
+        .categories
+        .is_destructive(&cmd.command, &cmd.category);
+    if destructive && !force {
+        let kind = if omniscient::category::Categorizer::runs_privileged(&cmd.command) {
+            "sudo/doas".to_string()
+        } else {
+            format!("'{}'", cmd.category)
+        };
+        eprintln!(
+            "Not run: {} commands are only printed. Use 'omniscient run {} --force' to run it.",
+            kind, id
+        );
+        return Ok(());
+    }
+
+    // The stored text is not what was run
+    if cmd.command.contains(omniscient::redact::REDACTED) {
+        return Err(omniscient::OmniscientError::other(format!(
+            "Not run: command {} contains a redacted secret ({})",
+            id,
+            omniscient::redact::REDACTED
+        )));
+    }
+
+    for difference in &differences {
+        eprintln!("  {} {}", "⚠".warning(), difference);
+    }
+    let question = if differences.is_empty() {
+        "Run it? [y/N]: "
+    } else {
+        "Run anyway? [y/N]: "
+    };
+    if !yes && prompt(question)? != "y" {
+        eprintln!("Aborted");
+        return Ok(());
+    }
+
+    let shell = env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+    let status = std::process::Command::new(shell)
+        .arg("-c")
+        .arg(&cmd.command)
+        .status()?;
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+/// Get the `index`th (1-based) result, exiting if there is none
+fn pick_result(results: &[omniscient::CommandRecord], index: usize) -> &omniscient::CommandRecord {
+    let Some(cmd) = index.checked_sub(1).and_then(|i| results.get(i)) else {
+        eprintln!(
+            "✗ No result #{} ({} command(s) found)",
+            index,
+            results.len()
+        );
+        std::process::exit(1);
+    };
+    cmd
+}
+
+/// Copy the `index`th (1-based) result to the clipboard
+fn copy_result(results: &[omniscient::CommandRecord], index: usize) -> Result<()> {
+    let cmd = pick_result(results, index);
+    omniscient::clipboard::copy(&cmd.command)?;
+    eprintln!("{} Copied to clipboard: {}", "✓".success(), cmd.command);
+    Ok(())
+}
+
+/// Print the doctor's checks, exiting with 1 if any failed
+fn run_doctor(
+    config_path: std::path::PathBuf,
+    database: Option<String>,
+    shell: Option<&str>,
+) -> Result<()> {
+    use omniscient::doctor::{CheckStatus, Doctor};
+
+    let shell = match shell {
+        Some("zsh") => omniscient::ShellType::Zsh,
+        Some("bash") => omniscient::ShellType::Bash,
+        Some(other) => {
+            eprintln!(
+                "Error: Unsupported shell '{}'. Supported shells: zsh, bash",
+                other
+            );
+            std::process::exit(1);
+        }
+        None => omniscient::ShellHook::detect_shell()?,
+    };
+
+    let doctor = Doctor {
+        config_path,
+        database,
+        shell,
+        home: Config::home_dir()?,
+        session: env::var(omniscient::doctor::SESSION_ENV)
+            .ok()
+            .filter(|s| !s.is_empty()),
+    };
+    let checks = doctor.run();
+
+    println!("{}\n", "Omniscient doctor".bold());
+    for check in &checks {
+        let mark = match check.status {
+            CheckStatus::Ok => "✓".success(),
+            CheckStatus::Warning => "⚠".warning(),
+            CheckStatus::Error => "✗".failure(),
+        };
+        // Line up multi-line details (such as TOML parse errors) with the first
+        let detail = check
+            .detail
+            .trim_end()
+            .replace('\n', &format!("\n{:21}", ""));
+        println!("  {} {:<16} {}", mark, check.name, detail);
+        if let Some(fix) = &check.fix {
+            println!("    {} {}", "→".metadata(), fix);
+        }
+    }
+
+    let problems = |status| checks.iter().filter(|c| c.status == status).count();
+    let (errors, warnings) = (problems(CheckStatus::Error), problems(CheckStatus::Warning));
+    println!();
+    if errors + warnings == 0 {
+        println!("{} Everything looks good", "✓".success());
+    } else {
+        println!("{} error(s), {} warning(s)", errors, warnings);
+    }
+
+    if errors > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Answer JSON-RPC queries on a Unix socket until stopped
+#[cfg(unix)]
+fn serve_socket(config: &Config, socket: Option<std::path::PathBuf>) -> Result<()> {
+    // Every connection opens the database itself, so it must be a file
+    if config.storage.storage_type == omniscient::config::STORAGE_MEMORY {
+        return Err(omniscient::OmniscientError::config(
+            "--socket needs an on-disk database (storage.type = \"sqlite\")",
+        ));
+    }
+
+    // Create or upgrade the database before clients connect
+    omniscient::Storage::from_config(config)?;
+
+    let socket = match socket {
+        Some(path) => path,
+        None => omniscient::ipc::default_socket_path(config)?,
+    };
+    let server = omniscient::ipc::IpcServer::bind(&socket, &config.database_path()?)?;
+    println!("Listening on {} (Ctrl-C to stop)", socket.display());
+    server.run()
+}
+
+#[cfg(not(unix))]
+fn serve_socket(_config: &Config, _socket: Option<std::path::PathBuf>) -> Result<()> {
+    Err(omniscient::OmniscientError::other(
+        "--socket needs Unix domain sockets; use --api instead",
+    ))
+}
+
+/// Highlight redaction matches in `text` (overlapping matches are merged)
+fn highlight_matches(text: &str, matches: &[omniscient::RedactionMatch]) -> String {
+    let mut result = String::new();
+    let mut pos = 0;
+
+    for m in matches {
+        if m.end <= pos {
+            continue;
+        }
+        let start = m.start.max(pos);
+        result.push_str(&text[pos..start]);
+        result.push_str(&text[start..m.end].failure().bold().to_string());
+        pos = m.end;
+    }
+    result.push_str(&text[pos..]);
+
+    result
+}
+
+/// Read a line from stdin after printing `prompt`
+fn prompt(prompt: &str) -> Result<String> {
+    eprint!("{}", prompt);
+    std::io::Write::flush(&mut std::io::stderr())?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_lowercase())
+}
+
+/// Look up an import strategy by name, exiting on unknown names
+fn parse_strategy(name: &str) -> omniscient::ImportStrategy {
+    omniscient::ImportStrategy::from_name(name).unwrap_or_else(|| {
+        eprintln!(
+            "Error: Unsupported strategy '{}'. Supported strategies: skip, merge, preserve-higher, overwrite",
+            name
+        );
+        std::process::exit(1);
+    })
+}
+
+/// Format a byte count for human-readable display
+fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// Point installed services pinned to ~/.omniscient at the moved config
+/// and data directory
+fn repoint_services(data_dir: &std::path::Path, config_path: &std::path::Path) {
+    let Ok(manager) = omniscient::service::ServiceManager::detect() else {
+        return;
+    };
+    let (Ok(home), Ok(legacy)) = (Config::home_dir(), Config::legacy_dir()) else {
+        return;
+    };
+    let replacements = [
+        (
+            legacy.join("config.toml").to_string_lossy().into_owned(),
+            config_path.to_string_lossy().into_owned(),
+        ),
+        (
+            legacy.to_string_lossy().into_owned(),
+            data_dir.to_string_lossy().into_owned(),
+        ),
+    ];
+    match omniscient::service::repoint(manager, &home, &replacements) {
+        Ok(files) => {
+            for path in files {
+                eprintln!("  Updated {}", path.display());
+            }
+        }
+        Err(e) => eprintln!(
+            "{} Could not update the installed services: {}\n  Run 'omniscient daemon install' again.",
+            "Warning:".warning(),
+            e
+        ),
+    }
+}
+
+/// Resolve the directory to query (from --dir flag or current directory)
+///
+/// The directory is canonicalized like at capture (symlinks resolved, path
+/// rewrites applied) so aliases of a directory find its history.
+fn resolve_directory(dir_arg: Option<String>, config: &Config) -> Result<String> {
+    let dir = match dir_arg {
+        // Quoted glob patterns reach us with the tilde unexpanded
+        Some(path) => match Config::strip_tilde(&path) {
+            Some(rest) => Config::home_dir()?.join(rest).to_string_lossy().to_string(),
+            None => path,
+        },
+        None => env::current_dir()
+            .map(|p| p.to_string_lossy().to_string())
+            .map_err(omniscient::OmniscientError::Io)?,
+    };
+
+    Ok(omniscient::PathRewriter::from_config(config)?.canonicalize(&dir))
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{} {}", "Error:".failure().bold(), e);
+        if let Some(location) = e.location() {
+            eprintln!(
+                "  {}",
+                format!("(at {}:{})", location.file(), location.line()).metadata()
+            );
+        }
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let cli = Cli::parse();
+    if cli.no_color {
+        ColorMode::Never.apply();
+    }
+
+    // Move ~/.omniscient to the XDG directories, unless a path points into it.
+    // Never from the capture hook, which runs in every shell at once.
+    let pinned = cli.config.is_some()
+        || cli.db.is_some()
+        || env::var_os(omniscient::config::CONFIG_ENV).is_some()
+        || Config::database_from_env().is_some();
+    if !pinned && !matches!(cli.command, Commands::Capture { .. }) {
+        match Config::migrate_legacy_dir() {
+            Ok(Some(data_dir)) => {
+                let config_path = Config::config_path()?;
+                eprintln!(
+                    "{} Moved ~/.omniscient to {} (config: {})",
+                    "✓".success(),
+                    data_dir.display(),
+                    config_path.display()
+                );
+                repoint_services(&data_dir, &config_path);
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!(
+                "{} Could not move ~/.omniscient to the XDG directories, still using it: {}\n  \
+                 Set OMNISCIENT_HOME=~/.omniscient to keep it there.",
+                "Warning:".warning(),
+                e
+            ),
+        }
+    }
+
+    // Load configuration
+    let config_path = match cli.config {
+        Some(ref path) => path.clone(),
+        None => Config::config_path()?,
+    };
+    Config::check_not_moved(&config_path)?;
+
+    // Diagnose even a config that fails to load
+    if let Commands::Doctor { shell } = &cli.command {
+        return run_doctor(
+            config_path,
+            cli.db.clone().or_else(Config::database_from_env),
+            shell.as_deref(),
+        );
+    }
+
+    let mut config = Config::load_from(&config_path)?;
+
+    if let Some(db) = cli.db.clone().or_else(Config::database_from_env) {
+        config.storage.path = db;
+    }
+    Config::check_not_moved(&config.database_path()?)?;
+    if cli.backup_before_migrate {
+        config.storage.backup_before_migrate = true;
+    }
+
+    config.ensure_directories()?;
+
+    if !cli.no_color {
+        config.output.color.apply();
+    }
+    let _ = THEME.set(Theme::from_config(&config)?);
+    let _ = DISPLAY_WIDTH.set(if cli.full {
+        DisplayWidth::Full
+    } else {
+        config.display.width
+    });
+    let _ = DISPLAY_ZONE.set(config.display.timezone);
+
+    let use_pager = !cli.no_pager;
+
+    match cli.command {
+        Commands::Doctor { .. } => unreachable!("handled before the config is loaded"),
+        Commands::Init {
+            shell,
+            compat,
+            write,
+            uninstall,
+        } => {
+            use omniscient::ShellType;
+
+            // Determine shell type (manual or auto-detect)
+            let shell_type = if let Some(shell_name) = shell {
+                match shell_name.as_str() {
+                    "zsh" => ShellType::Zsh,
+                    "bash" => ShellType::Bash,
+                    _ => {
+                        eprintln!(
+                            "Error: Unsupported shell '{}'. Supported shells: zsh, bash",
+                            shell_name
+                        );
+                        if matches!(shell_name.as_str(), "powershell" | "pwsh" | "cmd") {
+                            eprintln!("Tip: {}", omniscient::shell::WINDOWS_SHELL_HINT);
+                        } else {
+                            eprintln!("Tip: Omit --shell flag to auto-detect your shell.");
+                        }
+                        std::process::exit(1);
+                    }
+                }
+            } else {
+                omniscient::ShellHook::detect_shell()?
+            };
+            let home = Config::home_dir()?;
+            let rc = omniscient::shell::rc_file(shell_type, &home);
+
+            if uninstall {
+                match omniscient::shell::remove_hook(&rc)? {
+                    RcChange::Removed => {
+                        println!("{} Removed the hook from {}", "✓".success(), rc.display());
+                        println!("  Open a new shell to stop capturing.");
+                    }
+                    _ => println!(
+                        "No hook added by 'omniscient init --write' in {}",
+                        rc.display()
+                    ),
+                }
+                if let Some((path, line)) = omniscient::lint::find_hook(shell_type, &home)? {
+                    eprintln!(
+                        "{} A hook remains at {}:{}; remove it by hand",
+                        "⚠".warning(),
+                        path.display(),
+                        line
+                    );
+                }
+                return Ok(());
+            }
+
+            let mut hook = omniscient::ShellHook::new(shell_type);
+            if compat {
+                hook = hook.with_compat_mode();
+            }
+            if config.capture.output {
+                hook = hook.with_output_capture();
+            }
+            if config.alerts.enabled && config.alerts.warn {
+                hook = hook.with_alert_warnings(&omniscient::alerts::warnings_path(&config)?);
+            }
+            if config.suggestions.on_cd {
+                hook = hook.with_dir_suggestions();
+            }
+            if write {
+                // A second hook would capture every command twice
+                if let Some((path, line)) =
+                    omniscient::lint::find_unmanaged_hook(shell_type, &home)?
+                {
+                    eprintln!(
+                        "Error: The hook is already set up at {}:{}",
+                        path.display(),
+                        line
+                    );
+                    eprintln!(
+                        "Remove that line first to let 'omniscient init --write' manage the hook."
+                    );
+                    std::process::exit(1);
+                }
+
+                let version = env!("CARGO_PKG_VERSION");
+                let change = hook.write_to(&rc)?;
+                match &change {
+                    RcChange::Unchanged => {
+                        println!(
+                            "{} The hook in {} is up to date",
+                            "✓".success(),
+                            rc.display()
+                        )
+                    }
+                    RcChange::Updated { from } => println!(
+                        "{} Updated the hook in {} (v{} → v{})",
+                        "✓".success(),
+                        rc.display(),
+                        from.as_deref().unwrap_or("?"),
+                        version
+                    ),
+                    _ => println!("{} Added the hook to {}", "✓".success(), rc.display()),
+                }
+                if change != RcChange::Unchanged {
+                    println!("  Reload your shell: source {}", rc.display());
+                }
+                if shell_type == ShellType::Bash
+                    && omniscient::lint::find_bash_preexec(&home)?.is_none()
+                {
+                    eprintln!(
+                        "{} bash-preexec is not loaded; the bash hook needs it (see 'omniscient init --shell bash')",
+                        "⚠".warning()
+                    );
+                }
+            } else {
+                println!("{}", hook.generate());
+                eprintln!("{}", hook.installation_instructions());
+            }
+
+            // Warn about other history tools whose hooks conflict with ours
+            let conflicts = omniscient::lint::scan(shell_type, &home)?;
+            if !conflicts.is_empty() {
+                eprintln!("{}", "Potential hook conflicts detected:".warning().bold());
+                for conflict in &conflicts {
+                    eprintln!("  {} {}", "⚠".warning(), conflict);
+                }
+                if !hook.is_compat_mode() {
+                    eprintln!(
+                        "\nTip: Run 'omniscient init --shell {} --compat' for a capture-only hook\n     that runs before other tools and binds no keys.",
+                        shell_type
+                    );
+                }
+            }
+
+            Ok(())
+        }
+        Commands::Capture {
+            exit_code,
+            duration,
+            expanded,
+            started_at,
+            host,
+            output_file,
+            capture_id,
+            command,
+        } => {
+            // Leave the work to a detached process so the caller returns at once
+            if config.capture.async_write
+                && env::var_os(omniscient::capture::FOREGROUND_ENV).is_none()
+            {
+                match omniscient::capture::spawn_detached() {
+                    Ok(()) => return Ok(()),
+                    Err(e) => eprintln!("omniscient: async capture failed, capturing now: {}", e),
+                }
+            }
+
+            // Commands excluded from output capture pass an empty path
+            let output_file = output_file
+                .filter(|path| !path.is_empty())
+                .map(std::path::PathBuf::from);
+
+            // Build the request from arguments and OMNISCIENT_* environment variables
+            let build_request = || -> Result<omniscient::CaptureRequest> {
+                let mut request = omniscient::CaptureRequest::new(&command, exit_code, duration)
+                    .with_env()
+                    .with_env_vars(&config.capture.env_vars)
+                    .with_tmux();
+                if let Ok(dir) = env::current_dir() {
+                    request = request.with_git(&dir);
+                }
+                request.expanded = expanded;
+                request.meta.host = host;
+                request.meta.capture_id = capture_id;
+                if let Some(ref value) = started_at {
+                    request.started_at = Some(omniscient::CaptureRequest::parse_started_at(value)?);
+                }
+                if let Some(ref path) = output_file {
+                    if config.capture.output {
+                        request =
+                            request.with_output_file(path, config.capture.output_max_kb * 1024);
+                    }
+                }
+                Ok(request.with_capture_id())
+            };
+
+            // Capture the command (errors are only reported, to not break the shell)
+            match build_request() {
+                Ok(request) => {
+                    let stored =
+                        omniscient::CommandCapture::new(config.clone()).and_then(|capture| {
+                            capture.capture_request(request.clone())?;
+                            Ok(capture)
+                        });
+                    match stored {
+                        // The database works again: store what it missed
+                        Ok(capture) => {
+                            let replayed = omniscient::CaptureJournal::from_config(&config)
+                                .and_then(|journal| capture.replay_journal(&journal));
+                            if let Err(e) = replayed {
+                                eprintln!("omniscient: journal replay error: {}", e);
+                            }
+                        }
+                        // Keep the command until the database can take it
+                        Err(e) if e.is_storage() => {
+                            let spilled = omniscient::CaptureJournal::from_config(&config)
+                                .and_then(|journal| journal.append(&request));
+                            if let Err(spill_error) = spilled {
+                                eprintln!("omniscient: capture error: {}", e);
+                                eprintln!("omniscient: capture journal error: {}", spill_error);
+                            }
+                        }
+                        Err(e) => eprintln!("omniscient: capture error: {}", e),
+                    }
+                }
+                Err(e) => eprintln!("omniscient: capture error: {}", e),
+            }
+
+            // The hook hands over a fresh temporary file per command
+            if let Some(ref path) = output_file {
+                let _ = std::fs::remove_file(path);
+            }
+
+            // The hook runs in the background, so this is a cheap place to keep
+            // automatic exports current
+            if let Err(e) = run_scheduled_export(&config) {
+                eprintln!("omniscient: automatic export error: {}", e);
+            }
+            if let Err(e) = run_scheduled_backup(&config) {
+                eprintln!("omniscient: automatic backup error: {}", e);
+            }
+
+            Ok(())
+        }
+        Commands::Search {
+            query,
+            limit,
+            dir,
+            recursive,
+            env_filters,
+            branch,
+            host,
+            tmux_session,
+            tmux_pane,
+            prefix,
+            ignore_case,
+            exclude,
+            exclude_category,
+            min_duration,
+            max_duration,
+            exit_code,
+            program,
+            flag,
+            exec,
+            copy,
+            yes,
+            paging,
+            formatting,
+        } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+            let query = query.unwrap_or_default();
+
+            let working_dir = if dir.is_some() {
+                Some(resolve_directory(dir, &config)?)
+            } else {
+                None
+            };
+
+            let mut env_vars = Vec::new();
+            for filter in &env_filters {
+                match filter.split_once('=') {
+                    Some((name, value)) if !name.is_empty() => {
+                        env_vars.push((name.to_string(), value.to_string()))
+                    }
+                    _ => {
+                        eprintln!(
+                            "Error: Invalid --env filter '{}'. Expected NAME=VALUE",
+                            filter
+                        );
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            let search_query = omniscient::SearchQuery {
+                text: (!query.is_empty()).then(|| query.clone()),
+                category: None,
+                success_only: None,
+                working_dir,
+                recursive,
+                env: env_vars,
+                branch,
+                host,
+                tmux_session,
+                tmux_pane,
+                prefix,
+                ignore_case,
+                exclude,
+                exclude_categories: exclude_category,
+                min_duration_ms: min_duration
+                    .as_deref()
+                    .map(omniscient::parse_duration)
+                    .transpose()?,
+                max_duration_ms: max_duration
+                    .as_deref()
+                    .map(omniscient::parse_duration)
+                    .transpose()?,
+                exit_code,
+                program,
+                flags: flag,
+                limit,
+                offset: paging.offset(limit),
+                order_by: omniscient::OrderBy::Relevance,
+            };
+
+            let results = storage.search(&search_query)?;
+
+            if results.is_empty() {
+                println!("No commands found matching '{}'", query);
+                return Ok(());
+            }
+
+            if let Some(index) = exec {
+                let cmd = pick_result(&results, index);
+                return rerun(&config, &storage, cmd, yes, false, false);
+            }
+            if let Some(index) = copy {
+                copy_result(&results, index)?;
+            }
+
+            let layout = formatting.layout(&config);
+            let mut out = omniscient::Pager::new(use_pager);
+            if let Layout::Verbose = layout {
+                writeln!(out, "\nFound {} matching command(s):\n", results.len())?;
+            }
+            for cmd in results {
+                match layout {
+                    Layout::Compact => {
+                        let command = highlight_match(&fit(&cmd.command, COMPACT_PREFIX), &query);
+                        writeln!(out, "{}", compact_line(&cmd, &command))?;
+                        continue;
+                    }
+                    Layout::Template(ref template) => {
+                        writeln!(out, "{}", template.render(&cmd))?;
+                        continue;
+                    }
+                    Layout::Verbose => {}
+                }
+                writeln!(
+                    out,
+                    "[{}] {} {}",
+                    display_time(&cmd.timestamp, "%Y-%m-%d %H:%M:%S").metadata(),
+                    colorize_status(&cmd),
+                    highlight_match(&fit(&cmd.command, VERBOSE_PREFIX), &query)
+                )?;
+                writeln!(
+                    out,
+                    "  ID: {} | Category: {} | Duration: {} | Usage: {} times | Dir: {}",
+                    cmd.id.unwrap_or_default(),
+                    colorize_category(&cmd.category),
+                    cmd.duration_display(),
+                    cmd.usage_count,
+                    cmd.working_dir.metadata()
+                )?;
+                let env = storage.get_latest_env(cmd.id.unwrap_or_default())?;
+                if !env.is_empty() {
+                    let pairs: Vec<String> =
+                        env.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                    writeln!(out, "  Env: {}", pairs.join(" ").metadata())?;
+                }
+                if let Some(ref note) = cmd.note {
+                    writeln!(out, "  Note: {}", note.italic())?;
+                }
+                writeln!(out)?;
+            }
+
+            out.finish()?;
+
+            Ok(())
+        }
+        Commands::Here {
+            recursive,
+            dir,
+            host,
+            limit,
+            paging,
+            formatting,
+        } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+            let mut working_dir = resolve_directory(dir, &config)?;
+            if let Some(ref host) = host {
+                working_dir = omniscient::remote::remote_dir(host, &working_dir);
+            }
+            let working_dir = Some(working_dir);
+
+            let results = storage.search(&omniscient::SearchQuery {
+                working_dir: working_dir.clone(),
+                recursive,
+                limit,
+                offset: paging.offset(limit),
+                order_by: omniscient::OrderBy::Timestamp,
+                ..Default::default()
+            })?;
+
+            if results.is_empty() {
+                println!("No commands in history for this directory.");
+                return Ok(());
+            }
+
+            // Display header with context
+            let dir_display = working_dir.as_ref().unwrap();
+            let mode = if recursive {
+                "(recursive)"
+            } else {
+                "(exact match)"
+            };
+            let layout = formatting.layout(&config);
+            let mut out = omniscient::Pager::new(use_pager);
+            if let Layout::Verbose = layout {
+                writeln!(out, "\nShowing commands in: {} {}\n", dir_display, mode)?;
+                writeln!(out, "Found {} command(s):\n", results.len())?;
+            }
+
+            // Reuse display format from Recent command
+            for cmd in results {
+                match layout {
+                    Layout::Compact => {
+                        writeln!(
+                            out,
+                            "{}",
+                            compact_line(&cmd, &fit(&cmd.command, COMPACT_PREFIX))
+                        )?;
+                        continue;
+                    }
+                    Layout::Template(ref template) => {
+                        writeln!(out, "{}", template.render(&cmd))?;
+                        continue;
+                    }
+                    Layout::Verbose => {}
+                }
+                writeln!(
+                    out,
+                    "[{}] {} {}",
+                    display_time(&cmd.timestamp, "%Y-%m-%d %H:%M:%S").metadata(),
+                    colorize_status(&cmd),
+                    display_command(&cmd, VERBOSE_PREFIX)
+                )?;
+                writeln!(
+                    out,
+                    "  ID: {} | Dir: {} | Category: {} | Duration: {} | Usage: {} times",
+                    cmd.id.unwrap_or_default(),
+                    cmd.working_dir.metadata(),
+                    colorize_category(&cmd.category),
+                    cmd.duration_display(),
+                    cmd.usage_count
+                )?;
+                writeln!(out)?;
+            }
+
+            out.finish()?;
+
+            Ok(())
+        }
+        Commands::OnCd { dir, count } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+            let working_dir = resolve_directory(dir, &config)?;
+            let count = count.unwrap_or(config.suggestions.count);
+
+            let top = storage.top_for_dir(&working_dir, count)?;
+            if top.is_empty() {
+                return Ok(());
+            }
+
+            println!("{}", "Frequent here:".metadata());
+            for (index, cmd) in top.iter().enumerate() {
+                let number = format!("{}.", index + 1);
+                println!(
+                    "  {} {}",
+                    number.metadata(),
+                    fit(&cmd.command, number.len() + 3)
+                );
+            }
+
+            Ok(())
+        }
+        Commands::Recent {
+            n,
+            dir,
+            recursive,
+            host,
+            tmux_session,
+            tmux_pane,
+            min_duration,
+            max_duration,
+            copy,
+            paging,
+            formatting,
+        } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            let working_dir = if dir.is_some() {
+                Some(resolve_directory(dir, &config)?)
+            } else {
+                None
+            };
+
+            let results = storage.search(&omniscient::SearchQuery {
+                working_dir,
+                recursive,
+                host,
+                tmux_session,
+                tmux_pane,
+                min_duration_ms: min_duration
+                    .as_deref()
+                    .map(omniscient::parse_duration)
+                    .transpose()?,
+                max_duration_ms: max_duration
+                    .as_deref()
+                    .map(omniscient::parse_duration)
+                    .transpose()?,
+                limit: n,
+                offset: paging.offset(n),
+                order_by: omniscient::OrderBy::Timestamp,
+                ..Default::default()
+            })?;
+
+            if results.is_empty() {
+                println!("No commands in history yet.");
+                return Ok(());
+            }
+
+            if let Some(index) = copy {
+                copy_result(&results, index)?;
+            }
+
+            let layout = formatting.layout(&config);
+            let mut out = omniscient::Pager::new(use_pager);
+            if let Layout::Verbose = layout {
+                writeln!(out, "\nMost recent {} command(s):\n", results.len())?;
+            }
+            for cmd in results {
+                match layout {
+                    Layout::Compact => {
+                        writeln!(
+                            out,
+                            "{}",
+                            compact_line(&cmd, &fit(&cmd.command, COMPACT_PREFIX))
+                        )?;
+                        continue;
+                    }
+                    Layout::Template(ref template) => {
+                        writeln!(out, "{}", template.render(&cmd))?;
+                        continue;
+                    }
+                    Layout::Verbose => {}
+                }
+                writeln!(
+                    out,
+                    "[{}] {} {}",
+                    display_time(&cmd.timestamp, "%Y-%m-%d %H:%M:%S").metadata(),
+                    colorize_status(&cmd),
+                    display_command(&cmd, VERBOSE_PREFIX)
+                )?;
+                writeln!(
+                    out,
+                    "  ID: {} | Category: {} | Duration: {} | Usage: {} times",
+                    cmd.id.unwrap_or_default(),
+                    colorize_category(&cmd.category),
                     cmd.duration_display(),
-                    cmd.usage_count,
-                    cmd.working_dir.dimmed()
+                    cmd.usage_count
+                )?;
+                writeln!(out)?;
+            }
+
+            out.finish()?;
+
+            Ok(())
+        }
+        Commands::Top {
+            n,
+            dir,
+            recursive,
+            copy,
+            paging,
+            formatting,
+        } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            let working_dir = if dir.is_some() {
+                Some(resolve_directory(dir, &config)?)
+            } else {
+                None
+            };
+
+            let offset = paging.offset(n);
+            let results = storage.search(&omniscient::SearchQuery {
+                working_dir,
+                recursive,
+                limit: n,
+                offset,
+                order_by: omniscient::OrderBy::UsageCount,
+                ..Default::default()
+            })?;
+
+            if results.is_empty() {
+                println!("No commands in history yet.");
+                return Ok(());
+            }
+
+            if let Some(index) = copy {
+                copy_result(&results, index)?;
+            }
+
+            let layout = formatting.layout(&config);
+            let mut out = omniscient::Pager::new(use_pager);
+            if let Layout::Verbose = layout {
+                writeln!(
+                    out,
+                    "\nTop {} most frequently used command(s):\n",
+                    results.len()
+                )?;
+            }
+            for (index, cmd) in results.iter().enumerate() {
+                match layout {
+                    Layout::Compact => {
+                        // Last use instead of first, and the usage count
+                        writeln!(
+                            out,
+                            "{} {} {} {}",
+                            display_time(&cmd.last_used, "%Y-%m-%d %H:%M:%S").metadata(),
+                            colorize_status(cmd),
+                            cmd.usage_count,
+                            fit(
+                                &cmd.command,
+                                COMPACT_PREFIX + cmd.usage_count.to_string().len() + 1
+                            )
+                        )?;
+                        continue;
+                    }
+                    Layout::Template(ref template) => {
+                        writeln!(out, "{}", template.render(cmd))?;
+                        continue;
+                    }
+                    Layout::Verbose => {}
+                }
+                writeln!(
+                    out,
+                    "{}. {} (used {} times)",
+                    offset + index + 1,
+                    fit(
+                        &cmd.command,
+                        visible_width(&format!(
+                            "{}.  (used {} times)",
+                            offset + index + 1,
+                            cmd.usage_count
+                        ))
+                    ),
+                    cmd.usage_count.to_string().bold()
+                )?;
+                writeln!(
+                    out,
+                    "   ID: {} | Category: {} | Last used: {} | Avg duration: {}",
+                    cmd.id.unwrap_or_default(),
+                    colorize_category(&cmd.category),
+                    display_time(&cmd.last_used, "%Y-%m-%d %H:%M:%S").metadata(),
+                    cmd.duration_display()
+                )?;
+                writeln!(out)?;
+            }
+
+            out.finish()?;
+
+            Ok(())
+        }
+        Commands::Fzf {
+            limit,
+            dir,
+            recursive,
+            select,
+        } => {
+            use std::io::{BufRead, Write};
+
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            if select {
+                for line in std::io::stdin().lock().lines() {
+                    let Some(selection) = omniscient::fzf::parse_selection(&line?) else {
+                        continue;
+                    };
+                    let stored = match selection.id {
+                        Some(id) => storage.get_by_id(id)?.map(|cmd| cmd.command),
+                        None => None,
+                    };
+                    println!("{}", stored.unwrap_or(selection.command));
+                }
+                return Ok(());
+            }
+
+            let working_dir = if dir.is_some() {
+                Some(resolve_directory(dir, &config)?)
+            } else {
+                None
+            };
+
+            let now = chrono::Utc::now();
+            let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+            let query = omniscient::SearchQuery {
+                working_dir,
+                recursive,
+                limit,
+                order_by: omniscient::OrderBy::LastUsed,
+                ..Default::default()
+            };
+            for cmd in storage.search(&query)? {
+                let written = writeln!(out, "{}", omniscient::fzf::format_line(&cmd, now));
+                // fzf closes the pipe once a selection is made
+                if matches!(&written, Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe) {
+                    return Ok(());
+                }
+                written?;
+            }
+            match out.flush() {
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+                result => Ok(result?),
+            }
+        }
+        Commands::Slow {
+            n,
+            by,
+            min_runs,
+            dir,
+            recursive,
+        } => {
+            let by = match by.as_str() {
+                "avg" | "average" => omniscient::SlowestBy::Average,
+                "max" => omniscient::SlowestBy::Max,
+                _ => {
+                    eprintln!("Error: Unsupported ranking '{}'. Supported: avg, max", by);
+                    std::process::exit(1);
+                }
+            };
+
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            let working_dir = if dir.is_some() {
+                Some(resolve_directory(dir, &config)?)
+            } else {
+                None
+            };
+
+            let results = storage.get_slowest(by, n, min_runs, working_dir, recursive)?;
+
+            if results.is_empty() {
+                println!("No commands in history yet.");
+                return Ok(());
+            }
+
+            println!("\nTop {} slowest command(s):\n", results.len());
+            for (index, stats) in results.iter().enumerate() {
+                let (avg, max) = (
+                    omniscient::humanize_duration(stats.avg_ms),
+                    omniscient::humanize_duration(stats.max_ms),
+                );
+                let (avg, max) = match by {
+                    omniscient::SlowestBy::Average => (avg.bold(), max.normal()),
+                    omniscient::SlowestBy::Max => (avg.normal(), max.bold()),
+                };
+                let used = visible_width(&format!("{}.  (avg {}, max {})", index + 1, avg, max));
+                println!(
+                    "{}. {} (avg {}, max {})",
+                    index + 1,
+                    fit(&stats.command, used),
+                    avg,
+                    max
+                );
+                println!(
+                    "   ID: {} | Runs: {} | Min: {} | {}",
+                    stats.command_id,
+                    stats.runs,
+                    omniscient::humanize_duration(stats.min_ms),
+                    stats.working_dir.metadata()
+                );
+                println!();
+            }
+
+            Ok(())
+        }
+        Commands::Dirs {
+            n,
+            dir,
+            recursive,
+            since,
+        } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            let working_dir = if dir.is_some() {
+                Some(resolve_directory(dir, &config)?)
+            } else {
+                None
+            };
+
+            let since = since
+                .as_deref()
+                .map(|value| omniscient::parse_time(value, chrono::Utc::now()))
+                .transpose()?;
+
+            let stats = storage.get_dir_stats(n, 3, working_dir, recursive, since, None)?;
+            if stats.is_empty() {
+                println!("No commands in history.");
+                return Ok(());
+            }
+
+            println!("\n=== Most Active Directories ===\n");
+            for (index, dir) in stats.iter().enumerate() {
+                println!("{}. {}", index + 1, dir.working_dir.bold());
+                println!(
+                    "   {} runs | {} commands | {} success | Last used: {}",
+                    dir.runs,
+                    dir.commands,
+                    format!("{:.0}%", dir.success_rate()).success(),
+                    display_time(&dir.last_used, "%Y-%m-%d %H:%M:%S").metadata()
+                );
+                let categories: Vec<String> = dir
+                    .top_categories
+                    .iter()
+                    .map(|c| format!("{} ({})", colorize_category(&c.category), c.count))
+                    .collect();
+                println!("   {}", categories.join(", "));
+            }
+
+            Ok(())
+        }
+        Commands::Failures {
+            n,
+            min_runs,
+            dir,
+            recursive,
+        } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            let working_dir = if dir.is_some() {
+                Some(resolve_directory(dir, &config)?)
+            } else {
+                None
+            };
+
+            let rates = storage.get_failure_rates(n, min_runs, working_dir.clone(), recursive)?;
+            let recent = storage.get_recent_failures(n, working_dir.clone(), recursive)?;
+            let codes = storage.get_exit_code_counts(working_dir, recursive)?;
+
+            if recent.is_empty() {
+                println!("No failed commands in history.");
+                return Ok(());
+            }
+
+            println!("\n=== Highest Failure Rates ===\n");
+            if rates.is_empty() {
+                println!(
+                    "No command has failed and been run at least {} times.",
+                    min_runs
+                );
+            }
+            for (index, stats) in rates.iter().enumerate() {
+                let rate = format!("{:.0}%", stats.failure_rate());
+                let used = visible_width(&format!(
+                    "{}.  ({} of {} runs failed, {})",
+                    index + 1,
+                    stats.failures,
+                    stats.runs,
+                    rate
+                ));
+                println!(
+                    "{}. {} ({} of {} runs failed, {})",
+                    index + 1,
+                    fit(&stats.command, used),
+                    stats.failures,
+                    stats.runs,
+                    rate.failure().bold()
+                );
+                println!(
+                    "   ID: {} | Last failed: {} | {}",
+                    stats.command_id,
+                    stats
+                        .last_failed
+                        .map(|t| display_time(&t, "%Y-%m-%d %H:%M:%S"))
+                        .unwrap_or_default()
+                        .metadata(),
+                    stats.working_dir.metadata()
+                );
+            }
+
+            println!("\n=== Recent Failures ===\n");
+            for run in &recent {
+                let (code, id) = (
+                    format!("[{}]", run.exit_code),
+                    format!("(ID: {})", run.command_id),
+                );
+                let used = VERBOSE_PREFIX + code.len() + id.len();
+                println!(
+                    "[{}] {} {} {}",
+                    display_time(&run.timestamp, "%Y-%m-%d %H:%M:%S"),
+                    code.failure(),
+                    fit(&run.command, used),
+                    id.metadata()
+                );
+            }
+
+            println!("\n=== Exit Codes ===\n");
+            let total: usize = codes.iter().map(|c| c.count).sum();
+            for code in &codes {
+                println!(
+                    "  {:>4}  {:6} ({:.1}%)  {}",
+                    code.exit_code,
+                    code.count,
+                    code.count as f64 / total as f64 * 100.0,
+                    omniscient::exit_code_meaning(code.exit_code)
+                        .unwrap_or("")
+                        .metadata()
+                );
+            }
+
+            println!();
+            Ok(())
+        }
+        Commands::Category {
+            name,
+            limit,
+            dir,
+            recursive,
+            group_by,
+            since,
+        } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            let working_dir = if dir.is_some() {
+                Some(resolve_directory(dir, &config)?)
+            } else {
+                None
+            };
+
+            if let Some(group_by) = group_by {
+                if !matches!(group_by.as_str(), "dir" | "directory") {
+                    eprintln!(
+                        "Error: Unsupported grouping '{}'. Supported groupings: dir",
+                        group_by
+                    );
+                    std::process::exit(1);
+                }
+                let since = since
+                    .as_deref()
+                    .map(|value| omniscient::parse_time(value, chrono::Utc::now()))
+                    .transpose()?;
+
+                let stats =
+                    storage.get_dir_stats(limit, 0, working_dir, recursive, since, Some(&name))?;
+                if stats.is_empty() {
+                    println!("No commands found in category '{}'", name);
+                    return Ok(());
+                }
+
+                println!("\n=== Where '{}' Commands Run ===\n", name);
+                for (index, dir) in stats.iter().enumerate() {
+                    println!("{}. {}", index + 1, dir.working_dir.bold());
+                    println!(
+                        "   {} runs | {} commands | {} success | {} total | Last used: {}",
+                        dir.runs,
+                        dir.commands,
+                        format!("{:.0}%", dir.success_rate()).success(),
+                        omniscient::humanize_duration(dir.duration_ms),
+                        display_time(&dir.last_used, "%Y-%m-%d %H:%M:%S").metadata()
+                    );
+                }
+                println!();
+                return Ok(());
+            }
+
+            let results = storage.get_by_category(&name, limit, working_dir, recursive)?;
+
+            if results.is_empty() {
+                println!("No commands found in category '{}'", name);
+                return Ok(());
+            }
+
+            println!(
+                "\nCommands in category '{}' ({} found):\n",
+                name,
+                results.len()
+            );
+            for cmd in results {
+                println!(
+                    "[{}] {} {}",
+                    display_time(&cmd.last_used, "%Y-%m-%d %H:%M:%S").metadata(),
+                    colorize_status(&cmd),
+                    display_command(&cmd, VERBOSE_PREFIX)
+                );
+                println!(
+                    "  ID: {} | Used {} times | Duration: {} | Dir: {}",
+                    cmd.id.unwrap_or_default(),
+                    cmd.usage_count.to_string().bold(),
+                    cmd.duration_display(),
+                    cmd.working_dir.metadata()
+                );
+                println!();
+            }
+
+            Ok(())
+        }
+        Commands::Workflows {
+            n,
+            dir,
+            recursive,
+            min_count,
+        } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            let working_dir = if dir.is_some() {
+                Some(resolve_directory(dir, &config)?)
+            } else {
+                None
+            };
+
+            let executions = storage.get_executions(working_dir, recursive)?;
+            let detector = omniscient::WorkflowDetector::new(30, min_count);
+            let workflows = detector.detect(&executions, n);
+
+            if workflows.is_empty() {
+                println!("No repeated command sequences found yet.");
+                return Ok(());
+            }
+
+            let mut current_dir: Option<&str> = None;
+            let mut index = 0;
+            for workflow in &workflows {
+                if current_dir != Some(workflow.working_dir.as_str()) {
+                    println!("\n{}\n", workflow.working_dir.bold());
+                    current_dir = Some(workflow.working_dir.as_str());
+                    index = 0;
+                }
+                index += 1;
+                println!(
+                    "{}. {} (repeated {} times)",
+                    index,
+                    workflow.display_steps(),
+                    workflow.occurrences.to_string().bold()
+                );
+            }
+
+            println!();
+            Ok(())
+        }
+        Commands::Aliases {
+            action:
+                AliasesAction::Suggest {
+                    shell,
+                    min_length,
+                    min_uses,
+                    limit,
+                },
+        } => {
+            use omniscient::AliasSyntax;
+
+            let syntax = match shell {
+                Some(name) => AliasSyntax::from_name(&name).unwrap_or_else(|| {
+                    eprintln!(
+                        "Error: Unsupported shell '{}'. Supported shells: zsh, bash, fish",
+                        name
+                    );
+                    std::process::exit(1);
+                }),
+                None => AliasSyntax::detect(),
+            };
+            let min_length = min_length.unwrap_or(config.aliases.min_length);
+            let min_uses = min_uses.unwrap_or(config.aliases.min_uses);
+
+            let storage = omniscient::Storage::from_config(&config)?;
+            let candidates = storage.get_alias_candidates(min_length, min_uses)?;
+
+            // Don't shadow programs on the PATH
+            let mut taken = std::collections::HashSet::new();
+            if let Some(path) = std::env::var_os("PATH") {
+                for dir in std::env::split_paths(&path) {
+                    let Ok(entries) = std::fs::read_dir(dir) else {
+                        continue;
+                    };
+                    taken.extend(
+                        entries
+                            .flatten()
+                            .map(|entry| entry.file_name().to_string_lossy().to_string()),
+                    );
+                }
+            }
+
+            let suggestions = omniscient::aliases::suggest(&candidates, &taken, limit);
+            if suggestions.is_empty() {
+                eprintln!(
+                    "No commands of {}+ characters run {}+ times without an alias yet.",
+                    min_length, min_uses
+                );
+                return Ok(());
+            }
+
+            println!("# Aliases suggested by omniscient ({})", syntax);
+            for suggestion in &suggestions {
+                println!(
+                    "{}  {}",
+                    syntax.define(&suggestion.name, &suggestion.command),
+                    format!(
+                        "# {} runs, saves {} characters",
+                        suggestion.uses, suggestion.saved
+                    )
+                    .metadata()
                 );
-                println!();
             }
 
             Ok(())
         }
-        Commands::Here {
-            recursive,
-            dir,
-            limit,
+        Commands::Typos {
+            n,
+            window,
+            max_distance,
         } => {
-            let storage = omniscient::Storage::new(&config.database_path()?)?;
-            let working_dir = Some(resolve_directory(dir)?);
+            let storage = omniscient::Storage::from_config(&config)?;
 
-            let results = storage.get_recent(limit, working_dir.clone(), recursive)?;
+            let executions = storage.get_executions(None, false)?;
+            let detector = omniscient::TypoDetector::new(window, max_distance);
+            let typos = detector.detect(&executions, n);
 
-            if results.is_empty() {
-                println!("No commands in history for this directory.");
+            if typos.is_empty() {
+                println!("No corrected typos found yet.");
                 return Ok(());
             }
 
-            // Display header with context
-            let dir_display = working_dir.as_ref().unwrap();
-            let mode = if recursive {
-                "(recursive)"
+            println!("\n{}\n", "Most common typos".bold());
+            for (index, typo) in typos.iter().enumerate() {
+                println!(
+                    "{}. {} → {} ({} times, last {})",
+                    index + 1,
+                    typo.typed.failure(),
+                    typo.corrected.success(),
+                    typo.occurrences.to_string().bold(),
+                    display_time(&typo.last_seen, "%Y-%m-%d %H:%M").metadata()
+                );
+                if let Some(alias) = typo.alias() {
+                    println!("   {} {}", "suggestion:".metadata(), alias.accent());
+                }
+            }
+
+            println!();
+            Ok(())
+        }
+        Commands::Bookmark { action } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            match action {
+                BookmarkAction::Add { id } => {
+                    if storage.add_bookmark(id)? {
+                        println!("★ Bookmarked command {}", id);
+                    } else {
+                        eprintln!("✗ No command with ID {}", id);
+                        std::process::exit(1);
+                    }
+                }
+                BookmarkAction::Remove { id } => {
+                    if storage.remove_bookmark(id)? {
+                        println!("Removed bookmark for command {}", id);
+                    } else if storage.get_by_id(id)?.is_none() {
+                        eprintln!("✗ No command with ID {}", id);
+                        std::process::exit(1);
+                    } else {
+                        eprintln!("✗ Command {} is not bookmarked", id);
+                    }
+                }
+                BookmarkAction::List { limit, paging } => {
+                    let results = storage.get_bookmarks(limit, paging.offset(limit))?;
+
+                    if results.is_empty() {
+                        println!("No bookmarked commands yet.");
+                        return Ok(());
+                    }
+
+                    let mut out = omniscient::Pager::new(use_pager);
+                    writeln!(out, "\nBookmarked command(s) ({} found):\n", results.len())?;
+                    for cmd in results {
+                        writeln!(out, "{} {}", "★".warning(), display_command(&cmd, 2))?;
+                        writeln!(
+                            out,
+                            "  ID: {} | Category: {} | Usage: {} times | Dir: {}",
+                            cmd.id.unwrap_or_default(),
+                            colorize_category(&cmd.category),
+                            cmd.usage_count,
+                            cmd.working_dir.metadata()
+                        )?;
+                        writeln!(out)?;
+                    }
+                    out.finish()?;
+                }
+            }
+
+            Ok(())
+        }
+        Commands::Note { id, text, clear } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            let record = match storage.get_by_id(id)? {
+                Some(record) => record,
+                None => {
+                    eprintln!("✗ No command with ID {}", id);
+                    return Ok(());
+                }
+            };
+
+            if clear {
+                storage.set_note(id, None)?;
+                println!("Removed note from command {}", id);
+            } else if let Some(text) = text {
+                storage.set_note(id, Some(&text))?;
+                println!("✓ Note saved for: {}", record.command);
             } else {
-                "(exact match)"
+                match record.note {
+                    Some(note) => println!("{}\n  Note: {}", record.command, note),
+                    None => println!("No note for: {}", record.command),
+                }
+            }
+
+            Ok(())
+        }
+        Commands::Show { id, limit, json } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            let detail = match storage.get_detail(id, limit)? {
+                Some(detail) => detail,
+                None => {
+                    eprintln!("✗ No command with ID {}", id);
+                    std::process::exit(1);
+                }
             };
-            println!("\nShowing commands in: {} {}\n", dir_display, mode);
-            println!("Found {} command(s):\n", results.len());
 
-            // Reuse display format from Recent command
-            for cmd in results {
-                println!(
-                    "[{}] {} {}",
-                    cmd.timestamp
-                        .format("%Y-%m-%d %H:%M:%S")
-                        .to_string()
-                        .dimmed(),
-                    colorize_status(&cmd),
-                    cmd.command
+            if json {
+                println!("{}", serde_json::to_string_pretty(&detail)?);
+                return Ok(());
+            }
+            print_detail(&detail, config.capture.output);
+
+            Ok(())
+        }
+        Commands::History { command, limit } => {
+            use omniscient::history::{sparkline, HistorySummary};
+
+            let storage = omniscient::Storage::from_config(&config)?;
+            let executions = storage.get_command_history(&command, limit)?;
+
+            let Some(summary) = HistorySummary::new(&executions) else {
+                println!("No recorded runs of '{}'", command);
+                return Ok(());
+            };
+
+            let time = |t: &chrono::DateTime<chrono::Utc>| display_time(t, "%Y-%m-%d %H:%M:%S");
+            let several = executions
+                .iter()
+                .any(|e| e.command_id != executions[0].command_id);
+            let mut out = omniscient::Pager::new(use_pager);
+
+            writeln!(out, "\n{}\n", format!("History of '{}'", command).bold())?;
+            writeln!(
+                out,
+                "  Runs:      {} ({} failed) from {} to {}",
+                summary.runs,
+                if summary.failures > 0 {
+                    summary.failures.to_string().failure()
+                } else {
+                    summary.failures.to_string().success()
+                },
+                time(&summary.first),
+                time(&summary.last)
+            )?;
+            let durations: Vec<i64> = executions.iter().map(|e| e.duration_ms).collect();
+            writeln!(
+                out,
+                "  Duration:  {} (min {}, avg {}, max {})\n",
+                sparkline(&durations).accent(),
+                omniscient::humanize_duration(summary.min_ms),
+                omniscient::humanize_duration(summary.avg_ms),
+                omniscient::humanize_duration(summary.max_ms)
+            )?;
+
+            for execution in &executions {
+                let status = if execution.exit_code == 0 {
+                    "✓".success()
+                } else {
+                    "✗".failure()
+                };
+                let mut line = format!(
+                    "[{}] {} {:>6}",
+                    time(&execution.timestamp).metadata(),
+                    status,
+                    omniscient::humanize_duration(execution.duration_ms)
                 );
+                if execution.exit_code != 0 {
+                    line.push_str(&format!("  exit {}", execution.exit_code));
+                }
+                line.push_str(&format!("  {}", execution.working_dir.metadata()));
+                if several {
+                    let used = visible_width(&line) + 2;
+                    line.push_str(&format!("  {}", fit(&execution.command, used)));
+                }
+                writeln!(out, "{}", line)?;
+            }
+
+            out.finish()?;
+
+            Ok(())
+        }
+        Commands::Flags {
+            program,
+            subcommand,
+            limit,
+        } => {
+            use omniscient::flags::FlagReport;
+
+            let storage = omniscient::Storage::from_config(&config)?;
+            let records = storage.get_by_program(&program)?;
+            let report = FlagReport::new(&records, subcommand.as_deref(), limit);
+
+            let name = match &subcommand {
+                Some(subcommand) => format!("{} {}", program, subcommand),
+                None => program.clone(),
+            };
+            if report.commands == 0 {
+                println!("No recorded commands running '{}'", name);
+                return Ok(());
+            }
+
+            let mut out = omniscient::Pager::new(use_pager);
+            writeln!(out, "\n{}\n", format!("Flags used with '{}'", name).bold())?;
+            writeln!(
+                out,
+                "  {} runs of {} distinct commands",
+                report.runs, report.commands
+            )?;
+
+            let sections = [
+                ("Flags", &report.flags),
+                ("Combinations", &report.combinations),
+                ("Subcommands", &report.subcommands),
+            ];
+            for (title, counts) in sections {
+                if counts.is_empty() {
+                    continue;
+                }
+                writeln!(out, "\n{}", title.bold())?;
+                let width = counts.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+                for (name, runs) in counts {
+                    let bar = (*runs as u64 * 30).div_ceil(report.runs as u64) as usize;
+                    writeln!(
+                        out,
+                        "  {:width$}  {:6}  {}",
+                        name,
+                        runs,
+                        "█".repeat(bar).accent(),
+                        width = width
+                    )?;
+                }
+            }
+            if report.flags.is_empty() {
+                writeln!(out, "\n  No flags recorded")?;
+            }
+
+            out.finish()?;
+
+            Ok(())
+        }
+        Commands::Similar {
+            command,
+            limit,
+            min_score,
+        } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+            let matches = omniscient::similar::find_similar(&storage, &command, min_score, limit)?;
+
+            if matches.is_empty() {
+                println!("No similar commands found.");
+                return Ok(());
+            }
+
+            let mut out = omniscient::Pager::new(use_pager);
+            writeln!(
+                out,
+                "
+Commands similar to '{}':
+",
+                command
+            )?;
+            for omniscient::SimilarCommand { record, score } in matches {
+                writeln!(
+                    out,
+                    "{} {} {}",
+                    format!("{:3.0}%", score * 100.0).accent(),
+                    colorize_status(&record),
+                    display_command(&record, 7)
+                )?;
+                writeln!(
+                    out,
+                    "     ID: {} | Dir: {} | Last used: {} | Usage: {} times",
+                    record.id.unwrap_or_default(),
+                    record.working_dir,
+                    display_time(&record.last_used, "%Y-%m-%d %H:%M"),
+                    record.usage_count
+                )?;
+            }
+            writeln!(out)?;
+
+            out.finish()?;
+
+            Ok(())
+        }
+        Commands::Promote {
+            id,
+            to_dir,
+            move_record,
+        } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            // Store absolute paths so directory-scoped queries match
+            let to_dir = std::fs::canonicalize(&to_dir)
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or(to_dir);
+
+            match storage.promote(id, &to_dir, move_record, config.capture.duplicate_key)? {
+                Some(new_id) => {
+                    let verb = if move_record { "Moved" } else { "Copied" };
+                    println!("✓ {} command {} to {} (ID: {})", verb, id, to_dir, new_id);
+                }
+                None => eprintln!("✗ No command with ID {}", id),
+            }
+
+            Ok(())
+        }
+        Commands::Run {
+            id,
+            yes,
+            dry_run,
+            force,
+        } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            let Some(cmd) = storage.get_by_id(id)? else {
+                eprintln!("✗ No command with ID {}", id);
+                std::process::exit(1);
+            };
+
+            rerun(&config, &storage, &cmd, yes, dry_run, force)
+        }
+        Commands::Pause { duration } => {
+            let until = match duration {
+                Some(ref value) => {
+                    let ms = omniscient::parse_duration(value)?;
+                    Some(chrono::Utc::now() + chrono::Duration::milliseconds(ms))
+                }
+                None => None,
+            };
+
+            omniscient::Pause::from_config(&config)?.pause(until)?;
+
+            match until {
+                Some(until) => println!(
+                    "⏸ Capture paused until {}",
+                    display_time(&until, "%Y-%m-%d %H:%M:%S")
+                ),
+                None => println!("⏸ Capture paused. Run 'omniscient resume' to start again."),
+            }
+            Ok(())
+        }
+        Commands::Resume => {
+            if omniscient::Pause::from_config(&config)?.resume()? {
+                println!("▶ Capture resumed");
+            } else {
+                println!("Capture was not paused");
+            }
+            if omniscient::Pause::disabled_by_env() {
                 println!(
-                    "  Dir: {} | Category: {} | Duration: {} | Usage: {} times",
-                    cmd.working_dir.dimmed(),
-                    colorize_category(&cmd.category),
-                    cmd.duration_display(),
-                    cmd.usage_count
+                    "Note: {} is set in this shell, so its commands are still skipped",
+                    omniscient::pause::DISABLE_ENV
                 );
-                println!();
+            }
+            Ok(())
+        }
+        Commands::Train { limit } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+            let mut trainer =
+                omniscient::Trainer::new(&storage, omniscient::Categorizer::from_config(&config));
+
+            let candidates = trainer.candidates(limit)?;
+            if candidates.is_empty() {
+                println!("Every command in your history already has a category.");
+                return Ok(());
+            }
+
+            let stdin = std::io::stdin();
+            let summary = trainer.run(&candidates, stdin.lock(), std::io::stdout())?;
+
+            if !summary.rules.is_empty() {
+                // Reload so command-line overrides are not written to the file
+                let mut saved = Config::load_from(&config_path)?;
+                saved.categories.rules.extend(summary.rules.clone());
+                let paths: Vec<Vec<String>> = summary
+                    .rules
+                    .keys()
+                    .map(|name| vec!["categories".into(), "rules".into(), name.clone()])
+                    .collect();
+                saved.save_paths(&config_path, &paths)?;
+            }
+
+            println!(
+                "\n✓ Learned {} rule(s), recategorized {} command(s), skipped {}",
+                summary.rules.len(),
+                summary.recategorized,
+                summary.skipped
+            );
+            if !summary.rules.is_empty() {
+                println!("  Rules saved to {}", config_path.display());
             }
 
             Ok(())
         }
-        Commands::Recent { n, dir, recursive } => {
-            let storage = omniscient::Storage::new(&config.database_path()?)?;
+        Commands::Bench {
+            generate,
+            path,
+            runs,
+        } => {
+            use omniscient::bench::{self, Timings};
 
-            let working_dir = if dir.is_some() {
-                Some(resolve_directory(dir)?)
+            let database = config.database_path()?;
+            let path = path.unwrap_or_else(|| database.with_file_name("bench.db"));
+            if path == database {
+                eprintln!(
+                    "Error: Refusing to benchmark your history database; pass another --path"
+                );
+                std::process::exit(1);
+            }
+
+            if let Some(count) = generate {
+                let count = bench::parse_count(&count)?;
+                for suffix in ["", "-wal", "-shm"] {
+                    let mut file = path.clone().into_os_string();
+                    file.push(suffix);
+                    match std::fs::remove_file(&file) {
+                        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                        _ => {}
+                    }
+                }
+
+                println!("Generating {} commands in {}...", count, path.display());
+                let start = std::time::Instant::now();
+                let storage = omniscient::Storage::new(&path)?;
+                bench::generate(&storage, count, 42)?;
+                println!("  done in {:.1}s", start.elapsed().as_secs_f64());
+            }
+
+            let report = bench::run(&config, &path, runs)?;
+
+            println!(
+                "\n{}\n",
+                format!("Benchmark on {} commands", report.records).bold()
+            );
+            let line = |name: &str, timings: &Timings, value: f64, target: f64| {
+                let verdict = if value <= target {
+                    "✓".success()
+                } else {
+                    "✗".failure()
+                };
+                println!(
+                    "  {} {:8} p50 {:8.2} ms  p95 {:8.2} ms  max {:8.2} ms  {}",
+                    verdict,
+                    name,
+                    timings.percentile(50.0),
+                    timings.percentile(95.0),
+                    timings.max(),
+                    format!("(target {:.0} ms)", target).metadata()
+                );
+            };
+            line(
+                "capture",
+                &report.capture,
+                report.capture.percentile(95.0),
+                bench::CAPTURE_TARGET_MS,
+            );
+            line(
+                "search",
+                &report.search,
+                report.search.percentile(95.0),
+                bench::SEARCH_TARGET_MS,
+            );
+            line(
+                "stats",
+                &report.stats,
+                report.stats.max(),
+                bench::STATS_TARGET_MS,
+            );
+            println!();
+
+            if !report.meets_targets() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Commands::Query { sql, json, limit } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+            let result = storage.query(&sql, limit)?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&result.to_json())?);
+                return Ok(());
+            }
+
+            let mut out = omniscient::Pager::new(use_pager);
+            let table = result.to_table();
+            let mut lines = table.lines();
+            if let Some(header) = lines.next() {
+                writeln!(out, "{}", header.bold())?;
+            }
+            for line in lines {
+                writeln!(out, "{}", line)?;
+            }
+            let note = if result.truncated {
+                format!(
+                    "\n(first {} rows; pass --limit for more)",
+                    result.rows.len()
+                )
             } else {
-                None
+                format!("\n({} rows)", result.rows.len())
             };
+            writeln!(out, "{}", note.metadata())?;
+            out.finish()?;
+            Ok(())
+        }
+        Commands::Db { action } => {
+            match action {
+                DbAction::Maintain => {
+                    let storage = omniscient::Storage::from_config(&config)?;
 
-            let results = storage.get_recent(n, working_dir, recursive)?;
+                    println!("Running database maintenance...");
+                    let report = storage.maintain()?;
 
-            if results.is_empty() {
-                println!("No commands in history yet.");
+                    if !report.is_healthy() {
+                        eprintln!("\n✗ Integrity check failed:");
+                        for error in &report.integrity_errors {
+                            eprintln!("  {}", error);
+                        }
+                        eprintln!("\nNo changes were made. Restore from a backup or an export if possible.");
+                        std::process::exit(1);
+                    }
+
+                    println!("\n✓ Integrity check passed");
+                    println!("✓ Search index and stats counters rebuilt");
+                    println!("✓ Database vacuumed and analyzed");
+                    println!(
+                        "\n  Size: {} → {} (reclaimed {})",
+                        format_bytes(report.size_before),
+                        format_bytes(report.size_after),
+                        format_bytes(report.reclaimed_bytes())
+                    );
+
+                    if let Some(path) = run_scheduled_export(&config)? {
+                        println!("✓ Automatic export written to {}", path.display());
+                    }
+                    if let Some(path) = run_scheduled_backup(&config)? {
+                        println!("✓ Automatic backup written to {}", path.display());
+                    }
+                    Ok(())
+                }
+                DbAction::UnifyPaths => {
+                    // Symlinks are resolved here even if capture leaves them
+                    let rewriter =
+                        omniscient::PathRewriter::from_config(&config)?.with_symlink_resolution();
+                    let storage = omniscient::Storage::from_config(&config)?;
+                    let moved = rewriter.unify(&storage, config.capture.duplicate_key)?;
+                    println!(
+                        "✓ Moved {} command(s) to their canonical directories",
+                        moved
+                    );
+
+                    if config.paths.rewrites.is_empty() {
+                        println!("\nOnly symlinks were resolved: no path rewrites configured.");
+                        println!("Add rules to {}, for example:", config_path.display());
+                        println!("  [paths.rewrites]");
+                        println!("  \"/mnt/c/Users/me\" = \"~\"");
+                    }
+                    Ok(())
+                }
+            }
+        }
+        Commands::Dedupe { dry_run } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+            let deduper = omniscient::Deduper::from_config(&config)?;
+            let groups = deduper.plan(storage.get_all()?);
+
+            if groups.is_empty() {
+                println!("No duplicates found.");
                 return Ok(());
             }
 
-            println!("\nMost recent {} command(s):\n", results.len());
-            for cmd in results {
+            for group in &groups {
                 println!(
-                    "[{}] {} {}",
-                    cmd.timestamp
-                        .format("%Y-%m-%d %H:%M:%S")
-                        .to_string()
-                        .dimmed(),
-                    colorize_status(&cmd),
-                    cmd.command
+                    "{} {}",
+                    fit(&group.command, group.working_dir.chars().count() + 1),
+                    group.working_dir.metadata()
                 );
+                let ids: Vec<String> = group
+                    .duplicates
+                    .iter()
+                    .map(|cmd| cmd.id.unwrap_or_default().to_string())
+                    .collect();
+                if ids.is_empty() {
+                    println!("  normalize ID {}", group.target.id.unwrap_or_default());
+                } else {
+                    println!(
+                        "  merge ID(s) {} into {}",
+                        ids.join(", "),
+                        group.target.id.unwrap_or_default()
+                    );
+                }
+            }
+            println!();
+
+            let duplicates: usize = groups.iter().map(|group| group.duplicates.len()).sum();
+            if dry_run {
                 println!(
-                    "  Category: {} | Duration: {} | Usage: {} times",
-                    colorize_category(&cmd.category),
-                    cmd.duration_display(),
-                    cmd.usage_count
+                    "Would merge {} duplicate record(s) across {} command(s). Run without --dry-run to apply.",
+                    duplicates,
+                    groups.len()
                 );
-                println!();
+                return Ok(());
             }
 
+            let removed = deduper.apply(&storage, &groups)?;
+            println!(
+                "✓ Merged {} duplicate record(s) across {} command(s)",
+                removed,
+                groups.len()
+            );
             Ok(())
         }
-        Commands::Top { n, dir, recursive } => {
-            let storage = omniscient::Storage::new(&config.database_path()?)?;
-
-            let working_dir = if dir.is_some() {
-                Some(resolve_directory(dir)?)
-            } else {
-                None
+        Commands::Report { period, format } => {
+            let Some(period) = omniscient::ReportPeriod::from_name(&period) else {
+                eprintln!(
+                    "Error: Unsupported period '{}'. Supported periods: week, month",
+                    period
+                );
+                std::process::exit(1);
+            };
+            let Some(format) = omniscient::ReportFormat::from_name(&format) else {
+                eprintln!(
+                    "Error: Unsupported format '{}'. Supported formats: text, markdown, html",
+                    format
+                );
+                std::process::exit(1);
             };
 
-            let results = storage.get_top(n, working_dir, recursive)?;
+            let storage = omniscient::Storage::from_config(&config)?;
+            let now = chrono::Utc::now();
+            let summary =
+                storage.get_summary(period.since(now), omniscient::report::SECTION_SIZE)?;
+            print!(
+                "{}",
+                omniscient::report::render(&summary, period, format, now)
+            );
 
-            if results.is_empty() {
-                println!("No commands in history yet.");
+            Ok(())
+        }
+        Commands::Wrapped { year } => {
+            use chrono::Datelike;
+
+            let storage = omniscient::Storage::from_config(&config)?;
+            let year = year.unwrap_or_else(|| chrono::Utc::now().year());
+            print!(
+                "{}",
+                omniscient::wrapped::render(&storage.get_wrapped(year)?)
+            );
+
+            Ok(())
+        }
+        Commands::Alerts { limit, clear } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+
+            if clear {
+                let count = storage.clear_alerts()?;
+                println!("✓ Cleared {} alert(s)", count);
                 return Ok(());
             }
 
-            println!("\nTop {} most frequently used command(s):\n", results.len());
-            for (index, cmd) in results.iter().enumerate() {
+            let alerts = storage.get_alerts(limit)?;
+            if alerts.is_empty() {
+                println!("No alerts.");
+                return Ok(());
+            }
+
+            println!("\nAlert(s) ({} shown):\n", alerts.len());
+            for alert in &alerts {
                 println!(
-                    "{}. {} (used {} times)",
-                    index + 1,
-                    cmd.command,
-                    cmd.usage_count.to_string().bold()
+                    "[{}] {} {}",
+                    display_time(&alert.timestamp, "%Y-%m-%d %H:%M:%S"),
+                    "⚠".failure().bold(),
+                    alert.rule.failure()
                 );
+                println!("  {}", alert.command);
                 println!(
-                    "   Category: {} | Last used: {} | Avg duration: {}",
-                    colorize_category(&cmd.category),
-                    cmd.last_used
-                        .format("%Y-%m-%d %H:%M:%S")
-                        .to_string()
-                        .dimmed(),
-                    cmd.duration_display()
+                    "  ID: {} | Dir: {}",
+                    alert.command_id,
+                    alert.working_dir.metadata()
                 );
                 println!();
             }
 
             Ok(())
         }
-        Commands::Category {
-            name,
-            limit,
-            dir,
-            recursive,
+        Commands::Backup { action } => {
+            let schedule = omniscient::BackupSchedule::from_config(&config)?;
+
+            match action {
+                BackupAction::Now => {
+                    let storage = omniscient::Storage::from_config(&config)?;
+                    let path = schedule.backup(&storage, chrono::Local::now().naive_local())?;
+                    println!("✓ Backup written to {}", path.display());
+                    if !config.backup.enabled {
+                        println!(
+                            "  Enable automatic backups with: omniscient config set backup.enabled true"
+                        );
+                    }
+                }
+                BackupAction::List => {
+                    let backups = schedule.existing()?;
+                    if backups.is_empty() {
+                        println!("No backups in {}", schedule.directory().display());
+                        return Ok(());
+                    }
+
+                    println!("\nBackups in {}:\n", schedule.directory().display());
+                    for (stamp, path) in backups.iter().rev() {
+                        let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+                        println!(
+                            "  {}  {:>10}  {}",
+                            stamp.format("%Y-%m-%d %H:%M:%S"),
+                            format_bytes(size),
+                            path.display().to_string().metadata()
+                        );
+                    }
+                    println!();
+                }
+            }
+
+            Ok(())
+        }
+        Commands::Stats {
+            capture_health,
+            by_branch,
+            activity,
+            vocabulary,
+            json,
+            period,
+            since,
+            until,
         } => {
-            let storage = omniscient::Storage::new(&config.database_path()?)?;
+            let storage = omniscient::Storage::from_config(&config)?;
 
-            let working_dir = if dir.is_some() {
-                Some(resolve_directory(dir)?)
-            } else {
-                None
-            };
+            if activity || vocabulary || since.is_some() || until.is_some() {
+                let name = period
+                    .as_deref()
+                    .unwrap_or(if vocabulary { "week" } else { "day" });
+                let Some(period) = omniscient::ActivityPeriod::from_name(name) else {
+                    eprintln!(
+                        "Error: Unsupported period '{}'. Supported periods: day, week, month",
+                        name
+                    );
+                    std::process::exit(1);
+                };
 
-            let results = storage.get_by_category(&name, limit, working_dir, recursive)?;
+                // Default to the last 30 days, 12 weeks or 12 months
+                let now = chrono::Utc::now();
+                let since = match since {
+                    Some(ref value) => omniscient::parse_time(value, now)?,
+                    None => match period {
+                        omniscient::ActivityPeriod::Day => now - chrono::Duration::days(30),
+                        omniscient::ActivityPeriod::Week => now - chrono::Duration::weeks(12),
+                        omniscient::ActivityPeriod::Month => now - chrono::Duration::days(365),
+                    },
+                };
+                let until = until
+                    .as_deref()
+                    .map(|value| omniscient::parse_time_end(value, now))
+                    .transpose()?;
 
-            if results.is_empty() {
-                println!("No commands found in category '{}'", name);
+                if vocabulary {
+                    let buckets = storage.get_vocabulary_growth(period, since, until)?;
+                    print_vocabulary(&buckets, period);
+                } else {
+                    let report = storage.get_activity(period, Some(since), until)?;
+                    print_activity(&report);
+                }
                 return Ok(());
             }
 
-            println!(
-                "\nCommands in category '{}' ({} found):\n",
-                name,
-                results.len()
-            );
-            for cmd in results {
-                println!(
-                    "[{}] {} {}",
-                    cmd.last_used
-                        .format("%Y-%m-%d %H:%M:%S")
-                        .to_string()
-                        .dimmed(),
-                    colorize_status(&cmd),
-                    cmd.command
-                );
+            if by_branch {
+                let branches = storage.get_branch_stats(20)?;
+
+                println!("\n=== Activity by Git Branch ===\n");
+                if branches.is_empty() {
+                    println!("No commands have been captured inside a git repository yet.");
+                }
+                for stats in &branches {
+                    println!(
+                        "  {:30} {:6} runs  {:5} commands  last {}",
+                        stats.branch.accent(),
+                        stats.executions,
+                        stats.commands,
+                        display_time(&stats.last_used, "%Y-%m-%d %H:%M")
+                    );
+                    if let Some(ref repo) = stats.repo {
+                        println!("  {}", repo.metadata());
+                    }
+                }
+                println!();
+                return Ok(());
+            }
+
+            if capture_health {
+                let skips = storage.get_skip_counts()?;
+
+                println!("\n=== Capture Health ===\n");
+                println!("Stored commands: {}", storage.count()?);
+
+                match omniscient::Pause::from_config(&config)?.state(chrono::Utc::now())? {
+                    omniscient::PauseState::Running => {}
+                    omniscient::PauseState::Paused => {
+                        println!("{}", "Capture is paused (omniscient resume)".warning())
+                    }
+                    omniscient::PauseState::PausedUntil(until) => println!(
+                        "{}",
+                        format!(
+                            "Capture is paused until {}",
+                            display_time(&until, "%Y-%m-%d %H:%M")
+                        )
+                        .warning()
+                    ),
+                }
+
+                let pending = omniscient::CaptureJournal::from_config(&config)?.pending()?;
+                if pending > 0 {
+                    println!(
+                        "{}",
+                        format!(
+                            "{} captures are waiting in the journal (stored with the next capture)",
+                            pending
+                        )
+                        .warning()
+                    );
+                }
+
+                if skips.is_empty() {
+                    println!("\nNo commands have been skipped.");
+                } else {
+                    println!("\nSkipped commands:");
+                    for skip in &skips {
+                        println!(
+                            "  {:10} {:6}  last {}  ({})",
+                            skip.reason.to_string().warning(),
+                            skip.count,
+                            display_time(&skip.last_skipped, "%Y-%m-%d %H:%M"),
+                            skip.reason.description()
+                        );
+                    }
+                }
+
                 println!(
-                    "  Used {} times | Duration: {} | Dir: {}",
-                    cmd.usage_count.to_string().bold(),
-                    cmd.duration_display(),
-                    cmd.working_dir.dimmed()
+                    "\n  min_duration={}, redaction {}",
+                    omniscient::humanize_duration(config.capture.min_duration_ms),
+                    if config.privacy.enabled {
+                        "enabled"
+                    } else {
+                        "disabled"
+                    }
                 );
                 println!();
+                return Ok(());
             }
 
-            Ok(())
-        }
-        Commands::Stats => {
-            let storage = omniscient::Storage::new(&config.database_path()?)?;
-            let stats = storage.get_stats()?;
+            let stats = storage.get_stats_with_trends()?;
+
+            if json {
+                let success_rate = stats.success_rate();
+                let mut value = serde_json::json!(stats);
+                value["success_rate"] = serde_json::json!(success_rate);
+                println!("{}", serde_json::to_string_pretty(&value)?);
+                return Ok(());
+            }
 
             println!("\n=== Omniscient Command History Statistics ===\n");
 
@@ -486,8 +3865,14 @@ fn main() -> Result<()> {
 
             if let (Some(oldest), Some(newest)) = (&stats.oldest_command, &stats.newest_command) {
                 println!("\nTime Range:");
-                println!("  First command: {}", oldest.format("%Y-%m-%d %H:%M:%S"));
-                println!("  Last command:  {}", newest.format("%Y-%m-%d %H:%M:%S"));
+                println!(
+                    "  First command: {}",
+                    display_time(oldest, "%Y-%m-%d %H:%M:%S")
+                );
+                println!(
+                    "  Last command:  {}",
+                    display_time(newest, "%Y-%m-%d %H:%M:%S")
+                );
 
                 let duration = *newest - *oldest;
                 let days = duration.num_days();
@@ -500,15 +3885,42 @@ fn main() -> Result<()> {
                 }
             }
 
+            let learned: usize = stats.vocabulary.iter().map(|b| b.new_commands).sum();
+            if learned > 0 {
+                let counts: Vec<i64> = stats
+                    .vocabulary
+                    .iter()
+                    .map(|b| b.new_commands as i64)
+                    .collect();
+                println!("\nVocabulary Growth:");
+                println!(
+                    "  New commands:  {} in the last {} weeks  {}  {}",
+                    learned,
+                    stats.vocabulary.len(),
+                    omniscient::history::sparkline(&counts).accent(),
+                    vocabulary_trend(&stats.vocabulary).metadata()
+                );
+            }
+
             if !stats.by_category.is_empty() {
                 println!("\nCommands by Category:");
                 for cat_stat in &stats.by_category {
                     let percentage = (cat_stat.count as f64 / stats.total_commands as f64) * 100.0;
                     println!(
-                        "  {:12} {:5} ({:.1}%)",
+                        "  {:12} {:5} ({:.1}%)  {}",
                         colorize_category(&cat_stat.category),
                         cat_stat.count,
-                        percentage
+                        percentage,
+                        format!(
+                            "{:.0}% ok, avg {}{}",
+                            cat_stat.success_rate,
+                            omniscient::humanize_duration(cat_stat.avg_duration_ms),
+                            cat_stat
+                                .last_used
+                                .map(|t| format!(", last used {}", display_time(&t, "%Y-%m-%d")))
+                                .unwrap_or_default()
+                        )
+                        .metadata()
                     );
                 }
             }
@@ -516,35 +3928,81 @@ fn main() -> Result<()> {
             println!();
             Ok(())
         }
-        Commands::Export { file } => {
-            let storage = omniscient::Storage::new(&config.database_path()?)?;
-            let exporter = omniscient::Exporter::new(storage);
+        Commands::Export {
+            file,
+            format,
+            category,
+            dir,
+            recursive,
+            since,
+            exclude_failed,
+            redact,
+            strip_locations,
+        } => {
+            let format = match format.as_str() {
+                "json" => omniscient::ExportFormat::Json,
+                "markdown" | "md" => omniscient::ExportFormat::Markdown,
+                "sqlite" => omniscient::ExportFormat::Sqlite,
+                "ndjson" | "jsonl" => omniscient::ExportFormat::Ndjson,
+                _ => {
+                    eprintln!(
+                        "Error: Unsupported format '{}'. Supported formats: json, ndjson, markdown, sqlite",
+                        format
+                    );
+                    std::process::exit(1);
+                }
+            };
+
+            let storage = omniscient::Storage::from_config(&config)?;
+            let redactor = omniscient::RedactionEngine::new(
+                config.privacy.redact_patterns.clone(),
+                config.privacy.enabled || redact,
+            )?;
+            let filter = omniscient::ExportFilter {
+                categories: category,
+                working_dir: dir
+                    .map(|dir| resolve_directory(Some(dir), &config))
+                    .transpose()?,
+                recursive,
+                since: since
+                    .as_deref()
+                    .map(|value| omniscient::parse_time(value, chrono::Utc::now()))
+                    .transpose()?,
+                exclude_failed,
+            };
+            let mut exporter = omniscient::Exporter::new(storage)
+                .with_format(format)
+                .with_redactor(redactor)
+                .with_filter(filter);
+            if redact {
+                exporter = exporter.with_masking(strip_locations);
+            }
 
             println!("Exporting command history to {}...", file);
 
-            match exporter.export(&file) {
-                Ok(stats) => {
-                    println!("\n✓ Export successful!");
-                    println!("  Commands exported: {}", stats.commands_exported);
-                    println!("  File: {}", stats.file_path);
-                    println!("\nYou can now:");
-                    println!("  - Backup this file to version control");
-                    println!("  - Import it on another machine");
-                    println!("  - Share it with your team");
-                    Ok(())
-                }
-                Err(e) => {
-                    eprintln!("✗ Export failed: {}", e);
-                    Err(e)
-                }
+            let stats = exporter.export(&file)?;
+            println!("\n✓ Export successful!");
+            println!("  Commands exported: {}", stats.commands_exported);
+            println!("  File: {}", stats.file_path);
+            println!("\nYou can now:");
+            if format == omniscient::ExportFormat::Markdown {
+                println!("  - Paste it into your team wiki as a runbook");
+            } else if format == omniscient::ExportFormat::Sqlite {
+                println!("  - Archive it alongside other backups");
+                println!("  - Query it with the sqlite3 shell or any SQLite tool");
+            } else {
+                println!("  - Backup this file to version control");
+                println!("  - Import it on another machine");
+                println!("  - Share it with your team");
             }
+            Ok(())
         }
-        Commands::Import { file } => {
-            let storage = omniscient::Storage::new(&config.database_path()?)?;
+        Commands::Import { file, strategy } => {
+            let strategy = parse_strategy(&strategy);
+            let storage = omniscient::Storage::from_config(&config)?;
 
             // Check if file exists
             if !std::path::Path::new(&file).exists() {
-                eprintln!("✗ Error: File '{}' not found", file);
                 return Err(omniscient::OmniscientError::Io(std::io::Error::new(
                     std::io::ErrorKind::NotFound,
                     format!("File '{}' not found", file),
@@ -553,28 +4011,406 @@ fn main() -> Result<()> {
 
             println!("Importing command history from {}...", file);
 
-            // Use PreserveHigher strategy by default (keeps the higher usage count)
-            let importer =
-                omniscient::Importer::new(storage, omniscient::ImportStrategy::PreserveHigher);
-
-            match importer.import(&file) {
-                Ok(stats) => {
-                    println!("\n✓ Import successful!");
-                    println!("  Total commands in file: {}", stats.total_commands);
-                    println!("  New commands imported: {}", stats.imported);
-                    println!("  Existing commands updated: {}", stats.updated);
-                    println!("  Duplicates skipped: {}", stats.skipped);
-                    println!("\n{}", stats.summary());
-                    Ok(())
+            let importer = omniscient::Importer::new(storage, strategy)
+                .with_duplicate_key(config.capture.duplicate_key);
+
+            let stats = importer.import(&file)?;
+            println!("\n✓ Import successful!");
+            println!("  Total commands in file: {}", stats.total_commands);
+            println!("  New commands imported: {}", stats.imported);
+            println!("  Existing commands updated: {}", stats.updated);
+            println!("  Duplicates skipped: {}", stats.skipped);
+            println!("\n{}", stats.summary());
+            Ok(())
+        }
+        Commands::Restore {
+            file,
+            strategy,
+            dry_run,
+            yes,
+            replace,
+        } => {
+            let path = std::path::Path::new(&file);
+            if !path.exists() {
+                eprintln!("Error: File '{}' not found", file);
+                std::process::exit(1);
+            }
+            let strategy = parse_strategy(&strategy);
+
+            if replace {
+                if !omniscient::backup::is_sqlite(path)? {
+                    eprintln!("Error: --replace needs an SQLite backup, not an export");
+                    std::process::exit(1);
                 }
-                Err(e) => {
-                    eprintln!("✗ Import failed: {}", e);
-                    Err(e)
+                omniscient::backup::verify(path)?;
+                let db_path = config.database_path()?;
+                println!(
+                    "{} will replace {}",
+                    path.display(),
+                    db_path.display().to_string().warning()
+                );
+                if dry_run {
+                    return Ok(());
+                }
+                if !yes && prompt("Replace the database? [y/N]: ")? != "y" {
+                    println!("Cancelled.");
+                    return Ok(());
+                }
+
+                let kept = omniscient::backup::replace_database(path, &db_path)?;
+                println!("✓ Database restored from {}", path.display());
+                println!("  The previous database was kept as {}", kept.display());
+                return Ok(());
+            }
+
+            let storage = omniscient::Storage::from_config(&config)?;
+            let importer = omniscient::Importer::new(storage, strategy)
+                .with_duplicate_key(config.capture.duplicate_key);
+
+            let preview = importer.preview(path)?;
+            println!("\nRestoring {} would:\n", file);
+            println!(
+                "  Add      {} new command(s)",
+                preview.imported.to_string().success()
+            );
+            println!(
+                "  Update   {} existing command(s)",
+                preview.updated.to_string().warning()
+            );
+            println!("  Skip     {} duplicate(s)", preview.skipped);
+            println!("  ({} command(s) in the file)\n", preview.total_commands);
+
+            if dry_run || preview.imported + preview.updated == 0 {
+                if preview.imported + preview.updated == 0 {
+                    println!("Nothing to restore.");
+                }
+                return Ok(());
+            }
+            if !yes && prompt("Restore? [y/N]: ")? != "y" {
+                println!("Cancelled.");
+                return Ok(());
+            }
+
+            let stats = importer.import_atomic(path)?;
+            println!("✓ {}", stats.summary());
+
+            Ok(())
+        }
+        Commands::RemoteInstall {
+            target,
+            name,
+            shell,
+        } => {
+            let host = omniscient::RemoteHost::new(&target, name.as_deref())?;
+            let shell_type = match shell.as_deref() {
+                Some("zsh") => omniscient::ShellType::Zsh,
+                Some("bash") => omniscient::ShellType::Bash,
+                Some(other) => {
+                    eprintln!(
+                        "Error: Unsupported shell '{}'. Supported shells: zsh, bash",
+                        other
+                    );
+                    std::process::exit(1);
+                }
+                None => host.detect_shell()?,
+            };
+
+            let rc = host.install(shell_type)?;
+            let mut config = config;
+            config
+                .remote
+                .hosts
+                .insert(host.name.clone(), host.target.clone());
+            config.save()?;
+
+            println!(
+                "{} Installed the {} hook on {} (sourced from {})",
+                "✓".success(),
+                shell_type,
+                host.target,
+                rc
+            );
+            println!(
+                "  Commands run there are tagged '{}'. Fetch them with: omniscient remote-sync {}",
+                host.name.accent(),
+                host.name
+            );
+            println!(
+                "  {}",
+                "New shells on the host pick up the hook; restart open ones.".metadata()
+            );
+
+            Ok(())
+        }
+        Commands::RemoteSync { host } => {
+            if omniscient::Pause::from_config(&config)?
+                .state(chrono::Utc::now())?
+                .is_paused()
+            {
+                eprintln!("Error: Capture is paused; run 'omniscient resume' before syncing");
+                std::process::exit(1);
+            }
+
+            let hosts: Vec<omniscient::RemoteHost> = match host {
+                Some(host) => {
+                    // A configured name, or any SSH destination
+                    let configured = config
+                        .remote
+                        .hosts
+                        .get(&host)
+                        .map(|target| omniscient::RemoteHost::new(target, Some(&host)));
+                    vec![configured.unwrap_or_else(|| omniscient::RemoteHost::new(&host, None))?]
+                }
+                None => config
+                    .remote
+                    .hosts
+                    .iter()
+                    .map(|(name, target)| omniscient::RemoteHost::new(target, Some(name)))
+                    .collect::<Result<_>>()?,
+            };
+            if hosts.is_empty() {
+                println!(
+                    "No remote hosts yet. Set one up with: omniscient remote-install user@host"
+                );
+                return Ok(());
+            }
+
+            let capture = omniscient::CommandCapture::new(config.clone())?;
+            let mut failed = false;
+            for host in &hosts {
+                let synced = host.fetch().and_then(|(requests, invalid)| {
+                    let report = capture.replay(&requests, invalid)?;
+                    host.acknowledge()?;
+                    Ok(report)
+                });
+                match synced {
+                    Ok(report) => {
+                        print!("{} {}: {} stored", "✓".success(), host.name, report.stored);
+                        if report.skipped > 0 {
+                            print!(", {} skipped", report.skipped);
+                        }
+                        if report.duplicates > 0 {
+                            print!(", {} already stored", report.duplicates);
+                        }
+                        if report.invalid > 0 {
+                            print!(", {}", format!("{} invalid", report.invalid).warning());
+                        }
+                        println!();
+                    }
+                    Err(e) => {
+                        eprintln!("{} {}: {}", "✗".failure(), host.name, e);
+                        failed = true;
+                    }
+                }
+            }
+
+            if failed {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Commands::RedactTest { command } => {
+            let redactor = omniscient::RedactionEngine::new(
+                config.privacy.redact_patterns.clone(),
+                config.privacy.enabled,
+            )?;
+
+            if !redactor.is_enabled() {
+                println!(
+                    "Redaction is disabled (privacy.enabled = false); every command is stored."
+                );
+                return Ok(());
+            }
+
+            let matches = redactor.find_matches(&command);
+            if matches.is_empty() {
+                println!("{} Would be stored: no pattern matches", "✓".success());
+                println!("  {}", command);
+                return Ok(());
+            }
+
+            let mut patterns: Vec<&str> = matches.iter().map(|m| m.pattern.as_str()).collect();
+            patterns.dedup();
+            println!(
+                "{} Would not be stored: matches {}",
+                "✗".failure(),
+                patterns
+                    .iter()
+                    .map(|p| format!("'{}'", p))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            println!("  {}", highlight_matches(&command, &matches));
+            if let Some(masked) = redactor.mask(&command) {
+                println!("  Stored records would be masked as: {}", masked.metadata());
+            }
+            Ok(())
+        }
+        Commands::RedactAudit { purge, mask, yes } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+            let redactor = omniscient::RedactionEngine::new(
+                config.privacy.redact_patterns.clone(),
+                config.privacy.enabled,
+            )?;
+
+            let findings = redactor.audit(&storage)?;
+            if findings.is_empty() {
+                println!(
+                    "{} No stored commands match the current redaction patterns",
+                    "✓".success()
+                );
+                return Ok(());
+            }
+
+            println!(
+                "\n{} stored command(s) match the current redaction patterns:\n",
+                findings.len().to_string().bold()
+            );
+            for finding in &findings {
+                let extra = if finding.typed_or_note_matches {
+                    " (alias or note)".warning().to_string()
+                } else {
+                    String::new()
+                };
+                println!(
+                    "  [{}] {}{}",
+                    finding.record.id.unwrap_or_default(),
+                    highlight_matches(&finding.record.command, &finding.command_matches),
+                    extra
+                );
+            }
+            println!();
+
+            let action = if purge {
+                "p".to_string()
+            } else if mask {
+                "m".to_string()
+            } else {
+                prompt("[p]urge, [m]ask or [K]eep these records? ")?
+            };
+            if !matches!(action.as_str(), "p" | "purge" | "m" | "mask") {
+                println!("Kept all records");
+                return Ok(());
+            }
+            let purge = action.starts_with('p');
+
+            if !yes && (purge || mask) {
+                let verb = if purge { "Delete" } else { "Mask" };
+                if prompt(&format!("{} {} record(s)? [y/N]: ", verb, findings.len()))? != "y" {
+                    println!("Aborted");
+                    return Ok(());
+                }
+            }
+
+            let mut changed = 0;
+            for finding in findings {
+                let mut record = finding.record;
+                if purge {
+                    if storage.delete(record.id.unwrap_or_default())? {
+                        changed += 1;
+                    }
+                } else if redactor.mask_record(&mut record) && storage.update_record(&record)? {
+                    changed += 1;
+                }
+            }
+
+            if purge {
+                println!("{} Deleted {} record(s)", "✓".success(), changed);
+            } else {
+                println!("{} Masked {} record(s)", "✓".success(), changed);
+            }
+            Ok(())
+        }
+        Commands::Redact {
+            action: RedactAction::Apply { delete, yes },
+        } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+            let redactor = omniscient::RedactionEngine::new(
+                config.privacy.redact_patterns.clone(),
+                config.privacy.enabled,
+            )?;
+
+            if !redactor.is_enabled() {
+                println!("Redaction is disabled (privacy.enabled = false); nothing to do.");
+                return Ok(());
+            }
+
+            if !yes {
+                let verb = if delete { "Delete" } else { "Mask" };
+                let answer = prompt(&format!(
+                    "{} every stored command matching {} pattern(s)? [y/N]: ",
+                    verb,
+                    redactor.pattern_count()
+                ))?;
+                if answer != "y" {
+                    println!("Aborted");
+                    return Ok(());
                 }
             }
+
+            let mode = if delete {
+                omniscient::RedactMode::Delete
+            } else {
+                omniscient::RedactMode::Mask
+            };
+            let report = redactor.apply(&storage, mode)?;
+
+            println!("{} Scanned {} record(s)", "✓".success(), report.scanned);
+            println!("  Masked:  {}", report.masked);
+            println!("  Deleted: {}", report.deleted);
+            if report.env_masked > 0 {
+                println!(
+                    "  Environment variables masked in {} execution(s)",
+                    report.env_masked
+                );
+            }
+            if report.outputs_masked + report.outputs_deleted > 0 {
+                println!(
+                    "  Captured output: {} masked, {} deleted",
+                    report.outputs_masked, report.outputs_deleted
+                );
+            }
+            if report.changed() {
+                println!(
+                    "{}",
+                    "⚠ Backups and exports made before now still contain the original text; \
+                     delete them if it must not be kept"
+                        .warning()
+                );
+            }
+            Ok(())
+        }
+        Commands::Config {
+            action: Some(ConfigAction::Get { key }),
+        } => {
+            match config.get_value(&key)? {
+                toml::Value::String(value) => println!("{}", value),
+                toml::Value::Table(table) => print!(
+                    "{}",
+                    toml::to_string_pretty(&table).map_err(|e| {
+                        omniscient::OmniscientError::config(format!(
+                            "Failed to serialize config: {}",
+                            e
+                        ))
+                    })?
+                ),
+                value => println!("{}", value),
+            }
+            Ok(())
+        }
+        Commands::Config {
+            action: Some(ConfigAction::Set { key, value }),
+        } => {
+            // Edit the file as written, without command-line overrides
+            let mut saved = Config::load_from(&config_path)?;
+            saved.set_value(&key, &value)?;
+            saved.save_key(&config_path, &key)?;
+
+            println!("✓ {} = {}", key, saved.get_value(&key)?);
+            Ok(())
         }
-        Commands::Config => {
-            println!("Configuration:");
+        Commands::Config { action: None } => {
+            println!("Configuration ({}):", config_path.display());
             println!(
                 "  Storage: {} at {}",
                 config.storage.storage_type, config.storage.path
@@ -589,9 +4425,137 @@ fn main() -> Result<()> {
                 config.privacy.redact_patterns.len()
             );
             println!(
-                "  Capture: min_duration={}ms, max_history={}",
-                config.capture.min_duration_ms, config.capture.max_history_size
+                "  Capture: min_duration={}, max_history={}",
+                omniscient::humanize_duration(config.capture.min_duration_ms),
+                config.capture.max_history_size
+            );
+            println!(
+                "  Alerts: {} (rules: {}{})",
+                if config.alerts.enabled {
+                    "enabled"
+                } else {
+                    "disabled"
+                },
+                config.alerts.watchlist.len(),
+                if config.alerts.warn { ", warn" } else { "" }
+            );
+            if !config.privacy.ignore_dirs.is_empty() {
+                println!("  Ignored dirs: {}", config.privacy.ignore_dirs.join(", "));
+            }
+            if !config.capture.env_vars.is_empty() {
+                println!("  Env vars: {}", config.capture.env_vars.join(", "));
+            }
+            Ok(())
+        }
+        Commands::Serve {
+            api: _,
+            socket: Some(socket),
+            addr: _,
+        } => serve_socket(&config, socket),
+        Commands::Serve { addr, .. } => {
+            let storage = omniscient::Storage::from_config(&config)?;
+            let token = Some(config.api.token.as_str()).filter(|token| !token.is_empty());
+            omniscient::api::check_addr(&addr, token)?;
+            println!("Serving the API on http://{} (Ctrl-C to stop)", addr);
+            omniscient::api::serve(&storage, &addr, token)
+        }
+        Commands::Daemon { action } => {
+            let manager = omniscient::service::ServiceManager::detect()?;
+
+            // Pin the services to this configuration and database
+            let mut global_args = vec![
+                "--config".to_string(),
+                std::path::absolute(&config_path)?
+                    .to_string_lossy()
+                    .into_owned(),
+            ];
+            if cli.db.is_some() || Config::database_from_env().is_some() {
+                global_args.push("--db".to_string());
+                global_args.push(
+                    std::path::absolute(config.database_path()?)?
+                        .to_string_lossy()
+                        .into_owned(),
+                );
+            }
+            let services = omniscient::service::Services::new(
+                manager,
+                &Config::home_dir()?,
+                std::env::current_exe()?,
+                global_args,
+                Config::omniscient_dir()?,
             );
+
+            match action {
+                DaemonAction::Install { print: true, .. } => {
+                    for file in services.files() {
+                        println!("{}", format!("# {}", file.path.display()).metadata());
+                        println!("{}", file.contents);
+                    }
+                }
+                DaemonAction::Install { no_start, .. } => {
+                    let files = services.install(!no_start)?;
+                    println!(
+                        "{} Installed {} service files:",
+                        "✓".success(),
+                        services.manager()
+                    );
+                    for path in &files {
+                        println!("  {}", path.display());
+                    }
+                    if no_start {
+                        println!("\nNot started (run without --no-start to enable them)");
+                    } else {
+                        println!("\nThe query daemon is running; maintenance runs daily.");
+                    }
+                }
+                DaemonAction::Uninstall => {
+                    let files = services.uninstall()?;
+                    if files.is_empty() {
+                        println!("No {} service files installed", services.manager());
+                    } else {
+                        println!("{} Stopped and removed:", "✓".success());
+                        for path in &files {
+                            println!("  {}", path.display());
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+        Commands::Completions {
+            shell,
+            list_categories,
+        } => {
+            if list_categories {
+                // Built-in and learned categories, plus any found in history
+                let mut categories = omniscient::Categorizer::from_config(&config).categories();
+                if let Ok(stats) =
+                    omniscient::Storage::from_config(&config).and_then(|s| s.get_stats())
+                {
+                    categories.extend(stats.by_category.into_iter().map(|c| c.category));
+                }
+                categories.sort();
+                categories.dedup();
+                for category in categories {
+                    println!("{}", category);
+                }
+                return Ok(());
+            }
+
+            let shell = shell.unwrap_or_default();
+            let Some(shell) = omniscient::completions::shell_from_name(&shell) else {
+                eprintln!(
+                    "Error: Unsupported shell '{}'. Supported shells: bash, zsh, fish, powershell, elvish",
+                    shell
+                );
+                std::process::exit(1);
+            };
+
+            omniscient::completions::write_completions(
+                shell,
+                &mut Cli::command(),
+                &mut std::io::stdout(),
+            )?;
             Ok(())
         }
     }
@@ -0,0 +1,714 @@
+/// Versioned schema migrations for the SQLite database
+///
+/// The schema version is stored in SQLite's `user_version` pragma. Each
+/// migration upgrades the schema by exactly one version and runs in its own
+/// transaction. Migrations are written to be idempotent because databases
+/// created before this framework existed report version 0 but may already
+/// contain some of the tables and columns.
+use crate::error::{OmniscientError, Result};
+use crate::parse::ParsedCommand;
+use rusqlite::{params, Connection};
+use std::path::{Path, PathBuf};
+
+/// A single schema upgrade step
+struct Migration {
+    /// Schema version after this migration has run
+    version: i64,
+
+    /// Short human-readable summary
+    description: &'static str,
+
+    /// Apply the migration
+    apply: fn(&Connection) -> Result<()>,
+}
+
+/// All migrations, in order
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema with full-text search",
+        apply: initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "execution log for sequence analysis",
+        apply: add_executions,
+    },
+    Migration {
+        version: 3,
+        description: "typed alias column",
+        apply: add_typed_command,
+    },
+    Migration {
+        version: 4,
+        description: "bookmarks",
+        apply: add_bookmarks,
+    },
+    Migration {
+        version: 5,
+        description: "notes on commands",
+        apply: add_notes,
+    },
+    Migration {
+        version: 6,
+        description: "incremental per-category counters for stats",
+        apply: add_category_counts,
+    },
+    Migration {
+        version: 7,
+        description: "capture skip counters",
+        apply: add_capture_skips,
+    },
+    Migration {
+        version: 8,
+        description: "captured environment variables per execution",
+        apply: add_execution_env,
+    },
+    Migration {
+        version: 9,
+        description: "git branch and repository per execution",
+        apply: add_execution_git,
+    },
+    Migration {
+        version: 10,
+        description: "exit code per execution",
+        apply: add_execution_exit_code,
+    },
+    Migration {
+        version: 11,
+        description: "duration per execution",
+        apply: add_execution_duration,
+    },
+    Migration {
+        version: 12,
+        description: "captured command output per execution",
+        apply: add_outputs,
+    },
+    Migration {
+        version: 13,
+        description: "alerts for watched dangerous commands",
+        apply: add_alerts,
+    },
+    Migration {
+        version: 14,
+        description: "parsed program, subcommand and words of commands",
+        apply: add_parsed_command,
+    },
+    Migration {
+        version: 15,
+        description: "index for the most used commands per directory",
+        apply: add_dir_usage_index,
+    },
+    Migration {
+        version: 16,
+        description: "tmux session, window and pane per execution",
+        apply: add_execution_tmux,
+    },
+    Migration {
+        version: 17,
+        description: "unique capture IDs for idempotent captures",
+        apply: add_execution_capture_id,
+    },
+    Migration {
+        version: 18,
+        description: "per-category duration totals and last use for stats",
+        apply: add_category_usage,
+    },
+];
+
+/// Recount `category_counts` from the commands table
+pub(crate) const REBUILD_CATEGORY_COUNTS: &str = "
+    DELETE FROM category_counts;
+    INSERT INTO category_counts (category, total, successful, duration_sum, last_used)
+    SELECT category, COUNT(*), SUM(exit_code = 0), SUM(duration_ms), MAX(last_used)
+    FROM commands GROUP BY category;";
+
+/// Schema version this build of Omniscient expects
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Read the schema version of an open database
+pub fn current_version(conn: &Connection) -> Result<i64> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+/// Descriptions of the migrations that have not been applied yet
+pub fn pending(conn: &Connection) -> Result<Vec<(i64, &'static str)>> {
+    let current = current_version(conn)?;
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current)
+        .map(|m| (m.version, m.description))
+        .collect())
+}
+
+/// Bring the database up to the latest schema version
+///
+/// Returns the number of migrations applied. Fails if the database was
+/// created by a newer version of Omniscient.
+pub fn migrate(conn: &Connection) -> Result<usize> {
+    let current = current_version(conn)?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(OmniscientError::DatabaseInit(format!(
+            "database schema version {} is newer than this build supports ({}); please upgrade omniscient",
+            current, latest
+        )));
+    }
+
+    let mut applied = 0;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = conn.unchecked_transaction()?;
+        (migration.apply)(conn).map_err(|e| {
+            OmniscientError::DatabaseInit(format!(
+                "migration {} ({}) failed: {}",
+                migration.version, migration.description, e
+            ))
+        })?;
+        conn.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+        applied += 1;
+    }
+
+    Ok(applied)
+}
+
+/// Whether the database already holds data from an earlier version
+pub fn has_existing_data(conn: &Connection) -> Result<bool> {
+    let tables: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'commands'",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(tables > 0)
+}
+
+/// Write a consistent copy of the database next to the original before migrating
+///
+/// The copy is named `<db>.v<version>-<timestamp>.bak`.
+pub fn backup(conn: &Connection, db_path: &Path) -> Result<PathBuf> {
+    let version = current_version(conn)?;
+    let stamp = chrono::Utc::now().format("%Y%m%d%H%M%S");
+    let file_name = format!(
+        "{}.v{}-{}.bak",
+        db_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "history.db".to_string()),
+        version,
+        stamp
+    );
+    let backup_path = db_path.with_file_name(file_name);
+
+    conn.execute(
+        "VACUUM INTO ?1",
+        [backup_path.to_string_lossy().to_string()],
+    )?;
+
+    Ok(backup_path)
+}
+
+/// Add a column to a table if it is missing
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<std::result::Result<Vec<_>, _>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Version 1: the original commands table, indexes and FTS5 index
+fn initial_schema(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS commands (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            command TEXT NOT NULL,
+            timestamp TEXT NOT NULL,
+            exit_code INTEGER NOT NULL,
+            duration_ms INTEGER NOT NULL,
+            working_dir TEXT NOT NULL,
+            category TEXT NOT NULL,
+            usage_count INTEGER NOT NULL DEFAULT 1,
+            last_used TEXT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_timestamp ON commands(timestamp DESC);
+        CREATE INDEX IF NOT EXISTS idx_category ON commands(category);
+        CREATE INDEX IF NOT EXISTS idx_usage ON commands(usage_count DESC);
+        CREATE INDEX IF NOT EXISTS idx_command ON commands(command);
+        CREATE INDEX IF NOT EXISTS idx_exit_code ON commands(exit_code);
+        CREATE INDEX IF NOT EXISTS idx_working_dir ON commands(working_dir);
+
+        -- Full-text search virtual table
+        CREATE VIRTUAL TABLE IF NOT EXISTS commands_fts USING fts5(
+            command,
+            content='commands',
+            content_rowid='id'
+        );
+
+        -- Triggers to keep FTS table in sync
+        CREATE TRIGGER IF NOT EXISTS commands_ai AFTER INSERT ON commands BEGIN
+            INSERT INTO commands_fts(rowid, command) VALUES (new.id, new.command);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS commands_ad AFTER DELETE ON commands BEGIN
+            INSERT INTO commands_fts(commands_fts, rowid, command)
+            VALUES('delete', old.id, old.command);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS commands_au AFTER UPDATE ON commands BEGIN
+            INSERT INTO commands_fts(commands_fts, rowid, command)
+            VALUES('delete', old.id, old.command);
+            INSERT INTO commands_fts(rowid, command) VALUES (new.id, new.command);
+        END;
+        "#,
+    )?;
+
+    Ok(())
+}
+
+/// Version 2: individual executions, used to detect command sequences
+fn add_executions(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS executions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            command_id INTEGER NOT NULL,
+            timestamp TEXT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_executions_timestamp ON executions(timestamp);
+
+        CREATE TRIGGER IF NOT EXISTS commands_executions_ad AFTER DELETE ON commands BEGIN
+            DELETE FROM executions WHERE command_id = old.id;
+        END;
+        "#,
+    )?;
+
+    add_column_if_missing(conn, "executions", "session", "TEXT")?;
+    add_column_if_missing(conn, "executions", "host", "TEXT")?;
+    add_column_if_missing(conn, "executions", "context", "TEXT")?;
+
+    Ok(())
+}
+
+/// Version 3: what the user typed when it differs from the stored command
+fn add_typed_command(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "commands", "typed_command", "TEXT")
+}
+
+/// Version 4: bookmarked (favorite) commands
+fn add_bookmarks(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS bookmarks (
+            command_id INTEGER PRIMARY KEY,
+            created_at TEXT NOT NULL
+        );
+
+        CREATE TRIGGER IF NOT EXISTS commands_bookmarks_ad AFTER DELETE ON commands BEGIN
+            DELETE FROM bookmarks WHERE command_id = old.id;
+        END;
+        "#,
+    )?;
+
+    Ok(())
+}
+
+/// Version 5: free-text notes on commands
+fn add_notes(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "commands", "note", "TEXT")
+}
+
+/// Version 6: per-category totals kept up to date by triggers
+fn add_category_counts(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS category_counts (
+            category TEXT PRIMARY KEY,
+            total INTEGER NOT NULL,
+            successful INTEGER NOT NULL
+        );
+
+        -- Rebuild from scratch so the counters match the existing rows
+        DELETE FROM category_counts;
+        INSERT INTO category_counts (category, total, successful)
+        SELECT category, COUNT(*), SUM(exit_code = 0) FROM commands GROUP BY category;
+
+        CREATE TRIGGER IF NOT EXISTS category_counts_ai AFTER INSERT ON commands BEGIN
+            INSERT INTO category_counts (category, total, successful)
+            VALUES (new.category, 1, new.exit_code = 0)
+            ON CONFLICT(category) DO UPDATE
+            SET total = total + 1, successful = successful + excluded.successful;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS category_counts_ad AFTER DELETE ON commands BEGIN
+            UPDATE category_counts
+            SET total = total - 1, successful = successful - (old.exit_code = 0)
+            WHERE category = old.category;
+            DELETE FROM category_counts WHERE category = old.category AND total <= 0;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS category_counts_au
+        AFTER UPDATE OF category, exit_code ON commands BEGIN
+            UPDATE category_counts
+            SET total = total - 1, successful = successful - (old.exit_code = 0)
+            WHERE category = old.category;
+            DELETE FROM category_counts WHERE category = old.category AND total <= 0;
+            INSERT INTO category_counts (category, total, successful)
+            VALUES (new.category, 1, new.exit_code = 0)
+            ON CONFLICT(category) DO UPDATE
+            SET total = total + 1, successful = successful + excluded.successful;
+        END;
+        "#,
+    )?;
+
+    Ok(())
+}
+
+/// Version 7: how often capture skipped commands, per reason
+fn add_capture_skips(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS capture_skips (
+            reason TEXT PRIMARY KEY,
+            count INTEGER NOT NULL,
+            last_skipped TEXT NOT NULL
+        );
+        "#,
+    )?;
+
+    Ok(())
+}
+
+/// Version 8: selected environment variables, stored as a JSON object
+fn add_execution_env(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "executions", "env", "TEXT")
+}
+
+/// Version 9: git branch and remote of the working directory
+fn add_execution_git(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "executions", "git_branch", "TEXT")?;
+    add_column_if_missing(conn, "executions", "git_repo", "TEXT")?;
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_executions_git_branch ON executions(git_branch);",
+    )?;
+
+    Ok(())
+}
+
+/// Version 10: exit code of each execution (older rows fall back to the
+/// command's exit code)
+fn add_execution_exit_code(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "executions", "exit_code", "INTEGER")
+}
+
+/// Version 11: duration of each execution (older rows fall back to the
+/// command's duration)
+fn add_execution_duration(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "executions", "duration_ms", "INTEGER")
+}
+
+/// Version 12: tail of the output printed by an execution (opt-in)
+fn add_outputs(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS outputs (
+            execution_id INTEGER PRIMARY KEY,
+            output TEXT NOT NULL,
+            truncated INTEGER NOT NULL DEFAULT 0
+        );
+
+        CREATE TRIGGER IF NOT EXISTS executions_outputs_ad AFTER DELETE ON executions BEGIN
+            DELETE FROM outputs WHERE execution_id = old.id;
+        END;
+        "#,
+    )?;
+
+    Ok(())
+}
+
+/// Version 13: alert log for commands matching the watchlist
+///
+/// Alerts keep their own copy of the command so they outlive the record.
+fn add_alerts(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS alerts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            command_id INTEGER NOT NULL,
+            rule TEXT NOT NULL,
+            command TEXT NOT NULL,
+            working_dir TEXT NOT NULL,
+            timestamp TEXT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_alerts_timestamp ON alerts(timestamp);
+        "#,
+    )?;
+
+    Ok(())
+}
+
+/// Version 14: program, subcommand and words of each command (see
+/// [`ParsedCommand`]), parsed for existing records
+fn add_parsed_command(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "commands", "program", "TEXT")?;
+    add_column_if_missing(conn, "commands", "subcommand", "TEXT")?;
+    add_column_if_missing(conn, "commands", "tokens", "TEXT")?;
+    // The update trigger removes each row's old text from the full-text
+    // index, which must therefore be complete (databases from before the
+    // index existed never had their rows added)
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_program ON commands(program, subcommand);
+         INSERT INTO commands_fts(commands_fts) VALUES('rebuild');",
+    )?;
+
+    let mut select = conn.prepare("SELECT id, command FROM commands")?;
+    let mut update = conn
+        .prepare("UPDATE commands SET program = ?1, subcommand = ?2, tokens = ?3 WHERE id = ?4")?;
+    let rows = select
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    for (id, command) in rows {
+        let parsed = ParsedCommand::parse(&command);
+        update.execute(params![
+            parsed.program,
+            parsed.subcommand,
+            parsed.stored_tokens(),
+            id
+        ])?;
+    }
+
+    Ok(())
+}
+
+/// Version 15: index answering "most used commands in this directory"
+/// without a sort, for suggestions on every `cd`
+fn add_dir_usage_index(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_dir_usage ON commands(working_dir, usage_count DESC);",
+    )?;
+    Ok(())
+}
+
+/// Version 16: tmux (or screen) session, window and pane of each execution
+fn add_execution_tmux(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "executions", "tmux_session", "TEXT")?;
+    add_column_if_missing(conn, "executions", "tmux_window", "TEXT")?;
+    add_column_if_missing(conn, "executions", "tmux_pane", "TEXT")?;
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_executions_tmux ON executions(tmux_session, tmux_pane);",
+    )?;
+
+    Ok(())
+}
+
+/// Version 17: client-generated ID of the capture that logged each
+/// execution, unique so a retried or replayed capture is stored once
+fn add_execution_capture_id(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "executions", "capture_id", "TEXT")?;
+    conn.execute_batch(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_executions_capture_id ON executions(capture_id);",
+    )?;
+
+    Ok(())
+}
+
+/// Version 18: total duration and latest use per category, so stats don't
+/// scan the commands table
+///
+/// The latest use can't be undone by subtraction: when a command leaves a
+/// category it is looked up again through an index.
+fn add_category_usage(conn: &Connection) -> Result<()> {
+    add_column_if_missing(
+        conn,
+        "category_counts",
+        "duration_sum",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_column_if_missing(conn, "category_counts", "last_used", "TEXT")?;
+
+    conn.execute_batch(REBUILD_CATEGORY_COUNTS)?;
+    conn.execute_batch(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_category_last_used ON commands(category, last_used);
+
+        DROP TRIGGER IF EXISTS category_counts_ai;
+        DROP TRIGGER IF EXISTS category_counts_ad;
+        DROP TRIGGER IF EXISTS category_counts_au;
+
+        CREATE TRIGGER category_counts_ai AFTER INSERT ON commands BEGIN
+            INSERT INTO category_counts (category, total, successful, duration_sum, last_used)
+            VALUES (new.category, 1, new.exit_code = 0, new.duration_ms, new.last_used)
+            ON CONFLICT(category) DO UPDATE
+            SET total = total + 1, successful = successful + excluded.successful,
+                duration_sum = duration_sum + excluded.duration_sum,
+                last_used = MAX(COALESCE(last_used, ''), excluded.last_used);
+        END;
+
+        CREATE TRIGGER category_counts_ad AFTER DELETE ON commands BEGIN
+            UPDATE category_counts
+            SET total = total - 1, successful = successful - (old.exit_code = 0),
+                duration_sum = duration_sum - old.duration_ms,
+                last_used = (SELECT MAX(last_used) FROM commands WHERE category = old.category)
+            WHERE category = old.category;
+            DELETE FROM category_counts WHERE category = old.category AND total <= 0;
+        END;
+
+        CREATE TRIGGER category_counts_au
+        AFTER UPDATE OF category, exit_code, duration_ms, last_used ON commands BEGIN
+            UPDATE category_counts
+            SET total = total - 1, successful = successful - (old.exit_code = 0),
+                duration_sum = duration_sum - old.duration_ms,
+                last_used = (SELECT MAX(last_used) FROM commands WHERE category = old.category)
+            WHERE category = old.category;
+            DELETE FROM category_counts WHERE category = old.category AND total <= 0;
+            INSERT INTO category_counts (category, total, successful, duration_sum, last_used)
+            VALUES (new.category, 1, new.exit_code = 0, new.duration_ms, new.last_used)
+            ON CONFLICT(category) DO UPDATE
+            SET total = total + 1, successful = successful + excluded.successful,
+                duration_sum = duration_sum + excluded.duration_sum,
+                last_used = MAX(COALESCE(last_used, ''), excluded.last_used);
+        END;
+        "#,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn column_names(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(&format!("PRAGMA table_info({})", table))
+            .unwrap();
+        stmt.query_map([], |row| row.get::<_, String>(1))
+            .unwrap()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_versions_are_sequential() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as i64 + 1);
+        }
+    }
+
+    #[test]
+    fn test_fresh_database_reaches_latest() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(!has_existing_data(&conn).unwrap());
+        assert_eq!(pending(&conn).unwrap().len(), MIGRATIONS.len());
+
+        let applied = migrate(&conn).unwrap();
+        assert_eq!(applied, MIGRATIONS.len());
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        assert!(pending(&conn).unwrap().is_empty());
+
+        // Running again is a no-op
+        assert_eq!(migrate(&conn).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_upgrades_pre_framework_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE commands (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                command TEXT NOT NULL,
+                timestamp TEXT NOT NULL,
+                exit_code INTEGER NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                working_dir TEXT NOT NULL,
+                category TEXT NOT NULL,
+                usage_count INTEGER NOT NULL DEFAULT 1,
+                last_used TEXT NOT NULL,
+                typed_command TEXT
+            );
+            INSERT INTO commands (command, timestamp, exit_code, duration_ms,
+                                  working_dir, category, usage_count, last_used)
+            VALUES ('ls', '2024-01-01T00:00:00+00:00', 0, 1, '/tmp', 'file', 1,
+                    '2024-01-01T00:00:00+00:00');",
+        )
+        .unwrap();
+        assert!(has_existing_data(&conn).unwrap());
+
+        migrate(&conn).unwrap();
+
+        let columns = column_names(&conn, "commands");
+        assert!(columns.contains(&"typed_command".to_string()));
+        assert!(columns.contains(&"note".to_string()));
+
+        // Existing commands are parsed
+        let program: String = conn
+            .query_row("SELECT program FROM commands", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(program, "ls");
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM commands", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+
+        // Counters are backfilled from the existing rows
+        let (total, successful): (i64, i64) = conn
+            .query_row(
+                "SELECT total, successful FROM category_counts WHERE category = 'file'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((total, successful), (1, 1));
+    }
+
+    #[test]
+    fn test_rejects_newer_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+
+        assert!(migrate(&conn).is_err());
+    }
+
+    #[test]
+    fn test_backup() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("history.db");
+        let conn = Connection::open(&db_path).unwrap();
+        migrate(&conn).unwrap();
+
+        let backup_path = backup(&conn, &db_path).unwrap();
+        assert!(backup_path.exists());
+
+        let file_name = backup_path.file_name().unwrap().to_string_lossy();
+        assert!(file_name.starts_with(&format!("history.db.v{}-", latest_version())));
+
+        let copy = Connection::open(&backup_path).unwrap();
+        assert_eq!(current_version(&copy).unwrap(), latest_version());
+    }
+}
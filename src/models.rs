@@ -1,6 +1,8 @@
 /// Data models for Omniscient
-use chrono::{DateTime, Utc};
+use crate::error::{OmniscientError, Result};
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /// Represents a single command execution record
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -31,6 +33,15 @@ pub struct CommandRecord {
 
     /// Timestamp of most recent execution
     pub last_used: DateTime<Utc>,
+
+    /// What the user actually typed, when it differs from `command`
+    /// (e.g. an alias such as `gst` that expanded to `git status`)
+    #[serde(default)]
+    pub typed_command: Option<String>,
+
+    /// Free-text note attached by the user
+    #[serde(default)]
+    pub note: Option<String>,
 }
 
 impl CommandRecord {
@@ -53,6 +64,8 @@ impl CommandRecord {
             category,
             usage_count: 1,
             last_used: timestamp,
+            typed_command: None,
+            note: None,
         }
     }
 
@@ -72,16 +85,297 @@ impl CommandRecord {
 
     /// Format duration for human-readable display
     pub fn duration_display(&self) -> String {
-        if self.duration_ms < 1000 {
-            format!("{}ms", self.duration_ms)
-        } else if self.duration_ms < 60_000 {
-            format!("{:.1}s", self.duration_ms as f64 / 1000.0)
-        } else {
-            let minutes = self.duration_ms / 60_000;
-            let seconds = (self.duration_ms % 60_000) / 1000;
-            format!("{}m{}s", minutes, seconds)
+        humanize_duration(self.duration_ms)
+    }
+}
+
+/// Parse a human-written duration into milliseconds
+///
+/// Accepts one or more `<number><unit>` parts with units `ms`, `s`, `m`, `h`
+/// and `d` (e.g. "500ms", "2s", "1.5s", "5m", "1h30m"). A bare number is
+/// taken as milliseconds.
+pub fn parse_duration(input: &str) -> Result<i64> {
+    let text = input.trim();
+    if text.is_empty() {
+        return Err(OmniscientError::other("empty duration"));
+    }
+
+    if let Ok(ms) = text.parse::<i64>() {
+        if ms < 0 {
+            return Err(OmniscientError::other(format!(
+                "duration cannot be negative: {}",
+                input
+            )));
+        }
+        return Ok(ms);
+    }
+
+    let invalid = || {
+        OmniscientError::other(format!(
+            "invalid duration '{}' (expected e.g. 500ms, 2s, 5m, 1h30m)",
+            input
+        ))
+    };
+
+    let mut total = 0.0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .ok_or_else(invalid)?;
+        let unit_len = rest[number_len..]
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len() - number_len);
+
+        let value: f64 = rest[..number_len].parse().map_err(|_| invalid())?;
+        let unit_ms = match &rest[number_len..number_len + unit_len] {
+            "ms" => 1.0,
+            "s" => 1_000.0,
+            "m" => 60_000.0,
+            "h" => 3_600_000.0,
+            "d" => 86_400_000.0,
+            _ => return Err(invalid()),
+        };
+
+        total += value * unit_ms;
+        rest = &rest[number_len + unit_len..];
+    }
+
+    Ok(total.round() as i64)
+}
+
+/// Longest run of consecutive days, as (first day, length)
+///
+/// `days` must be sorted and free of duplicates.
+pub fn longest_streak(days: &[NaiveDate]) -> Option<(NaiveDate, usize)> {
+    let mut best: Option<(NaiveDate, usize)> = None;
+    let mut current: Option<(NaiveDate, usize)> = None;
+
+    for (index, &day) in days.iter().enumerate() {
+        current = match current {
+            Some((start, length)) if day.pred_opt() == Some(days[index - 1]) => {
+                Some((start, length + 1))
+            }
+            _ => Some((day, 1)),
+        };
+        if current.map(|c| c.1) > best.map(|b| b.1) {
+            best = current;
         }
     }
+
+    best
+}
+
+/// Format a duration in milliseconds for display ("500ms", "2.5s", "2m5s", "1h3m")
+pub fn humanize_duration(duration_ms: i64) -> String {
+    if duration_ms < 1000 {
+        format!("{}ms", duration_ms)
+    } else if duration_ms < 60_000 {
+        format!("{:.1}s", duration_ms as f64 / 1000.0)
+    } else if duration_ms < 3_600_000 {
+        let minutes = duration_ms / 60_000;
+        let seconds = (duration_ms % 60_000) / 1000;
+        format!("{}m{}s", minutes, seconds)
+    } else {
+        let hours = duration_ms / 3_600_000;
+        let minutes = (duration_ms % 3_600_000) / 60_000;
+        format!("{}h{}m", hours, minutes)
+    }
+}
+
+/// Parse the start of a time range: a date ("2025-11-01"), an RFC 3339
+/// timestamp, or a duration before `now` ("7d", "12h")
+pub fn parse_time(input: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let text = input.trim();
+
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    parse_duration(text)
+        .map(|ms| now - chrono::Duration::milliseconds(ms))
+        .map_err(|_| {
+            OmniscientError::other(format!(
+                "invalid time '{}' (expected e.g. 2025-11-01, 2025-11-01T09:00:00Z or 7d)",
+                input
+            ))
+        })
+}
+
+/// Parse the end of a time range like [`parse_time`], except that a bare
+/// date includes the whole day
+pub fn parse_time_end(input: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let time = parse_time(input, now)?;
+
+    if NaiveDate::parse_from_str(input.trim(), "%Y-%m-%d").is_ok() {
+        Ok(time + chrono::Duration::days(1))
+    } else {
+        Ok(time)
+    }
+}
+
+/// Which fields decide whether a new command duplicates an existing record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateKey {
+    /// Same command text in the same directory
+    #[default]
+    CommandAndDir,
+
+    /// Same command text, directory and exit code (failed and successful runs
+    /// are kept apart)
+    CommandDirAndExitCode,
+
+    /// Same command text, regardless of directory
+    Command,
+}
+
+/// A single execution of a stored command, used for sequence analysis
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Execution {
+    /// ID of the command record that was executed
+    pub command_id: i64,
+
+    /// The command text
+    pub command: String,
+
+    /// Working directory of the command record
+    pub working_dir: String,
+
+    /// When this execution happened
+    pub timestamp: DateTime<Utc>,
+
+    /// Exit code of this execution (the command's, for executions logged
+    /// before it was recorded per execution)
+    #[serde(default)]
+    pub exit_code: i32,
+
+    /// Duration of this execution in milliseconds (with the same fallback)
+    #[serde(default)]
+    pub duration_ms: i64,
+
+    /// Session, host and context reported by the shell hook
+    #[serde(default)]
+    pub meta: ExecutionMeta,
+}
+
+/// Optional per-execution metadata supplied by the capturing hook
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ExecutionMeta {
+    /// Identifier of the shell session (from `OMNISCIENT_SESSION`)
+    pub session: Option<String>,
+
+    /// Host the command ran on (from `--host`)
+    pub host: Option<String>,
+
+    /// Free-form context label (from `OMNISCIENT_CONTEXT`)
+    pub context: Option<String>,
+
+    /// Git branch checked out in the working directory
+    #[serde(default)]
+    pub branch: Option<String>,
+
+    /// Remote URL of the git repository containing the working directory
+    #[serde(default)]
+    pub repo: Option<String>,
+
+    /// tmux (or GNU screen) session the command ran in
+    #[serde(default)]
+    pub tmux_session: Option<String>,
+
+    /// tmux (or screen) window index
+    #[serde(default)]
+    pub tmux_window: Option<String>,
+
+    /// tmux pane ID (`$TMUX_PANE`, e.g. `%3`)
+    #[serde(default)]
+    pub tmux_pane: Option<String>,
+
+    /// Environment variables listed in `capture.env_vars` that were set
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+
+    /// Unique ID the client gave this capture (`--capture-id`); a capture
+    /// with an ID that is already stored is not stored again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_id: Option<String>,
+}
+
+impl ExecutionMeta {
+    /// Where in tmux the command ran, as `session:window %pane` (any part
+    /// may be missing)
+    pub fn tmux_location(&self) -> Option<String> {
+        let window = match (&self.tmux_session, &self.tmux_window) {
+            (Some(session), Some(window)) => Some(format!("{}:{}", session, window)),
+            (session, window) => session.clone().or_else(|| window.clone()),
+        };
+        match (window, &self.tmux_pane) {
+            (Some(window), Some(pane)) => Some(format!("{} {}", window, pane)),
+            (window, pane) => window.or_else(|| pane.clone()),
+        }
+    }
+}
+
+/// Everything stored about one command (`omniscient show`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommandDetail {
+    /// The command record itself
+    #[serde(flatten)]
+    pub record: CommandRecord,
+
+    /// Whether the command is bookmarked
+    pub bookmarked: bool,
+
+    /// Number of logged executions
+    pub execution_count: usize,
+
+    /// Most recent executions, newest first
+    pub executions: Vec<Execution>,
+
+    /// Output of the most recent execution that has some
+    pub output: Option<CommandOutput>,
+}
+
+/// A captured command that matched the alert watchlist
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Alert {
+    /// Unique identifier (database primary key)
+    pub id: Option<i64>,
+
+    /// ID of the command record (which may have been deleted since)
+    pub command_id: i64,
+
+    /// Name of the watchlist rule that matched
+    pub rule: String,
+
+    /// The command text
+    pub command: String,
+
+    /// Directory the command ran in
+    pub working_dir: String,
+
+    /// When the command ran
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Output captured from one execution (see `capture.output`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommandOutput {
+    /// ID of the execution that printed it
+    pub execution_id: i64,
+
+    /// When that execution happened
+    pub timestamp: DateTime<Utc>,
+
+    /// Combined stdout/stderr, possibly only its tail
+    pub text: String,
+
+    /// Whether earlier output was dropped to stay within `output_max_kb`
+    pub truncated: bool,
 }
 
 /// Statistics about command history
@@ -96,16 +390,32 @@ pub struct Stats {
     /// Number of failed commands (exit code != 0)
     pub failed_commands: usize,
 
-    /// Commands grouped by category with counts
-    pub by_category: Vec<CategoryStats>,
+    /// Commands grouped by category with counts, success rates and durations
+    pub by_category: Vec<CategoryBreakdown>,
 
     /// Date of oldest command
     pub oldest_command: Option<DateTime<Utc>>,
 
     /// Date of newest command
     pub newest_command: Option<DateTime<Utc>>,
+
+    /// Executions per day over the last [`STATS_DAYS`] days (days without
+    /// activity are left out; empty unless the trends were requested)
+    #[serde(default)]
+    pub daily: Vec<ActivityBucket>,
+
+    /// Commands run for the first time per week over the last
+    /// [`STATS_WEEKS`] weeks (empty unless the trends were requested)
+    #[serde(default)]
+    pub vocabulary: Vec<VocabularyBucket>,
 }
 
+/// Number of days of daily counts in [`Stats`]
+pub const STATS_DAYS: i64 = 30;
+
+/// Number of weeks of vocabulary growth in [`Stats`]
+pub const STATS_WEEKS: i64 = 12;
+
 impl Stats {
     /// Calculate success rate as a percentage
     pub fn success_rate(&self) -> f64 {
@@ -118,14 +428,486 @@ impl Stats {
 }
 
 /// Statistics for a single category
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct CategoryStats {
     pub category: String,
     pub count: usize,
 }
 
+/// Counts, success rate and average duration of a category (`omniscient stats`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CategoryBreakdown {
+    pub category: String,
+
+    /// Number of commands
+    pub count: usize,
+
+    /// Commands whose last run exited with 0
+    pub successful: usize,
+
+    /// Percentage of successful commands
+    pub success_rate: f64,
+
+    /// Average duration of the commands in milliseconds
+    pub avg_duration_ms: i64,
+
+    /// When a command of the category was last run
+    #[serde(default)]
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+/// Size of the buckets in an activity time series
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityPeriod {
+    /// One bucket per calendar day
+    Day,
+
+    /// One bucket per week, starting on Monday
+    Week,
+
+    /// One bucket per calendar month
+    Month,
+}
+
+impl ActivityPeriod {
+    /// Look up a period by name ("day", "week" or "month")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "day" | "daily" => Some(ActivityPeriod::Day),
+            "week" | "weekly" => Some(ActivityPeriod::Week),
+            "month" | "monthly" => Some(ActivityPeriod::Month),
+            _ => None,
+        }
+    }
+
+    /// First day of the bucket `date` falls in
+    pub fn start_of(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            ActivityPeriod::Day => date,
+            ActivityPeriod::Week => {
+                date - chrono::Duration::days(date.weekday().num_days_from_monday().into())
+            }
+            ActivityPeriod::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    /// First day of the bucket after the one starting on `start`
+    pub fn next(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            ActivityPeriod::Day => start + chrono::Duration::days(1),
+            ActivityPeriod::Week => start + chrono::Duration::weeks(1),
+            ActivityPeriod::Month => start + Months::new(1),
+        }
+    }
+}
+
+/// Executions within one day or week
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ActivityBucket {
+    /// First day of the bucket
+    pub start: NaiveDate,
+
+    /// Number of executions
+    pub executions: usize,
+
+    /// Number of executions that exited with 0
+    pub successful: usize,
+}
+
+impl ActivityBucket {
+    /// Success rate of the bucket as a percentage
+    pub fn success_rate(&self) -> f64 {
+        if self.executions == 0 {
+            0.0
+        } else {
+            (self.successful as f64 / self.executions as f64) * 100.0
+        }
+    }
+}
+
+/// Commands run for the first time within one day, week or month
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VocabularyBucket {
+    /// First day of the bucket
+    pub start: NaiveDate,
+
+    /// Number of commands first run in the bucket
+    pub new_commands: usize,
+
+    /// Number of distinct commands known at the end of the bucket
+    pub total: usize,
+}
+
+/// Execution activity over time
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ActivityReport {
+    /// Bucket size
+    pub period: ActivityPeriod,
+
+    /// Buckets with at least one execution, oldest first
+    pub buckets: Vec<ActivityBucket>,
+
+    /// Executions per weekday (Monday first) and hour of day, in the time
+    /// zone set with [`Storage::set_utc_offset`](crate::Storage::set_utc_offset)
+    pub heatmap: [[usize; 24]; 7],
+}
+
+impl ActivityReport {
+    /// Total number of executions in the report
+    pub fn total(&self) -> usize {
+        self.buckets.iter().map(|b| b.executions).sum()
+    }
+
+    /// The hour of day with the most executions, if there were any
+    pub fn busiest_hour(&self) -> Option<usize> {
+        (0..24)
+            .map(|hour| {
+                (
+                    hour,
+                    self.heatmap.iter().map(|day| day[hour]).sum::<usize>(),
+                )
+            })
+            .filter(|(_, count)| *count > 0)
+            .max_by_key(|(hour, count)| (*count, std::cmp::Reverse(*hour)))
+            .map(|(hour, _)| hour)
+    }
+}
+
+/// Statistics for a single git branch
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BranchStats {
+    /// Branch name
+    pub branch: String,
+
+    /// Remote URL of the repository, if known
+    pub repo: Option<String>,
+
+    /// Number of executions on the branch
+    pub executions: usize,
+
+    /// Number of distinct commands run on the branch
+    pub commands: usize,
+
+    /// When a command last ran on the branch
+    pub last_used: DateTime<Utc>,
+}
+
+/// Activity in a single working directory
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DirStats {
+    /// Working directory
+    pub working_dir: String,
+
+    /// Number of executions in the directory
+    pub runs: usize,
+
+    /// Number of distinct commands run in the directory
+    pub commands: usize,
+
+    /// Number of executions that exited with 0
+    pub successful: usize,
+
+    /// When a command last ran in the directory
+    pub last_used: DateTime<Utc>,
+
+    /// Total run time of the executions in milliseconds
+    #[serde(default)]
+    pub duration_ms: i64,
+
+    /// Most used categories in the directory, by executions
+    pub top_categories: Vec<CategoryStats>,
+}
+
+impl DirStats {
+    /// Success rate as a percentage
+    pub fn success_rate(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            (self.successful as f64 / self.runs as f64) * 100.0
+        }
+    }
+}
+
+/// Number of runs of one program (the first word of a command)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ToolUsage {
+    pub tool: String,
+    pub count: usize,
+}
+
+/// Number of runs of one stored command
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommandCount {
+    pub command_id: i64,
+    pub command: String,
+    pub runs: usize,
+}
+
+/// A year in review (`omniscient wrapped`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Wrapped {
+    /// Calendar year (UTC)
+    pub year: i32,
+
+    /// Number of executions
+    pub runs: usize,
+
+    /// Number of executions that exited with 0
+    pub successful: usize,
+
+    /// Number of distinct commands run
+    pub commands: usize,
+
+    /// Most run commands
+    pub top_commands: Vec<CommandCount>,
+
+    /// Command with the most failed runs
+    pub most_failed: Option<FailureStats>,
+
+    /// Command with the longest single run
+    pub longest_running: Option<DurationStats>,
+
+    /// Runs per category, most used first
+    pub categories: Vec<CategoryStats>,
+
+    /// Runs per month, January first
+    pub months: [usize; 12],
+
+    /// Number of days with at least one run
+    pub active_days: usize,
+
+    /// Longest run of consecutive active days, as (first day, length)
+    pub longest_streak: Option<(NaiveDate, usize)>,
+}
+
+/// Recap of the activity since a point in time (`omniscient report`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Summary {
+    /// Start of the summarized period
+    pub since: DateTime<Utc>,
+
+    /// Number of executions
+    pub runs: usize,
+
+    /// Number of executions that exited with 0
+    pub successful: usize,
+
+    /// Number of distinct commands run
+    pub commands: usize,
+
+    /// Number of commands run for the first time
+    pub new_command_count: usize,
+
+    /// Most used of the new commands
+    pub new_commands: Vec<CommandRecord>,
+
+    /// Most used programs
+    pub top_tools: Vec<ToolUsage>,
+
+    /// Directories with the most runs
+    pub busiest_dirs: Vec<DirStats>,
+
+    /// Commands with the longest average duration
+    pub slowest: Vec<DurationStats>,
+}
+
+impl Summary {
+    /// Success rate as a percentage
+    pub fn success_rate(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            (self.successful as f64 / self.runs as f64) * 100.0
+        }
+    }
+}
+
+/// How long one stored command takes across its executions
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DurationStats {
+    /// ID of the command record
+    pub command_id: i64,
+
+    /// The command text
+    pub command: String,
+
+    /// Working directory of the command record
+    pub working_dir: String,
+
+    /// Number of executions the figures are based on
+    pub runs: usize,
+
+    /// Average duration in milliseconds
+    pub avg_ms: i64,
+
+    /// Longest duration in milliseconds
+    pub max_ms: i64,
+
+    /// Shortest duration in milliseconds
+    pub min_ms: i64,
+}
+
+/// How often one stored command fails across its executions
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FailureStats {
+    /// ID of the command record
+    pub command_id: i64,
+
+    /// The command text
+    pub command: String,
+
+    /// Working directory of the command record
+    pub working_dir: String,
+
+    /// Number of executions
+    pub runs: usize,
+
+    /// Number of executions with a non-zero exit code
+    pub failures: usize,
+
+    /// When the command last failed
+    pub last_failed: Option<DateTime<Utc>>,
+}
+
+impl FailureStats {
+    /// Failure rate as a percentage
+    pub fn failure_rate(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            (self.failures as f64 / self.runs as f64) * 100.0
+        }
+    }
+}
+
+/// One failed execution of a stored command
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FailedRun {
+    /// ID of the command record
+    pub command_id: i64,
+
+    /// The command text
+    pub command: String,
+
+    /// Working directory of the command record
+    pub working_dir: String,
+
+    /// Non-zero exit code
+    pub exit_code: i32,
+
+    /// When the execution happened
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Number of failed executions with one exit code
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExitCodeCount {
+    pub exit_code: i32,
+    pub count: usize,
+}
+
+/// What a conventional shell exit code usually means, if it is well known
+pub fn exit_code_meaning(exit_code: i32) -> Option<&'static str> {
+    match exit_code {
+        1 => Some("general error"),
+        2 => Some("misuse of shell builtin / usage error"),
+        126 => Some("not executable"),
+        127 => Some("command not found"),
+        130 => Some("interrupted (Ctrl-C)"),
+        137 => Some("killed (SIGKILL, often out of memory)"),
+        141 => Some("broken pipe"),
+        143 => Some("terminated (SIGTERM)"),
+        _ => None,
+    }
+}
+
+/// Ordering for the slowest commands report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlowestBy {
+    /// Highest average duration first
+    Average,
+
+    /// Highest single duration first
+    Max,
+}
+
+/// Why the capture hook did not store a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The command was empty or whitespace only
+    Empty,
+
+    /// The command finished faster than `capture.min_duration_ms`
+    TooFast,
+
+    /// The command (or the alias typed for it) matched a redaction pattern
+    Redacted,
+
+    /// Capture was paused (`omniscient pause` or `OMNISCIENT_DISABLE`)
+    Paused,
+
+    /// The command ran under an ignored directory
+    /// (`.omniscientignore` or `privacy.ignore_dirs`)
+    Ignored,
+}
+
+impl SkipReason {
+    /// Every skip reason, in display order
+    pub const ALL: &'static [SkipReason] = &[
+        SkipReason::Empty,
+        SkipReason::TooFast,
+        SkipReason::Redacted,
+        SkipReason::Paused,
+        SkipReason::Ignored,
+    ];
+
+    /// Stable name used in storage and output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::Empty => "empty",
+            SkipReason::TooFast => "too_fast",
+            SkipReason::Redacted => "redacted",
+            SkipReason::Paused => "paused",
+            SkipReason::Ignored => "ignored",
+        }
+    }
+
+    /// Look up a reason by its stable name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|r| r.as_str() == name)
+    }
+
+    /// Explain the reason to the user
+    pub fn description(&self) -> &'static str {
+        match self {
+            SkipReason::Empty => "empty command",
+            SkipReason::TooFast => "faster than capture.min_duration_ms",
+            SkipReason::Redacted => "matched a privacy.redact_patterns entry",
+            SkipReason::Paused => "capture was paused",
+            SkipReason::Ignored => "run under an ignored directory",
+        }
+    }
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// How often the capture hook skipped commands for one reason
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkipCount {
+    pub reason: SkipReason,
+    pub count: u64,
+    pub last_skipped: DateTime<Utc>,
+}
+
 /// Query parameters for searching commands
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SearchQuery {
     /// Text to search for (optional)
     pub text: Option<String>,
@@ -142,9 +924,59 @@ pub struct SearchQuery {
     /// Include subdirectories when filtering by working_dir
     pub recursive: bool,
 
+    /// Only commands run at least once with these environment variables set
+    /// to these values
+    pub env: Vec<(String, String)>,
+
+    /// Only commands run at least once on this git branch
+    pub branch: Option<String>,
+
+    /// Only commands run at least once on this host (see `--host` of
+    /// `omniscient capture` and `omniscient remote-sync`)
+    pub host: Option<String>,
+
+    /// Only commands run at least once in this tmux (or screen) session
+    pub tmux_session: Option<String>,
+
+    /// Only commands run at least once in this tmux pane (`%3`)
+    pub tmux_pane: Option<String>,
+
+    /// Match each word of `text` as a prefix (`git chec` finds `git checkout`)
+    pub prefix: bool,
+
+    /// Match `text` anywhere in the command, ignoring case, instead of as
+    /// whole words (slower: skips the full-text index)
+    pub ignore_case: bool,
+
+    /// Leave out commands containing any of these terms (ignoring case)
+    pub exclude: Vec<String>,
+
+    /// Leave out commands in any of these categories
+    pub exclude_categories: Vec<String>,
+
+    /// Only commands that took at least this long (milliseconds)
+    pub min_duration_ms: Option<i64>,
+
+    /// Only commands that took at most this long (milliseconds)
+    pub max_duration_ms: Option<i64>,
+
+    /// Only commands that exited with this code at least once (e.g. 137 for
+    /// commands killed by the OOM killer)
+    pub exit_code: Option<i32>,
+
+    /// Only commands running this program (`kubectl`, also behind `sudo`
+    /// or after `cd x &&`)
+    pub program: Option<String>,
+
+    /// Only commands given all of these flags (`-n`, `-n prod`, `--output=json`)
+    pub flags: Vec<String>,
+
     /// Maximum number of results
     pub limit: usize,
 
+    /// Number of results to skip (for paging through results)
+    pub offset: usize,
+
     /// How to order results
     pub order_by: OrderBy,
 }
@@ -157,18 +989,38 @@ impl Default for SearchQuery {
             success_only: None,
             working_dir: None,
             recursive: false,
+            env: Vec::new(),
+            branch: None,
+            host: None,
+            tmux_session: None,
+            tmux_pane: None,
+            prefix: false,
+            ignore_case: false,
+            exclude: Vec::new(),
+            exclude_categories: Vec::new(),
+            min_duration_ms: None,
+            max_duration_ms: None,
+            exit_code: None,
+            program: None,
+            flags: Vec::new(),
             limit: 20,
+            offset: 0,
             order_by: OrderBy::Timestamp,
         }
     }
 }
 
 /// Ordering options for search results
-#[derive(Debug, Clone, Copy)]
+///
+/// Bookmarked commands come first in every ordering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum OrderBy {
     /// Most recent first
     Timestamp,
 
+    /// Most recently used first
+    LastUsed,
+
     /// Most frequently used first
     UsageCount,
 
@@ -256,6 +1108,114 @@ mod tests {
         assert_eq!(cmd.duration_display(), "2m5s");
     }
 
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("250").unwrap(), 250);
+        assert_eq!(parse_duration("500ms").unwrap(), 500);
+        assert_eq!(parse_duration("2s").unwrap(), 2_000);
+        assert_eq!(parse_duration("1.5s").unwrap(), 1_500);
+        assert_eq!(parse_duration("5m").unwrap(), 300_000);
+        assert_eq!(parse_duration(" 1h30m ").unwrap(), 5_400_000);
+        assert_eq!(parse_duration("1d").unwrap(), 86_400_000);
+
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("-5").is_err());
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("1..2s").is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let now = "2025-11-10T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        assert_eq!(
+            parse_time("2025-11-01", now).unwrap().to_rfc3339(),
+            "2025-11-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            parse_time("2025-11-01T09:30:00+02:00", now)
+                .unwrap()
+                .to_rfc3339(),
+            "2025-11-01T07:30:00+00:00"
+        );
+        assert_eq!(
+            parse_time("7d", now).unwrap().to_rfc3339(),
+            "2025-11-03T12:00:00+00:00"
+        );
+        assert_eq!(
+            parse_time_end("2025-11-01", now).unwrap().to_rfc3339(),
+            "2025-11-02T00:00:00+00:00"
+        );
+        assert!(parse_time("last tuesday", now).is_err());
+    }
+
+    #[test]
+    fn test_busiest_hour() {
+        let mut report = ActivityReport {
+            period: ActivityPeriod::Day,
+            buckets: Vec::new(),
+            heatmap: [[0; 24]; 7],
+        };
+        assert_eq!(report.busiest_hour(), None);
+
+        report.heatmap[0][9] = 3;
+        report.heatmap[4][14] = 2;
+        report.heatmap[5][14] = 2;
+        assert_eq!(report.busiest_hour(), Some(14));
+    }
+
+    #[test]
+    fn test_period_buckets() {
+        let day = |d: &str| d.parse::<NaiveDate>().unwrap();
+        // A Thursday
+        let date = day("2025-01-30");
+
+        assert_eq!(ActivityPeriod::Day.start_of(date), date);
+        assert_eq!(ActivityPeriod::Week.start_of(date), day("2025-01-27"));
+        assert_eq!(ActivityPeriod::Month.start_of(date), day("2025-01-01"));
+
+        assert_eq!(
+            ActivityPeriod::Week.next(day("2025-01-27")),
+            day("2025-02-03")
+        );
+        assert_eq!(
+            ActivityPeriod::Month.next(day("2025-01-01")),
+            day("2025-02-01")
+        );
+        assert_eq!(
+            ActivityPeriod::from_name("monthly"),
+            Some(ActivityPeriod::Month)
+        );
+    }
+
+    #[test]
+    fn test_humanize_duration() {
+        assert_eq!(humanize_duration(0), "0ms");
+        assert_eq!(humanize_duration(2_500), "2.5s");
+        assert_eq!(humanize_duration(60_000), "1m0s");
+        assert_eq!(humanize_duration(3_780_000), "1h3m");
+    }
+
+    #[test]
+    fn test_longest_streak() {
+        let day = |d: &str| d.parse::<NaiveDate>().unwrap();
+        assert_eq!(longest_streak(&[]), None);
+        assert_eq!(
+            longest_streak(&[day("2025-03-01")]),
+            Some((day("2025-03-01"), 1))
+        );
+        let days = [
+            day("2025-02-27"),
+            day("2025-02-28"),
+            day("2025-03-02"),
+            day("2025-03-03"),
+            day("2025-03-04"),
+            day("2025-03-10"),
+        ];
+        assert_eq!(longest_streak(&days), Some((day("2025-03-02"), 3)));
+    }
+
     #[test]
     fn test_stats_success_rate() {
         let stats = Stats {
@@ -265,6 +1225,8 @@ mod tests {
             by_category: vec![],
             oldest_command: None,
             newest_command: None,
+            daily: vec![],
+            vocabulary: vec![],
         };
 
         assert_eq!(stats.success_rate(), 85.0);
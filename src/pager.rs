@@ -0,0 +1,173 @@
+/// Paging of long listings
+///
+/// Listing commands write into a [`Pager`] instead of stdout. When stdout is
+/// a terminal and the output is taller than it, the output is shown through
+/// `$PAGER` (`less` by default) or, if that can't be started, a built-in
+/// pager that shows one screen at a time.
+use crate::error::Result;
+use crate::format::terminal_size;
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// Pager used when `$PAGER` is not set
+const DEFAULT_PAGER: &str = "less";
+
+/// `less` options used unless `$LESS` is set: keep colors (R) and don't
+/// clear the screen on exit (X)
+const DEFAULT_LESS: &str = "RX";
+
+/// Terminal height assumed when it can't be determined
+const DEFAULT_HEIGHT: usize = 24;
+
+/// Buffered output that is paged when it doesn't fit on the screen
+#[derive(Debug, Default)]
+pub struct Pager {
+    buffer: Vec<u8>,
+    enabled: bool,
+}
+
+impl Pager {
+    /// Create a pager; output is only paged if `enabled` and stdout is a
+    /// terminal
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            buffer: Vec::new(),
+            enabled: enabled && io::stdout().is_terminal(),
+        }
+    }
+
+    /// Show the buffered output
+    pub fn finish(self) -> Result<()> {
+        let text = String::from_utf8_lossy(&self.buffer);
+        let height = terminal_height();
+
+        if !self.enabled || !needs_paging(&text, height) {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&self.buffer)?;
+            return Ok(stdout.flush()?);
+        }
+
+        if run_external(&text).is_err() {
+            page_builtin(&text, height)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for Pager {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Whether `text` is taller than a terminal of `height` lines
+pub fn needs_paging(text: &str, height: usize) -> bool {
+    text.lines().count() >= height
+}
+
+/// Program and arguments of the pager to run
+///
+/// `$PAGER` is split on whitespace (e.g. `less -S`); an empty or unset
+/// value falls back to `less`.
+pub fn pager_command(pager: Option<&str>) -> (String, Vec<String>) {
+    let mut words = pager
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string);
+
+    match words.next() {
+        Some(program) => (program, words.collect()),
+        None => (DEFAULT_PAGER.to_string(), Vec::new()),
+    }
+}
+
+/// Pipe `text` through the external pager
+fn run_external(text: &str) -> io::Result<()> {
+    let (program, args) = pager_command(env::var("PAGER").ok().as_deref());
+    let mut command = Command::new(program);
+    command.args(args).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", DEFAULT_LESS);
+    }
+
+    let mut child = command.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The user may quit the pager before reading everything
+        match stdin.write_all(text.as_bytes()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+            _ => {}
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// Show `text` one screen at a time, waiting for Enter between screens
+fn page_builtin(text: &str, height: usize) -> Result<()> {
+    let lines: Vec<&str> = text.lines().collect();
+    let page_size = height.saturating_sub(1).max(1);
+    let mut stdout = io::stdout().lock();
+    let stdin = io::stdin();
+
+    for (index, chunk) in lines.chunks(page_size).enumerate() {
+        for line in chunk {
+            writeln!(stdout, "{}", line)?;
+        }
+        if (index + 1) * page_size >= lines.len() {
+            break;
+        }
+
+        write!(stdout, "-- More -- (Enter for more, q to quit) ")?;
+        stdout.flush()?;
+        let mut answer = String::new();
+        if stdin.lock().read_line(&mut answer)? == 0 || answer.trim() == "q" {
+            break;
+        }
+    }
+
+    Ok(stdout.flush()?)
+}
+
+/// Number of lines of the terminal, from `$LINES` or `stty size`
+fn terminal_height() -> usize {
+    env::var("LINES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .or_else(|| terminal_size().map(|(rows, _)| rows))
+        .unwrap_or(DEFAULT_HEIGHT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pager_command() {
+        assert_eq!(pager_command(None), ("less".to_string(), Vec::new()));
+        assert_eq!(pager_command(Some("  ")), ("less".to_string(), Vec::new()));
+        assert_eq!(
+            pager_command(Some("less -S")),
+            ("less".to_string(), vec!["-S".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_needs_paging() {
+        assert!(!needs_paging("a\nb\n", 24));
+        assert!(needs_paging(&"line\n".repeat(24), 24));
+    }
+
+    #[test]
+    fn test_disabled_pager_buffers() {
+        let mut pager = Pager::new(false);
+        write!(pager, "hello").unwrap();
+        assert_eq!(pager.buffer, b"hello");
+        assert!(!pager.enabled);
+    }
+}
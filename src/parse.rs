@@ -0,0 +1,286 @@
+/// Structured view of a command line: program, subcommand, flags, arguments
+///
+/// Commands are split into words the way a POSIX shell would (quotes and
+/// escapes), without expanding anything. Of a pipeline or list the primary
+/// command is parsed: the first one that isn't setup like `cd`, after
+/// wrappers such as `sudo` or `env FOO=1` (see [`Categorizer`]). The
+/// program, subcommand and words are stored next to each command so
+/// searches can filter on them (`--program kubectl --flag "-n prod"`).
+use crate::category::Categorizer;
+
+/// Separator between stored tokens, which commands practically never contain
+pub const TOKEN_SEPARATOR: char = '\u{1f}';
+
+/// Programs whose first argument is a subcommand (`git commit`, `kubectl get`)
+const SUBCOMMAND_PROGRAMS: &[&str] = &[
+    "apt",
+    "apt-get",
+    "aws",
+    "az",
+    "brew",
+    "bun",
+    "cargo",
+    "dnf",
+    "docker",
+    "docker-compose",
+    "gcloud",
+    "gem",
+    "gh",
+    "git",
+    "go",
+    "helm",
+    "just",
+    "kind",
+    "kubectl",
+    "make",
+    "minikube",
+    "npm",
+    "omniscient",
+    "pip",
+    "pip3",
+    "pnpm",
+    "podman",
+    "pulumi",
+    "rustup",
+    "systemctl",
+    "terraform",
+    "terragrunt",
+    "yarn",
+    "yum",
+];
+
+/// A command split into its parts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedCommand {
+    /// Program name without its path (`kubectl`); empty for blank commands
+    pub program: String,
+
+    /// First argument of programs that take subcommands (`get`)
+    pub subcommand: Option<String>,
+
+    /// Options as written (`-n`, `--all`, `--output=json`), up to `--`
+    pub flags: Vec<String>,
+
+    /// Remaining words, including option values (which options take a
+    /// value depends on the program)
+    pub args: Vec<String>,
+
+    /// Every word, program first
+    pub tokens: Vec<String>,
+}
+
+impl ParsedCommand {
+    /// Parse the primary command of a command line
+    pub fn parse(command: &str) -> Self {
+        let segments = Categorizer::segments(command);
+        let Some(primary) = segments
+            .iter()
+            .find(|segment| !Categorizer::is_setup(segment))
+            .or(segments.first())
+        else {
+            return Self::default();
+        };
+
+        let tokens = tokenize(Categorizer::unwrap(primary));
+        let Some(first) = tokens.first() else {
+            return Self::default();
+        };
+        let program = first.rsplit('/').next().unwrap_or(first).to_string();
+
+        let mut parsed = Self {
+            program,
+            ..Default::default()
+        };
+        let mut options_ended = false;
+        for token in &tokens[1..] {
+            if !options_ended && token == "--" {
+                options_ended = true;
+            } else if !options_ended && token.len() > 1 && token.starts_with('-') {
+                parsed.flags.push(token.clone());
+            } else if parsed.subcommand.is_none()
+                && parsed.args.is_empty()
+                && SUBCOMMAND_PROGRAMS.contains(&parsed.program.as_str())
+            {
+                parsed.subcommand = Some(token.clone());
+            } else {
+                parsed.args.push(token.clone());
+            }
+        }
+
+        parsed.tokens = tokens;
+        parsed
+    }
+
+    /// The tokens as stored in the database: joined and surrounded by
+    /// [`TOKEN_SEPARATOR`] so every word can be matched exactly
+    pub fn stored_tokens(&self) -> String {
+        let mut stored = String::from(TOKEN_SEPARATOR);
+        for token in &self.tokens {
+            stored.extend(token.chars().filter(|&c| c != TOKEN_SEPARATOR));
+            stored.push(TOKEN_SEPARATOR);
+        }
+        stored
+    }
+
+    /// Whether the command was given a flag, optionally with a value
+    /// (`-n`, `-n prod` or `-n=prod`; see [`flag_patterns`])
+    pub fn has_flag(&self, flag: &str) -> bool {
+        let stored = self.stored_tokens();
+        flag_patterns(flag)
+            .iter()
+            .any(|pattern| stored.contains(pattern.as_str()))
+    }
+}
+
+/// Substrings of [`ParsedCommand::stored_tokens`] that mean a command was
+/// given `flag`
+///
+/// `-n` matches `-n` on its own or with an `=value`; `-n prod` and
+/// `-n=prod` match `-n prod` and `-n=prod`.
+pub fn flag_patterns(flag: &str) -> Vec<String> {
+    let flag = flag.trim();
+    let sep = TOKEN_SEPARATOR;
+    let split = flag
+        .split_once(char::is_whitespace)
+        .or_else(|| flag.split_once('='));
+
+    match split {
+        Some((name, value)) => {
+            let value = value.trim();
+            vec![
+                format!("{sep}{name}{sep}{value}{sep}"),
+                format!("{sep}{name}={value}{sep}"),
+            ]
+        }
+        None => vec![format!("{sep}{flag}{sep}"), format!("{sep}{flag}=")],
+    }
+}
+
+/// Split a simple command into words like a POSIX shell
+///
+/// Single quotes keep everything literally, double quotes allow `\"`,
+/// `\\`, `\$` and `` \` `` escapes, and a backslash outside quotes escapes
+/// the next character (a backslash-newline joins lines). An unclosed quote
+/// extends to the end. Variables, globs and substitutions are kept as
+/// written.
+pub fn tokenize(command: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_token = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    current.push(c);
+                }
+            }
+            '"' => {
+                in_token = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' if matches!(chars.peek(), Some('"' | '\\' | '$' | '`')) => {
+                            current.extend(chars.next());
+                        }
+                        c => current.push(c),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') | None => {}
+                Some(next) => {
+                    in_token = true;
+                    current.push(next);
+                }
+            },
+            c if c.is_whitespace() => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            c => {
+                in_token = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if in_token {
+        tokens.push(current);
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(list: &[&str]) -> Vec<String> {
+        list.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize(r#"git commit -m "fix: handle \"quotes\"" --amend"#),
+            words(&["git", "commit", "-m", "fix: handle \"quotes\"", "--amend"])
+        );
+        assert_eq!(
+            tokenize("echo 'a  b' c\\ d \"$HOME\" ''"),
+            words(&["echo", "a  b", "c d", "$HOME", ""])
+        );
+        assert_eq!(
+            tokenize("docker run \\\n  --rm alpine"),
+            words(&["docker", "run", "--rm", "alpine"])
+        );
+        assert_eq!(tokenize("echo 'unclosed x"), words(&["echo", "unclosed x"]));
+        assert!(tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed = ParsedCommand::parse("kubectl get pods -n prod --watch -- -x");
+        assert_eq!(parsed.program, "kubectl");
+        assert_eq!(parsed.subcommand.as_deref(), Some("get"));
+        assert_eq!(parsed.flags, words(&["-n", "--watch"]));
+        assert_eq!(parsed.args, words(&["pods", "prod", "-x"]));
+
+        let parsed = ParsedCommand::parse("cd ~/api && sudo -u app /usr/bin/git push origin");
+        assert_eq!(parsed.program, "git");
+        assert_eq!(parsed.subcommand.as_deref(), Some("push"));
+        assert_eq!(parsed.args, words(&["origin"]));
+
+        // Programs without subcommands
+        let parsed = ParsedCommand::parse("ls -la src");
+        assert_eq!(parsed.subcommand, None);
+        assert_eq!(parsed.args, words(&["src"]));
+
+        assert_eq!(ParsedCommand::parse("cd /tmp").program, "cd");
+        assert_eq!(ParsedCommand::parse(""), ParsedCommand::default());
+    }
+
+    #[test]
+    fn test_flags() {
+        let parsed = ParsedCommand::parse("kubectl -n prod get pods --output=json");
+        assert!(parsed.has_flag("-n"));
+        assert!(parsed.has_flag("-n prod"));
+        assert!(parsed.has_flag("-n=prod"));
+        assert!(parsed.has_flag("--output json"));
+        assert!(parsed.has_flag("--output"));
+        assert!(!parsed.has_flag("-n staging"));
+        assert!(!parsed.has_flag("-N"));
+        assert!(!parsed.has_flag("--out"));
+
+        assert_eq!(
+            ParsedCommand::parse("git status").stored_tokens(),
+            "\u{1f}git\u{1f}status\u{1f}"
+        );
+    }
+}
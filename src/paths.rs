@@ -0,0 +1,402 @@
+/// Working directory rewriting - collapses aliases of the same directory
+///
+/// Symlinked home directories, bind mounts and WSL drive mounts make one
+/// directory show up under several paths, which splits its history. Symlinks
+/// are resolved, then rewrite rules map each alias prefix onto one canonical
+/// prefix (for aliases a symlink can't explain, like bind mounts or the same
+/// project checked out in several places). A `*` in a rule matches within one
+/// path component, so `/Volumes/*/work` covers every mounted volume.
+///
+/// Directories are kept with `/` separators on every platform; Windows paths
+/// such as `C:\Users\me\code` become `C:/Users/me/code`.
+use crate::config::Config;
+use crate::error::Result;
+use crate::models::DuplicateKey;
+use crate::storage::Storage;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Applies prefix rewrite rules to working directories
+#[derive(Debug, Clone, Default)]
+pub struct PathRewriter {
+    /// (from components, to) pairs with `~` expanded, most specific first
+    rules: Vec<(Vec<String>, String)>,
+
+    /// Whether symlinks are resolved before the rules are applied
+    resolve_symlinks: bool,
+}
+
+impl PathRewriter {
+    /// Create a rewriter from a `from -> to` map
+    ///
+    /// A leading `~` on either side is expanded to `home`. A trailing `/*` is
+    /// the same as none: rules always carry over the rest of the path.
+    pub fn new(rewrites: &BTreeMap<String, String>, home: &str) -> Self {
+        let expand = |path: &str| -> String {
+            let path = normalize_separators(path);
+            let path = path.trim_end_matches('/');
+            let path = path.strip_suffix("/*").unwrap_or(path);
+            if path == "~" {
+                home.to_string()
+            } else if let Some(rest) = path.strip_prefix("~/") {
+                format!("{}/{}", home.trim_end_matches('/'), rest)
+            } else {
+                path.to_string()
+            }
+        };
+
+        let mut rules: Vec<(Vec<String>, String)> = rewrites
+            .iter()
+            .map(|(from, to)| (expand(from), expand(to)))
+            .filter(|(from, to)| !from.is_empty() && from != to)
+            .map(|(from, to)| (from.split('/').map(str::to_string).collect(), to))
+            .collect();
+
+        // The most specific rule wins: the longest, then the one with the
+        // fewest wildcards
+        rules.sort_by_key(|(from, _)| {
+            let literal = from.iter().filter(|part| !part.contains('*')).count();
+            std::cmp::Reverse((from.len(), literal))
+        });
+
+        Self {
+            rules,
+            resolve_symlinks: false,
+        }
+    }
+
+    /// Resolve symlinks in directories that exist before rewriting them
+    pub fn with_symlink_resolution(mut self) -> Self {
+        self.resolve_symlinks = true;
+        self
+    }
+
+    /// Create a rewriter from the `[paths]` config section
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let home = Config::home_dir()?;
+        let rewriter = Self::new(&config.paths.rewrites, &home.to_string_lossy());
+        Ok(if config.paths.resolve_symlinks {
+            rewriter.with_symlink_resolution()
+        } else {
+            rewriter
+        })
+    }
+
+    /// Whether the rewriter leaves every path as it is
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && !self.resolve_symlinks
+    }
+
+    /// Canonical form of a working directory: symlinks resolved (if enabled
+    /// and the directory still exists), separators normalized, trailing
+    /// slashes removed and the rewrite rules applied
+    pub fn canonicalize(&self, path: &str) -> String {
+        let resolved = match fs::canonicalize(path) {
+            Ok(real) if self.resolve_symlinks => real.to_string_lossy().into_owned(),
+            _ => path.to_string(),
+        };
+        let resolved = normalize_separators(&resolved);
+        match resolved.trim_end_matches('/') {
+            "" if resolved.starts_with('/') => "/".to_string(),
+            // A drive root keeps its slash
+            drive if drive.len() == 2 && drive.ends_with(':') && resolved.len() > 2 => {
+                format!("{}/", drive)
+            }
+            trimmed => self.rewrite(trimmed),
+        }
+    }
+
+    /// Rewrite a path using the most specific matching prefix rule
+    ///
+    /// Prefixes only match whole path components, so `/mnt/c/Users/me` does
+    /// not rewrite `/mnt/c/Users/mel`.
+    pub fn rewrite(&self, path: &str) -> String {
+        let parts: Vec<&str> = path.split('/').collect();
+
+        for (from, to) in &self.rules {
+            if from.len() > parts.len()
+                || !from
+                    .iter()
+                    .zip(&parts)
+                    .all(|(pattern, part)| component_matches(pattern, part))
+            {
+                continue;
+            }
+
+            let rest = &parts[from.len()..];
+            return if rest.is_empty() {
+                to.clone()
+            } else {
+                format!("{}/{}", to, rest.join("/"))
+            };
+        }
+
+        path.to_string()
+    }
+
+    /// Canonicalize the working directories of existing records
+    ///
+    /// Records that end up duplicating a command already stored under the
+    /// canonical path (under `key`, `capture.duplicate_key`) are merged into
+    /// it. Returns the number of records moved. Runs in one transaction, so
+    /// an interrupted run leaves every record where it was.
+    pub fn unify(&self, storage: &Storage, key: DuplicateKey) -> Result<usize> {
+        storage.with_tx(|| {
+            let mut moved = 0;
+
+            for dir in storage.get_working_dirs()? {
+                let canonical = self.canonicalize(&dir);
+                if canonical == dir {
+                    continue;
+                }
+
+                for id in storage.get_ids_in_dir(&dir)? {
+                    if storage.promote(id, &canonical, true, key)?.is_some() {
+                        moved += 1;
+                    }
+                }
+            }
+
+            Ok(moved)
+        })
+    }
+}
+
+/// A directory with `/` separators
+///
+/// On Windows backslashes become slashes and the `\\?\` prefix of
+/// canonical paths is dropped, so a directory matches however it was typed.
+/// Elsewhere a backslash is an ordinary file name character and the path is
+/// kept as it is.
+pub fn normalize_separators(path: &str) -> String {
+    if cfg!(windows) {
+        windows_to_slashes(path)
+    } else {
+        path.to_string()
+    }
+}
+
+fn windows_to_slashes(path: &str) -> String {
+    let path = match path.strip_prefix(r"\\?\UNC\") {
+        Some(share) => format!(r"\\{}", share),
+        None => path.strip_prefix(r"\\?\").unwrap_or(path).to_string(),
+    };
+    path.replace('\\', "/")
+}
+
+/// Match one path component against a pattern where `*` stands for any
+/// run of characters
+fn component_matches(pattern: &str, component: &str) -> bool {
+    if !pattern.contains('*') {
+        return pattern == component;
+    }
+
+    let mut pieces = pattern.split('*');
+    let first = pieces.next().unwrap_or_default();
+    let Some(mut rest) = component.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut pieces: Vec<&str> = pieces.collect();
+    let last = pieces.pop().unwrap_or_default();
+    for piece in pieces {
+        match rest.find(piece) {
+            Some(index) => rest = &rest[index + piece.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CommandRecord;
+    use chrono::Utc;
+    use tempfile::NamedTempFile;
+
+    fn rewriter(rules: &[(&str, &str)]) -> PathRewriter {
+        let map = rules
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect();
+        PathRewriter::new(&map, "/home/me")
+    }
+
+    #[test]
+    fn test_rewrite_prefix() {
+        let rewriter = rewriter(&[("/mnt/c/Users/me", "~"), ("/private/var", "/var")]);
+
+        assert_eq!(rewriter.rewrite("/mnt/c/Users/me"), "/home/me");
+        assert_eq!(
+            rewriter.rewrite("/mnt/c/Users/me/code/api"),
+            "/home/me/code/api"
+        );
+        assert_eq!(rewriter.rewrite("/private/var/tmp"), "/var/tmp");
+        assert_eq!(rewriter.rewrite("/srv/app"), "/srv/app");
+    }
+
+    #[test]
+    fn test_rewrite_matches_whole_components() {
+        let rewriter = rewriter(&[("/mnt/c/Users/me", "~")]);
+        assert_eq!(rewriter.rewrite("/mnt/c/Users/mel"), "/mnt/c/Users/mel");
+    }
+
+    #[test]
+    fn test_wildcard_rules() {
+        let rules = rewriter(&[
+            ("/Volumes/*/work/*", "~/work"),
+            ("/home/*/work", "~/work"),
+            ("/srv/checkouts/api-*", "~/work/api"),
+        ]);
+
+        assert_eq!(
+            rules.rewrite("/Volumes/External/work/api/src"),
+            "/home/me/work/api/src"
+        );
+        assert_eq!(rules.rewrite("/home/ci/work/api"), "/home/me/work/api");
+        assert_eq!(
+            rules.rewrite("/srv/checkouts/api-v2/cmd"),
+            "/home/me/work/api/cmd"
+        );
+        // Wildcards stay within one component
+        assert_eq!(rules.rewrite("/Volumes/a/b/work"), "/Volumes/a/b/work");
+        assert_eq!(rules.rewrite("/srv/checkouts/web"), "/srv/checkouts/web");
+
+        // Literal rules beat wildcard rules of the same length
+        let specific = rewriter(&[("/home/*/src", "/src"), ("/home/me/src", "~/code")]);
+        assert_eq!(specific.rewrite("/home/me/src/x"), "/home/me/code/x");
+        assert_eq!(specific.rewrite("/home/ci/src/x"), "/src/x");
+    }
+
+    #[test]
+    fn test_component_matches() {
+        assert!(component_matches("api-*", "api-v2"));
+        assert!(component_matches("*", ""));
+        assert!(component_matches("a*b*c", "aXbYc"));
+        assert!(!component_matches("a*b*c", "aXc"));
+        assert!(!component_matches("ab*ba", "aba"));
+        assert!(!component_matches("api", "api-v2"));
+    }
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        let rewriter = rewriter(&[("/mnt/data", "/data"), ("/mnt/data/home", "~")]);
+        assert_eq!(rewriter.rewrite("/mnt/data/home/src"), "/home/me/src");
+        assert_eq!(rewriter.rewrite("/mnt/data/x"), "/data/x");
+    }
+
+    #[test]
+    fn test_windows_separators() {
+        assert_eq!(windows_to_slashes(r"C:\Users\me\code"), "C:/Users/me/code");
+        assert_eq!(windows_to_slashes(r"\\?\C:\Users\me"), "C:/Users/me");
+        assert_eq!(
+            windows_to_slashes(r"\\?\UNC\server\share\src"),
+            "//server/share/src"
+        );
+        assert_eq!(windows_to_slashes("/home/me/code"), "/home/me/code");
+
+        // Drive roots keep their slash
+        assert_eq!(PathRewriter::default().canonicalize("C:/"), "C:/");
+        assert_eq!(PathRewriter::default().canonicalize("C:/code/"), "C:/code");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_canonicalize_resolves_symlinks() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let real = dir.path().join("real");
+        let link = dir.path().join("link");
+        fs::create_dir(&real).unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        let link = link.to_string_lossy();
+        let real = fs::canonicalize(&real).unwrap();
+
+        // Off unless enabled
+        assert_eq!(PathRewriter::default().canonicalize(&link), link);
+
+        let resolving = PathRewriter::default().with_symlink_resolution();
+        assert_eq!(resolving.canonicalize(&link), real.to_string_lossy());
+        assert_eq!(
+            resolving.canonicalize(&format!("{}/", link)),
+            real.to_string_lossy()
+        );
+        // Directories that no longer exist are kept as they are
+        assert_eq!(
+            resolving.canonicalize("/nonexistent/dir/"),
+            "/nonexistent/dir"
+        );
+        assert_eq!(resolving.canonicalize("/"), "/");
+
+        // Rewrite rules apply to the resolved path
+        let rules = rewriter(&[("/nonexistent", "/srv")]).with_symlink_resolution();
+        assert_eq!(rules.canonicalize("/nonexistent/dir"), "/srv/dir");
+    }
+
+    #[test]
+    fn test_unify_merges_existing_rows() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let storage = Storage::new(temp_file.path()).unwrap();
+
+        let record = |dir: &str, usage: i32| {
+            let mut cmd = CommandRecord::new(
+                "make".to_string(),
+                Utc::now(),
+                0,
+                10,
+                dir.to_string(),
+                "build".to_string(),
+            );
+            cmd.usage_count = usage;
+            cmd
+        };
+        storage.insert(&record("/home/me/proj", 2)).unwrap();
+        storage.insert(&record("/mnt/c/Users/me/proj", 3)).unwrap();
+
+        let moved = rewriter(&[("/mnt/c/Users/me", "~")])
+            .unify(&storage, DuplicateKey::CommandAndDir)
+            .unwrap();
+        assert_eq!(moved, 1);
+
+        let records = storage.get_all().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].working_dir, "/home/me/proj");
+        assert_eq!(records[0].usage_count, 5);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unify_resolves_symlinks_without_rules() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let real = temp_dir.path().join("real");
+        let link = temp_dir.path().join("link");
+        fs::create_dir(&real).unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        let real = fs::canonicalize(&real).unwrap();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let storage = Storage::new(temp_file.path()).unwrap();
+        for dir in [&real, &link] {
+            let cmd = CommandRecord::new(
+                "make".to_string(),
+                Utc::now(),
+                0,
+                10,
+                dir.to_string_lossy().into_owned(),
+                "build".to_string(),
+            );
+            storage.insert(&cmd).unwrap();
+        }
+
+        let moved = PathRewriter::default()
+            .with_symlink_resolution()
+            .unify(&storage, DuplicateKey::CommandAndDir)
+            .unwrap();
+        assert_eq!(moved, 1);
+
+        let records = storage.get_all().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].working_dir, real.to_string_lossy());
+        assert_eq!(records[0].usage_count, 2);
+    }
+}
@@ -0,0 +1,144 @@
+/// Capture pausing - temporarily stops commands from being recorded
+///
+/// `omniscient pause` writes a marker file next to the database holding an
+/// optional expiry time; `omniscient resume` (or the expiry) removes it.
+/// Setting `OMNISCIENT_DISABLE` disables capture for a single shell instead.
+use crate::config::Config;
+use crate::error::{OmniscientError, Result};
+use chrono::{DateTime, Utc};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Environment variable that disables capture when set (to anything but `0`)
+pub const DISABLE_ENV: &str = "OMNISCIENT_DISABLE";
+
+/// Whether capture is currently paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseState {
+    /// Commands are captured
+    Running,
+
+    /// Capture is paused until `omniscient resume`
+    Paused,
+
+    /// Capture is paused until the given time
+    PausedUntil(DateTime<Utc>),
+}
+
+impl PauseState {
+    /// Whether commands are currently being skipped
+    pub fn is_paused(&self) -> bool {
+        !matches!(self, PauseState::Running)
+    }
+}
+
+/// The pause marker for one database
+#[derive(Debug, Clone)]
+pub struct Pause {
+    path: PathBuf,
+}
+
+impl Pause {
+    /// Use the marker at `path`
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Use the marker belonging to the configured database (`<db>.paused`)
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut path = config.database_path()?.into_os_string();
+        path.push(".paused");
+        Ok(Self::new(path))
+    }
+
+    /// Whether `OMNISCIENT_DISABLE` disables capture in this process
+    pub fn disabled_by_env() -> bool {
+        env::var(DISABLE_ENV).is_ok_and(|value| !value.is_empty() && value != "0")
+    }
+
+    /// Pause capture, indefinitely or until the given time
+    pub fn pause(&self, until: Option<DateTime<Utc>>) -> Result<()> {
+        let contents = until.map(|t| t.to_rfc3339()).unwrap_or_default();
+        fs::write(&self.path, contents)?;
+        Ok(())
+    }
+
+    /// Resume capture, returning false if it was not paused
+    pub fn resume(&self) -> Result<bool> {
+        match fs::remove_file(&self.path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Read the pause state, removing the marker once it has expired
+    pub fn state(&self, now: DateTime<Utc>) -> Result<PauseState> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(PauseState::Running),
+            Err(e) => return Err(e.into()),
+        };
+
+        let contents = contents.trim();
+        if contents.is_empty() {
+            return Ok(PauseState::Paused);
+        }
+
+        let until = DateTime::parse_from_rfc3339(contents)
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(|e| {
+                OmniscientError::other(format!(
+                    "Invalid pause marker {}: {}",
+                    self.path.display(),
+                    e
+                ))
+            })?;
+
+        if until <= now {
+            self.resume()?;
+            return Ok(PauseState::Running);
+        }
+
+        Ok(PauseState::PausedUntil(until))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pause_and_resume() {
+        let dir = TempDir::new().unwrap();
+        let pause = Pause::new(dir.path().join("history.db.paused"));
+        let now = Utc::now();
+
+        assert_eq!(pause.state(now).unwrap(), PauseState::Running);
+        assert!(!pause.resume().unwrap());
+
+        pause.pause(None).unwrap();
+        assert_eq!(pause.state(now).unwrap(), PauseState::Paused);
+
+        assert!(pause.resume().unwrap());
+        assert_eq!(pause.state(now).unwrap(), PauseState::Running);
+    }
+
+    #[test]
+    fn test_pause_expires() {
+        let dir = TempDir::new().unwrap();
+        let pause = Pause::new(dir.path().join("history.db.paused"));
+        let now = Utc::now();
+        let until = now + Duration::hours(1);
+
+        pause.pause(Some(until)).unwrap();
+        assert!(pause.state(now).unwrap().is_paused());
+
+        let later = now + Duration::hours(2);
+        assert_eq!(pause.state(later).unwrap(), PauseState::Running);
+        assert!(!pause.resume().unwrap(), "expired marker is removed");
+    }
+}
@@ -0,0 +1,234 @@
+/// Read-only SQL queries (`omniscient query "<sql>"`)
+///
+/// An escape hatch for questions no flag answers yet: any single SELECT
+/// statement runs against the database (tables `commands`, `executions`,
+/// `outputs`, ...) and the rows are printed as a table or JSON. Anything
+/// that could write is refused before it runs, and the connection is
+/// switched to `query_only` while it does.
+use crate::error::{OmniscientError, Result};
+use crate::format::fit;
+use serde_json::{Map, Value};
+
+/// Cells are cut to this many characters in tables
+const MAX_CELL_WIDTH: usize = 60;
+
+/// Columns and rows returned by a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResult {
+    /// Column names, in the order of the SELECT
+    pub columns: Vec<String>,
+
+    /// Rows of values, one per column
+    pub rows: Vec<Vec<Value>>,
+
+    /// Whether more rows matched than were returned
+    pub truncated: bool,
+}
+
+impl QueryResult {
+    /// The rows as a JSON array of objects keyed by column name
+    pub fn to_json(&self) -> Value {
+        Value::Array(
+            self.rows
+                .iter()
+                .map(|row| {
+                    let object: Map<String, Value> = self
+                        .columns
+                        .iter()
+                        .cloned()
+                        .zip(row.iter().cloned())
+                        .collect();
+                    Value::Object(object)
+                })
+                .collect(),
+        )
+    }
+
+    /// The rows as an aligned text table with a header; numbers are
+    /// right-aligned, long or multi-line text is cut to one line
+    pub fn to_table(&self) -> String {
+        let cells: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(cell).collect())
+            .collect();
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                cells
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain([name.chars().count()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let line = |values: Vec<String>, right: &dyn Fn(usize) -> bool| {
+            let padded: Vec<String> = values
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    if right(i) {
+                        format!("{:>width$}", value, width = widths[i])
+                    } else {
+                        format!("{:<width$}", value, width = widths[i])
+                    }
+                })
+                .collect();
+            format!("{}\n", padded.join("  ").trim_end())
+        };
+
+        let mut out = line(self.columns.clone(), &|_| false);
+        out.push_str(&line(
+            widths.iter().map(|width| "-".repeat(*width)).collect(),
+            &|_| false,
+        ));
+        for (row, values) in self.rows.iter().zip(cells) {
+            out.push_str(&line(values, &|i| row[i].is_number()));
+        }
+        out
+    }
+}
+
+/// A value as a table cell: NULL for missing values, text on one line
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::String(text) => fit(text, Some(MAX_CELL_WIDTH)),
+        other => other.to_string(),
+    }
+}
+
+/// Refuse anything but a single SELECT (or `WITH ... SELECT`) statement
+///
+/// Storage also checks that SQLite considers the prepared statement
+/// read-only, which catches writes hidden in a `WITH` clause.
+pub fn check_select(sql: &str) -> Result<()> {
+    let keyword: String = strip_comments(sql)
+        .trim_start()
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    if !matches!(
+        keyword.to_ascii_uppercase().as_str(),
+        "SELECT" | "WITH" | "VALUES"
+    ) {
+        return Err(OmniscientError::other(
+            "Only SELECT queries are allowed (omniscient query is read-only)",
+        ));
+    }
+    if let Some(end) = statement_end(sql) {
+        if !strip_comments(&sql[end + 1..])
+            .trim_end_matches(';')
+            .trim()
+            .is_empty()
+        {
+            return Err(OmniscientError::other("Only a single statement is allowed"));
+        }
+    }
+    Ok(())
+}
+
+/// Position of the first `;` outside quotes and comments
+fn statement_end(sql: &str) -> Option<usize> {
+    let mut chars = sql.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            ';' => return Some(i),
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                for (_, c) in chars.by_ref() {
+                    // A doubled quote is an escaped one and closes and reopens
+                    if c == close {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek().map(|(_, c)| *c) == Some('-') => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek().map(|(_, c)| *c) == Some('*') => {
+                chars.next();
+                let mut star = false;
+                for (_, c) in chars.by_ref() {
+                    if star && c == '/' {
+                        break;
+                    }
+                    star = c == '*';
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `sql` without the leading `--` and `/* */` comments
+fn strip_comments(sql: &str) -> &str {
+    let mut rest = sql.trim_start();
+    loop {
+        if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment.split_once('\n').map_or("", |(_, after)| after);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, after)| after);
+        } else {
+            return rest;
+        }
+        rest = rest.trim_start();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_select() {
+        assert!(check_select("SELECT 1").is_ok());
+        assert!(check_select("  select * from commands").is_ok());
+        assert!(check_select("-- top\n/* c */ WITH x AS (SELECT 1) SELECT * FROM x").is_ok());
+
+        assert!(check_select("DELETE FROM commands").is_err());
+        assert!(check_select("-- SELECT\nDROP TABLE commands").is_err());
+        assert!(check_select("PRAGMA writable_schema = 1").is_err());
+        assert!(check_select("").is_err());
+
+        assert!(check_select("SELECT ';' AS x; -- done\n;").is_ok());
+        assert!(check_select("SELECT 1; DELETE FROM commands").is_err());
+        assert!(check_select("SELECT 'it''s'; /* x */ DROP TABLE commands").is_err());
+    }
+
+    #[test]
+    fn test_table_and_json() {
+        let result = QueryResult {
+            columns: vec!["category".to_string(), "runs".to_string()],
+            rows: vec![
+                vec![json!("git"), json!(120)],
+                vec![json!("kubernetes"), json!(7)],
+                vec![Value::Null, json!(1.5)],
+            ],
+            truncated: false,
+        };
+
+        assert_eq!(
+            result.to_table(),
+            "category    runs\n\
+             ----------  ----\n\
+             git          120\n\
+             kubernetes     7\n\
+             NULL         1.5\n"
+        );
+        assert_eq!(
+            result.to_json()[1],
+            json!({"category": "kubernetes", "runs": 7})
+        );
+    }
+}
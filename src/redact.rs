@@ -1,19 +1,93 @@
 /// Redaction engine for filtering sensitive data from commands
 use crate::error::Result;
+use crate::models::CommandRecord;
+use crate::storage::{RecordChange, Storage};
 use regex::Regex;
+use std::collections::BTreeMap;
+
+/// Text that replaces redacted content
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Records rewritten per transaction when redacting stored history
+const APPLY_BATCH_SIZE: usize = 500;
+
+/// What to do with stored records that match a redaction pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactMode {
+    /// Replace the sensitive part with [`REDACTED`]
+    Mask,
+
+    /// Delete the record
+    Delete,
+}
+
+/// Outcome of applying redaction to stored history
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RedactionReport {
+    /// Records checked
+    pub scanned: usize,
+
+    /// Records masked
+    pub masked: usize,
+
+    /// Records deleted
+    pub deleted: usize,
+
+    /// Executions whose recorded environment variables were masked
+    pub env_masked: usize,
+
+    /// Captured outputs masked
+    pub outputs_masked: usize,
+
+    /// Captured outputs deleted
+    pub outputs_deleted: usize,
+}
+
+impl RedactionReport {
+    /// Whether anything stored was masked or deleted
+    pub fn changed(&self) -> bool {
+        self.masked + self.deleted + self.env_masked + self.outputs_masked + self.outputs_deleted
+            > 0
+    }
+}
 
 /// Engine for redacting sensitive information from commands
 pub struct RedactionEngine {
     patterns: Vec<Regex>,
+    sources: Vec<String>,
     enabled: bool,
 }
 
+/// One place where a redaction pattern matched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedactionMatch {
+    /// The pattern as configured
+    pub pattern: String,
+
+    /// Byte range of the match
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A stored record that matches the current redaction patterns
+#[derive(Debug, Clone)]
+pub struct AuditFinding {
+    /// The matching record
+    pub record: CommandRecord,
+
+    /// Matches in the command text
+    pub command_matches: Vec<RedactionMatch>,
+
+    /// Whether the typed alias or the note matched as well
+    pub typed_or_note_matches: bool,
+}
+
 impl RedactionEngine {
     /// Create a new redaction engine with the given patterns
     pub fn new(pattern_strings: Vec<String>, enabled: bool) -> Result<Self> {
         let mut patterns = Vec::new();
 
-        for pattern in pattern_strings {
+        for pattern in &pattern_strings {
             // Create case-insensitive regex patterns
             let regex = Regex::new(&format!("(?i){}", pattern)).map_err(|e| {
                 crate::error::OmniscientError::redaction(format!(
@@ -24,7 +98,11 @@ impl RedactionEngine {
             patterns.push(regex);
         }
 
-        Ok(Self { patterns, enabled })
+        Ok(Self {
+            patterns,
+            sources: pattern_strings,
+            enabled,
+        })
     }
 
     /// Check if a command should be redacted
@@ -41,12 +119,178 @@ impl RedactionEngine {
     /// Redact a command if it matches any patterns
     pub fn redact(&self, command: &str) -> String {
         if self.should_redact(command) {
-            "[REDACTED]".to_string()
+            REDACTED.to_string()
         } else {
             command.to_string()
         }
     }
 
+    /// Find every match of every pattern, ordered by position
+    pub fn find_matches(&self, text: &str) -> Vec<RedactionMatch> {
+        if !self.enabled {
+            return Vec::new();
+        }
+
+        let mut matches: Vec<RedactionMatch> = self
+            .patterns
+            .iter()
+            .zip(&self.sources)
+            .flat_map(|(pattern, source)| {
+                pattern.find_iter(text).map(|m| RedactionMatch {
+                    pattern: source.clone(),
+                    start: m.start(),
+                    end: m.end(),
+                })
+            })
+            .collect();
+        matches.sort_by_key(|m| (m.start, m.end));
+        matches
+    }
+
+    /// Mask everything from the first sensitive word onwards
+    ///
+    /// `export PASSWORD=hunter2` becomes `export [REDACTED]`, keeping the
+    /// harmless prefix searchable. Returns `None` if nothing matches.
+    pub fn mask(&self, text: &str) -> Option<String> {
+        let first = self.find_matches(text).into_iter().next()?;
+        let word_start = text[..first.start]
+            .rfind(char::is_whitespace)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+
+        Some(format!("{}{}", &text[..word_start], REDACTED))
+    }
+
+    /// Mask the command, typed alias and note of a record in place,
+    /// returning whether anything changed
+    pub fn mask_record(&self, record: &mut CommandRecord) -> bool {
+        let mut changed = false;
+
+        if let Some(masked) = self.mask(&record.command) {
+            record.command = masked;
+            changed = true;
+        }
+        for field in [&mut record.typed_command, &mut record.note] {
+            if let Some(masked) = field.as_deref().and_then(|text| self.mask(text)) {
+                *field = Some(masked);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// Mask environment variable values that match, returning whether
+    /// anything changed
+    pub fn mask_env(&self, env: &mut BTreeMap<String, String>) -> bool {
+        let mut changed = false;
+        for value in env.values_mut() {
+            if value != REDACTED && self.should_redact(value) {
+                *value = REDACTED.to_string();
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Replace each output line that matches with [`REDACTED`], or `None`
+    /// if no line does
+    pub fn mask_output(&self, output: &str) -> Option<String> {
+        let matches = |line: &str| line != REDACTED && self.should_redact(line);
+        output.lines().any(matches).then(|| {
+            output
+                .lines()
+                .map(|line| if matches(line) { REDACTED } else { line })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+    }
+
+    /// Scan stored records for content the current patterns would redact
+    pub fn audit(&self, storage: &Storage) -> Result<Vec<AuditFinding>> {
+        let mut findings = Vec::new();
+
+        storage.for_each_record(|record| {
+            let command_matches = self.find_matches(&record.command);
+            let typed_or_note_matches = [&record.typed_command, &record.note]
+                .into_iter()
+                .flatten()
+                .any(|text| self.should_redact(text));
+
+            if !command_matches.is_empty() || typed_or_note_matches {
+                findings.push(AuditFinding {
+                    record,
+                    command_matches,
+                    typed_or_note_matches,
+                });
+            }
+            Ok(())
+        })?;
+
+        Ok(findings)
+    }
+
+    /// Mask or delete every stored record that matches the current patterns
+    ///
+    /// Recorded environment variables are masked either way; captured
+    /// output that matches is masked line by line, or deleted with
+    /// [`RedactMode::Delete`]. When anything changed, the old text is also
+    /// purged from the database file (see [`Storage::purge_freed_data`]);
+    /// backups and exports made earlier still contain it.
+    pub fn apply(&self, storage: &Storage, action: RedactMode) -> Result<RedactionReport> {
+        let mut report = RedactionReport::default();
+        if !self.enabled {
+            report.scanned = storage.count()?;
+            return Ok(report);
+        }
+
+        report.scanned = storage.rewrite_records(APPLY_BATCH_SIZE, |record| match action {
+            RedactMode::Delete => {
+                let matches = [
+                    Some(&record.command),
+                    record.typed_command.as_ref(),
+                    record.note.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .any(|text| self.should_redact(text));
+                matches.then(|| {
+                    report.deleted += 1;
+                    RecordChange::Delete
+                })
+            }
+            RedactMode::Mask => {
+                let mut masked = record.clone();
+                self.mask_record(&mut masked).then(|| {
+                    report.masked += 1;
+                    RecordChange::Update(masked)
+                })
+            }
+        })?;
+
+        report.env_masked =
+            storage.rewrite_execution_env(APPLY_BATCH_SIZE, |env| self.mask_env(env))?;
+        storage.rewrite_outputs(APPLY_BATCH_SIZE, |output| {
+            let masked = self.mask_output(output)?;
+            Some(match action {
+                RedactMode::Delete => {
+                    report.outputs_deleted += 1;
+                    RecordChange::Delete
+                }
+                RedactMode::Mask => {
+                    report.outputs_masked += 1;
+                    RecordChange::Update(masked)
+                }
+            })
+        })?;
+
+        if report.changed() {
+            storage.purge_freed_data()?;
+        }
+
+        Ok(report)
+    }
+
     /// Get the number of active patterns
     pub fn pattern_count(&self) -> usize {
         self.patterns.len()
@@ -200,6 +444,173 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_find_matches() {
+        let engine =
+            RedactionEngine::new(vec!["token".to_string(), "pass\\w*".to_string()], true).unwrap();
+
+        let matches = engine.find_matches("curl -H 'Token: x' -u me:$PASSWD");
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].pattern, "token");
+        assert_eq!((matches[0].start, matches[0].end), (9, 14));
+        assert_eq!(matches[1].pattern, "pass\\w*");
+
+        assert!(engine.find_matches("git status").is_empty());
+    }
+
+    #[test]
+    fn test_mask() {
+        let engine = RedactionEngine::default();
+
+        assert_eq!(
+            engine.mask("export PASSWORD=hunter2").as_deref(),
+            Some("export [REDACTED]")
+        );
+        assert_eq!(
+            engine
+                .mask("curl -H 'Authorization: token abc' api")
+                .as_deref(),
+            Some("curl -H 'Authorization: [REDACTED]")
+        );
+        assert_eq!(engine.mask("TOKEN=abc make").as_deref(), Some("[REDACTED]"));
+        assert_eq!(engine.mask("git status"), None);
+    }
+
+    #[test]
+    fn test_audit() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let storage = Storage::new(temp_file.path()).unwrap();
+        let record = |command: &str| {
+            CommandRecord::new(
+                command.to_string(),
+                chrono::Utc::now(),
+                0,
+                10,
+                "/tmp".to_string(),
+                "other".to_string(),
+            )
+        };
+
+        storage
+            .insert(&record("export GITHUB_TOKEN=ghp_x"))
+            .unwrap();
+        storage.insert(&record("git status")).unwrap();
+        let mut noted = record("make deploy");
+        noted.note = Some("needs the staging password".to_string());
+        storage.insert(&noted).unwrap();
+
+        let engine = RedactionEngine::default();
+        let findings = engine.audit(&storage).unwrap();
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].command_matches[0].pattern, "token");
+        assert!(findings[1].command_matches.is_empty());
+        assert!(findings[1].typed_or_note_matches);
+    }
+
+    #[test]
+    fn test_apply() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let storage = Storage::new(temp_file.path()).unwrap();
+        let record = |command: &str| {
+            CommandRecord::new(
+                command.to_string(),
+                chrono::Utc::now(),
+                0,
+                10,
+                "/tmp".to_string(),
+                "other".to_string(),
+            )
+        };
+        storage
+            .insert(&record("curl -H 'x-api-token: abc' api.example.com"))
+            .unwrap();
+        storage.insert(&record("git status")).unwrap();
+        storage.insert(&record("export SECRET_KEY=abc")).unwrap();
+
+        // Patterns added after the records were stored
+        let engine =
+            RedactionEngine::new(vec!["token".to_string(), "secret".to_string()], true).unwrap();
+
+        let report = engine.apply(&storage, RedactMode::Mask).unwrap();
+        assert_eq!(
+            report,
+            RedactionReport {
+                scanned: 3,
+                masked: 2,
+                ..Default::default()
+            }
+        );
+        let commands: Vec<String> = storage
+            .get_all()
+            .unwrap()
+            .into_iter()
+            .map(|r| r.command)
+            .collect();
+        assert!(commands.contains(&"curl -H [REDACTED]".to_string()));
+        assert!(commands.contains(&"export [REDACTED]".to_string()));
+
+        // Masked records no longer match, so applying again is a no-op
+        let report = engine.apply(&storage, RedactMode::Mask).unwrap();
+        assert_eq!(report.masked, 0);
+
+        let engine = RedactionEngine::new(vec!["git".to_string()], true).unwrap();
+        let report = engine.apply(&storage, RedactMode::Delete).unwrap();
+        assert_eq!(report.deleted, 1);
+        assert_eq!(storage.count().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_apply_masks_env_and_output() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let storage = Storage::new(temp_file.path()).unwrap();
+        let id = storage
+            .insert(&CommandRecord::new(
+                "./deploy.sh".to_string(),
+                chrono::Utc::now(),
+                0,
+                10,
+                "/srv".to_string(),
+                "deploy".to_string(),
+            ))
+            .unwrap();
+        let meta = crate::models::ExecutionMeta {
+            env: [
+                (
+                    "DB_URL".to_string(),
+                    "postgres://app:hunter2@db".to_string(),
+                ),
+                ("STAGE".to_string(), "prod".to_string()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let execution = storage
+            .log_execution(id, chrono::Utc::now(), 0, 10, &meta)
+            .unwrap();
+        storage
+            .save_output(execution, "connecting\nusing hunter2\ndone", false)
+            .unwrap();
+
+        // Patterns added after the output and variables were stored
+        let engine = RedactionEngine::new(vec!["hunter2".to_string()], true).unwrap();
+        let report = engine.apply(&storage, RedactMode::Mask).unwrap();
+        assert_eq!((report.env_masked, report.outputs_masked), (1, 1));
+
+        let executions = storage.get_executions(None, false).unwrap();
+        assert_eq!(executions[0].meta.env["DB_URL"], REDACTED);
+        assert_eq!(executions[0].meta.env["STAGE"], "prod");
+        let output = storage.get_latest_output(id).unwrap().unwrap();
+        assert_eq!(output.text, "connecting\n[REDACTED]\ndone");
+
+        // Nothing left to mask; deleting removes matching output
+        let report = engine.apply(&storage, RedactMode::Mask).unwrap();
+        assert_eq!((report.env_masked, report.outputs_masked), (0, 0));
+        let engine = RedactionEngine::new(vec!["connecting".to_string()], true).unwrap();
+        let report = engine.apply(&storage, RedactMode::Delete).unwrap();
+        assert_eq!((report.deleted, report.outputs_deleted), (0, 1));
+        assert!(storage.get_latest_output(id).unwrap().is_none());
+    }
+
     #[test]
     fn test_common_false_positives() {
         let engine = RedactionEngine::new(vec!["pass".to_string()], true).unwrap();
@@ -211,4 +622,31 @@ mod tests {
         // This is a tradeoff - we match partial words for safety
         assert!(engine.should_redact("compass")); // Contains "pass"
     }
+
+    #[test]
+    fn test_apply_purges_original_text_from_disk() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let storage = Storage::new(temp_file.path()).unwrap();
+        storage
+            .insert(&CommandRecord::new(
+                "mysql -p hunter2 app".to_string(),
+                chrono::Utc::now(),
+                0,
+                10,
+                "/srv".to_string(),
+                "database".to_string(),
+            ))
+            .unwrap();
+
+        let engine = RedactionEngine::new(vec!["hunter2".to_string()], true).unwrap();
+        assert!(engine.apply(&storage, RedactMode::Mask).unwrap().changed());
+
+        // Not in free pages, the search index or the write-ahead log
+        let mut on_disk = std::fs::read(temp_file.path()).unwrap();
+        let wal = format!("{}-wal", temp_file.path().display());
+        on_disk.extend(std::fs::read(wal).unwrap_or_default());
+        assert!(!on_disk.windows(7).any(|bytes| bytes == b"hunter2"));
+
+        assert!(!engine.apply(&storage, RedactMode::Mask).unwrap().changed());
+    }
 }
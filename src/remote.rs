@@ -0,0 +1,365 @@
+/// Remote capture - history of commands run over SSH
+///
+/// `omniscient remote-install user@host` installs a small shell hook on the
+/// remote host that needs nothing but the shell: after each command it
+/// appends a capture line to `~/.omniscient/remote.journal` there, in the
+/// same NDJSON format as the local [capture journal](crate::journal).
+/// `omniscient remote-sync` later fetches those lines over SSH and stores
+/// them like local captures (so redaction, ignore rules and categories
+/// apply), tagged with the host's name.
+///
+/// Remote directories are stored as `name:/path`, so they never merge with
+/// local directories of the same path; `omniscient here --host name` shows
+/// them. Fetching works like the local journal: the remote file is moved to
+/// `remote.journal.sync` first and only deleted once everything in it is
+/// committed locally.
+use crate::capture::CaptureRequest;
+use crate::error::{OmniscientError, Result};
+use crate::journal::read_requests;
+use crate::shell::ShellType;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Where the hook lives on the remote host
+const HOOK_PATH: &str = "~/.omniscient/remote-hook.sh";
+
+/// Fetch the remote journal, moving it aside unless a fetch is unfinished
+const FETCH_SCRIPT: &str = "cd ~/.omniscient 2>/dev/null || exit 0; \
+     [ -f remote.journal.sync ] || mv remote.journal remote.journal.sync 2>/dev/null; \
+     cat remote.journal.sync 2>/dev/null; exit 0";
+
+/// Delete the fetched journal once it is stored
+const ACKNOWLEDGE_SCRIPT: &str = "rm -f ~/.omniscient/remote.journal.sync";
+
+/// JSON string escaping and the journal line writer, shared by both shells
+const RECORD_FUNCTIONS: &str = r#"
+_omniscient_json() {
+    local s=$1
+    s=${s//\\/\\\\}
+    s=${s//\"/\\\"}
+    s=${s//$'\n'/\\n}
+    s=${s//$'\t'/\\t}
+    s=${s//$'\r'/\\r}
+    # Other control characters (escape sequences from pasted colored output)
+    if [[ $s == *[[:cntrl:]]* ]]; then
+        local out= c i
+        for (( i = 0; i < ${#s}; i++ )); do
+            c=${s:i:1}
+            [[ $c == [[:cntrl:]] ]] && printf -v c '\\u%04x' "'$c"
+            out+=$c
+        done
+        s=$out
+    fi
+    printf '%s' "$s"
+}
+
+# Append one capture: exit code, command, start time, duration (ms). The
+# capture ID (session and sequence number) lets a repeated sync store it once.
+# Commands may contain secrets, so only the user can read the journal.
+_omniscient_remote_record() {
+    [[ -z "$2" ]] && return
+    [[ -n "$OMNISCIENT_DISABLE" && "$OMNISCIENT_DISABLE" != 0 ]] && return
+    [[ -d ~/.omniscient ]] || mkdir -m 700 -p ~/.omniscient
+    _OMNISCIENT_REMOTE_SEQ=$(( ${_OMNISCIENT_REMOTE_SEQ:-0} + 1 ))
+    (umask 077
+    printf '{"command":"%s","exit_code":%d,"duration_ms":%d,"started_at":"%s","working_dir":"%s","meta":{"session":"%s","capture_id":"%s-%d"}}\n' \
+        "$(_omniscient_json "$2")" "$1" "$4" "$3" "$(_omniscient_json "$PWD")" \
+        "$_OMNISCIENT_REMOTE_SESSION" "$_OMNISCIENT_REMOTE_SESSION" "$_OMNISCIENT_REMOTE_SEQ" \
+        >> ~/.omniscient/remote.journal)
+}
+"#;
+
+/// Zsh hook: preexec/precmd, with durations
+const ZSH_HOOK: &str = r#"
+_omniscient_remote_preexec() {
+    _OMNISCIENT_REMOTE_CMD=$1
+    _OMNISCIENT_REMOTE_AT=$(date -u +%Y-%m-%dT%H:%M:%SZ)
+    _OMNISCIENT_REMOTE_START=$EPOCHREALTIME
+}
+
+_omniscient_remote_precmd() {
+    local exit_code=$?
+    [[ -n "$_OMNISCIENT_REMOTE_AT" ]] || return
+    local duration=$(( int((EPOCHREALTIME - _OMNISCIENT_REMOTE_START) * 1000) ))
+    _omniscient_remote_record "$exit_code" "$_OMNISCIENT_REMOTE_CMD" \
+        "$_OMNISCIENT_REMOTE_AT" "$duration"
+    unset _OMNISCIENT_REMOTE_CMD _OMNISCIENT_REMOTE_AT _OMNISCIENT_REMOTE_START
+}
+
+zmodload zsh/datetime 2>/dev/null
+_OMNISCIENT_REMOTE_SESSION="zsh-$$-$(date +%s)"
+precmd_functions=(_omniscient_remote_precmd ${precmd_functions:#_omniscient_remote_precmd})
+preexec_functions+=(_omniscient_remote_preexec)
+"#;
+
+/// Bash hook: PROMPT_COMMAND only, so it needs no bash-preexec (and records
+/// no durations)
+const BASH_HOOK: &str = r#"
+_omniscient_remote_prompt() {
+    local exit_code=$?
+    local entry
+    entry=$(HISTTIMEFORMAT= history 1)
+    entry=${entry#"${entry%%[![:space:]]*}"}
+    local number=${entry%% *}
+    local cmd=${entry#* }
+    cmd=${cmd#"${cmd%%[![:space:]]*}"}
+
+    # The first prompt and empty lines repeat the last history entry
+    if [[ -n "${_OMNISCIENT_REMOTE_LAST+set}" && "$number" != "$_OMNISCIENT_REMOTE_LAST" ]]; then
+        _omniscient_remote_record "$exit_code" "$cmd" \
+            "$(date -u +%Y-%m-%dT%H:%M:%SZ)" 0
+    fi
+    _OMNISCIENT_REMOTE_LAST=$number
+    return $exit_code
+}
+
+_OMNISCIENT_REMOTE_SESSION="bash-$$-$(date +%s)"
+PROMPT_COMMAND="_omniscient_remote_prompt${PROMPT_COMMAND:+; $PROMPT_COMMAND}"
+"#;
+
+/// A remote host captured over SSH
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteHost {
+    /// Name the host's commands are tagged with
+    pub name: String,
+
+    /// SSH destination (`user@host` or an alias from ~/.ssh/config)
+    pub target: String,
+}
+
+impl RemoteHost {
+    /// A host reached at `target`, named `name` or else after the host part
+    /// of the target (`deploy@build.example.com` is `build.example.com`)
+    pub fn new(target: &str, name: Option<&str>) -> Result<Self> {
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => target.rsplit('@').next().unwrap_or(target).to_string(),
+        };
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if target.is_empty() || !valid {
+            return Err(OmniscientError::config(format!(
+                "Invalid remote host name '{}' (use letters, digits, '-', '_' and '.')",
+                name
+            )));
+        }
+        // ssh would take it as an option, such as -oProxyCommand=...
+        if target.starts_with('-') || target.chars().any(char::is_whitespace) {
+            return Err(OmniscientError::config(format!(
+                "Invalid SSH destination '{}'",
+                target
+            )));
+        }
+
+        Ok(Self {
+            name,
+            target: target.to_string(),
+        })
+    }
+
+    /// The login shell of the remote user, if it is one the hook supports
+    pub fn detect_shell(&self) -> Result<ShellType> {
+        let shell = self.ssh("echo \"$SHELL\"", None)?;
+        match shell.trim().rsplit('/').next() {
+            Some("zsh") => Ok(ShellType::Zsh),
+            Some("bash") => Ok(ShellType::Bash),
+            _ => Err(OmniscientError::Shell(format!(
+                "Unsupported shell '{}' on {} (pass --shell zsh or --shell bash)",
+                shell.trim(),
+                self.target
+            ))),
+        }
+    }
+
+    /// Install the hook and source it from the shell's rc file, returning
+    /// the rc file
+    ///
+    /// Installing again replaces the hook and leaves the rc file alone.
+    pub fn install(&self, shell: ShellType) -> Result<&'static str> {
+        let rc = match shell {
+            ShellType::Zsh => "~/.zshrc",
+            ShellType::Bash => "~/.bashrc",
+        };
+        let script = format!(
+            "mkdir -p ~/.omniscient && cat > {hook} && \
+             {{ grep -qF '.omniscient/remote-hook.sh' {rc} 2>/dev/null || \
+             printf '\\n# Omniscient remote capture\\n[ -f {hook} ] && . {hook}\\n' >> {rc}; }}",
+            hook = HOOK_PATH,
+            rc = rc
+        );
+        self.ssh(&script, Some(&hook(shell)))?;
+        Ok(rc)
+    }
+
+    /// Fetch the captures waiting on the host, with the number of lines that
+    /// could not be read
+    ///
+    /// The captures are tagged with this host (see [`tag`]). Call
+    /// [`Self::acknowledge`] once they are stored.
+    pub fn fetch(&self) -> Result<(Vec<CaptureRequest>, usize)> {
+        let journal = self.ssh(FETCH_SCRIPT, None)?;
+        let (mut requests, invalid) = read_requests(journal.as_bytes())?;
+        for request in &mut requests {
+            tag(request, &self.name);
+        }
+        Ok((requests, invalid))
+    }
+
+    /// Delete the fetched captures on the host
+    pub fn acknowledge(&self) -> Result<()> {
+        self.ssh(ACKNOWLEDGE_SCRIPT, None).map(|_| ())
+    }
+
+    /// Run a command on the host, returning what it printed
+    fn ssh(&self, script: &str, input: Option<&str>) -> Result<String> {
+        let mut child = Command::new("ssh")
+            .arg("--")
+            .arg(&self.target)
+            .arg(script)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| OmniscientError::Shell(format!("Could not run ssh: {}", e)))?;
+
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin.write_all(input.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(OmniscientError::Shell(format!(
+                "ssh {} failed ({})",
+                self.target, output.status
+            )));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// The hook script installed on remote hosts
+pub fn hook(shell: ShellType) -> String {
+    let header = "# Omniscient remote capture hook\n\
+                  # Installed by: omniscient remote-install (synced with omniscient remote-sync)\n\
+                  # Commands are appended to ~/.omniscient/remote.journal until synced\n";
+    let hook = match shell {
+        ShellType::Zsh => ZSH_HOOK,
+        ShellType::Bash => BASH_HOOK,
+    };
+    format!("{}{}{}", header, RECORD_FUNCTIONS, hook)
+}
+
+/// How the directory `dir` on host `name` is stored (`name:/path`)
+pub fn remote_dir(name: &str, dir: &str) -> String {
+    format!("{}:{}", name, dir)
+}
+
+/// Tag a capture from host `name`: set its host and prefix its directory
+/// and capture ID
+pub fn tag(request: &mut CaptureRequest, name: &str) {
+    request.meta.host = Some(name.to_string());
+    if let Some(ref mut capture_id) = request.meta.capture_id {
+        *capture_id = format!("{}:{}", name, capture_id);
+    }
+    let dir = request.working_dir.as_deref().unwrap_or("/unknown");
+    request.working_dir = Some(remote_dir(name, dir));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_host_names() {
+        let host = RemoteHost::new("deploy@build.example.com", None).unwrap();
+        assert_eq!(host.name, "build.example.com");
+        assert_eq!(host.target, "deploy@build.example.com");
+
+        let host = RemoteHost::new("deploy@10.0.0.5", Some("staging")).unwrap();
+        assert_eq!(host.name, "staging");
+
+        assert!(RemoteHost::new("box", Some("my box")).is_err());
+        assert!(RemoteHost::new("", None).is_err());
+        assert!(RemoteHost::new("-oProxyCommand=touch /tmp/x", Some("box")).is_err());
+        assert!(RemoteHost::new("-V", None).is_err());
+    }
+
+    #[test]
+    fn test_journal_lines_from_hook_are_tagged() {
+        // A line as the hook writes it, with an escaped quote and newline
+        let journal = concat!(
+            r#"{"command":"echo \"hi\"\nls","exit_code":0,"duration_ms":12,"#,
+            r#""started_at":"2026-03-01T10:00:00Z","working_dir":"/srv/app","#,
+            r#""meta":{"session":"zsh-1-2","capture_id":"zsh-1-2-7"}}"#,
+            "\n{\"command\": \"trunc\n"
+        );
+        let (mut requests, invalid) = read_requests(journal.as_bytes()).unwrap();
+        assert_eq!(invalid, 1);
+        assert_eq!(requests[0].command, "echo \"hi\"\nls");
+
+        tag(&mut requests[0], "build");
+        assert_eq!(requests[0].working_dir.as_deref(), Some("build:/srv/app"));
+        assert_eq!(requests[0].meta.host.as_deref(), Some("build"));
+        assert_eq!(requests[0].meta.session.as_deref(), Some("zsh-1-2"));
+        assert_eq!(
+            requests[0].meta.capture_id.as_deref(),
+            Some("build:zsh-1-2-7")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hook_journal_is_private_and_valid() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let home = tempfile::TempDir::new().unwrap();
+        let command = "printf '\x1b[31mred\x1b[0m\x08 \"q\"'\n  \tindented";
+        let script = format!(
+            "{}\numask 022\n_OMNISCIENT_REMOTE_SESSION=s\n\
+             _omniscient_remote_record 0 \"$CMD\" 2026-03-01T10:00:00Z 5\n",
+            RECORD_FUNCTIONS
+        );
+        let Ok(status) = Command::new("bash")
+            .arg("-c")
+            .arg(&script)
+            .env("HOME", home.path())
+            .env("CMD", command)
+            .current_dir(home.path())
+            .status()
+        else {
+            return; // No bash to run the hook with
+        };
+        assert!(status.success());
+
+        let dir = home.path().join(".omniscient");
+        let journal = dir.join("remote.journal");
+        let mode =
+            |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(&journal), 0o600);
+
+        let (requests, invalid) = read_requests(&std::fs::read(&journal).unwrap()[..]).unwrap();
+        assert_eq!(invalid, 0);
+        assert_eq!(requests[0].command, command);
+        assert_eq!(requests[0].meta.capture_id.as_deref(), Some("s-1"));
+    }
+
+    #[test]
+    fn test_hook_scripts() {
+        let zsh = hook(ShellType::Zsh);
+        assert!(zsh.contains("preexec_functions+=(_omniscient_remote_preexec)"));
+        assert!(zsh.contains("OMNISCIENT_DISABLE"));
+        assert!(!zsh.contains("omniscient capture"));
+
+        let bash = hook(ShellType::Bash);
+        assert!(bash.contains("PROMPT_COMMAND=\"_omniscient_remote_prompt"));
+        assert!(bash.contains("_omniscient_json"));
+        assert!(bash.contains(r#""capture_id":"%s-%d""#));
+    }
+}
@@ -0,0 +1,352 @@
+/// Weekly and monthly summary reports (`omniscient report`)
+///
+/// A report recaps a trailing period: how much was run and how successfully,
+/// which commands were new, the most used tools, the busiest directories and
+/// the slowest commands. It renders as plain text for the terminal, or as
+/// Markdown or a standalone HTML page for sharing.
+use crate::export::longest_backtick_run;
+use crate::models::{humanize_duration, Summary};
+use chrono::{DateTime, Duration, Utc};
+use std::fmt::Write;
+
+/// Number of entries listed per report section
+pub const SECTION_SIZE: usize = 5;
+
+/// Length of the period a report covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportPeriod {
+    /// The last 7 days
+    Week,
+
+    /// The last 30 days
+    Month,
+}
+
+impl ReportPeriod {
+    /// Look up a period by name ("week" or "month")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "week" | "weekly" => Some(ReportPeriod::Week),
+            "month" | "monthly" => Some(ReportPeriod::Month),
+            _ => None,
+        }
+    }
+
+    /// Start of the period ending at `now`
+    pub fn since(self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            ReportPeriod::Week => now - Duration::days(7),
+            ReportPeriod::Month => now - Duration::days(30),
+        }
+    }
+
+    /// Adjective used in report titles
+    fn adjective(self) -> &'static str {
+        match self {
+            ReportPeriod::Week => "Weekly",
+            ReportPeriod::Month => "Monthly",
+        }
+    }
+}
+
+/// Output format of a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    /// Plain text for the terminal
+    #[default]
+    Text,
+
+    /// Markdown, e.g. for a wiki or chat
+    Markdown,
+
+    /// Standalone HTML page
+    Html,
+}
+
+impl ReportFormat {
+    /// Look up a format by name ("text", "markdown"/"md" or "html")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" | "txt" => Some(ReportFormat::Text),
+            "markdown" | "md" => Some(ReportFormat::Markdown),
+            "html" => Some(ReportFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+/// Render a summary as a report
+pub fn render(
+    summary: &Summary,
+    period: ReportPeriod,
+    format: ReportFormat,
+    now: DateTime<Utc>,
+) -> String {
+    let sections = Sections::new(summary);
+    let title = format!(
+        "{} Omniscient Report: {} to {}",
+        period.adjective(),
+        summary.since.format("%Y-%m-%d"),
+        now.format("%Y-%m-%d")
+    );
+
+    match format {
+        ReportFormat::Text => render_text(&title, &sections),
+        ReportFormat::Markdown => render_markdown(&title, &sections),
+        ReportFormat::Html => render_html(&title, &sections),
+    }
+}
+
+/// Report content independent of the output format
+struct Sections {
+    /// Headline figures as (label, value)
+    totals: Vec<(&'static str, String)>,
+
+    /// Titled lists whose items are (code, detail)
+    lists: Vec<(&'static str, Vec<(String, String)>)>,
+}
+
+impl Sections {
+    fn new(summary: &Summary) -> Self {
+        let runs = |count: usize| format!("{} {}", count, if count == 1 { "run" } else { "runs" });
+
+        let totals = vec![
+            (
+                "Commands run",
+                format!(
+                    "{} ({:.0}% successful)",
+                    summary.runs,
+                    summary.success_rate()
+                ),
+            ),
+            ("Distinct commands", summary.commands.to_string()),
+            ("New commands", summary.new_command_count.to_string()),
+        ];
+
+        let lists = vec![
+            (
+                "Top Tools",
+                summary
+                    .top_tools
+                    .iter()
+                    .map(|tool| (tool.tool.clone(), runs(tool.count)))
+                    .collect(),
+            ),
+            (
+                "Busiest Projects",
+                summary
+                    .busiest_dirs
+                    .iter()
+                    .map(|dir| {
+                        (
+                            dir.working_dir.clone(),
+                            format!("{}, {:.0}% successful", runs(dir.runs), dir.success_rate()),
+                        )
+                    })
+                    .collect(),
+            ),
+            (
+                "New Commands",
+                summary
+                    .new_commands
+                    .iter()
+                    .map(|cmd| (cmd.command.clone(), runs(cmd.usage_count as usize)))
+                    .collect(),
+            ),
+            (
+                "Slowest Commands",
+                summary
+                    .slowest
+                    .iter()
+                    .map(|stats| {
+                        (
+                            stats.command.clone(),
+                            format!(
+                                "{} on average, {}",
+                                humanize_duration(stats.avg_ms),
+                                runs(stats.runs)
+                            ),
+                        )
+                    })
+                    .collect(),
+            ),
+        ];
+
+        Self { totals, lists }
+    }
+}
+
+fn render_text(title: &str, sections: &Sections) -> String {
+    let mut out = format!("{}\n{}\n\n", title, "=".repeat(title.chars().count()));
+    for (label, value) in &sections.totals {
+        let _ = writeln!(out, "{:18} {}", format!("{}:", label), value);
+    }
+
+    for (heading, items) in &sections.lists {
+        if items.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "\n{}:", heading);
+        for (index, (code, detail)) in items.iter().enumerate() {
+            let _ = writeln!(out, "  {}. {} ({})", index + 1, one_line(code), detail);
+        }
+    }
+
+    out
+}
+
+fn render_markdown(title: &str, sections: &Sections) -> String {
+    let mut out = format!("# {}\n\n", title);
+    for (label, value) in &sections.totals {
+        let _ = writeln!(out, "- **{}:** {}", label, value);
+    }
+
+    for (heading, items) in &sections.lists {
+        if items.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "\n## {}\n", heading);
+        for (index, (code, detail)) in items.iter().enumerate() {
+            let code = one_line(code);
+            // Use a delimiter longer than any backtick run inside the code,
+            // padded if the code itself starts or ends with a backtick
+            let ticks = "`".repeat(longest_backtick_run(&code) + 1);
+            let pad = if code.starts_with('`') || code.ends_with('`') {
+                " "
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                out,
+                "{}. {ticks}{pad}{code}{pad}{ticks} ({})",
+                index + 1,
+                detail
+            );
+        }
+    }
+
+    out
+}
+
+fn render_html(title: &str, sections: &Sections) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>body {{ font-family: sans-serif; max-width: 50em; margin: 2em auto; }} \
+         code {{ background: #f4f4f4; padding: 0 .2em; }}</style>\n</head>\n<body>\n\
+         <h1>{title}</h1>\n<ul>\n",
+        title = escape_html(title)
+    );
+    for (label, value) in &sections.totals {
+        let _ = writeln!(
+            out,
+            "<li><strong>{}:</strong> {}</li>",
+            escape_html(label),
+            escape_html(value)
+        );
+    }
+    out.push_str("</ul>\n");
+
+    for (heading, items) in &sections.lists {
+        if items.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "<h2>{}</h2>\n<ol>", escape_html(heading));
+        for (code, detail) in items {
+            let _ = writeln!(
+                out,
+                "<li><code>{}</code> ({})</li>",
+                escape_html(code),
+                escape_html(detail)
+            );
+        }
+        out.push_str("</ol>\n");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Keep a multi-line command on one list line
+fn one_line(text: &str) -> String {
+    text.replace('\n', " ⏎ ")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CommandRecord, ToolUsage};
+
+    fn summary(now: DateTime<Utc>) -> Summary {
+        Summary {
+            since: now - Duration::days(7),
+            runs: 20,
+            successful: 19,
+            commands: 4,
+            new_command_count: 1,
+            new_commands: vec![CommandRecord::new(
+                "grep -r '<main>' src | head".to_string(),
+                now,
+                0,
+                10,
+                "/work/api".to_string(),
+                "search".to_string(),
+            )],
+            top_tools: vec![ToolUsage {
+                tool: "git".to_string(),
+                count: 12,
+            }],
+            busiest_dirs: Vec::new(),
+            slowest: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_period_and_format_names() {
+        let now = Utc::now();
+        assert_eq!(
+            ReportPeriod::from_name("week").unwrap().since(now),
+            now - Duration::days(7)
+        );
+        assert_eq!(
+            ReportPeriod::from_name("monthly"),
+            Some(ReportPeriod::Month)
+        );
+        assert_eq!(ReportPeriod::from_name("year"), None);
+        assert_eq!(ReportFormat::from_name("md"), Some(ReportFormat::Markdown));
+        assert_eq!(ReportFormat::from_name("pdf"), None);
+    }
+
+    #[test]
+    fn test_render_text_and_markdown() {
+        let now = Utc::now();
+        let summary = summary(now);
+
+        let text = render(&summary, ReportPeriod::Week, ReportFormat::Text, now);
+        assert!(text.starts_with("Weekly Omniscient Report"));
+        assert!(text.contains("Commands run:      20 (95% successful)"));
+        assert!(text.contains("  1. git (12 runs)"));
+        // Empty sections are left out
+        assert!(!text.contains("Slowest Commands"));
+
+        let markdown = render(&summary, ReportPeriod::Week, ReportFormat::Markdown, now);
+        assert!(markdown.contains("## Top Tools\n\n1. `git` (12 runs)"));
+        assert!(markdown.contains("- **New commands:** 1"));
+    }
+
+    #[test]
+    fn test_render_html_escapes() {
+        let now = Utc::now();
+        let html = render(&summary(now), ReportPeriod::Month, ReportFormat::Html, now);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Monthly Omniscient Report"));
+        assert!(html.contains("<code>grep -r '&lt;main&gt;' src | head</code> (1 run)"));
+        assert!(!html.contains("<main>"));
+    }
+}
@@ -0,0 +1,183 @@
+/// Re-running stored commands - warns when the current context differs from
+/// the one the command was recorded in
+///
+/// A command that was safe on `main` with `AWS_PROFILE=staging` may not be
+/// safe on a feature branch with `AWS_PROFILE=prod`. Before a stored command
+/// runs again, its recorded directory, git branch, repository and captured
+/// environment variables are compared with the current ones.
+use crate::models::{CommandRecord, ExecutionMeta};
+use crate::redact::REDACTED;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// One way the current context differs from the recorded one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextDifference {
+    /// What differs ("directory", "branch", "repository" or "$NAME")
+    pub field: String,
+
+    /// Value when the command was recorded (`None` if unset)
+    pub recorded: Option<String>,
+
+    /// Value now (`None` if unset)
+    pub current: Option<String>,
+}
+
+impl fmt::Display for ContextDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<String>| match value {
+            Some(value) => format!("'{}'", value),
+            None => "unset".to_string(),
+        };
+
+        match self.field.as_str() {
+            "directory" => write!(
+                f,
+                "recorded in {}, you're in {}",
+                show(&self.recorded),
+                show(&self.current)
+            ),
+            "branch" => write!(
+                f,
+                "recorded on branch {}, you're on {}",
+                show(&self.recorded),
+                show(&self.current)
+            ),
+            field => write!(
+                f,
+                "{} was {} when recorded, now {}",
+                field,
+                show(&self.recorded),
+                show(&self.current)
+            ),
+        }
+    }
+}
+
+/// Compare where and how a command was recorded with the current context
+///
+/// `recorded` is the metadata of the command's latest execution, if one was
+/// logged; `current` should be gathered the same way capture gathers it.
+/// Environment variables are compared when either side has them; redacted
+/// values are skipped because they cannot be compared.
+pub fn compare_context(
+    record: &CommandRecord,
+    recorded: Option<&ExecutionMeta>,
+    current_dir: &str,
+    current: &ExecutionMeta,
+) -> Vec<ContextDifference> {
+    let mut differences = Vec::new();
+
+    let mut push = |field: &str, recorded: Option<&String>, current: Option<&String>| {
+        if recorded != current {
+            differences.push(ContextDifference {
+                field: field.to_string(),
+                recorded: recorded.cloned(),
+                current: current.cloned(),
+            });
+        }
+    };
+
+    push(
+        "directory",
+        Some(&record.working_dir),
+        Some(&current_dir.to_string()),
+    );
+
+    let Some(recorded) = recorded else {
+        return differences;
+    };
+
+    // Only compare git details when the command was recorded inside a repository
+    if recorded.repo.is_some() || recorded.branch.is_some() {
+        push("repository", recorded.repo.as_ref(), current.repo.as_ref());
+        push("branch", recorded.branch.as_ref(), current.branch.as_ref());
+    }
+
+    let names: BTreeSet<&String> = recorded.env.keys().chain(current.env.keys()).collect();
+    for name in names {
+        let (then, now) = (recorded.env.get(name), current.env.get(name));
+        if [then, now].iter().flatten().any(|value| *value == REDACTED) {
+            continue;
+        }
+        push(&format!("${}", name), then, now);
+    }
+
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn record(dir: &str) -> CommandRecord {
+        CommandRecord::new(
+            "terraform apply".to_string(),
+            Utc::now(),
+            0,
+            1000,
+            dir.to_string(),
+            "cloud".to_string(),
+        )
+    }
+
+    fn meta(branch: &str, env: &[(&str, &str)]) -> ExecutionMeta {
+        ExecutionMeta {
+            branch: Some(branch.to_string()),
+            repo: Some("git@example.com:acme/infra.git".to_string()),
+            env: env
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_same_context() {
+        let recorded = meta("main", &[("AWS_PROFILE", "staging")]);
+        let differences = compare_context(&record("/infra"), Some(&recorded), "/infra", &recorded);
+        assert!(differences.is_empty());
+    }
+
+    #[test]
+    fn test_reports_each_difference() {
+        let recorded = meta("main", &[("AWS_PROFILE", "staging"), ("TOKEN", REDACTED)]);
+        let current = meta(
+            "feature/x",
+            &[("AWS_PROFILE", "prod"), ("KUBECONFIG", "/k")],
+        );
+
+        let differences = compare_context(&record("/infra"), Some(&recorded), "/tmp", &current);
+        let fields: Vec<&str> = differences.iter().map(|d| d.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["directory", "branch", "$AWS_PROFILE", "$KUBECONFIG"]
+        );
+
+        assert_eq!(
+            differences[1].to_string(),
+            "recorded on branch 'main', you're on 'feature/x'"
+        );
+        assert_eq!(
+            differences[3].to_string(),
+            "$KUBECONFIG was unset when recorded, now '/k'"
+        );
+    }
+
+    #[test]
+    fn test_without_recorded_execution_only_directory_is_compared() {
+        let current = meta("main", &[("AWS_PROFILE", "prod")]);
+        let differences = compare_context(&record("/infra"), None, "/infra", &current);
+        assert!(differences.is_empty());
+    }
+
+    #[test]
+    fn test_git_skipped_when_recorded_outside_repository() {
+        let recorded = ExecutionMeta::default();
+        let current = meta("main", &[]);
+        let differences = compare_context(&record("/infra"), Some(&recorded), "/infra", &current);
+        assert!(differences.is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+/// Rotated files written on a schedule (scheduled exports and backups)
+///
+/// Files are named `<prefix><stamp><suffix>` in one directory, such as
+/// `history-2025-03-01.json.zst`. A new one is due once the newest is old
+/// enough, and only the newest `keep` are kept. Any capture may find a file
+/// due, so writing takes a lock in the directory: other processes skip the
+/// work instead of racing on the same temporary file.
+use crate::error::Result;
+use crate::lock::FileLock;
+use chrono::{NaiveDate, NaiveDateTime};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Rotated files in a directory
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    directory: PathBuf,
+    prefix: &'static str,
+    suffix: &'static str,
+    stamp_format: &'static str,
+    interval_days: i64,
+    keep: usize,
+}
+
+impl Rotation {
+    /// Files named `<prefix><stamp><suffix>` in `directory`, with stamps
+    /// in `stamp_format` (a date, or a date and time)
+    pub fn new<P: Into<PathBuf>>(
+        directory: P,
+        prefix: &'static str,
+        suffix: &'static str,
+        stamp_format: &'static str,
+    ) -> Self {
+        Self {
+            directory: directory.into(),
+            prefix,
+            suffix,
+            stamp_format,
+            interval_days: 1,
+            keep: 1,
+        }
+    }
+
+    /// Write a new file once the newest is at least `days` old
+    pub fn with_interval_days(mut self, days: i64) -> Self {
+        self.interval_days = days.max(1);
+        self
+    }
+
+    /// Keep only the newest `keep` files
+    pub fn with_keep(mut self, keep: usize) -> Self {
+        self.keep = keep.max(1);
+        self
+    }
+
+    /// Directory the files are written to
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Existing files with their stamps, oldest first
+    pub fn existing(&self) -> Result<Vec<(NaiveDateTime, PathBuf)>> {
+        if !self.directory.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let stamp = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(self.prefix))
+                .and_then(|rest| rest.strip_suffix(self.suffix))
+                .and_then(|stamp| self.parse_stamp(stamp));
+
+            if let Some(stamp) = stamp {
+                files.push((stamp, path));
+            }
+        }
+
+        files.sort();
+        Ok(files)
+    }
+
+    fn parse_stamp(&self, stamp: &str) -> Option<NaiveDateTime> {
+        NaiveDateTime::parse_from_str(stamp, self.stamp_format)
+            .ok()
+            .or_else(|| {
+                NaiveDate::parse_from_str(stamp, self.stamp_format)
+                    .ok()
+                    .map(|date| date.and_time(chrono::NaiveTime::MIN))
+            })
+    }
+
+    /// Whether a new file should be written at `now` (local time)
+    pub fn is_due(&self, now: NaiveDateTime) -> Result<bool> {
+        Ok(match self.existing()?.last() {
+            Some((newest, _)) => (now.date() - newest.date()).num_days() >= self.interval_days,
+            None => true,
+        })
+    }
+
+    /// Write a file if one is due and no other process is writing it
+    ///
+    /// Returns the path of the new file, or `None` if none was due or
+    /// another process holds the lock.
+    pub fn write_if_due<F>(&self, now: NaiveDateTime, write: F) -> Result<Option<PathBuf>>
+    where
+        F: FnOnce(&Path) -> Result<()>,
+    {
+        if !self.is_due(now)? {
+            return Ok(None);
+        }
+
+        let Some(_lock) = FileLock::try_acquire(&self.lock_path())? else {
+            return Ok(None);
+        };
+        // Another process may have written it while we checked
+        if !self.is_due(now)? {
+            return Ok(None);
+        }
+        self.write_locked(now, write).map(Some)
+    }
+
+    /// Write a file now, waiting for any other process writing one, then
+    /// delete files beyond the retention limit
+    ///
+    /// `write` gets a temporary path; only when it succeeds is the file
+    /// moved into place, so a crash never leaves a truncated file.
+    pub fn write<F>(&self, now: NaiveDateTime, write: F) -> Result<PathBuf>
+    where
+        F: FnOnce(&Path) -> Result<()>,
+    {
+        let _lock = FileLock::acquire(&self.lock_path())?;
+        self.write_locked(now, write)
+    }
+
+    fn write_locked<F>(&self, now: NaiveDateTime, write: F) -> Result<PathBuf>
+    where
+        F: FnOnce(&Path) -> Result<()>,
+    {
+        fs::create_dir_all(&self.directory)?;
+        let file_name = format!(
+            "{}{}{}",
+            self.prefix,
+            now.format(self.stamp_format),
+            self.suffix
+        );
+        let path = self.directory.join(&file_name);
+
+        let partial = self.directory.join(format!(".{}.partial", file_name));
+        if let Err(e) = write(&partial) {
+            let _ = fs::remove_file(&partial);
+            return Err(e);
+        }
+        fs::rename(&partial, &path)?;
+
+        self.prune()?;
+        Ok(path)
+    }
+
+    /// Delete the oldest files beyond the retention limit
+    ///
+    /// Returns the number of files deleted.
+    pub fn prune(&self) -> Result<usize> {
+        let files = self.existing()?;
+        let excess = files.len().saturating_sub(self.keep);
+
+        for (_, path) in &files[..excess] {
+            fs::remove_file(path)?;
+        }
+
+        Ok(excess)
+    }
+
+    /// Lock file held while a file is written (`.history.db.lock`)
+    fn lock_path(&self) -> PathBuf {
+        self.directory.join(format!(
+            ".{}{}.lock",
+            self.prefix.trim_end_matches('-'),
+            self.suffix
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 3, day)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_write_if_due_skips_while_locked() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let rotation = Rotation::new(dir.path(), "history-", ".txt", "%Y-%m-%d").with_keep(2);
+        let write = |path: &Path| Ok(fs::write(path, "export")?);
+
+        let lock = FileLock::try_acquire(&rotation.lock_path()).unwrap();
+        assert!(rotation.write_if_due(at(1), write).unwrap().is_none());
+        assert!(rotation.existing().unwrap().is_empty());
+        drop(lock);
+
+        let path = rotation.write_if_due(at(1), write).unwrap().unwrap();
+        assert_eq!(path.file_name().unwrap(), "history-2025-03-01.txt");
+        assert!(rotation.write_if_due(at(1), write).unwrap().is_none());
+
+        // A failed write leaves nothing behind
+        assert!(rotation
+            .write_if_due(at(2), |_| Err(crate::OmniscientError::other("disk full")))
+            .is_err());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        rotation.write(at(3), write).unwrap();
+        rotation.write(at(4), write).unwrap();
+        let days: Vec<u32> = rotation
+            .existing()
+            .unwrap()
+            .iter()
+            .map(|(stamp, _)| chrono::Datelike::day(stamp))
+            .collect();
+        assert_eq!(days, vec![3, 4]);
+    }
+}
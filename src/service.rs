@@ -0,0 +1,502 @@
+/// Service manager integration (`omniscient daemon install`)
+///
+/// Generates a systemd user unit (Linux) or launchd agent (macOS) keeping
+/// the query daemon (`omniscient serve --socket`) running, plus a daily job
+/// running `omniscient db maintain`. Installing writes the files and
+/// enables them with `systemctl --user` or `launchctl`.
+use crate::error::{OmniscientError, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// systemd unit of the daemon
+const SYSTEMD_DAEMON: &str = "omniscient.service";
+
+/// systemd units of the daily maintenance
+const SYSTEMD_MAINTENANCE: &str = "omniscient-maintenance.service";
+const SYSTEMD_TIMER: &str = "omniscient-maintenance.timer";
+
+/// launchd labels of the daemon and the daily maintenance
+const LAUNCHD_DAEMON: &str = "com.github.daneb.omniscient";
+const LAUNCHD_MAINTENANCE: &str = "com.github.daneb.omniscient.maintenance";
+
+/// Service manager that runs the daemon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceManager {
+    /// systemd user units in ~/.config/systemd/user
+    Systemd,
+
+    /// launchd agents in ~/Library/LaunchAgents
+    Launchd,
+}
+
+impl ServiceManager {
+    /// The service manager of this platform
+    pub fn detect() -> Result<Self> {
+        if cfg!(target_os = "macos") {
+            Ok(ServiceManager::Launchd)
+        } else if cfg!(target_os = "linux") {
+            Ok(ServiceManager::Systemd)
+        } else {
+            Err(OmniscientError::other(
+                "No supported service manager on this platform (systemd or launchd)",
+            ))
+        }
+    }
+
+    /// Directory the service files go in
+    pub fn directory(&self, home: &Path) -> PathBuf {
+        match self {
+            ServiceManager::Systemd => home.join(".config/systemd/user"),
+            ServiceManager::Launchd => home.join("Library/LaunchAgents"),
+        }
+    }
+
+    /// Names of the service files
+    fn file_names(&self) -> Vec<String> {
+        match self {
+            ServiceManager::Systemd => [SYSTEMD_DAEMON, SYSTEMD_MAINTENANCE, SYSTEMD_TIMER]
+                .map(String::from)
+                .to_vec(),
+            ServiceManager::Launchd => [LAUNCHD_DAEMON, LAUNCHD_MAINTENANCE]
+                .map(|label| format!("{}.plist", label))
+                .to_vec(),
+        }
+    }
+}
+
+/// Replace paths in the installed service files, such as a config path
+/// pinned in a directory that has since moved, returning the files changed
+///
+/// Each `(from, to)` pair is applied in order. systemd reloads the changed
+/// units and restarts the daemon if it is running.
+pub fn repoint(
+    manager: ServiceManager,
+    home: &Path,
+    replacements: &[(String, String)],
+) -> Result<Vec<PathBuf>> {
+    let directory = manager.directory(home);
+    let mut changed = Vec::new();
+    for name in manager.file_names() {
+        let path = directory.join(name);
+        let Ok(contents) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let updated = replacements
+            .iter()
+            .fold(contents.clone(), |text, (from, to)| text.replace(from, to));
+        if updated != contents {
+            fs::write(&path, updated)?;
+            changed.push(path);
+        }
+    }
+
+    if manager == ServiceManager::Systemd && !changed.is_empty() {
+        for args in [&["daemon-reload"][..], &["try-restart", SYSTEMD_DAEMON]] {
+            let _ = Command::new("systemctl")
+                .arg("--user")
+                .args(args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+    Ok(changed)
+}
+
+impl fmt::Display for ServiceManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceManager::Systemd => write!(f, "systemd"),
+            ServiceManager::Launchd => write!(f, "launchd"),
+        }
+    }
+}
+
+/// A generated service file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceFile {
+    /// Where the file is installed
+    pub path: PathBuf,
+
+    /// File contents
+    pub contents: String,
+}
+
+/// Service files running `program` (with `global_args`, such as `--config
+/// <path>`, before each subcommand)
+///
+/// `log_dir` receives the daemon's output under launchd; systemd keeps it in
+/// the journal.
+pub struct Services {
+    manager: ServiceManager,
+    directory: PathBuf,
+    program: PathBuf,
+    global_args: Vec<String>,
+    log_dir: PathBuf,
+}
+
+impl Services {
+    /// Services for `manager`, installed under `home`
+    pub fn new(
+        manager: ServiceManager,
+        home: &Path,
+        program: PathBuf,
+        global_args: Vec<String>,
+        log_dir: PathBuf,
+    ) -> Self {
+        Self {
+            manager,
+            directory: manager.directory(home),
+            program,
+            global_args,
+            log_dir,
+        }
+    }
+
+    /// The service manager
+    pub fn manager(&self) -> ServiceManager {
+        self.manager
+    }
+
+    /// Generate the service files
+    pub fn files(&self) -> Vec<ServiceFile> {
+        let daemon = self.command(&["serve", "--socket"]);
+        let maintenance = self.command(&["db", "maintain"]);
+
+        match self.manager {
+            ServiceManager::Systemd => vec![
+                self.file(SYSTEMD_DAEMON, systemd_daemon(&daemon)),
+                self.file(SYSTEMD_MAINTENANCE, systemd_maintenance(&maintenance)),
+                self.file(SYSTEMD_TIMER, SYSTEMD_TIMER_UNIT.to_string()),
+            ],
+            ServiceManager::Launchd => vec![
+                self.file(
+                    &format!("{}.plist", LAUNCHD_DAEMON),
+                    launchd_agent(LAUNCHD_DAEMON, &daemon, &self.log_dir, true),
+                ),
+                self.file(
+                    &format!("{}.plist", LAUNCHD_MAINTENANCE),
+                    launchd_agent(LAUNCHD_MAINTENANCE, &maintenance, &self.log_dir, false),
+                ),
+            ],
+        }
+    }
+
+    /// Write the service files and start them, returning the files written
+    pub fn install(&self, start: bool) -> Result<Vec<PathBuf>> {
+        fs::create_dir_all(&self.directory)?;
+        let files = self.files();
+        for file in &files {
+            fs::write(&file.path, &file.contents)?;
+        }
+
+        if start {
+            match self.manager {
+                ServiceManager::Systemd => {
+                    systemctl(&["daemon-reload"])?;
+                    systemctl(&["enable", "--now", SYSTEMD_DAEMON, SYSTEMD_TIMER])?;
+                }
+                ServiceManager::Launchd => {
+                    for file in &files {
+                        // Reloading picks up a changed file; unloading fails if it wasn't loaded
+                        let _ = launchctl(&["unload", &file.path.to_string_lossy()]);
+                        launchctl(&["load", "-w", &file.path.to_string_lossy()])?;
+                    }
+                }
+            }
+        }
+
+        Ok(files.into_iter().map(|file| file.path).collect())
+    }
+
+    /// Stop the services and delete their files, returning the files deleted
+    pub fn uninstall(&self) -> Result<Vec<PathBuf>> {
+        let files: Vec<PathBuf> = self
+            .files()
+            .into_iter()
+            .map(|file| file.path)
+            .filter(|path| path.exists())
+            .collect();
+
+        // Stopping fails harmlessly for services that are not running
+        match self.manager {
+            ServiceManager::Systemd => {
+                let _ = systemctl(&["disable", "--now", SYSTEMD_DAEMON, SYSTEMD_TIMER]);
+            }
+            ServiceManager::Launchd => {
+                for path in &files {
+                    let _ = launchctl(&["unload", "-w", &path.to_string_lossy()]);
+                }
+            }
+        }
+
+        for path in &files {
+            fs::remove_file(path)?;
+        }
+        if self.manager == ServiceManager::Systemd && !files.is_empty() {
+            let _ = systemctl(&["daemon-reload"]);
+        }
+
+        Ok(files)
+    }
+
+    /// The program, global arguments and `args`
+    fn command(&self, args: &[&str]) -> Vec<String> {
+        let mut command = vec![self.program.to_string_lossy().into_owned()];
+        command.extend(self.global_args.iter().cloned());
+        command.extend(args.iter().map(|arg| arg.to_string()));
+        command
+    }
+
+    fn file(&self, name: &str, contents: String) -> ServiceFile {
+        ServiceFile {
+            path: self.directory.join(name),
+            contents,
+        }
+    }
+}
+
+/// Daily timer of the maintenance unit
+const SYSTEMD_TIMER_UNIT: &str = "[Unit]
+Description=Daily Omniscient database maintenance
+
+[Timer]
+OnCalendar=daily
+Persistent=true
+RandomizedDelaySec=1h
+
+[Install]
+WantedBy=timers.target
+";
+
+fn systemd_daemon(command: &[String]) -> String {
+    format!(
+        "[Unit]
+Description=Omniscient command history daemon (query socket for editors and widgets)
+Documentation=https://github.com/daneb/omniscient
+
+[Service]
+Type=simple
+ExecStart={}
+Restart=on-failure
+RestartSec=5
+
+[Install]
+WantedBy=default.target
+",
+        systemd_command_line(command)
+    )
+}
+
+fn systemd_maintenance(command: &[String]) -> String {
+    format!(
+        "[Unit]
+Description=Omniscient database maintenance (integrity check, index rebuild, vacuum)
+
+[Service]
+Type=oneshot
+ExecStart={}
+",
+        systemd_command_line(command)
+    )
+}
+
+/// A command line for `ExecStart=`, quoting words with spaces or quotes
+fn systemd_command_line(command: &[String]) -> String {
+    command
+        .iter()
+        .map(|word| {
+            let word = word.replace('%', "%%");
+            if word.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+                format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+            } else {
+                word
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A launchd agent running `command`, either kept alive or daily at 03:30
+fn launchd_agent(label: &str, command: &[String], log_dir: &Path, keep_alive: bool) -> String {
+    let arguments: String = command
+        .iter()
+        .map(|word| format!("        <string>{}</string>\n", xml_escape(word)))
+        .collect();
+    let schedule = if keep_alive {
+        "    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+"
+    } else {
+        "    <key>StartCalendarInterval</key>
+    <dict>
+        <key>Hour</key>
+        <integer>3</integer>
+        <key>Minute</key>
+        <integer>30</integer>
+    </dict>
+"
+    };
+    let log = xml_escape(&log_dir.join(format!("{}.log", label)).to_string_lossy());
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}    </array>
+{schedule}    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    run("systemctl", &[&["--user"], args].concat())
+}
+
+fn launchctl(args: &[&str]) -> Result<()> {
+    run("launchctl", args)
+}
+
+/// Run a service manager command, failing if it does
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|e| OmniscientError::other(format!("Could not run {}: {}", program, e)))?;
+    if !status.success() {
+        return Err(OmniscientError::other(format!(
+            "{} {} failed ({})",
+            program,
+            args.join(" "),
+            status
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn services(manager: ServiceManager) -> Services {
+        Services::new(
+            manager,
+            Path::new("/home/me"),
+            PathBuf::from("/opt/my tools/omniscient"),
+            vec![
+                "--config".to_string(),
+                "/home/me/.omniscient/config.toml".to_string(),
+            ],
+            PathBuf::from("/home/me/.omniscient"),
+        )
+    }
+
+    #[test]
+    fn test_systemd_units() {
+        let files = services(ServiceManager::Systemd).files();
+        let names: Vec<String> = files
+            .iter()
+            .map(|f| f.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            vec![SYSTEMD_DAEMON, SYSTEMD_MAINTENANCE, SYSTEMD_TIMER]
+        );
+        assert!(files[0].path.starts_with("/home/me/.config/systemd/user"));
+        assert!(files[0].contents.contains(
+            "ExecStart=\"/opt/my tools/omniscient\" --config /home/me/.omniscient/config.toml serve --socket\n"
+        ));
+        assert!(files[1].contents.contains("db maintain\n"));
+        assert!(files[2].contents.contains("OnCalendar=daily"));
+    }
+
+    #[test]
+    fn test_launchd_agents() {
+        let files = services(ServiceManager::Launchd).files();
+        assert_eq!(
+            files[0].path,
+            PathBuf::from("/home/me/Library/LaunchAgents/com.github.daneb.omniscient.plist")
+        );
+        assert!(files[0]
+            .contents
+            .contains("<string>/opt/my tools/omniscient</string>"));
+        assert!(files[0].contents.contains("<key>KeepAlive</key>"));
+        assert!(files[1]
+            .contents
+            .contains("<key>StartCalendarInterval</key>"));
+        assert!(files[1].contents.contains("<string>maintain</string>"));
+    }
+
+    #[test]
+    fn test_repoint_installed_files() {
+        let home = tempfile::TempDir::new().unwrap();
+        // launchd: nothing to reload
+        let services = Services::new(
+            ServiceManager::Launchd,
+            home.path(),
+            PathBuf::from("/bin/omniscient"),
+            vec![
+                "--config".to_string(),
+                "/h/.omniscient/config.toml".to_string(),
+            ],
+            PathBuf::from("/h/.omniscient"),
+        );
+        services.install(false).unwrap();
+
+        let changed = repoint(
+            ServiceManager::Launchd,
+            home.path(),
+            &[
+                (
+                    "/h/.omniscient/config.toml".to_string(),
+                    "/h/.config/omniscient/config.toml".to_string(),
+                ),
+                (
+                    "/h/.omniscient".to_string(),
+                    "/h/.local/share/omniscient".to_string(),
+                ),
+            ],
+        )
+        .unwrap();
+        assert_eq!(changed.len(), 2);
+        let daemon = fs::read_to_string(&changed[0]).unwrap();
+        assert!(daemon.contains("<string>/h/.config/omniscient/config.toml</string>"));
+        assert!(daemon.contains("/h/.local/share/omniscient/com.github.daneb.omniscient.log"));
+        assert!(!daemon.contains(".omniscient/"));
+
+        // Already up to date
+        assert!(repoint(ServiceManager::Launchd, home.path(), &[])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_systemd_command_line_quoting() {
+        let words = ["/bin/omniscient", "--db", "/tmp/50% \"full\".db"].map(String::from);
+        assert_eq!(
+            systemd_command_line(&words),
+            r#"/bin/omniscient --db "/tmp/50%% \"full\".db""#
+        );
+    }
+}
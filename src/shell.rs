@@ -1,6 +1,9 @@
-use crate::Result;
+use crate::{OmniscientError, Result};
 /// Shell integration - generates hooks for different shells
 use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 
 /// Supported shell types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -19,25 +22,123 @@ impl fmt::Display for ShellType {
     }
 }
 
+/// Where to capture from on Windows, where only zsh and bash have hooks
+pub const WINDOWS_SHELL_HINT: &str = "PowerShell and cmd have no hook yet. \
+On Windows, capture from WSL or Git Bash: run `omniscient init --shell bash` there.";
+
 /// Shell hook generator
 pub struct ShellHook {
     shell_type: ShellType,
+    compat_mode: bool,
+    output_capture: bool,
+    alert_warnings: Option<String>,
+    dir_suggestions: bool,
 }
 
+/// Placeholders in the hook templates, filled in by [`ShellHook::fill`]
+const OUTPUT_START: &str = "#OUTPUT_START#\n";
+const OUTPUT_STOP: &str = "#OUTPUT_STOP#\n";
+const OUTPUT_ARG: &str = "#OUTPUT_ARG#";
+const ALERTS: &str = "#ALERTS#\n";
+
+/// Programs that need a real terminal, so their output is never teed
+const TERMINAL_PROGRAMS: &str =
+    "vi|vim|nvim|nano|emacs|less|more|man|top|htop|ssh|tmux|screen|fzf|watch";
+
 impl ShellHook {
     /// Create a new shell hook generator
     pub fn new(shell_type: ShellType) -> Self {
-        Self { shell_type }
+        Self {
+            shell_type,
+            compat_mode: false,
+            output_capture: false,
+            alert_warnings: None,
+            dir_suggestions: false,
+        }
+    }
+
+    /// Generate hooks that coexist with other history tools (atuin, mcfly, ...)
+    ///
+    /// In compatibility mode the hook only captures: it never binds keys, and its
+    /// precmd function is registered first so other tools cannot clobber `$?`.
+    pub fn with_compat_mode(mut self) -> Self {
+        self.compat_mode = true;
+        self
+    }
+
+    /// Whether compatibility mode is enabled
+    pub fn is_compat_mode(&self) -> bool {
+        self.compat_mode
+    }
+
+    /// Generate hooks that tee each command's stdout/stderr into a temporary
+    /// file and hand it to `omniscient capture --output-file`
+    ///
+    /// Commands then write to a pipe instead of the terminal, so programs
+    /// that need a terminal (editors, pagers, ssh, ...) are left alone.
+    pub fn with_output_capture(mut self) -> Self {
+        self.output_capture = true;
+        self
+    }
+
+    /// Generate hooks that print and clear the alert warnings queued in
+    /// `path` before each prompt
+    pub fn with_alert_warnings(mut self, path: &Path) -> Self {
+        self.alert_warnings = Some(path.to_string_lossy().into_owned());
+        self
+    }
+
+    /// Generate hooks that print the most used commands of each directory
+    /// entered with `cd` (via `omniscient on-cd`)
+    pub fn with_dir_suggestions(mut self) -> Self {
+        self.dir_suggestions = true;
+        self
     }
 
     /// Generate the shell hook code
     pub fn generate(&self) -> String {
-        match self.shell_type {
-            ShellType::Zsh => self.generate_zsh(),
-            ShellType::Bash => self.generate_bash(),
+        let (code, suggestions) = match self.shell_type {
+            ShellType::Zsh => (self.fill(self.generate_zsh()), ZSH_DIR_SUGGESTIONS),
+            ShellType::Bash => (self.fill(self.generate_bash()), BASH_DIR_SUGGESTIONS),
+        };
+        if self.dir_suggestions {
+            code + suggestions
+        } else {
+            code
         }
     }
 
+    /// Fill the output capture and alert placeholders of a hook template
+    fn fill(&self, template: String) -> String {
+        let alerts = match self.alert_warnings {
+            Some(ref path) => {
+                let path = format!("'{}'", path.replace('\'', r"'\''"));
+                format!(
+                    "    if [[ -s {path} ]]; then\n        command cat {path} >&2\n        command rm -f {path}\n    fi\n"
+                )
+            }
+            None => String::new(),
+        };
+        let template = template.replace(ALERTS, &alerts);
+
+        if !self.output_capture {
+            return template
+                .replace(OUTPUT_START, "")
+                .replace(OUTPUT_STOP, "")
+                .replace(OUTPUT_ARG, "");
+        }
+
+        let functions = match self.shell_type {
+            ShellType::Zsh => ZSH_OUTPUT_FUNCTIONS,
+            ShellType::Bash => BASH_OUTPUT_FUNCTIONS,
+        };
+        template
+            .replace(OUTPUT_START, "    _omniscient_output_start \"$1\"\n")
+            .replace(OUTPUT_STOP, "    _omniscient_output_stop\n")
+            .replace(OUTPUT_ARG, "--output-file \"$_OMNISCIENT_OUTPUT\" ")
+            + &functions.replace("#PROGRAMS#", TERMINAL_PROGRAMS)
+    }
+
     /// Generate Zsh hook code
     fn generate_zsh(&self) -> String {
         r#"
@@ -45,37 +146,73 @@ impl ShellHook {
 # Generated by: omniscient init
 # Add this to your ~/.zshrc
 
+# Identify this shell session so command sequences can be grouped
+export OMNISCIENT_SESSION="${OMNISCIENT_SESSION:-zsh-$$-$(date +%s)}"
+
 # Start timer before command execution
+# $1 holds the command exactly as typed (line breaks and indentation
+# included), $3 the full command with aliases expanded
 _omniscient_preexec() {
     export _OMNISCIENT_START=$EPOCHREALTIME
+    _OMNISCIENT_TYPED="$1"
+    _OMNISCIENT_EXPANDED="$3"
+    # Identifies this run, so capturing it twice stores it once
+    _OMNISCIENT_CAPTURE_ID="$OMNISCIENT_SESSION-$_OMNISCIENT_START-$RANDOM"
+#OUTPUT_START#
 }
 
 # Capture command after execution
 _omniscient_precmd() {
     local exit_code=$?
-    local cmd=$(fc -ln -1 | sed 's/^[[:space:]]*//')
-    
+#OUTPUT_STOP#
+#ALERTS#
+
+    # Incognito: OMNISCIENT_DISABLE=1 stops capture in this shell
+    if [[ -n "$OMNISCIENT_DISABLE" && "$OMNISCIENT_DISABLE" != 0 ]]; then
+        unset _OMNISCIENT_START _OMNISCIENT_TYPED _OMNISCIENT_EXPANDED _OMNISCIENT_CAPTURE_ID
+        return
+    fi
+
+    local cmd="$_OMNISCIENT_TYPED"
+
     if [[ -n "$_OMNISCIENT_START" ]]; then
         local end=$EPOCHREALTIME
         local duration=$(( int((end - _OMNISCIENT_START) * 1000) ))
 
         # Run capture in background to avoid blocking shell
         # Redirect output and disown to prevent job notifications
-        omniscient capture --exit-code "$exit_code" --duration "$duration" "$cmd" &>/dev/null &!
+        omniscient capture --exit-code "$exit_code" --duration "$duration" \
+            --timestamp "$_OMNISCIENT_START" --capture-id "$_OMNISCIENT_CAPTURE_ID" \
+            --expanded "$_OMNISCIENT_EXPANDED" #OUTPUT_ARG#"$cmd" &>/dev/null &!
 
-        unset _OMNISCIENT_START
+        unset _OMNISCIENT_START _OMNISCIENT_TYPED _OMNISCIENT_EXPANDED _OMNISCIENT_CAPTURE_ID
     fi
 }
 
-# Register hooks with Zsh
-precmd_functions+=(_omniscient_precmd)
-preexec_functions+=(_omniscient_preexec)
-
+"#
+        .to_string()
+            + self.zsh_registration()
+            + r#"
 # Optional: Set up completion (future enhancement)
 # autoload -Uz compinit
 # compinit
 "#
-        .to_string()
+    }
+
+    /// Zsh hook registration, respecting compatibility mode
+    fn zsh_registration(&self) -> &'static str {
+        if self.compat_mode {
+            r#"# Register hooks with Zsh (compatibility mode: capture only, no key bindings)
+# Our precmd runs first so other history tools cannot clobber the exit status
+precmd_functions=(_omniscient_precmd ${precmd_functions:#_omniscient_precmd})
+preexec_functions+=(_omniscient_preexec)
+"#
+        } else {
+            r#"# Register hooks with Zsh
+precmd_functions+=(_omniscient_precmd)
+preexec_functions+=(_omniscient_preexec)
+"#
+        }
     }
 
     /// Generate Bash hook code
@@ -90,34 +227,76 @@ preexec_functions+=(_omniscient_preexec)
 # curl -sSL https://github.com/rcaloras/bash-preexec/raw/master/bash-preexec.sh -o ~/.bash-preexec.sh
 # Then add to your bash profile: source ~/.bash-preexec.sh
 
+# Identify this shell session so command sequences can be grouped
+export OMNISCIENT_SESSION="${OMNISCIENT_SESSION:-bash-$$-$(date +%s)}"
+
 # Start timer before command execution
 _omniscient_preexec() {
     _OMNISCIENT_START=$(date +%s%N)
+    # Identifies this run, so capturing it twice stores it once
+    _OMNISCIENT_CAPTURE_ID="$OMNISCIENT_SESSION-$_OMNISCIENT_START-$RANDOM"
+#OUTPUT_START#
 }
 
 # Capture command after execution
 _omniscient_precmd() {
     local exit_code=$?
-    local cmd=$(history 1 | sed 's/^[ ]*[0-9]*[ ]*//')
+#OUTPUT_STOP#
+#ALERTS#
+
+    # Incognito: OMNISCIENT_DISABLE=1 stops capture in this shell
+    if [[ -n "$OMNISCIENT_DISABLE" && "$OMNISCIENT_DISABLE" != 0 ]]; then
+        unset _OMNISCIENT_START _OMNISCIENT_CAPTURE_ID
+        return
+    fi
+
+    # Only the first line starts with the history number; later lines of a
+    # multi-line command (heredocs, loops) are kept as typed
+    local cmd=$(HISTTIMEFORMAT= builtin history 1 | sed '1 s/^[ ]*[0-9]*[* ]*//')
+
+    # Expand a leading alias so it can be categorized (e.g. gst -> git status)
+    local first=${cmd%% *}
+    local expanded=""
+    if [[ -n "$first" ]] && [[ -n "${BASH_ALIASES[$first]+x}" ]]; then
+        expanded="${BASH_ALIASES[$first]}${cmd:${#first}}"
+    fi
 
     if [[ -n "$_OMNISCIENT_START" ]]; then
         local end=$(date +%s%N)
         local duration=$(( (end - _OMNISCIENT_START) / 1000000 ))
+        # Start time in epoch seconds with milliseconds
+        local started="$(( _OMNISCIENT_START / 1000000000 )).${_OMNISCIENT_START: -9:3}"
 
         # Run capture in background to avoid blocking shell
         # Redirect output and disown to prevent job notifications
-        omniscient capture --exit-code "$exit_code" --duration "$duration" "$cmd" &>/dev/null &
+        omniscient capture --exit-code "$exit_code" --duration "$duration" \
+            --timestamp "$started" --capture-id "$_OMNISCIENT_CAPTURE_ID" \
+            --expanded "$expanded" #OUTPUT_ARG#"$cmd" &>/dev/null &
         disown
 
-        unset _OMNISCIENT_START
+        unset _OMNISCIENT_START _OMNISCIENT_CAPTURE_ID
     fi
 }
 
-# Register hooks with bash-preexec
+"#
+        .to_string()
+            + self.bash_registration()
+    }
+
+    /// Bash hook registration, respecting compatibility mode
+    fn bash_registration(&self) -> &'static str {
+        if self.compat_mode {
+            r#"# Register hooks with bash-preexec (compatibility mode: capture only, no key bindings)
+# Our precmd runs first so other history tools cannot clobber the exit status
+preexec_functions+=(_omniscient_preexec)
+precmd_functions=(_omniscient_precmd "${precmd_functions[@]}")
+"#
+        } else {
+            r#"# Register hooks with bash-preexec
 preexec_functions+=(_omniscient_preexec)
 precmd_functions+=(_omniscient_precmd)
 "#
-        .to_string()
+        }
     }
 
     /// Auto-detect the current shell
@@ -131,6 +310,14 @@ precmd_functions+=(_omniscient_precmd)
             }
         }
 
+        // Native Windows shells don't set $SHELL and can't run the hooks
+        if cfg!(windows) {
+            return Err(OmniscientError::other(format!(
+                "Could not detect zsh or bash. {}",
+                WINDOWS_SHELL_HINT
+            )));
+        }
+
         // Default to Zsh if detection fails
         Ok(ShellType::Zsh)
     }
@@ -199,12 +386,259 @@ Optional: Export your history before setup to preserve existing data.
     }
 }
 
+/// Zsh functions that tee command output into a temporary file
+const ZSH_OUTPUT_FUNCTIONS: &str = r#"
+# Output capture (capture.output): tee stdout/stderr into a temporary file
+# that `omniscient capture` reads the tail of and deletes
+_omniscient_output_start() {
+    _OMNISCIENT_OUTPUT=""
+    [[ -n "$OMNISCIENT_DISABLE" && "$OMNISCIENT_DISABLE" != 0 ]] && return
+    case ${${(z)1}[1]} in
+        #PROGRAMS#) return ;;
+    esac
+    _OMNISCIENT_OUTPUT=$(mktemp "${TMPDIR:-/tmp}/omniscient-output.XXXXXX") || return
+    exec {_OMNISCIENT_STDOUT}>&1 {_OMNISCIENT_STDERR}>&2
+    exec > >(tee -a "$_OMNISCIENT_OUTPUT") 2> >(tee -a "$_OMNISCIENT_OUTPUT" >&2)
+}
+
+_omniscient_output_stop() {
+    [[ -n "$_OMNISCIENT_STDOUT" ]] || return
+    exec 1>&$_OMNISCIENT_STDOUT 2>&$_OMNISCIENT_STDERR
+    exec {_OMNISCIENT_STDOUT}>&- {_OMNISCIENT_STDERR}>&-
+    unset _OMNISCIENT_STDOUT _OMNISCIENT_STDERR
+}
+"#;
+
+/// Bash functions that tee command output into a temporary file (bash 4.1+)
+const BASH_OUTPUT_FUNCTIONS: &str = r#"
+# Output capture (capture.output): tee stdout/stderr into a temporary file
+# that `omniscient capture` reads the tail of and deletes (needs bash 4.1+)
+_omniscient_output_start() {
+    _OMNISCIENT_OUTPUT=""
+    [[ -n "$OMNISCIENT_DISABLE" && "$OMNISCIENT_DISABLE" != 0 ]] && return
+    local program=${1%% *}
+    case $program in
+        #PROGRAMS#) return ;;
+    esac
+    _OMNISCIENT_OUTPUT=$(mktemp "${TMPDIR:-/tmp}/omniscient-output.XXXXXX") || return
+    exec {_OMNISCIENT_STDOUT}>&1 {_OMNISCIENT_STDERR}>&2
+    exec > >(tee -a "$_OMNISCIENT_OUTPUT") 2> >(tee -a "$_OMNISCIENT_OUTPUT" >&2)
+}
+
+_omniscient_output_stop() {
+    [[ -n "$_OMNISCIENT_STDOUT" ]] || return
+    exec 1>&$_OMNISCIENT_STDOUT 2>&$_OMNISCIENT_STDERR
+    exec {_OMNISCIENT_STDOUT}>&- {_OMNISCIENT_STDERR}>&-
+    unset _OMNISCIENT_STDOUT _OMNISCIENT_STDERR
+}
+"#;
+
+/// Zsh function printing the most used commands of each directory entered
+const ZSH_DIR_SUGGESTIONS: &str = r#"
+# Directory suggestions (suggestions.on_cd): show the commands you run most
+# in each directory you enter
+_omniscient_chpwd() {
+    [[ -o interactive ]] || return
+    omniscient on-cd 2>/dev/null
+}
+chpwd_functions+=(_omniscient_chpwd)
+"#;
+
+/// Bash function printing the most used commands of each directory entered
+/// (bash has no chpwd hook, so the directory is compared before each prompt)
+const BASH_DIR_SUGGESTIONS: &str = r#"
+# Directory suggestions (suggestions.on_cd): show the commands you run most
+# in each directory you enter
+_omniscient_chpwd() {
+    [[ "$PWD" == "$_OMNISCIENT_LAST_DIR" ]] && return
+    [[ -n "$_OMNISCIENT_LAST_DIR" ]] && omniscient on-cd 2>/dev/null
+    _OMNISCIENT_LAST_DIR=$PWD
+}
+precmd_functions+=(_omniscient_chpwd)
+"#;
+
 impl Default for ShellHook {
     fn default() -> Self {
         Self::new(ShellType::Zsh)
     }
 }
 
+/// First line of the hook block `omniscient init --write` manages in rc
+/// files, followed by the version that wrote it
+pub(crate) const BLOCK_START: &str = "# >>> omniscient hook";
+
+/// Last line of the managed hook block
+pub(crate) const BLOCK_END: &str = "# <<< omniscient hook <<<";
+
+/// What writing or removing the managed hook block did to an rc file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RcChange {
+    /// The block was added
+    Added,
+
+    /// The block was replaced; `from` is the version that wrote the old one
+    Updated { from: Option<String> },
+
+    /// The block was already up to date
+    Unchanged,
+
+    /// The block was removed
+    Removed,
+
+    /// There was no block to remove
+    NotFound,
+}
+
+/// The rc file `omniscient init --write` edits for a shell
+pub fn rc_file(shell: ShellType, home: &Path) -> PathBuf {
+    match shell {
+        ShellType::Zsh => home.join(".zshrc"),
+        ShellType::Bash => home.join(".bashrc"),
+    }
+}
+
+impl ShellHook {
+    /// Write the hook into its managed block in `rc`, adding the block (and
+    /// the file) if needed
+    ///
+    /// The file is only written when the block changes.
+    pub fn write_to(&self, rc: &Path) -> Result<RcChange> {
+        let contents = read_rc(rc)?;
+        let (updated, change) =
+            write_block(&contents, &self.generate(), env!("CARGO_PKG_VERSION"))?;
+        if change != RcChange::Unchanged {
+            write_rc(rc, &updated)?;
+        }
+        Ok(change)
+    }
+}
+
+/// Remove the managed hook block from `rc`
+pub fn remove_hook(rc: &Path) -> Result<RcChange> {
+    let contents = read_rc(rc)?;
+    let (updated, change) = remove_block(&contents)?;
+    if change == RcChange::Removed {
+        write_rc(rc, &updated)?;
+    }
+    Ok(change)
+}
+
+/// Replace an rc file's contents through a temporary file, so a crash or a
+/// full disk never leaves it half written
+///
+/// A symlinked rc file (as dotfile managers create) is written through the
+/// link, and the file keeps its permissions.
+fn write_rc(rc: &Path, contents: &str) -> Result<()> {
+    let target = match fs::canonicalize(rc) {
+        Ok(target) => target,
+        Err(e) if e.kind() == ErrorKind::NotFound => rc.to_path_buf(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut name = std::ffi::OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(".omniscient-partial");
+    let partial = target.with_file_name(name);
+
+    let written = fs::write(&partial, contents).and_then(|()| {
+        if let Ok(metadata) = fs::metadata(&target) {
+            fs::set_permissions(&partial, metadata.permissions())?;
+        }
+        fs::rename(&partial, &target)
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&partial);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// An rc file's contents, empty if it does not exist
+fn read_rc(rc: &Path) -> Result<String> {
+    match fs::read_to_string(rc) {
+        Ok(contents) => Ok(contents),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// `contents` with the managed block holding `hook`, written by `version`,
+/// added at the end or replacing the existing block
+pub fn write_block(contents: &str, hook: &str, version: &str) -> Result<(String, RcChange)> {
+    let block = format!(
+        "{} (v{}) >>>\n{}\n{}\n",
+        BLOCK_START,
+        version,
+        hook.trim_matches('\n'),
+        BLOCK_END
+    );
+
+    let Some((start, end)) = find_block(contents)? else {
+        let mut updated = contents.to_string();
+        if !updated.is_empty() {
+            if !updated.ends_with('\n') {
+                updated.push('\n');
+            }
+            updated.push('\n');
+        }
+        updated.push_str(&block);
+        return Ok((updated, RcChange::Added));
+    };
+
+    let existing = &contents[start..end];
+    if existing.trim_end() == block.trim_end() {
+        return Ok((contents.to_string(), RcChange::Unchanged));
+    }
+    let from = existing
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix(BLOCK_START))
+        .and_then(|rest| rest.trim().strip_prefix("(v"))
+        .and_then(|rest| rest.split_once(')'))
+        .map(|(version, _)| version.to_string());
+
+    let updated = format!("{}{}{}", &contents[..start], block, &contents[end..]);
+    Ok((updated, RcChange::Updated { from }))
+}
+
+/// `contents` without the managed block (and the blank line added before it)
+pub fn remove_block(contents: &str) -> Result<(String, RcChange)> {
+    let Some((start, end)) = find_block(contents)? else {
+        return Ok((contents.to_string(), RcChange::NotFound));
+    };
+
+    let before = &contents[..start];
+    let before = before
+        .strip_suffix('\n')
+        .filter(|b| b.ends_with('\n'))
+        .unwrap_or(before);
+    Ok((format!("{}{}", before, &contents[end..]), RcChange::Removed))
+}
+
+/// Byte range of the managed block, including its final newline
+fn find_block(contents: &str) -> Result<Option<(usize, usize)>> {
+    let mut start = None;
+    let mut offset = 0;
+
+    for line in contents.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if start.is_none() && trimmed.starts_with(BLOCK_START) {
+            start = Some(offset);
+        } else if let (Some(start), BLOCK_END) = (start, trimmed) {
+            return Ok(Some((start, offset + line.len())));
+        }
+        offset += line.len();
+    }
+
+    match start {
+        // Replacing up to the end of the file could delete the user's own lines
+        Some(_) => Err(OmniscientError::Shell(format!(
+            "The omniscient hook block has no end marker ('{}'); fix the rc file by hand",
+            BLOCK_END
+        ))),
+        None => Ok(None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -224,6 +658,10 @@ mod tests {
         assert!(code.contains("_omniscient_preexec"));
         assert!(code.contains("_omniscient_precmd"));
         assert!(code.contains("EPOCHREALTIME"));
+        assert!(code.contains("--timestamp \"$_OMNISCIENT_START\"")); // Recorded at the start
+        assert!(code.contains("--capture-id \"$_OMNISCIENT_CAPTURE_ID\"")); // Set in preexec
+        assert!(code.contains("_OMNISCIENT_TYPED=\"$1\"")); // Multi-line commands kept as typed
+        assert!(!code.contains("fc -ln"));
         assert!(code.contains("precmd_functions+="));
         assert!(code.contains("preexec_functions+="));
         assert!(code.contains("omniscient capture"));
@@ -290,12 +728,15 @@ mod tests {
         assert!(code.contains("_omniscient_preexec"));
         assert!(code.contains("_omniscient_precmd"));
         assert!(code.contains("date +%s%N")); // Bash timing
+        assert!(code.contains("--timestamp \"$started\""));
         assert!(code.contains("history 1")); // Bash command extraction
+        assert!(code.contains("sed '1 s/")); // Later lines of multi-line commands kept
         assert!(code.contains("preexec_functions+="));
         assert!(code.contains("precmd_functions+="));
         assert!(code.contains("omniscient capture"));
         assert!(code.contains("&")); // Background execution
         assert!(code.contains("disown")); // Bash disown
+        assert!(code.contains("--capture-id \"$_OMNISCIENT_CAPTURE_ID\""));
     }
 
     #[test]
@@ -344,6 +785,41 @@ mod tests {
         assert!(code.contains("--duration"));
     }
 
+    #[test]
+    fn test_hooks_pass_alias_expansion() {
+        let zsh = ShellHook::new(ShellType::Zsh).generate();
+        assert!(zsh.contains("_OMNISCIENT_EXPANDED=\"$3\""));
+        assert!(zsh.contains("--expanded"));
+
+        let bash = ShellHook::new(ShellType::Bash).generate();
+        assert!(bash.contains("BASH_ALIASES"));
+        assert!(bash.contains("--expanded"));
+    }
+
+    #[test]
+    fn test_compat_mode_registers_precmd_first() {
+        let zsh = ShellHook::new(ShellType::Zsh).with_compat_mode();
+        assert!(zsh.is_compat_mode());
+        let code = zsh.generate();
+        assert!(code.contains("precmd_functions=(_omniscient_precmd"));
+        assert!(!code.contains("precmd_functions+="));
+        assert!(!code.contains("bindkey"));
+
+        let bash = ShellHook::new(ShellType::Bash)
+            .with_compat_mode()
+            .generate();
+        assert!(bash.contains("precmd_functions=(_omniscient_precmd \"${precmd_functions[@]}\")"));
+        assert!(!bash.contains("bind -x"));
+    }
+
+    #[test]
+    fn test_hooks_export_session() {
+        for shell in [ShellType::Zsh, ShellType::Bash] {
+            let code = ShellHook::new(shell).generate();
+            assert!(code.contains("export OMNISCIENT_SESSION="));
+        }
+    }
+
     #[test]
     fn test_hook_captures_all_required_data() {
         let hook = ShellHook::new(ShellType::Zsh);
@@ -356,4 +832,145 @@ mod tests {
         assert!(code.contains("--exit-code"));
         assert!(code.contains("--duration"));
     }
+
+    #[test]
+    fn test_output_capture_is_opt_in() {
+        for shell_type in [ShellType::Zsh, ShellType::Bash] {
+            let code = ShellHook::new(shell_type).generate();
+            assert!(!code.contains("#OUTPUT"), "{}", shell_type);
+            assert!(!code.contains("--output-file"), "{}", shell_type);
+            assert!(!code.contains("tee"), "{}", shell_type);
+
+            let code = ShellHook::new(shell_type).with_output_capture().generate();
+            assert!(!code.contains("#OUTPUT"), "{}", shell_type);
+            assert!(!code.contains("#PROGRAMS#"), "{}", shell_type);
+            assert!(code.contains("_omniscient_output_start \"$1\""));
+            assert!(code.contains("--output-file \"$_OMNISCIENT_OUTPUT\" \"$cmd\""));
+            assert!(code.contains("vim|nvim"));
+
+            // Output is restored before anything else runs after the command
+            let precmd = code.find("local exit_code=$?").unwrap();
+            let stop = code.find("    _omniscient_output_stop").unwrap();
+            let disable = code.find("# Incognito").unwrap();
+            assert!(precmd < stop && stop < disable, "{}", shell_type);
+        }
+    }
+
+    #[test]
+    fn test_alert_warnings() {
+        for shell_type in [ShellType::Zsh, ShellType::Bash] {
+            let code = ShellHook::new(shell_type).generate();
+            assert!(!code.contains("#ALERTS#"), "{}", shell_type);
+            assert!(!code.contains("command cat"), "{}", shell_type);
+
+            let code = ShellHook::new(shell_type)
+                .with_alert_warnings(Path::new("/home/o'neil/history.db.alerts"))
+                .generate();
+            assert!(code.contains("if [[ -s '/home/o'\\''neil/history.db.alerts' ]]; then"));
+            assert!(code.contains("command cat '/home/o'\\''neil/history.db.alerts' >&2"));
+        }
+    }
+
+    #[test]
+    fn test_dir_suggestions_are_opt_in() {
+        for shell_type in [ShellType::Zsh, ShellType::Bash] {
+            let code = ShellHook::new(shell_type).generate();
+            assert!(!code.contains("omniscient on-cd"), "{}", shell_type);
+
+            let code = ShellHook::new(shell_type)
+                .with_compat_mode()
+                .with_dir_suggestions()
+                .generate();
+            assert!(code.contains("omniscient on-cd"), "{}", shell_type);
+            assert!(code.contains("_functions+=(_omniscient_chpwd)"));
+        }
+    }
+
+    #[test]
+    fn test_write_block_adds_updates_and_removes() {
+        let rc = "export EDITOR=vim";
+
+        let (added, change) = write_block(rc, "hook v1\n", "1.0.0").unwrap();
+        assert_eq!(change, RcChange::Added);
+        assert_eq!(
+            added,
+            "export EDITOR=vim\n\n# >>> omniscient hook (v1.0.0) >>>\nhook v1\n# <<< omniscient hook <<<\n"
+        );
+
+        let (same, change) = write_block(&added, "hook v1", "1.0.0").unwrap();
+        assert_eq!(change, RcChange::Unchanged);
+        assert_eq!(same, added);
+
+        let edited = format!("{}alias ll='ls -l'\n", added);
+        let (updated, change) = write_block(&edited, "hook v2", "1.1.0").unwrap();
+        assert_eq!(
+            change,
+            RcChange::Updated {
+                from: Some("1.0.0".to_string())
+            }
+        );
+        assert!(updated.contains("(v1.1.0) >>>\nhook v2\n"));
+        assert!(!updated.contains("hook v1"));
+        assert!(updated.ends_with("<<<\nalias ll='ls -l'\n"));
+
+        let (removed, change) = remove_block(&updated).unwrap();
+        assert_eq!(change, RcChange::Removed);
+        assert_eq!(removed, "export EDITOR=vim\nalias ll='ls -l'\n");
+        assert_eq!(remove_block(&removed).unwrap().1, RcChange::NotFound);
+    }
+
+    #[test]
+    fn test_unterminated_block_is_left_alone() {
+        let rc = "# >>> omniscient hook (v1.0.0) >>>\nhook\nexport PATH=~/bin:$PATH\n";
+        assert!(write_block(rc, "hook", "1.0.0").is_err());
+        assert!(remove_block(rc).is_err());
+    }
+
+    #[test]
+    fn test_write_to_and_remove_hook() {
+        let home = tempfile::TempDir::new().unwrap();
+        let rc = rc_file(ShellType::Bash, home.path());
+        let hook = ShellHook::new(ShellType::Bash);
+
+        assert_eq!(hook.write_to(&rc).unwrap(), RcChange::Added);
+        assert_eq!(hook.write_to(&rc).unwrap(), RcChange::Unchanged);
+        assert!(crate::lint::find_hook(ShellType::Bash, home.path())
+            .unwrap()
+            .is_some());
+
+        assert_eq!(remove_hook(&rc).unwrap(), RcChange::Removed);
+        assert_eq!(fs::read_to_string(&rc).unwrap(), "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_to_keeps_symlinked_rc() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let home = tempfile::TempDir::new().unwrap();
+        let dotfiles = home.path().join("dotfiles");
+        fs::create_dir(&dotfiles).unwrap();
+        let real = dotfiles.join("bashrc");
+        fs::write(&real, "export EDITOR=vim\n").unwrap();
+        fs::set_permissions(&real, fs::Permissions::from_mode(0o600)).unwrap();
+        let rc = rc_file(ShellType::Bash, home.path());
+        std::os::unix::fs::symlink(&real, &rc).unwrap();
+
+        ShellHook::new(ShellType::Bash).write_to(&rc).unwrap();
+        assert!(fs::symlink_metadata(&rc).unwrap().file_type().is_symlink());
+        assert!(fs::read_to_string(&real).unwrap().contains(BLOCK_END));
+        assert_eq!(
+            fs::metadata(&real).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert_eq!(fs::read_dir(&dotfiles).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_hooks_honor_disable_env() {
+        for shell_type in [ShellType::Zsh, ShellType::Bash] {
+            let code = ShellHook::new(shell_type).generate();
+            assert!(code.contains("OMNISCIENT_DISABLE"), "{}", shell_type);
+        }
+    }
 }
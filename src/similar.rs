@@ -0,0 +1,175 @@
+/// Similar command lookup (`omniscient similar`)
+///
+/// Finds stored commands that resemble one you half remember. Two measures
+/// are combined and the higher one wins: trigram similarity catches small
+/// edits (`ffmpeg -i in.mp4 -crf 23` vs `-crf 28`), token-set similarity
+/// catches the same words in another order (`tar -xzf a.tgz -C /tmp` vs
+/// `tar -C /tmp -xzf a.tgz`). Both are Jaccard indexes, from 0 to 1.
+use crate::error::Result;
+use crate::models::CommandRecord;
+use crate::parse::tokenize;
+use crate::storage::Storage;
+use std::collections::HashSet;
+
+/// A stored command and how similar it is to the query
+#[derive(Debug, Clone)]
+pub struct SimilarCommand {
+    /// The stored command
+    pub record: CommandRecord,
+
+    /// Similarity to the query, from 0 (nothing shared) to 1 (identical)
+    pub score: f64,
+}
+
+/// Precomputed trigrams and words of a command
+pub struct Fingerprint {
+    trigrams: HashSet<[char; 3]>,
+    tokens: HashSet<String>,
+}
+
+impl Fingerprint {
+    /// Fingerprint a command, ignoring case and runs of whitespace
+    pub fn new(command: &str) -> Self {
+        let normalized = command
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+
+        // Padded so that short commands and word edges still give trigrams
+        let chars: Vec<char> = format!("  {} ", normalized).chars().collect();
+        let trigrams = chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect();
+        let tokens = tokenize(&normalized).into_iter().collect();
+
+        Self { trigrams, tokens }
+    }
+
+    /// Similarity of two commands, from 0 to 1
+    pub fn similarity(&self, other: &Fingerprint) -> f64 {
+        jaccard(&self.trigrams, &other.trigrams).max(jaccard(&self.tokens, &other.tokens))
+    }
+}
+
+/// Size of the intersection over size of the union
+fn jaccard<T: Eq + std::hash::Hash>(a: &HashSet<T>, b: &HashSet<T>) -> f64 {
+    let shared = a.intersection(b).count();
+    let union = a.len() + b.len() - shared;
+    if union == 0 {
+        return 0.0;
+    }
+    shared as f64 / union as f64
+}
+
+/// Similarity of two commands, from 0 to 1
+pub fn similarity(a: &str, b: &str) -> f64 {
+    Fingerprint::new(a).similarity(&Fingerprint::new(b))
+}
+
+/// The `limit` stored commands most similar to `command`, scoring at least
+/// `min_score`, best first
+///
+/// Every stored command is compared, streaming so large histories are not
+/// loaded into memory at once. Ties go to the more frequently used command.
+pub fn find_similar(
+    storage: &Storage,
+    command: &str,
+    min_score: f64,
+    limit: usize,
+) -> Result<Vec<SimilarCommand>> {
+    let query = Fingerprint::new(command);
+    let mut matches: Vec<SimilarCommand> = Vec::new();
+
+    storage.for_each_record(|record| {
+        let score = query.similarity(&Fingerprint::new(&record.command));
+        if score >= min_score {
+            matches.push(SimilarCommand { record, score });
+            // Keep memory bounded on histories where much of everything matches
+            if matches.len() >= limit.max(1) * 8 {
+                rank(&mut matches, limit);
+            }
+        }
+        Ok(())
+    })?;
+
+    rank(&mut matches, limit);
+    Ok(matches)
+}
+
+/// Sort best first and keep the top `limit`
+fn rank(matches: &mut Vec<SimilarCommand>, limit: usize) {
+    matches.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(b.record.usage_count.cmp(&a.record.usage_count))
+            .then(b.record.last_used.cmp(&a.record.last_used))
+    });
+    matches.truncate(limit);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn record(command: &str, usage_count: i32) -> CommandRecord {
+        let mut record = CommandRecord::new(
+            command.to_string(),
+            Utc::now(),
+            0,
+            10,
+            "/tmp".to_string(),
+            "other".to_string(),
+        );
+        record.usage_count = usage_count;
+        record
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("ls -la", "ls -la"), 1.0);
+        assert_eq!(similarity("LS   -la", "ls -la"), 1.0);
+        assert_eq!(
+            similarity("tar -xzf a.tgz -C /tmp", "tar -C /tmp -xzf a.tgz"),
+            1.0
+        );
+
+        let edited = similarity(
+            "ffmpeg -i in.mp4 -c:v libx264 -crf 23 out.mp4",
+            "ffmpeg -i in.mp4 -c:v libx264 -crf 28 out.mp4",
+        );
+        assert!(edited > 0.8 && edited < 1.0, "{}", edited);
+
+        assert!(similarity("git status", "cargo build") < 0.1);
+        assert_eq!(similarity("", "ls"), 0.0);
+    }
+
+    #[test]
+    fn test_find_similar() {
+        let storage = Storage::in_memory().unwrap();
+        storage
+            .insert_batch(&[
+                record("ffmpeg -i talk.mov -vf scale=1280:-2 -crf 28 talk.mp4", 2),
+                record("ffmpeg -i demo.mov -vf scale=1280:-2 -crf 23 demo.mp4", 5),
+                record("ffmpeg -version", 1),
+                record("git status", 40),
+            ])
+            .unwrap();
+
+        let matches = find_similar(
+            &storage,
+            "ffmpeg -i clip.mov -vf scale=1280:-2 -crf 26 clip.mp4",
+            0.3,
+            10,
+        )
+        .unwrap();
+        assert_eq!(matches.len(), 2);
+        assert!(matches[0].score >= matches[1].score);
+        assert!(matches
+            .iter()
+            .all(|m| m.record.command.contains("scale=1280")));
+
+        let matches = find_similar(&storage, "ffmpeg -i", 0.0, 1).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].record.command.starts_with("ffmpeg"));
+    }
+}
@@ -1,18 +1,163 @@
 /// Storage layer for command history using SQLite
-use crate::error::Result;
-use crate::models::{CategoryStats, CommandRecord, OrderBy, SearchQuery, Stats};
-use chrono::Utc;
+use crate::cache::QueryCache;
+use crate::config::{Config, STORAGE_MEMORY};
+use crate::error::{OmniscientError, Result, ResultExt};
+use crate::migrations;
+use crate::models::{
+    longest_streak, ActivityBucket, ActivityPeriod, ActivityReport, Alert, BranchStats,
+    CategoryBreakdown, CategoryStats, CommandCount, CommandDetail, CommandOutput, CommandRecord,
+    DirStats, DuplicateKey, DurationStats, Execution, ExecutionMeta, ExitCodeCount, FailedRun,
+    FailureStats, OrderBy, SearchQuery, SkipCount, SkipReason, SlowestBy, Stats, Summary,
+    ToolUsage, VocabularyBucket, Wrapped, STATS_DAYS, STATS_WEEKS,
+};
+use crate::parse::{flag_patterns, ParsedCommand};
+use crate::paths::normalize_separators;
+use crate::query::{check_select, QueryResult};
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::types::ValueRef;
 use rusqlite::{params, Connection, OptionalExtension};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
 use std::path::Path;
+use std::time::Duration;
+
+/// How long a connection waits for another connection's lock by default (ms)
+pub const DEFAULT_BUSY_TIMEOUT_MS: u64 = 5000;
+
+/// Longest pause between retries of a write that found the database locked
+const MAX_RETRY_DELAY_MS: u64 = 500;
+
+/// Prepared statements kept per connection (capture, search and batch
+/// paths reuse theirs instead of preparing them again)
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// Statement used to insert a command record
+const INSERT_SQL: &str = r#"
+    INSERT INTO commands (command, timestamp, exit_code, duration_ms,
+                         working_dir, category, usage_count, last_used,
+                         typed_command, note, program, subcommand, tokens)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+"#;
+
+/// Columns and join used to load executions
+const EXECUTION_SELECT: &str = "
+    SELECT e.command_id, c.command, c.working_dir, e.timestamp,
+           e.session, e.host, e.context, e.env, e.git_branch, e.git_repo,
+           COALESCE(e.exit_code, c.exit_code), COALESCE(e.duration_ms, c.duration_ms),
+           e.tmux_session, e.tmux_window, e.tmux_pane, e.capture_id
+    FROM executions e
+    JOIN commands c ON c.id = e.command_id";
+
+/// Runs of every command, one row per execution
+///
+/// Commands without logged executions count as a single run; executions
+/// logged before exit codes were recorded use their command's exit code.
+const RUNS_FROM: &str = "
+    FROM commands c
+    LEFT JOIN executions e ON e.command_id = c.id
+    WHERE 1=1";
+
+/// Exit code of a row from [`RUNS_FROM`]
+const RUN_EXIT_CODE: &str = "COALESCE(e.exit_code, c.exit_code)";
+
+/// Timestamp of a row from [`RUNS_FROM`]
+const RUN_TIMESTAMP: &str = "COALESCE(e.timestamp, c.last_used)";
+
+/// Frecency: uses weighted by hours since the last one
+const FRECENCY: &str = "CAST(usage_count AS REAL) / ((julianday('now') - julianday(last_used)) * 24.0 + 1.0) DESC, usage_count DESC";
+
+/// Key for a cached read
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    Search(Box<SearchQuery>),
+    TopForDir(String, usize),
+    Stats,
+}
+
+/// Result of a cached read
+#[derive(Debug, Clone)]
+enum CachedResult {
+    Records(Vec<CommandRecord>),
+    Stats(Stats),
+}
+
+/// Result of a database maintenance run
+#[derive(Debug, Clone)]
+pub struct MaintenanceReport {
+    /// Problems reported by `PRAGMA integrity_check` (empty when healthy)
+    pub integrity_errors: Vec<String>,
+
+    /// Database size before maintenance (bytes)
+    pub size_before: u64,
+
+    /// Database size after maintenance (bytes)
+    pub size_after: u64,
+}
+
+impl MaintenanceReport {
+    /// Whether the integrity check passed
+    pub fn is_healthy(&self) -> bool {
+        self.integrity_errors.is_empty()
+    }
+
+    /// Bytes reclaimed by vacuuming
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.size_before.saturating_sub(self.size_after)
+    }
+}
+
+/// Result of a read-only health check (see [`Storage::check_health`])
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    /// SQLite journal mode ("wal" normally)
+    pub journal_mode: String,
+
+    /// Problems reported by `PRAGMA quick_check` (empty when healthy)
+    pub integrity_errors: Vec<String>,
+
+    /// Why the full-text search index is out of sync with the commands, if it is
+    pub search_index_error: Option<String>,
+
+    /// When a command was last captured
+    pub last_capture: Option<DateTime<Utc>>,
+}
+
+/// Change to make to a stored record during [`Storage::rewrite_records`]
+/// (or to a captured output during [`Storage::rewrite_outputs`])
+#[derive(Debug, Clone)]
+pub enum RecordChange<T = CommandRecord> {
+    /// Overwrite the record with these values
+    Update(T),
+
+    /// Delete the record
+    Delete,
+}
 
 /// SQLite-based storage for command history
 pub struct Storage {
     conn: Connection,
+
+    /// Optional cache for repeated identical reads (disabled by default)
+    cache: RefCell<Option<QueryCache<CacheKey, CachedResult>>>,
+
+    /// Database state the cache contents were read at
+    cache_generation: Cell<Option<(i64, u64)>>,
+
+    /// Minutes east of UTC that day and hour buckets are shifted by
+    utc_offset: Cell<i32>,
 }
 
 impl Storage {
     /// Create a new storage instance, initializing the database if needed
     pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
+        Self::open(db_path, false)
+    }
+
+    /// Open the database, applying any pending schema migrations
+    ///
+    /// When `backup_before_migrate` is set and an existing database needs
+    /// upgrading, a copy is written next to it before any migration runs.
+    pub fn open<P: AsRef<Path>>(db_path: P, backup_before_migrate: bool) -> Result<Self> {
         let path = db_path.as_ref();
 
         // Create parent directory if it doesn't exist
@@ -20,745 +165,4989 @@ impl Storage {
             std::fs::create_dir_all(parent)?;
         }
 
-        let conn = Connection::open(path)?;
+        let conn =
+            Connection::open(path).with_context(|| format!("open database {}", path.display()))?;
+        conn.busy_timeout(Duration::from_millis(DEFAULT_BUSY_TIMEOUT_MS))?;
 
         // Enable WAL mode for better concurrency
-        conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA synchronous=NORMAL;")?;
+        conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA synchronous=NORMAL;")
+            .with_context(|| format!("configure database {}", path.display()))?;
 
-        let mut storage = Self { conn };
-        storage.initialize_schema()?;
+        if backup_before_migrate
+            && !migrations::pending(&conn)?.is_empty()
+            && migrations::has_existing_data(&conn)?
+        {
+            migrations::backup(&conn, path)
+                .with_context(|| format!("back up {} before migrating", path.display()))?;
+        }
+        migrations::migrate(&conn)
+            .with_context(|| format!("migrate database {}", path.display()))?;
 
-        Ok(storage)
+        Ok(Self::with_connection(conn))
     }
 
-    /// Initialize the database schema
-    fn initialize_schema(&mut self) -> Result<()> {
-        self.conn.execute_batch(
-            r#"
-            CREATE TABLE IF NOT EXISTS commands (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                command TEXT NOT NULL,
-                timestamp TEXT NOT NULL,
-                exit_code INTEGER NOT NULL,
-                duration_ms INTEGER NOT NULL,
-                working_dir TEXT NOT NULL,
-                category TEXT NOT NULL,
-                usage_count INTEGER NOT NULL DEFAULT 1,
-                last_used TEXT NOT NULL
-            );
+    /// Create an empty database that lives in memory only
+    ///
+    /// Nothing touches disk and everything is gone when the storage is
+    /// dropped, which suits tests and short-lived tools.
+    pub fn in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory().context("open in-memory database")?;
+        migrations::migrate(&conn).context("migrate in-memory database")?;
 
-            CREATE INDEX IF NOT EXISTS idx_timestamp ON commands(timestamp DESC);
-            CREATE INDEX IF NOT EXISTS idx_category ON commands(category);
-            CREATE INDEX IF NOT EXISTS idx_usage ON commands(usage_count DESC);
-            CREATE INDEX IF NOT EXISTS idx_command ON commands(command);
-            CREATE INDEX IF NOT EXISTS idx_exit_code ON commands(exit_code);
-            CREATE INDEX IF NOT EXISTS idx_working_dir ON commands(working_dir);
-
-            -- Full-text search virtual table
-            CREATE VIRTUAL TABLE IF NOT EXISTS commands_fts USING fts5(
-                command,
-                content='commands',
-                content_rowid='id'
-            );
+        Ok(Self::with_connection(conn))
+    }
 
-            -- Triggers to keep FTS table in sync
-            CREATE TRIGGER IF NOT EXISTS commands_ai AFTER INSERT ON commands BEGIN
-                INSERT INTO commands_fts(rowid, command) VALUES (new.id, new.command);
-            END;
-
-            CREATE TRIGGER IF NOT EXISTS commands_ad AFTER DELETE ON commands BEGIN
-                INSERT INTO commands_fts(commands_fts, rowid, command)
-                VALUES('delete', old.id, old.command);
-            END;
-
-            CREATE TRIGGER IF NOT EXISTS commands_au AFTER UPDATE ON commands BEGIN
-                INSERT INTO commands_fts(commands_fts, rowid, command)
-                VALUES('delete', old.id, old.command);
-                INSERT INTO commands_fts(rowid, command) VALUES (new.id, new.command);
-            END;
-            "#,
-        )?;
+    /// Open the backend selected by `storage.type` and apply the other
+    /// storage settings from `config`
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let storage = match config.storage.storage_type.as_str() {
+            STORAGE_MEMORY => Self::in_memory()?,
+            _ => Self::open(
+                config.database_path()?,
+                config.storage.backup_before_migrate,
+            )?,
+        };
+        storage.set_busy_timeout(config.storage.busy_timeout_ms.max(0) as u64)?;
+        storage.enable_query_cache(config.storage.query_cache_size);
+        storage.set_utc_offset(config.display.timezone.offset_minutes(&Utc::now()));
+
+        Ok(storage)
+    }
+
+    fn with_connection(conn: Connection) -> Self {
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        Self {
+            conn,
+            cache: RefCell::new(None),
+            cache_generation: Cell::new(None),
+            utc_offset: Cell::new(0),
+        }
+    }
 
+    /// Bucket activity by days and hours of the zone `minutes` east of UTC
+    /// (the display time zone) instead of UTC days
+    pub fn set_utc_offset(&self, minutes: i32) {
+        self.utc_offset.set(minutes);
+    }
+
+    /// Change how long to wait for other connections' locks before failing
+    /// with `SQLITE_BUSY`
+    pub fn set_busy_timeout(&self, timeout_ms: u64) -> Result<()> {
+        self.conn.busy_timeout(Duration::from_millis(timeout_ms))?;
         Ok(())
     }
 
+    /// Run `write` in a transaction, retrying up to `retries` times with
+    /// exponential backoff while another process holds the database lock
+    ///
+    /// The transaction takes the write lock up front (`BEGIN IMMEDIATE`), so
+    /// a locked database is noticed before any work is done and the busy
+    /// timeout applies instead of failing halfway through.
+    pub fn write_with_retry<T, F>(&self, retries: u32, mut write: F) -> Result<T>
+    where
+        F: FnMut() -> Result<T>,
+    {
+        let mut attempt = 0;
+        loop {
+            match self.with_tx(&mut write) {
+                Err(e) if e.is_busy() && attempt < retries => {
+                    std::thread::sleep(Self::retry_delay(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Run `write` in a transaction that is always rolled back, to find out
+    /// what it would do without changing the database
+    pub fn dry_run<T, F>(&self, write: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        self.conn.execute_batch("BEGIN IMMEDIATE")?;
+        let result = write();
+        if !self.conn.is_autocommit() {
+            self.conn.execute_batch("ROLLBACK")?;
+        }
+        result
+    }
+
+    /// Run `f` in a transaction, committing if it succeeds and rolling back
+    /// if it fails
+    ///
+    /// The write lock is taken up front (`BEGIN IMMEDIATE`). Within another
+    /// transaction `f` joins it instead, so storage methods built on this
+    /// compose into larger atomic operations (a capture, an import).
+    pub fn with_tx<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        if !self.conn.is_autocommit() {
+            return f();
+        }
+
+        self.conn.execute_batch("BEGIN IMMEDIATE")?;
+
+        let result = f().and_then(|value| {
+            self.conn.execute_batch("COMMIT")?;
+            Ok(value)
+        });
+        if result.is_err() && !self.conn.is_autocommit() {
+            let _ = self.conn.execute_batch("ROLLBACK");
+        }
+
+        result
+    }
+
+    /// Backoff before retry `attempt`: doubling from 10ms, with jitter so
+    /// competing shells don't retry in lockstep
+    fn retry_delay(attempt: u32) -> Duration {
+        let base = (10u64 << attempt.min(6)).min(MAX_RETRY_DELAY_MS);
+        let jitter = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| u64::from(d.subsec_nanos()) % (base / 2 + 1))
+            .unwrap_or(0);
+        Duration::from_millis(base + jitter)
+    }
+
+    /// Schema version of the open database
+    pub fn schema_version(&self) -> Result<i64> {
+        migrations::current_version(&self.conn)
+    }
+
+    /// Map a row selected with the standard column list to a command record
+    fn row_to_record(row: &rusqlite::Row) -> rusqlite::Result<CommandRecord> {
+        Ok(CommandRecord {
+            id: Some(row.get(0)?),
+            command: row.get(1)?,
+            timestamp: row.get::<_, String>(2)?.parse().unwrap(),
+            exit_code: row.get(3)?,
+            duration_ms: row.get(4)?,
+            working_dir: row.get(5)?,
+            category: row.get(6)?,
+            usage_count: row.get(7)?,
+            last_used: row.get::<_, String>(8)?.parse().unwrap(),
+            typed_command: row.get(9)?,
+            note: row.get(10)?,
+        })
+    }
+
     /// Insert a new command record
     pub fn insert(&self, cmd: &CommandRecord) -> Result<i64> {
-        let timestamp_str = cmd.timestamp.to_rfc3339();
-        let last_used_str = cmd.last_used.to_rfc3339();
-
-        self.conn.execute(
-            r#"
-            INSERT INTO commands (command, timestamp, exit_code, duration_ms,
-                                 working_dir, category, usage_count, last_used)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
-            "#,
-            params![
-                cmd.command,
-                timestamp_str,
-                cmd.exit_code,
-                cmd.duration_ms,
-                cmd.working_dir,
-                cmd.category,
-                cmd.usage_count,
-                last_used_str,
-            ],
-        )?;
+        let mut stmt = self.conn.prepare_cached(INSERT_SQL)?;
+        Self::execute_insert(&mut stmt, cmd).context("insert command")?;
 
         Ok(self.conn.last_insert_rowid())
     }
 
+    /// Insert many command records in a single transaction
+    ///
+    /// Much faster than repeated `insert` calls for large imports. Either all
+    /// records are inserted or none are. Returns the new IDs in input order.
+    /// Inside a transaction the caller already opened, the inserts become
+    /// part of it.
+    ///
+    /// The importer is the only bulk writer. There is no import from shell
+    /// history files: they carry no exit codes or working directories, which
+    /// every record needs, so history starts with the hooks.
+    pub fn insert_batch(&self, cmds: &[CommandRecord]) -> Result<Vec<i64>> {
+        self.with_tx(|| {
+            let mut ids = Vec::with_capacity(cmds.len());
+            let mut stmt = self.conn.prepare_cached(INSERT_SQL)?;
+            for (index, cmd) in cmds.iter().enumerate() {
+                Self::execute_insert(&mut stmt, cmd)
+                    .with_context(|| format!("insert command {} of batch", index + 1))?;
+                ids.push(self.conn.last_insert_rowid());
+            }
+            Ok(ids)
+        })
+    }
+
+    /// Bind a record to a prepared `INSERT_SQL` statement and run it
+    fn execute_insert(stmt: &mut rusqlite::CachedStatement, cmd: &CommandRecord) -> Result<()> {
+        let parsed = ParsedCommand::parse(&cmd.command);
+        stmt.execute(params![
+            cmd.command,
+            cmd.timestamp.to_rfc3339(),
+            cmd.exit_code,
+            cmd.duration_ms,
+            cmd.working_dir,
+            cmd.category,
+            cmd.usage_count,
+            cmd.last_used.to_rfc3339(),
+            cmd.typed_command,
+            cmd.note,
+            parsed.program,
+            parsed.subcommand,
+            parsed.stored_tokens(),
+        ])?;
+
+        Ok(())
+    }
+
     /// Find a duplicate command (same command text and working directory)
     pub fn find_duplicate(
         &self,
         command: &str,
         working_dir: &str,
     ) -> Result<Option<CommandRecord>> {
-        let mut stmt = self.conn.prepare(
+        self.find_duplicate_by(DuplicateKey::CommandAndDir, command, working_dir, 0)
+    }
+
+    /// Find an existing record that counts as a duplicate under the given key
+    pub fn find_duplicate_by(
+        &self,
+        key: DuplicateKey,
+        command: &str,
+        working_dir: &str,
+        exit_code: i32,
+    ) -> Result<Option<CommandRecord>> {
+        let mut sql = String::from(
             "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
-                    category, usage_count, last_used
+                    category, usage_count, last_used, typed_command, note
              FROM commands
-             WHERE command = ?1 AND working_dir = ?2
-             LIMIT 1",
-        )?;
+             WHERE command = ?",
+        );
+
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(command.to_string())];
+
+        match key {
+            DuplicateKey::Command => {}
+            DuplicateKey::CommandAndDir => {
+                sql.push_str(" AND working_dir = ?");
+                params.push(Box::new(working_dir.to_string()));
+            }
+            DuplicateKey::CommandDirAndExitCode => {
+                sql.push_str(" AND working_dir = ? AND exit_code = ?");
+                params.push(Box::new(working_dir.to_string()));
+                params.push(Box::new(exit_code));
+            }
+        }
+
+        sql.push_str(" ORDER BY id ASC LIMIT 1");
+
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
 
         let record = stmt
-            .query_row(params![command, working_dir], |row| {
-                Ok(CommandRecord {
-                    id: Some(row.get(0)?),
-                    command: row.get(1)?,
-                    timestamp: row.get::<_, String>(2)?.parse().unwrap(),
-                    exit_code: row.get(3)?,
-                    duration_ms: row.get(4)?,
-                    working_dir: row.get(5)?,
-                    category: row.get(6)?,
-                    usage_count: row.get(7)?,
-                    last_used: row.get::<_, String>(8)?.parse().unwrap(),
-                })
-            })
+            .query_row(param_refs.as_slice(), Self::row_to_record)
             .optional()?;
 
         Ok(record)
     }
 
-    /// Increment usage count for an existing command
-    pub fn increment_usage(&self, id: i64) -> Result<()> {
-        let now = Utc::now().to_rfc3339();
-
-        self.conn.execute(
-            "UPDATE commands SET usage_count = usage_count + 1, last_used = ?1 WHERE id = ?2",
-            params![now, id],
-        )?;
+    /// Increment usage count for an existing command run at `used_at`
+    ///
+    /// `last_used` only moves forward, so a run replayed out of order
+    /// doesn't make the command look older than it is.
+    pub fn increment_usage(&self, id: i64, used_at: DateTime<Utc>) -> Result<()> {
+        self.conn
+            .prepare_cached(
+                "UPDATE commands
+                 SET usage_count = usage_count + 1,
+                     last_used = CASE WHEN julianday(?1) > julianday(last_used)
+                                      THEN ?1 ELSE last_used END
+                 WHERE id = ?2",
+            )?
+            .execute(params![used_at.to_rfc3339(), id])?;
 
         Ok(())
     }
 
-    /// Sanitizes a query string for FTS5 search by wrapping it in quotes
-    /// This treats the query as a literal phrase, preventing FTS5 syntax errors
-    /// for special characters like dots, asterisks, etc.
-    ///
-    /// # Arguments
-    /// * `query` - The raw search query from the user
-    ///
-    /// # Returns
-    /// A sanitized query string safe for FTS5 MATCH clause
-    ///
-    /// # Examples
-    /// ```
-    /// use omniscient::Storage;
-    /// # use tempfile::NamedTempFile;
-    /// # let temp_file = NamedTempFile::new().unwrap();
-    /// # let storage = Storage::new(temp_file.path()).unwrap();
-    /// // The sanitize function is private, but here's how it works internally
-    /// let query = "10.104.113.39";
-    /// // Internally sanitized to: "\"10.104.113.39\""
-    /// ```
-    fn sanitize_fts5_query(query: &str) -> String {
-        // Escape existing double quotes by doubling them (FTS5 standard)
-        let escaped = query.replace("\"", "\"\"");
+    /// Set the usage count of a command, returning false if no command has the given ID
+    pub fn set_usage_count(&self, id: i64, count: i32) -> Result<bool> {
+        let updated = self
+            .conn
+            .execute(
+                "UPDATE commands SET usage_count = ?1 WHERE id = ?2",
+                params![count, id],
+            )
+            .with_context(|| format!("set usage count of command {}", id))?;
 
-        // Wrap entire query in quotes for literal phrase search
-        // This makes FTS5 treat all special characters as literals
-        format!("\"{}\"", escaped)
+        Ok(updated > 0)
     }
 
-    /// Fallback search using SQL LIKE when FTS5 fails
-    /// This is slower but handles any character combination
-    ///
-    /// # Arguments
-    /// * `text` - The search text
-    /// * `category` - Optional category filter
-    /// * `success_only` - Optional success filter
-    /// * `limit` - Maximum number of results
+    /// Overwrite every field of an existing record with the given values
     ///
-    /// # Returns
-    /// Vector of matching command records
-    fn search_with_like(&self, query: &SearchQuery, text: &str) -> Result<Vec<CommandRecord>> {
-        let mut sql = String::from(
-            "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
-                    category, usage_count, last_used
-             FROM commands
-             WHERE command LIKE ?",
-        );
+    /// The record's `id` selects the row. Returns false if no command has that ID.
+    pub fn update_record(&self, cmd: &CommandRecord) -> Result<bool> {
+        let id = cmd
+            .id
+            .ok_or_else(|| OmniscientError::other("cannot update a record without an ID"))?;
+        let parsed = ParsedCommand::parse(&cmd.command);
 
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(format!("%{}%", text))];
+        let updated = self
+            .conn
+            .prepare_cached(
+                r#"
+            UPDATE commands
+            SET command = ?1, timestamp = ?2, exit_code = ?3, duration_ms = ?4,
+                working_dir = ?5, category = ?6, usage_count = ?7, last_used = ?8,
+                typed_command = ?9, note = ?10, program = ?11, subcommand = ?12,
+                tokens = ?13
+            WHERE id = ?14
+            "#,
+            )?
+            .execute(params![
+                cmd.command,
+                cmd.timestamp.to_rfc3339(),
+                cmd.exit_code,
+                cmd.duration_ms,
+                cmd.working_dir,
+                cmd.category,
+                cmd.usage_count,
+                cmd.last_used.to_rfc3339(),
+                cmd.typed_command,
+                cmd.note,
+                parsed.program,
+                parsed.subcommand,
+                parsed.stored_tokens(),
+                id,
+            ])
+            .with_context(|| format!("update command {}", id))?;
 
-        // Add category filter
-        if let Some(ref cat) = &query.category {
-            sql.push_str(" AND category = ?");
-            params.push(Box::new(cat.clone()));
-        }
+        Ok(updated > 0)
+    }
 
-        // Add success filter
-        if let Some(success) = query.success_only {
-            if success {
-                sql.push_str(" AND exit_code = 0");
-            } else {
-                sql.push_str(" AND exit_code != 0");
-            }
-        }
+    /// Move records out of category `from` wherever `new_category` returns one
+    ///
+    /// Runs in a single transaction. Returns the number of records changed.
+    pub fn recategorize<F>(&self, from: &str, new_category: F) -> Result<usize>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        self.with_tx(|| {
+            let mut select = self
+                .conn
+                .prepare("SELECT id, command FROM commands WHERE category = ?1")?;
+            let rows = select
+                .query_map(params![from], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
 
-        // Add working directory filter
-        if let Some(ref dir) = &query.working_dir {
-            if query.recursive {
-                sql.push_str(" AND working_dir LIKE ?");
-                params.push(Box::new(format!("{}%", dir)));
-            } else {
-                sql.push_str(" AND working_dir = ?");
-                params.push(Box::new(dir.clone()));
+            let mut update = self
+                .conn
+                .prepare_cached("UPDATE commands SET category = ?1 WHERE id = ?2")?;
+            let mut changed = 0;
+            for (id, command) in rows {
+                if let Some(category) = new_category(&command).filter(|c| c != from) {
+                    update.execute(params![category, id])?;
+                    changed += 1;
+                }
             }
-        }
+            Ok(changed)
+        })
+    }
 
-        // Add ordering
-        match query.order_by {
-            OrderBy::Timestamp => sql.push_str(" ORDER BY timestamp DESC"),
-            OrderBy::UsageCount => sql.push_str(" ORDER BY usage_count DESC, timestamp DESC"),
-            OrderBy::Relevance => sql.push_str(
-                " ORDER BY CAST(usage_count AS REAL) / ((julianday('now') - julianday(last_used)) * 24.0 + 1.0) DESC, usage_count DESC"
-            ),
-        }
+    /// Count a command the capture hook decided not to store
+    pub fn record_skip(&self, reason: SkipReason) -> Result<()> {
+        self.conn
+            .prepare_cached(
+                "INSERT INTO capture_skips (reason, count, last_skipped) VALUES (?1, 1, ?2)
+                 ON CONFLICT(reason) DO UPDATE SET count = count + 1, last_skipped = excluded.last_skipped",
+            )?
+            .execute(params![reason.as_str(), Utc::now().to_rfc3339()])?;
 
-        sql.push_str(&format!(" LIMIT {}", query.limit));
+        Ok(())
+    }
 
-        let mut stmt = self.conn.prepare(&sql)?;
-        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    /// Get skip counters, most frequent reason first
+    pub fn get_skip_counts(&self) -> Result<Vec<SkipCount>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT reason, count, last_skipped FROM capture_skips ORDER BY count DESC, reason ASC",
+        )?;
 
-        let records = stmt
-            .query_map(param_refs.as_slice(), |row| {
-                Ok(CommandRecord {
-                    id: Some(row.get(0)?),
-                    command: row.get(1)?,
-                    timestamp: row.get::<_, String>(2)?.parse().unwrap(),
-                    exit_code: row.get(3)?,
-                    duration_ms: row.get(4)?,
-                    working_dir: row.get(5)?,
-                    category: row.get(6)?,
-                    usage_count: row.get(7)?,
-                    last_used: row.get::<_, String>(8)?.parse().unwrap(),
-                })
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
             })?
             .collect::<std::result::Result<Vec<_>, _>>()?;
 
-        Ok(records)
+        // Reasons written by a newer version are ignored
+        Ok(rows
+            .into_iter()
+            .filter_map(|(reason, count, last_skipped)| {
+                Some(SkipCount {
+                    reason: SkipReason::from_name(&reason)?,
+                    count,
+                    last_skipped: last_skipped.parse().ok()?,
+                })
+            })
+            .collect())
     }
 
-    /// Search commands with various filters
-    pub fn search(&self, query: &SearchQuery) -> Result<Vec<CommandRecord>> {
-        let mut sql = String::from(
-            "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
-                    category, usage_count, last_used
-             FROM commands
-             WHERE 1=1",
-        );
+    /// Record a single execution of an existing command, returning its ID
+    pub fn log_execution(
+        &self,
+        command_id: i64,
+        timestamp: DateTime<Utc>,
+        exit_code: i32,
+        duration_ms: i64,
+        meta: &ExecutionMeta,
+    ) -> Result<i64> {
+        let env = if meta.env.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&meta.env)?)
+        };
 
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+        self.conn
+            .prepare_cached(
+                "INSERT INTO executions (command_id, timestamp, exit_code, duration_ms,
+                                         session, host, context, env, git_branch, git_repo,
+                                         tmux_session, tmux_window, tmux_pane, capture_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            )?
+            .execute(params![
+                command_id,
+                timestamp.to_rfc3339(),
+                exit_code,
+                duration_ms,
+                meta.session,
+                meta.host,
+                meta.context,
+                env,
+                meta.branch,
+                meta.repo,
+                meta.tmux_session,
+                meta.tmux_window,
+                meta.tmux_pane,
+                meta.capture_id,
+            ])
+            .with_context(|| format!("log execution of command {}", command_id))?;
 
-        // Add category filter
-        if let Some(ref category) = query.category {
-            sql.push_str(" AND category = ?");
-            params.push(Box::new(category.clone()));
-        }
+        Ok(self.conn.last_insert_rowid())
+    }
 
-        // Add success filter
-        if let Some(success_only) = query.success_only {
-            if success_only {
-                sql.push_str(" AND exit_code = 0");
-            } else {
-                sql.push_str(" AND exit_code != 0");
-            }
-        }
+    /// The command an execution with this capture ID was logged for, if any
+    pub fn find_capture(&self, capture_id: &str) -> Result<Option<i64>> {
+        let command_id = self
+            .conn
+            .prepare_cached("SELECT command_id FROM executions WHERE capture_id = ?1")?
+            .query_row(params![capture_id], |row| row.get(0))
+            .optional()?;
 
-        // Add working directory filter
-        if let Some(ref dir) = query.working_dir {
-            if query.recursive {
-                sql.push_str(" AND working_dir LIKE ?");
-                params.push(Box::new(format!("{}%", dir)));
-            } else {
-                sql.push_str(" AND working_dir = ?");
-                params.push(Box::new(dir.clone()));
-            }
-        }
+        Ok(command_id)
+    }
 
-        // Add text search if provided
-        if let Some(ref text) = query.text {
-            // Sanitize query for FTS5 to handle special characters
-            let sanitized = Self::sanitize_fts5_query(text);
-            sql.push_str(" AND id IN (SELECT rowid FROM commands_fts WHERE command MATCH ?)");
-            params.push(Box::new(sanitized));
-        }
+    /// Record that a command matched a watchlist rule
+    pub fn log_alert(&self, alert: &Alert) -> Result<i64> {
+        self.conn
+            .prepare_cached(
+                "INSERT INTO alerts (command_id, rule, command, working_dir, timestamp)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?
+            .execute(params![
+                alert.command_id,
+                alert.rule,
+                alert.command,
+                alert.working_dir,
+                alert.timestamp.to_rfc3339(),
+            ])
+            .context("log alert")?;
 
-        // Add ordering
-        match query.order_by {
-            OrderBy::Timestamp => sql.push_str(" ORDER BY timestamp DESC"),
-            OrderBy::UsageCount => sql.push_str(" ORDER BY usage_count DESC, timestamp DESC"),
-            OrderBy::Relevance => sql.push_str(
-                " ORDER BY CAST(usage_count AS REAL) / ((julianday('now') - julianday(last_used)) * 24.0 + 1.0) DESC, usage_count DESC"
-            ),
-        }
+        Ok(self.conn.last_insert_rowid())
+    }
 
-        sql.push_str(&format!(" LIMIT {}", query.limit));
+    /// Get the most recent alerts, newest first
+    pub fn get_alerts(&self, limit: usize) -> Result<Vec<Alert>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, command_id, rule, command, working_dir, timestamp
+             FROM alerts
+             ORDER BY timestamp DESC, id DESC
+             LIMIT ?1",
+        )?;
 
-        // Try FTS5 search first, fall back to LIKE if it fails
-        let stmt_result = self.conn.prepare(&sql);
+        let alerts = stmt
+            .query_map(params![limit as i64], |row| {
+                Ok(Alert {
+                    id: Some(row.get(0)?),
+                    command_id: row.get(1)?,
+                    rule: row.get(2)?,
+                    command: row.get(3)?,
+                    working_dir: row.get(4)?,
+                    timestamp: row.get::<_, String>(5)?.parse().unwrap(),
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
 
-        let records = match stmt_result {
-            Ok(mut stmt) => {
-                let param_refs: Vec<&dyn rusqlite::ToSql> =
-                    params.iter().map(|p| p.as_ref()).collect();
+        Ok(alerts)
+    }
 
-                let rows_result = stmt.query_map(param_refs.as_slice(), |row| {
-                    Ok(CommandRecord {
-                        id: Some(row.get(0)?),
-                        command: row.get(1)?,
-                        timestamp: row.get::<_, String>(2)?.parse().unwrap(),
-                        exit_code: row.get(3)?,
-                        duration_ms: row.get(4)?,
-                        working_dir: row.get(5)?,
-                        category: row.get(6)?,
-                        usage_count: row.get(7)?,
-                        last_used: row.get::<_, String>(8)?.parse().unwrap(),
-                    })
-                });
+    /// Delete all alerts, returning how many there were
+    pub fn clear_alerts(&self) -> Result<usize> {
+        Ok(self.conn.execute("DELETE FROM alerts", [])?)
+    }
 
-                match rows_result {
-                    Ok(rows) => rows.collect::<std::result::Result<Vec<_>, _>>()?,
-                    Err(_) if query.text.is_some() => {
-                        // FTS5 query failed, fall back to LIKE search
-                        self.search_with_like(query, query.text.as_ref().unwrap())?
-                    }
-                    Err(e) => return Err(e.into()),
-                }
-            }
-            Err(_) if query.text.is_some() => {
-                // FTS5 prepare failed, fall back to LIKE search
-                self.search_with_like(query, query.text.as_ref().unwrap())?
-            }
-            Err(e) => return Err(e.into()),
-        };
+    /// Store the output printed by an execution
+    pub fn save_output(&self, execution_id: i64, text: &str, truncated: bool) -> Result<()> {
+        self.conn
+            .prepare_cached(
+                "INSERT OR REPLACE INTO outputs (execution_id, output, truncated)
+                 VALUES (?1, ?2, ?3)",
+            )?
+            .execute(params![execution_id, text, truncated])
+            .with_context(|| format!("save output of execution {}", execution_id))?;
 
-        Ok(records)
+        Ok(())
     }
 
-    /// Get the most recent N commands
-    pub fn get_recent(
+    /// Output of the most recent execution of a command that has one
+    pub fn get_latest_output(&self, command_id: i64) -> Result<Option<CommandOutput>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT o.execution_id, e.timestamp, o.output, o.truncated
+             FROM outputs o
+             JOIN executions e ON e.id = o.execution_id
+             WHERE e.command_id = ?1
+             ORDER BY e.id DESC
+             LIMIT 1",
+                params![command_id],
+                |row| {
+                    Ok(CommandOutput {
+                        execution_id: row.get(0)?,
+                        timestamp: row.get::<_, String>(1)?.parse().unwrap(),
+                        text: row.get(2)?,
+                        truncated: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    /// Get logged executions in chronological order, optionally filtered by directory
+    pub fn get_executions(
         &self,
-        limit: usize,
         working_dir: Option<String>,
         recursive: bool,
-    ) -> Result<Vec<CommandRecord>> {
-        let query = SearchQuery {
-            text: None,
-            category: None,
-            success_only: None,
-            working_dir,
-            recursive,
-            limit,
-            order_by: OrderBy::Timestamp,
-        };
+    ) -> Result<Vec<Execution>> {
+        let mut sql = String::from(EXECUTION_SELECT);
 
-        self.search(&query)
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+
+        if let Some(ref dir) = working_dir {
+            sql.push_str(" WHERE 1=1");
+            Self::push_dir_filter(&mut sql, &mut params, "c.working_dir", dir, recursive);
+        }
+
+        sql.push_str(" ORDER BY e.timestamp ASC, e.id ASC");
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+        let executions = stmt
+            .query_map(param_refs.as_slice(), Self::row_to_execution)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(executions)
     }
 
-    /// Get the most frequently used commands
-    pub fn get_top(
-        &self,
-        limit: usize,
-        working_dir: Option<String>,
-        recursive: bool,
-    ) -> Result<Vec<CommandRecord>> {
-        let query = SearchQuery {
-            text: None,
-            category: None,
-            success_only: None,
-            working_dir,
-            recursive,
-            limit,
-            order_by: OrderBy::UsageCount,
-        };
+    /// Get the most recent execution of a command, if any was logged
+    pub fn get_latest_execution(&self, command_id: i64) -> Result<Option<Execution>> {
+        let sql = format!(
+            "{} WHERE e.command_id = ?1 ORDER BY e.timestamp DESC, e.id DESC LIMIT 1",
+            EXECUTION_SELECT
+        );
 
-        self.search(&query)
+        let execution = self
+            .conn
+            .query_row(&sql, params![command_id], Self::row_to_execution)
+            .optional()
+            .with_context(|| format!("load latest execution of command {}", command_id))?;
+
+        Ok(execution)
     }
 
-    /// Get all commands in a specific category
-    pub fn get_by_category(
-        &self,
-        category: &str,
-        limit: usize,
-        working_dir: Option<String>,
-        recursive: bool,
-    ) -> Result<Vec<CommandRecord>> {
-        let query = SearchQuery {
-            text: None,
-            category: Some(category.to_string()),
-            success_only: None,
-            working_dir,
-            recursive,
-            limit,
-            order_by: OrderBy::UsageCount,
+    /// Get the most recent executions of a command, newest first
+    pub fn get_command_executions(&self, command_id: i64, limit: usize) -> Result<Vec<Execution>> {
+        let sql = format!(
+            "{} WHERE e.command_id = ?1 ORDER BY e.timestamp DESC, e.id DESC LIMIT ?2",
+            EXECUTION_SELECT
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let executions = stmt
+            .query_map(params![command_id, limit as i64], Self::row_to_execution)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(executions)
+    }
+
+    /// Get the most recent executions of a command, oldest first
+    ///
+    /// `command` is matched exactly, or as a glob pattern (SQLite GLOB
+    /// syntax) when it contains `*`, `?` or `[`, which takes in the runs of
+    /// every matching command.
+    pub fn get_command_history(&self, command: &str, limit: usize) -> Result<Vec<Execution>> {
+        let operator = if command.contains(['*', '?', '[']) {
+            "GLOB"
+        } else {
+            "="
         };
+        let sql = format!(
+            "{} WHERE c.command {} ?1 ORDER BY e.timestamp DESC, e.id DESC LIMIT ?2",
+            EXECUTION_SELECT, operator
+        );
 
-        self.search(&query)
+        let mut stmt = self.conn.prepare(&sql)?;
+        let mut executions = stmt
+            .query_map(params![command, limit as i64], Self::row_to_execution)?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .with_context(|| format!("load history of '{}'", command))?;
+        executions.reverse();
+
+        Ok(executions)
     }
 
-    /// Get statistics about the command history
-    pub fn get_stats(&self) -> Result<Stats> {
-        // Total commands
-        let total_commands: usize =
-            self.conn
-                .query_row("SELECT COUNT(*) FROM commands", [], |row| row.get(0))?;
+    /// Get a command with its bookmark, recent executions and latest output
+    pub fn get_detail(&self, id: i64, execution_limit: usize) -> Result<Option<CommandDetail>> {
+        let Some(record) = self.get_by_id(id)? else {
+            return Ok(None);
+        };
 
-        // Successful commands
-        let successful_commands: usize = self.conn.query_row(
-            "SELECT COUNT(*) FROM commands WHERE exit_code = 0",
-            [],
+        let execution_count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM executions WHERE command_id = ?1",
+            params![id],
             |row| row.get(0),
         )?;
 
-        // Failed commands
-        let failed_commands = total_commands - successful_commands;
-
-        // Commands by category
-        let mut stmt = self.conn.prepare(
-            "SELECT category, COUNT(*) as count FROM commands
-             GROUP BY category ORDER BY count DESC",
-        )?;
+        Ok(Some(CommandDetail {
+            record,
+            bookmarked: self.is_bookmarked(id)?,
+            execution_count: execution_count as usize,
+            executions: self.get_command_executions(id, execution_limit)?,
+            output: self.get_latest_output(id)?,
+        }))
+    }
 
-        let by_category = stmt
-            .query_map([], |row| {
-                Ok(CategoryStats {
-                    category: row.get(0)?,
-                    count: row.get(1)?,
-                })
-            })?
-            .collect::<std::result::Result<Vec<_>, _>>()?;
+    /// Map a row selected with `EXECUTION_SELECT` to an execution
+    fn row_to_execution(row: &rusqlite::Row) -> rusqlite::Result<Execution> {
+        Ok(Execution {
+            command_id: row.get(0)?,
+            command: row.get(1)?,
+            working_dir: row.get(2)?,
+            timestamp: row.get::<_, String>(3)?.parse().unwrap(),
+            exit_code: row.get(10)?,
+            duration_ms: row.get(11)?,
+            meta: ExecutionMeta {
+                session: row.get(4)?,
+                host: row.get(5)?,
+                context: row.get(6)?,
+                env: Self::parse_env(row.get(7)?),
+                branch: row.get(8)?,
+                repo: row.get(9)?,
+                tmux_session: row.get(12)?,
+                tmux_window: row.get(13)?,
+                tmux_pane: row.get(14)?,
+                capture_id: row.get(15)?,
+            },
+        })
+    }
 
-        // Oldest command timestamp
-        let oldest_command: Option<String> = self
+    /// Get the environment captured with the most recent execution of a command
+    pub fn get_latest_env(&self, command_id: i64) -> Result<BTreeMap<String, String>> {
+        let env: Option<String> = self
             .conn
             .query_row(
-                "SELECT timestamp FROM commands ORDER BY timestamp ASC LIMIT 1",
-                [],
+                "SELECT env FROM executions WHERE command_id = ?1
+                 ORDER BY timestamp DESC, id DESC LIMIT 1",
+                params![command_id],
                 |row| row.get(0),
             )
-            .optional()?;
+            .optional()?
+            .flatten();
 
-        // Newest command timestamp
-        let newest_command: Option<String> = self
-            .conn
-            .query_row(
-                "SELECT timestamp FROM commands ORDER BY timestamp DESC LIMIT 1",
-                [],
-                |row| row.get(0),
-            )
-            .optional()?;
+        Ok(Self::parse_env(env))
+    }
 
-        Ok(Stats {
-            total_commands,
-            successful_commands,
-            failed_commands,
-            by_category,
-            oldest_command: oldest_command.and_then(|s| s.parse().ok()),
-            newest_command: newest_command.and_then(|s| s.parse().ok()),
-        })
+    /// Decode the JSON `env` column of an execution
+    fn parse_env(json: Option<String>) -> BTreeMap<String, String> {
+        json.and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
     }
 
-    /// Get all commands (for export)
-    pub fn get_all(&self) -> Result<Vec<CommandRecord>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
-                    category, usage_count, last_used
-             FROM commands
-             ORDER BY timestamp ASC",
-        )?;
+    /// Append an `AND` condition per variable, matching commands that ran at
+    /// least once with that environment variable set to that value
+    fn push_env_filter(
+        sql: &mut String,
+        params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+        env: &[(String, String)],
+    ) {
+        for (name, value) in env {
+            sql.push_str(
+                " AND id IN (SELECT command_id FROM executions
+                             WHERE json_extract(env, '$.\"' || ? || '\"') = ?)",
+            );
+            params.push(Box::new(name.clone()));
+            params.push(Box::new(value.clone()));
+        }
+    }
 
-        let records = stmt
-            .query_map([], |row| {
-                Ok(CommandRecord {
-                    id: Some(row.get(0)?),
-                    command: row.get(1)?,
-                    timestamp: row.get::<_, String>(2)?.parse().unwrap(),
-                    exit_code: row.get(3)?,
-                    duration_ms: row.get(4)?,
-                    working_dir: row.get(5)?,
-                    category: row.get(6)?,
-                    usage_count: row.get(7)?,
-                    last_used: row.get::<_, String>(8)?.parse().unwrap(),
-                })
-            })?
-            .collect::<std::result::Result<Vec<_>, _>>()?;
+    /// Append an `ORDER BY` clause, with bookmarked commands pinned first
+    fn push_order(sql: &mut String, order_by: OrderBy) {
+        let order = match order_by {
+            OrderBy::Timestamp => "timestamp DESC",
+            OrderBy::LastUsed => "last_used DESC",
+            OrderBy::UsageCount => "usage_count DESC, timestamp DESC",
+            OrderBy::Relevance => FRECENCY,
+        };
+        sql.push_str(" ORDER BY id IN (SELECT command_id FROM bookmarks) DESC, ");
+        sql.push_str(order);
+    }
 
-        Ok(records)
+    /// Append an `AND` condition matching commands run at least once in the
+    /// query's tmux session and/or pane
+    fn push_tmux_filter(
+        sql: &mut String,
+        params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+        query: &SearchQuery,
+    ) {
+        if query.tmux_session.is_none() && query.tmux_pane.is_none() {
+            return;
+        }
+        sql.push_str(" AND id IN (SELECT command_id FROM executions WHERE 1=1");
+        for (column, value) in [
+            ("tmux_session", &query.tmux_session),
+            ("tmux_pane", &query.tmux_pane),
+        ] {
+            if let Some(value) = value {
+                sql.push_str(&format!(" AND {} = ?", column));
+                params.push(Box::new(value.clone()));
+            }
+        }
+        sql.push(')');
     }
 
-    /// Get total number of commands
-    pub fn count(&self) -> Result<usize> {
-        let count: usize = self
-            .conn
-            .query_row("SELECT COUNT(*) FROM commands", [], |row| row.get(0))?;
-        Ok(count)
+    /// Append `AND` conditions leaving out excluded terms and categories
+    fn push_exclude_filter(
+        sql: &mut String,
+        params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+        query: &SearchQuery,
+    ) {
+        for term in &query.exclude {
+            sql.push_str(" AND instr(lower(command), lower(?)) = 0");
+            params.push(Box::new(term.clone()));
+        }
+
+        if !query.exclude_categories.is_empty() {
+            let placeholders = vec!["?"; query.exclude_categories.len()].join(", ");
+            sql.push_str(&format!(" AND category NOT IN ({})", placeholders));
+            for category in &query.exclude_categories {
+                params.push(Box::new(category.clone()));
+            }
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use chrono::Utc;
-    use tempfile::NamedTempFile;
+    /// Append `AND` conditions on the parsed program and flags
+    fn push_parsed_filter(
+        sql: &mut String,
+        params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+        query: &SearchQuery,
+    ) {
+        if let Some(ref program) = query.program {
+            sql.push_str(" AND program = ?");
+            params.push(Box::new(program.clone()));
+        }
 
-    fn create_test_storage() -> Storage {
-        let temp_file = NamedTempFile::new().unwrap();
-        Storage::new(temp_file.path()).unwrap()
+        for flag in &query.flags {
+            let patterns = flag_patterns(flag);
+            let conditions = vec!["instr(tokens, ?) > 0"; patterns.len()].join(" OR ");
+            sql.push_str(&format!(" AND ({})", conditions));
+            for pattern in patterns {
+                params.push(Box::new(pattern));
+            }
+        }
+    }
+
+    /// Append `AND` conditions on the duration of commands
+    fn push_duration_filter(
+        sql: &mut String,
+        params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+        query: &SearchQuery,
+    ) {
+        if let Some(min) = query.min_duration_ms {
+            sql.push_str(" AND duration_ms >= ?");
+            params.push(Box::new(min));
+        }
+
+        if let Some(max) = query.max_duration_ms {
+            sql.push_str(" AND duration_ms <= ?");
+            params.push(Box::new(max));
+        }
+    }
+
+    /// Append an `AND` condition restricting `column` to a directory
+    ///
+    /// Directories containing `*`, `?` or `[` are treated as glob patterns
+    /// (SQLite GLOB syntax, where `*` also matches `/`). With `recursive`,
+    /// subdirectories of every match are included too.
+    fn push_dir_filter(
+        sql: &mut String,
+        params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+        column: &str,
+        dir: &str,
+        recursive: bool,
+    ) {
+        let dir = &normalize_separators(dir);
+        let is_glob = dir.contains(['*', '?', '[']);
+
+        match (is_glob, recursive) {
+            (true, true) => {
+                let pattern = dir.trim_end_matches('/');
+                sql.push_str(&format!(" AND ({} GLOB ? OR {} GLOB ?)", column, column));
+                params.push(Box::new(pattern.to_string()));
+                params.push(Box::new(format!("{}/*", pattern)));
+            }
+            (true, false) => {
+                sql.push_str(&format!(" AND {} GLOB ?", column));
+                params.push(Box::new(dir.to_string()));
+            }
+            (false, true) => {
+                sql.push_str(&format!(" AND {} LIKE ?", column));
+                params.push(Box::new(format!("{}%", dir)));
+            }
+            (false, false) => {
+                sql.push_str(&format!(" AND {} = ?", column));
+                params.push(Box::new(dir.to_string()));
+            }
+        }
     }
 
-    fn create_test_command(command: &str, category: &str, exit_code: i32) -> CommandRecord {
-        CommandRecord::new(
-            command.to_string(),
-            Utc::now(),
-            exit_code,
-            100,
-            "/tmp".to_string(),
-            category.to_string(),
-        )
+    /// Sanitizes a query string for FTS5 search by wrapping it in quotes
+    /// This treats the query as a literal phrase, preventing FTS5 syntax errors
+    /// for special characters like dots, asterisks, etc.
+    ///
+    /// # Arguments
+    /// * `query` - The raw search query from the user
+    ///
+    /// # Returns
+    /// A sanitized query string safe for FTS5 MATCH clause
+    ///
+    /// # Examples
+    /// ```
+    /// use omniscient::Storage;
+    /// # use tempfile::NamedTempFile;
+    /// # let temp_file = NamedTempFile::new().unwrap();
+    /// # let storage = Storage::new(temp_file.path()).unwrap();
+    /// // The sanitize function is private, but here's how it works internally
+    /// let query = "10.104.113.39";
+    /// // Internally sanitized to: "\"10.104.113.39\""
+    /// ```
+    fn sanitize_fts5_query(query: &str) -> String {
+        // Escape existing double quotes by doubling them (FTS5 standard)
+        let escaped = query.replace("\"", "\"\"");
+
+        // Wrap entire query in quotes for literal phrase search
+        // This makes FTS5 treat all special characters as literals
+        format!("\"{}\"", escaped)
+    }
+
+    /// Build an FTS5 query matching every word of `query` as a token prefix
+    ///
+    /// Each word is quoted like in [`Storage::sanitize_fts5_query`] and gets
+    /// a trailing `*`, so `git chec` matches `git checkout main`.
+    fn fts5_prefix_query(query: &str) -> String {
+        let terms: Vec<String> = query
+            .split_whitespace()
+            .map(|word| format!("{}*", Self::sanitize_fts5_query(word)))
+            .collect();
+
+        if terms.is_empty() {
+            Self::sanitize_fts5_query(query)
+        } else {
+            terms.join(" ")
+        }
+    }
+
+    /// Substring search using SQL LIKE, used for `ignore_case` searches and
+    /// when FTS5 fails
+    /// This is slower but handles any character combination; LIKE ignores
+    /// the case of ASCII letters
+    ///
+    /// # Arguments
+    /// * `text` - The search text
+    /// * `category` - Optional category filter
+    /// * `success_only` - Optional success filter
+    /// * `limit` - Maximum number of results
+    ///
+    /// # Returns
+    /// Vector of matching command records
+    fn search_with_like(&self, query: &SearchQuery, text: &str) -> Result<Vec<CommandRecord>> {
+        let mut sql = String::from(
+            "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
+                    category, usage_count, last_used, typed_command, note
+             FROM commands
+             WHERE command LIKE ?",
+        );
+
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(format!("%{}%", text))];
+
+        // Add category filter
+        if let Some(ref cat) = &query.category {
+            sql.push_str(" AND category = ?");
+            params.push(Box::new(cat.clone()));
+        }
+
+        // Add success filter
+        if let Some(success) = query.success_only {
+            if success {
+                sql.push_str(" AND exit_code = 0");
+            } else {
+                sql.push_str(" AND exit_code != 0");
+            }
+        }
+
+        // Add exit code filter (the latest run or any recorded execution)
+        if let Some(exit_code) = query.exit_code {
+            sql.push_str(
+                " AND (exit_code = ? OR id IN (SELECT command_id FROM executions WHERE exit_code = ?))",
+            );
+            params.push(Box::new(exit_code));
+            params.push(Box::new(exit_code));
+        }
+
+        // Add working directory filter
+        if let Some(ref dir) = query.working_dir {
+            Self::push_dir_filter(&mut sql, &mut params, "working_dir", dir, query.recursive);
+        }
+
+        // Add environment filter
+        Self::push_env_filter(&mut sql, &mut params, &query.env);
+
+        // Add git branch filter
+        if let Some(ref branch) = query.branch {
+            sql.push_str(" AND id IN (SELECT command_id FROM executions WHERE git_branch = ?)");
+            params.push(Box::new(branch.clone()));
+        }
+
+        // Add host filter
+        if let Some(ref host) = query.host {
+            sql.push_str(" AND id IN (SELECT command_id FROM executions WHERE host = ?)");
+            params.push(Box::new(host.clone()));
+        }
+
+        // Add tmux filters
+        Self::push_tmux_filter(&mut sql, &mut params, query);
+
+        // Add exclusions
+        Self::push_exclude_filter(&mut sql, &mut params, query);
+
+        // Add duration filter
+        Self::push_duration_filter(&mut sql, &mut params, query);
+
+        // Add program and flag filters
+        Self::push_parsed_filter(&mut sql, &mut params, query);
+
+        // Add ordering
+        Self::push_order(&mut sql, query.order_by);
+
+        sql.push_str(&format!(" LIMIT {} OFFSET {}", query.limit, query.offset));
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+        let records = stmt
+            .query_map(param_refs.as_slice(), Self::row_to_record)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(records)
+    }
+
+    /// Enable caching of search and stats results, keeping at most `capacity` entries
+    ///
+    /// Useful for long-running callers (prompts, widgets, watch mode) that repeat
+    /// identical queries. Any write to the database, from this or another
+    /// connection, invalidates the cache. A capacity of 0 disables caching.
+    pub fn enable_query_cache(&self, capacity: usize) {
+        let cache = (capacity > 0).then(|| QueryCache::new(capacity));
+        *self.cache.borrow_mut() = cache;
+        self.cache_generation.set(None);
+    }
+
+    /// Identify the current database state; changes whenever any connection writes
+    fn data_generation(&self) -> Result<(i64, u64)> {
+        let data_version: i64 = self
+            .conn
+            .query_row("PRAGMA data_version", [], |row| row.get(0))?;
+        Ok((data_version, self.conn.total_changes()))
+    }
+
+    /// Serve a read from the cache when enabled and still valid
+    fn cached<F>(&self, key: CacheKey, compute: F) -> Result<CachedResult>
+    where
+        F: FnOnce() -> Result<CachedResult>,
+    {
+        if self.cache.borrow().is_none() {
+            return compute();
+        }
+
+        let generation = self.data_generation()?;
+        {
+            let mut cache = self.cache.borrow_mut();
+            let cache = cache.as_mut().unwrap();
+
+            if self.cache_generation.get() != Some(generation) {
+                cache.clear();
+                self.cache_generation.set(Some(generation));
+            } else if let Some(hit) = cache.get(&key) {
+                return Ok(hit);
+            }
+        }
+
+        let result = compute()?;
+        if let Some(cache) = self.cache.borrow_mut().as_mut() {
+            cache.put(key, result.clone());
+        }
+
+        Ok(result)
+    }
+
+    /// Search commands with various filters
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<CommandRecord>> {
+        let key = CacheKey::Search(Box::new(query.clone()));
+        let result = self
+            .cached(key, || self.run_search(query).map(CachedResult::Records))
+            .with_context(|| match query.text {
+                Some(ref text) => format!("search for '{}'", text),
+                None => "list commands".to_string(),
+            })?;
+
+        match result {
+            CachedResult::Records(records) => Ok(records),
+            CachedResult::Stats(_) => unreachable!("search key always caches records"),
+        }
+    }
+
+    /// Run a search against the database, bypassing the cache
+    fn run_search(&self, query: &SearchQuery) -> Result<Vec<CommandRecord>> {
+        if let (Some(text), true) = (&query.text, query.ignore_case) {
+            return self.search_with_like(query, text);
+        }
+
+        let mut sql = String::from(
+            "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
+                    category, usage_count, last_used, typed_command, note
+             FROM commands
+             WHERE 1=1",
+        );
+
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+
+        // Add category filter
+        if let Some(ref category) = query.category {
+            sql.push_str(" AND category = ?");
+            params.push(Box::new(category.clone()));
+        }
+
+        // Add success filter
+        if let Some(success_only) = query.success_only {
+            if success_only {
+                sql.push_str(" AND exit_code = 0");
+            } else {
+                sql.push_str(" AND exit_code != 0");
+            }
+        }
+
+        // Add exit code filter (the latest run or any recorded execution)
+        if let Some(exit_code) = query.exit_code {
+            sql.push_str(
+                " AND (exit_code = ? OR id IN (SELECT command_id FROM executions WHERE exit_code = ?))",
+            );
+            params.push(Box::new(exit_code));
+            params.push(Box::new(exit_code));
+        }
+
+        // Add working directory filter
+        if let Some(ref dir) = query.working_dir {
+            Self::push_dir_filter(&mut sql, &mut params, "working_dir", dir, query.recursive);
+        }
+
+        // Add environment filter
+        Self::push_env_filter(&mut sql, &mut params, &query.env);
+
+        // Add git branch filter
+        if let Some(ref branch) = query.branch {
+            sql.push_str(" AND id IN (SELECT command_id FROM executions WHERE git_branch = ?)");
+            params.push(Box::new(branch.clone()));
+        }
+
+        // Add host filter
+        if let Some(ref host) = query.host {
+            sql.push_str(" AND id IN (SELECT command_id FROM executions WHERE host = ?)");
+            params.push(Box::new(host.clone()));
+        }
+
+        // Add tmux filters
+        Self::push_tmux_filter(&mut sql, &mut params, query);
+
+        // Add exclusions
+        Self::push_exclude_filter(&mut sql, &mut params, query);
+
+        // Add duration filter
+        Self::push_duration_filter(&mut sql, &mut params, query);
+
+        // Add program and flag filters
+        Self::push_parsed_filter(&mut sql, &mut params, query);
+
+        // Add text search if provided
+        if let Some(ref text) = query.text {
+            // Sanitize query for FTS5 to handle special characters
+            let sanitized = if query.prefix {
+                Self::fts5_prefix_query(text)
+            } else {
+                Self::sanitize_fts5_query(text)
+            };
+            sql.push_str(" AND id IN (SELECT rowid FROM commands_fts WHERE command MATCH ?)");
+            params.push(Box::new(sanitized));
+        }
+
+        // Add ordering
+        Self::push_order(&mut sql, query.order_by);
+
+        sql.push_str(&format!(" LIMIT {} OFFSET {}", query.limit, query.offset));
+
+        // Try FTS5 search first, fall back to LIKE if it fails
+        let stmt_result = self.conn.prepare_cached(&sql);
+
+        let records = match stmt_result {
+            Ok(mut stmt) => {
+                let param_refs: Vec<&dyn rusqlite::ToSql> =
+                    params.iter().map(|p| p.as_ref()).collect();
+
+                let rows_result = stmt.query_map(param_refs.as_slice(), Self::row_to_record);
+
+                match rows_result {
+                    Ok(rows) => rows.collect::<std::result::Result<Vec<_>, _>>()?,
+                    Err(_) if query.text.is_some() => {
+                        // FTS5 query failed, fall back to LIKE search
+                        self.search_with_like(query, query.text.as_ref().unwrap())?
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Err(_) if query.text.is_some() => {
+                // FTS5 prepare failed, fall back to LIKE search
+                self.search_with_like(query, query.text.as_ref().unwrap())?
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(records)
+    }
+
+    /// Get the most recent N commands
+    pub fn get_recent(
+        &self,
+        limit: usize,
+        working_dir: Option<String>,
+        recursive: bool,
+    ) -> Result<Vec<CommandRecord>> {
+        let query = SearchQuery {
+            text: None,
+            category: None,
+            success_only: None,
+            working_dir,
+            recursive,
+            limit,
+            order_by: OrderBy::Timestamp,
+            ..Default::default()
+        };
+
+        self.search(&query)
+    }
+
+    /// Get the most frequently used commands
+    pub fn get_top(
+        &self,
+        limit: usize,
+        working_dir: Option<String>,
+        recursive: bool,
+    ) -> Result<Vec<CommandRecord>> {
+        let query = SearchQuery {
+            text: None,
+            category: None,
+            success_only: None,
+            working_dir,
+            recursive,
+            limit,
+            order_by: OrderBy::UsageCount,
+            ..Default::default()
+        };
+
+        self.search(&query)
+    }
+
+    /// Get the `limit` most used commands run in exactly `working_dir`
+    ///
+    /// Meant for suggestions shown on every `cd`: it finds the directory's
+    /// commands through a dedicated index, pins bookmarked ones first, skips
+    /// failed commands that ran only once (typos, mostly), and is served
+    /// from the query cache when that is enabled.
+    pub fn top_for_dir(&self, working_dir: &str, limit: usize) -> Result<Vec<CommandRecord>> {
+        let key = CacheKey::TopForDir(working_dir.to_string(), limit);
+        let result = self
+            .cached(key, || {
+                let mut stmt = self.conn.prepare_cached(
+                    "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
+                            category, usage_count, last_used, typed_command, note
+                     FROM commands INDEXED BY idx_dir_usage
+                     WHERE working_dir = ?1
+                       AND (exit_code = 0 OR usage_count > 1)
+                     ORDER BY id IN (SELECT command_id FROM bookmarks) DESC, usage_count DESC
+                     LIMIT ?2",
+                )?;
+                let records = stmt
+                    .query_map(params![working_dir, limit as i64], Self::row_to_record)?
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                Ok(CachedResult::Records(records))
+            })
+            .with_context(|| format!("load top commands in {}", working_dir))?;
+
+        match result {
+            CachedResult::Records(records) => Ok(records),
+            CachedResult::Stats(_) => unreachable!("directory key always caches records"),
+        }
+    }
+
+    /// Get commands of at least `min_length` characters run at least
+    /// `min_uses` times (across directories), most used first
+    ///
+    /// Commands ever run through an alias are left out, since they
+    /// already have one.
+    pub fn get_alias_candidates(
+        &self,
+        min_length: usize,
+        min_uses: i64,
+    ) -> Result<Vec<(String, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT command, SUM(usage_count) AS uses
+             FROM commands
+             WHERE length(command) >= ?1
+             GROUP BY command
+             HAVING uses >= ?2 AND COUNT(typed_command) = 0
+             ORDER BY uses DESC, command",
+        )?;
+
+        let candidates = stmt
+            .query_map(params![min_length as i64, min_uses], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("load alias candidates")?;
+
+        Ok(candidates)
+    }
+
+    /// Get commands starting with `prefix`, most relevant first
+    pub fn suggest(
+        &self,
+        prefix: &str,
+        limit: usize,
+        working_dir: Option<String>,
+        recursive: bool,
+    ) -> Result<Vec<CommandRecord>> {
+        let mut sql = String::from(
+            "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
+                    category, usage_count, last_used, typed_command, note
+             FROM commands
+             WHERE substr(command, 1, length(?1)) = ?1",
+        );
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(prefix.to_string())];
+
+        if let Some(ref dir) = working_dir {
+            Self::push_dir_filter(&mut sql, &mut params, "working_dir", dir, recursive);
+        }
+
+        Self::push_order(&mut sql, OrderBy::Relevance);
+        sql.push_str(&format!(" LIMIT {}", limit));
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let records = stmt
+            .query_map(param_refs.as_slice(), Self::row_to_record)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(records)
+    }
+
+    /// Get all commands in a specific category
+    pub fn get_by_category(
+        &self,
+        category: &str,
+        limit: usize,
+        working_dir: Option<String>,
+        recursive: bool,
+    ) -> Result<Vec<CommandRecord>> {
+        let query = SearchQuery {
+            text: None,
+            category: Some(category.to_string()),
+            success_only: None,
+            working_dir,
+            recursive,
+            limit,
+            order_by: OrderBy::UsageCount,
+            ..Default::default()
+        };
+
+        self.search(&query)
+    }
+
+    /// Get statistics about the command history
+    ///
+    /// Read from counters, so it stays fast on large histories; `daily` and
+    /// `vocabulary` are left empty (see [`Storage::get_stats_with_trends`]).
+    pub fn get_stats(&self) -> Result<Stats> {
+        let result = self
+            .cached(CacheKey::Stats, || {
+                self.compute_stats().map(CachedResult::Stats)
+            })
+            .context("compute stats")?;
+
+        match result {
+            CachedResult::Stats(stats) => Ok(stats),
+            CachedResult::Records(_) => unreachable!("stats key always caches stats"),
+        }
+    }
+
+    /// Get statistics along with daily executions over the last
+    /// [`STATS_DAYS`] days and vocabulary growth over the last
+    /// [`STATS_WEEKS`] weeks (`omniscient stats`)
+    ///
+    /// The trends scan recent history, so prompts and other frequent callers
+    /// should use [`Storage::get_stats`].
+    pub fn get_stats_with_trends(&self) -> Result<Stats> {
+        let mut stats = self.get_stats()?;
+
+        stats.daily = self
+            .get_activity(
+                ActivityPeriod::Day,
+                Some(Utc::now() - chrono::Duration::days(STATS_DAYS)),
+                None,
+            )?
+            .buckets;
+
+        stats.vocabulary = self.get_vocabulary_growth(
+            ActivityPeriod::Week,
+            Utc::now() - chrono::Duration::weeks(STATS_WEEKS - 1),
+            None,
+        )?;
+
+        Ok(stats)
+    }
+
+    /// Compute statistics from the database, bypassing the cache
+    fn compute_stats(&self) -> Result<Stats> {
+        // Totals come from the trigger-maintained counters rather than
+        // scanning the commands table
+        let (total_commands, successful_commands): (usize, usize) = self.conn.query_row(
+            "SELECT COALESCE(SUM(total), 0), COALESCE(SUM(successful), 0) FROM category_counts",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        // Failed commands
+        let failed_commands = total_commands - successful_commands;
+
+        // Commands by category
+        let mut stmt = self.conn.prepare(
+            "SELECT category, total, successful,
+                    CASE WHEN total > 0 THEN duration_sum / total ELSE 0 END, last_used
+             FROM category_counts
+             ORDER BY total DESC, category ASC",
+        )?;
+
+        let by_category = stmt
+            .query_map([], |row| {
+                let count: usize = row.get(1)?;
+                let successful: usize = row.get(2)?;
+                Ok(CategoryBreakdown {
+                    category: row.get(0)?,
+                    count,
+                    successful,
+                    success_rate: if count == 0 {
+                        0.0
+                    } else {
+                        successful as f64 / count as f64 * 100.0
+                    },
+                    avg_duration_ms: row.get(3)?,
+                    last_used: row
+                        .get::<_, Option<String>>(4)?
+                        .and_then(|time| time.parse().ok()),
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        // Oldest command timestamp
+        let oldest_command: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT timestamp FROM commands ORDER BY timestamp ASC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        // Newest command timestamp
+        let newest_command: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT timestamp FROM commands ORDER BY timestamp DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(Stats {
+            total_commands,
+            successful_commands,
+            failed_commands,
+            by_category,
+            oldest_command: oldest_command.and_then(|s| s.parse().ok()),
+            newest_command: newest_command.and_then(|s| s.parse().ok()),
+            daily: Vec::new(),
+            vocabulary: Vec::new(),
+        })
+    }
+
+    /// Get per-branch activity, most active branches first
+    pub fn get_branch_stats(&self, limit: usize) -> Result<Vec<BranchStats>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT git_branch, git_repo, COUNT(*), COUNT(DISTINCT command_id), MAX(timestamp)
+             FROM executions
+             WHERE git_branch IS NOT NULL
+             GROUP BY git_branch, git_repo
+             ORDER BY COUNT(*) DESC, MAX(timestamp) DESC
+             LIMIT ?1",
+        )?;
+
+        let stats = stmt
+            .query_map(params![limit as i64], |row| {
+                Ok(BranchStats {
+                    branch: row.get(0)?,
+                    repo: row.get(1)?,
+                    executions: row.get(2)?,
+                    commands: row.get(3)?,
+                    last_used: row.get::<_, String>(4)?.parse().unwrap(),
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(stats)
+    }
+
+    /// Get per-directory activity, most active directories first
+    ///
+    /// Each directory lists up to `categories` of its most used categories.
+    /// With `since`, only runs from then on are counted; with `category`,
+    /// only runs of commands in that category (where a category is used
+    /// most).
+    pub fn get_dir_stats(
+        &self,
+        limit: usize,
+        categories: usize,
+        working_dir: Option<String>,
+        recursive: bool,
+        since: Option<DateTime<Utc>>,
+        category: Option<&str>,
+    ) -> Result<Vec<DirStats>> {
+        let mut sql = format!(
+            "SELECT c.working_dir, COUNT(*), COUNT(DISTINCT c.id), SUM({code} = 0), MAX({ts}),
+                    SUM(COALESCE(e.duration_ms, c.duration_ms))
+             {from}",
+            code = RUN_EXIT_CODE,
+            ts = RUN_TIMESTAMP,
+            from = RUNS_FROM
+        );
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+
+        if let Some(ref dir) = working_dir {
+            Self::push_dir_filter(&mut sql, &mut params, "c.working_dir", dir, recursive);
+        }
+        if let Some(category) = category {
+            sql.push_str(" AND c.category = ?");
+            params.push(Box::new(category.to_string()));
+        }
+        let since = since.map(|since| since.to_rfc3339());
+        if let Some(ref since) = since {
+            sql.push_str(&format!(
+                " AND julianday({}) >= julianday(?)",
+                RUN_TIMESTAMP
+            ));
+            params.push(Box::new(since.clone()));
+        }
+
+        sql.push_str(&format!(
+            " GROUP BY c.working_dir
+              ORDER BY COUNT(*) DESC, MAX({}) DESC
+              LIMIT ?",
+            RUN_TIMESTAMP
+        ));
+        params.push(Box::new(limit as i64));
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let mut stats = stmt
+            .query_map(param_refs.as_slice(), |row| {
+                Ok(DirStats {
+                    working_dir: row.get(0)?,
+                    runs: row.get(1)?,
+                    commands: row.get(2)?,
+                    successful: row.get(3)?,
+                    last_used: row.get::<_, String>(4)?.parse().unwrap(),
+                    duration_ms: row.get(5)?,
+                    top_categories: Vec::new(),
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("compute directory stats")?;
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT c.category, COUNT(*)
+             {from} AND c.working_dir = ?1
+                    AND (?3 IS NULL OR julianday({ts}) >= julianday(?3))
+             GROUP BY c.category
+             ORDER BY COUNT(*) DESC, c.category
+             LIMIT ?2",
+            from = RUNS_FROM,
+            ts = RUN_TIMESTAMP
+        ))?;
+        for dir in &mut stats {
+            dir.top_categories = stmt
+                .query_map(params![dir.working_dir, categories as i64, since], |row| {
+                    Ok(CategoryStats {
+                        category: row.get(0)?,
+                        count: row.get(1)?,
+                    })
+                })?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+        }
+
+        Ok(stats)
+    }
+
+    /// Summarize the activity since a point in time, listing up to `limit`
+    /// entries per section
+    pub fn get_summary(&self, since: DateTime<Utc>, limit: usize) -> Result<Summary> {
+        let filter = format!("AND julianday({}) >= julianday(?1)", RUN_TIMESTAMP);
+        let since_param = since.to_rfc3339();
+
+        let (runs, successful, commands) = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*), COALESCE(SUM({code} = 0), 0), COUNT(DISTINCT c.id)
+                     {from} {filter}",
+                    code = RUN_EXIT_CODE,
+                    from = RUNS_FROM,
+                    filter = filter
+                ),
+                params![since_param],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .context("count runs")?;
+
+        let new_command_count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM commands WHERE julianday(timestamp) >= julianday(?1)",
+            params![since_param],
+            |row| row.get(0),
+        )?;
+        let mut stmt = self.conn.prepare(
+            "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
+                    category, usage_count, last_used, typed_command, note
+             FROM commands
+             WHERE julianday(timestamp) >= julianday(?1)
+             ORDER BY usage_count DESC, last_used DESC
+             LIMIT ?2",
+        )?;
+        let new_commands = stmt
+            .query_map(params![since_param, limit as i64], Self::row_to_record)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        // The program is the first word of the command
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT substr(c.command, 1, instr(c.command || ' ', ' ') - 1) AS tool, COUNT(*)
+             {from} {filter}
+             GROUP BY tool
+             ORDER BY COUNT(*) DESC, tool
+             LIMIT ?2",
+            from = RUNS_FROM,
+            filter = filter
+        ))?;
+        let top_tools = stmt
+            .query_map(params![since_param, limit as i64], |row| {
+                Ok(ToolUsage {
+                    tool: row.get(0)?,
+                    count: row.get(1)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT c.id, c.command, c.working_dir, COUNT(*),
+                    AVG(COALESCE(e.duration_ms, c.duration_ms)) AS avg_ms,
+                    MAX(COALESCE(e.duration_ms, c.duration_ms)),
+                    MIN(COALESCE(e.duration_ms, c.duration_ms))
+             {from} {filter}
+             GROUP BY c.id
+             ORDER BY avg_ms DESC
+             LIMIT ?2",
+            from = RUNS_FROM,
+            filter = filter
+        ))?;
+        let slowest = stmt
+            .query_map(params![since_param, limit as i64], |row| {
+                Ok(DurationStats {
+                    command_id: row.get(0)?,
+                    command: row.get(1)?,
+                    working_dir: row.get(2)?,
+                    runs: row.get(3)?,
+                    avg_ms: row.get::<_, f64>(4)?.round() as i64,
+                    max_ms: row.get(5)?,
+                    min_ms: row.get(6)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("find slowest commands")?;
+
+        Ok(Summary {
+            since,
+            runs,
+            successful,
+            commands,
+            new_command_count: new_command_count as usize,
+            new_commands,
+            top_tools,
+            busiest_dirs: self.get_dir_stats(limit, 3, None, false, Some(since), None)?,
+            slowest,
+        })
+    }
+
+    /// Aggregate a calendar year (UTC) for `omniscient wrapped`
+    pub fn get_wrapped(&self, year: i32) -> Result<Wrapped> {
+        let start = |year: i32| {
+            NaiveDate::from_ymd_opt(year, 1, 1)
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .map(|time| time.and_utc().to_rfc3339())
+                .ok_or_else(|| OmniscientError::other(format!("Invalid year {}", year)))
+        };
+        let range = params![start(year)?, start(year + 1)?];
+
+        // Query over the year's runs; `{code}`, `{ts}` and `{duration}` stand
+        // for a run's exit code, timestamp and duration
+        let runs = |select: &str, rest: &str| {
+            format!(
+                "SELECT {select} {from}
+                 AND julianday({ts}) >= julianday(?1) AND julianday({ts}) < julianday(?2)
+                 {rest}",
+                from = RUNS_FROM,
+                ts = RUN_TIMESTAMP
+            )
+            .replace("{code}", RUN_EXIT_CODE)
+            .replace("{ts}", RUN_TIMESTAMP)
+            .replace("{duration}", "COALESCE(e.duration_ms, c.duration_ms)")
+        };
+
+        let (total, successful, commands) = self.conn.query_row(
+            &runs(
+                "COUNT(*), COALESCE(SUM({code} = 0), 0), COUNT(DISTINCT c.id)",
+                "",
+            ),
+            range,
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        let mut stmt = self.conn.prepare(&runs(
+            "c.id, c.command, COUNT(*)",
+            "GROUP BY c.id ORDER BY COUNT(*) DESC, MAX({ts}) DESC LIMIT 10",
+        ))?;
+        let top_commands = stmt
+            .query_map(range, |row| {
+                Ok(CommandCount {
+                    command_id: row.get(0)?,
+                    command: row.get(1)?,
+                    runs: row.get(2)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let most_failed = self
+            .conn
+            .query_row(
+                &runs(
+                    "c.id, c.command, c.working_dir, COUNT(*), SUM({code} != 0) AS failures,
+                     MAX(CASE WHEN {code} != 0 THEN {ts} END)",
+                    "GROUP BY c.id HAVING failures > 0 ORDER BY failures DESC, COUNT(*) LIMIT 1",
+                ),
+                range,
+                |row| {
+                    Ok(FailureStats {
+                        command_id: row.get(0)?,
+                        command: row.get(1)?,
+                        working_dir: row.get(2)?,
+                        runs: row.get(3)?,
+                        failures: row.get(4)?,
+                        last_failed: row.get::<_, Option<String>>(5)?.map(|t| t.parse().unwrap()),
+                    })
+                },
+            )
+            .optional()?;
+
+        let longest_running = self
+            .conn
+            .query_row(
+                &runs(
+                    "c.id, c.command, c.working_dir, COUNT(*), AVG({duration}),
+                     MAX({duration}) AS max_ms, MIN({duration})",
+                    "GROUP BY c.id ORDER BY max_ms DESC LIMIT 1",
+                ),
+                range,
+                |row| {
+                    Ok(DurationStats {
+                        command_id: row.get(0)?,
+                        command: row.get(1)?,
+                        working_dir: row.get(2)?,
+                        runs: row.get(3)?,
+                        avg_ms: row.get::<_, f64>(4)?.round() as i64,
+                        max_ms: row.get(5)?,
+                        min_ms: row.get(6)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        let mut stmt = self.conn.prepare(&runs(
+            "c.category, COUNT(*)",
+            "GROUP BY c.category ORDER BY COUNT(*) DESC, c.category",
+        ))?;
+        let categories = stmt
+            .query_map(range, |row| {
+                Ok(CategoryStats {
+                    category: row.get(0)?,
+                    count: row.get(1)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let mut months = [0; 12];
+        let mut stmt = self.conn.prepare(&runs(
+            "CAST(strftime('%m', {ts}) AS INTEGER) AS month, COUNT(*)",
+            "GROUP BY month",
+        ))?;
+        let rows = stmt.query_map(range, |row| {
+            Ok((row.get::<_, usize>(0)?, row.get::<_, usize>(1)?))
+        })?;
+        for row in rows {
+            let (month, count) = row?;
+            if (1..=12).contains(&month) {
+                months[month - 1] = count;
+            }
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare(&runs("DISTINCT date({ts}) AS day", "ORDER BY day"))?;
+        let days = stmt
+            .query_map(range, |row| row.get::<_, String>(0))?
+            .collect::<std::result::Result<Vec<_>, _>>()?
+            .iter()
+            .filter_map(|day| day.parse().ok())
+            .collect::<Vec<NaiveDate>>();
+
+        Ok(Wrapped {
+            year,
+            runs: total,
+            successful,
+            commands,
+            top_commands,
+            most_failed,
+            longest_running,
+            categories,
+            months,
+            active_days: days.len(),
+            longest_streak: longest_streak(&days),
+        })
+    }
+
+    /// Aggregate executions per day or week, plus an hour-of-day heatmap
+    ///
+    /// Only executions within `[since, until)` are counted. Executions logged
+    /// before exit codes were recorded use their command's exit code.
+    pub fn get_activity(
+        &self,
+        period: ActivityPeriod,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<ActivityReport> {
+        let mut filter = String::from(" WHERE 1=1");
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+        if let Some(since) = since {
+            filter.push_str(" AND julianday(e.timestamp) >= julianday(?)");
+            params.push(Box::new(since.to_rfc3339()));
+        }
+        if let Some(until) = until {
+            filter.push_str(" AND julianday(e.timestamp) < julianday(?)");
+            params.push(Box::new(until.to_rfc3339()));
+        }
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+        let local = self.local_time_sql("e.timestamp");
+        let bucket = self.bucket_sql(period, "e.timestamp");
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {bucket} AS bucket, COUNT(*),
+                    SUM(COALESCE(e.exit_code, c.exit_code) = 0)
+             FROM executions e
+             JOIN commands c ON c.id = e.command_id
+             {filter}
+             GROUP BY bucket
+             ORDER BY bucket ASC"
+        ))?;
+        let buckets = stmt
+            .query_map(param_refs.as_slice(), |row| {
+                Ok(ActivityBucket {
+                    start: row.get::<_, String>(0)?.parse().unwrap(),
+                    executions: row.get(1)?,
+                    successful: row.get(2)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("aggregate activity")?;
+
+        let mut heatmap = [[0; 24]; 7];
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT CAST(strftime('%w', {local}) AS INTEGER),
+                    CAST(strftime('%H', {local}) AS INTEGER), COUNT(*)
+             FROM executions e
+             JOIN commands c ON c.id = e.command_id
+             {filter}
+             GROUP BY 1, 2"
+        ))?;
+        let mut rows = stmt.query(param_refs.as_slice())?;
+        while let Some(row) = rows.next()? {
+            let (weekday, hour, count): (usize, usize, usize) =
+                (row.get(0)?, row.get(1)?, row.get(2)?);
+            // strftime counts from Sunday; the heatmap starts on Monday
+            heatmap[(weekday + 6) % 7][hour] = count;
+        }
+
+        Ok(ActivityReport {
+            period,
+            buckets,
+            heatmap,
+        })
+    }
+
+    /// Count the commands run for the first time per day, week or month
+    /// within `[since, until)`, oldest first
+    ///
+    /// Every bucket of the range is included, also those without new
+    /// commands, so the counts can be charted as a trend. `until` defaults to
+    /// now.
+    pub fn get_vocabulary_growth(
+        &self,
+        period: ActivityPeriod,
+        since: DateTime<Utc>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<VocabularyBucket>> {
+        let until = until.unwrap_or_else(Utc::now);
+        let range = params![since.to_rfc3339(), until.to_rfc3339()];
+
+        let mut total: usize = self.conn.query_row(
+            "SELECT COUNT(*) FROM commands WHERE julianday(timestamp) < julianday(?1)",
+            params![since.to_rfc3339()],
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} AS bucket, COUNT(*)
+             FROM commands
+             WHERE julianday(timestamp) >= julianday(?1) AND julianday(timestamp) < julianday(?2)
+             GROUP BY bucket",
+            self.bucket_sql(period, "timestamp")
+        ))?;
+        let counts = stmt
+            .query_map(range, |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("count new commands")?
+            .into_iter()
+            .filter_map(|(start, count)| Some((start.parse::<NaiveDate>().ok()?, count)))
+            .collect::<BTreeMap<_, _>>();
+
+        let offset = chrono::Duration::minutes(self.utc_offset.get().into());
+        let mut start = period.start_of((since + offset).date_naive());
+        let last = period.start_of((until - chrono::Duration::seconds(1) + offset).date_naive());
+        let mut buckets = Vec::new();
+        while start <= last {
+            let new_commands = counts.get(&start).copied().unwrap_or(0);
+            total += new_commands;
+            buckets.push(VocabularyBucket {
+                start,
+                new_commands,
+                total,
+            });
+            start = period.next(start);
+        }
+
+        Ok(buckets)
+    }
+
+    /// `column` shifted to the display time zone, as SQLite date function
+    /// arguments
+    fn local_time_sql(&self, column: &str) -> String {
+        format!("{column}, '{:+} minutes'", self.utc_offset.get())
+    }
+
+    /// The first day of the `period` bucket `column` falls in, in the display
+    /// time zone; weeks start on the Monday on or before it
+    fn bucket_sql(&self, period: ActivityPeriod, column: &str) -> String {
+        let local = self.local_time_sql(column);
+        match period {
+            ActivityPeriod::Day => format!("date({local})"),
+            ActivityPeriod::Week => format!("date({local}, 'weekday 0', '-6 days')"),
+            ActivityPeriod::Month => format!("date({local}, 'start of month')"),
+        }
+    }
+
+    /// Find the commands that take longest, by average or maximum duration
+    ///
+    /// Commands without logged executions count as a single run of their
+    /// stored duration; so do executions logged before durations were
+    /// recorded. Only commands with at least `min_runs` runs are included.
+    pub fn get_slowest(
+        &self,
+        by: SlowestBy,
+        limit: usize,
+        min_runs: usize,
+        working_dir: Option<String>,
+        recursive: bool,
+    ) -> Result<Vec<DurationStats>> {
+        let mut sql = String::from(
+            "SELECT c.id, c.command, c.working_dir, MAX(COUNT(e.id), 1) AS runs,
+                    AVG(COALESCE(e.duration_ms, c.duration_ms)) AS avg_ms,
+                    MAX(COALESCE(e.duration_ms, c.duration_ms)) AS max_ms,
+                    MIN(COALESCE(e.duration_ms, c.duration_ms))
+             FROM commands c
+             LEFT JOIN executions e ON e.command_id = c.id
+             WHERE 1=1",
+        );
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+
+        if let Some(ref dir) = working_dir {
+            Self::push_dir_filter(&mut sql, &mut params, "c.working_dir", dir, recursive);
+        }
+
+        sql.push_str(" GROUP BY c.id HAVING runs >= ?");
+        params.push(Box::new(min_runs as i64));
+
+        sql.push_str(match by {
+            SlowestBy::Average => " ORDER BY avg_ms DESC, max_ms DESC",
+            SlowestBy::Max => " ORDER BY max_ms DESC, avg_ms DESC",
+        });
+        sql.push_str(" LIMIT ?");
+        params.push(Box::new(limit as i64));
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let stats = stmt
+            .query_map(param_refs.as_slice(), |row| {
+                Ok(DurationStats {
+                    command_id: row.get(0)?,
+                    command: row.get(1)?,
+                    working_dir: row.get(2)?,
+                    runs: row.get(3)?,
+                    avg_ms: row.get::<_, f64>(4)?.round() as i64,
+                    max_ms: row.get(5)?,
+                    min_ms: row.get(6)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("find slowest commands")?;
+
+        Ok(stats)
+    }
+
+    /// Find the commands that fail most often (by failure rate, then count)
+    ///
+    /// Only commands with at least one failure and `min_runs` runs are included.
+    pub fn get_failure_rates(
+        &self,
+        limit: usize,
+        min_runs: usize,
+        working_dir: Option<String>,
+        recursive: bool,
+    ) -> Result<Vec<FailureStats>> {
+        let mut sql = format!(
+            "SELECT c.id, c.command, c.working_dir, MAX(COUNT(e.id), 1) AS runs,
+                    SUM({code} != 0) AS failures,
+                    MAX(CASE WHEN {code} != 0 THEN {ts} END)
+             {from}",
+            code = RUN_EXIT_CODE,
+            ts = RUN_TIMESTAMP,
+            from = RUNS_FROM
+        );
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+
+        if let Some(ref dir) = working_dir {
+            Self::push_dir_filter(&mut sql, &mut params, "c.working_dir", dir, recursive);
+        }
+
+        sql.push_str(
+            " GROUP BY c.id HAVING failures > 0 AND runs >= ?
+              ORDER BY CAST(failures AS REAL) / runs DESC, failures DESC
+              LIMIT ?",
+        );
+        params.push(Box::new(min_runs as i64));
+        params.push(Box::new(limit as i64));
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let stats = stmt
+            .query_map(param_refs.as_slice(), |row| {
+                Ok(FailureStats {
+                    command_id: row.get(0)?,
+                    command: row.get(1)?,
+                    working_dir: row.get(2)?,
+                    runs: row.get(3)?,
+                    failures: row.get(4)?,
+                    last_failed: row.get::<_, Option<String>>(5)?.map(|t| t.parse().unwrap()),
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("compute failure rates")?;
+
+        Ok(stats)
+    }
+
+    /// Get the most recent failed executions, newest first
+    pub fn get_recent_failures(
+        &self,
+        limit: usize,
+        working_dir: Option<String>,
+        recursive: bool,
+    ) -> Result<Vec<FailedRun>> {
+        let mut sql = format!(
+            "SELECT c.id, c.command, c.working_dir, {code}, {ts}
+             {from} AND {code} != 0",
+            code = RUN_EXIT_CODE,
+            ts = RUN_TIMESTAMP,
+            from = RUNS_FROM
+        );
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+
+        if let Some(ref dir) = working_dir {
+            Self::push_dir_filter(&mut sql, &mut params, "c.working_dir", dir, recursive);
+        }
+
+        sql.push_str(&format!(
+            " ORDER BY julianday({}) DESC, e.id DESC LIMIT ?",
+            RUN_TIMESTAMP
+        ));
+        params.push(Box::new(limit as i64));
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let runs = stmt
+            .query_map(param_refs.as_slice(), |row| {
+                Ok(FailedRun {
+                    command_id: row.get(0)?,
+                    command: row.get(1)?,
+                    working_dir: row.get(2)?,
+                    exit_code: row.get(3)?,
+                    timestamp: row.get::<_, String>(4)?.parse().unwrap(),
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("find recent failures")?;
+
+        Ok(runs)
+    }
+
+    /// Count failed executions per non-zero exit code, most common first
+    pub fn get_exit_code_counts(
+        &self,
+        working_dir: Option<String>,
+        recursive: bool,
+    ) -> Result<Vec<ExitCodeCount>> {
+        let mut sql = format!(
+            "SELECT {code} AS code, COUNT(*) AS n
+             {from} AND {code} != 0",
+            code = RUN_EXIT_CODE,
+            from = RUNS_FROM
+        );
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+
+        if let Some(ref dir) = working_dir {
+            Self::push_dir_filter(&mut sql, &mut params, "c.working_dir", dir, recursive);
+        }
+
+        sql.push_str(" GROUP BY code ORDER BY n DESC, code ASC");
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let counts = stmt
+            .query_map(param_refs.as_slice(), |row| {
+                Ok(ExitCodeCount {
+                    exit_code: row.get(0)?,
+                    count: row.get(1)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("count exit codes")?;
+
+        Ok(counts)
+    }
+
+    /// Get all commands (for export)
+    pub fn get_all(&self) -> Result<Vec<CommandRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
+                    category, usage_count, last_used, typed_command, note
+             FROM commands
+             ORDER BY timestamp ASC",
+        )?;
+
+        let records = stmt
+            .query_map([], Self::row_to_record)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(records)
+    }
+
+    /// Visit every command (oldest first) without loading them all into memory
+    ///
+    /// Returns the number of records visited.
+    pub fn for_each_record<F>(&self, mut visit: F) -> Result<usize>
+    where
+        F: FnMut(CommandRecord) -> Result<()>,
+    {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
+                    category, usage_count, last_used, typed_command, note
+             FROM commands
+             ORDER BY timestamp ASC",
+        )?;
+
+        let mut count = 0;
+        for record in stmt.query_map([], Self::row_to_record)? {
+            visit(record?)?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Rewrite or delete stored commands, `batch_size` records at a time
+    ///
+    /// Each batch is read and changed in its own transaction, so a large
+    /// history is never loaded into memory at once. Returns the number of
+    /// records visited.
+    pub fn rewrite_records<F>(&self, batch_size: usize, mut change: F) -> Result<usize>
+    where
+        F: FnMut(&CommandRecord) -> Option<RecordChange>,
+    {
+        let batch_size = batch_size.max(1);
+        let mut last_id = 0;
+        let mut visited = 0;
+
+        loop {
+            let count = self.with_tx(|| {
+                let batch = self
+                    .conn
+                    .prepare_cached(
+                        "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
+                                category, usage_count, last_used, typed_command, note
+                         FROM commands
+                         WHERE id > ?1
+                         ORDER BY id
+                         LIMIT ?2",
+                    )?
+                    .query_map(params![last_id, batch_size], Self::row_to_record)?
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+
+                for record in &batch {
+                    let id = record.id.unwrap_or_default();
+                    match change(record) {
+                        Some(RecordChange::Update(updated)) => {
+                            self.update_record(&CommandRecord {
+                                id: Some(id),
+                                ..updated
+                            })?;
+                        }
+                        Some(RecordChange::Delete) => {
+                            self.delete(id)?;
+                        }
+                        None => {}
+                    }
+                    last_id = id;
+                }
+                Ok(batch.len())
+            })?;
+
+            visited += count;
+            if count < batch_size {
+                return Ok(visited);
+            }
+        }
+    }
+
+    /// Edit the recorded environment variables of every execution in
+    /// batches of `batch_size` per transaction
+    ///
+    /// `change` edits the variables in place and returns whether it changed
+    /// anything. Returns the number of executions changed.
+    pub fn rewrite_execution_env<F>(&self, batch_size: usize, mut change: F) -> Result<usize>
+    where
+        F: FnMut(&mut BTreeMap<String, String>) -> bool,
+    {
+        let batch_size = batch_size.max(1);
+        let mut last_id = 0;
+        let mut changed = 0;
+
+        loop {
+            let count = self.with_tx(|| {
+                let batch = self
+                    .conn
+                    .prepare_cached(
+                        "SELECT id, env FROM executions
+                         WHERE id > ?1 AND env IS NOT NULL
+                         ORDER BY id
+                         LIMIT ?2",
+                    )?
+                    .query_map(params![last_id, batch_size], |row| {
+                        Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?))
+                    })?
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+
+                let mut update = self
+                    .conn
+                    .prepare_cached("UPDATE executions SET env = ?2 WHERE id = ?1")?;
+                for (id, json) in &batch {
+                    let mut env = Self::parse_env(json.clone());
+                    if change(&mut env) {
+                        update.execute(params![id, serde_json::to_string(&env)?])?;
+                        changed += 1;
+                    }
+                    last_id = *id;
+                }
+                Ok(batch.len())
+            })?;
+
+            if count < batch_size {
+                return Ok(changed);
+            }
+        }
+    }
+
+    /// Rewrite or delete captured outputs in batches of `batch_size` per
+    /// transaction
+    ///
+    /// `change` returns what to do with an output's text, or `None` to keep
+    /// it. Returns the number of outputs visited.
+    pub fn rewrite_outputs<F>(&self, batch_size: usize, mut change: F) -> Result<usize>
+    where
+        F: FnMut(&str) -> Option<RecordChange<String>>,
+    {
+        let batch_size = batch_size.max(1);
+        let mut last_id = 0;
+        let mut visited = 0;
+
+        loop {
+            let count = self.with_tx(|| {
+                let batch = self
+                    .conn
+                    .prepare_cached(
+                        "SELECT execution_id, output FROM outputs
+                         WHERE execution_id > ?1
+                         ORDER BY execution_id
+                         LIMIT ?2",
+                    )?
+                    .query_map(params![last_id, batch_size], |row| {
+                        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                    })?
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+
+                for (id, output) in &batch {
+                    match change(output) {
+                        Some(RecordChange::Update(text)) => {
+                            self.conn
+                                .prepare_cached(
+                                    "UPDATE outputs SET output = ?2 WHERE execution_id = ?1",
+                                )?
+                                .execute(params![id, text])?;
+                        }
+                        Some(RecordChange::Delete) => {
+                            self.conn
+                                .prepare_cached("DELETE FROM outputs WHERE execution_id = ?1")?
+                                .execute(params![id])?;
+                        }
+                        None => {}
+                    }
+                    last_id = *id;
+                }
+                Ok(batch.len())
+            })?;
+
+            visited += count;
+            if count < batch_size {
+                return Ok(visited);
+            }
+        }
+    }
+
+    /// Get every distinct working directory with stored commands
+    pub fn get_working_dirs(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT working_dir FROM commands ORDER BY working_dir")?;
+
+        let dirs = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(dirs)
+    }
+
+    /// Get the IDs of all commands stored for exactly this directory
+    pub fn get_ids_in_dir(&self, working_dir: &str) -> Result<Vec<i64>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id FROM commands WHERE working_dir = ?1 ORDER BY id")?;
+
+        let ids = stmt
+            .query_map(params![working_dir], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(ids)
+    }
+
+    /// Get a single command by ID
+    pub fn get_by_id(&self, id: i64) -> Result<Option<CommandRecord>> {
+        let record = self
+            .conn
+            .query_row(
+                "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
+                        category, usage_count, last_used, typed_command, note
+                 FROM commands
+                 WHERE id = ?1",
+                params![id],
+                Self::row_to_record,
+            )
+            .optional()
+            .with_context(|| format!("load command {}", id))?;
+
+        Ok(record)
+    }
+
+    /// Get every command running `program`, most used first
+    pub fn get_by_program(&self, program: &str) -> Result<Vec<CommandRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, command, timestamp, exit_code, duration_ms, working_dir,
+                    category, usage_count, last_used, typed_command, note
+             FROM commands
+             WHERE program = ?1
+             ORDER BY usage_count DESC, id",
+        )?;
+
+        let records = stmt
+            .query_map(params![program], Self::row_to_record)?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .with_context(|| format!("load commands of '{}'", program))?;
+
+        Ok(records)
+    }
+
+    /// Delete a command (and its executions and bookmark), returning false if not found
+    pub fn delete(&self, id: i64) -> Result<bool> {
+        let deleted = self
+            .conn
+            .prepare_cached("DELETE FROM commands WHERE id = ?1")?
+            .execute(params![id])
+            .with_context(|| format!("delete command {}", id))?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Copy (or move) a command into another directory's scope
+    ///
+    /// If the target directory already has the same command under `key`
+    /// (`capture.duplicate_key`), the records are merged: a move adds the
+    /// usage counts together, a copy leaves the target as it is. Returns the
+    /// ID of the record in the target directory, or `None` if no command has
+    /// the given ID.
+    pub fn promote(
+        &self,
+        id: i64,
+        to_dir: &str,
+        move_record: bool,
+        key: DuplicateKey,
+    ) -> Result<Option<i64>> {
+        let source = match self.get_by_id(id)? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+
+        if source.working_dir == to_dir {
+            return Ok(Some(id));
+        }
+
+        let target_id = self.with_tx(|| {
+            let existing =
+                self.find_duplicate_by(key, &source.command, to_dir, source.exit_code)?;
+            Ok(match existing {
+                // Directories don't tell records apart under this key
+                Some(existing) if existing.id == Some(id) => {
+                    if move_record {
+                        self.conn.execute(
+                            "UPDATE commands SET working_dir = ?1 WHERE id = ?2",
+                            params![to_dir, id],
+                        )?;
+                    }
+                    id
+                }
+                Some(existing) => {
+                    let target_id = existing.id.unwrap();
+                    if move_record {
+                        self.absorb(id, target_id)?;
+                    }
+                    target_id
+                }
+                None => {
+                    let mut copy = source.clone();
+                    copy.id = None;
+                    copy.working_dir = to_dir.to_string();
+                    let target_id = self.insert(&copy)?;
+                    if move_record {
+                        self.move_history(id, target_id)?;
+                        self.delete(id)?;
+                    }
+                    target_id
+                }
+            })
+        })?;
+
+        Ok(Some(target_id))
+    }
+
+    /// Merge duplicate records into one
+    ///
+    /// The sources are folded into `target_id` (see [`Storage::absorb`]) and
+    /// the target is given the normalized `command` and `working_dir`.
+    pub fn merge_commands(
+        &self,
+        target_id: i64,
+        source_ids: &[i64],
+        command: &str,
+        working_dir: &str,
+    ) -> Result<()> {
+        self.with_tx(|| {
+            for &source_id in source_ids {
+                self.absorb(source_id, target_id)?;
+            }
+            let parsed = ParsedCommand::parse(command);
+            self.conn
+                .execute(
+                    "UPDATE commands SET command = ?1, working_dir = ?2, program = ?3,
+                                         subcommand = ?4, tokens = ?5
+                     WHERE id = ?6",
+                    params![
+                        command,
+                        working_dir,
+                        parsed.program,
+                        parsed.subcommand,
+                        parsed.stored_tokens(),
+                        target_id
+                    ],
+                )
+                .with_context(|| format!("normalize command {}", target_id))?;
+            Ok(())
+        })
+    }
+
+    /// Fold one record into another and delete it
+    ///
+    /// Usage counts are added up, the target keeps the earliest first-seen
+    /// and the latest last-used timestamp, and takes over the source's note
+    /// if it has none of its own. Must run inside a transaction.
+    fn absorb(&self, source_id: i64, target_id: i64) -> Result<()> {
+        self.conn
+            .execute(
+                "UPDATE commands
+                 SET usage_count = commands.usage_count + s.usage_count,
+                     timestamp = MIN(commands.timestamp, s.timestamp),
+                     last_used = MAX(commands.last_used, s.last_used),
+                     note = COALESCE(commands.note, s.note)
+                 FROM (SELECT usage_count, timestamp, last_used, note
+                       FROM commands WHERE id = ?1) AS s
+                 WHERE commands.id = ?2",
+                params![source_id, target_id],
+            )
+            .with_context(|| format!("merge command {} into command {}", source_id, target_id))?;
+        self.move_history(source_id, target_id)?;
+        self.delete(source_id)?;
+
+        Ok(())
+    }
+
+    /// Reassign the executions, bookmark and alerts of one record to another
+    fn move_history(&self, source_id: i64, target_id: i64) -> Result<()> {
+        self.conn
+            .execute(
+                "UPDATE executions SET command_id = ?1 WHERE command_id = ?2",
+                params![target_id, source_id],
+            )
+            .with_context(|| {
+                format!(
+                    "move executions of command {} to command {}",
+                    source_id, target_id
+                )
+            })?;
+        self.conn.execute(
+            "INSERT OR IGNORE INTO bookmarks (command_id, created_at)
+             SELECT ?1, created_at FROM bookmarks WHERE command_id = ?2",
+            params![target_id, source_id],
+        )?;
+        self.conn.execute(
+            "UPDATE alerts SET command_id = ?1 WHERE command_id = ?2",
+            params![target_id, source_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Set or clear the note on a command, returning false if no command has the given ID
+    pub fn set_note(&self, id: i64, note: Option<&str>) -> Result<bool> {
+        let updated = self
+            .conn
+            .execute(
+                "UPDATE commands SET note = ?1 WHERE id = ?2",
+                params![note, id],
+            )
+            .with_context(|| format!("set note on command {}", id))?;
+
+        Ok(updated > 0)
+    }
+
+    /// Bookmark a command, returning false if no command has the given ID
+    pub fn add_bookmark(&self, id: i64) -> Result<bool> {
+        if self.get_by_id(id)?.is_none() {
+            return Ok(false);
+        }
+
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO bookmarks (command_id, created_at) VALUES (?1, ?2)",
+                params![id, Utc::now().to_rfc3339()],
+            )
+            .with_context(|| format!("bookmark command {}", id))?;
+
+        Ok(true)
+    }
+
+    /// Remove a bookmark, returning false if the command was not bookmarked
+    pub fn remove_bookmark(&self, id: i64) -> Result<bool> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM bookmarks WHERE command_id = ?1", params![id])
+            .with_context(|| format!("remove bookmark on command {}", id))?;
+
+        Ok(removed > 0)
+    }
+
+    /// Check whether a command is bookmarked
+    pub fn is_bookmarked(&self, id: i64) -> Result<bool> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM bookmarks WHERE command_id = ?1",
+            params![id],
+            |row| row.get(0),
+        )?;
+
+        Ok(count > 0)
+    }
+
+    /// Get bookmarked commands, most recently bookmarked first, skipping the
+    /// first `offset`
+    pub fn get_bookmarks(&self, limit: usize, offset: usize) -> Result<Vec<CommandRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.id, c.command, c.timestamp, c.exit_code, c.duration_ms, c.working_dir,
+                    c.category, c.usage_count, c.last_used, c.typed_command, c.note
+             FROM bookmarks b
+             JOIN commands c ON c.id = b.command_id
+             ORDER BY b.created_at DESC
+             LIMIT ?1 OFFSET ?2",
+        )?;
+
+        let records = stmt
+            .query_map(params![limit as i64, offset as i64], Self::row_to_record)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(records)
+    }
+
+    /// Check integrity, rebuild the full-text index and stats counters, and compact the database
+    ///
+    /// If the integrity check fails, the report lists the problems and no
+    /// further steps are run so the damaged file is left untouched.
+    pub fn maintain(&self) -> Result<MaintenanceReport> {
+        let size_before = self.database_size()?;
+
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check")?;
+        let integrity_errors: Vec<String> = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("check database integrity")?
+            .into_iter()
+            .filter(|line| line != "ok")
+            .collect();
+
+        if integrity_errors.is_empty() {
+            self.conn
+                .execute_batch(&format!(
+                    "INSERT INTO commands_fts(commands_fts) VALUES('rebuild');
+                         {}
+                         VACUUM;
+                         ANALYZE;
+                         PRAGMA wal_checkpoint(TRUNCATE);",
+                    migrations::REBUILD_CATEGORY_COUNTS
+                ))
+                .context("rebuild search index and compact database")?;
+        }
+
+        Ok(MaintenanceReport {
+            integrity_errors,
+            size_before,
+            size_after: self.database_size()?,
+        })
+    }
+
+    /// Run a read-only SQL query (`omniscient query`), returning at most
+    /// `limit` rows
+    ///
+    /// Only a single SELECT statement is accepted (see
+    /// [`check_select`](crate::query::check_select)), SQLite must consider it
+    /// read-only, and the connection refuses writes while it runs.
+    pub fn query(&self, sql: &str, limit: usize) -> Result<QueryResult> {
+        check_select(sql)?;
+        let mut stmt = self.conn.prepare(sql)?;
+        if !stmt.readonly() {
+            return Err(OmniscientError::other(
+                "Only SELECT queries are allowed (omniscient query is read-only)",
+            ));
+        }
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+
+        self.conn.pragma_update(None, "query_only", true)?;
+        let result = (|| -> Result<(Vec<Vec<serde_json::Value>>, bool)> {
+            let mut rows = stmt.query([])?;
+            let mut values = Vec::new();
+            while let Some(row) = rows.next()? {
+                if values.len() == limit {
+                    return Ok((values, true));
+                }
+                values.push(
+                    (0..columns.len())
+                        .map(|i| Ok(json_value(row.get_ref(i)?)))
+                        .collect::<Result<Vec<_>>>()?,
+                );
+            }
+            Ok((values, false))
+        })();
+        self.conn.pragma_update(None, "query_only", false)?;
+
+        let (rows, truncated) = result?;
+        Ok(QueryResult {
+            columns,
+            rows,
+            truncated,
+        })
+    }
+
+    /// Check the database without changing it: journal mode, structural
+    /// integrity, the search index, and when the last capture happened
+    ///
+    /// Uses `PRAGMA quick_check`, which skips the index content comparison of
+    /// a full integrity check and stays fast on large histories.
+    pub fn check_health(&self) -> Result<HealthReport> {
+        let journal_mode: String = self
+            .conn
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+
+        let mut stmt = self.conn.prepare("PRAGMA quick_check")?;
+        let integrity_errors: Vec<String> = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("check database integrity")?
+            .into_iter()
+            .filter(|line| line != "ok")
+            .collect();
+
+        // Compares the index against the commands table; fails if they differ
+        let search_index_error = self
+            .conn
+            .execute(
+                "INSERT INTO commands_fts(commands_fts, rank) VALUES('integrity-check', 1)",
+                [],
+            )
+            .err()
+            .map(|e| e.to_string());
+
+        let last_capture: Option<String> =
+            self.conn
+                .query_row("SELECT MAX(last_used) FROM commands", [], |row| row.get(0))?;
+
+        Ok(HealthReport {
+            journal_mode,
+            integrity_errors,
+            search_index_error,
+            last_capture: last_capture.and_then(|s| s.parse().ok()),
+        })
+    }
+
+    /// File the database is stored in (`None` for an in-memory database)
+    pub fn database_path(&self) -> Option<&Path> {
+        self.conn
+            .path()
+            .filter(|path| !path.is_empty())
+            .map(Path::new)
+    }
+
+    /// Copy the database to a standalone file using SQLite's online backup API
+    ///
+    /// The copy is consistent even while other processes keep writing.
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut dest =
+            Connection::open(path).with_context(|| format!("create backup {}", path.display()))?;
+        {
+            let backup = rusqlite::backup::Backup::new(&self.conn, &mut dest)?;
+            backup
+                .run_to_completion(256, std::time::Duration::from_millis(10), None)
+                .with_context(|| format!("copy database to {}", path.display()))?;
+        }
+
+        // The copy is meant to be moved around as a single file
+        dest.pragma_update(None, "journal_mode", "DELETE")?;
+
+        Ok(())
+    }
+
+    /// Remove what a redacted copy of the database shouldn't carry
+    ///
+    /// Deletes captured output and recorded environment variables; with
+    /// `strip_locations` also working directories, hostnames and repository
+    /// URLs. Only meant for archive copies (see [`Self::prune_for_archive`]).
+    pub fn scrub_for_sharing(&self, strip_locations: bool) -> Result<()> {
+        self.conn.execute_batch(
+            "DELETE FROM outputs;
+             UPDATE executions SET env = NULL;",
+        )?;
+        if strip_locations {
+            self.conn.execute_batch(
+                "UPDATE commands SET working_dir = '';
+                 UPDATE executions SET host = NULL, git_repo = NULL;",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Strip a copy of the database down to shareable history
+    ///
+    /// Deletes commands for which `should_drop` returns true along with
+    /// capture bookkeeping, then vacuums so nothing deleted survives in free
+    /// pages. Only meant for archive copies; returns the number of commands
+    /// deleted.
+    pub fn prune_for_archive<F>(&self, should_drop: F) -> Result<usize>
+    where
+        F: Fn(&CommandRecord) -> bool,
+    {
+        let deleted = self.with_tx(|| {
+            let mut delete = self
+                .conn
+                .prepare_cached("DELETE FROM commands WHERE id = ?1")?;
+            let mut deleted = 0;
+            for record in self.get_all()? {
+                if should_drop(&record) {
+                    deleted += delete.execute(params![record.id])?;
+                }
+            }
+            self.conn.execute("DELETE FROM capture_skips", [])?;
+            Ok(deleted)
+        })?;
+
+        self.conn.execute_batch(
+            "INSERT INTO commands_fts(commands_fts) VALUES('rebuild');
+             PRAGMA journal_mode=DELETE;
+             VACUUM;",
+        )?;
+
+        Ok(deleted)
+    }
+
+    /// Drop the old contents of rewritten and deleted rows from disk
+    ///
+    /// SQLite leaves them in free pages and the write-ahead log, and the
+    /// search index keeps the words of old command text. This rebuilds the
+    /// index, compacts the file and empties the log, so redacted text can't
+    /// be read back from the database (copies made before still can).
+    pub fn purge_freed_data(&self) -> Result<()> {
+        self.conn
+            .execute_batch(
+                "INSERT INTO commands_fts(commands_fts) VALUES('rebuild');
+                 PRAGMA wal_checkpoint(TRUNCATE);
+                 VACUUM;
+                 PRAGMA wal_checkpoint(TRUNCATE);",
+            )
+            .context("purge rewritten data")?;
+
+        Ok(())
+    }
+
+    /// Size of the database in bytes (page count × page size)
+    fn database_size(&self) -> Result<u64> {
+        let page_count: u64 = self
+            .conn
+            .query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let page_size: u64 = self
+            .conn
+            .query_row("PRAGMA page_size", [], |row| row.get(0))?;
+
+        Ok(page_count * page_size)
+    }
+
+    /// Get total number of commands
+    pub fn count(&self) -> Result<usize> {
+        let count: usize = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM commands", [], |row| row.get(0))?;
+        Ok(count)
+    }
+}
+
+/// A SQLite value as JSON; blobs become their size
+fn json_value(value: ValueRef) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(n) => n.into(),
+        ValueRef::Real(x) => x.into(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into(),
+        ValueRef::Blob(blob) => format!("<{} bytes>", blob.len()).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use tempfile::NamedTempFile;
+
+    fn create_test_storage() -> Storage {
+        let temp_file = NamedTempFile::new().unwrap();
+        Storage::new(temp_file.path()).unwrap()
+    }
+
+    fn create_test_command(command: &str, category: &str, exit_code: i32) -> CommandRecord {
+        CommandRecord::new(
+            command.to_string(),
+            Utc::now(),
+            exit_code,
+            100,
+            "/tmp".to_string(),
+            category.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_storage_creation() {
+        let storage = create_test_storage();
+        assert_eq!(storage.count().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_insert_command() {
+        let storage = create_test_storage();
+        let cmd = create_test_command("git status", "git", 0);
+
+        let id = storage.insert(&cmd).unwrap();
+        assert!(id > 0);
+        assert_eq!(storage.count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_insert_batch() {
+        let storage = create_test_storage();
+        let cmds = vec![
+            create_test_command("git status", "git", 0),
+            create_test_command("docker ps", "docker", 0),
+            create_test_command("ls -la", "file", 1),
+        ];
+
+        let ids = storage.insert_batch(&cmds).unwrap();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(storage.count().unwrap(), 3);
+
+        let last = storage.get_by_id(ids[2]).unwrap().unwrap();
+        assert_eq!(last.command, "ls -la");
+        assert_eq!(last.exit_code, 1);
+
+        // Inserted records are searchable through the FTS triggers
+        let query = SearchQuery {
+            text: Some("docker".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(storage.search(&query).unwrap().len(), 1);
+
+        assert!(storage.insert_batch(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_find_duplicate() {
+        let storage = create_test_storage();
+        let cmd = create_test_command("git status", "git", 0);
+
+        storage.insert(&cmd).unwrap();
+
+        let duplicate = storage.find_duplicate("git status", "/tmp").unwrap();
+        assert!(duplicate.is_some());
+        assert_eq!(duplicate.unwrap().command, "git status");
+
+        let not_found = storage.find_duplicate("git commit", "/tmp").unwrap();
+        assert!(not_found.is_none());
+    }
+
+    #[test]
+    fn test_find_duplicate_by_key() {
+        let storage = create_test_storage();
+        storage
+            .insert(&create_test_command("terraform apply", "cloud", 1))
+            .unwrap();
+
+        let find = |key, dir, exit_code| {
+            storage
+                .find_duplicate_by(key, "terraform apply", dir, exit_code)
+                .unwrap()
+        };
+
+        assert!(find(DuplicateKey::CommandAndDir, "/tmp", 0).is_some());
+        assert!(find(DuplicateKey::CommandAndDir, "/other", 1).is_none());
+
+        assert!(find(DuplicateKey::CommandDirAndExitCode, "/tmp", 1).is_some());
+        assert!(find(DuplicateKey::CommandDirAndExitCode, "/tmp", 0).is_none());
+
+        assert!(find(DuplicateKey::Command, "/other", 0).is_some());
+    }
+
+    #[test]
+    fn test_increment_usage() {
+        let storage = create_test_storage();
+        let cmd = create_test_command("ls", "file", 0);
+
+        let id = storage.insert(&cmd).unwrap();
+        storage.increment_usage(id, cmd.last_used).unwrap();
+
+        let records = storage.get_all().unwrap();
+        assert_eq!(records[0].usage_count, 2);
+    }
+
+    #[test]
+    fn test_increment_usage_keeps_latest_last_used() {
+        let storage = create_test_storage();
+        let cmd = create_test_command("ls", "file", 0);
+        let id = storage.insert(&cmd).unwrap();
+
+        let later = cmd.last_used + chrono::Duration::hours(2);
+        storage.increment_usage(id, later).unwrap();
+        assert_eq!(storage.get_by_id(id).unwrap().unwrap().last_used, later);
+
+        // An older run replayed afterwards doesn't move it back
+        storage
+            .increment_usage(id, cmd.last_used - chrono::Duration::days(1))
+            .unwrap();
+        let record = storage.get_by_id(id).unwrap().unwrap();
+        assert_eq!(record.last_used, later);
+        assert_eq!(record.usage_count, 3);
+    }
+
+    #[test]
+    fn test_set_usage_count() {
+        let storage = create_test_storage();
+        let id = storage
+            .insert(&create_test_command("git status", "git", 0))
+            .unwrap();
+
+        assert!(storage.set_usage_count(id, 42).unwrap());
+        assert_eq!(storage.get_by_id(id).unwrap().unwrap().usage_count, 42);
+        assert!(!storage.set_usage_count(id + 1, 1).unwrap());
+    }
+
+    #[test]
+    fn test_update_record() {
+        let storage = create_test_storage();
+        let id = storage
+            .insert(&create_test_command("git status", "git", 0))
+            .unwrap();
+
+        let mut record = storage.get_by_id(id).unwrap().unwrap();
+        record.command = "git status -sb".to_string();
+        record.exit_code = 1;
+        record.usage_count = 9;
+        record.note = Some("short format".to_string());
+        assert!(storage.update_record(&record).unwrap());
+
+        assert_eq!(storage.get_by_id(id).unwrap().unwrap(), record);
+
+        // The search index follows the new command text
+        let query = SearchQuery {
+            text: Some("sb".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(storage.search(&query).unwrap().len(), 1);
+
+        record.id = None;
+        assert!(storage.update_record(&record).is_err());
+    }
+
+    #[test]
+    fn test_skip_counts() {
+        let storage = create_test_storage();
+        assert!(storage.get_skip_counts().unwrap().is_empty());
+
+        storage.record_skip(SkipReason::TooFast).unwrap();
+        storage.record_skip(SkipReason::Redacted).unwrap();
+        storage.record_skip(SkipReason::TooFast).unwrap();
+
+        let counts = storage.get_skip_counts().unwrap();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[0].reason, SkipReason::TooFast);
+        assert_eq!(counts[0].count, 2);
+        assert_eq!(counts[1].reason, SkipReason::Redacted);
+        assert_eq!(counts[1].count, 1);
+    }
+
+    #[test]
+    fn test_backup_to_and_prune_for_archive() {
+        let storage = create_test_storage();
+        storage
+            .insert(&create_test_command("git status", "git", 0))
+            .unwrap();
+        storage
+            .insert(&create_test_command("export TOKEN=abc", "other", 0))
+            .unwrap();
+        storage.record_skip(SkipReason::TooFast).unwrap();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let copy_path = dir.path().join("copy.db");
+        storage.backup_to(&copy_path).unwrap();
+
+        let copy = Storage::new(&copy_path).unwrap();
+        assert_eq!(copy.count().unwrap(), 2);
+
+        let deleted = copy
+            .prune_for_archive(|record| record.command.contains("TOKEN"))
+            .unwrap();
+        assert_eq!(deleted, 1);
+        assert_eq!(copy.count().unwrap(), 1);
+        assert!(copy.get_skip_counts().unwrap().is_empty());
+        assert_eq!(copy.get_stats().unwrap().total_commands, 1);
+
+        // The original is untouched
+        assert_eq!(storage.count().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_search_with_dir_glob() {
+        let storage = create_test_storage();
+        for dir in [
+            "/work/billing/api",
+            "/work/search/api",
+            "/work/search/api/handlers",
+            "/work/search/web",
+        ] {
+            let mut cmd = create_test_command("cargo test", "rust", 0);
+            cmd.working_dir = dir.to_string();
+            storage.insert(&cmd).unwrap();
+        }
+
+        let dirs = |dir: &str, recursive: bool| -> Vec<String> {
+            let query = SearchQuery {
+                working_dir: Some(dir.to_string()),
+                recursive,
+                ..Default::default()
+            };
+            let mut dirs: Vec<String> = storage
+                .search(&query)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.working_dir)
+                .collect();
+            dirs.sort();
+            dirs
+        };
+
+        assert_eq!(
+            dirs("/work/*/api", false),
+            vec!["/work/billing/api", "/work/search/api"]
+        );
+        assert_eq!(dirs("/work/*/api", true).len(), 3);
+        assert_eq!(dirs("/work/search/[aw]*", false).len(), 3);
+
+        // Plain paths keep exact matching
+        assert_eq!(dirs("/work/search/api", false), vec!["/work/search/api"]);
+    }
+
+    #[test]
+    fn test_search_by_category() {
+        let storage = create_test_storage();
+
+        storage
+            .insert(&create_test_command("git status", "git", 0))
+            .unwrap();
+        storage
+            .insert(&create_test_command("git commit", "git", 0))
+            .unwrap();
+        storage
+            .insert(&create_test_command("docker ps", "docker", 0))
+            .unwrap();
+
+        let git_commands = storage.get_by_category("git", 10, None, false).unwrap();
+        assert_eq!(git_commands.len(), 2);
+    }
+
+    #[test]
+    fn test_sanitize_fts5_query_simple() {
+        let result = Storage::sanitize_fts5_query("hello world");
+        assert_eq!(result, "\"hello world\"");
+    }
+
+    #[test]
+    fn test_sanitize_fts5_query_with_dots() {
+        let result = Storage::sanitize_fts5_query("10.104.113.39");
+        assert_eq!(result, "\"10.104.113.39\"");
+    }
+
+    #[test]
+    fn test_sanitize_fts5_query_with_quotes() {
+        let result = Storage::sanitize_fts5_query("grep \"pattern\"");
+        assert_eq!(result, "\"grep \"\"pattern\"\"\"");
+    }
+
+    #[test]
+    fn test_sanitize_fts5_query_with_asterisk() {
+        let result = Storage::sanitize_fts5_query("ls *.txt");
+        assert_eq!(result, "\"ls *.txt\"");
+    }
+
+    #[test]
+    fn test_sanitize_fts5_query_url() {
+        let result = Storage::sanitize_fts5_query("https://example.com");
+        assert_eq!(result, "\"https://example.com\"");
+    }
+
+    #[test]
+    fn test_fts5_prefix_query() {
+        assert_eq!(
+            Storage::fts5_prefix_query("git  chec"),
+            "\"git\"* \"chec\"*"
+        );
+        assert_eq!(Storage::fts5_prefix_query("say\"hi"), "\"say\"\"hi\"*");
+        assert_eq!(Storage::fts5_prefix_query(" "), "\" \"");
+    }
+
+    #[test]
+    fn test_search_prefix_and_ignore_case() {
+        let storage = create_test_storage();
+        for command in ["git checkout main", "docker-compose up", "make DeployProd"] {
+            storage
+                .insert(&create_test_command(command, "other", 0))
+                .unwrap();
+        }
+
+        let search = |text: &str, prefix: bool, ignore_case: bool| {
+            let query = SearchQuery {
+                text: Some(text.to_string()),
+                prefix,
+                ignore_case,
+                limit: 10,
+                ..Default::default()
+            };
+            storage
+                .search(&query)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.command)
+                .collect::<Vec<_>>()
+        };
+
+        // Whole tokens only by default
+        assert!(search("git chec", false, false).is_empty());
+        assert_eq!(search("git chec", true, false), vec!["git checkout main"]);
+        assert_eq!(search("GIT CHEC", true, false), vec!["git checkout main"]);
+
+        // Anywhere in the command, in any case
+        assert!(search("deploy", false, false).is_empty());
+        assert_eq!(search("deploy", false, true), vec!["make DeployProd"]);
+        assert_eq!(search("COMPOSE U", false, true), vec!["docker-compose up"]);
+    }
+
+    #[test]
+    fn test_search_offset() {
+        let storage = create_test_storage();
+        for (index, command) in ["one", "two", "three"].iter().enumerate() {
+            let mut record = create_test_command(command, "other", 0);
+            record.usage_count = 3 - index as i32;
+            storage.insert(&record).unwrap();
+        }
+
+        let page = |offset: usize| {
+            let query = SearchQuery {
+                limit: 2,
+                offset,
+                order_by: OrderBy::UsageCount,
+                ..Default::default()
+            };
+            storage
+                .search(&query)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.command)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(page(0), vec!["one", "two"]);
+        assert_eq!(page(2), vec!["three"]);
+        assert!(page(4).is_empty());
+    }
+
+    #[test]
+    fn test_search_excludes() {
+        let storage = create_test_storage();
+        storage
+            .insert(&create_test_command("docker ps", "docker", 0))
+            .unwrap();
+        storage
+            .insert(&create_test_command("docker-compose up", "docker", 0))
+            .unwrap();
+        storage
+            .insert(&create_test_command("docker logs api", "logs", 0))
+            .unwrap();
+
+        let search = |exclude: &[&str], exclude_categories: &[&str]| {
+            let query = SearchQuery {
+                text: Some("docker".to_string()),
+                exclude: exclude.iter().map(|s| s.to_string()).collect(),
+                exclude_categories: exclude_categories.iter().map(|s| s.to_string()).collect(),
+                limit: 10,
+                ..Default::default()
+            };
+            let mut commands: Vec<String> = storage
+                .search(&query)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.command)
+                .collect();
+            commands.sort();
+            commands
+        };
+
+        assert_eq!(search(&[], &[]).len(), 3);
+        assert_eq!(
+            search(&["COMPOSE"], &[]),
+            vec!["docker logs api", "docker ps"]
+        );
+        assert_eq!(search(&["compose", "logs"], &[]), vec!["docker ps"]);
+        assert_eq!(
+            search(&[], &["logs"]),
+            vec!["docker ps", "docker-compose up"]
+        );
+    }
+
+    #[test]
+    fn test_search_by_duration() {
+        let storage = create_test_storage();
+        for (command, duration_ms) in [("ls", 5), ("cargo build", 2_000), ("make test", 90_000)] {
+            let mut record = create_test_command(command, "build", 0);
+            record.duration_ms = duration_ms;
+            storage.insert(&record).unwrap();
+        }
+
+        let search = |text: Option<&str>, min: Option<i64>, max: Option<i64>| {
+            let query = SearchQuery {
+                text: text.map(str::to_string),
+                ignore_case: text.is_some(),
+                min_duration_ms: min,
+                max_duration_ms: max,
+                limit: 10,
+                ..Default::default()
+            };
+            let mut commands: Vec<String> = storage
+                .search(&query)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.command)
+                .collect();
+            commands.sort();
+            commands
+        };
+
+        assert_eq!(
+            search(None, Some(1_000), None),
+            vec!["cargo build", "make test"]
+        );
+        assert_eq!(search(None, None, Some(2_000)), vec!["cargo build", "ls"]);
+        assert_eq!(search(None, Some(100), Some(60_000)), vec!["cargo build"]);
+        assert_eq!(search(Some("t"), Some(60_000), None), vec!["make test"]);
+    }
+
+    #[test]
+    fn test_search_by_program_and_flags() {
+        let storage = create_test_storage();
+        for command in [
+            "kubectl get pods -n prod",
+            "cd deploy && kubectl apply -f api.yaml --namespace=prod",
+            "kubectl get pods -n staging",
+            "echo kubectl -n prod",
+        ] {
+            storage
+                .insert(&create_test_command(command, "kubernetes", 0))
+                .unwrap();
+        }
+
+        let search = |program: Option<&str>, flags: &[&str], text: Option<&str>| {
+            let query = SearchQuery {
+                text: text.map(str::to_string),
+                program: program.map(str::to_string),
+                flags: flags.iter().map(|f| f.to_string()).collect(),
+                order_by: OrderBy::Timestamp,
+                ..Default::default()
+            };
+            let mut commands: Vec<String> = storage
+                .search(&query)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.command)
+                .collect();
+            commands.sort();
+            commands
+        };
+
+        assert_eq!(search(Some("kubectl"), &[], None).len(), 3);
+        assert_eq!(
+            search(Some("kubectl"), &["-n prod"], None),
+            vec!["kubectl get pods -n prod"]
+        );
+        assert_eq!(
+            search(None, &["--namespace prod"], None),
+            vec!["cd deploy && kubectl apply -f api.yaml --namespace=prod"]
+        );
+        assert_eq!(search(Some("kubectl"), &["-n", "-f"], None).len(), 0);
+        assert_eq!(
+            search(Some("kubectl"), &["-n"], Some("staging")),
+            vec!["kubectl get pods -n staging"]
+        );
+
+        // Rewritten commands are parsed again
+        let mut record = storage.search(&SearchQuery::default()).unwrap()[0].clone();
+        record.command = "helm upgrade api -n prod".to_string();
+        storage.update_record(&record).unwrap();
+        assert_eq!(search(Some("helm"), &["-n prod"], None).len(), 1);
+    }
+
+    #[test]
+    fn test_get_alias_candidates() {
+        let storage = create_test_storage();
+        for (command, dir, uses) in [
+            ("docker compose up -d", "/a", 3),
+            ("docker compose up -d", "/b", 4),
+            ("git status", "/a", 50),
+            ("kubectl get pods -A", "/a", 2),
+        ] {
+            let mut record = create_test_command(command, "other", 0);
+            record.working_dir = dir.to_string();
+            record.usage_count = uses;
+            storage.insert(&record).unwrap();
+        }
+        let mut aliased = create_test_command("git log --oneline --graph", "git", 0);
+        aliased.usage_count = 20;
+        aliased.typed_command = Some("glog".to_string());
+        storage.insert(&aliased).unwrap();
+
+        assert_eq!(
+            storage.get_alias_candidates(15, 5).unwrap(),
+            vec![("docker compose up -d".to_string(), 7)]
+        );
+        assert_eq!(storage.get_alias_candidates(15, 1).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_search_by_exit_code() {
+        let storage = create_test_storage();
+        storage
+            .insert(&create_test_command("make test", "build", 0))
+            .unwrap();
+        storage
+            .insert(&create_test_command("cargo build", "build", 137))
+            .unwrap();
+        storage
+            .insert(&create_test_command("sleep 100", "system", 130))
+            .unwrap();
+        // Killed once, succeeded on the latest run
+        storage
+            .insert(&create_test_command("make test", "build", 137))
+            .unwrap();
+        storage
+            .insert(&create_test_command("make test", "build", 0))
+            .unwrap();
+
+        let search = |exit_code: i32| {
+            let query = SearchQuery {
+                exit_code: Some(exit_code),
+                limit: 10,
+                ..Default::default()
+            };
+            let mut commands: Vec<String> = storage
+                .search(&query)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.command)
+                .collect();
+            commands.sort();
+            commands
+        };
+
+        assert_eq!(search(137), vec!["cargo build", "make test"]);
+        assert_eq!(search(130), vec!["sleep 100"]);
+        assert!(search(1).is_empty());
+    }
+
+    #[test]
+    fn test_search_with_ip_address() {
+        let storage = create_test_storage();
+
+        // Insert a command with an IP address
+        let record = create_test_command("ssh user@10.104.113.39", "network", 0);
+        storage.insert(&record).unwrap();
+
+        // Search for the IP address
+        let query = SearchQuery {
+            text: Some("10.104.113.39".to_string()),
+            category: None,
+            success_only: None,
+            working_dir: None,
+            recursive: false,
+            limit: 10,
+            order_by: OrderBy::Relevance,
+            ..Default::default()
+        };
+
+        let results = storage.search(&query).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].command.contains("10.104.113.39"));
+    }
+
+    #[test]
+    fn test_search_with_url() {
+        let storage = create_test_storage();
+
+        let record = create_test_command("curl https://api.github.com/users/daneb", "network", 0);
+        storage.insert(&record).unwrap();
+
+        let query = SearchQuery {
+            text: Some("api.github.com".to_string()),
+            category: None,
+            success_only: None,
+            working_dir: None,
+            recursive: false,
+            limit: 10,
+            order_by: OrderBy::Relevance,
+            ..Default::default()
+        };
+
+        let results = storage.search(&query).unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_search_with_file_path() {
+        let storage = create_test_storage();
+
+        let record = create_test_command("cat ./config/settings.yaml", "file", 0);
+        storage.insert(&record).unwrap();
+
+        let query = SearchQuery {
+            text: Some("./config/settings.yaml".to_string()),
+            category: None,
+            success_only: None,
+            working_dir: None,
+            recursive: false,
+            limit: 10,
+            order_by: OrderBy::Relevance,
+            ..Default::default()
+        };
+
+        let results = storage.search(&query).unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_search_with_multiple_special_chars() {
+        let storage = create_test_storage();
+
+        let record = create_test_command("scp file.txt user@host.com:/path/to/dest", "network", 0);
+        storage.insert(&record).unwrap();
+
+        let query = SearchQuery {
+            text: Some("user@host.com".to_string()),
+            category: None,
+            success_only: None,
+            working_dir: None,
+            recursive: false,
+            limit: 10,
+            order_by: OrderBy::Relevance,
+            ..Default::default()
+        };
+
+        let results = storage.search(&query).unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_search_empty_query_still_works() {
+        let storage = create_test_storage();
+
+        let record = create_test_command("ls -la", "file", 0);
+        storage.insert(&record).unwrap();
+
+        // Search without text (should use other filters)
+        let query = SearchQuery {
+            text: None,
+            category: Some("file".to_string()),
+            success_only: None,
+            working_dir: None,
+            recursive: false,
+            limit: 10,
+            order_by: OrderBy::Timestamp,
+            ..Default::default()
+        };
+
+        let results = storage.search(&query).unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_get_stats() {
+        let storage = create_test_storage();
+
+        storage
+            .insert(&create_test_command("success1", "git", 0))
+            .unwrap();
+        storage
+            .insert(&create_test_command("success2", "docker", 0))
+            .unwrap();
+        let mut failure = create_test_command("failure", "git", 1);
+        failure.duration_ms = 300;
+        failure.last_used = Utc::now() + chrono::Duration::hours(1);
+        let failure_id = storage.insert(&failure).unwrap();
+        storage
+            .log_execution(failure_id, Utc::now(), 1, 300, &ExecutionMeta::default())
+            .unwrap();
+
+        let stats = storage.get_stats().unwrap();
+        assert_eq!(stats.total_commands, 3);
+        assert_eq!(stats.successful_commands, 2);
+        assert_eq!(stats.failed_commands, 1);
+        assert_eq!(stats.success_rate(), 66.66666666666666);
+
+        let git = &stats.by_category[0];
+        assert_eq!(git.category, "git");
+        assert_eq!((git.count, git.successful), (2, 1));
+        assert_eq!(git.success_rate, 50.0);
+        assert_eq!(git.avg_duration_ms, 200);
+        assert_eq!(git.last_used, Some(failure.last_used));
+
+        assert!(stats.daily.is_empty());
+        let stats = storage.get_stats_with_trends().unwrap();
+        assert_eq!(stats.daily.len(), 1);
+        assert_eq!(stats.daily[0].start, Utc::now().date_naive());
+        assert_eq!(stats.daily[0].executions, 1);
+    }
+
+    #[test]
+    fn test_stats_counters_follow_updates_and_deletes() {
+        let storage = create_test_storage();
+        let git_id = storage
+            .insert(&create_test_command("git push", "git", 1))
+            .unwrap();
+        let docker_id = storage
+            .insert(&create_test_command("docker ps", "docker", 0))
+            .unwrap();
+        storage
+            .insert(&create_test_command("git status", "git", 0))
+            .unwrap();
+
+        // Fix up the failed push: now successful and recategorized
+        let mut record = storage.get_by_id(git_id).unwrap().unwrap();
+        record.exit_code = 0;
+        record.category = "network".to_string();
+        storage.update_record(&record).unwrap();
+
+        storage.delete(docker_id).unwrap();
+
+        let stats = storage.get_stats().unwrap();
+        assert_eq!(stats.total_commands, 2);
+        assert_eq!(stats.successful_commands, 2);
+        assert_eq!(stats.failed_commands, 0);
+
+        let categories: Vec<(String, usize)> = stats
+            .by_category
+            .iter()
+            .map(|c| (c.category.clone(), c.count))
+            .collect();
+        assert_eq!(
+            categories,
+            vec![("git".to_string(), 1), ("network".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_stats_category_duration_and_last_use_follow_changes() {
+        let storage = create_test_storage();
+        let now = Utc::now();
+        let insert = |command: &str, duration_ms: i64, hours_ago: i64| {
+            let mut record = create_test_command(command, "git", 0);
+            record.duration_ms = duration_ms;
+            record.last_used = now - chrono::Duration::hours(hours_ago);
+            storage.insert(&record).unwrap()
+        };
+        let newest = insert("git push", 400, 1);
+        insert("git status", 100, 5);
+        let oldest = insert("git log", 100, 9);
+
+        let git = || storage.get_stats().unwrap().by_category[0].clone();
+        assert_eq!(git().avg_duration_ms, 200);
+        assert_eq!(git().last_used, Some(now - chrono::Duration::hours(1)));
+
+        // A rerun moves the latest use forward
+        storage.increment_usage(oldest, now).unwrap();
+        assert_eq!(git().last_used, Some(now));
+
+        // Removing or recategorizing the newest falls back to the next one
+        storage.delete(oldest).unwrap();
+        assert_eq!(git().last_used, Some(now - chrono::Duration::hours(1)));
+        assert_eq!(git().avg_duration_ms, 250);
+
+        let mut record = storage.get_by_id(newest).unwrap().unwrap();
+        record.category = "network".to_string();
+        storage.update_record(&record).unwrap();
+        assert_eq!(git().last_used, Some(now - chrono::Duration::hours(5)));
+        assert_eq!(git().avg_duration_ms, 100);
+
+        // The same totals come out of a full recount
+        storage.maintain().unwrap();
+        let git_after = git();
+        assert_eq!(git_after.avg_duration_ms, 100);
+        assert_eq!(git_after.last_used, Some(now - chrono::Duration::hours(5)));
+    }
+
+    #[test]
+    fn test_open_database_without_typed_command_column() {
+        let temp_file = NamedTempFile::new().unwrap();
+        {
+            let conn = Connection::open(temp_file.path()).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE commands (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    command TEXT NOT NULL,
+                    timestamp TEXT NOT NULL,
+                    exit_code INTEGER NOT NULL,
+                    duration_ms INTEGER NOT NULL,
+                    working_dir TEXT NOT NULL,
+                    category TEXT NOT NULL,
+                    usage_count INTEGER NOT NULL DEFAULT 1,
+                    last_used TEXT NOT NULL
+                );",
+            )
+            .unwrap();
+        }
+
+        let storage = Storage::new(temp_file.path()).unwrap();
+        let mut cmd = create_test_command("git status", "git", 0);
+        cmd.typed_command = Some("gst".to_string());
+        storage.insert(&cmd).unwrap();
+
+        let records = storage.get_all().unwrap();
+        assert_eq!(records[0].typed_command.as_deref(), Some("gst"));
+    }
+
+    #[test]
+    fn test_outputs() {
+        let storage = create_test_storage();
+        let id = storage
+            .insert(&create_test_command("cargo test", "rust", 0))
+            .unwrap();
+        assert_eq!(storage.get_latest_output(id).unwrap(), None);
+
+        let meta = ExecutionMeta::default();
+        let first = storage.log_execution(id, Utc::now(), 1, 10, &meta).unwrap();
+        storage.save_output(first, "test failed", false).unwrap();
+        let second = storage.log_execution(id, Utc::now(), 0, 10, &meta).unwrap();
+        storage.save_output(second, "ok", true).unwrap();
+        // An execution without output doesn't hide the last one that had some
+        storage.log_execution(id, Utc::now(), 0, 10, &meta).unwrap();
+
+        let output = storage.get_latest_output(id).unwrap().unwrap();
+        assert_eq!(output.execution_id, second);
+        assert_eq!(output.text, "ok");
+        assert!(output.truncated);
+
+        // Outputs go away with their command
+        storage.delete(id).unwrap();
+        let remaining: i64 = storage
+            .conn
+            .query_row("SELECT COUNT(*) FROM outputs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+
+    #[test]
+    fn test_get_detail() {
+        let storage = create_test_storage();
+        let id = storage
+            .insert(&create_test_command("cargo build", "rust", 0))
+            .unwrap();
+        assert_eq!(storage.get_detail(id + 1, 10).unwrap(), None);
+
+        let meta = ExecutionMeta {
+            branch: Some("main".to_string()),
+            ..Default::default()
+        };
+        let now = Utc::now();
+        storage.log_execution(id, now, 0, 1200, &meta).unwrap();
+        storage
+            .log_execution(id, now + chrono::Duration::seconds(5), 101, 300, &meta)
+            .unwrap();
+        storage
+            .log_execution(id, now + chrono::Duration::seconds(9), 0, 900, &meta)
+            .unwrap();
+        storage.add_bookmark(id).unwrap();
+
+        let detail = storage.get_detail(id, 2).unwrap().unwrap();
+        assert_eq!(detail.record.command, "cargo build");
+        assert!(detail.bookmarked);
+        assert_eq!(detail.execution_count, 3);
+        assert_eq!(detail.executions.len(), 2);
+        assert_eq!(detail.executions[0].duration_ms, 900);
+        assert_eq!(detail.executions[1].exit_code, 101);
+        assert_eq!(detail.executions[1].meta.branch.as_deref(), Some("main"));
+        assert_eq!(detail.output, None);
+    }
+
+    #[test]
+    fn test_get_command_history() {
+        let storage = create_test_storage();
+        let build = storage
+            .insert(&create_test_command("cargo build", "rust", 0))
+            .unwrap();
+        let release = storage
+            .insert(&create_test_command("cargo build --release", "rust", 0))
+            .unwrap();
+        let test = storage
+            .insert(&create_test_command("cargo test", "rust", 0))
+            .unwrap();
+
+        let meta = ExecutionMeta::default();
+        let now = Utc::now();
+        for (offset, id, exit_code) in [
+            (0, build, 0),
+            (1, release, 101),
+            (2, build, 0),
+            (3, test, 0),
+        ] {
+            storage
+                .log_execution(
+                    id,
+                    now + chrono::Duration::seconds(offset),
+                    exit_code,
+                    100,
+                    &meta,
+                )
+                .unwrap();
+        }
+
+        let history = storage.get_command_history("cargo build", 10).unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(history[0].timestamp < history[1].timestamp);
+        assert!(history.iter().all(|e| e.command == "cargo build"));
+
+        let history = storage.get_command_history("cargo build*", 10).unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[1].exit_code, 101);
+
+        // The most recent runs, still oldest first
+        let history = storage.get_command_history("cargo *", 2).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].command, "cargo build");
+        assert_eq!(history[1].command, "cargo test");
+
+        assert!(storage.get_command_history("make", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_promote_copy() {
+        let storage = create_test_storage();
+        let mut cmd = create_test_command("make deploy", "build", 0);
+        cmd.usage_count = 7;
+        let id = storage.insert(&cmd).unwrap();
+
+        let new_id = storage
+            .promote(id, "/projects/copy", false, DuplicateKey::CommandAndDir)
+            .unwrap()
+            .unwrap();
+        assert_ne!(new_id, id);
+
+        let copy = storage.get_by_id(new_id).unwrap().unwrap();
+        assert_eq!(copy.working_dir, "/projects/copy");
+        assert_eq!(copy.usage_count, 7);
+        assert!(storage.get_by_id(id).unwrap().is_some());
+
+        // Copying again reuses the existing record
+        assert_eq!(
+            storage
+                .promote(id, "/projects/copy", false, DuplicateKey::CommandAndDir)
+                .unwrap(),
+            Some(new_id)
+        );
+        assert_eq!(storage.count().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_promote_move_merges() {
+        let storage = create_test_storage();
+        let id = storage
+            .insert(&create_test_command("make deploy", "build", 0))
+            .unwrap();
+        storage
+            .log_execution(id, Utc::now(), 0, 10, &ExecutionMeta::default())
+            .unwrap();
+        storage.add_bookmark(id).unwrap();
+
+        let mut existing = create_test_command("make deploy", "build", 0);
+        existing.working_dir = "/projects/new".to_string();
+        existing.usage_count = 3;
+        let target = storage.insert(&existing).unwrap();
+
+        let moved = storage
+            .promote(id, "/projects/new", true, DuplicateKey::CommandAndDir)
+            .unwrap();
+        assert_eq!(moved, Some(target));
+        assert!(storage.get_by_id(id).unwrap().is_none());
+
+        let merged = storage.get_by_id(target).unwrap().unwrap();
+        assert_eq!(merged.usage_count, 4);
+        assert!(storage.is_bookmarked(target).unwrap());
+        assert_eq!(
+            storage.get_executions(None, false).unwrap()[0].command_id,
+            target
+        );
+    }
+
+    #[test]
+    fn test_promote_respects_duplicate_key() {
+        let storage = create_test_storage();
+        let mut failed = create_test_command("make deploy", "build", 0);
+        failed.exit_code = 2;
+        let id = storage.insert(&failed).unwrap();
+        let mut passed = create_test_command("make deploy", "build", 0);
+        passed.working_dir = "/projects/new".to_string();
+        let target = storage.insert(&passed).unwrap();
+
+        // Failed and successful runs stay apart
+        let moved = storage
+            .promote(
+                id,
+                "/projects/new",
+                true,
+                DuplicateKey::CommandDirAndExitCode,
+            )
+            .unwrap()
+            .unwrap();
+        assert_ne!(moved, target);
+        assert_eq!(storage.get_by_id(moved).unwrap().unwrap().exit_code, 2);
+        assert_eq!(storage.count().unwrap(), 2);
+
+        // Without directories in the key, moving only changes the directory
+        let only = create_test_storage();
+        let id = only
+            .insert(&create_test_command("make deploy", "build", 0))
+            .unwrap();
+        assert_eq!(
+            only.promote(id, "/projects/new", true, DuplicateKey::Command)
+                .unwrap(),
+            Some(id)
+        );
+        assert_eq!(
+            only.get_by_id(id).unwrap().unwrap().working_dir,
+            "/projects/new"
+        );
+    }
+
+    #[test]
+    fn test_promote_missing() {
+        let storage = create_test_storage();
+        assert!(storage
+            .promote(42, "/anywhere", false, DuplicateKey::CommandAndDir)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_maintain_reclaims_space() {
+        let storage = create_test_storage();
+
+        let ids: Vec<i64> = (0..500)
+            .map(|i| {
+                let cmd =
+                    create_test_command(&format!("echo {} {}", i, "x".repeat(200)), "other", 0);
+                storage.insert(&cmd).unwrap()
+            })
+            .collect();
+        for id in ids {
+            storage.delete(id).unwrap();
+        }
+
+        let report = storage.maintain().unwrap();
+        assert!(report.is_healthy());
+        assert!(report.reclaimed_bytes() > 0);
+        assert!(report.size_after < report.size_before);
+    }
+
+    #[test]
+    fn test_maintain_keeps_search_working() {
+        let storage = create_test_storage();
+        storage
+            .insert(&create_test_command("git status", "git", 0))
+            .unwrap();
+
+        storage.maintain().unwrap();
+
+        let query = SearchQuery {
+            text: Some("status".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(storage.search(&query).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_query_is_read_only() {
+        let storage = create_test_storage();
+        for command in ["git status", "git push", "ls"] {
+            storage
+                .insert(&create_test_command(command, "git", 0))
+                .unwrap();
+        }
+
+        let result = storage
+            .query(
+                "SELECT category, COUNT(*) AS n FROM commands GROUP BY category",
+                10,
+            )
+            .unwrap();
+        assert_eq!(result.columns, vec!["category", "n"]);
+        assert_eq!(
+            result.rows,
+            vec![vec![serde_json::json!("git"), serde_json::json!(3)]]
+        );
+        assert!(!result.truncated);
+
+        let result = storage.query("SELECT command FROM commands", 2).unwrap();
+        assert_eq!(result.rows.len(), 2);
+        assert!(result.truncated);
+
+        assert!(storage.query("DELETE FROM commands", 10).is_err());
+        assert!(storage
+            .query("WITH x AS (SELECT 1) DELETE FROM commands", 10)
+            .is_err());
+        assert!(storage.query("SELECT 1; DELETE FROM commands", 10).is_err());
+        assert_eq!(storage.count().unwrap(), 3);
+
+        // Writes work again afterwards
+        storage
+            .insert(&create_test_command("cargo test", "build", 0))
+            .unwrap();
+        assert_eq!(storage.count().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_check_health_finds_stale_search_index() {
+        let storage = create_test_storage();
+        storage
+            .insert(&create_test_command("git status", "git", 0))
+            .unwrap();
+
+        let report = storage.check_health().unwrap();
+        assert!(report.integrity_errors.is_empty());
+        assert_eq!(report.search_index_error, None);
+        assert!(report.last_capture.is_some());
+
+        // An index entry without a command behind it
+        storage
+            .conn
+            .execute(
+                "INSERT INTO commands_fts(rowid, command) VALUES (999, 'ghost')",
+                [],
+            )
+            .unwrap();
+        assert!(storage.check_health().unwrap().search_index_error.is_some());
+
+        storage.maintain().unwrap();
+        assert_eq!(storage.check_health().unwrap().search_index_error, None);
+    }
+
+    #[test]
+    fn test_set_note() {
+        let storage = create_test_storage();
+        let id = storage
+            .insert(&create_test_command(
+                "sudo systemctl restart nginx",
+                "system",
+                0,
+            ))
+            .unwrap();
+
+        assert!(storage
+            .set_note(id, Some("needed after cert renewal"))
+            .unwrap());
+        let record = storage.get_by_id(id).unwrap().unwrap();
+        assert_eq!(record.note.as_deref(), Some("needed after cert renewal"));
+
+        assert!(storage.set_note(id, None).unwrap());
+        assert!(storage.get_by_id(id).unwrap().unwrap().note.is_none());
+
+        assert!(!storage.set_note(9999, Some("missing")).unwrap());
+    }
+
+    #[test]
+    fn test_bookmarks() {
+        let storage = create_test_storage();
+        let id = storage
+            .insert(&create_test_command("git status", "git", 0))
+            .unwrap();
+
+        assert!(!storage.is_bookmarked(id).unwrap());
+        assert!(storage.add_bookmark(id).unwrap());
+        assert!(storage.add_bookmark(id).unwrap()); // idempotent
+        assert!(storage.is_bookmarked(id).unwrap());
+        assert!(!storage.add_bookmark(9999).unwrap());
+
+        let bookmarks = storage.get_bookmarks(10, 0).unwrap();
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].command, "git status");
+
+        assert!(storage.remove_bookmark(id).unwrap());
+        assert!(!storage.remove_bookmark(id).unwrap());
+        assert!(storage.get_bookmarks(10, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_bookmarks_pinned_in_relevance_search() {
+        let storage = create_test_storage();
+
+        let mut popular = create_test_command("git status", "git", 0);
+        popular.usage_count = 50;
+        storage.insert(&popular).unwrap();
+        let rare = storage
+            .insert(&create_test_command("git stash pop", "git", 0))
+            .unwrap();
+
+        let query = SearchQuery {
+            text: Some("git".to_string()),
+            order_by: OrderBy::Relevance,
+            ..Default::default()
+        };
+        assert_eq!(storage.search(&query).unwrap()[0].command, "git status");
+
+        storage.add_bookmark(rare).unwrap();
+        assert_eq!(storage.search(&query).unwrap()[0].command, "git stash pop");
+    }
+
+    #[test]
+    fn test_bookmarks_pinned_in_every_ordering() {
+        let storage = create_test_storage();
+
+        let mut popular = create_test_command("git status", "git", 0);
+        popular.usage_count = 50;
+        storage.insert(&popular).unwrap();
+        // Older and less used, so last in every ordering until bookmarked
+        let mut old = create_test_command("git stash pop", "git", 0);
+        old.timestamp -= chrono::Duration::days(1);
+        old.last_used = old.timestamp;
+        let rare = storage.insert(&old).unwrap();
+
+        let newest = SearchQuery::default();
+        assert_eq!(storage.search(&newest).unwrap()[0].command, "git status");
+
+        storage.add_bookmark(rare).unwrap();
+        for order_by in [OrderBy::Timestamp, OrderBy::LastUsed, OrderBy::UsageCount] {
+            let query = SearchQuery {
+                order_by,
+                ..Default::default()
+            };
+            assert_eq!(storage.search(&query).unwrap()[0].id, Some(rare));
+        }
+        assert_eq!(
+            storage.suggest("git st", 5, None, false).unwrap()[0].id,
+            Some(rare)
+        );
+        assert_eq!(storage.top_for_dir("/tmp", 5).unwrap()[0].id, Some(rare));
+    }
+
+    #[test]
+    fn test_query_cache_invalidated_by_writes() {
+        let storage = create_test_storage();
+        storage.enable_query_cache(8);
+
+        storage
+            .insert(&create_test_command("git status", "git", 0))
+            .unwrap();
+        assert_eq!(storage.get_recent(10, None, false).unwrap().len(), 1);
+        assert_eq!(storage.get_stats().unwrap().total_commands, 1);
+
+        storage
+            .insert(&create_test_command("git log", "git", 0))
+            .unwrap();
+        assert_eq!(storage.get_recent(10, None, false).unwrap().len(), 2);
+        assert_eq!(storage.get_stats().unwrap().total_commands, 2);
+    }
+
+    #[test]
+    fn test_top_for_dir() {
+        let storage = create_test_storage();
+        storage.enable_query_cache(8);
+
+        let mut records = vec![
+            create_test_command("cargo test", "package", 0),
+            create_test_command("cargo build", "package", 0),
+            create_test_command("carg build", "other", 127),
+            create_test_command("make", "package", 0),
+        ];
+        records[0].usage_count = 5;
+        records[1].usage_count = 9;
+        records[3].working_dir = "/elsewhere".to_string();
+        storage.insert_batch(&records).unwrap();
+
+        let top = storage.top_for_dir("/tmp", 3).unwrap();
+        let commands: Vec<&str> = top.iter().map(|r| r.command.as_str()).collect();
+        assert_eq!(commands, vec!["cargo build", "cargo test"]);
+        assert_eq!(storage.top_for_dir("/tmp", 1).unwrap().len(), 1);
+        assert!(storage.top_for_dir("/nowhere", 3).unwrap().is_empty());
+
+        storage
+            .insert(&create_test_command("ls", "filesystem", 0))
+            .unwrap();
+        assert_eq!(storage.top_for_dir("/tmp", 3).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_query_cache_sees_other_connections() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let reader = Storage::new(temp_file.path()).unwrap();
+        let writer = Storage::new(temp_file.path()).unwrap();
+        reader.enable_query_cache(8);
+
+        assert_eq!(reader.get_stats().unwrap().total_commands, 0);
+
+        writer
+            .insert(&create_test_command("docker ps", "docker", 0))
+            .unwrap();
+        assert_eq!(reader.get_stats().unwrap().total_commands, 1);
+    }
+
+    #[test]
+    fn test_log_and_get_executions() {
+        let storage = create_test_storage();
+
+        let build = storage
+            .insert(&create_test_command("cargo build", "package", 0))
+            .unwrap();
+        let test = storage
+            .insert(&create_test_command("cargo test", "package", 0))
+            .unwrap();
+
+        let now = Utc::now();
+        let meta = ExecutionMeta {
+            session: Some("s1".to_string()),
+            host: Some("laptop".to_string()),
+            ..Default::default()
+        };
+        storage.log_execution(build, now, 0, 10, &meta).unwrap();
+        storage
+            .log_execution(test, now + chrono::Duration::seconds(5), 0, 10, &meta)
+            .unwrap();
+        storage
+            .log_execution(
+                build,
+                now + chrono::Duration::seconds(10),
+                0,
+                10,
+                &ExecutionMeta::default(),
+            )
+            .unwrap();
+
+        let executions = storage.get_executions(None, false).unwrap();
+        let commands: Vec<&str> = executions.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec!["cargo build", "cargo test", "cargo build"]);
+        assert_eq!(executions[0].meta, meta);
+        assert_eq!(executions[2].meta, ExecutionMeta::default());
+
+        let elsewhere = storage
+            .get_executions(Some("/elsewhere".to_string()), false)
+            .unwrap();
+        assert!(elsewhere.is_empty());
+    }
+
+    #[test]
+    fn test_search_by_host_and_tmux_pane() {
+        let storage = create_test_storage();
+        let deploy = storage
+            .insert(&create_test_command(
+                "kubectl rollout restart deploy/api",
+                "k8s",
+                0,
+            ))
+            .unwrap();
+        let logs = storage
+            .insert(&create_test_command("kubectl logs deploy/api", "k8s", 0))
+            .unwrap();
+
+        let pane = ExecutionMeta {
+            host: Some("bastion".to_string()),
+            tmux_session: Some("incident".to_string()),
+            tmux_window: Some("1".to_string()),
+            tmux_pane: Some("%3".to_string()),
+            ..Default::default()
+        };
+        let other_pane = ExecutionMeta {
+            tmux_pane: Some("%4".to_string()),
+            ..pane.clone()
+        };
+        storage
+            .log_execution(deploy, Utc::now(), 0, 10, &pane)
+            .unwrap();
+        storage
+            .log_execution(logs, Utc::now(), 0, 10, &other_pane)
+            .unwrap();
+        assert_eq!(storage.get_executions(None, false).unwrap()[0].meta, pane);
+        assert_eq!(pane.tmux_location().as_deref(), Some("incident:1 %3"));
+
+        let search = |query: SearchQuery| -> Vec<String> {
+            let mut commands: Vec<String> = storage
+                .search(&query)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.command)
+                .collect();
+            commands.sort();
+            commands
+        };
+        let session = SearchQuery {
+            tmux_session: Some("incident".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search(session.clone()).len(), 2);
+        assert_eq!(
+            search(SearchQuery {
+                tmux_pane: Some("%3".to_string()),
+                ..session
+            }),
+            vec!["kubectl rollout restart deploy/api"]
+        );
+        assert_eq!(
+            search(SearchQuery {
+                host: Some("bastion".to_string()),
+                text: Some("logs".to_string()),
+                ..Default::default()
+            }),
+            vec!["kubectl logs deploy/api"]
+        );
+        assert!(search(SearchQuery {
+            host: Some("laptop".to_string()),
+            ..Default::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn test_search_by_env() {
+        let storage = create_test_storage();
+
+        let apply = storage
+            .insert(&create_test_command("terraform apply", "cloud", 0))
+            .unwrap();
+        let plan = storage
+            .insert(&create_test_command("terraform plan", "cloud", 0))
+            .unwrap();
+
+        let env = |profile: &str| ExecutionMeta {
+            env: [("AWS_PROFILE".to_string(), profile.to_string())].into(),
+            ..Default::default()
+        };
+        let now = Utc::now();
+        storage
+            .log_execution(apply, now, 0, 10, &env("prod"))
+            .unwrap();
+        storage
+            .log_execution(plan, now, 0, 10, &env("staging"))
+            .unwrap();
+        storage
+            .log_execution(plan, now, 0, 10, &ExecutionMeta::default())
+            .unwrap();
+
+        let query = SearchQuery {
+            text: Some("terraform".to_string()),
+            env: vec![("AWS_PROFILE".to_string(), "prod".to_string())],
+            ..Default::default()
+        };
+        let results = storage.search(&query).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].command, "terraform apply");
+
+        assert_eq!(
+            storage.get_latest_env(apply).unwrap()["AWS_PROFILE"],
+            "prod"
+        );
+        assert!(storage.get_latest_env(plan).unwrap().is_empty());
+        assert_eq!(
+            storage.get_executions(None, false).unwrap()[0].meta,
+            env("prod")
+        );
     }
 
     #[test]
-    fn test_storage_creation() {
+    fn test_dir_stats() {
         let storage = create_test_storage();
-        assert_eq!(storage.count().unwrap(), 0);
-    }
 
-    #[test]
-    fn test_insert_command() {
-        let storage = create_test_storage();
-        let cmd = create_test_command("git status", "git", 0);
+        let in_dir = |command: &str, category: &str, exit_code: i32, dir: &str| {
+            let mut record = create_test_command(command, category, exit_code);
+            record.working_dir = dir.to_string();
+            storage.insert(&record).unwrap()
+        };
+        let build = in_dir("cargo build", "build", 0, "/work/api");
+        let push = in_dir("git push", "git", 0, "/work/api");
+        in_dir("git status", "git", 0, "/work/api");
+        in_dir("ls", "file", 0, "/home/me");
 
-        let id = storage.insert(&cmd).unwrap();
-        assert!(id > 0);
-        assert_eq!(storage.count().unwrap(), 1);
+        let now = Utc::now();
+        let meta = ExecutionMeta::default();
+        for exit_code in [0, 1, 1] {
+            storage
+                .log_execution(build, now, exit_code, 10, &meta)
+                .unwrap();
+        }
+        storage.log_execution(push, now, 0, 10, &meta).unwrap();
+
+        let stats = storage
+            .get_dir_stats(10, 1, None, false, None, None)
+            .unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].working_dir, "/work/api");
+        // Commands without logged executions count as one run
+        assert_eq!((stats[0].runs, stats[0].commands), (5, 3));
+        assert_eq!(stats[0].successful, 3);
+        assert_eq!(stats[0].top_categories.len(), 1);
+        assert_eq!(stats[0].top_categories[0].category, "build");
+        assert_eq!(stats[0].top_categories[0].count, 3);
+        assert_eq!(stats[1].working_dir, "/home/me");
+        assert_eq!(stats[1].success_rate(), 100.0);
+
+        let stats = storage
+            .get_dir_stats(10, 3, Some("/work".to_string()), true, None, None)
+            .unwrap();
+        assert_eq!(stats.len(), 1);
+
+        let since = now + chrono::Duration::minutes(1);
+        assert!(storage
+            .get_dir_stats(10, 3, None, false, Some(since), None)
+            .unwrap()
+            .is_empty());
+
+        // Where git commands run, with their total run time
+        let git = storage
+            .get_dir_stats(10, 0, None, false, None, Some("git"))
+            .unwrap();
+        assert_eq!(git.len(), 1);
+        assert_eq!((git[0].runs, git[0].commands), (2, 2));
+        assert_eq!(git[0].duration_ms, 110);
+        assert!(git[0].top_categories.is_empty());
     }
 
     #[test]
-    fn test_find_duplicate() {
+    fn test_get_summary() {
         let storage = create_test_storage();
-        let cmd = create_test_command("git status", "git", 0);
+        let now = Utc::now();
+        let week_ago = now - chrono::Duration::days(7);
 
-        storage.insert(&cmd).unwrap();
+        let mut old = create_test_command("git pull", "git", 0);
+        old.timestamp = now - chrono::Duration::days(30);
+        let pull = storage.insert(&old).unwrap();
+        let build = storage
+            .insert(&create_test_command("cargo build --release", "build", 0))
+            .unwrap();
+        storage
+            .insert(&create_test_command("git push", "git", 1))
+            .unwrap();
 
-        let duplicate = storage.find_duplicate("git status", "/tmp").unwrap();
-        assert!(duplicate.is_some());
-        assert_eq!(duplicate.unwrap().command, "git status");
+        let meta = ExecutionMeta::default();
+        storage
+            .log_execution(pull, now - chrono::Duration::days(30), 0, 10, &meta)
+            .unwrap();
+        storage.log_execution(pull, now, 0, 10, &meta).unwrap();
+        storage.log_execution(build, now, 0, 60_000, &meta).unwrap();
 
-        let not_found = storage.find_duplicate("git commit", "/tmp").unwrap();
-        assert!(not_found.is_none());
+        let summary = storage.get_summary(week_ago, 5).unwrap();
+        assert_eq!(summary.since, week_ago);
+        // The month-old run of git pull is outside the period
+        assert_eq!((summary.runs, summary.successful), (3, 2));
+        assert_eq!(summary.commands, 3);
+        assert_eq!(summary.new_command_count, 2);
+        assert!(summary.new_commands.iter().all(|c| c.command != "git pull"));
+        assert_eq!(summary.top_tools[0].tool, "git");
+        assert_eq!(summary.top_tools[0].count, 2);
+        assert_eq!(summary.busiest_dirs[0].runs, 3);
+        assert_eq!(summary.slowest[0].command, "cargo build --release");
     }
 
     #[test]
-    fn test_increment_usage() {
+    fn test_get_wrapped() {
         let storage = create_test_storage();
-        let cmd = create_test_command("ls", "file", 0);
+        let at = |time: &str| time.parse::<DateTime<Utc>>().unwrap();
 
-        let id = storage.insert(&cmd).unwrap();
-        storage.increment_usage(id).unwrap();
+        let mut test = create_test_command("cargo test", "rust", 0);
+        test.timestamp = at("2025-03-01T10:00:00Z");
+        let test = storage.insert(&test).unwrap();
+        let mut build = create_test_command("cargo build --release", "build", 0);
+        build.timestamp = at("2025-03-02T10:00:00Z");
+        let build = storage.insert(&build).unwrap();
 
-        let records = storage.get_all().unwrap();
-        assert_eq!(records[0].usage_count, 2);
+        let meta = ExecutionMeta::default();
+        for (time, exit_code) in [
+            ("2025-03-01T10:00:00Z", 0),
+            ("2025-03-02T10:00:00Z", 101),
+            ("2025-03-03T10:00:00Z", 101),
+            ("2025-07-01T10:00:00Z", 0),
+            ("2024-12-31T23:00:00Z", 0),
+        ] {
+            storage
+                .log_execution(test, at(time), exit_code, 100, &meta)
+                .unwrap();
+        }
+        storage
+            .log_execution(build, at("2025-03-02T11:00:00Z"), 0, 90_000, &meta)
+            .unwrap();
+
+        let wrapped = storage.get_wrapped(2025).unwrap();
+        assert_eq!(
+            (wrapped.runs, wrapped.successful, wrapped.commands),
+            (5, 3, 2)
+        );
+        assert_eq!(wrapped.top_commands[0].command, "cargo test");
+        assert_eq!(wrapped.top_commands[0].runs, 4);
+        assert_eq!(wrapped.most_failed.unwrap().failures, 2);
+        assert_eq!(wrapped.longest_running.unwrap().max_ms, 90_000);
+        assert_eq!(wrapped.categories[0].category, "rust");
+        assert_eq!(wrapped.months[2], 4);
+        assert_eq!(wrapped.months[6], 1);
+        assert_eq!(wrapped.active_days, 4);
+        assert_eq!(
+            wrapped.longest_streak,
+            Some(("2025-03-01".parse().unwrap(), 3))
+        );
+
+        assert_eq!(storage.get_wrapped(2023).unwrap().runs, 0);
     }
 
     #[test]
-    fn test_search_by_category() {
+    fn test_branch_filter_and_stats() {
         let storage = create_test_storage();
 
+        let build = storage
+            .insert(&create_test_command("cargo build", "build", 0))
+            .unwrap();
+        let release = storage
+            .insert(&create_test_command("cargo publish", "package", 0))
+            .unwrap();
+
+        let on = |branch: &str| ExecutionMeta {
+            branch: Some(branch.to_string()),
+            repo: Some("git@github.com:acme/api.git".to_string()),
+            ..Default::default()
+        };
+        let now = Utc::now();
+        for _ in 0..3 {
+            storage
+                .log_execution(build, now, 0, 10, &on("main"))
+                .unwrap();
+        }
         storage
-            .insert(&create_test_command("git status", "git", 0))
+            .log_execution(build, now, 0, 10, &on("release/1.2"))
             .unwrap();
         storage
-            .insert(&create_test_command("git commit", "git", 0))
+            .log_execution(release, now, 0, 10, &on("release/1.2"))
             .unwrap();
         storage
-            .insert(&create_test_command("docker ps", "docker", 0))
+            .log_execution(release, now, 0, 10, &ExecutionMeta::default())
             .unwrap();
 
-        let git_commands = storage.get_by_category("git", 10, None, false).unwrap();
-        assert_eq!(git_commands.len(), 2);
-    }
+        let query = SearchQuery {
+            branch: Some("main".to_string()),
+            ..Default::default()
+        };
+        let results = storage.search(&query).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].command, "cargo build");
 
-    #[test]
-    fn test_sanitize_fts5_query_simple() {
-        let result = Storage::sanitize_fts5_query("hello world");
-        assert_eq!(result, "\"hello world\"");
+        let stats = storage.get_branch_stats(10).unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].branch, "main");
+        assert_eq!((stats[0].executions, stats[0].commands), (3, 1));
+        assert_eq!(stats[1].branch, "release/1.2");
+        assert_eq!((stats[1].executions, stats[1].commands), (2, 2));
+        assert_eq!(
+            storage.get_executions(None, false).unwrap()[0].meta,
+            on("main")
+        );
     }
 
     #[test]
-    fn test_sanitize_fts5_query_with_dots() {
-        let result = Storage::sanitize_fts5_query("10.104.113.39");
-        assert_eq!(result, "\"10.104.113.39\"");
-    }
+    fn test_activity() {
+        let storage = create_test_storage();
+        let id = storage
+            .insert(&create_test_command("cargo test", "build", 0))
+            .unwrap();
 
-    #[test]
-    fn test_sanitize_fts5_query_with_quotes() {
-        let result = Storage::sanitize_fts5_query("grep \"pattern\"");
-        assert_eq!(result, "\"grep \"\"pattern\"\"\"");
-    }
+        let at = |time: &str| time.parse::<DateTime<Utc>>().unwrap();
+        let meta = ExecutionMeta::default();
+        for (time, exit_code) in [
+            ("2025-11-08T09:15:00Z", 0),
+            ("2025-11-10T09:05:00Z", 1),
+            ("2025-11-10T09:40:00.123456789Z", 0),
+            ("2025-11-11T14:00:00Z", 0),
+        ] {
+            storage
+                .log_execution(id, at(time), exit_code, 10, &meta)
+                .unwrap();
+        }
 
-    #[test]
-    fn test_sanitize_fts5_query_with_asterisk() {
-        let result = Storage::sanitize_fts5_query("ls *.txt");
-        assert_eq!(result, "\"ls *.txt\"");
+        let daily = storage
+            .get_activity(ActivityPeriod::Day, None, None)
+            .unwrap();
+        let days: Vec<(String, usize, usize)> = daily
+            .buckets
+            .iter()
+            .map(|b| (b.start.to_string(), b.executions, b.successful))
+            .collect();
+        assert_eq!(
+            days,
+            vec![
+                ("2025-11-08".to_string(), 1, 1),
+                ("2025-11-10".to_string(), 2, 1),
+                ("2025-11-11".to_string(), 1, 1),
+            ]
+        );
+        assert_eq!(daily.heatmap[0][9], 2, "Monday 09:00");
+        assert_eq!(daily.heatmap[5][9], 1, "Saturday 09:00");
+        assert_eq!(daily.busiest_hour(), Some(9));
+
+        let weekly = storage
+            .get_activity(ActivityPeriod::Week, None, None)
+            .unwrap();
+        let weeks: Vec<(String, usize)> = weekly
+            .buckets
+            .iter()
+            .map(|b| (b.start.to_string(), b.executions))
+            .collect();
+        assert_eq!(
+            weeks,
+            vec![("2025-11-03".to_string(), 1), ("2025-11-10".to_string(), 3)]
+        );
+
+        let ranged = storage
+            .get_activity(
+                ActivityPeriod::Day,
+                Some(at("2025-11-10T00:00:00Z")),
+                Some(at("2025-11-11T00:00:00Z")),
+            )
+            .unwrap();
+        assert_eq!(ranged.total(), 2);
+
+        // Ten hours east of UTC, 14:00 on the 11th is midnight on the 12th
+        storage.set_utc_offset(600);
+        let shifted = storage
+            .get_activity(ActivityPeriod::Day, None, None)
+            .unwrap();
+        assert_eq!(
+            shifted.buckets.last().unwrap().start.to_string(),
+            "2025-11-12"
+        );
+        assert_eq!(shifted.heatmap[0][19], 2, "Monday 19:00");
     }
 
     #[test]
-    fn test_sanitize_fts5_query_url() {
-        let result = Storage::sanitize_fts5_query("https://example.com");
-        assert_eq!(result, "\"https://example.com\"");
+    fn test_vocabulary_growth() {
+        let storage = create_test_storage();
+        let at = |time: &str| time.parse::<DateTime<Utc>>().unwrap();
+        for (command, time) in [
+            ("ls", "2025-10-20T09:00:00Z"),
+            ("jq .", "2025-11-03T09:00:00Z"),
+            ("rg todo", "2025-11-05T09:00:00Z"),
+            ("fd .rs", "2025-11-19T09:00:00Z"),
+        ] {
+            let mut record = create_test_command(command, "other", 0);
+            record.timestamp = at(time);
+            storage.insert(&record).unwrap();
+        }
+
+        let weekly = storage
+            .get_vocabulary_growth(
+                ActivityPeriod::Week,
+                at("2025-11-01T00:00:00Z"),
+                Some(at("2025-11-24T00:00:00Z")),
+            )
+            .unwrap();
+        let weeks: Vec<(String, usize, usize)> = weekly
+            .iter()
+            .map(|b| (b.start.to_string(), b.new_commands, b.total))
+            .collect();
+        assert_eq!(
+            weeks,
+            vec![
+                ("2025-10-27".to_string(), 0, 1),
+                ("2025-11-03".to_string(), 2, 3),
+                ("2025-11-10".to_string(), 0, 3),
+                ("2025-11-17".to_string(), 1, 4),
+            ]
+        );
+
+        let monthly = storage
+            .get_vocabulary_growth(
+                ActivityPeriod::Month,
+                at("2025-10-01T00:00:00Z"),
+                Some(at("2025-12-01T00:00:00Z")),
+            )
+            .unwrap();
+        assert_eq!(monthly.len(), 2);
+        assert_eq!(monthly[1].new_commands, 3);
     }
 
     #[test]
-    fn test_search_with_ip_address() {
+    fn test_slowest() {
         let storage = create_test_storage();
+        let meta = ExecutionMeta::default();
+        let now = Utc::now();
 
-        // Insert a command with an IP address
-        let record = create_test_command("ssh user@10.104.113.39", "network", 0);
-        storage.insert(&record).unwrap();
+        let build = storage
+            .insert(&create_test_command("cargo build", "build", 0))
+            .unwrap();
+        for duration in [60_000, 90_000, 30_000] {
+            storage
+                .log_execution(build, now, 0, duration, &meta)
+                .unwrap();
+        }
 
-        // Search for the IP address
-        let query = SearchQuery {
-            text: Some("10.104.113.39".to_string()),
-            category: None,
-            success_only: None,
-            working_dir: None,
-            recursive: false,
-            limit: 10,
-            order_by: OrderBy::Relevance,
-        };
+        let tests = storage
+            .insert(&create_test_command("cargo test", "build", 0))
+            .unwrap();
+        for duration in [1_000, 200_000] {
+            storage
+                .log_execution(tests, now, 0, duration, &meta)
+                .unwrap();
+        }
 
-        let results = storage.search(&query).unwrap();
-        assert_eq!(results.len(), 1);
-        assert!(results[0].command.contains("10.104.113.39"));
+        // Imported without executions: one run of its stored duration
+        let mut deploy = create_test_command("make deploy", "build", 0);
+        deploy.duration_ms = 500;
+        storage.insert(&deploy).unwrap();
+
+        let by_avg = storage
+            .get_slowest(SlowestBy::Average, 10, 1, None, false)
+            .unwrap();
+        let order: Vec<&str> = by_avg.iter().map(|s| s.command.as_str()).collect();
+        assert_eq!(order, vec!["cargo test", "cargo build", "make deploy"]);
+        assert_eq!(by_avg[0].avg_ms, 100_500);
+        assert_eq!(
+            (by_avg[1].runs, by_avg[1].min_ms, by_avg[1].max_ms),
+            (3, 30_000, 90_000)
+        );
+        assert_eq!(by_avg[2].runs, 1);
+
+        let by_max = storage
+            .get_slowest(SlowestBy::Max, 1, 1, None, false)
+            .unwrap();
+        assert_eq!(by_max[0].command, "cargo test");
+
+        let repeated = storage
+            .get_slowest(SlowestBy::Average, 10, 3, None, false)
+            .unwrap();
+        assert_eq!(repeated.len(), 1);
+        assert_eq!(repeated[0].command, "cargo build");
     }
 
     #[test]
-    fn test_search_with_url() {
+    fn test_failure_analytics() {
         let storage = create_test_storage();
+        let meta = ExecutionMeta::default();
+        let at = |minute: i64| {
+            "2025-11-10T09:00:00Z".parse::<DateTime<Utc>>().unwrap()
+                + chrono::Duration::minutes(minute)
+        };
 
-        let record = create_test_command("curl https://api.github.com/users/daneb", "network", 0);
-        storage.insert(&record).unwrap();
+        let flaky = storage
+            .insert(&create_test_command("cargo test", "build", 0))
+            .unwrap();
+        for (minute, exit_code) in [(0, 0), (1, 101), (2, 0), (3, 101)] {
+            storage
+                .log_execution(flaky, at(minute), exit_code, 10, &meta)
+                .unwrap();
+        }
 
-        let query = SearchQuery {
-            text: Some("api.github.com".to_string()),
-            category: None,
-            success_only: None,
-            working_dir: None,
-            recursive: false,
-            limit: 10,
-            order_by: OrderBy::Relevance,
-        };
+        let broken = storage
+            .insert(&create_test_command("./missing.sh", "other", 127))
+            .unwrap();
+        for minute in [4, 5] {
+            storage
+                .log_execution(broken, at(minute), 127, 10, &meta)
+                .unwrap();
+        }
 
-        let results = storage.search(&query).unwrap();
-        assert_eq!(results.len(), 1);
+        // Imported without executions: one failed run
+        storage
+            .insert(&create_test_command("make lint", "build", 2))
+            .unwrap();
+        storage
+            .insert(&create_test_command("ls", "file", 0))
+            .unwrap();
+
+        let rates = storage.get_failure_rates(10, 2, None, false).unwrap();
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates[0].command, "./missing.sh");
+        assert_eq!(rates[0].failure_rate(), 100.0);
+        assert_eq!((rates[1].runs, rates[1].failures), (4, 2));
+        assert_eq!(rates[1].last_failed, Some(at(3)));
+        assert_eq!(
+            storage.get_failure_rates(10, 1, None, false).unwrap().len(),
+            3
+        );
+
+        // The imported failure was last used now
+        let recent = storage.get_recent_failures(3, None, false).unwrap();
+        let recent: Vec<(&str, i32)> = recent
+            .iter()
+            .map(|r| (r.command.as_str(), r.exit_code))
+            .collect();
+        assert_eq!(
+            recent,
+            vec![
+                ("make lint", 2),
+                ("./missing.sh", 127),
+                ("./missing.sh", 127)
+            ]
+        );
+
+        let codes: Vec<(i32, usize)> = storage
+            .get_exit_code_counts(None, false)
+            .unwrap()
+            .iter()
+            .map(|c| (c.exit_code, c.count))
+            .collect();
+        assert_eq!(codes, vec![(101, 2), (127, 2), (2, 1)]);
     }
 
     #[test]
-    fn test_search_with_file_path() {
+    fn test_rewrite_records_in_batches() {
         let storage = create_test_storage();
+        for i in 0..5 {
+            storage
+                .insert(&create_test_command(&format!("cmd {}", i), "other", 0))
+                .unwrap();
+        }
 
-        let record = create_test_command("cat ./config/settings.yaml", "file", 0);
-        storage.insert(&record).unwrap();
+        let visited = storage
+            .rewrite_records(2, |record| match record.command.as_str() {
+                "cmd 1" => Some(RecordChange::Delete),
+                "cmd 3" => Some(RecordChange::Update(CommandRecord {
+                    command: "cmd three".to_string(),
+                    ..record.clone()
+                })),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(visited, 5);
 
-        let query = SearchQuery {
-            text: Some("./config/settings.yaml".to_string()),
-            category: None,
-            success_only: None,
-            working_dir: None,
-            recursive: false,
-            limit: 10,
-            order_by: OrderBy::Relevance,
-        };
+        let commands: Vec<String> = storage
+            .get_all()
+            .unwrap()
+            .into_iter()
+            .map(|r| r.command)
+            .collect();
+        assert_eq!(commands.len(), 4);
+        assert!(!commands.contains(&"cmd 1".to_string()));
+        assert!(commands.contains(&"cmd three".to_string()));
+    }
 
-        let results = storage.search(&query).unwrap();
-        assert_eq!(results.len(), 1);
+    #[test]
+    fn test_in_memory() {
+        let storage = Storage::in_memory().unwrap();
+        assert_eq!(
+            storage.schema_version().unwrap(),
+            migrations::latest_version()
+        );
+
+        let id = storage
+            .insert(&create_test_command("git status", "git", 0))
+            .unwrap();
+        storage.add_bookmark(id).unwrap();
+        assert_eq!(storage.get_bookmarks(10, 0).unwrap().len(), 1);
+        assert_eq!(storage.get_stats().unwrap().total_commands, 1);
+
+        // Each in-memory storage is independent
+        assert_eq!(Storage::in_memory().unwrap().count().unwrap(), 0);
     }
 
     #[test]
-    fn test_search_with_multiple_special_chars() {
-        let storage = create_test_storage();
+    fn test_from_config() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.storage.path = dir.path().join("history.db").to_string_lossy().to_string();
 
-        let record = create_test_command("scp file.txt user@host.com:/path/to/dest", "network", 0);
-        storage.insert(&record).unwrap();
+        config.storage.storage_type = STORAGE_MEMORY.to_string();
+        let storage = Storage::from_config(&config).unwrap();
+        storage
+            .insert(&create_test_command("ls", "file", 0))
+            .unwrap();
+        assert!(!dir.path().join("history.db").exists());
 
-        let query = SearchQuery {
-            text: Some("user@host.com".to_string()),
-            category: None,
-            success_only: None,
-            working_dir: None,
-            recursive: false,
-            limit: 10,
-            order_by: OrderBy::Relevance,
-        };
+        config.storage.storage_type = "sqlite".to_string();
+        Storage::from_config(&config).unwrap();
+        assert!(dir.path().join("history.db").exists());
+    }
 
-        let results = storage.search(&query).unwrap();
-        assert_eq!(results.len(), 1);
+    #[test]
+    fn test_write_with_retry() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let holder = Storage::new(temp_file.path()).unwrap();
+        let writer = Storage::new(temp_file.path()).unwrap();
+        writer.set_busy_timeout(0).unwrap();
+
+        holder.conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+        let error = writer
+            .write_with_retry(0, || writer.insert(&create_test_command("ls", "file", 0)))
+            .unwrap_err();
+        assert!(error.is_busy());
+
+        // The lock is released while the writer is backing off
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            holder.conn.execute_batch("COMMIT").unwrap();
+        });
+        let id = writer
+            .write_with_retry(20, || writer.insert(&create_test_command("ls", "file", 0)))
+            .unwrap();
+        release.join().unwrap();
+        assert!(writer.get_by_id(id).unwrap().is_some());
+
+        // Failed writes are rolled back
+        let result: Result<()> = writer.write_with_retry(3, || {
+            writer.insert(&create_test_command("pwd", "file", 0))?;
+            Err(OmniscientError::other("boom"))
+        });
+        assert!(result.is_err());
+        assert_eq!(writer.count().unwrap(), 1);
     }
 
     #[test]
-    fn test_search_empty_query_still_works() {
+    fn test_with_tx() {
         let storage = create_test_storage();
 
-        let record = create_test_command("ls -la", "file", 0);
-        storage.insert(&record).unwrap();
-
-        // Search without text (should use other filters)
-        let query = SearchQuery {
-            text: None,
-            category: Some("file".to_string()),
-            success_only: None,
-            working_dir: None,
-            recursive: false,
-            limit: 10,
-            order_by: OrderBy::Timestamp,
-        };
+        // Nested calls join the outer transaction and fail with it
+        let result: Result<()> = storage.with_tx(|| {
+            storage.insert_batch(&[create_test_command("ls", "file", 0)])?;
+            storage.with_tx(|| storage.insert(&create_test_command("pwd", "file", 0)))?;
+            Err(OmniscientError::other("boom"))
+        });
+        assert!(result.is_err());
+        assert!(storage.conn.is_autocommit());
+        assert_eq!(storage.count().unwrap(), 0);
 
-        let results = storage.search(&query).unwrap();
-        assert_eq!(results.len(), 1);
+        let id = storage
+            .with_tx(|| storage.insert(&create_test_command("ls", "file", 0)))
+            .unwrap();
+        assert!(storage.get_by_id(id).unwrap().is_some());
     }
 
     #[test]
-    fn test_get_stats() {
+    fn test_suggest() {
         let storage = create_test_storage();
+        for command in ["git status", "git stash pop", "gitk", "cargo build"] {
+            storage
+                .insert(&create_test_command(command, "other", 0))
+                .unwrap();
+        }
+        let mut used = create_test_command("git stage -p", "git", 0);
+        used.usage_count = 20;
+        storage.insert(&used).unwrap();
 
+        let commands: Vec<String> = storage
+            .suggest("git st", 10, None, false)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.command)
+            .collect();
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[0], "git stage -p");
+        assert!(!commands.contains(&"gitk".to_string()));
+
+        // LIKE wildcards in the prefix are literal
+        assert!(storage.suggest("git%", 10, None, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_recategorize() {
+        let storage = create_test_storage();
         storage
-            .insert(&create_test_command("success1", "git", 0))
+            .insert(&create_test_command("./deploy.sh prod", "other", 0))
             .unwrap();
         storage
-            .insert(&create_test_command("success2", "docker", 0))
+            .insert(&create_test_command("./deploy.sh staging", "other", 0))
             .unwrap();
         storage
-            .insert(&create_test_command("failure", "git", 1))
+            .insert(&create_test_command("mystery", "other", 0))
+            .unwrap();
+
+        let changed = storage
+            .recategorize("other", |cmd| {
+                cmd.contains("deploy.sh").then(|| "deploy".to_string())
+            })
             .unwrap();
+        assert_eq!(changed, 2);
 
         let stats = storage.get_stats().unwrap();
-        assert_eq!(stats.total_commands, 3);
-        assert_eq!(stats.successful_commands, 2);
-        assert_eq!(stats.failed_commands, 1);
-        assert_eq!(stats.success_rate(), 66.66666666666666);
+        let deploy = stats
+            .by_category
+            .iter()
+            .find(|c| c.category == "deploy")
+            .unwrap();
+        assert_eq!(deploy.count, 2);
+        assert_eq!(
+            storage
+                .get_by_category("other", 10, None, false)
+                .unwrap()
+                .len(),
+            1
+        );
     }
 }
@@ -0,0 +1,255 @@
+/// Output colors and themes
+///
+/// Terminal output is styled by role (success, failure, warning, metadata,
+/// highlight, accent) and by command category rather than with fixed colors,
+/// so the `[output.theme]` config section can restyle it. A style is a list
+/// of words: a color (`red`, `bright blue`, `#ff8800`) and modifiers (`bold`,
+/// `dimmed`, `italic`, `underline`), or `none` for plain text.
+use crate::config::{Config, ThemeConfig};
+use crate::error::{OmniscientError, Result};
+use crate::format::ColorMode;
+use colored::{Color, ColoredString, Colorize};
+use std::collections::HashMap;
+
+/// Category colors used unless the theme overrides them
+const BUILTIN_CATEGORIES: &[(&str, &str)] = &[
+    ("git", "cyan"),
+    ("docker", "blue"),
+    ("network", "magenta"),
+    ("file", "yellow"),
+    ("package", "bright green"),
+    ("database", "bright magenta"),
+    ("kubernetes", "bright blue"),
+    ("cloud", "bright cyan"),
+    ("system", "bright yellow"),
+    ("editor", "white"),
+    ("build", "bright red"),
+    ("vcs", "bright white"),
+    ("test", "green"),
+    ("deploy", "red"),
+    ("lint", "bright yellow"),
+    ("dev", "bright cyan"),
+];
+
+impl ColorMode {
+    /// Make all colored output follow this mode
+    pub fn apply(self) {
+        match self {
+            ColorMode::Auto => colored::control::unset_override(),
+            ColorMode::Always => colored::control::set_override(true),
+            ColorMode::Never => colored::control::set_override(false),
+        }
+    }
+}
+
+/// A color plus text modifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    color: Option<Color>,
+    bold: bool,
+    dimmed: bool,
+    italic: bool,
+    underline: bool,
+}
+
+impl Style {
+    /// Parse a style such as `bright red bold` or `#5f87af underline`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut style = Style::default();
+        let mut words = spec.split_whitespace().peekable();
+
+        while let Some(word) = words.next() {
+            let word = word.to_lowercase();
+            match word.as_str() {
+                "none" | "plain" => {}
+                "bold" => style.bold = true,
+                "dim" | "dimmed" => style.dimmed = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                _ => {
+                    // "bright red" is one color
+                    let name = match words.peek() {
+                        Some(next) if word == "bright" => {
+                            format!("bright {}", next.to_lowercase())
+                        }
+                        _ => word.replace('_', " "),
+                    };
+                    if word == "bright" {
+                        words.next();
+                    }
+                    style.color = Some(parse_color(&name).ok_or_else(|| {
+                        OmniscientError::config(format!(
+                            "Invalid style '{}': unknown color '{}'",
+                            spec, name
+                        ))
+                    })?);
+                }
+            }
+        }
+
+        Ok(style)
+    }
+
+    /// Apply the style to some text
+    pub fn paint(&self, text: &str) -> ColoredString {
+        let mut painted = match self.color {
+            Some(color) => text.color(color),
+            None => text.normal(),
+        };
+        if self.bold {
+            painted = painted.bold();
+        }
+        if self.dimmed {
+            painted = painted.dimmed();
+        }
+        if self.italic {
+            painted = painted.italic();
+        }
+        if self.underline {
+            painted = painted.underline();
+        }
+        painted
+    }
+}
+
+/// Parse a color name or a `#rrggbb` hex color
+fn parse_color(name: &str) -> Option<Color> {
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color::TrueColor {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        });
+    }
+
+    name.parse().ok()
+}
+
+/// Styles for every output role
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// Successful commands and completed actions
+    pub success: Style,
+
+    /// Failed commands and errors
+    pub failure: Style,
+
+    /// Warnings and things that need attention
+    pub warning: Style,
+
+    /// Timestamps, directories and other secondary details
+    pub metadata: Style,
+
+    /// Search matches
+    pub highlight: Style,
+
+    /// Charts, branches and other emphasis
+    pub accent: Style,
+
+    /// Category name -> style
+    categories: HashMap<String, Style>,
+}
+
+impl Theme {
+    /// Build a theme from the `[output.theme]` section
+    pub fn new(config: &ThemeConfig) -> Result<Self> {
+        let mut categories = HashMap::new();
+        for (name, spec) in BUILTIN_CATEGORIES {
+            categories.insert(name.to_string(), Style::parse(spec)?);
+        }
+        for (name, spec) in &config.categories {
+            categories.insert(name.clone(), Style::parse(spec)?);
+        }
+
+        Ok(Self {
+            success: Style::parse(&config.success)?,
+            failure: Style::parse(&config.failure)?,
+            warning: Style::parse(&config.warning)?,
+            metadata: Style::parse(&config.metadata)?,
+            highlight: Style::parse(&config.highlight)?,
+            accent: Style::parse(&config.accent)?,
+            categories,
+        })
+    }
+
+    /// The configured theme
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Self::new(&config.output.theme)
+    }
+
+    /// A category name in its color (uncategorized names stay plain)
+    pub fn category(&self, category: &str) -> ColoredString {
+        match self.categories.get(category) {
+            Some(style) => style.paint(category),
+            None => category.normal(),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::new(&ThemeConfig::default()).expect("default theme is valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_style() {
+        assert_eq!(
+            Style::parse("bright red bold").unwrap(),
+            Style {
+                color: Some(Color::BrightRed),
+                bold: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            Style::parse("bright_blue").unwrap().color,
+            Some(Color::BrightBlue)
+        );
+        assert_eq!(
+            Style::parse("#ff8800 underline").unwrap(),
+            Style {
+                color: Some(Color::TrueColor {
+                    r: 255,
+                    g: 136,
+                    b: 0
+                }),
+                underline: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(Style::parse("none").unwrap(), Style::default());
+
+        let err = Style::parse("blurple").unwrap_err();
+        assert!(err.to_string().contains("unknown color 'blurple'"));
+        assert!(Style::parse("#12345").is_err());
+    }
+
+    #[test]
+    fn test_theme_categories() {
+        let mut config = ThemeConfig::default();
+        config
+            .categories
+            .insert("git".to_string(), "bright magenta".to_string());
+        config
+            .categories
+            .insert("deploy".to_string(), "red".to_string());
+        let theme = Theme::new(&config).unwrap();
+
+        assert_eq!(
+            theme.categories["git"],
+            Style::parse("bright magenta").unwrap()
+        );
+        assert_eq!(theme.categories["deploy"], Style::parse("red").unwrap());
+        assert_eq!(theme.categories["docker"], Style::parse("blue").unwrap());
+        assert_eq!(theme.metadata, Style::parse("dimmed").unwrap());
+    }
+}
@@ -0,0 +1,125 @@
+/// Time zone of displayed timestamps
+///
+/// Timestamps are stored in UTC. Listings, `show` and `stats` print them in
+/// the system's local time unless `display.timezone` names another zone
+/// (an IANA name such as "Europe/Berlin", or "UTC").
+use chrono::{DateTime, Local, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Zone timestamps are displayed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum DisplayZone {
+    /// The system's local time zone
+    #[default]
+    Local,
+
+    /// A named zone from the tz database
+    Named(Tz),
+}
+
+impl DisplayZone {
+    /// Look up a zone by name ("local", "UTC" or an IANA name)
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("local") {
+            return Some(DisplayZone::Local);
+        }
+        if name.eq_ignore_ascii_case("utc") {
+            return Some(DisplayZone::Named(Tz::UTC));
+        }
+        name.parse().ok().map(DisplayZone::Named)
+    }
+
+    /// Format `time` in this zone (strftime)
+    pub fn format(&self, time: &DateTime<Utc>, format: &str) -> String {
+        match self {
+            DisplayZone::Local => time.with_timezone(&Local).format(format).to_string(),
+            DisplayZone::Named(tz) => time.with_timezone(tz).format(format).to_string(),
+        }
+    }
+
+    /// Offset from UTC in minutes at `time` (east is positive)
+    pub fn offset_minutes(&self, time: &DateTime<Utc>) -> i32 {
+        let seconds = match self {
+            DisplayZone::Local => Local
+                .offset_from_utc_datetime(&time.naive_utc())
+                .fix()
+                .local_minus_utc(),
+            DisplayZone::Named(tz) => tz
+                .offset_from_utc_datetime(&time.naive_utc())
+                .fix()
+                .local_minus_utc(),
+        };
+        seconds / 60
+    }
+}
+
+impl fmt::Display for DisplayZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayZone::Local => write!(f, "local"),
+            DisplayZone::Named(tz) => write!(f, "{}", tz.name()),
+        }
+    }
+}
+
+impl TryFrom<String> for DisplayZone {
+    type Error = String;
+
+    fn try_from(name: String) -> std::result::Result<Self, Self::Error> {
+        Self::from_name(&name).ok_or_else(|| {
+            format!(
+                "unknown time zone '{}' (expected \"local\", \"UTC\" or a name such as \"Europe/Berlin\")",
+                name
+            )
+        })
+    }
+}
+
+impl From<DisplayZone> for String {
+    fn from(zone: DisplayZone) -> Self {
+        zone.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2025-07-01T22:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(DisplayZone::from_name("local"), Some(DisplayZone::Local));
+        assert_eq!(
+            DisplayZone::from_name("utc"),
+            Some(DisplayZone::Named(Tz::UTC))
+        );
+        assert_eq!(
+            DisplayZone::from_name("Europe/Berlin").unwrap().to_string(),
+            "Europe/Berlin"
+        );
+        assert_eq!(DisplayZone::from_name("Mars/Olympus"), None);
+    }
+
+    #[test]
+    fn test_format_in_zone() {
+        let berlin = DisplayZone::from_name("Europe/Berlin").unwrap();
+        // Summer time: UTC+2, so the next day
+        assert_eq!(berlin.format(&time(), "%Y-%m-%d %H:%M"), "2025-07-02 00:30");
+        assert_eq!(berlin.offset_minutes(&time()), 120);
+
+        let new_york = DisplayZone::from_name("America/New_York").unwrap();
+        assert_eq!(new_york.format(&time(), "%H:%M %Z"), "18:30 EDT");
+        assert_eq!(new_york.offset_minutes(&time()), -240);
+
+        let utc = DisplayZone::Named(Tz::UTC);
+        assert_eq!(utc.format(&time(), "%H:%M"), "22:30");
+    }
+}
@@ -0,0 +1,93 @@
+/// Terminal multiplexer detection - the tmux or GNU screen pane a command ran in
+///
+/// Inside tmux, `$TMUX_PANE` identifies the pane (`%3`) for the life of the
+/// server; the session name and window index are asked from tmux itself.
+/// Inside screen, `$STY` (`12345.name`) names the session and `$WINDOW`
+/// the window. Knowing the pane lets you replay what happened in one pane
+/// during an incident (`omniscient recent --tmux-session ops --tmux-pane %3`).
+use std::env;
+use std::process::{Command, Stdio};
+
+/// The multiplexer session, window and pane of the current shell
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PaneInfo {
+    /// Session name
+    pub session: Option<String>,
+
+    /// Window index
+    pub window: Option<String>,
+
+    /// Pane ID (tmux only, e.g. `%3`)
+    pub pane: Option<String>,
+}
+
+impl PaneInfo {
+    /// Detect the tmux pane or screen window of this process, if any
+    pub fn detect() -> Option<Self> {
+        if let Some(pane) = env::var("TMUX_PANE").ok().filter(|p| !p.is_empty()) {
+            let display = Command::new("tmux")
+                .args(["display-message", "-p", "-t", &pane])
+                .arg("#{session_name}\t#{window_index}")
+                .stdin(Stdio::null())
+                .stderr(Stdio::null())
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).into_owned());
+            return Some(Self::from_tmux(&pane, display.as_deref()));
+        }
+
+        let sty = env::var("STY").ok().filter(|s| !s.is_empty())?;
+        Some(Self::from_screen(&sty, env::var("WINDOW").ok().as_deref()))
+    }
+
+    /// A tmux pane, with the `session<TAB>window` line tmux printed if it
+    /// could be asked
+    fn from_tmux(pane: &str, display: Option<&str>) -> Self {
+        let (session, window) = display
+            .and_then(|line| line.trim_end().split_once('\t'))
+            .map(|(session, window)| (Some(session.to_string()), Some(window.to_string())))
+            .unwrap_or_default();
+        Self {
+            session,
+            window,
+            pane: Some(pane.to_string()),
+        }
+    }
+
+    /// A screen window; `$STY` is `<pid>.<session name>`
+    fn from_screen(sty: &str, window: Option<&str>) -> Self {
+        let session = sty.split_once('.').map_or(sty, |(_, name)| name);
+        Self {
+            session: Some(session.to_string()),
+            window: window.filter(|w| !w.is_empty()).map(str::to_string),
+            pane: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_tmux() {
+        let info = PaneInfo::from_tmux("%3", Some("ops\t2\n"));
+        assert_eq!(info.session.as_deref(), Some("ops"));
+        assert_eq!(info.window.as_deref(), Some("2"));
+        assert_eq!(info.pane.as_deref(), Some("%3"));
+
+        // A tmux server that can't be asked still gives the pane
+        let info = PaneInfo::from_tmux("%7", None);
+        assert_eq!(info.session, None);
+        assert_eq!(info.pane.as_deref(), Some("%7"));
+    }
+
+    #[test]
+    fn test_from_screen() {
+        let info = PaneInfo::from_screen("48211.incident.host", Some("1"));
+        assert_eq!(info.session.as_deref(), Some("incident.host"));
+        assert_eq!(info.window.as_deref(), Some("1"));
+        assert_eq!(info.pane, None);
+    }
+}
@@ -0,0 +1,394 @@
+/// Interactive category training - teaches the categorizer about commands it
+/// files under "other"
+///
+/// Each training step assigns a category to a command name. The rule is
+/// applied to matching history straight away; the caller persists the
+/// learned rules to the `[categories]` config section.
+use crate::category::Categorizer;
+use crate::error::Result;
+use crate::storage::Storage;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+/// Category of commands no rule matches
+pub const UNCATEGORIZED: &str = "other";
+
+/// A command name without a category
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrainingCandidate {
+    /// Command name (first word without path)
+    pub name: String,
+
+    /// Most frequently used command with this name
+    pub example: String,
+
+    /// Number of uncategorized records with this name
+    pub records: usize,
+
+    /// Combined usage count of those records
+    pub usage: i64,
+}
+
+/// What a training session changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrainingSummary {
+    /// Learned rules (command name -> category)
+    pub rules: BTreeMap<String, String>,
+
+    /// Number of history records moved out of "other"
+    pub recategorized: usize,
+
+    /// Number of candidates skipped
+    pub skipped: usize,
+}
+
+/// What the user answered for one candidate
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Answer {
+    Assign(String),
+    Skip,
+    Quit,
+    Invalid(String),
+}
+
+/// Walks the user through uncategorized commands
+pub struct Trainer<'a> {
+    storage: &'a Storage,
+    categorizer: Categorizer,
+    choices: Vec<String>,
+}
+
+impl<'a> Trainer<'a> {
+    /// Create a trainer that extends `categorizer` and updates `storage`
+    pub fn new(storage: &'a Storage, categorizer: Categorizer) -> Self {
+        let choices = categorizer.categories();
+        Self {
+            storage,
+            categorizer,
+            choices,
+        }
+    }
+
+    /// Find the most used uncategorized command names
+    pub fn candidates(&self, limit: usize) -> Result<Vec<TrainingCandidate>> {
+        // name -> (candidate, usage of the current example)
+        let mut by_name: BTreeMap<String, (TrainingCandidate, i32)> = BTreeMap::new();
+
+        self.storage.for_each_record(|record| {
+            if record.category != UNCATEGORIZED {
+                return Ok(());
+            }
+
+            let name = Categorizer::command_name(&record.command);
+            if name.is_empty() {
+                return Ok(());
+            }
+
+            let (candidate, example_usage) = by_name.entry(name.to_string()).or_insert_with(|| {
+                let candidate = TrainingCandidate {
+                    name: name.to_string(),
+                    example: record.command.clone(),
+                    records: 0,
+                    usage: 0,
+                };
+                (candidate, record.usage_count)
+            });
+
+            candidate.records += 1;
+            candidate.usage += i64::from(record.usage_count);
+            if record.usage_count > *example_usage {
+                candidate.example = record.command.clone();
+                *example_usage = record.usage_count;
+            }
+
+            Ok(())
+        })?;
+
+        let mut candidates: Vec<TrainingCandidate> = by_name
+            .into_values()
+            .map(|(candidate, _)| candidate)
+            .collect();
+        candidates.sort_by(|a, b| b.usage.cmp(&a.usage).then_with(|| a.name.cmp(&b.name)));
+        candidates.truncate(limit);
+
+        Ok(candidates)
+    }
+
+    /// Prompt for a category for each candidate, reading answers from `input`
+    ///
+    /// Answers are a category number, a new category name, an empty line to
+    /// skip, or `q` to stop. Replacing an existing rule for a name asks for
+    /// confirmation first.
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        candidates: &[TrainingCandidate],
+        mut input: R,
+        mut output: W,
+    ) -> Result<TrainingSummary> {
+        let mut summary = TrainingSummary::default();
+
+        writeln!(output, "Categories: {}", self.numbered_choices())?;
+
+        'candidates: for (index, candidate) in candidates.iter().enumerate() {
+            writeln!(
+                output,
+                "\n[{}/{}] {} ({} commands, used {} times)",
+                index + 1,
+                candidates.len(),
+                candidate.name,
+                candidate.records,
+                candidate.usage
+            )?;
+            writeln!(output, "      e.g. {}", candidate.example)?;
+
+            let category = loop {
+                write!(
+                    output,
+                    "Category [1-{}, new name, Enter to skip, q to quit]: ",
+                    self.choices.len()
+                )?;
+                output.flush()?;
+
+                match self.parse_answer(&Self::read_line(&mut input)?) {
+                    Answer::Assign(category) => break category,
+                    Answer::Skip => {
+                        summary.skipped += 1;
+                        continue 'candidates;
+                    }
+                    Answer::Quit => break 'candidates,
+                    Answer::Invalid(reason) => writeln!(output, "{}", reason)?,
+                }
+            };
+
+            if let Some(existing) = self.categorizer.rule_for(&candidate.name) {
+                if existing != category {
+                    write!(
+                        output,
+                        "'{}' is already categorized as '{}'. Replace with '{}'? [y/N]: ",
+                        candidate.name, existing, category
+                    )?;
+                    output.flush()?;
+
+                    if !Self::read_line(&mut input)?.eq_ignore_ascii_case("y") {
+                        summary.skipped += 1;
+                        continue;
+                    }
+                }
+            }
+
+            let changed = self.learn(&candidate.name, &category)?;
+            writeln!(
+                output,
+                "  {} -> {} ({} commands recategorized)",
+                candidate.name, category, changed
+            )?;
+
+            summary.rules.insert(candidate.name.clone(), category);
+            summary.recategorized += changed;
+        }
+
+        Ok(summary)
+    }
+
+    /// Add a rule and apply it to uncategorized history
+    pub fn learn(&mut self, name: &str, category: &str) -> Result<usize> {
+        self.categorizer.add_rule(name, category);
+
+        if !self.choices.iter().any(|c| c == category) {
+            self.choices.push(category.to_string());
+        }
+
+        self.storage.recategorize(UNCATEGORIZED, |command| {
+            (Categorizer::command_name(command) == name).then(|| category.to_string())
+        })
+    }
+
+    /// Interpret one line of input
+    fn parse_answer(&self, line: &str) -> Answer {
+        let line = line.trim();
+
+        if line.is_empty() {
+            return Answer::Skip;
+        }
+        if line.eq_ignore_ascii_case("q") {
+            return Answer::Quit;
+        }
+
+        if let Ok(number) = line.parse::<usize>() {
+            return match self.choices.get(number.wrapping_sub(1)) {
+                Some(category) => Answer::Assign(category.clone()),
+                None => Answer::Invalid(format!("No category numbered {}", number)),
+            };
+        }
+
+        let valid = line
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            Answer::Invalid(format!(
+                "Invalid category '{}': use letters, digits, '-' and '_'",
+                line
+            ))
+        } else if line == UNCATEGORIZED {
+            Answer::Invalid(format!("'{}' is the uncategorized bucket", line))
+        } else {
+            Answer::Assign(line.to_lowercase())
+        }
+    }
+
+    /// The category choices as "1) build  2) cloud ..."
+    fn numbered_choices(&self) -> String {
+        self.choices
+            .iter()
+            .enumerate()
+            .map(|(i, c)| format!("{}) {}", i + 1, c))
+            .collect::<Vec<_>>()
+            .join("  ")
+    }
+
+    /// Read a trimmed line; end of input reads as `q`
+    fn read_line<R: BufRead>(input: &mut R) -> Result<String> {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok("q".to_string());
+        }
+        Ok(line.trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CommandRecord;
+    use chrono::Utc;
+    use std::io::Cursor;
+    use tempfile::NamedTempFile;
+
+    fn storage_with(commands: &[(&str, i32)]) -> (NamedTempFile, Storage) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let storage = Storage::new(temp_file.path()).unwrap();
+        let categorizer = Categorizer::new();
+
+        for (command, usage) in commands {
+            let mut record = CommandRecord::new(
+                command.to_string(),
+                Utc::now(),
+                0,
+                10,
+                "/tmp".to_string(),
+                categorizer.categorize(command),
+            );
+            record.usage_count = *usage;
+            storage.insert(&record).unwrap();
+        }
+
+        (temp_file, storage)
+    }
+
+    #[test]
+    fn test_candidates_grouped_by_name() {
+        let (_file, storage) = storage_with(&[
+            ("./provision.sh prod", 5),
+            ("./provision.sh staging", 2),
+            ("jq .name package.json", 3),
+            ("git status", 50),
+        ]);
+        let trainer = Trainer::new(&storage, Categorizer::new());
+
+        let candidates = trainer.candidates(10).unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].name, "provision.sh");
+        assert_eq!(candidates[0].records, 2);
+        assert_eq!(candidates[0].usage, 7);
+        assert_eq!(candidates[0].example, "./provision.sh prod");
+        assert_eq!(candidates[1].name, "jq");
+
+        assert_eq!(trainer.candidates(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_run_assigns_by_number_and_name() {
+        let (_file, storage) = storage_with(&[
+            ("./provision.sh prod", 5),
+            ("jq .name package.json", 3),
+            ("mystery", 1),
+        ]);
+        let mut trainer = Trainer::new(&storage, Categorizer::new());
+        let candidates = trainer.candidates(10).unwrap();
+
+        let build = Categorizer::new()
+            .categories()
+            .iter()
+            .position(|c| c == "build")
+            .unwrap()
+            + 1;
+        let input = format!("Deploy\n{}\n\n", build);
+        let mut output = Vec::new();
+        let summary = trainer
+            .run(&candidates, Cursor::new(input), &mut output)
+            .unwrap();
+
+        assert_eq!(summary.rules["provision.sh"], "deploy");
+        assert_eq!(summary.rules["jq"], "build");
+        assert_eq!(summary.recategorized, 2);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(
+            storage.get_by_category("deploy", 10, None, false).unwrap()[0].command,
+            "./provision.sh prod"
+        );
+        assert_eq!(
+            storage
+                .get_by_category("other", 10, None, false)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_run_reprompts_on_invalid_and_stops_on_quit() {
+        let (_file, storage) = storage_with(&[("./a.sh", 2), ("./b.sh", 1)]);
+        let mut trainer = Trainer::new(&storage, Categorizer::new());
+        let candidates = trainer.candidates(10).unwrap();
+
+        let mut output = Vec::new();
+        let summary = trainer
+            .run(
+                &candidates,
+                Cursor::new("999\nbad name\nother\nscripts\nq\n"),
+                &mut output,
+            )
+            .unwrap();
+
+        assert_eq!(summary.rules.len(), 1);
+        assert_eq!(summary.rules["a.sh"], "scripts");
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("No category numbered 999"));
+        assert!(output.contains("Invalid category 'bad name'"));
+    }
+
+    #[test]
+    fn test_run_confirms_replacing_existing_rule() {
+        let (_file, storage) = storage_with(&[("./provision.sh prod", 1), ("./tidy.sh", 1)]);
+        let rules: BTreeMap<String, String> = [
+            ("provision.sh".to_string(), "ops".to_string()),
+            ("tidy.sh".to_string(), "ci".to_string()),
+        ]
+        .into();
+        let mut trainer = Trainer::new(&storage, Categorizer::new().with_rules(&rules));
+        let candidates = trainer.candidates(10).unwrap();
+        assert_eq!(candidates[0].name, "provision.sh");
+
+        let mut output = Vec::new();
+        let summary = trainer
+            .run(&candidates, Cursor::new("release\nn\nqa\ny\n"), &mut output)
+            .unwrap();
+
+        assert_eq!(summary.skipped, 1);
+        assert!(!summary.rules.contains_key("provision.sh"));
+        assert_eq!(summary.rules["tidy.sh"], "qa");
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("'provision.sh' is already categorized as 'ops'"));
+    }
+}
@@ -0,0 +1,241 @@
+/// Typo detection - finds failed commands that were immediately retyped
+///
+/// A typo is a failed command followed, in the same shell session and
+/// within a few seconds, by a nearly identical command (by edit distance)
+/// that succeeded. Repeated typos are worth an alias (`alias gti=git`) or
+/// a shell correction.
+use crate::models::Execution;
+use crate::parse::tokenize;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+/// Exit code of a command interrupted with Ctrl-C, which is not a typo
+const INTERRUPTED: i32 = 130;
+
+/// A mistyped command and the command that corrected it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Typo {
+    /// The failed command
+    pub typed: String,
+
+    /// The successful command run right after it
+    pub corrected: String,
+
+    /// Number of times the correction was observed
+    pub occurrences: usize,
+
+    /// When the typo was last made
+    pub last_seen: DateTime<Utc>,
+}
+
+impl Typo {
+    /// An alias fixing the typo, when only the program name was mistyped
+    /// (`gti status` → `git status` gives `alias gti='git'`)
+    pub fn alias(&self) -> Option<String> {
+        let typed = tokenize(&self.typed);
+        let corrected = tokenize(&self.corrected);
+        let (typed_program, typed_rest) = typed.split_first()?;
+        let (program, rest) = corrected.split_first()?;
+
+        let valid_name = typed_program
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if typed_program == program || typed_rest != rest || !valid_name {
+            return None;
+        }
+        Some(format!("alias {}='{}'", typed_program, program))
+    }
+}
+
+/// Detects failed commands that were corrected right away
+pub struct TypoDetector {
+    window: Duration,
+    max_distance: usize,
+}
+
+impl TypoDetector {
+    /// Create a new detector
+    ///
+    /// The correction must start within `window_secs` of the failed command
+    /// finishing and differ from it by at most `max_distance` edits.
+    pub fn new(window_secs: i64, max_distance: usize) -> Self {
+        Self {
+            window: Duration::seconds(window_secs),
+            max_distance: max_distance.max(1),
+        }
+    }
+
+    /// Detect typos, returning at most `limit` of the most frequent ones
+    ///
+    /// Executions must be in chronological order. Results are sorted by
+    /// occurrences (most frequent first), then by how recently they were seen.
+    pub fn detect(&self, executions: &[Execution], limit: usize) -> Vec<Typo> {
+        // The previous execution of each shell session
+        let mut previous: HashMap<Option<&str>, &Execution> = HashMap::new();
+        let mut typos: HashMap<(&str, &str), Typo> = HashMap::new();
+
+        for run in executions {
+            let session = run.meta.session.as_deref();
+            if let Some(prev) = previous.insert(session, run) {
+                if !self.is_correction(prev, run) {
+                    continue;
+                }
+                let typo = typos
+                    .entry((prev.command.as_str(), run.command.as_str()))
+                    .or_insert_with(|| Typo {
+                        typed: prev.command.clone(),
+                        corrected: run.command.clone(),
+                        occurrences: 0,
+                        last_seen: prev.timestamp,
+                    });
+                typo.occurrences += 1;
+                typo.last_seen = typo.last_seen.max(prev.timestamp);
+            }
+        }
+
+        let mut typos: Vec<Typo> = typos.into_values().collect();
+        typos.sort_by(|a, b| {
+            b.occurrences
+                .cmp(&a.occurrences)
+                .then(b.last_seen.cmp(&a.last_seen))
+                .then(a.typed.cmp(&b.typed))
+        });
+        typos.truncate(limit);
+        typos
+    }
+
+    /// Whether `run` fixed the failed `prev`
+    fn is_correction(&self, prev: &Execution, run: &Execution) -> bool {
+        if prev.exit_code == 0 || prev.exit_code == INTERRUPTED || run.exit_code != 0 {
+            return false;
+        }
+        if prev.working_dir != run.working_dir {
+            return false;
+        }
+        let finished = prev.timestamp + Duration::milliseconds(prev.duration_ms);
+        if run.timestamp - finished > self.window {
+            return false;
+        }
+
+        // Allow a few edits, but never rewriting (almost) all of a short command
+        let distance = edit_distance(&prev.command, &run.command);
+        let shortest = prev
+            .command
+            .chars()
+            .count()
+            .min(run.command.chars().count());
+        distance > 0 && distance <= self.max_distance && distance < shortest
+    }
+}
+
+impl Default for TypoDetector {
+    fn default() -> Self {
+        Self::new(30, 2)
+    }
+}
+
+/// Number of single-character insertions, deletions, substitutions and
+/// adjacent transpositions turning `a` into `b` (optimal string alignment)
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // Three rows of the distance matrix: two rows back, previous, current
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current: Vec<usize> = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn execution(command: &str, exit_code: i32, at: DateTime<Utc>) -> Execution {
+        Execution {
+            command_id: 0,
+            command: command.to_string(),
+            working_dir: "/project".to_string(),
+            timestamp: at,
+            exit_code,
+            duration_ms: 100,
+            meta: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("git status", "git status"), 0);
+        assert_eq!(edit_distance("gti status", "git status"), 1);
+        assert_eq!(edit_distance("git pul", "git pull"), 1);
+        assert_eq!(edit_distance("cargo tset", "cargo test"), 1);
+        assert_eq!(edit_distance("ls", "cd"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_detects_typos() {
+        let start = Utc::now();
+        let secs = |s| start + Duration::seconds(s);
+        let executions = vec![
+            execution("gti status", 127, secs(0)),
+            execution("git status", 0, secs(3)),
+            execution("gti status", 127, secs(100)),
+            execution("git status", 0, secs(102)),
+            execution("git pul", 1, secs(200)),
+            execution("git pull", 0, secs(205)),
+            // Too late to be a correction
+            execution("carg build", 127, secs(300)),
+            execution("cargo build", 0, secs(400)),
+            // Interrupted, or a different command altogether
+            execution("sleep 10", INTERRUPTED, secs(500)),
+            execution("sleep 1", 0, secs(501)),
+            execution("ls", 2, secs(600)),
+            execution("cd", 0, secs(601)),
+        ];
+
+        let typos = TypoDetector::default().detect(&executions, 10);
+        assert_eq!(typos.len(), 2);
+        assert_eq!(typos[0].typed, "gti status");
+        assert_eq!(typos[0].corrected, "git status");
+        assert_eq!(typos[0].occurrences, 2);
+        assert_eq!(typos[0].last_seen, secs(100));
+        assert_eq!(typos[0].alias().as_deref(), Some("alias gti='git'"));
+        assert_eq!(typos[1].typed, "git pul");
+        assert_eq!(typos[1].alias(), None);
+
+        assert_eq!(TypoDetector::default().detect(&executions, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_sessions_are_separate() {
+        let start = Utc::now();
+        let mut typed = execution("gti log", 127, start);
+        typed.meta.session = Some("a".to_string());
+        let mut other = execution("make", 0, start + Duration::seconds(1));
+        other.meta.session = Some("b".to_string());
+        let mut corrected = execution("git log", 0, start + Duration::seconds(2));
+        corrected.meta.session = Some("a".to_string());
+
+        let typos = TypoDetector::default().detect(&[typed, other, corrected], 10);
+        assert_eq!(typos.len(), 1);
+        assert_eq!(typos[0].corrected, "git log");
+    }
+}
@@ -0,0 +1,306 @@
+/// Workflow detection - finds command sequences that are repeated across sessions
+use crate::models::Execution;
+use chrono::Duration;
+use std::collections::{BTreeMap, HashMap};
+
+/// Shortest sequence considered a workflow
+const MIN_STEPS: usize = 2;
+
+/// Longest sequence considered a workflow
+const MAX_STEPS: usize = 4;
+
+/// A command sequence that was repeated within a project
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workflow {
+    /// Working directory (project) the sequence was run in
+    pub working_dir: String,
+
+    /// The commands in execution order
+    pub steps: Vec<String>,
+
+    /// Number of times the sequence was observed
+    pub occurrences: usize,
+}
+
+impl Workflow {
+    /// Format the steps as a single arrow-separated line
+    pub fn display_steps(&self) -> String {
+        self.steps.join(" → ")
+    }
+}
+
+/// Detects frequently repeated command sequences within sessions
+pub struct WorkflowDetector {
+    session_gap: Duration,
+    min_occurrences: usize,
+}
+
+impl WorkflowDetector {
+    /// Create a new detector
+    ///
+    /// Executions more than `session_gap_minutes` apart are treated as separate
+    /// sessions, and only sequences seen at least `min_occurrences` times are reported.
+    pub fn new(session_gap_minutes: i64, min_occurrences: usize) -> Self {
+        Self {
+            session_gap: Duration::minutes(session_gap_minutes),
+            min_occurrences: min_occurrences.max(1),
+        }
+    }
+
+    /// Detect workflows, returning at most `limit` per working directory
+    ///
+    /// Executions must be in chronological order. Results are grouped by
+    /// directory and sorted by occurrences (most frequent first).
+    pub fn detect(&self, executions: &[Execution], limit: usize) -> Vec<Workflow> {
+        let mut by_dir: BTreeMap<&str, Vec<&Execution>> = BTreeMap::new();
+        for execution in executions {
+            by_dir
+                .entry(execution.working_dir.as_str())
+                .or_default()
+                .push(execution);
+        }
+
+        let mut workflows = Vec::new();
+        for (dir, runs) in by_dir {
+            let mut found = self.detect_in_dir(dir, &runs);
+            found.truncate(limit);
+            workflows.extend(found);
+        }
+
+        workflows
+    }
+
+    /// Detect workflows among the executions of a single directory
+    fn detect_in_dir(&self, dir: &str, runs: &[&Execution]) -> Vec<Workflow> {
+        let mut counts: HashMap<Vec<&str>, usize> = HashMap::new();
+
+        for session in self.split_sessions(runs) {
+            for len in MIN_STEPS..=MAX_STEPS {
+                for window in session.windows(len) {
+                    *counts.entry(window.to_vec()).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let frequent: Vec<(Vec<&str>, usize)> = counts
+            .into_iter()
+            .filter(|(_, count)| *count >= self.min_occurrences)
+            .collect();
+
+        // Drop sequences fully explained by a longer sequence with the same count
+        let mut workflows: Vec<Workflow> = frequent
+            .iter()
+            .filter(|(steps, count)| {
+                !frequent.iter().any(|(other, other_count)| {
+                    other.len() > steps.len()
+                        && other_count == count
+                        && other.windows(steps.len()).any(|w| w == steps.as_slice())
+                })
+            })
+            .map(|(steps, count)| Workflow {
+                working_dir: dir.to_string(),
+                steps: steps.iter().map(|s| s.to_string()).collect(),
+                occurrences: *count,
+            })
+            .collect();
+
+        workflows.sort_by(|a, b| {
+            b.occurrences
+                .cmp(&a.occurrences)
+                .then(b.steps.len().cmp(&a.steps.len()))
+                .then(a.steps.cmp(&b.steps))
+        });
+
+        workflows
+    }
+
+    /// Split executions into sessions, collapsing immediate repeats of the same command
+    fn split_sessions<'a>(&self, runs: &[&'a Execution]) -> Vec<Vec<&'a str>> {
+        let mut sessions: Vec<Vec<&'a str>> = Vec::new();
+        let mut previous: Option<&Execution> = None;
+
+        for run in runs {
+            let new_session = match previous {
+                Some(prev) => {
+                    run.timestamp - prev.timestamp > self.session_gap
+                        || Self::different_sessions(prev, run)
+                }
+                None => true,
+            };
+
+            if new_session {
+                sessions.push(Vec::new());
+            }
+
+            let session = sessions.last_mut().unwrap();
+            if session.last() != Some(&run.command.as_str()) {
+                session.push(run.command.as_str());
+            }
+
+            previous = Some(run);
+        }
+
+        sessions
+    }
+
+    /// Whether two executions were reported by different shell sessions
+    fn different_sessions(a: &Execution, b: &Execution) -> bool {
+        match (&a.meta.session, &b.meta.session) {
+            (Some(a), Some(b)) => a != b,
+            _ => false,
+        }
+    }
+}
+
+impl Default for WorkflowDetector {
+    fn default() -> Self {
+        Self::new(30, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    fn execution(command: &str, dir: &str, at: DateTime<Utc>) -> Execution {
+        Execution {
+            command_id: 0,
+            command: command.to_string(),
+            working_dir: dir.to_string(),
+            timestamp: at,
+            exit_code: 0,
+            duration_ms: 0,
+            meta: Default::default(),
+        }
+    }
+
+    fn session(commands: &[&str], dir: &str, start: DateTime<Utc>) -> Vec<Execution> {
+        commands
+            .iter()
+            .enumerate()
+            .map(|(i, cmd)| execution(cmd, dir, start + Duration::minutes(i as i64)))
+            .collect()
+    }
+
+    #[test]
+    fn test_detects_repeated_sequence() {
+        let start = Utc::now();
+        let mut executions = session(
+            &["cargo build", "cargo test", "git commit"],
+            "/project",
+            start,
+        );
+        executions.extend(session(
+            &["cargo build", "cargo test", "git commit"],
+            "/project",
+            start + Duration::hours(2),
+        ));
+
+        let workflows = WorkflowDetector::default().detect(&executions, 10);
+
+        assert_eq!(workflows.len(), 1);
+        assert_eq!(
+            workflows[0].steps,
+            vec!["cargo build", "cargo test", "git commit"]
+        );
+        assert_eq!(workflows[0].occurrences, 2);
+        assert_eq!(
+            workflows[0].display_steps(),
+            "cargo build → cargo test → git commit"
+        );
+    }
+
+    #[test]
+    fn test_sequences_do_not_span_sessions() {
+        let start = Utc::now();
+        let mut executions = session(&["cargo build"], "/project", start);
+        executions.extend(session(
+            &["cargo test"],
+            "/project",
+            start + Duration::hours(1),
+        ));
+        executions.extend(session(
+            &["cargo build"],
+            "/project",
+            start + Duration::hours(3),
+        ));
+        executions.extend(session(
+            &["cargo test"],
+            "/project",
+            start + Duration::hours(4),
+        ));
+
+        let workflows = WorkflowDetector::default().detect(&executions, 10);
+        assert!(workflows.is_empty());
+    }
+
+    #[test]
+    fn test_sequences_do_not_span_shell_sessions() {
+        let start = Utc::now();
+        let mut executions = Vec::new();
+        for i in 0..2 {
+            let at = start + Duration::hours(i * 2);
+            let mut build = execution("cargo build", "/project", at);
+            build.meta.session = Some("tab-1".to_string());
+            let mut test = execution("cargo test", "/project", at + Duration::seconds(5));
+            test.meta.session = Some("tab-2".to_string());
+            executions.push(build);
+            executions.push(test);
+        }
+
+        let workflows = WorkflowDetector::default().detect(&executions, 10);
+        assert!(workflows.is_empty());
+    }
+
+    #[test]
+    fn test_immediate_repeats_are_collapsed() {
+        let start = Utc::now();
+        let mut executions = session(
+            &["cargo build", "cargo build", "cargo test"],
+            "/project",
+            start,
+        );
+        executions.extend(session(
+            &["cargo build", "cargo test"],
+            "/project",
+            start + Duration::hours(2),
+        ));
+
+        let workflows = WorkflowDetector::default().detect(&executions, 10);
+        assert_eq!(workflows.len(), 1);
+        assert_eq!(workflows[0].steps, vec!["cargo build", "cargo test"]);
+    }
+
+    #[test]
+    fn test_grouped_per_directory_with_limit() {
+        let start = Utc::now();
+        let mut executions = Vec::new();
+        for i in 0..3 {
+            let at = start + Duration::hours(i * 2);
+            executions.extend(session(&["make", "make test"], "/a", at));
+            executions.extend(session(&["npm ci", "npm test"], "/b", at));
+            executions.extend(session(&["ls", "cd src"], "/b", at + Duration::minutes(10)));
+        }
+
+        let workflows = WorkflowDetector::default().detect(&executions, 1);
+
+        assert_eq!(workflows.len(), 2);
+        assert_eq!(workflows[0].working_dir, "/a");
+        assert_eq!(workflows[1].working_dir, "/b");
+    }
+
+    #[test]
+    fn test_min_occurrences() {
+        let start = Utc::now();
+        let mut executions = session(&["make", "make test"], "/a", start);
+        executions.extend(session(
+            &["make", "make test"],
+            "/a",
+            start + Duration::hours(2),
+        ));
+
+        let workflows = WorkflowDetector::new(30, 3).detect(&executions, 10);
+        assert!(workflows.is_empty());
+    }
+}
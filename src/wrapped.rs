@@ -0,0 +1,209 @@
+/// Year-in-review recap (`omniscient wrapped`)
+///
+/// Renders a [`Wrapped`] aggregate as plain text with ASCII bar charts: top
+/// commands, categories, runs per month, the most failed and the longest
+/// running command, and the longest streak of active days.
+use crate::models::{humanize_duration, Wrapped};
+use std::fmt::Write;
+
+/// Width of the longest bar in a chart
+const BAR_WIDTH: usize = 30;
+
+/// Commands longer than this are shortened in charts
+const LABEL_WIDTH: usize = 36;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Render a year in review
+pub fn render(wrapped: &Wrapped) -> String {
+    let title = format!("Your {} in the Shell", wrapped.year);
+    let border = "═".repeat(title.chars().count() + 6);
+    let mut out = format!("╔{}╗\n║   {}   ║\n╚{}╝\n\n", border, title, border);
+
+    if wrapped.runs == 0 {
+        let _ = writeln!(out, "No commands recorded in {}.", wrapped.year);
+        return out;
+    }
+
+    let success_rate = wrapped.successful as f64 / wrapped.runs as f64 * 100.0;
+    let _ = writeln!(
+        out,
+        "  {} commands run · {} distinct · {:.0}% successful",
+        wrapped.runs, wrapped.commands, success_rate
+    );
+    let _ = write!(out, "  Active on {}", days(wrapped.active_days));
+    if let Some((start, length)) = wrapped.longest_streak {
+        let _ = write!(
+            out,
+            " · Longest streak: {} (from {})",
+            days(length),
+            start.format("%b %-d")
+        );
+    }
+    out.push('\n');
+
+    section(&mut out, "Top 10 Commands");
+    let max = wrapped.top_commands.first().map_or(0, |c| c.runs);
+    for (index, command) in wrapped.top_commands.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "  {:>2}. {:<width$} {} {}",
+            index + 1,
+            shorten(&command.command),
+            bar(command.runs, max),
+            command.runs,
+            width = LABEL_WIDTH
+        );
+    }
+
+    section(&mut out, "Categories");
+    let max = wrapped.categories.first().map_or(0, |c| c.count);
+    for category in &wrapped.categories {
+        let _ = writeln!(
+            out,
+            "  {:<14} {} {:.0}%",
+            category.category,
+            bar(category.count, max),
+            category.count as f64 / wrapped.runs as f64 * 100.0
+        );
+    }
+
+    section(&mut out, "Month by Month");
+    let max = wrapped.months.iter().copied().max().unwrap_or(0);
+    for (name, &count) in MONTHS.iter().zip(&wrapped.months) {
+        let _ = writeln!(out, "  {} {} {}", name, bar(count, max), count);
+    }
+
+    if let Some(ref failed) = wrapped.most_failed {
+        section(&mut out, "Most Failed");
+        let _ = writeln!(
+            out,
+            "  {} - failed {} of {} runs",
+            shorten(&failed.command),
+            failed.failures,
+            failed.runs
+        );
+    }
+
+    if let Some(ref longest) = wrapped.longest_running {
+        section(&mut out, "Longest Running");
+        let _ = writeln!(
+            out,
+            "  {} - {}",
+            shorten(&longest.command),
+            humanize_duration(longest.max_ms)
+        );
+    }
+
+    out
+}
+
+fn days(count: usize) -> String {
+    format!("{} {}", count, if count == 1 { "day" } else { "days" })
+}
+
+fn section(out: &mut String, title: &str) {
+    let _ = write!(out, "\n{}\n{}\n", title, "─".repeat(title.chars().count()));
+}
+
+/// Bar scaled so that `max` fills the chart width; non-zero values always
+/// get at least one block
+fn bar(value: usize, max: usize) -> String {
+    if max == 0 || value == 0 {
+        return String::new();
+    }
+    "█".repeat((value * BAR_WIDTH / max).max(1))
+}
+
+/// Single-line command that fits the label column
+fn shorten(command: &str) -> String {
+    let command = command.replace('\n', " ⏎ ");
+    if command.chars().count() <= LABEL_WIDTH {
+        command
+    } else {
+        let mut short: String = command.chars().take(LABEL_WIDTH - 1).collect();
+        short.push('…');
+        short
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CategoryStats, CommandCount};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_bar_and_shorten() {
+        assert_eq!(bar(0, 10), "");
+        assert_eq!(bar(10, 10).chars().count(), BAR_WIDTH);
+        assert_eq!(bar(1, 1000), "█");
+        assert_eq!(shorten("ls"), "ls");
+        let long = shorten(&"x".repeat(100));
+        assert_eq!(long.chars().count(), LABEL_WIDTH);
+        assert!(long.ends_with('…'));
+    }
+
+    #[test]
+    fn test_render() {
+        let mut months = [0; 12];
+        months[2] = 8;
+        months[3] = 2;
+        let wrapped = Wrapped {
+            year: 2025,
+            runs: 10,
+            successful: 9,
+            commands: 2,
+            top_commands: vec![
+                CommandCount {
+                    command_id: 1,
+                    command: "git status".to_string(),
+                    runs: 8,
+                },
+                CommandCount {
+                    command_id: 2,
+                    command: "cargo build".to_string(),
+                    runs: 2,
+                },
+            ],
+            most_failed: None,
+            longest_running: None,
+            categories: vec![CategoryStats {
+                category: "git".to_string(),
+                count: 8,
+            }],
+            months,
+            active_days: 4,
+            longest_streak: Some((NaiveDate::from_ymd_opt(2025, 3, 2).unwrap(), 3)),
+        };
+
+        let text = render(&wrapped);
+        assert!(text.contains("Your 2025 in the Shell"));
+        assert!(text.contains("10 commands run · 2 distinct · 90% successful"));
+        assert!(text.contains("Longest streak: 3 days (from Mar 2)"));
+        assert!(text.contains(&format!("   1. {:<36} {} 8", "git status", bar(8, 8))));
+        assert!(text.contains("git            "));
+        assert!(text.contains("Jan  0"));
+        assert!(!text.contains("Most Failed"));
+    }
+
+    #[test]
+    fn test_render_empty_year() {
+        let wrapped = Wrapped {
+            year: 1999,
+            runs: 0,
+            successful: 0,
+            commands: 0,
+            top_commands: Vec::new(),
+            most_failed: None,
+            longest_running: None,
+            categories: Vec::new(),
+            months: [0; 12],
+            active_days: 0,
+            longest_streak: None,
+        };
+        assert!(render(&wrapped).contains("No commands recorded in 1999."));
+    }
+}
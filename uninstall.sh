@@ -54,7 +54,7 @@ SHELL_RC=$(detect_shell_rc)
 echo -e "This will uninstall Omniscient from your system.\n"
 echo "The following will be removed/modified:"
 echo "  1. Binary from ~/.cargo/bin/omniscient"
-echo "  2. Configuration directory at ~/.omniscient"
+echo "  2. Configuration and data directories (optional)"
 echo "  3. Shell hooks from $SHELL_RC"
 echo ""
 
@@ -115,28 +115,43 @@ fi
 
 # Step 3: Ask about data removal
 echo ""
-print_warning "Your command history data is stored at ~/.omniscient"
+# OMNISCIENT_HOME, the XDG directories, or ~/.omniscient from older versions
+OMNISCIENT_DIRS=()
+for dir in "${OMNISCIENT_HOME:-}" \
+    "${XDG_DATA_HOME:-$HOME/.local/share}/omniscient" \
+    "${XDG_CONFIG_HOME:-$HOME/.config}/omniscient" \
+    "$HOME/.omniscient"; do
+    if [ -n "$dir" ] && [ -d "$dir" ]; then
+        OMNISCIENT_DIRS+=("$dir")
+    fi
+done
+
+print_warning "Your command history data is stored at: ${OMNISCIENT_DIRS[*]:-(not found)}"
 read -p "Do you want to delete your command history data? [y/N] " -n 1 -r
 echo
 
 if [[ $REPLY =~ ^[Yy]$ ]]; then
-    OMNISCIENT_DIR="$HOME/.omniscient"
-    if [ -d "$OMNISCIENT_DIR" ]; then
+    if [ ${#OMNISCIENT_DIRS[@]} -gt 0 ]; then
         # Create backup before deletion
         BACKUP_DIR="${HOME}/omniscient_backup_$(date +%Y%m%d_%H%M%S)"
         print_info "Creating backup at $BACKUP_DIR..."
-        cp -r "$OMNISCIENT_DIR" "$BACKUP_DIR"
+        mkdir -p "$BACKUP_DIR"
+        for dir in "${OMNISCIENT_DIRS[@]}"; do
+            cp -r "$dir" "$BACKUP_DIR/$(basename "$(dirname "$dir")")-$(basename "$dir")"
+        done
         print_success "Backup created at $BACKUP_DIR"
 
-        # Remove directory
-        rm -rf "$OMNISCIENT_DIR"
-        print_success "Removed data directory $OMNISCIENT_DIR"
+        # Remove directories
+        for dir in "${OMNISCIENT_DIRS[@]}"; do
+            rm -rf "$dir"
+            print_success "Removed data directory $dir"
+        done
     else
-        print_warning "Data directory not found at $OMNISCIENT_DIR"
+        print_warning "No data directory found"
     fi
 else
-    print_info "Keeping data directory at ~/.omniscient"
-    print_info "You can manually delete it later if needed"
+    print_info "Keeping data directories: ${OMNISCIENT_DIRS[*]:-(none)}"
+    print_info "You can manually delete them later if needed"
 fi
 
 echo ""